@@ -0,0 +1,132 @@
+//! In-process caching of values read from Redis, so that frequently polled endpoints don't query Redis on every
+//! request.
+//!
+//! Values are cached by this server alone, so may be up to the cache's TTL out of date (e.g. not yet showing a newly
+//! created queue), and may differ between servers.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::ResponseCacheConfig;
+use crate::models::{queue, ServerInfo};
+
+/// Key values are cached by in caches holding a single value.
+const SINGLE_KEY: &str = "";
+
+/// Caches of responses from each cached endpoint, holding data for every namespace, which handlers filter for the
+/// client making each request.
+#[derive(Debug)]
+pub struct ResponseCache {
+    /// Server information returned by `GET /info`.
+    pub info: TtlCache<ServerInfo>,
+
+    /// All queue names, returned by `GET /queue`.
+    pub queue_names: TtlCache<Vec<String>>,
+
+    /// Summaries of all queues, returned by `GET /queue?summary=true`.
+    pub queue_summaries: TtlCache<HashMap<String, queue::Summary>>,
+
+    /// Sizes of queues by name, returned by `GET /queue/{name}/size`.
+    pub queue_sizes: TtlCache<u64>,
+
+    /// Backlogs of queues by name, returned by `GET /queue/{name}/backlog`.
+    pub queue_backlogs: TtlCache<queue::Backlog>,
+}
+
+impl ResponseCache {
+    /// Create empty caches with the cache times given in configuration.
+    pub fn new(config: &ResponseCacheConfig) -> Self {
+        Self {
+            info: TtlCache::new(config.info.0),
+            queue_names: TtlCache::new(config.queue_index.0),
+            queue_summaries: TtlCache::new(config.queue_index.0),
+            queue_sizes: TtlCache::new(config.queue_size.0),
+            queue_backlogs: TtlCache::new(config.queue_backlog.0),
+        }
+    }
+}
+
+/// Cache of values by key, each of which expires a fixed time after it's inserted.
+#[derive(Debug)]
+pub struct TtlCache<V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, V)>>,
+}
+
+impl<V: Clone> TtlCache<V> {
+    /// Create an empty cache, whose values expire after given TTL. Nothing is cached if the TTL is zero.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check whether values are cached at all.
+    pub fn is_enabled(&self) -> bool {
+        self.ttl > Duration::from_secs(0)
+    }
+
+    /// Get the value cached for given key, if it hasn't expired.
+    pub fn get(&self, key: &str, now: Instant) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        let (inserted_at, value) = entries.get(key)?;
+        if now.saturating_duration_since(*inserted_at) < self.ttl {
+            Some(value.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Get the value of a cache holding a single value, if it hasn't expired.
+    pub fn get_single(&self, now: Instant) -> Option<V> {
+        self.get(SINGLE_KEY, now)
+    }
+
+    /// Cache the value of a cache holding a single value.
+    pub fn insert_single(&self, value: V, now: Instant) {
+        self.insert(SINGLE_KEY, value, now)
+    }
+
+    /// Cache a value for given key, replacing any existing value, and removing any expired values.
+    pub fn insert(&self, key: &str, value: V, now: Instant) {
+        if !self.is_enabled() {
+            return;
+        }
+        let ttl = self.ttl;
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (inserted_at, _)| now.saturating_duration_since(*inserted_at) < ttl);
+        entries.insert(key.to_owned(), (now, value));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expiry() {
+        let cache = TtlCache::new(Duration::from_secs(2));
+        let now = Instant::now();
+        assert_eq!(cache.get("a", now), None);
+
+        cache.insert("a", 1, now);
+        assert_eq!(cache.get("a", now), Some(1));
+        assert_eq!(cache.get("a", now + Duration::from_secs(1)), Some(1));
+        assert_eq!(cache.get("a", now + Duration::from_secs(2)), None);
+
+        cache.insert("b", 2, now + Duration::from_secs(3));
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+        assert_eq!(cache.get("b", now + Duration::from_secs(3)), Some(2));
+    }
+
+    #[test]
+    fn disabled() {
+        let cache = TtlCache::new(Duration::from_secs(0));
+        assert!(!cache.is_enabled());
+        let now = Instant::now();
+        cache.insert("a", 1, now);
+        assert_eq!(cache.get("a", now), None);
+    }
+}
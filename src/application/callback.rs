@@ -0,0 +1,143 @@
+//! Sends jobs' results to the callback URL given when they were created, once they've ended, so that submitters
+//! don't need to poll for them.
+//!
+//! Each result is delivered by a job on the `ocypod:callbacks` system queue, so deliveries are retried by the queue's
+//! retry settings, survive server restarts, and can be inspected through the API.
+
+use actix_web::client::Client;
+use log::info;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+
+use crate::application::pool::PooledConnection;
+use crate::application::system::SystemQueue;
+use crate::application::RedisManager;
+use crate::events::EventKind;
+use crate::models::{job, OcyError, OcyResult};
+
+/// Header containing the signature of a callback request's body, when a secret is configured.
+pub const SIGNATURE_HEADER: &str = "X-Ocypod-Signature";
+
+/// Fields of a job needed to check whether it has a result to deliver.
+const DELIVERY_FIELDS: &[job::Field] = &[job::Field::Id, job::Field::CallbackUrl, job::Field::Ended];
+
+/// Fields of a job sent to its callback URL.
+const CALLBACK_FIELDS: &[job::Field] = &[
+    job::Field::Id,
+    job::Field::Queue,
+    job::Field::Status,
+    job::Field::Tags,
+    job::Field::CreatedAt,
+    job::Field::StartedAt,
+    job::Field::EndedAt,
+    job::Field::Output,
+    job::Field::ErrorCode,
+    job::Field::ErrorDetails,
+    job::Field::CallbackUrl,
+    job::Field::Ended,
+];
+
+/// Whether given event may mean a job has ended.
+pub fn is_relevant(event: EventKind) -> bool {
+    matches!(
+        event,
+        EventKind::Completed | EventKind::Failed | EventKind::TimedOut | EventKind::Cancelled | EventKind::Quarantined
+    )
+}
+
+/// Get the value of the signature header for given request body, the hex encoded HMAC-SHA256 of the body using
+/// given secret, prefixed with "sha256=".
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, body);
+    let hex: String = tag.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", hex)
+}
+
+/// Input of a job on the `ocypod:callbacks` system queue, giving the job whose result it delivers.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Delivery {
+    /// ID of the job whose result is delivered.
+    pub job_id: u64,
+}
+
+/// Queue a delivery of given job's result to its callback URL, if it has one and has ended (i.e. won't be retried).
+///
+/// `conn` must be for the job's shard, and `system_conn` for the shard of the `ocypod:callbacks` queue. Returns the ID
+/// of the delivery job, or `None` if there's nothing to deliver.
+pub async fn queue_delivery(
+    conn: &mut PooledConnection,
+    system_conn: &mut PooledConnection,
+    job_id: u64,
+) -> OcyResult<Option<u64>> {
+    let job = match RedisManager::job_fields(conn, job_id, Some(DELIVERY_FIELDS)).await {
+        Ok(job) => job,
+        // deleted or expired in the meantime
+        Err(OcyError::NoSuchJob(_)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    if job.callback_url().is_none() || !job.ended() {
+        return Ok(None);
+    }
+
+    let job_req = job::CreateRequest {
+        input: Some(serde_json::to_value(Delivery { job_id }).unwrap()),
+        ..Default::default()
+    };
+    let delivery_id = RedisManager::create_job(system_conn, SystemQueue::Callbacks.name(), &job_req).await?.id();
+    Ok(Some(delivery_id))
+}
+
+/// Send given job's result to its callback URL, if it still has one.
+///
+/// Returns true if the result was delivered, false if there was nothing to deliver, or an error if the callback URL
+/// couldn't be reached or didn't respond with a 2xx status.
+pub async fn send_result(
+    conn: &mut PooledConnection,
+    client: &Client,
+    job_id: u64,
+    secret: Option<&str>,
+) -> Result<bool, String> {
+    let job = match RedisManager::job_fields(conn, job_id, Some(CALLBACK_FIELDS)).await {
+        Ok(job) => job,
+        // deleted or expired in the meantime
+        Err(OcyError::NoSuchJob(_)) => return Ok(false),
+        Err(err) => return Err(err.to_string()),
+    };
+    let url = match job.callback_url() {
+        Some(url) if job.ended() => url,
+        _ => return Ok(false),
+    };
+
+    let body = serde_json::to_vec(&job).unwrap();
+    deliver(client, &url, &body, secret).await?;
+    info!("[job:{}] result sent to {}", job_id, &url);
+    Ok(true)
+}
+
+/// POST given body to a callback URL, returning an error unless it responds with a 2xx status.
+async fn deliver(client: &Client, url: &str, body: &[u8], secret: Option<&str>) -> Result<(), String> {
+    let mut req = client.post(url).content_type("application/json");
+    if let Some(secret) = secret {
+        req = req.header(SIGNATURE_HEADER, signature(secret, body));
+    }
+    match req.send_body(body.to_vec()).await {
+        Ok(res) if res.status().is_success() => Ok(()),
+        Ok(res) => Err(format!("callback responded with {}", res.status())),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sign() {
+        // from RFC 4231 test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
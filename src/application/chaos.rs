@@ -0,0 +1,81 @@
+//! Failures and conditions injected on purpose, so that client libraries and worker frameworks can be tested against
+//! them deterministically. Only built with the "testing" feature, and inactive until set via the `/testing`
+//! endpoints.
+
+use std::sync::RwLock;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{DateTime, Duration, OcyError, OcyResult};
+
+/// Conditions currently injected into this server.
+static CHAOS: RwLock<Chaos> = RwLock::new(Chaos {
+    redis_latency: None,
+    dequeue_failure_percent: 0,
+    frozen_time: None,
+});
+
+/// Conditions injected into this server, which are all inactive by default.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Chaos {
+    /// Delay added to every Redis command and pipeline sent by this server.
+    pub redis_latency: Option<Duration>,
+
+    /// Percentage of requests to take a job from a queue that fail, between 0 and 100.
+    pub dequeue_failure_percent: u64,
+
+    /// Time used as the current time when checking whether ended jobs have expired.
+    pub frozen_time: Option<DateTime>,
+}
+
+impl Chaos {
+    /// Get the conditions currently injected into this server.
+    pub fn current() -> Self {
+        CHAOS.read().unwrap().clone()
+    }
+
+    /// Replace the conditions injected into this server, after checking they're valid.
+    pub fn set(chaos: Chaos) -> OcyResult<()> {
+        if chaos.dequeue_failure_percent > 100 {
+            return Err(OcyError::bad_request("Invalid dequeue_failure_percent, must be between 0 and 100"));
+        }
+        *CHAOS.write().unwrap() = chaos;
+        Ok(())
+    }
+}
+
+/// Get the delay to add to a Redis command, if any.
+pub fn redis_latency() -> Option<std::time::Duration> {
+    CHAOS.read().unwrap().redis_latency.as_ref().map(|latency| latency.0)
+}
+
+/// Decide whether a request to take a job from a queue should fail.
+pub fn fail_dequeue() -> bool {
+    let percent = CHAOS.read().unwrap().dequeue_failure_percent;
+    percent > 0 && rand::thread_rng().gen_range(0, 100) < percent
+}
+
+/// Get the time to check whether ended jobs have expired as of, which is the current time unless it's frozen.
+pub fn expiry_now() -> DateTime {
+    CHAOS.read().unwrap().frozen_time.clone().unwrap_or_else(super::clock::now)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_and_reset() {
+        let chaos: Chaos = serde_json::from_str(r#"{"redis_latency": "5ms", "dequeue_failure_percent": 100}"#).unwrap();
+        Chaos::set(chaos).unwrap();
+        assert_eq!(redis_latency(), Some(std::time::Duration::from_millis(5)));
+        assert!(fail_dequeue());
+
+        assert!(Chaos::set(Chaos { dequeue_failure_percent: 101, ..Default::default() }).is_err());
+        Chaos::set(Chaos::default()).unwrap();
+        assert_eq!(Chaos::current(), Chaos::default());
+        assert!(!fail_dequeue());
+    }
+}
@@ -0,0 +1,110 @@
+//! Source of the current time for job and monitor logic, so that timeouts, retries, and expiry can be tested
+//! reproducibly by overriding it, rather than by waiting for real time to pass.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::models::{DateTime, Duration};
+
+/// Source of the current time.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Get the current date/time according to this clock.
+    fn now(&self) -> DateTime;
+}
+
+/// Clock reading the system's time, used unless overridden.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime {
+        DateTime::now()
+    }
+}
+
+/// Clock that only moves when it's set or advanced.
+#[derive(Debug)]
+pub struct ManualClock(Mutex<DateTime>);
+
+impl ManualClock {
+    /// Create a clock stopped at given date/time.
+    pub fn new(now: DateTime) -> Self {
+        Self(Mutex::new(now))
+    }
+
+    /// Move this clock to given date/time.
+    pub fn set(&self, now: DateTime) {
+        *self.0.lock().unwrap() = now;
+    }
+
+    /// Move this clock forward by given duration.
+    pub fn advance(&self, duration: &Duration) {
+        let mut now = self.0.lock().unwrap();
+        *now = now.plus(duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+thread_local! {
+    /// Clock overriding the system clock on this thread, if any.
+    static OVERRIDE: RefCell<Option<Arc<dyn Clock>>> = RefCell::new(None);
+}
+
+/// Get the current date/time, from this thread's overriding clock if there is one, otherwise from the system clock.
+pub fn now() -> DateTime {
+    OVERRIDE
+        .with(|clock| clock.borrow().as_ref().map(|clock| clock.now()))
+        .unwrap_or_else(|| SystemClock.now())
+}
+
+/// Override the clock used on the current thread until the returned guard is dropped.
+///
+/// Overrides are per thread, so tests run in parallel don't affect each other, and only apply to async tests run on a
+/// single threaded runtime. Only available in tests, or when built with the "testing" feature.
+#[cfg(any(test, feature = "testing"))]
+pub fn override_clock(clock: Arc<dyn Clock>) -> ClockOverride {
+    let previous = OVERRIDE.with(|current| current.borrow_mut().replace(clock));
+    ClockOverride { previous }
+}
+
+/// Guard restoring the clock that was in use on a thread before it was overridden, once dropped.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug)]
+#[must_use]
+pub struct ClockOverride {
+    previous: Option<Arc<dyn Clock>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Drop for ClockOverride {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        OVERRIDE.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manual_override() {
+        let start = DateTime::now().plus(&Duration::from_secs(3600));
+        let clock = Arc::new(ManualClock::new(start.clone()));
+        {
+            let _guard = override_clock(clock.clone());
+            assert_eq!(now(), start);
+            clock.advance(&Duration::from_secs(90));
+            assert_eq!(now().seconds_since(&start), 90);
+        }
+
+        // system clock is used again once the override is dropped
+        assert!(now() < start);
+    }
+}
@@ -0,0 +1,152 @@
+//! Encryption at rest of job inputs and outputs.
+//!
+//! When an encryption key is configured, job inputs and outputs are encrypted with AES-256-GCM before being written
+//! to Redis, and decrypted when read, so they can't be read from Redis directly or from its RDB/AOF files. Encrypted
+//! values are stored as `ENCRYPTED_PREFIX` followed by the base64 encoded nonce and ciphertext. Since serialised JSON
+//! never starts with this prefix, values written before encryption was enabled are still read as they are.
+
+use std::fs;
+use std::sync::OnceLock;
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::config::EncryptionConfig;
+use crate::models::{OcyError, OcyResult};
+
+/// Prefix of encrypted values, including the version of the encryption format.
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Length of encryption keys in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// Cipher used to encrypt job data, set once at startup.
+static CIPHER: OnceLock<PayloadCipher> = OnceLock::new();
+
+/// Encrypts and decrypts job data with a single key.
+#[derive(Debug)]
+pub struct PayloadCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl PayloadCipher {
+    /// Create a cipher using given 256 bit key.
+    pub fn new(key: &[u8]) -> OcyResult<Self> {
+        let key = UnboundKey::new(&AES_256_GCM, key)
+            .map_err(|_| OcyError::bad_request(format!("Encryption key must be {} bytes", KEY_LEN)))?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Create a cipher using the key given in configuration, if any.
+    pub fn from_config(config: &EncryptionConfig) -> OcyResult<Option<Self>> {
+        let encoded = match (&config.key, &config.key_env, &config.key_file) {
+            (Some(key), _, _) => key.clone(),
+            (None, Some(var), _) => std::env::var(var)
+                .map_err(|_| OcyError::bad_request(format!("Encryption key environment variable {} not set", var)))?,
+            (None, None, Some(path)) => fs::read_to_string(path).map_err(|err| {
+                OcyError::bad_request(format!("Failed to read encryption key file {}: {}", path.display(), err))
+            })?,
+            (None, None, None) => return Ok(None),
+        };
+        let key = base64::decode(encoded.trim())
+            .map_err(|err| OcyError::bad_request(format!("Encryption key isn't valid base64: {}", err)))?;
+        Self::new(&key).map(Some)
+    }
+
+    /// Encrypt given value.
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).expect("failed to generate nonce");
+        let mut in_out = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut in_out)
+            .expect("failed to encrypt job data");
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&in_out);
+        format!("{}{}", ENCRYPTED_PREFIX, base64::encode(&sealed))
+    }
+
+    /// Decrypt a value previously encrypted with this cipher's key.
+    pub fn decrypt(&self, encrypted: &str) -> OcyResult<String> {
+        let sealed = encrypted
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .and_then(|encoded| base64::decode(encoded).ok())
+            .filter(|sealed| sealed.len() >= NONCE_LEN)
+            .ok_or_else(|| OcyError::Internal("Invalid encrypted job data".to_owned()))?;
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| OcyError::Internal("Invalid encrypted job data".to_owned()))?;
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self.key.open_in_place(nonce, Aad::empty(), &mut in_out).map_err(|_| {
+            OcyError::Internal("Failed to decrypt job data, was it encrypted with a different key?".to_owned())
+        })?;
+        String::from_utf8(plaintext.to_vec()).map_err(|err| OcyError::Internal(err.to_string()))
+    }
+}
+
+/// Set the cipher used to encrypt job data. Can only be set once, subsequent calls are ignored.
+pub fn init(cipher: PayloadCipher) {
+    let _ = CIPHER.set(cipher);
+}
+
+/// Get job data to write to Redis, encrypted if encryption is enabled.
+pub fn seal(plaintext: String) -> String {
+    match CIPHER.get() {
+        Some(cipher) => cipher.encrypt(&plaintext),
+        None => plaintext,
+    }
+}
+
+/// Get job data read from Redis, decrypting it if it's encrypted.
+pub fn open(stored: String) -> OcyResult<String> {
+    if !stored.starts_with(ENCRYPTED_PREFIX) {
+        return Ok(stored);
+    }
+    match CIPHER.get() {
+        Some(cipher) => cipher.decrypt(&stored),
+        None => Err(OcyError::Internal("Job data is encrypted, but no encryption key is configured".to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let cipher = PayloadCipher::new(&[7; KEY_LEN]).unwrap();
+        let plaintext = r#"{"card_number":"4111111111111111"}"#;
+        let encrypted = cipher.encrypt(plaintext);
+        assert!(encrypted.starts_with(ENCRYPTED_PREFIX));
+        assert!(!encrypted.contains("4111"));
+        assert_ne!(cipher.encrypt(plaintext), encrypted);
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), plaintext);
+
+        let other = PayloadCipher::new(&[8; KEY_LEN]).unwrap();
+        assert!(other.decrypt(&encrypted).is_err());
+        assert!(cipher.decrypt("enc:v1:not-base64!").is_err());
+        assert!(cipher.decrypt("enc:v1:").is_err());
+    }
+
+    #[test]
+    fn invalid_keys() {
+        assert!(PayloadCipher::new(&[0; 16]).is_err());
+
+        let mut config = EncryptionConfig::default();
+        assert!(PayloadCipher::from_config(&config).unwrap().is_none());
+        config.key = Some(base64::encode([1; KEY_LEN]));
+        assert!(PayloadCipher::from_config(&config).unwrap().is_some());
+        config.key = Some("c2hvcnQ=".to_owned());
+        assert!(PayloadCipher::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn unencrypted() {
+        assert_eq!(open("{\"a\":1}".to_owned()).unwrap(), "{\"a\":1}");
+    }
+}
@@ -0,0 +1,45 @@
+//! Drain mode, used to stop a server handing out jobs before it's shut down, e.g. from a Kubernetes `preStop` hook.
+//!
+//! While draining, workers asking for jobs get none, jobs aren't pushed to callback URLs, and the server reports that
+//! it isn't ready, so that it's removed from load balancing. All other requests are handled as usual, so that workers
+//! can finish and report on the jobs they're already running.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag indicating whether the server is draining.
+#[derive(Clone, Debug, Default)]
+pub struct Drain(Arc<AtomicBool>);
+
+impl Drain {
+    /// Create a new flag, initially not draining.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start or stop draining, returning whether the server was already draining.
+    pub fn set(&self, draining: bool) -> bool {
+        self.0.swap(draining, Ordering::SeqCst)
+    }
+
+    /// Check whether the server is draining.
+    pub fn is_draining(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared() {
+        let drain = Drain::new();
+        let other = drain.clone();
+        assert!(!other.is_draining());
+        assert!(!drain.set(true));
+        assert!(other.is_draining());
+        assert!(other.set(false));
+        assert!(!drain.is_draining());
+    }
+}
@@ -0,0 +1,246 @@
+//! Exporting metrics to systems that can't scrape the `/metrics` endpoint, by pushing them to a Prometheus
+//! pushgateway, or sending them to a StatsD server over UDP.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+
+use super::metrics::{Kind, Labels, Sample};
+
+/// Maximum size of a single StatsD packet, small enough to avoid fragmentation on typical networks.
+const MAX_PACKET_LEN: usize = 1432;
+
+/// Dialect of StatsD metrics are sent in.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsdFormat {
+    /// Plain StatsD, with label values such as monitors included in metric names, e.g.
+    /// `ocypod.monitor_passes_total.retry:1|c`.
+    #[default]
+    Statsd,
+
+    /// DogStatsD as used by Datadog, with labels given as tags, e.g.
+    /// `ocypod.monitor_passes_total:1|c|#monitor:retry`.
+    Datadog,
+}
+
+/// Get the URL to push metrics to on a Prometheus pushgateway, grouped by given job and instance labels.
+pub fn pushgateway_url(base_url: &str, job: &str, instance: &str) -> String {
+    format!(
+        "{}/metrics/job{}/instance{}",
+        base_url.trim_end_matches('/'),
+        grouping_label(job),
+        grouping_label(instance)
+    )
+}
+
+/// Get a label value as a path segment of a pushgateway URL, base64 encoding it if it can't be used as it is.
+fn grouping_label(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+        format!("/{}", value)
+    } else {
+        format!("@base64/{}", base64::encode_config(value, base64::URL_SAFE))
+    }
+}
+
+/// Formats metrics as StatsD packets.
+///
+/// StatsD counters are increments rather than totals, so the value of each counter last sent is kept, and only the
+/// change since then is sent. StatsD has no histograms, so only the sum and count of histograms are sent, as
+/// counters.
+#[derive(Debug)]
+pub struct StatsdFormatter {
+    prefix: String,
+    format: StatsdFormat,
+    sent_counters: HashMap<(&'static str, Labels), f64>,
+}
+
+impl StatsdFormatter {
+    /// Create a formatter for metrics with names starting with given prefix, or no prefix if empty.
+    pub fn new(prefix: &str, format: StatsdFormat) -> Self {
+        Self {
+            prefix: prefix.to_owned(),
+            format,
+            sent_counters: HashMap::new(),
+        }
+    }
+
+    /// Get the lines to send for given samples, split into packets. Counters that haven't changed since they were
+    /// last sent are skipped.
+    pub fn packets(&mut self, samples: &[Sample]) -> Vec<String> {
+        let mut packets: Vec<String> = Vec::new();
+        for sample in samples {
+            let line = match self.line(sample) {
+                Some(line) => line,
+                None => continue,
+            };
+            match packets.last_mut() {
+                Some(packet) if packet.len() + line.len() < MAX_PACKET_LEN => {
+                    packet.push('\n');
+                    packet.push_str(&line);
+                }
+                _ => packets.push(line),
+            }
+        }
+        packets
+    }
+
+    fn line(&mut self, sample: &Sample) -> Option<String> {
+        let value: f64 = sample.value.parse().ok()?;
+        let (value, kind) = match sample.kind {
+            Kind::Gauge => (value, "g"),
+            Kind::Histogram if sample.name.ends_with("_bucket") => return None,
+            Kind::Counter | Kind::Histogram => {
+                let key = (sample.name, sample.labels.clone());
+                let previous = self.sent_counters.insert(key, value).unwrap_or_default();
+                // a counter lower than before means it's been reset, so all of it is new
+                let delta = if value >= previous { value - previous } else { value };
+                if delta == 0.0 {
+                    return None;
+                }
+                (delta, "c")
+            }
+        };
+
+        let name = sample.name.trim_start_matches("ocypod_");
+        let name = if self.prefix.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}.{}", self.prefix, name)
+        };
+        if sample.labels.is_empty() {
+            return Some(format!("{}:{}|{}", name, value, kind));
+        }
+        Some(match self.format {
+            StatsdFormat::Statsd => {
+                let values: Vec<&str> = sample.labels.iter().map(|(_, value)| value.as_str()).collect();
+                format!("{}.{}:{}|{}", name, values.join("."), value, kind)
+            }
+            StatsdFormat::Datadog => {
+                let tags: Vec<String> =
+                    sample.labels.iter().map(|(label, value)| format!("{}:{}", label, value)).collect();
+                format!("{}:{}|{}|#{}", name, value, kind, tags.join(","))
+            }
+        })
+    }
+}
+
+/// Send given packets to the StatsD server at given address.
+pub async fn send_statsd(addr: &str, packets: &[String]) -> io::Result<()> {
+    let target = tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no addresses found for {}", addr)))?;
+    let local: SocketAddr = if target.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let mut socket = UdpSocket::bind(local).await?;
+    for packet in packets {
+        socket.send_to(packet.as_bytes(), &target).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::metrics::Monitor;
+
+    fn sample(name: &'static str, kind: Kind, monitor: Option<Monitor>, value: &str) -> Sample {
+        Sample {
+            name,
+            help: "",
+            kind,
+            labels: monitor.map(|monitor| ("monitor", monitor.label().to_owned())).into_iter().collect(),
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn pushgateway() {
+        assert_eq!(
+            pushgateway_url("http://localhost:9091/", "ocypod", "worker-1"),
+            "http://localhost:9091/metrics/job/ocypod/instance/worker-1"
+        );
+        assert_eq!(
+            pushgateway_url("http://localhost:9091", "ocypod", "a/b"),
+            "http://localhost:9091/metrics/job/ocypod/instance@base64/YS9i"
+        );
+    }
+
+    #[test]
+    fn statsd() {
+        let mut formatter = StatsdFormatter::new("ocypod", StatsdFormat::Statsd);
+        let samples = vec![
+            sample("ocypod_file_writes_total", Kind::Counter, None, "3"),
+            sample("ocypod_monitor_passes_total", Kind::Counter, Some(Monitor::Retry), "2"),
+            sample("ocypod_monitor_last_jobs_transitioned", Kind::Gauge, Some(Monitor::Retry), "0"),
+        ];
+        assert_eq!(
+            formatter.packets(&samples),
+            vec![
+                "ocypod.file_writes_total:3|c\nocypod.monitor_passes_total.retry:2|c\n\
+                 ocypod.monitor_last_jobs_transitioned.retry:0|g"
+            ]
+        );
+
+        // only changes to counters are sent
+        let samples = vec![
+            sample("ocypod_file_writes_total", Kind::Counter, None, "3"),
+            sample("ocypod_monitor_passes_total", Kind::Counter, Some(Monitor::Retry), "5"),
+        ];
+        assert_eq!(formatter.packets(&samples), vec!["ocypod.monitor_passes_total.retry:3|c"]);
+    }
+
+    #[test]
+    fn datadog() {
+        let mut formatter = StatsdFormatter::new("", StatsdFormat::Datadog);
+        let samples = vec![
+            sample("ocypod_monitor_pass_duration_seconds_total", Kind::Counter, Some(Monitor::Push), "1.500000"),
+            sample("ocypod_file_pending", Kind::Gauge, None, "4"),
+        ];
+        assert_eq!(
+            formatter.packets(&samples),
+            vec!["monitor_pass_duration_seconds_total:1.5|c|#monitor:push\nfile_pending:4|g"]
+        );
+    }
+
+    #[test]
+    fn histograms() {
+        let histogram = |name: &'static str, value: &str| Sample {
+            name,
+            help: "",
+            kind: Kind::Histogram,
+            labels: vec![("operation", "dequeue".to_owned()), ("le", "0.001".to_owned())],
+            value: value.to_owned(),
+        };
+        let samples = vec![
+            histogram("ocypod_redis_command_duration_seconds_bucket", "2"),
+            Sample {
+                labels: vec![("operation", "dequeue".to_owned())],
+                ..histogram("ocypod_redis_command_duration_seconds_count", "3")
+            },
+        ];
+        let mut formatter = StatsdFormatter::new("ocypod", StatsdFormat::Statsd);
+        assert_eq!(formatter.packets(&samples), vec!["ocypod.redis_command_duration_seconds_count.dequeue:3|c"]);
+        let mut formatter = StatsdFormatter::new("", StatsdFormat::Datadog);
+        assert_eq!(formatter.packets(&samples), vec!["redis_command_duration_seconds_count:3|c|#operation:dequeue"]);
+    }
+
+    #[test]
+    fn statsd_packets() {
+        let mut formatter = StatsdFormatter::new("ocypod", StatsdFormat::Statsd);
+        let samples: Vec<Sample> = (0..100)
+            .map(|_| sample("ocypod_file_pending", Kind::Gauge, None, "1"))
+            .collect();
+        let packets = formatter.packets(&samples);
+        assert!(packets.len() > 1);
+        assert!(packets.iter().all(|packet| packet.len() <= MAX_PACKET_LEN));
+        assert_eq!(packets.iter().map(|packet| packet.lines().count()).sum::<usize>(), 100);
+    }
+}
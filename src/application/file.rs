@@ -1,10 +1,17 @@
 //! Handles using the file system as a persistence layer for contingency purposes
 
-use std::path::Path;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::{env, fs, str};
 use chrono::Utc;
+use log::{debug, error, info, warn};
 
-use crate::models::job;
+use crate::application::metrics::METRICS;
+use crate::application::shard::RedisShards;
+use crate::config::{AuthConfig, Durability};
+use crate::events::{EventBus, EventKind};
+use crate::models::{job, OcyError, OcyResult};
 
 /// stores various paths for writing files to
 #[derive(Debug)]
@@ -29,58 +36,323 @@ pub fn get_paths() -> Result<SysPaths, Box<dyn std::error::Error>> {
 }
 
 /// gets file contents
-pub fn get_file_contents(filename: &str) -> Result<String, std::io::Error> {
+pub fn get_file_contents(filename: &str) -> Result<String, io::Error> {
     fs::read_to_string(filename)
 }
 
-/// writes job json to a file
-pub fn write_job(queue_name: &str, json: &job::CreateRequest) -> Result<(String, i64), Box<dyn std::error::Error>>  {
-    
+/// Directory holding the files of job creation attempts, each kept until its job is created in Redis.
+const ATTEMPTS_DIR: &str = "queues";
+
+/// Directory holding the files of job creation attempts accepted with a 202 while Redis was unavailable, which are
+/// replayed to Redis once it recovers. Only these attempts are replayed, since any other attempt's request was either
+/// still being handled, or failed, in which case its client was told that no job was created.
+const PENDING_DIR: &str = "pending";
+
+/// Number of IDs available for job creation attempts within each millisecond.
+const IDS_PER_MILLI: i64 = 1000;
+
+/// Last ID given to a job creation attempt, so that attempts made in the same millisecond get different IDs.
+static LAST_ATTEMPT_ID: AtomicI64 = AtomicI64::new(0);
+
+/// gets the directory holding job creation attempts of the given kind for a queue
+fn job_dir(kind: &str, queue_name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
     let paths = get_paths()?;
-    
-    let output_dir = format!("{}/queues/{}", paths.exe, queue_name);
+    Ok(Path::new(&paths.exe).join(kind).join(queue_name))
+}
+
+/// gets the next ID for a job creation attempt, made from the current time in milliseconds followed by a counter
+fn next_attempt_id() -> i64 {
+    let now = Utc::now().timestamp_millis() * IDS_PER_MILLI;
+    let mut last = LAST_ATTEMPT_ID.load(Ordering::Relaxed);
+    loop {
+        let id = now.max(last + 1);
+        match LAST_ATTEMPT_ID.compare_exchange_weak(last, id, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return id,
+            Err(current) => last = current,
+        }
+    }
+}
+
+/// gets the time in milliseconds that a job creation attempt with the given ID was made at
+pub fn attempt_millis(attempt_id: i64) -> i64 {
+    attempt_id / IDS_PER_MILLI
+}
+
+/// writes job json to a file, flushing it to disk first if required by the durability setting
+///
+/// Returns the file's path, and the attempt's ID, which is unique to this attempt and is used as the file's name.
+pub fn write_job(
+    queue_name: &str,
+    json: &job::CreateRequest,
+    durability: Durability,
+) -> Result<(String, i64), Box<dyn std::error::Error>>  {
+
+    let res = write_job_file(queue_name, json, durability);
+
+    METRICS.record_file_write(res.is_ok());
+
+    res
+}
+
+fn write_job_file(
+    queue_name: &str,
+    json: &job::CreateRequest,
+    durability: Durability,
+) -> Result<(String, i64), Box<dyn std::error::Error>>  {
+
+    let output_dir = job_dir(ATTEMPTS_DIR, queue_name)?;
 
     fs::create_dir_all(&output_dir)?;
-    
-    let dt = Utc::now();
-    
-    let timestamp: i64 = dt.timestamp_millis();
 
     let file_contents = serde_json::to_string(json)?;
 
-    let destination = format!("{}/{}.json", output_dir, timestamp);
+    //never overwrite another attempt's file, e.g. one written by another server sharing the directory
+    let (destination, attempt_id, mut file) = loop {
+        let attempt_id = next_attempt_id();
+        let destination = output_dir.join(format!("{}.json", attempt_id));
+        match fs::OpenOptions::new().write(true).create_new(true).open(&destination) {
+            Ok(file) => break (destination, attempt_id, file),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    };
+
+    file.write_all(file_contents.as_bytes())?;
 
-    let _res = fs::write(&destination, file_contents);
+    if durability != Durability::None {
+        file.sync_all()?;
+    }
 
-    Ok((destination, timestamp))
+    //the file's directory entry must also be flushed for the file to be found after a crash
+    if durability == Durability::FsyncDir {
+        fs::File::open(&output_dir)?.sync_all()?;
+    }
+
+    Ok((destination.display().to_string(), attempt_id))
 }
 
-/// get a job creation attempt by the queue_name and timestamp
-pub fn get_job(queue_name: &str, timestamp: i64) -> Result<job::CreateRequest, Box<dyn std::error::Error>>  {
+/// marks a job creation attempt as accepted for replay, moving it to the directory of pending attempts
+///
+/// This must succeed before the attempt's client is told that it was accepted, since only pending attempts are
+/// replayed once Redis recovers.
+pub fn mark_pending(
+    queue_name: &str,
+    attempt_id: i64,
+    durability: Durability,
+) -> Result<(), Box<dyn std::error::Error>> {
 
-    let paths = get_paths()?;
-    
-    let output_dir = format!("{}/queues/{}", paths.exe, queue_name);
+    let attempts_dir = job_dir(ATTEMPTS_DIR, queue_name)?;
 
-    let src = format!("{}/{}.json", output_dir, timestamp);
+    let pending_dir = job_dir(PENDING_DIR, queue_name)?;
 
-    let contents = get_file_contents(&src)?;
+    fs::create_dir_all(&pending_dir)?;
 
-    let create_request = serde_json::from_str(&contents)?;
-  
-    Ok(create_request)
+    let file_name = format!("{}.json", attempt_id);
+
+    fs::rename(attempts_dir.join(&file_name), pending_dir.join(&file_name))?;
+
+    if durability == Durability::FsyncDir {
+        fs::File::open(&pending_dir)?.sync_all()?;
+        fs::File::open(&attempts_dir)?.sync_all()?;
+    }
+
+    Ok(())
 }
 
-///delete job file
-pub fn delete_job(queue_name: &str, timestamp: i64) -> Result<(), Box<dyn std::error::Error>> {
+/// claims a job creation attempt, pending or not, so that it's only created once, e.g. when reattempted while
+/// being replayed, returning its request and whether it was pending, or None if it doesn't exist or is claimed
+///
+/// Once claimed, an attempt must be either deleted, rejected, or released.
+pub fn claim_job(
+    queue_name: &str,
+    attempt_id: i64,
+) -> Result<Option<(job::CreateRequest, bool)>, Box<dyn std::error::Error>> {
 
-    let paths = get_paths()?;
-    
-    let output_dir = format!("{}/queues/{}", paths.exe, queue_name);
+    for (kind, pending) in &[(PENDING_DIR, true), (ATTEMPTS_DIR, false)] {
+        let dir = job_dir(kind, queue_name)?;
+        let claimed = dir.join(format!("{}.claimed", attempt_id));
+        match fs::rename(dir.join(format!("{}.json", attempt_id)), &claimed) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        }
+        return match get_file_contents(&claimed.display().to_string()) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(create_request) => Ok(Some((create_request, *pending))),
+                Err(err) => {
+                    let _rel = release_job(queue_name, attempt_id, *pending);
+                    Err(err.into())
+                }
+            },
+            Err(err) => {
+                let _rel = release_job(queue_name, attempt_id, *pending);
+                Err(err.into())
+            }
+        };
+    }
+
+    Ok(None)
+}
+
+/// releases a claimed job creation attempt, e.g. when its job couldn't be created because Redis is unavailable
+pub fn release_job(queue_name: &str, attempt_id: i64, pending: bool) -> Result<(), Box<dyn std::error::Error>> {
 
-    let dst = format!("{}/{}.json", output_dir, timestamp);
+    let dir = job_dir(if pending { PENDING_DIR } else { ATTEMPTS_DIR }, queue_name)?;
 
-    fs::remove_file(dst)?;
+    fs::rename(
+        dir.join(format!("{}.claimed", attempt_id)),
+        dir.join(format!("{}.json", attempt_id)),
+    )?;
 
     Ok(())
 }
+
+///delete a job creation attempt's file, either unclaimed or claimed
+pub fn delete_job(queue_name: &str, attempt_id: i64, pending: bool) -> Result<(), Box<dyn std::error::Error>> {
+
+    let dir = job_dir(if pending { PENDING_DIR } else { ATTEMPTS_DIR }, queue_name)?;
+
+    match fs::remove_file(dir.join(format!("{}.claimed", attempt_id))) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => fs::remove_file(dir.join(format!("{}.json", attempt_id)))?,
+        res => res?,
+    }
+
+    Ok(())
+}
+
+///mark a claimed pending job creation attempt as rejected, so that it is no longer replayed
+pub fn reject_job(queue_name: &str, attempt_id: i64) -> Result<(), Box<dyn std::error::Error>> {
+
+    let dir = job_dir(PENDING_DIR, queue_name)?;
+
+    fs::rename(
+        dir.join(format!("{}.claimed", attempt_id)),
+        dir.join(format!("{}.rejected", attempt_id)),
+    )?;
+
+    Ok(())
+}
+
+/// count pending job creation attempts waiting on disk to be replayed, or None if they couldn't be listed
+pub fn count_jobs() -> Option<usize> {
+    match list_jobs() {
+        Ok(jobs) => Some(jobs.len()),
+        Err(err) => {
+            warn!("Failed to count job creation requests on disk: {}", err);
+            None
+        }
+    }
+}
+
+/// list all pending job creation attempts waiting on disk to be replayed, as (queue_name, attempt_id) pairs, oldest
+/// first
+pub fn list_jobs() -> Result<Vec<(String, i64)>, Box<dyn std::error::Error>> {
+
+    let paths = get_paths()?;
+
+    let pending_dir = Path::new(&paths.exe).join(PENDING_DIR);
+
+    let mut jobs = Vec::new();
+    if !pending_dir.is_dir() {
+        return Ok(jobs);
+    }
+
+    for queue_entry in fs::read_dir(pending_dir)? {
+        let queue_entry = queue_entry?;
+        if !queue_entry.file_type()?.is_dir() {
+            continue;
+        }
+        let queue_name = queue_entry.file_name().to_string_lossy().into_owned();
+        for job_entry in fs::read_dir(queue_entry.path())? {
+            let job_path = job_entry?.path();
+            if job_path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let stem = job_path.file_stem().and_then(|stem| stem.to_str());
+            if let Some(attempt_id) = stem.and_then(|stem| stem.parse().ok()) {
+                jobs.push((queue_name.clone(), attempt_id));
+            }
+        }
+    }
+    jobs.sort_by_key(|(_, attempt_id)| *attempt_id);
+
+    Ok(jobs)
+}
+
+/// records the time of the original job creation attempt in the job's input, if the input is an object
+pub fn mark_attempted(job_req: &mut job::CreateRequest, attempt_id: i64) {
+    //this will not work in the input value is not an object
+    if let Some(serde_json::Value::Object(input)) = &mut job_req.input {
+        input.insert("attempted_on".to_owned(), attempt_millis(attempt_id).into());
+    }
+}
+
+/// replays all pending job creation attempts waiting on disk to Redis, deleting each file once its job is created
+///
+/// Stops at the first Redis connection error, leaving any remaining files to be replayed later. Jobs are checked
+/// against their namespace's quota, and left to be replayed later while it's exceeded. Jobs that Redis rejects (e.g.
+/// because their queue no longer exists) are marked as rejected rather than retried.
+pub async fn replay_jobs(shards: &RedisShards, auth: &AuthConfig, events: &EventBus) -> OcyResult<usize> {
+    let pending = list_jobs().map_err(|err| OcyError::Internal(err.to_string()))?;
+    let mut replayed = 0;
+
+    for (queue_name, attempt_id) in pending {
+        let mut job_req = match claim_job(&queue_name, attempt_id) {
+            Ok(Some((job_req, _))) => job_req,
+            // already claimed, e.g. being reattempted
+            Ok(None) => continue,
+            Err(err) => {
+                error!("[queue:{}] failed to read job attempt {}: {}", &queue_name, attempt_id, err);
+                continue;
+            }
+        };
+        mark_attempted(&mut job_req, attempt_id);
+
+        match shards.create_job_within_quota(&queue_name, &job_req, auth.queue_quota(&queue_name)).await {
+            Ok(created) => {
+                debug!("[queue:{}] replayed job attempt {} as job {}", &queue_name, attempt_id, created.id());
+                if let job::Created::New(job_id) = created {
+                    events.job_event(EventKind::Created, job_id, Some(&queue_name));
+                }
+                let _del = delete_job(&queue_name, attempt_id, true);
+                METRICS.record_file_replay(true);
+                replayed += 1;
+            }
+            Err(err @ OcyError::RedisConnection(_)) => {
+                if let Err(rel_err) = release_job(&queue_name, attempt_id, true) {
+                    error!("[queue:{}] failed to release job attempt {}: {}", &queue_name, attempt_id, rel_err);
+                }
+                return Err(err);
+            }
+            Err(OcyError::QuotaExceeded(msg)) => {
+                debug!("[queue:{}] postponing job attempt {}: {}", &queue_name, attempt_id, msg);
+                if let Err(rel_err) = release_job(&queue_name, attempt_id, true) {
+                    error!("[queue:{}] failed to release job attempt {}: {}", &queue_name, attempt_id, rel_err);
+                }
+            }
+            Err(err) => {
+                error!("[queue:{}] rejecting job attempt {}: {}", &queue_name, attempt_id, err);
+                let _rej = reject_job(&queue_name, attempt_id);
+                METRICS.record_file_replay(false);
+            }
+        }
+    }
+
+    if replayed > 0 {
+        info!("Replayed {} job(s) accepted while Redis was unavailable", replayed);
+    }
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn attempt_ids_unique() {
+        let before = Utc::now().timestamp_millis();
+        let ids: Vec<i64> = (0..5000).map(|_| next_attempt_id()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(attempt_millis(ids[0]) >= before);
+    }
+}
@@ -1,12 +1,33 @@
 //! Defines most application logic that's based around jobs.
 
-use log::{debug, info};
+use log::{debug, info, warn};
 use redis::{aio::ConnectionLike, AsyncCommands, Pipeline, RedisWrite, ToRedisArgs};
 
-use super::{keys, RedisQueue, RedisTag};
-use crate::models::{job, DateTime, OcyError, OcyResult};
+use super::{clock, crypto, keys, RedisQueue, RedisTag};
+use super::tolerance::HEARTBEAT_TOLERANCE;
+use crate::models::{job, queue, DateTime, Duration, OcyError, OcyResult, Tenant};
 use crate::transaction_async;
 
+/// Moves a job from its queue to the held list, but only if it's still waiting in its queue.
+const HOLD_SCRIPT: &str = r#"
+if redis.call("lrem", KEYS[1], 1, ARGV[1]) == 0 then
+    return 0
+end
+redis.call("rpush", KEYS[2], ARGV[1])
+redis.call("hset", KEYS[3], ARGV[2], 1)
+return 1
+"#;
+
+/// Moves a job from the held list back to its queue, but only if it's still held.
+const RELEASE_HOLD_SCRIPT: &str = r#"
+if redis.call("lrem", KEYS[2], 1, ARGV[1]) == 0 then
+    return 0
+end
+redis.call("lpush", KEYS[1], ARGV[1])
+redis.call("hdel", KEYS[3], ARGV[2])
+return 1
+"#;
+
 /// Convenient wrapper struct for combing a job ID plus a connection.
 #[derive(Debug)]
 pub struct RedisJob {
@@ -46,25 +67,12 @@ impl RedisJob {
             let mut fields: Vec<job::Field> = fields.to_vec();
             let mut hidden_fields = Vec::new();
 
-            // TODO: better handling of internal vs. visible fields here, this is all pretty messy
-            // ended is a derived field from retries and status, so ensure they're present.
-            if fields.contains(&job::Field::Ended) {
-                // If caller didn't request retries, ensure it's a hidden field.
-                if !fields.contains(&job::Field::Retries) {
-                    hidden_fields.push(job::Field::Retries);
-                    fields.push(job::Field::Retries);
-                }
-
-                // If caller didn't request retries_attempted, ensure it's a hidden field.
-                if !fields.contains(&job::Field::RetriesAttempted) {
-                    hidden_fields.push(job::Field::RetriesAttempted);
-                    fields.push(job::Field::RetriesAttempted);
-                }
-
-                // If caller didn't request status, ensure it's a hidden field.
-                if !fields.contains(&job::Field::Status) {
-                    hidden_fields.push(job::Field::Status);
-                    fields.push(job::Field::Status);
+            // derived fields (e.g. ended, run_time) are computed from stored fields, so ensure they're present,
+            // hiding any the caller didn't request
+            for dependency in fields.iter().flat_map(job::Field::dependencies).collect::<Vec<_>>() {
+                if !fields.contains(dependency) {
+                    hidden_fields.push(dependency.clone());
+                    fields.push(dependency.clone());
                 }
             }
 
@@ -106,6 +114,11 @@ impl RedisJob {
         &self.key
     }
 
+    /// Get the Redis key this job is stored under while it's in the trash.
+    pub fn trash_key(&self) -> String {
+        format!("{}{}", keys::TRASH_JOB_PREFIX, self.id)
+    }
+
     /// Create a Redis key for a job from a job ID.
     pub fn build_key(id: u64) -> String {
         format!("{}{}", keys::JOB_PREFIX, id)
@@ -117,6 +130,14 @@ impl RedisJob {
         C: ConnectionLike + Send,
     {
         debug!("[{}] update request: {:?}", &self.key, update_req);
+        let has_error = update_req.error_code.is_some() || update_req.error_details.is_some();
+        if has_error && update_req.status != Some(job::Status::Failed) {
+            return Err(OcyError::bad_request("error_code and error_details can only be given when failing a job"));
+        }
+        if update_req.retry_after.is_some() && update_req.status != Some(job::Status::Failed) {
+            return Err(OcyError::bad_request("retry_after can only be given when failing a job"));
+        }
+
         let _: () = transaction_async!(conn, &[&self.key], {
             let mut pipe = redis::pipe();
             let pipe_ref = pipe.atomic();
@@ -129,6 +150,16 @@ impl RedisJob {
                 info!("[{}] {}", &self.key, status);
             }
 
+            if let Some(ref error_code) = update_req.error_code {
+                pipe_ref.hset(&self.key, job::Field::ErrorCode, error_code);
+            }
+            if let Some(ref error_details) = update_req.error_details {
+                pipe_ref.hset(&self.key, job::Field::ErrorDetails, error_details.to_string());
+            }
+            if let Some(ref retry_after) = update_req.retry_after {
+                pipe_ref.hset(&self.key, job::Field::RetryAfter, retry_after);
+            }
+
             pipe.query_async(conn).await?
         });
         Ok(())
@@ -137,20 +168,46 @@ impl RedisJob {
     /// Add commands to a pipeline to mark this job as completed.
     ///
     /// Note: caller is responsible for ensuring job exists and status change is valid before this is called.
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_lifetimes))]
+    #[allow(clippy::needless_lifetimes)]
     pub fn complete<'b>(&self, pipe: &'b mut Pipeline) -> &'b mut Pipeline {
         pipe.hset(&self.key, job::Field::Status, job::Status::Completed)
-            .hset(&self.key, job::Field::EndedAt, DateTime::now())
+            .hset(&self.key, job::Field::EndedAt, clock::now())
             .lrem(keys::RUNNING_KEY, 1, self.id)
             .lpush(keys::ENDED_KEY, self.id)
             .incr(keys::STAT_JOBS_COMPLETED_KEY, 1)
     }
 
+    /// Add commands to a pipeline to mark this job as completed, recording how long it ran for in its queue's latency
+    /// stats, and recording it as its queue's latest result for its unique key if it has one.
+    ///
+    /// Note: caller is responsible for ensuring job exists and status change is valid before this is called.
+    #[allow(clippy::needless_lifetimes)]
+    async fn index_result_in_pipe<'b, C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        pipe: &'b mut Pipeline,
+    ) -> OcyResult<&'b mut Pipeline> {
+        let (queue, unique_key, started_at): (Option<String>, Option<String>, Option<DateTime>) =
+            conn.hget(&self.key, &[job::Field::Queue, job::Field::UniqueKey, job::Field::StartedAt]).await?;
+        if let Some(queue) = queue {
+            let queue = RedisQueue::from_string(queue)?;
+            if let Some(unique_key) = unique_key {
+                pipe.hset(queue.results_key(), unique_key, self.id).ignore();
+            }
+            if let Some(started_at) = started_at {
+                let now = clock::now();
+                let start_to_end_ms = now.millis_since(&started_at).max(0) as u64;
+                queue.record_run_in_pipe(pipe, queue::Run { ended_at: now.timestamp(), start_to_end_ms });
+            }
+        }
+        Ok(self.complete(pipe))
+    }
+
     /// Add commands to pipeline to re-queue this job so that it can be retried later.
     ///
     /// If `incr_retries` is true, then increment the count of retry attempts for this job. This will
     /// typically be done for automatic retries, but not for manually requested retries.
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_lifetimes))]
+    #[allow(clippy::needless_lifetimes)]
     pub async fn requeue<'b, C: ConnectionLike + Send>(
         &self,
         conn: &mut C,
@@ -158,7 +215,11 @@ impl RedisJob {
         incr_retries: bool,
     ) -> OcyResult<&'b mut Pipeline> {
         let queue = self.queue(conn).await?.ensure_exists(conn).await?; // ensure both job and queue exist
+        let list = self.queue_list(conn).await?;
+        let output_len: i64 = field_len(conn, &self.key, job::Field::Output).await?;
 
+        queue.add_queue_list_in_pipe(pipe, &list);
+        record_storage_in_pipe(pipe, &queue.name, -output_len);
         pipe.hdel(
             &self.key,
             &[
@@ -166,44 +227,136 @@ impl RedisJob {
                 job::Field::EndedAt,
                 job::Field::LastHeartbeat,
                 job::Field::Output,
+                job::Field::RetryAfter,
             ],
         )
         .hset(&self.key, job::Field::Status, job::Status::Queued)
+        .hset(&self.key, job::Field::QueuedAt, clock::now())
         .lrem(keys::FAILED_KEY, 1, self.id)
         .lrem(keys::ENDED_KEY, 1, self.id)
         .lrem(keys::TIMEDOUT_KEY, 1, self.id)
-        .lpush(&queue.jobs_key, self.id)
+        .lrem(keys::QUARANTINED_KEY, 1, self.id)
+        .lpush(queue.queue_list_key(&list), self.id)
         .incr(keys::STAT_JOBS_RETRIED_KEY, 1);
 
         if incr_retries {
             pipe.hincr(&self.key, job::Field::RetriesAttempted, 1);
+        } else {
+            // manually requeued jobs get a clean slate
+            pipe.hdel(&self.key, &[job::Field::PoisonStrikes, job::Field::QuarantineReason]);
         }
 
         Ok(pipe)
     }
 
+    /// Move this running job back to the front of its queue, e.g. if it couldn't be delivered to a worker.
+    ///
+    /// Returns false if the job no longer exists or is no longer running.
+    pub async fn release<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<bool> {
+        let released: bool = transaction_async!(conn, &[&self.key], {
+            match self.status(conn).await {
+                Ok(job::Status::Running) => {
+                    let queue = self.queue(conn).await?;
+                    let list = self.queue_list(conn).await?;
+                    let mut pipe = redis::pipe();
+                    let result: Option<()> = queue
+                        .add_queue_list_in_pipe(pipe.atomic(), &list)
+                        .hdel(&self.key, &[job::Field::StartedAt, job::Field::LastHeartbeat, job::Field::Progress])
+                        .hset(&self.key, job::Field::Status, job::Status::Queued)
+                        .hset(&self.key, job::Field::QueuedAt, clock::now())
+                        .lrem(keys::RUNNING_KEY, 1, self.id)
+                        // jobs are taken from the right, so this is next
+                        .rpush(queue.queue_list_key(&list), self.id)
+                        .query_async(conn)
+                        .await?;
+                    result.map(|_| true)
+                }
+                Ok(_) => Some(false),
+                Err(OcyError::NoSuchJob(_)) => Some(false),
+                Err(err) => return Err(err),
+            }
+        });
+
+        if released {
+            info!("[{}] released back to queue", &self.key);
+        }
+        Ok(released)
+    }
+
+    /// Put this job on hold, moving it out of its queue so that it's not given to workers until released.
+    ///
+    /// Only jobs waiting in their queue can be held.
+    pub async fn hold<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<()> {
+        let queue = self.queue(conn).await?; // only present if job exists
+        let list = self.queue_list(conn).await?;
+        let held: bool = redis::Script::new(HOLD_SCRIPT)
+            .key(queue.queue_list_key(&list))
+            .key(keys::HELD_KEY)
+            .key(&self.key)
+            .arg(self.id)
+            .arg(job::Field::Held)
+            .invoke_async(conn)
+            .await?;
+
+        if !held {
+            return Err(OcyError::conflict(format!("Cannot hold job {}, job is not queued", self.id)));
+        }
+        info!("[{}] held", &self.key);
+        Ok(())
+    }
+
+    /// Release this job from hold, moving it to the back of its queue.
+    pub async fn release_hold<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<()> {
+        let queue = self.queue(conn).await?.ensure_exists(conn).await?; // ensure both job and queue exist
+        let list = self.queue_list(conn).await?;
+        let released: bool = redis::Script::new(RELEASE_HOLD_SCRIPT)
+            .key(queue.queue_list_key(&list))
+            .key(keys::HELD_KEY)
+            .key(&self.key)
+            .arg(self.id)
+            .arg(job::Field::Held)
+            .invoke_async(conn)
+            .await?;
+
+        if !released {
+            return Err(OcyError::conflict(format!("Cannot release job {}, job is not on hold", self.id)));
+        }
+        let _: () = conn.hset(&self.key, job::Field::QueuedAt, clock::now()).await?;
+        if list != job::QueueList::default() {
+            let _: () = queue.add_queue_list_in_pipe(&mut redis::pipe(), &list).query_async(conn).await?;
+        }
+        info!("[{}] released from hold", &self.key);
+        Ok(())
+    }
+
     /// Add commands to pipeline to re-queue this job so that it can be retried later.
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_lifetimes))]
+    #[allow(clippy::needless_lifetimes)]
     pub async fn cancel<'b, C: ConnectionLike + Send>(
         &self,
         conn: &mut C,
         pipe: &'b mut Pipeline,
     ) -> OcyResult<&'b mut Pipeline> {
         let queue = self.queue(conn).await?; // only present if job exists
+        let list = self.queue_list(conn).await?;
 
         Ok(pipe
             .hset(&self.key, job::Field::Status, job::Status::Cancelled)
-            .hset(&self.key, job::Field::EndedAt, DateTime::now())
+            .hset(&self.key, job::Field::EndedAt, clock::now())
             .lrem(keys::RUNNING_KEY, 1, self.id) // remove from running queue if present
             .lrem(keys::FAILED_KEY, 1, self.id) // remove from failed queue if present
             .lrem(keys::TIMEDOUT_KEY, 1, self.id) // remove from timedout queue if present
-            .lrem(&queue.jobs_key, 1, self.id) // remove from original queue if present
+            .lrem(keys::QUARANTINED_KEY, 1, self.id) // remove from quarantined queue if present
+            .lrem(keys::HELD_KEY, 1, self.id) // remove from held queue if present
+            .hdel(&self.key, job::Field::Held)
+            .lrem(queue.queue_list_key(&list), 1, self.id) // remove from original queue if present
             .rpush(keys::ENDED_KEY, self.id) // add to ended queue
             .incr(keys::STAT_JOBS_CANCELLED_KEY, 1))
     }
 
     /// Add commands to pipeline to mark this job as failed or timed out.
     ///
+    /// Timing out always counts as a poison strike against the job, see `strike_if_quick_fail` for failures.
+    ///
     /// Note: caller is responsible for ensuring job exists and status change is valid before this is called.
     pub fn fail<'b>(&self, pipe: &'b mut Pipeline, status: &job::Status) -> &'b mut Pipeline {
         assert!(status == &job::Status::TimedOut || status == &job::Status::Failed);
@@ -213,13 +366,136 @@ impl RedisJob {
             _ => panic!("fail() was called with invalid status of: {}", status),
         };
 
-        pipe.hset(&self.key, job::Field::Status, status)
-            .hset(&self.key, job::Field::EndedAt, DateTime::now())
+        if status == &job::Status::TimedOut {
+            pipe.hincr(&self.key, job::Field::PoisonStrikes, 1);
+        }
+
+        // clear any error and retry hint from a previous attempt
+        pipe.hdel(&self.key, &[job::Field::ErrorCode, job::Field::ErrorDetails, job::Field::RetryAfter])
+            .hset(&self.key, job::Field::Status, status)
+            .hset(&self.key, job::Field::EndedAt, clock::now())
             .lrem(keys::RUNNING_KEY, 1, self.id)
             .rpush(keys::FAILED_KEY, self.id)
             .incr(stats_key, 1)
     }
 
+    /// Add commands to pipeline to record a poison strike against this running job if it's failing within its quick
+    /// fail window, i.e. it most likely crashed its worker, or can never succeed.
+    #[allow(clippy::needless_lifetimes)]
+    pub async fn strike_if_quick_fail<'b, C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        pipe: &'b mut Pipeline,
+    ) -> OcyResult<&'b mut Pipeline> {
+        let (started_at, window): (Option<DateTime>, Option<Duration>) = conn
+            .hget(&self.key, &[job::Field::StartedAt, job::Field::QuickFailWindow])
+            .await?;
+
+        if let (Some(started_at), Some(window)) = (started_at, window) {
+            let window_seconds = window.as_secs();
+            if window_seconds > 0 && clock::now().seconds_since(&started_at).max(0) as u64 <= window_seconds {
+                pipe.hincr(&self.key, job::Field::PoisonStrikes, 1);
+            }
+        }
+
+        Ok(pipe)
+    }
+
+    /// Move this job to the quarantined queue, if it's failed or timed out and has run out of poison strikes.
+    ///
+    /// Returns false if the job no longer exists, or should no longer be quarantined.
+    pub async fn quarantine<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<bool> {
+        let quarantined: bool = transaction_async!(conn, &[&self.key], {
+            let retry_meta = job::RetryMeta::from_conn(conn, &self.key).await?;
+            match retry_meta.retry_action() {
+                job::RetryAction::Quarantine => {
+                    let reason = format!(
+                        "timed out or failed shortly after starting {} time(s)",
+                        retry_meta.poison_strikes()
+                    );
+                    let mut pipe = redis::pipe();
+                    let result: Option<()> =
+                        self.quarantine_from(pipe.atomic(), keys::FAILED_KEY, &reason).query_async(conn).await?;
+                    result.map(|_| true)
+                }
+                _ => Some(false),
+            }
+        });
+
+        if quarantined {
+            warn!("[{}] quarantined", &self.key);
+        }
+        Ok(quarantined)
+    }
+
+    /// Add commands to a pipeline to move this job from the list with given key to the quarantined queue, ending it
+    /// with given reason.
+    #[allow(clippy::needless_lifetimes)]
+    fn quarantine_from<'b>(&self, pipe: &'b mut Pipeline, list_key: &str, reason: &str) -> &'b mut Pipeline {
+        pipe.hset(&self.key, job::Field::Status, job::Status::Quarantined)
+            .hset(&self.key, job::Field::QuarantineReason, reason)
+            .hset(&self.key, job::Field::EndedAt, clock::now())
+            .lrem(list_key, 1, self.id)
+            .rpush(keys::QUARANTINED_KEY, self.id)
+    }
+
+    /// Move this job from its queue to the quarantined queue, if it's still queued, and was queued longer ago than
+    /// given maximum age.
+    ///
+    /// Returns false if the job no longer exists, or should no longer be quarantined.
+    pub async fn quarantine_if_expired_in_queue<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        max_age: &Duration,
+    ) -> OcyResult<bool> {
+        let quarantined: bool = transaction_async!(conn, &[&self.key], {
+            if job::QueueAgeMeta::from_conn(conn, &self.key).await?.has_expired_in_queue(max_age) {
+                let queue = self.queue(conn).await?;
+                let list = self.queue_list(conn).await?;
+                let mut pipe = redis::pipe();
+                let result: Option<()> = self
+                    .quarantine_from(pipe.atomic(), &queue.queue_list_key(&list), job::EXPIRED_IN_QUEUE_REASON)
+                    .query_async(conn)
+                    .await?;
+                result.map(|_| true)
+            } else {
+                Some(false)
+            }
+        });
+
+        if quarantined {
+            warn!("[{}] quarantined, not started within its queue's max age", &self.key);
+        }
+        Ok(quarantined)
+    }
+
+    /// Stop tracking this job's SLA deadline, marking it as having breached its SLA if it wasn't completed or
+    /// cancelled by its deadline. Returns true if the job breached its SLA.
+    pub async fn check_sla<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<bool> {
+        let breached: bool = transaction_async!(conn, &[&self.key], {
+            let sla_meta = job::SlaMeta::from_conn(conn, &self.key).await?;
+            let breached = sla_meta.sla_outcome() == job::SlaOutcome::Breached;
+            let mut pipe = redis::pipe();
+            let pipe_ref = pipe.atomic().zrem(keys::SLA_DEADLINES_KEY, self.id).ignore();
+            if breached {
+                pipe_ref
+                    .hset(&self.key, job::Field::SlaBreached, true)
+                    .ignore()
+                    .rpush(keys::SLA_BREACHED_KEY, self.id)
+                    .ignore()
+                    .incr(keys::STAT_JOBS_SLA_BREACHED_KEY, 1)
+                    .ignore();
+            }
+            let result: Option<()> = pipe_ref.query_async(conn).await?;
+            result.map(|_| breached)
+        });
+
+        if breached {
+            warn!("[{}] breached SLA", &self.key);
+        }
+        Ok(breached)
+    }
+
     /// Move this job to the ended queue if it's failed or timed out.
     pub async fn end_failed<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<bool> {
         let result: bool = transaction_async!(conn, &[&self.key], {
@@ -275,6 +551,14 @@ impl RedisJob {
         }
     }
 
+    /// Get which of its queue's lists this job is kept in while queued.
+    pub async fn queue_list<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<job::QueueList> {
+        let (routing_key, session_key, serialization_key) = conn
+            .hget(&self.key, &[job::Field::RoutingKey, job::Field::SessionKey, job::Field::SerializationKey])
+            .await?;
+        Ok(job::QueueList { routing_key, session_key, serialization_key })
+    }
+
     /// Get this job's output field.
     pub async fn output<C: ConnectionLike + Send>(
         &self,
@@ -293,7 +577,7 @@ impl RedisJob {
         if exists {
             match output {
                 // JSON parse error should never happen unless someone manually writes data to Redis outside of Ocypod.
-                Some(ref s) => Ok(serde_json::from_str(s)?),
+                Some(s) => Ok(serde_json::from_str(&crypto::open(s)?)?),
                 None => Ok(serde_json::Value::Null),
             }
         } else {
@@ -309,14 +593,14 @@ impl RedisJob {
     ) -> OcyResult<()> {
         let _: () = transaction_async!(conn, &[&self.key], {
             let mut pipe = redis::pipe();
-            let pipe_ref = self.set_output_in_pipe(conn, pipe.atomic(), &value).await?;
+            let pipe_ref = self.set_output_in_pipe(conn, pipe.atomic(), value).await?;
             pipe_ref.query_async(conn).await?
         });
         Ok(())
     }
 
     /// Add commands to update this job's output to a pipeline.
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_lifetimes))]
+    #[allow(clippy::needless_lifetimes)]
     pub async fn set_output_in_pipe<'b, C: ConnectionLike + Send>(
         &self,
         conn: &mut C,
@@ -324,17 +608,63 @@ impl RedisJob {
         value: &serde_json::Value,
     ) -> OcyResult<&'b mut Pipeline> {
         match self.status(conn).await? {
-            job::Status::Running => Ok(pipe.hset(&self.key, job::Field::Output, value.to_string())),
+            job::Status::Running => {
+                let queue = self.queue(conn).await?;
+                let replacement = queue.fit_output(conn, self.id, value).await?;
+                let output = crypto::seal(replacement.as_ref().unwrap_or(value).to_string());
+                let old_len: i64 = field_len(conn, &self.key, job::Field::Output).await?;
+                record_storage_in_pipe(pipe, &queue.name, output.len() as i64 - old_len);
+                pipe.hset(&self.key, job::Field::Output, output);
+                if replacement.is_some() {
+                    Ok(pipe.hset(&self.key, job::Field::OutputTruncated, true))
+                } else {
+                    Ok(pipe.hdel(&self.key, job::Field::OutputTruncated))
+                }
+            }
             _ => Err(OcyError::conflict("Can only set output for running jobs")),
         }
     }
 
+    /// Replace this job's input in a transaction, only allowed while the job is still queued.
+    ///
+    /// Setting the input to `null` removes it.
+    pub async fn set_input<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        value: &serde_json::Value,
+    ) -> OcyResult<()> {
+        let _: () = transaction_async!(conn, &[&self.key], {
+            if self.status(conn).await? != job::Status::Queued {
+                return Err(OcyError::conflict("Can only set input for queued jobs"));
+            }
+            let queue = self.queue(conn).await?;
+            queue.check_input_size(conn, value).await?;
+            let old_len: i64 = field_len(conn, &self.key, job::Field::Input).await?;
+
+            let mut pipe = redis::pipe();
+            match value {
+                serde_json::Value::Null => {
+                    record_storage_in_pipe(&mut pipe, &queue.name, -old_len);
+                    pipe.atomic().hdel(&self.key, job::Field::Input)
+                }
+                _ => {
+                    let input = crypto::seal(value.to_string());
+                    record_storage_in_pipe(&mut pipe, &queue.name, input.len() as i64 - old_len);
+                    pipe.atomic().hset(&self.key, job::Field::Input, input)
+                }
+            };
+            pipe.query_async(conn).await?
+        });
+        info!("[{}] input updated", &self.key);
+        Ok(())
+    }
+
     /// Get this job's status field.
     pub async fn status<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<job::Status> {
         debug!("Fetching job status for job_id={}", self.id);
         conn.hget::<_, _, Option<job::Status>>(&self.key, job::Field::Status)
             .await?
-            .ok_or_else(|| OcyError::NoSuchJob(self.id))
+            .ok_or(OcyError::NoSuchJob(self.id))
     }
 
     /// Update this job's status field in a transaction.
@@ -347,7 +677,7 @@ impl RedisJob {
         let watch_keys = match status {
             job::Status::Queued => vec![
                 self.key.to_owned(),
-                self.queue(conn).await?.jobs_key,
+                self.queue(conn).await?.queue_list_key(&self.queue_list(conn).await?),
             ],
             _ => vec![self.key.to_owned()],
         };
@@ -365,7 +695,7 @@ impl RedisJob {
     /// Add commands to update this job's status to a pipeline.
     ///
     /// Checks the validity of status transitions.
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_lifetimes))]
+    #[allow(clippy::needless_lifetimes)]
     pub async fn set_status_in_pipe<'b, C: ConnectionLike + Send>(
         &self,
         conn: &mut C,
@@ -378,21 +708,20 @@ impl RedisJob {
             self.id, &current_status, &status
         );
 
-        // ensure status transitions are valid
-        Ok(match (current_status, status) {
-            (job::Status::Running, job::Status::Completed) => self.complete(pipe),
-            (job::Status::Running, cause @ job::Status::Failed) => self.fail(pipe, cause),
-            (job::Status::Running, cause @ job::Status::TimedOut) => self.fail(pipe, cause),
-            (job::Status::Running, job::Status::Cancelled) => self.cancel(conn, pipe).await?,
-            (job::Status::Failed, job::Status::Cancelled) => self.cancel(conn, pipe).await?,
-            (job::Status::Queued, job::Status::Cancelled) => self.cancel(conn, pipe).await?,
-            (job::Status::Cancelled, job::Status::Queued) => {
-                self.requeue(conn, pipe, false).await?
+        // ensure status transitions are valid, and allowed by the job's queue
+        let transition = job::Transition::new(current_status, status.clone());
+        self.queue(conn).await?.check_transition(conn, &transition).await?;
+        Ok(match status {
+            job::Status::Completed => self.index_result_in_pipe(conn, pipe).await?,
+            cause @ job::Status::Failed => {
+                let pipe = self.strike_if_quick_fail(conn, pipe).await?;
+                self.fail(pipe, cause)
             }
-            (job::Status::Failed, job::Status::Queued) => self.requeue(conn, pipe, false).await?,
-            (job::Status::TimedOut, job::Status::Queued) => self.requeue(conn, pipe, false).await?,
-            (from, to) => {
-                return Err(OcyError::conflict(format!("Cannot change status from {} to {}", from, to)))
+            cause @ job::Status::TimedOut => self.fail(pipe, cause),
+            job::Status::Cancelled => self.cancel(conn, pipe).await?,
+            job::Status::Queued => self.requeue(conn, pipe, false).await?,
+            job::Status::Running | job::Status::Quarantined => {
+                return Err(OcyError::conflict(format!("Cannot change status to {}", status)))
             }
         })
     }
@@ -407,7 +736,7 @@ impl RedisJob {
 
             redis::pipe()
                 .atomic()
-                .hset(&self.key, job::Field::LastHeartbeat, DateTime::now())
+                .hset(&self.key, job::Field::LastHeartbeat, clock::now())
                 .ignore()
                 .query_async(conn)
                 .await?
@@ -422,16 +751,19 @@ impl RedisJob {
             // only existing/running jobs can have heartbeat updated
             let job_status = self.status(conn).await?;
 
-            if !vec![job::Status::TimedOut, job::Status::Failed].contains(&job_status) {
+            if ![job::Status::TimedOut, job::Status::Failed].contains(&job_status) {
                 return Err(OcyError::conflict(format!("Cannot retry job {}, job is not failed or timed_out", self.id)));
             }
+            let transition = job::Transition::new(job_status, job::Status::Queued);
+            self.queue(conn).await?.check_transition(conn, &transition).await?;
             
-            self.requeue(conn, redis::pipe().atomic(), true)
+            let _: () = self.requeue(conn, redis::pipe().atomic(), true)
                 .await?
                 .query_async(conn)
                 .await?;
             
             let input: Option<String> = conn.hget(&self.key, job::Field::Input).await?;
+            let input = input.map(crypto::open).transpose()?;
             
             let payload =
                 job::Payload::new(self.id(), input.map(|s| serde_json::from_str(&s).unwrap()));
@@ -439,7 +771,8 @@ impl RedisJob {
             let result: Option<()> = redis::pipe()
                 .atomic()
                 .hset(&self.key, job::Field::Status, job::Status::Running)
-                .hset(&self.key, job::Field::StartedAt, DateTime::now())
+                .hset(&self.key, job::Field::StartedAt, clock::now())
+                .hdel(&self.key, job::Field::Progress)
                 .rpush(keys::RUNNING_KEY, self.id())
                 .query_async(conn)
                 .await?;
@@ -456,12 +789,15 @@ impl RedisJob {
     ///
     /// Callers should typically check for job expiry outside of a transaction (and probably in a pipeline),
     /// then only call this on jobs to expire (since transaction here is much more expensive).
-    pub async fn apply_expiry<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<bool> {
+    pub async fn apply_expiry<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        sweep: &queue::ExpirySweep,
+    ) -> OcyResult<bool> {
         let expired: bool = transaction_async!(conn, &[&self.key], {
-            if job::ExpiryMeta::from_conn(conn, &self.key)
-                .await?
-                .should_expire()
-            {
+            let expiry_meta = job::ExpiryMeta::from_conn(conn, &self.key).await?;
+            let statuses = expiry_meta.queue().and_then(|queue| sweep.statuses(&queue));
+            if statuses.is_some_and(|statuses| expiry_meta.should_expire(statuses)) {
                 let result: Option<()> = self
                     .delete_in_pipe(conn, redis::pipe().atomic())
                     .await?
@@ -489,7 +825,7 @@ impl RedisJob {
             // on timeout to confirm that this job should still timeout within a transaction
             if job::TimeoutMeta::from_conn(conn, &self.key)
                 .await?
-                .has_timed_out()
+                .has_timed_out(HEARTBEAT_TOLERANCE.factor())
             {
                 let result: Option<()> = self
                     .fail(redis::pipe().atomic(), &job::Status::TimedOut)
@@ -563,33 +899,193 @@ impl RedisJob {
     }
 
     /// Add commands to delete this job status to a pipeline.
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_lifetimes))]
+    #[allow(clippy::needless_lifetimes)]
     pub async fn delete_in_pipe<'b, C: ConnectionLike + Send>(
         &self,
         conn: &mut C,
         pipe: &'b mut Pipeline,
     ) -> OcyResult<&'b mut Pipeline> {
-        let (queue, tags): (Option<String>, Option<String>) = conn
-            .hget(&self.key, &[job::Field::Queue, job::Field::Tags])
+        let pipe = self.unindex_in_pipe(conn, pipe).await?;
+        Ok(pipe.del(&self.key)) // always delete job itself
+    }
+
+    /// Move this job to the trash, where it can be restored until `recovery_window` has elapsed.
+    ///
+    /// Returns `true` if this job was moved to the trash, `false` if the job wasn't found.
+    pub async fn trash<C: ConnectionLike + Send>(&self, conn: &mut C, recovery_window: &Duration) -> OcyResult<bool> {
+        let purge_at = clock::now().plus(recovery_window).timestamp();
+        let trashed: bool = transaction_async!(conn, &[&self.key], {
+            let mut pipe = redis::pipe();
+            let pipe_ref = pipe.atomic();
+            match self.unindex_in_pipe(conn, pipe_ref).await {
+                Ok(p) => {
+                    let result: Option<()> = p
+                        .rename(&self.key, &self.trash_key())
+                        .ignore()
+                        .zadd(keys::TRASH_KEY, self.id, purge_at)
+                        .ignore()
+                        .query_async(conn)
+                        .await?;
+                    result.map(|_| true)
+                }
+                Err(OcyError::NoSuchJob(_)) => Some(false), // job already deleted
+                Err(err) => return Err(err),
+            }
+        });
+
+        if trashed {
+            info!("[{}] moved to trash", &self.key);
+        }
+        Ok(trashed)
+    }
+
+    /// Restore this job from the trash, adding it back to the queues and tags it was removed from.
+    ///
+    /// Queued jobs are added to the back of their queue, and running jobs are still checked for timeouts.
+    pub async fn restore<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<()> {
+        let trash_key = self.trash_key();
+        let _: () = transaction_async!(conn, &[&self.key, &trash_key], {
+            let queue_name: Option<String> = conn.hget(&trash_key, job::Field::Queue).await?;
+            let queue = match queue_name {
+                Some(queue_name) => RedisQueue::from_string(queue_name)?,
+                None => return Err(OcyError::NoSuchJob(self.id)), // not in trash
+            };
+            let storage_bytes = Self::storage_bytes(conn, &trash_key).await?;
+
+            let fields = &[
+                job::Field::Status,
+                job::Field::Tags,
+                job::Field::Retries,
+                job::Field::RetriesAttempted,
+                job::Field::Deadline,
+                job::Field::SlaBreached,
+                job::Field::RoutingKey,
+                job::Field::SessionKey,
+                job::Field::SerializationKey,
+            ];
+            let v: redis::Value = conn.hget(&trash_key, fields).await?;
+            let job_meta = job::JobMeta::from_redis_value(fields, &v, &[])?;
+            let status = job_meta.status();
+            let queue = if status == job::Status::Queued {
+                queue.ensure_exists(conn).await? // queued jobs can only be restored to an existing queue
+            } else {
+                queue
+            };
+
+            let mut pipe = redis::pipe();
+            let pipe_ref = pipe
+                .atomic()
+                .rename(&trash_key, &self.key)
+                .ignore()
+                .zrem(keys::TRASH_KEY, self.id)
+                .ignore();
+            record_storage_in_pipe(pipe_ref, &queue.name, storage_bytes);
+
+            match status {
+                job::Status::Queued => {
+                    let list = job_meta.queue_list();
+                    queue.add_queue_list_in_pipe(pipe_ref, &list);
+                    pipe_ref
+                        .hdel(&self.key, job::Field::Held)
+                        .hset(&self.key, job::Field::QueuedAt, clock::now())
+                        .lpush(queue.queue_list_key(&list), self.id)
+                }
+                job::Status::Running => pipe_ref.rpush(keys::RUNNING_KEY, self.id),
+                job::Status::Quarantined => pipe_ref.rpush(keys::QUARANTINED_KEY, self.id),
+                job::Status::Failed | job::Status::TimedOut if !job_meta.ended() => {
+                    pipe_ref.rpush(keys::FAILED_KEY, self.id)
+                }
+                job::Status::TimedOut => pipe_ref.rpush(keys::TIMEDOUT_KEY, self.id).rpush(keys::ENDED_KEY, self.id),
+                job::Status::Failed | job::Status::Completed | job::Status::Cancelled => {
+                    pipe_ref.rpush(keys::ENDED_KEY, self.id)
+                }
+            }
+            .ignore();
+
+            if job_meta.sla_breached() {
+                pipe_ref.rpush(keys::SLA_BREACHED_KEY, self.id).ignore();
+            } else if let Some(deadline) = job_meta.deadline() {
+                pipe_ref.zadd(keys::SLA_DEADLINES_KEY, self.id, deadline.timestamp()).ignore();
+            }
+
+            if let Some(tags) = job_meta.tags() {
+                for tag in tags {
+                    pipe_ref.sadd(RedisTag::build_key(&tag), self.id).ignore();
+                }
+            }
+
+            pipe_ref.query_async(conn).await?
+        });
+
+        info!("[{}] restored from trash", &self.key);
+        Ok(())
+    }
+
+    /// Get the total size in bytes of the input and output stored in the job hash with given key.
+    async fn storage_bytes<C: ConnectionLike + Send>(conn: &mut C, key: &str) -> OcyResult<i64> {
+        let (input_len, output_len): (i64, i64) = redis::pipe()
+            .cmd("HSTRLEN")
+            .arg(key)
+            .arg(job::Field::Input)
+            .cmd("HSTRLEN")
+            .arg(key)
+            .arg(job::Field::Output)
+            .query_async(conn)
+            .await?;
+        Ok(input_len + output_len)
+    }
+
+    /// Add commands to remove this job from all queues and tags it might be in to a pipeline, without deleting
+    /// the job itself.
+    #[allow(clippy::needless_lifetimes)]
+    async fn unindex_in_pipe<'b, C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        pipe: &'b mut Pipeline,
+    ) -> OcyResult<&'b mut Pipeline> {
+        let (queue, tags, unique_key): (Option<String>, Option<String>, Option<String>) = conn
+            .hget(&self.key, &[job::Field::Queue, job::Field::Tags, job::Field::UniqueKey])
             .await?;
 
         if let Some(queue) = queue {
-            pipe.lrem(RedisQueue::build_jobs_key(&queue), 1, self.id)
+            let queue = RedisQueue::from_string(queue)?;
+            let list = self.queue_list(conn).await?;
+            let storage_bytes = Self::storage_bytes(conn, &self.key).await?;
+            record_storage_in_pipe(pipe, &queue.name, -storage_bytes);
+            pipe.lrem(queue.queue_list_key(&list), 1, self.id)
                 .ignore();
+            if let Some(unique_key) = unique_key {
+                // a newer job may have been created with the same unique key since
+                let unique_job_id: Option<u64> = conn.hget(queue.unique_jobs_key(), &unique_key).await?;
+                if unique_job_id == Some(self.id) {
+                    pipe.hdel(queue.unique_jobs_key(), &unique_key).ignore();
+                }
+                let result_job_id: Option<u64> = conn.hget(queue.results_key(), &unique_key).await?;
+                if result_job_id == Some(self.id) {
+                    pipe.hdel(queue.results_key(), unique_key).ignore();
+                }
+            }
         } else {
             // queue is mandatory field, if missing then means job has been deleted
             return Err(OcyError::NoSuchJob(self.id));
         }
 
-        // delete job itself, and remove it from all global queues it might be in
-        pipe.del(&self.key) // always delete job itself
-            .lrem(keys::FAILED_KEY, 1, self.id)
+        // remove job from all global queues it might be in
+        pipe.lrem(keys::FAILED_KEY, 1, self.id)
             .ignore()
             .lrem(keys::RUNNING_KEY, 1, self.id)
             .ignore()
             .lrem(keys::ENDED_KEY, 1, self.id)
             .ignore()
             .lrem(keys::TIMEDOUT_KEY, 1, self.id)
+            .ignore()
+            .lrem(keys::QUARANTINED_KEY, 1, self.id)
+            .ignore()
+            .lrem(keys::HELD_KEY, 1, self.id)
+            .ignore()
+            .zrem(keys::SLA_DEADLINES_KEY, self.id)
+            .ignore()
+            .lrem(keys::SLA_BREACHED_KEY, 1, self.id)
             .ignore();
 
 
@@ -601,4 +1097,16 @@ impl RedisJob {
 
         Ok(pipe)
     }
-}
\ No newline at end of file
+}
+/// Get the length in bytes of given field of the job hash with given key, or 0 if it's not set.
+async fn field_len<C: ConnectionLike + Send>(conn: &mut C, key: &str, field: job::Field) -> OcyResult<i64> {
+    Ok(redis::cmd("HSTRLEN").arg(key).arg(field).query_async(conn).await?)
+}
+
+/// Add a command recording a change in the storage used by the namespace of given queue to a pipeline, if the queue is
+/// in a namespace.
+pub(crate) fn record_storage_in_pipe(pipe: &mut Pipeline, queue_name: &str, delta: i64) {
+    if let (Some(namespace), true) = (Tenant::namespace_of(queue_name), delta != 0) {
+        pipe.hincr(keys::NAMESPACE_STORAGE_KEY, namespace, delta).ignore();
+    }
+}
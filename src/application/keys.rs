@@ -23,9 +23,60 @@ pub const TIMEDOUT_KEY: &str = "ocypod:timedout";
 /// or failed/timed out with no remaining retries to attempted. Jobs in this queue are monitored for expiry.
 pub const ENDED_KEY: &str = "ocypod:ended";
 
+/// Redis key for the quarantined job list. Jobs are moved here from the failed queue when they've repeatedly timed
+/// out or failed shortly after starting. Jobs in this queue are never retried or expired automatically.
+pub const QUARANTINED_KEY: &str = "ocypod:quarantined";
+
+/// Redis key for the held job list. Queued jobs are moved here from their queue when put on hold, so that they're not
+/// given to workers, and moved back to their queue when released.
+pub const HELD_KEY: &str = "ocypod:held";
+
+/// Redis key for the SLA deadline set. Jobs with a deadline are added here scored by their deadline's Unix
+/// timestamp, and are checked for SLA breaches once their deadline has passed.
+pub const SLA_DEADLINES_KEY: &str = "ocypod:sla_deadlines";
+
+/// Redis key for the SLA breach list. Jobs are added here when they've failed to complete by their deadline, and
+/// remain until deleted.
+pub const SLA_BREACHED_KEY: &str = "ocypod:sla_breached";
+
+/// Redis key for the trash set. Deleted jobs are added here scored by the Unix timestamp they can be permanently
+/// removed at, until then they can be restored.
+pub const TRASH_KEY: &str = "ocypod:trash";
+
+/// Redis key for the storage used by each namespace's jobs. This is a hash mapping namespaces to the total size in
+/// bytes of the inputs and outputs of their jobs on this Redis instance, updated whenever jobs are created, updated,
+/// or deleted, so that storage quotas can be checked without reading every job.
+pub const NAMESPACE_STORAGE_KEY: &str = "ocypod:namespace_storage";
+
 /// Redis key for the job ID counter. This is used as a counter to generate unique IDs for each job.
 pub const JOB_ID_KEY: &str = "ocypod:job_id";
 
+/// Redis key for the version of the key layout Ocypod's data is stored in. Used to check that a server is compatible
+/// with existing data at startup, and to determine which migrations are needed to upgrade it.
+pub const SCHEMA_VERSION_KEY: &str = "ocypod:schema_version";
+
+/// Redis key for the leader lock. When leader election is enabled, this holds the instance ID of the server that runs
+/// the timeout, retry, and expiry monitors, and expires unless regularly renewed by that server.
+pub const LEADER_KEY: &str = "ocypod:leader";
+
+/// Redis key for the hash of workers that have been asked to drain, keyed by worker ID, with the date/time each started
+/// draining. Stored on the primary shard, so that every server refuses to give new jobs to draining workers.
+pub const DRAINING_WORKERS_KEY: &str = "ocypod:draining_workers";
+
+/// Prefix used for keys counting the jobs that have finished on each queue, for notification rules. Each key is a hash
+/// counting the jobs finishing in a short time bucket, starting at the Unix timestamp it's suffixed with, e.g.
+/// "ocypod:notify:counts:1700000000", with fields such as "failed:foo" for jobs that failed on queue "foo".
+pub const NOTIFY_COUNTS_PREFIX: &str = "ocypod:notify:counts:";
+
+/// Prefix used for keys holding queues' health as tracked by each health rule. Each key is a hash for a rule,
+/// suffixed with its position in the configuration, e.g. "ocypod:notify:health:0", with fields such as
+/// "foo:successes" for queue "foo".
+pub const NOTIFY_HEALTH_PREFIX: &str = "ocypod:notify:health:";
+
+/// Prefix used for keys set when a notification rule is triggered for a queue, which expire at the end of its
+/// cooldown. A rule at position 0 in the configuration triggered for queue "foo" sets "ocypod:notify:fired:0:foo".
+pub const NOTIFY_FIRED_PREFIX: &str = "ocypod:notify:fired:";
+
 /// Prefix used for queue settings keys in Redis. A user created queue with name "foo" have its configuration stored
 /// under the key "queue:foo".
 pub const QUEUE_PREFIX: &str = "ocypod:queue:";
@@ -33,12 +84,71 @@ pub const QUEUE_PREFIX: &str = "ocypod:queue:";
 /// Prefix used for job keys in Redis. A job with the ID 123 would be stored under the key "job:123".
 pub const JOB_PREFIX: &str = "ocypod:job:";
 
+/// Prefix used for deleted job keys in Redis. A deleted job with the ID 123 would be stored under the key
+/// "trash:job:123" until it's restored or permanently removed.
+pub const TRASH_JOB_PREFIX: &str = "ocypod:trash:job:";
+
 /// Suffix used with queue keys get the Redis key for queued jobs. A user created queue with name "foo" would store
 /// its queued jobs under the key "queue:foo:jobs";
 pub const QUEUE_JOBS_SUFFIX: &str = ":jobs";
 
+/// Infix used with queue keys and routing keys to get the Redis key for queued jobs with a routing key. A user created
+/// queue with name "foo" would store its queued jobs with routing key "gpu" under the key "queue:foo:routed:gpu:jobs".
+pub const QUEUE_ROUTED_INFIX: &str = ":routed:";
+
+/// Suffix used with queue keys to get the Redis key for the set of routing keys its jobs have been created with. A user
+/// created queue with name "foo" would store these under the key "queue:foo:routing_keys".
+pub const QUEUE_ROUTING_KEYS_SUFFIX: &str = ":routing_keys";
+
+/// Infix used with queue keys and session keys to get the Redis keys for a session's queued jobs and state. A user
+/// created queue with name "foo" would store its queued jobs with session key "abc" under the key
+/// "queue:foo:session:abc:jobs", and the worker and ID of the session's last started job under "queue:foo:session:abc".
+pub const QUEUE_SESSION_INFIX: &str = ":session:";
+
+/// Suffix used with queue keys to get the Redis key for the set of sessions with queued jobs. A user created queue with
+/// name "foo" would store these under the key "queue:foo:sessions".
+pub const QUEUE_SESSIONS_SUFFIX: &str = ":sessions";
+
+/// Infix used with queue keys and serialization keys to get the Redis key for queued jobs with a serialization key. A
+/// user created queue with name "foo" would store its queued jobs with serialization key "abc" under the key
+/// "queue:foo:serial:abc:jobs".
+pub const QUEUE_SERIAL_INFIX: &str = ":serial:";
+
+/// Suffix used with queue keys to get the Redis key for the set of serialization keys with queued jobs. A user created
+/// queue with name "foo" would store these under the key "queue:foo:serial_keys".
+pub const QUEUE_SERIAL_KEYS_SUFFIX: &str = ":serial_keys";
+
+/// Suffix used with queue keys to get the Redis key for the hash of the ID of the last started job with each
+/// serialization key. A user created queue with name "foo" would store these under the key "queue:foo:serial_jobs".
+pub const QUEUE_SERIAL_JOBS_SUFFIX: &str = ":serial_jobs";
+
+/// Suffix used with queue keys to get the Redis key for the hash of the ID of the latest job created with each unique
+/// key. A user created queue with name "foo" would store these under the key "queue:foo:unique_jobs".
+pub const QUEUE_UNIQUE_JOBS_SUFFIX: &str = ":unique_jobs";
+
+/// Suffix used with queue keys to get the Redis key for the hash of the ID of the latest completed job with each unique
+/// key. A user created queue with name "foo" would store these under the key "queue:foo:results".
+pub const QUEUE_RESULTS_SUFFIX: &str = ":results";
+
+/// Suffix used with queue keys to get the Redis key for the sorted set of workers that have recently asked for jobs
+/// from it, scored by when they last asked. A user created queue with name "foo" would store these under the key
+/// "queue:foo:workers".
+pub const QUEUE_WORKERS_SUFFIX: &str = ":workers";
+
+/// Suffix used with queue keys to get the Redis key counting its recent retries. A user created queue with name "foo"
+/// would count retries against its retry budget under the key "queue:foo:retry_count".
+pub const QUEUE_RETRY_COUNT_SUFFIX: &str = ":retry_count";
+
+/// Suffix used with queue keys to get the Redis key recording when its recent jobs were started, and how long they
+/// waited. A user created queue with name "foo" would record these under the key "queue:foo:starts".
+pub const QUEUE_STARTS_SUFFIX: &str = ":starts";
+
+/// Suffix used with queue keys to get the Redis key recording when its recent jobs were completed, and how long they
+/// ran for. A user created queue with name "foo" would record these under the key "queue:foo:runs".
+pub const QUEUE_RUNS_SUFFIX: &str = ":runs";
+
 /// Prefix used for tag keys in Redis. These are used to index jobs by any tags they were given at creation time.
-/// A tag created with name "foo" would be stored a "tag:foo".
+/// A tag created with name "foo" would be stored as "ocypod:tag:foo".
 pub const TAG_PREFIX: &str = "ocypod:tag:";
 
 pub const STAT_JOBS_CREATED_KEY: &str = "ocypod:stats:jobs:num_created";
@@ -47,12 +157,14 @@ pub const STAT_JOBS_RETRIED_KEY: &str = "ocypod:stats:jobs:num_retried";
 pub const STAT_JOBS_FAILED_KEY: &str = "ocypod:stats:jobs:num_failed";
 pub const STAT_JOBS_TIMED_OUT_KEY: &str = "ocypod:stats:jobs:num_timed_out";
 pub const STAT_JOBS_CANCELLED_KEY: &str = "ocypod:stats:jobs:cancelled";
+pub const STAT_JOBS_SLA_BREACHED_KEY: &str = "ocypod:stats:jobs:num_sla_breached";
 
-pub static STATS_KEYS: [&str; 6] = [
+pub static STATS_KEYS: [&str; 7] = [
     STAT_JOBS_CREATED_KEY,
     STAT_JOBS_COMPLETED_KEY,
     STAT_JOBS_RETRIED_KEY,
     STAT_JOBS_FAILED_KEY,
     STAT_JOBS_TIMED_OUT_KEY,
     STAT_JOBS_CANCELLED_KEY,
+    STAT_JOBS_SLA_BREACHED_KEY,
 ];
@@ -0,0 +1,125 @@
+//! Detection of jobs removed by Redis itself rather than by Ocypod, e.g. evicted due to Redis' `maxmemory` policy,
+//! using Redis keyspace notifications.
+//!
+//! Ocypod never sets an expiry on job keys, so any job key that expires or is evicted leaves its ID behind in the
+//! lists used to index jobs by status and queue. These IDs are removed as soon as the notification is received, and
+//! each lost job is logged and counted in metrics.
+//!
+//! Redis only sends these notifications if its `notify-keyspace-events` setting includes `E` (keyevent
+//! notifications), `x` (expired events), and `e` (evicted events). Notifications sent while not subscribed, e.g.
+//! while reconnecting, are lost, though their jobs will still be found by integrity checks.
+//!
+//! Since eviction corrupts queue state before it can be detected, each Redis instance's `maxmemory-policy` is also
+//! checked, at startup and periodically, so that evicting policies can be fixed before they remove any jobs.
+
+use futures::StreamExt;
+use log::{debug, warn};
+use redis::aio::ConnectionLike;
+use redis::ConnectionInfo;
+
+use super::keys;
+use super::metrics::METRICS;
+use super::pool::RedisPool;
+use super::shard::RedisShards;
+use super::RedisManager;
+use crate::models::OcyResult;
+
+/// Subscribe to expired and evicted key notifications from given Redis instance, removing any jobs lost from the
+/// lists they're indexed in, using given pool. Returns once the subscription is closed, e.g. due to a lost connection.
+pub async fn watch(pool: &RedisPool, info: ConnectionInfo) -> OcyResult<()> {
+    let db = info.db;
+    let mut pubsub = redis::Client::open(info)?.get_async_connection().await?.into_pubsub();
+    pubsub.subscribe(format!("__keyevent@{}__:expired", db)).await?;
+    pubsub.subscribe(format!("__keyevent@{}__:evicted", db)).await?;
+    debug!("Subscribed to keyspace notifications for Redis database {}", db);
+
+    let mut messages = pubsub.on_message();
+    while let Some(msg) = messages.next().await {
+        let key: String = match msg.get_payload() {
+            Ok(key) => key,
+            Err(_) => continue,
+        };
+        let evicted = msg.get_channel_name().ends_with(":evicted");
+        let action = if evicted { "evicted" } else { "expired" };
+        let job_id = match parse_job_key(&key) {
+            Some(job_id) => job_id,
+            // other keys are expected to expire, but none should be evicted
+            None if evicted && key.starts_with("ocypod:") => {
+                warn!("Redis evicted {}, consider increasing its maxmemory or using the noeviction policy", key);
+                continue;
+            }
+            None => continue,
+        };
+
+        METRICS.record_lost_job(evicted);
+        match RedisManager::remove_lost_job(&mut pool.get(), job_id).await {
+            Ok(removed) => warn!("[{}] {} by Redis, removed from {:?}", key, action, removed),
+            Err(err) => warn!("[{}] {} by Redis, failed to remove from indexes: {}", key, action, err),
+        }
+    }
+    Ok(())
+}
+
+/// Get the `maxmemory-policy` of the Redis instance given connection is to, if it may evict keys.
+///
+/// Uses `INFO memory` rather than `CONFIG GET`, since `CONFIG` is often disabled by managed Redis services. Policies
+/// other than `noeviction` only evict keys once `maxmemory` is reached, so they're ignored if no limit is set.
+pub async fn evicting_policy<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<Option<String>> {
+    let info: String = redis::cmd("INFO").arg("memory").query_async(conn).await?;
+    Ok(parse_evicting_policy(&info))
+}
+
+/// Get the index and eviction policy of each shard whose Redis instance may evict keys, updating the number of such
+/// shards in metrics.
+pub async fn evicting_shards(shards: &RedisShards) -> OcyResult<Vec<(usize, String)>> {
+    let mut evicting = Vec::new();
+    for (shard, pool) in shards.all().iter().enumerate() {
+        if let Some(policy) = evicting_policy(&mut pool.get()).await? {
+            evicting.push((shard, policy));
+        }
+    }
+    METRICS.record_evicting_shards(evicting.len());
+    Ok(evicting)
+}
+
+/// Get the eviction policy from the output of `INFO memory`, if it may evict keys.
+fn parse_evicting_policy(info: &str) -> Option<String> {
+    let field = |name: &str| {
+        info.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .map(str::trim)
+    };
+    let maxmemory: u64 = field("maxmemory").and_then(|v| v.parse().ok()).unwrap_or(0);
+    match field("maxmemory_policy") {
+        Some(policy) if policy != "noeviction" && maxmemory > 0 => Some(policy.to_owned()),
+        _ => None,
+    }
+}
+
+/// Get the ID of the job stored under given Redis key, if it's a job key.
+fn parse_job_key(key: &str) -> Option<u64> {
+    key.strip_prefix(keys::JOB_PREFIX)?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn job_keys() {
+        assert_eq!(parse_job_key("ocypod:job:123"), Some(123));
+        assert_eq!(parse_job_key("ocypod:job:abc"), None);
+        assert_eq!(parse_job_key("ocypod:trash:job:123"), None);
+        assert_eq!(parse_job_key("ocypod:queue:a:retry_count"), None);
+    }
+
+    #[test]
+    fn policies() {
+        let info = |maxmemory, policy| format!("# Memory\r\nmaxmemory:{}\r\nmaxmemory_policy:{}\r\n", maxmemory, policy);
+        assert_eq!(parse_evicting_policy(&info(1024, "allkeys-lru")), Some("allkeys-lru".to_owned()));
+        assert_eq!(parse_evicting_policy(&info(1024, "volatile-ttl")), Some("volatile-ttl".to_owned()));
+        assert_eq!(parse_evicting_policy(&info(1024, "noeviction")), None);
+        assert_eq!(parse_evicting_policy(&info(0, "allkeys-lru")), None);
+        assert_eq!(parse_evicting_policy("# Memory\r\n"), None);
+    }
+}
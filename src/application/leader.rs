@@ -0,0 +1,133 @@
+//! Leader election between multiple servers sharing the same Redis.
+//!
+//! The leader holds a lock in Redis with a TTL, which it renews periodically. If the leader stops renewing it (e.g.
+//! due to crashing, or losing its connection to Redis), the lock expires and another server takes over.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{debug, error, info, warn};
+use redis::aio::ConnectionLike;
+
+use super::keys;
+use super::pool::PooledConnection;
+use crate::models::OcyResult;
+
+/// Extends the lock's TTL, but only if it's still held by given instance.
+const RENEW_SCRIPT: &str = r#"
+if redis.call("get", KEYS[1]) == ARGV[1] then
+    return redis.call("pexpire", KEYS[1], ARGV[2])
+else
+    return 0
+end
+"#;
+
+/// Shared view of whether this server is currently the leader.
+#[derive(Clone, Debug)]
+pub struct Leadership {
+    leader: Arc<AtomicBool>,
+}
+
+impl Leadership {
+    /// Get leadership for a server that always leads, used when leader election is disabled.
+    pub fn always() -> Self {
+        Self {
+            leader: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Check whether this server is currently the leader, and should run the monitors.
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::SeqCst)
+    }
+
+    fn set_leader(&self, instance_id: &str, leader: bool) {
+        if self.leader.swap(leader, Ordering::SeqCst) != leader {
+            if leader {
+                info!("[{}] elected leader, running monitors", instance_id);
+            } else {
+                warn!("[{}] no longer leader, monitors paused", instance_id);
+            }
+        }
+    }
+}
+
+/// Generate an instance ID that's unique to this server process.
+pub fn default_instance_id() -> String {
+    let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "ocypod".to_owned());
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    format!("{}-{}-{}", host, std::process::id(), started)
+}
+
+/// Start periodic background task that tries to acquire or renew the leader lock.
+///
+/// Returns a handle that reports whether this server is currently the leader, which starts out as not leading until
+/// the lock has been acquired.
+pub fn start_leader_election(
+    conn: PooledConnection,
+    instance_id: String,
+    lock_ttl: Duration,
+) -> Leadership {
+    info!(
+        "[{}] leader election enabled, lock expires after {}",
+        &instance_id,
+        humantime::format_duration(lock_ttl)
+    );
+    let leadership = Leadership {
+        leader: Arc::new(AtomicBool::new(false)),
+    };
+
+    let handle = leadership.clone();
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(lock_ttl / 3);
+        let mut conn = conn;
+        loop {
+            interval.tick().await;
+            let leader = if handle.is_leader() {
+                renew_lock(&mut conn, &instance_id, lock_ttl).await
+            } else {
+                acquire_lock(&mut conn, &instance_id, lock_ttl).await
+            };
+
+            match leader {
+                Ok(leader) => handle.set_leader(&instance_id, leader),
+                Err(err) => {
+                    // lock can't be renewed, so step down before it expires and another server takes over
+                    error!("[{}] leader election failed: {}", &instance_id, err);
+                    handle.set_leader(&instance_id, false);
+                }
+            }
+        }
+    });
+
+    leadership
+}
+
+/// Try to acquire the leader lock, returns true if it was acquired.
+async fn acquire_lock<C: ConnectionLike>(conn: &mut C, instance_id: &str, lock_ttl: Duration) -> OcyResult<bool> {
+    let result: Option<String> = redis::cmd("SET")
+        .arg(keys::LEADER_KEY)
+        .arg(instance_id)
+        .arg("NX")
+        .arg("PX")
+        .arg(lock_ttl.as_millis() as u64)
+        .query_async(conn)
+        .await?;
+    debug!("[{}] leader lock acquired: {}", instance_id, result.is_some());
+    Ok(result.is_some())
+}
+
+/// Try to extend the leader lock, returns false if it's no longer held by this server.
+async fn renew_lock<C: ConnectionLike>(conn: &mut C, instance_id: &str, lock_ttl: Duration) -> OcyResult<bool> {
+    let renewed: bool = redis::Script::new(RENEW_SCRIPT)
+        .key(keys::LEADER_KEY)
+        .arg(instance_id)
+        .arg(lock_ttl.as_millis() as u64)
+        .invoke_async(conn)
+        .await?;
+    Ok(renewed)
+}
@@ -0,0 +1,19 @@
+//! Rules for valid queue and tag names, configured by the `[limits]` section and set once at startup.
+
+use std::sync::OnceLock;
+
+use crate::config::LimitsConfig;
+
+/// Rules names are validated against, set once at startup.
+static LIMITS: OnceLock<LimitsConfig> = OnceLock::new();
+
+/// Set the rules names are validated against. Can only be set once, subsequent calls are ignored, so must be called
+/// before any names are validated.
+pub fn init(limits: LimitsConfig) {
+    let _ = LIMITS.set(limits);
+}
+
+/// Get the rules names are validated against, which are the defaults unless set at startup.
+pub fn get() -> &'static LimitsConfig {
+    LIMITS.get_or_init(LimitsConfig::default)
+}
@@ -2,17 +2,144 @@
 //!
 //! Main struct provided is `RedisManager`, through which all job queue operations are exposed.
 //! These will typically have HTTP handlers mapped to them.
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::default::Default;
 
 use log::{debug, info, warn};
-use redis::{aio::ConnectionLike, AsyncCommands};
-
-use super::{job::RedisJob, keys, queue::RedisQueue, tag::RedisTag};
-use crate::models::{job, queue, DateTime, JobStats, OcyError, OcyResult, QueueInfo, ServerInfo};
+use rand::Rng;
+use redis::{aio::ConnectionLike, AsyncCommands, RedisResult};
+
+use super::{
+    clock, crypto,
+    job::{record_storage_in_pipe, RedisJob},
+    keys, limits,
+    queue::{RedisQueue, MAX_SAMPLE_SIZE},
+    scan::JobScan,
+    tag::RedisTag,
+};
+use super::tolerance::HEARTBEAT_TOLERANCE;
+use crate::config::StickySessionsConfig;
+use crate::models::{
+    job, queue, quota, tag, DateTime, Duration, IntegrityReport, JobStats, OcyError, OcyResult, QueueInfo, ServerInfo,
+    Tenant, WorkerStatus, NAMESPACE_SEPARATOR,
+};
 use crate::redis_utils::vec_from_redis_pipe;
 use crate::transaction_async;
 
+/// Adds to the storage used by a namespace, unless it would exceed the namespace's limit, returning whether it was
+/// added.
+const RESERVE_STORAGE_SCRIPT: &str = r#"
+local used = tonumber(redis.call("hget", KEYS[1], ARGV[1])) or 0
+if used + tonumber(ARGV[2]) > tonumber(ARGV[3]) then
+    return 0
+end
+redis.call("hincrby", KEYS[1], ARGV[1], ARGV[2])
+return 1
+"#;
+
+/// Sets the storage used by each namespace, unless another server has already done so, or it's been updated since.
+const INIT_STORAGE_SCRIPT: &str = r#"
+if redis.call("exists", KEYS[1]) == 1 then
+    return 0
+end
+redis.call("hset", KEYS[1], unpack(ARGV))
+return 1
+"#;
+
+/// Status lists checked for integrity, and the statuses jobs in each should have.
+const INTEGRITY_INDEXES: &[(&str, &[job::Status])] = &[
+    (keys::RUNNING_KEY, &[job::Status::Running]),
+    (keys::FAILED_KEY, &[job::Status::Failed, job::Status::TimedOut]),
+    (keys::TIMEDOUT_KEY, &[job::Status::TimedOut]),
+    (
+        keys::ENDED_KEY,
+        &[job::Status::Failed, job::Status::TimedOut, job::Status::Completed, job::Status::Cancelled],
+    ),
+    (keys::QUARANTINED_KEY, &[job::Status::Quarantined]),
+    (keys::HELD_KEY, &[job::Status::Queued]),
+];
+
+/// Removes a job ID from a list or set, unless the job exists with one of the allowed statuses, and is on the expected
+/// queue if one is given.
+const REMOVE_INDEX_ENTRY_SCRIPT: &str = r#"
+local status = redis.call("hget", KEYS[2], ARGV[2])
+if status and (ARGV[4] == "" or redis.call("hget", KEYS[2], ARGV[3]) == ARGV[4]) then
+    for i = 5, #ARGV do
+        if status == ARGV[i] then
+            return 0
+        end
+    end
+end
+if redis.call("type", KEYS[1]).ok == "set" then
+    return redis.call("srem", KEYS[1], ARGV[1])
+end
+return redis.call("lrem", KEYS[1], 0, ARGV[1])
+"#;
+
+/// Adds a job ID to the end of a list, as long as the job still has the expected status, and isn't in any of the
+/// given lists.
+const RESTORE_ORPHANED_JOB_SCRIPT: &str = r#"
+if redis.call("hget", KEYS[1], ARGV[2]) ~= ARGV[3] then
+    return 0
+end
+for i = 2, #KEYS do
+    for _, job_id in ipairs(redis.call("lrange", KEYS[i], 0, -1)) do
+        if job_id == ARGV[1] then
+            return 0
+        end
+    end
+end
+redis.call("rpush", KEYS[2], ARGV[1])
+return 1
+"#;
+
+/// Removes a job ID from every given list and sorted set, as long as the job no longer exists. Returns the keys it
+/// was removed from.
+const REMOVE_LOST_JOB_SCRIPT: &str = r#"
+if redis.call("exists", KEYS[1]) == 1 then
+    return {}
+end
+local removed = {}
+for i = 2, #KEYS do
+    local key_type = redis.call("type", KEYS[i]).ok
+    local count = 0
+    if key_type == "list" then
+        count = redis.call("lrem", KEYS[i], 0, ARGV[1])
+    elseif key_type == "zset" then
+        count = redis.call("zrem", KEYS[i], ARGV[1])
+    end
+    if count > 0 then
+        table.insert(removed, KEYS[i])
+    end
+end
+return removed
+"#;
+
+/// Updates the heartbeat of each given job that's running and on a queue with the given prefix, and in the given
+/// comma separated list of queues unless it's empty, along with its progress if given (as `ARGV[8 + i]` for `KEYS[i]`,
+/// empty to leave it unchanged). Returns, for each job, 0 if it doesn't exist (or is on another queue), 1 if it's not
+/// running, or 2 if it was updated.
+const HEARTBEAT_JOBS_SCRIPT: &str = r#"
+local results = {}
+for i, key in ipairs(KEYS) do
+    local job = redis.call("hmget", key, ARGV[3], ARGV[4])
+    if not job[1] or not job[2] or string.sub(job[2], 1, #ARGV[2]) ~= ARGV[2] then
+        results[i] = 0
+    elseif ARGV[8] ~= "" and not string.find("," .. ARGV[8] .. ",", "," .. job[2] .. ",", 1, true) then
+        results[i] = 0
+    elseif job[1] ~= ARGV[5] then
+        results[i] = 1
+    else
+        redis.call("hset", key, ARGV[6], ARGV[1])
+        if ARGV[8 + i] ~= "" then
+            redis.call("hset", key, ARGV[7], ARGV[8 + i])
+        end
+        results[i] = 2
+    end
+end
+return results
+"#;
+
 // TODO: now that RedisManager this has no state, should its methods just be moved to module functions?
 
 /// Manages queues and jobs within Redis. Contains main public functions that are called by HTTP services.
@@ -35,14 +162,38 @@ impl RedisManager {
             .await
     }
 
-    /// Delete queue with given name from Redis.
+    /// Create a new queue in Redis with given name and settings, failing if the queue already exists.
+    pub async fn create_queue<C: ConnectionLike + Send>(
+        conn: &mut C,
+        name: &str,
+        settings: &queue::Settings,
+    ) -> OcyResult<()> {
+        RedisQueue::from_string(name)?.create(conn, settings).await
+    }
+
+    /// Update only the given settings of an existing queue, returning the queue's new settings.
+    pub async fn update_queue<C: ConnectionLike + Send>(
+        conn: &mut C,
+        name: &str,
+        update: &queue::SettingsUpdate,
+    ) -> OcyResult<queue::Settings> {
+        RedisQueue::from_string(name)?.update(conn, update).await
+    }
+
+    /// Delete queue with given name from Redis, along with any jobs queued on it.
     ///
-    /// Returns true if a queue was deleted, and false if no queue with given name was found.
+    /// Queues with queued jobs aren't deleted unless `force` is true, to avoid accidentally losing jobs.
     pub async fn delete_queue<C: ConnectionLike + Send>(
         conn: &mut C,
         name: &str,
-    ) -> OcyResult<bool> {
-        RedisQueue::from_string(name)?.delete(conn).await
+        force: bool,
+    ) -> OcyResult<queue::Deletion> {
+        RedisQueue::from_string(name)?.delete(conn, force).await
+    }
+
+    /// Get a consistent snapshot of queue with given name's settings and jobs, e.g. for backups.
+    pub async fn queue_snapshot<C: ConnectionLike + Send>(conn: &mut C, name: &str) -> OcyResult<queue::Snapshot> {
+        RedisQueue::from_string(name)?.snapshot(conn).await
     }
 
     /// Delete a job with given ID from Redis.
@@ -55,6 +206,50 @@ impl RedisManager {
         RedisJob::new(job_id).delete(conn).await
     }
 
+    /// Move a job to the trash, where it can be restored until `recovery_window` has elapsed.
+    ///
+    /// Returns true if a job was found and moved to the trash, false if no job with given ID was found.
+    pub async fn trash_job<C: ConnectionLike + Send>(
+        conn: &mut C,
+        job_id: u64,
+        recovery_window: &Duration,
+    ) -> OcyResult<bool> {
+        RedisJob::new(job_id).trash(conn, recovery_window).await
+    }
+
+    /// Restore a job from the trash.
+    pub async fn restore_job<C: ConnectionLike + Send>(conn: &mut C, job_id: u64) -> OcyResult<()> {
+        RedisJob::new(job_id).restore(conn).await
+    }
+
+    /// Permanently remove all jobs from the trash whose recovery window has elapsed, a batch at a time.
+    pub async fn purge_trash<C: ConnectionLike + Send>(
+        conn: &mut C,
+        batching: &queue::Batching,
+    ) -> OcyResult<Vec<u64>> {
+        debug!("Checking for deleted jobs to purge");
+        let now = clock::now().timestamp();
+        let mut purged = Vec::new();
+        let mut scan = JobScan::sorted_set(conn, keys::TRASH_KEY, now, batching).await?;
+        while let Some(job_ids) = scan.next_batch(conn).await? {
+            let mut pipeline = redis::pipe();
+            let pipe = &mut pipeline;
+            for job_id in &job_ids {
+                pipe.del(RedisJob::new(*job_id).trash_key())
+                    .ignore()
+                    .zrem(keys::TRASH_KEY, *job_id)
+                    .ignore();
+            }
+            let _: () = pipe.query_async(conn).await?;
+            purged.extend(job_ids);
+        }
+
+        if !purged.is_empty() {
+            info!("Purged {} deleted job(s) from trash", purged.len());
+        }
+        Ok(purged)
+    }
+
     /// Get summary of server and queue data. Currently contains:
     /// * count of each job's status by queue
     /// * total number of jobs processed and their final status
@@ -79,7 +274,13 @@ impl RedisManager {
         let mut pipeline = redis::pipe();
         let pipe = &mut pipeline;
 
-        for queue_key in &[keys::FAILED_KEY, keys::ENDED_KEY, keys::RUNNING_KEY, keys::TIMEDOUT_KEY] {
+        for queue_key in &[
+            keys::FAILED_KEY,
+            keys::ENDED_KEY,
+            keys::RUNNING_KEY,
+            keys::TIMEDOUT_KEY,
+            keys::QUARANTINED_KEY,
+        ] {
             for job_id in conn.lrange::<_, Vec<u64>>(*queue_key, 0, -1).await? {
                 pipe.hget(
                     RedisJob::new(job_id).key(),
@@ -106,6 +307,15 @@ impl RedisManager {
             queue_info.incr_status_count(&status);
         }
 
+        let mut pipeline = redis::pipe();
+        let pipe = &mut pipeline;
+        for job_id in conn.lrange::<_, Vec<u64>>(keys::HELD_KEY, 0, -1).await? {
+            pipe.hget(RedisJob::new(job_id).key(), job::Field::Queue);
+        }
+        for queue_name in vec_from_redis_pipe::<C, Option<String>>(conn, pipe).await?.into_iter().flatten() {
+            queues_info.entry(queue_name).or_insert_with(QueueInfo::default).held += 1;
+        }
+
         let job_stats: JobStats = conn.get(&keys::STATS_KEYS).await?;
         Ok(ServerInfo {
             queues: queues_info,
@@ -113,6 +323,137 @@ impl RedisManager {
         })
     }
 
+    /// Get a summary of each queue's job counts, main settings, the age of its oldest queued job, and whether it's
+    /// paused, by queue name.
+    pub async fn queue_summaries<C: ConnectionLike + Send>(
+        conn: &mut C,
+    ) -> OcyResult<HashMap<String, queue::Summary>> {
+        let mut queues_info = Self::server_info(conn).await?.queues;
+        let queues = Self::queue_names(conn)
+            .await?
+            .into_iter()
+            .map(RedisQueue::from_string)
+            .collect::<OcyResult<Vec<_>>>()?;
+
+        let mut pipe = redis::pipe();
+        for queue in &queues {
+            queue.summary_in_pipe(&mut pipe);
+        }
+        let values: Vec<redis::Value> = pipe.query_async(conn).await?;
+
+        // queues may have been deleted since their names were fetched
+        let mut found = Vec::new();
+        for (queue, values) in queues.into_iter().zip(values.chunks(4)) {
+            if redis::from_redis_value(&values[0])? {
+                let settings: queue::Settings = redis::from_redis_value(&values[1])?;
+                let oldest_job_id: Option<u64> = redis::from_redis_value(&values[2])?;
+                let paused_until: Option<DateTime> = redis::from_redis_value(&values[3])?;
+                found.push((queue, settings, oldest_job_id, paused_until));
+            }
+        }
+
+        let mut pipe = redis::pipe();
+        for job_id in found.iter().filter_map(|(_, _, job_id, _)| *job_id) {
+            pipe.hget(RedisJob::new(job_id).key(), job::Field::CreatedAt);
+        }
+        let mut created_ats = vec_from_redis_pipe::<C, Option<DateTime>>(conn, &pipe).await?.into_iter();
+
+        let now = clock::now();
+        let mut summaries = HashMap::new();
+        for (queue, settings, oldest_job_id, paused_until) in found {
+            let oldest_queued_age = oldest_job_id
+                .and_then(|_| created_ats.next().flatten())
+                .map(|created_at| Duration::from_secs(now.seconds_since(&created_at).max(0) as u64));
+            let jobs = queues_info.remove(&queue.name).unwrap_or_default();
+            let paused_until = paused_until.filter(|paused_until| paused_until > &now);
+            let summary = queue::Summary::new(jobs, settings, oldest_queued_age, paused_until);
+            summaries.insert(queue.name, summary);
+        }
+        Ok(summaries)
+    }
+
+    /// Get IDs of jobs matching given search, newest first, returning at most `limit` IDs.
+    ///
+    /// Candidate jobs are found using the tag, queue, and status lists, then checked against the full search.
+    pub async fn search_job_ids<C: ConnectionLike + Send>(
+        conn: &mut C,
+        query: &job::SearchQuery,
+        limit: usize,
+    ) -> OcyResult<Vec<u64>> {
+        let mut candidates = match query.tag {
+            Some(ref tag) => RedisTag::from_str(tag)?.tagged_job_ids(conn).await?,
+            None => {
+                let queues: Vec<RedisQueue> = match query.queue {
+                    Some(ref queue_name) => vec![RedisQueue::from_string(queue_name)?],
+                    None => Self::queue_names(conn)
+                        .await?
+                        .into_iter()
+                        .map(RedisQueue::from_string)
+                        .collect::<OcyResult<_>>()?,
+                };
+                let mut queue_keys = Vec::new();
+                for queue in &queues {
+                    queue_keys.extend(queue.queued_keys(conn).await?);
+                }
+                let status_keys: &[&str] = match query.status {
+                    Some(job::Status::Queued) => &[keys::HELD_KEY],
+                    Some(job::Status::Running) => &[keys::RUNNING_KEY],
+                    Some(job::Status::Failed) => &[keys::FAILED_KEY, keys::ENDED_KEY],
+                    Some(job::Status::TimedOut) => &[keys::TIMEDOUT_KEY, keys::ENDED_KEY],
+                    Some(job::Status::Quarantined) => &[keys::QUARANTINED_KEY, keys::ENDED_KEY],
+                    Some(job::Status::Completed) | Some(job::Status::Cancelled) => &[keys::ENDED_KEY],
+                    None => &[
+                        keys::HELD_KEY,
+                        keys::RUNNING_KEY,
+                        keys::FAILED_KEY,
+                        keys::ENDED_KEY,
+                        keys::TIMEDOUT_KEY,
+                        keys::QUARANTINED_KEY,
+                    ],
+                };
+
+                let mut pipe = redis::pipe();
+                if query.status.is_none() || query.status == Some(job::Status::Queued) {
+                    for queue_key in &queue_keys {
+                        pipe.lrange(queue_key, 0, -1);
+                    }
+                }
+                for status_key in status_keys {
+                    pipe.lrange(*status_key, 0, -1);
+                }
+                vec_from_redis_pipe::<C, Vec<u64>>(conn, &pipe).await?.into_iter().flatten().collect()
+            }
+        };
+        candidates.sort_unstable_by(|a, b| b.cmp(a));
+        candidates.dedup();
+        if let Some(cursor) = query.cursor {
+            candidates.retain(|job_id| *job_id < cursor);
+        }
+
+        // check candidates in batches until enough matching jobs are found
+        let mut job_ids = Vec::new();
+        for batch in candidates.chunks(limit.max(100)) {
+            let mut pipe = redis::pipe();
+            for job_id in batch {
+                pipe.hget(RedisJob::new(*job_id).key(), &[job::Field::Queue, job::Field::Status]);
+            }
+            let results = vec_from_redis_pipe::<C, (Option<String>, Option<job::Status>)>(conn, &pipe).await?;
+            for (job_id, result) in batch.iter().zip(results) {
+                // option used to allow for jobs being deleted between calls
+                if let (Some(queue_name), Some(status)) = result {
+                    if query.matches(&queue_name, &status) {
+                        job_ids.push(*job_id);
+                    }
+                }
+            }
+            if job_ids.len() >= limit {
+                job_ids.truncate(limit);
+                break;
+            }
+        }
+        Ok(job_ids)
+    }
+
     /// Get one or more metadata fields from given job ID.
     ///
     /// If `None` is given as the `fields` argument, then get all fields.
@@ -124,6 +465,22 @@ impl RedisManager {
         RedisJob::new(job_id).fields(conn, fields).await
     }
 
+    /// Get the name of the queue a job was created on, whether or not it's in the trash.
+    ///
+    /// Returns `None` if no job with given ID exists.
+    pub async fn job_queue_including_trash<C: ConnectionLike + Send>(
+        conn: &mut C,
+        job_id: u64,
+    ) -> OcyResult<Option<String>> {
+        let job = RedisJob::new(job_id);
+        let (queue, trash_queue): (Option<String>, Option<String>) = redis::pipe()
+            .hget(job.key(), job::Field::Queue)
+            .hget(job.trash_key(), job::Field::Queue)
+            .query_async(conn)
+            .await?;
+        Ok(queue.or(trash_queue))
+    }
+
     /// Update one or more job metadata fields.
     ///
     /// Only following fields can be updated in this way:
@@ -146,6 +503,67 @@ impl RedisManager {
         RedisJob::new(job_id).update_heartbeat(conn).await
     }
 
+    /// Update the `last_heartbeat` field of each given running job with the current date/time, along with its
+    /// `progress` field if progress is given, atomically in a single round trip. Jobs on queues outside given
+    /// namespace, or outside given list of queues if given, are treated as not existing.
+    ///
+    /// Note: caller is responsible for ensuring all jobs are stored on the shard the connection is for.
+    pub async fn update_job_heartbeats<C: ConnectionLike + Send>(
+        conn: &mut C,
+        heartbeats: &[job::Heartbeat],
+        namespace: Option<&str>,
+        queues: Option<&[String]>,
+    ) -> OcyResult<job::HeartbeatResults> {
+        let mut results = job::HeartbeatResults::default();
+        if heartbeats.is_empty() {
+            return Ok(results);
+        }
+        if queues.is_some_and(|queues| queues.is_empty()) {
+            results.not_found = heartbeats.iter().map(job::Heartbeat::id).collect();
+            return Ok(results);
+        }
+
+        let queue_prefix = namespace.map(|ns| format!("{}{}", ns, NAMESPACE_SEPARATOR)).unwrap_or_default();
+        let script = redis::Script::new(HEARTBEAT_JOBS_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        for heartbeat in heartbeats {
+            invocation.key(RedisJob::new(heartbeat.id()).key());
+        }
+        invocation
+            .arg(clock::now())
+            .arg(queue_prefix)
+            .arg(job::Field::Status)
+            .arg(job::Field::Queue)
+            .arg(job::Status::Running)
+            .arg(job::Field::LastHeartbeat)
+            .arg(job::Field::Progress)
+            .arg(queues.map(|queues| queues.join(",")).unwrap_or_default());
+        for heartbeat in heartbeats {
+            invocation.arg(heartbeat.progress().map(|progress| progress.to_string()).unwrap_or_default());
+        }
+        let outcomes: Vec<u8> = invocation.invoke_async(conn).await?;
+
+        for (heartbeat, outcome) in heartbeats.iter().zip(outcomes) {
+            match outcome {
+                2 => results.updated.push(heartbeat.id()),
+                1 => results.not_running.push(heartbeat.id()),
+                _ => results.not_found.push(heartbeat.id()),
+            }
+        }
+        debug!("{} job heartbeats updated", results.updated.len());
+        Ok(results)
+    }
+
+    /// Put a queued job on hold, so that it's not given to workers until released.
+    pub async fn hold_job<C: ConnectionLike + Send>(conn: &mut C, job_id: u64) -> OcyResult<()> {
+        RedisJob::new(job_id).hold(conn).await
+    }
+
+    /// Release a job from hold, moving it back to its queue.
+    pub async fn release_held_job<C: ConnectionLike + Send>(conn: &mut C, job_id: u64) -> OcyResult<()> {
+        RedisJob::new(job_id).release_hold(conn).await
+    }
+
     /// Retry the job
     pub async fn retry_job<C: ConnectionLike + Send>(
         conn: &mut C,
@@ -192,6 +610,15 @@ impl RedisManager {
         RedisJob::new(job_id).set_output(conn, value).await
     }
 
+    /// Replace a queued job's `input` field with the given input data.
+    pub async fn set_job_input<C: ConnectionLike + Send>(
+        conn: &mut C,
+        job_id: u64,
+        value: &serde_json::Value,
+    ) -> OcyResult<()> {
+        RedisJob::new(job_id).set_input(conn, value).await
+    }
+
     // TODO: add an endpoint to get fields too?
     /// Get a list of jobs IDs with given tag name.
     pub async fn tagged_job_ids<C: ConnectionLike + Send>(
@@ -201,6 +628,35 @@ impl RedisManager {
         RedisTag::from_str(tag_name)?.tagged_job_ids(conn).await
     }
 
+    /// Attach given tag to given jobs. Jobs on queues outside given namespace, or outside the given queues if any, are
+    /// treated as not existing.
+    pub async fn apply_tag<C: ConnectionLike + Send>(
+        conn: &mut C,
+        tag_name: &str,
+        job_ids: &[u64],
+        namespace: Option<&str>,
+        queues: Option<&[String]>,
+    ) -> OcyResult<tag::ApplyResults> {
+        let queue_prefix = namespace.map(|ns| format!("{}{}", ns, NAMESPACE_SEPARATOR)).unwrap_or_default();
+        RedisTag::from_str(tag_name)?.apply(conn, job_ids, &queue_prefix, queues).await
+    }
+
+    /// Retry, cancel or delete every job with given tag, moving deleted jobs to the trash if `recovery_window` isn't
+    /// zero. Jobs on queues outside given namespace, or outside the given queues if any, are treated as not existing.
+    pub async fn bulk_tag_operation<C: ConnectionLike + Send>(
+        conn: &mut C,
+        tag_name: &str,
+        operation: tag::BulkOperation,
+        namespace: Option<&str>,
+        queues: Option<&[String]>,
+        recovery_window: &Duration,
+    ) -> OcyResult<tag::BulkResults> {
+        let queue_prefix = namespace.map(|ns| format!("{}{}", ns, NAMESPACE_SEPARATOR)).unwrap_or_default();
+        RedisTag::from_str(tag_name)?
+            .bulk(conn, operation, &queue_prefix, queues, recovery_window)
+            .await
+    }
+
     /// Get list of all queue names.
     pub async fn queue_names<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<Vec<String>> {
         let mut names: Vec<String> = conn.smembers(keys::QUEUES_KEY).await?;
@@ -208,6 +664,120 @@ impl RedisManager {
         Ok(names)
     }
 
+    /// Check whether a queue with given name exists.
+    pub async fn queue_exists<C: ConnectionLike + Send>(conn: &mut C, queue_name: &str) -> OcyResult<bool> {
+        Ok(RedisQueue::from_string(queue_name)?.exists(conn).await?)
+    }
+
+    /// Get the resources used by queues in given namespace.
+    ///
+    /// Storage is read from the namespace's storage counter, rather than by checking every job.
+    pub async fn namespace_usage<C: ConnectionLike + Send>(conn: &mut C, namespace: &str) -> OcyResult<quota::Usage> {
+        let tenant = Tenant::with_namespace(namespace);
+        let queues: Vec<RedisQueue> = Self::queue_names(conn)
+            .await?
+            .into_iter()
+            .filter(|name| tenant.owns(name))
+            .map(RedisQueue::from_string)
+            .collect::<OcyResult<_>>()?;
+        let mut queue_keys = Vec::new();
+        for queue in &queues {
+            queue_keys.extend(queue.queued_keys(conn).await?);
+        }
+
+        let mut pipe = redis::pipe();
+        for queue_key in &queue_keys {
+            pipe.llen(queue_key);
+        }
+        let queued_jobs: u64 = vec_from_redis_pipe::<C, u64>(conn, &pipe).await?.into_iter().sum();
+
+        Ok(quota::Usage {
+            queues: queues.len() as u64,
+            queued_jobs,
+            storage_bytes: Self::namespace_storage(conn, namespace).await?,
+        })
+    }
+
+    /// Get the total size in bytes of the inputs and outputs of jobs in given namespace.
+    pub async fn namespace_storage<C: ConnectionLike + Send>(conn: &mut C, namespace: &str) -> OcyResult<u64> {
+        let storage_bytes: Option<i64> = conn.hget(keys::NAMESPACE_STORAGE_KEY, namespace).await?;
+        Ok(storage_bytes.unwrap_or_default().max(0) as u64)
+    }
+
+    /// Measure the storage used by each namespace's jobs by checking every job, as stored in the namespace storage
+    /// counters.
+    async fn measure_namespace_storage<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<HashMap<String, i64>> {
+        let mut queue_keys = Vec::new();
+        for queue_name in Self::queue_names(conn).await? {
+            if Tenant::namespace_of(&queue_name).is_some() {
+                queue_keys.extend(RedisQueue::from_string(queue_name)?.queued_keys(conn).await?);
+            }
+        }
+
+        let mut pipe = redis::pipe();
+        for queue_key in &queue_keys {
+            pipe.lrange(queue_key, 0, -1);
+        }
+        for key in &[
+            keys::LIMBO_KEY,
+            keys::RUNNING_KEY,
+            keys::FAILED_KEY,
+            keys::ENDED_KEY,
+            keys::TIMEDOUT_KEY,
+            keys::QUARANTINED_KEY,
+            keys::HELD_KEY,
+        ] {
+            pipe.lrange(*key, 0, -1);
+        }
+        let mut job_ids: Vec<u64> =
+            vec_from_redis_pipe::<C, Vec<u64>>(conn, &pipe).await?.into_iter().flatten().collect();
+        job_ids.sort_unstable();
+        job_ids.dedup();
+
+        let mut storage: HashMap<String, i64> = HashMap::new();
+        for batch in job_ids.chunks(1000) {
+            let mut pipe = redis::pipe();
+            for job_id in batch {
+                let job = RedisJob::new(*job_id);
+                pipe.hget(job.key(), job::Field::Queue)
+                    .cmd("HSTRLEN")
+                    .arg(job.key())
+                    .arg(job::Field::Input)
+                    .cmd("HSTRLEN")
+                    .arg(job.key())
+                    .arg(job::Field::Output);
+            }
+            let sizes: Vec<(Option<String>, i64, i64)> = pipe.query_async(conn).await?;
+            for (queue_name, input_len, output_len) in sizes {
+                if let Some(namespace) = queue_name.as_deref().and_then(Tenant::namespace_of) {
+                    *storage.entry(namespace.to_owned()).or_default() += input_len + output_len;
+                }
+            }
+        }
+        Ok(storage)
+    }
+
+    /// Initialise the namespace storage counters from every job if they don't exist yet, e.g. for data stored before
+    /// they were introduced.
+    pub async fn init_namespace_storage<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<()> {
+        if conn.exists(keys::NAMESPACE_STORAGE_KEY).await? {
+            return Ok(());
+        }
+        let storage = Self::measure_namespace_storage(conn).await?;
+        // an empty namespace marks the counters as initialised, even when no namespaces use any storage
+        let script = redis::Script::new(INIT_STORAGE_SCRIPT);
+        let mut script = script.prepare_invoke();
+        script.key(keys::NAMESPACE_STORAGE_KEY).arg("").arg(0);
+        for (namespace, bytes) in &storage {
+            script.arg(namespace).arg(*bytes);
+        }
+        let initialised: bool = script.invoke_async(conn).await?;
+        if initialised {
+            info!("Initialised storage usage of {} namespace(s)", storage.len());
+        }
+        Ok(())
+    }
+
     /// Get given queue's current settings.
     pub async fn queue_settings<C: ConnectionLike + Send>(
         conn: &mut C,
@@ -220,6 +790,130 @@ impl RedisManager {
             .await
     }
 
+    /// Get requests to create copies of each of a queue's queued jobs, in the order they'd be taken from the queue.
+    pub async fn queued_job_copies<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+    ) -> OcyResult<Vec<job::CreateRequest>> {
+        let queue = RedisQueue::from_string(queue_name)?
+            .ensure_exists(conn)
+            .await?;
+
+        // jobs are taken from the end of each of the queue's lists
+        let mut job_ids = Vec::new();
+        for queue_key in queue.queued_keys(conn).await? {
+            let queued_ids: Vec<u64> = conn.lrange(queue_key, 0, -1).await?;
+            job_ids.extend(queued_ids.into_iter().rev());
+        }
+        let mut job_reqs = Vec::with_capacity(job_ids.len());
+        for job_id in job_ids {
+            match Self::job_fields(conn, job_id, Some(job::COPY_FIELDS)).await {
+                Ok(job) => job_reqs.push(job::CreateRequest::from_job(&job)),
+                Err(OcyError::NoSuchJob(_)) => continue, // deleted in the meantime
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(job_reqs)
+    }
+
+    /// Get current settings for all queues, by queue name.
+    pub async fn all_queue_settings<C: ConnectionLike + Send>(
+        conn: &mut C,
+    ) -> OcyResult<HashMap<String, queue::Settings>> {
+        let mut all_settings = HashMap::new();
+        for queue_name in Self::queue_names(conn).await? {
+            match Self::queue_settings(conn, &queue_name).await {
+                Ok(settings) => all_settings.insert(queue_name, settings),
+                Err(OcyError::NoSuchQueue(_)) => continue, // deleted in the meantime
+                Err(err) => return Err(err),
+            };
+        }
+        Ok(all_settings)
+    }
+
+    /// Get expiry policies for all queues, merging any per-queue overrides with given defaults.
+    pub async fn queue_expiry_policies<C: ConnectionLike + Send>(
+        conn: &mut C,
+        default_statuses: &[job::Status],
+        default_interval: std::time::Duration,
+    ) -> OcyResult<HashMap<String, queue::ExpiryPolicy>> {
+        Ok(Self::all_queue_settings(conn)
+            .await?
+            .into_iter()
+            .map(|(queue_name, settings)| {
+                let policy = queue::ExpiryPolicy::merge(&settings, default_statuses, default_interval);
+                (queue_name, policy)
+            })
+            .collect())
+    }
+
+    /// Get suggested amount of time clients should wait before polling given queue again, based on how long it
+    /// has been since a job was last created on it.
+    pub async fn queue_poll_hint<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        max: std::time::Duration,
+    ) -> OcyResult<std::time::Duration> {
+        let idle_time = RedisQueue::from_string(queue_name)?
+            .ensure_exists(conn)
+            .await?
+            .idle_time(conn)
+            .await?;
+        Ok(RedisQueue::poll_hint(idle_time, max))
+    }
+
+    /// Get the URL jobs on given queue are pushed to, if any.
+    pub async fn queue_callback<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+    ) -> OcyResult<Option<String>> {
+        RedisQueue::from_string(queue_name)?
+            .ensure_exists(conn)
+            .await?
+            .callback(conn)
+            .await
+    }
+
+    /// Set or clear the URL jobs on given queue are pushed to.
+    pub async fn set_queue_callback<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        url: Option<&str>,
+    ) -> OcyResult<()> {
+        RedisQueue::from_string(queue_name)?
+            .ensure_exists(conn)
+            .await?
+            .set_callback(conn, url)
+            .await
+    }
+
+    /// Get (queue name, callback URL) pairs for all queues with push delivery enabled.
+    pub async fn queue_callbacks<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<Vec<(String, String)>> {
+        let queue_names = Self::queue_names(conn).await?;
+        let mut pipeline = redis::pipe();
+        let pipe = &mut pipeline;
+        for queue_name in &queue_names {
+            pipe.hget(RedisQueue::build_key(queue_name), queue::Field::CallbackUrl);
+        }
+
+        let callbacks: Vec<Option<String>> = vec_from_redis_pipe(conn, pipe).await?;
+        Ok(queue_names
+            .into_iter()
+            .zip(callbacks)
+            .filter_map(|(queue_name, url)| url.map(|url| (queue_name, url)))
+            .collect())
+    }
+
+    /// Move a running job back to the front of its queue, returning false if it's no longer running.
+    pub async fn release_job<C: ConnectionLike + Send>(conn: &mut C, job_id: u64) -> OcyResult<bool> {
+        RedisJob::new(job_id).release(conn).await
+    }
+
+    /// Get the name of the queue given job was created on.
+    pub async fn job_queue<C: ConnectionLike + Send>(conn: &mut C, job_id: u64) -> OcyResult<String> {
+        Ok(RedisJob::new(job_id).queue(conn).await?.name)
+    }
+
     /// Get the number of queues jobs in given queue.
     pub async fn queue_size<C: ConnectionLike + Send>(
         conn: &mut C,
@@ -256,279 +950,831 @@ impl RedisManager {
         RedisQueue::from_string(queue_name)?.job_ids(conn).await
     }
 
+    /// Get the IDs of all jobs on given queue, or only those with given status.
+    pub async fn filtered_queue_job_ids<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        status: Option<&job::Status>,
+    ) -> OcyResult<Vec<u64>> {
+        let mut job_ids = RedisQueue::from_string(queue_name)?.ensure_exists(conn).await?.job_ids(conn).await?;
+        let mut filtered = match status {
+            Some(status) => job_ids.remove(status).unwrap_or_default(),
+            None => job_ids.into_values().flatten().collect(),
+        };
+        filtered.sort_unstable();
+        Ok(filtered)
+    }
+
+    /// Get up to `n` randomly chosen jobs on given queue with given status, with all their fields.
+    pub async fn sample_jobs<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        status: &job::Status,
+        n: usize,
+    ) -> OcyResult<Vec<job::JobMeta>> {
+        if n > MAX_SAMPLE_SIZE {
+            return Err(OcyError::bad_request(format!("Can't sample more than {} jobs at once", MAX_SAMPLE_SIZE)));
+        }
+        let queue = RedisQueue::from_string(queue_name)?.ensure_exists(conn).await?;
+
+        let mut jobs = Vec::new();
+        for job_id in queue.sample_job_ids(conn, status, n).await? {
+            match RedisJob::new(job_id).fields(conn, None).await {
+                Ok(job) => jobs.push(job),
+                Err(OcyError::NoSuchJob(_)) => continue, // deleted in the meantime
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(jobs)
+    }
+
     /// Check all jobs in the failed queue for retries.
     ///
     /// Any which can be retried are re-queued on the queue they were created it.
     ///
     /// Any which have no automatic retries remaining are moved to the ended queue.
-    pub async fn check_job_retries<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<Vec<u64>> {
+    ///
+    /// Once a queue's retry budget has been exceeded, the queue is paused for its retry budget cooldown, and its
+    /// failed jobs are left in the failed queue until the pause has ended.
+    ///
+    /// Only jobs on queues that are due to be checked by given sweep are considered.
+    pub async fn check_job_retries<C: ConnectionLike + Send>(
+        conn: &mut C,
+        sweep: &queue::CheckSweep,
+    ) -> OcyResult<Vec<u64>> {
         debug!("Checking for jobs to retry");
         let mut requeued: Vec<u64> = Vec::new();
+        let all_settings = Self::all_queue_settings(conn).await?;
+        let mut budgeted_queues: HashSet<String> = HashSet::new();
+        let mut exhausted_queues: HashSet<String> = HashSet::new();
+
+        let mut scan = JobScan::list(conn, keys::FAILED_KEY, &sweep.batching).await?;
+        while let Some(job_ids) = scan.next_batch(conn).await? {
+            let mut pipeline = redis::pipe();
+            let pipe = &mut pipeline;
+            for job_id in job_ids {
+                pipe.hget(RedisJob::new(job_id).key(), job::RetryMeta::fields());
+            }
 
-        let mut pipeline = redis::pipe();
-        let pipe = &mut pipeline;
-        for job_id in conn.lrange::<_, Vec<u64>>(keys::FAILED_KEY, 0, -1).await? {
-            pipe.hget(RedisJob::new(job_id).key(), job::RetryMeta::fields());
-        }
-
-        for retry_meta in vec_from_redis_pipe::<C, job::RetryMeta>(conn, pipe).await? {
-            match retry_meta.retry_action() {
-                job::RetryAction::Retry => {
-                    let job = RedisJob::new(retry_meta.id());
-                    if job.apply_retries(conn).await? {
-                        requeued.push(job.id());
-                    }
+            for retry_meta in vec_from_redis_pipe::<C, job::RetryMeta>(conn, pipe).await? {
+                if !sweep.includes(retry_meta.queue().as_deref()) {
+                    continue;
                 }
-                job::RetryAction::End => {
-                    let job = RedisJob::new(retry_meta.id());
-                    job.end_failed(conn).await?;
+                match retry_meta.retry_action() {
+                    job::RetryAction::Retry => {
+                        if let Some(queue_name) = retry_meta.queue() {
+                            if exhausted_queues.contains(&queue_name) {
+                                continue;
+                            }
+                            if let Some(settings) = all_settings.get(&queue_name) {
+                                if let Some(budget) = settings.retry_budget {
+                                    let queue = RedisQueue::from_string(&queue_name)?;
+                                    // don't retry jobs on queues that are already paused
+                                    if budgeted_queues.insert(queue_name.clone())
+                                        && queue.paused_until(conn).await?.is_some()
+                                    {
+                                        exhausted_queues.insert(queue_name);
+                                        continue;
+                                    }
+                                    let cooldown = &settings.retry_budget_cooldown;
+                                    if !queue.spend_retry_budget(conn, budget, cooldown).await? {
+                                        exhausted_queues.insert(queue_name);
+                                        continue;
+                                    }
+                                }
+                            }
+                        }
+
+                        let job = RedisJob::new(retry_meta.id());
+                        if job.apply_retries(conn).await? {
+                            requeued.push(job.id());
+                        }
+                    }
+                    job::RetryAction::End => {
+                        let job = RedisJob::new(retry_meta.id());
+                        job.end_failed(conn).await?;
+                    }
+                    job::RetryAction::Quarantine => (), // see check_job_quarantine
+                    job::RetryAction::None => (),
                 }
-                job::RetryAction::None => (),
             }
         }
 
         Ok(requeued)
     }
 
-    /// Check all jobs in the running queue for timeouts.
+    /// Check all jobs in the failed queue for poison strikes.
     ///
-    /// Any which timeout are moved to the failed queue, where they'll eventually either be retried, or moved to the
-    /// ended queue.
-    pub async fn check_job_timeouts<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<Vec<u64>> {
-        debug!("Checking job timeouts");
-        let mut timeouts: Vec<u64> = Vec::new();
-
-        let mut pipeline = redis::pipe();
-        let pipe = &mut pipeline;
-        for job_id in conn.lrange::<_, Vec<u64>>(keys::RUNNING_KEY, 0, -1).await? {
-            pipe.hget(RedisJob::new(job_id).key(), job::TimeoutMeta::fields());
-        }
+    /// Any which have timed out or failed shortly after starting too many times are moved to the quarantined queue,
+    /// where they remain until manually re-queued, cancelled or deleted.
+    ///
+    /// Only jobs on queues that are due to be checked by given sweep are considered.
+    pub async fn check_job_quarantine<C: ConnectionLike + Send>(
+        conn: &mut C,
+        sweep: &queue::CheckSweep,
+    ) -> OcyResult<Vec<u64>> {
+        debug!("Checking for jobs to quarantine");
+        let mut quarantined: Vec<u64> = Vec::new();
+
+        let mut scan = JobScan::list(conn, keys::FAILED_KEY, &sweep.batching).await?;
+        while let Some(job_ids) = scan.next_batch(conn).await? {
+            let mut pipeline = redis::pipe();
+            let pipe = &mut pipeline;
+            for job_id in job_ids {
+                pipe.hget(RedisJob::new(job_id).key(), job::RetryMeta::fields());
+            }
 
-        for timeout_meta in vec_from_redis_pipe::<C, job::TimeoutMeta>(conn, pipe).await? {
-            if timeout_meta.has_timed_out() {
-                let job = RedisJob::new(timeout_meta.id());
-                if job.apply_timeouts(conn).await? {
-                    timeouts.push(job.id());
+            for retry_meta in vec_from_redis_pipe::<C, job::RetryMeta>(conn, pipe).await? {
+                if !sweep.includes(retry_meta.queue().as_deref()) {
+                    continue;
+                }
+                if let job::RetryAction::Quarantine = retry_meta.retry_action() {
+                    let job = RedisJob::new(retry_meta.id());
+                    if job.quarantine(conn).await? {
+                        quarantined.push(job.id());
+                    }
                 }
             }
         }
 
-        Ok(timeouts)
+        Ok(quarantined)
     }
 
-    /// Check all jobs in the ended queue for expiry. Any expired jobs will be entirely removed from the queue system.
-    pub async fn check_job_expiry<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<Vec<u64>> {
-        debug!("Checking for expired jobs");
-        let mut expired: Vec<u64> = Vec::new();
-
-        let mut pipeline = redis::pipe();
-        let pipe = &mut pipeline;
-        for job_id in conn.lrange::<_, Vec<u64>>(keys::ENDED_KEY, 0, -1).await? {
-            pipe.hget(RedisJob::new(job_id).key(), job::ExpiryMeta::fields());
-        }
+    /// Get metadata for running jobs from given queue whose last heartbeat (or start time, if they've not sent a
+    /// heartbeat) is older than given threshold, but which haven't timed out.
+    pub async fn stuck_jobs<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        running_longer_than: &Duration,
+    ) -> OcyResult<Vec<job::JobMeta>> {
+        RedisQueue::from_string(queue_name)?.ensure_exists(conn).await?;
 
-        for expiry_meta in vec_from_redis_pipe::<C, job::ExpiryMeta>(conn, pipe).await? {
-            if expiry_meta.should_expire() {
-                let job = RedisJob::new(expiry_meta.id());
-                if job.apply_expiry(conn).await? {
-                    expired.push(job.id());
+        let now = clock::now();
+        let mut jobs = Vec::new();
+        for job_id in conn.lrange::<_, Vec<u64>>(keys::RUNNING_KEY, 0, -1).await? {
+            let fields = &[
+                job::Field::Id,
+                job::Field::Queue,
+                job::Field::Status,
+                job::Field::StartedAt,
+                job::Field::LastHeartbeat,
+                job::Field::Timeout,
+                job::Field::HeartbeatTimeout,
+            ];
+            let job_meta = match RedisJob::new(job_id).metadata(conn, fields).await {
+                Ok(job_meta) => job_meta,
+                Err(OcyError::NoSuchJob(_)) => continue, // deleted in the meantime
+                Err(err) => return Err(err),
+            };
+            if job_meta.queue() != queue_name || job_meta.status() != job::Status::Running {
+                continue;
+            }
+
+            let last_active = match job_meta.last_heartbeat().or_else(|| job_meta.started_at()) {
+                Some(last_active) => last_active,
+                None => continue,
+            };
+            if now.seconds_since(&last_active) > running_longer_than.as_secs() as i64 {
+                jobs.push(job_meta);
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// Check all jobs whose SLA deadline has passed.
+    ///
+    /// Any which weren't completed or cancelled by their deadline are marked as having breached their SLA, and
+    /// counted in the server's statistics.
+    ///
+    /// Only jobs on queues that are due to be checked by given sweep are considered.
+    pub async fn check_sla_deadlines<C: ConnectionLike + Send>(
+        conn: &mut C,
+        sweep: &queue::CheckSweep,
+    ) -> OcyResult<Vec<u64>> {
+        debug!("Checking for jobs that have breached their SLA");
+        let mut breached: Vec<u64> = Vec::new();
+
+        let now = clock::now().timestamp();
+        let mut scan = JobScan::sorted_set(conn, keys::SLA_DEADLINES_KEY, now, &sweep.batching).await?;
+        while let Some(job_ids) = scan.next_batch(conn).await? {
+            let mut pipeline = redis::pipe();
+            let pipe = &mut pipeline;
+            for job_id in &job_ids {
+                pipe.hget(RedisJob::new(*job_id).key(), job::SlaMeta::fields());
+            }
+
+            let sla_metas: Vec<job::SlaMeta> = vec_from_redis_pipe(conn, pipe).await?;
+            for (job_id, sla_meta) in job_ids.into_iter().zip(sla_metas) {
+                if !sweep.includes(sla_meta.queue().as_deref()) {
+                    continue;
+                }
+                let job = RedisJob::new(job_id);
+                if job.check_sla(conn).await? {
+                    breached.push(job_id);
                 }
             }
         }
 
-        Ok(expired)
+        Ok(breached)
     }
 
-    // TODO: make available as endpoint? Or optional periodic check?
-    /// Checks the integrity of Redis DB, e.g. checking for dangling indexes, jobs in invalid states, etc.
+    /// Check the queued jobs of all queues with a `max_queue_age`.
     ///
-    /// Mostly intended for use during development, as it has a non-trivial runtime cost.
-    pub async fn check_db_integrity<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<()> {
-        for queue_name in Self::queue_names(conn).await? {
-            let queue = RedisQueue::from_string(&queue_name)?;
-            if !(queue.exists(conn).await?) {
-                warn!(
-                    "Queue '{}' found in {}, but not as key",
-                    queue_name,
-                    keys::QUEUES_KEY
-                );
+    /// Any which have waited to be started for longer than their queue's maximum age are moved to the quarantined
+    /// queue with the reason `expired_in_queue`, where they remain until manually re-queued, cancelled or deleted.
+    ///
+    /// Only queues that are due to be checked by given sweep are considered.
+    pub async fn check_queue_ages<C: ConnectionLike + Send>(
+        conn: &mut C,
+        sweep: &queue::CheckSweep,
+    ) -> OcyResult<Vec<u64>> {
+        debug!("Checking for jobs that have waited too long to start");
+        let mut quarantined: Vec<u64> = Vec::new();
+
+        for (queue_name, settings) in Self::all_queue_settings(conn).await? {
+            let max_age = match settings.max_queue_age {
+                Some(max_age) if sweep.includes(Some(&queue_name)) => max_age,
+                _ => continue,
+            };
+
+            for queue_key in RedisQueue::from_string(&queue_name)?.queued_keys(conn).await? {
+                let mut scan = JobScan::list(conn, &queue_key, &sweep.batching).await?;
+                while let Some(job_ids) = scan.next_batch(conn).await? {
+                    let mut pipeline = redis::pipe();
+                    let pipe = &mut pipeline;
+                    for job_id in job_ids {
+                        pipe.hget(RedisJob::new(job_id).key(), job::QueueAgeMeta::fields());
+                    }
+
+                    for age_meta in vec_from_redis_pipe::<C, job::QueueAgeMeta>(conn, pipe).await? {
+                        if !age_meta.has_expired_in_queue(&max_age) {
+                            continue;
+                        }
+                        let job = RedisJob::new(age_meta.id());
+                        if job.quarantine_if_expired_in_queue(conn, &max_age).await? {
+                            quarantined.push(job.id());
+                        }
+                    }
+                }
             }
         }
 
-        let mut iter: redis::AsyncIter<String> = conn.scan_match::<_, String>("queue:*").await?;
-        let mut queues = Vec::new();
-        while let Some(queue_key) = iter.next_item().await {
-            if !queue_key.ends_with(":jobs") {
-                queues.push(queue_key);
+        Ok(quarantined)
+    }
+
+    /// Get metadata for all jobs from given queue that have breached their SLA, including their deadline.
+    pub async fn sla_breaches<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+    ) -> OcyResult<Vec<job::JobMeta>> {
+        RedisQueue::from_string(queue_name)?.ensure_exists(conn).await?;
+
+        let mut jobs = Vec::new();
+        for job_id in conn.lrange::<_, Vec<u64>>(keys::SLA_BREACHED_KEY, 0, -1).await? {
+            let fields = &[
+                job::Field::Id,
+                job::Field::Queue,
+                job::Field::Status,
+                job::Field::CreatedAt,
+                job::Field::EndedAt,
+                job::Field::Deadline,
+            ];
+            match RedisJob::new(job_id).metadata(conn, fields).await {
+                Ok(job_meta) if job_meta.queue() == queue_name => jobs.push(job_meta),
+                Ok(_) => (),
+                Err(OcyError::NoSuchJob(_)) => (), // deleted in the meantime
+                Err(err) => return Err(err),
             }
         }
+        Ok(jobs)
+    }
 
-        for queue_key in queues {
-            if !conn
-                .sismember::<_, _, bool>(keys::QUEUES_KEY, &queue_key[6..])
-                .await?
-            {
-                warn!(
-                    "Queue '{}' found as key, but not in {}",
-                    &queue_key,
-                    keys::QUEUES_KEY
-                );
-            }
+    /// Get the numbers of queued and running jobs on given queue, and the age of its oldest queued job.
+    pub async fn queue_backlog<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+    ) -> OcyResult<queue::Backlog> {
+        RedisQueue::from_string(queue_name)?
+            .ensure_exists(conn)
+            .await?
+            .backlog(conn)
+            .await
+    }
+
+    /// Estimate how long jobs wait on given queue, considering jobs started from it within given window.
+    pub async fn queue_latency<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        window: std::time::Duration,
+    ) -> OcyResult<queue::Latency> {
+        RedisQueue::from_string(queue_name)?
+            .ensure_exists(conn)
+            .await?
+            .latency(conn, window)
+            .await
+    }
+
+    /// Get failed jobs from given queue, grouped by the reason they failed.
+    pub async fn failure_summary<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+    ) -> OcyResult<queue::FailureSummary> {
+        let queue = RedisQueue::from_string(queue_name)?.ensure_exists(conn).await?;
+        let job_ids = queue.job_ids(conn).await?.remove(&job::Status::Failed).unwrap_or_default();
+
+        let mut pipe = redis::pipe();
+        for job_id in &job_ids {
+            pipe.hget(RedisJob::new(*job_id).key(), &[job::Field::ErrorCode, job::Field::Output]);
         }
+        let results = vec_from_redis_pipe::<C, (Option<String>, Option<String>)>(conn, &pipe).await?;
 
-        let _: () = transaction_async!(conn, &[keys::RUNNING_KEY], {
-            let mut pipe = redis::pipe();
-            let pipe_ref = pipe.atomic();
+        // JSON parse error should never happen unless someone manually writes data to Redis outside of Ocypod
+        let failures = job_ids.into_iter().zip(results).map(|(job_id, (error_code, output))| {
+            (job_id, error_code, output.and_then(|s| crypto::open(s).ok()).and_then(|s| serde_json::from_str(&s).ok()))
+        });
+        Ok(queue::FailureSummary::from_failures(failures))
+    }
 
-            for job_id in conn.lrange::<_, Vec<u64>>(keys::RUNNING_KEY, 0, -1).await? {
-                pipe_ref.hget(
-                    RedisJob::new(job_id).key(),
-                    &[job::Field::Id, job::Field::Status, job::Field::StartedAt],
-                );
+    /// Get metadata for all quarantined jobs from given queue, including the reason each was quarantined.
+    pub async fn quarantined_jobs<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+    ) -> OcyResult<Vec<job::JobMeta>> {
+        let queue = RedisQueue::from_string(queue_name)?.ensure_exists(conn).await?;
+        let mut job_ids = queue.job_ids(conn).await?;
+
+        let mut jobs = Vec::new();
+        for job_id in job_ids.remove(&job::Status::Quarantined).unwrap_or_default() {
+            let fields = &[
+                job::Field::Id,
+                job::Field::PoisonStrikes,
+                job::Field::QuarantineReason,
+                job::Field::EndedAt,
+            ];
+            match RedisJob::new(job_id).metadata(conn, fields).await {
+                Ok(job_meta) => jobs.push(job_meta),
+                Err(OcyError::NoSuchJob(_)) => (), // deleted in the meantime
+                Err(err) => return Err(err),
             }
+        }
+        Ok(jobs)
+    }
 
-            let info: Vec<(Option<u64>, Option<job::Status>, Option<DateTime>)> =
-                vec_from_redis_pipe(conn, pipe_ref).await?;
-            for (job_id, status, started_at) in info {
-                let job_id = match job_id {
-                    Some(job_id) => job_id,
-                    None => {
-                        warn!(
-                            "Found job in {} queue, but did not find key",
-                            keys::RUNNING_KEY
-                        );
-                        continue;
-                    }
-                };
+    /// Check all jobs in the running queue for timeouts.
+    ///
+    /// Any which timeout are moved to the failed queue, where they'll eventually either be retried, or moved to the
+    /// ended queue.
+    ///
+    /// Only jobs on queues that are due to be checked by given sweep are considered. Heartbeat timeouts are relaxed
+    /// while this server is overloaded, see `tolerance`.
+    pub async fn check_job_timeouts<C: ConnectionLike + Send>(
+        conn: &mut C,
+        sweep: &queue::CheckSweep,
+    ) -> OcyResult<Vec<u64>> {
+        debug!("Checking job timeouts");
+        let mut timeouts: Vec<u64> = Vec::new();
 
-                match status {
-                    Some(job::Status::Running) => (),
-                    Some(status) => {
-                        warn!("Found status '{}' in {} queue", status, keys::RUNNING_KEY)
+        let mut scan = JobScan::list(conn, keys::RUNNING_KEY, &sweep.batching).await?;
+        while let Some(job_ids) = scan.next_batch(conn).await? {
+            let mut pipeline = redis::pipe();
+            let pipe = &mut pipeline;
+            for job_id in job_ids {
+                pipe.hget(RedisJob::new(job_id).key(), job::TimeoutMeta::fields());
+            }
+
+            let heartbeat_factor = HEARTBEAT_TOLERANCE.factor();
+            for timeout_meta in vec_from_redis_pipe::<C, job::TimeoutMeta>(conn, pipe).await? {
+                if sweep.includes(timeout_meta.queue().as_deref()) && timeout_meta.has_timed_out(heartbeat_factor) {
+                    let job = RedisJob::new(timeout_meta.id());
+                    if job.apply_timeouts(conn).await? {
+                        timeouts.push(job.id());
                     }
-                    None => warn!(
-                        "Found job {} in {} queue, but did not find key",
-                        job_id,
-                        keys::RUNNING_KEY
-                    ),
                 }
+            }
+        }
 
-                if started_at.is_none() {
-                    warn!(
-                        "Found job {} in {} queue, but job has no started_at",
-                        job_id,
-                        keys::RUNNING_KEY
-                    );
+        Ok(timeouts)
+    }
+
+    /// Check all jobs in the ended queue for expiry. Any expired jobs will be entirely removed from the queue system.
+    ///
+    /// Only jobs on queues that are due to be checked by given sweep are considered.
+    pub async fn check_job_expiry<C: ConnectionLike + Send>(
+        conn: &mut C,
+        sweep: &queue::ExpirySweep,
+    ) -> OcyResult<Vec<u64>> {
+        debug!("Checking for expired jobs");
+        let mut expired: Vec<u64> = Vec::new();
+
+        let mut scan = JobScan::list(conn, keys::ENDED_KEY, &sweep.batching).await?;
+        while let Some(job_ids) = scan.next_batch(conn).await? {
+            for job_id in Self::expired_among(conn, &job_ids, sweep).await? {
+                let job = RedisJob::new(job_id);
+                if job.apply_expiry(conn, sweep).await? {
+                    expired.push(job.id());
                 }
             }
+        }
 
-            Some(())
-        });
+        Ok(expired)
+    }
 
-        let _: () = transaction_async!(conn, &[keys::FAILED_KEY], {
-            let mut pipe = redis::pipe();
-            let pipe_ref = pipe.atomic();
+    /// Get IDs of all jobs in the ended queue that have expired, without removing them.
+    ///
+    /// Only jobs on queues that are due to be checked by given sweep are considered.
+    pub async fn expired_job_ids<C: ConnectionLike + Send>(
+        conn: &mut C,
+        sweep: &queue::ExpirySweep,
+    ) -> OcyResult<Vec<u64>> {
+        let mut expired = Vec::new();
+        let mut scan = JobScan::list(conn, keys::ENDED_KEY, &sweep.batching).await?;
+        while let Some(job_ids) = scan.next_batch(conn).await? {
+            expired.extend(Self::expired_among(conn, &job_ids, sweep).await?);
+        }
+        Ok(expired)
+    }
 
-            for job_id in conn.lrange::<_, Vec<u64>>(keys::FAILED_KEY, 0, -1).await? {
-                pipe_ref.hget(
-                    RedisJob::new(job_id).key(),
-                    &[job::Field::Id, job::Field::Status, job::Field::EndedAt],
-                );
+    /// Get IDs of the given ended jobs that have expired.
+    async fn expired_among<C: ConnectionLike + Send>(
+        conn: &mut C,
+        job_ids: &[u64],
+        sweep: &queue::ExpirySweep,
+    ) -> OcyResult<Vec<u64>> {
+        let mut pipeline = redis::pipe();
+        let pipe = &mut pipeline;
+        for job_id in job_ids {
+            pipe.hget(RedisJob::new(*job_id).key(), job::ExpiryMeta::fields());
+        }
+
+        let mut expired = Vec::new();
+        for expiry_meta in vec_from_redis_pipe::<C, job::ExpiryMeta>(conn, pipe).await? {
+            let statuses = expiry_meta.queue().and_then(|queue| sweep.statuses(&queue));
+            if statuses.is_some_and(|statuses| expiry_meta.should_expire(statuses)) {
+                expired.push(expiry_meta.id());
             }
+        }
+        Ok(expired)
+    }
 
-            let info: Vec<(Option<u64>, Option<job::Status>, Option<DateTime>)> =
-                vec_from_redis_pipe(conn, pipe_ref).await?;
-            for (job_id, status, ended_at) in info {
-                let job_id = match job_id {
-                    Some(job_id) => job_id,
-                    None => {
-                        warn!(
-                            "Found job in {} queue, but did not find key",
-                            keys::FAILED_KEY
-                        );
-                        continue;
-                    }
-                };
+    /// Immediately check given queue's ended jobs for expiry, using its expiry policy.
+    ///
+    /// If `dry_run` is true, then IDs of jobs that would be expired are returned, but nothing is removed.
+    pub async fn expire_queue<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        default_statuses: &[job::Status],
+        dry_run: bool,
+    ) -> OcyResult<Vec<u64>> {
+        let settings = Self::queue_settings(conn, queue_name).await?;
+        let statuses = settings.expiry_check_statuses.unwrap_or_else(|| default_statuses.to_vec());
 
-                match status {
-                    Some(job::Status::Failed) | Some(job::Status::TimedOut) => (),
-                    Some(status) => {
-                        warn!("Found status '{}' in {} queue", status, keys::FAILED_KEY)
-                    }
-                    None => warn!(
-                        "Found job {} in {} queue, but did not find key",
-                        job_id,
-                        keys::FAILED_KEY
-                    ),
-                }
+        let mut sweep = queue::ExpirySweep::default();
+        sweep.queues.insert(queue_name.to_owned(), Some(statuses));
 
-                if ended_at.is_none() {
-                    warn!(
-                        "Found job {} in {} queue, but job has no ended_at",
-                        job_id,
-                        keys::FAILED_KEY
-                    );
-                }
+        if dry_run {
+            Self::expired_job_ids(conn, &sweep).await
+        } else {
+            Self::check_job_expiry(conn, &sweep).await
+        }
+    }
+
+    /// Delete all of given queue's ended jobs, regardless of whether they've expired.
+    ///
+    /// If `dry_run` is true, then IDs of jobs that would be deleted are returned, but nothing is removed.
+    pub async fn purge_queue<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        dry_run: bool,
+    ) -> OcyResult<Vec<u64>> {
+        let queue = RedisQueue::from_string(queue_name)?.ensure_exists(conn).await?;
+
+        let mut pipeline = redis::pipe();
+        let pipe = &mut pipeline;
+        for job_id in conn.lrange::<_, Vec<u64>>(keys::ENDED_KEY, 0, -1).await? {
+            pipe.hget(RedisJob::new(job_id).key(), &[job::Field::Id, job::Field::Queue]);
+        }
+
+        let mut purged = Vec::new();
+        for (job_id, job_queue) in vec_from_redis_pipe::<C, (Option<u64>, Option<String>)>(conn, pipe).await? {
+            let job_id = match job_id {
+                Some(job_id) if job_queue.as_ref() == Some(&queue.name) => job_id,
+                _ => continue,
+            };
+
+            if dry_run || RedisJob::new(job_id).delete(conn).await? {
+                purged.push(job_id);
             }
+        }
 
-            Some(())
-        });
+        if !dry_run {
+            info!("[{}] purged {} ended job(s)", &queue.key, purged.len());
+        }
+        Ok(purged)
+    }
 
-        let _: () = transaction_async!(conn, &[keys::ENDED_KEY], {
-            let mut pipe = redis::pipe();
-            let pipe_ref = pipe.atomic();
+    /// Check the consistency of jobs and their indexes in Redis, and optionally repair any problems found.
+    ///
+    /// Looks for queues with no settings, IDs of jobs that no longer exist in status lists, queues and tags, IDs in
+    /// status lists or queues that don't match the job's status, and jobs that aren't in any status list or queue.
+    ///
+    /// Each repair is only applied if the problem still exists at the time, so jobs changing state during the check
+    /// aren't affected. Orphaned jobs are restored to the status list or queue matching their status, other problems
+    /// are repaired by removing the invalid entry.
+    pub async fn check_integrity<C: ConnectionLike + Send>(conn: &mut C, repair: bool) -> OcyResult<IntegrityReport> {
+        let mut report = IntegrityReport { repaired: repair, ..Default::default() };
 
-            for job_id in conn.lrange::<_, Vec<u64>>(keys::ENDED_KEY, 0, -1).await? {
-                pipe_ref.hget(
-                    RedisJob::new(job_id).key(),
-                    &[job::Field::Id, job::Field::Status, job::Field::EndedAt],
-                );
+        let mut queues = Vec::new();
+        for queue_name in Self::queue_names(conn).await? {
+            let queue = RedisQueue::from_string(&queue_name)?;
+            if queue.exists(conn).await? {
+                queues.push(queue);
+            } else {
+                if repair {
+                    let _: () = conn.srem(keys::QUEUES_KEY, &queue_name).await?;
+                }
+                report.dangling_queues.push(queue_name);
             }
+        }
 
-            let info: Vec<(Option<u64>, Option<job::Status>, Option<DateTime>)> =
-                vec_from_redis_pipe(conn, pipe_ref).await?;
-            for (job_id, status, ended_at) in info {
-                let job_id = match job_id {
-                    Some(job_id) => job_id,
-                    None => {
-                        warn!(
-                            "Found job in {} queue, but did not find key",
-                            keys::ENDED_KEY
-                        );
-                        continue;
-                    }
+        // scan for jobs before reading indexes, so that jobs created in between aren't thought to be orphaned
+        let mut job_ids = Vec::new();
+        let mut iter: redis::AsyncIter<String> = conn.scan_match(format!("{}*", keys::JOB_PREFIX)).await?;
+        while let Some(job_key) = iter.next_item().await {
+            if let Ok(job_id) = job_key[keys::JOB_PREFIX.len()..].parse::<u64>() {
+                job_ids.push(job_id);
+            }
+        }
+
+        // index of each key, along with the statuses and queue its jobs should have
+        let mut indexes: Vec<(String, &[job::Status], Option<&str>)> = INTEGRITY_INDEXES
+            .iter()
+            .map(|(key, statuses)| (key.to_string(), *statuses, None))
+            .collect();
+        for queue in &queues {
+            for queue_key in queue.queued_keys(conn).await? {
+                indexes.push((queue_key, &[job::Status::Queued], Some(&queue.name)));
+            }
+        }
+
+        // read all indexes atomically, since jobs are moved between them atomically
+        let mut pipe = redis::pipe();
+        pipe.atomic().lrange(keys::LIMBO_KEY, 0, -1);
+        for (key, _, _) in &indexes {
+            pipe.lrange(key, 0, -1);
+        }
+        let mut index_ids: Vec<Vec<u64>> = pipe.query_async(conn).await?;
+        let limbo_ids = index_ids.remove(0);
+
+        let mut indexed: HashSet<u64> = limbo_ids.into_iter().collect();
+        for ((key, statuses, queue_name), ids) in indexes.iter().zip(index_ids) {
+            indexed.extend(&ids);
+            let mut pipe = redis::pipe();
+            for job_id in &ids {
+                pipe.hget(RedisJob::new(*job_id).key(), &[job::Field::Status, job::Field::Queue]);
+            }
+            let job_info: Vec<(Option<job::Status>, Option<String>)> = vec_from_redis_pipe(conn, &pipe).await?;
+            for (job_id, (status, queue)) in ids.into_iter().zip(job_info) {
+                let wrong_queue = queue_name.is_some_and(|name| queue.as_deref() != Some(name));
+                let problems = match status {
+                    None => &mut report.dangling_ids,
+                    Some(ref status) if wrong_queue || !statuses.contains(status) => &mut report.mismatched_ids,
+                    Some(_) => continue,
                 };
+                if repair {
+                    Self::remove_index_entry(conn, key, job_id, statuses, *queue_name).await?;
+                }
+                problems.entry(key.clone()).or_default().push(job_id);
+            }
+        }
 
-                match status {
-                    Some(job::Status::Failed)
-                    | Some(job::Status::TimedOut)
-                    | Some(job::Status::Completed)
-                    | Some(job::Status::Cancelled) => (),
-                    Some(status) => warn!("Found status '{}' in {} queue", status, keys::ENDED_KEY),
-                    None => warn!(
-                        "Found job {} in {} queue, but did not find key",
-                        job_id,
-                        keys::ENDED_KEY
-                    ),
+        for (tag_key, job_ids) in Self::dangling_tag_ids(conn).await? {
+            if repair {
+                for job_id in &job_ids {
+                    Self::remove_index_entry(conn, &tag_key, *job_id, &job::ALL_STATUSES, None).await?;
                 }
+            }
+            report.dangling_ids.insert(tag_key, job_ids);
+        }
 
-                if ended_at.is_none() {
-                    warn!(
-                        "Found job {} in {} queue, but job has no started_at",
-                        job_id,
-                        keys::ENDED_KEY
-                    );
+        let index_keys: Vec<&str> = std::iter::once(keys::LIMBO_KEY)
+            .chain(indexes.iter().map(|(key, _, _)| key.as_str()))
+            .collect();
+        let mut orphan_ids: Vec<u64> = job_ids.into_iter().filter(|job_id| !indexed.contains(job_id)).collect();
+        orphan_ids.sort_unstable();
+        orphan_ids.dedup(); // SCAN may return a key more than once
+        for job_id in orphan_ids {
+            let job = RedisJob::new(job_id);
+            let (status, queue_name, held): (Option<job::Status>, Option<String>, Option<bool>) = conn
+                .hget(job.key(), &[job::Field::Status, job::Field::Queue, job::Field::Held])
+                .await?;
+            let status = match status {
+                Some(status) => status,
+                None => continue, // deleted in the meantime
+            };
+            if repair {
+                Self::restore_orphaned_job(conn, &job, &status, queue_name, held.unwrap_or_default(), &index_keys)
+                    .await?;
+            }
+            report.orphaned_jobs.push(job_id);
+        }
+
+        let mut storage = Self::measure_namespace_storage(conn).await?;
+        let counted: HashMap<String, i64> = conn.hgetall(keys::NAMESPACE_STORAGE_KEY).await?;
+        for (namespace, counted_bytes) in counted {
+            if !namespace.is_empty() {
+                *storage.entry(namespace).or_default() -= counted_bytes;
+            }
+        }
+        for (namespace, measured_minus_counted) in storage {
+            if measured_minus_counted == 0 {
+                continue;
+            }
+            if repair {
+                // adjusted rather than set, so that changes made since measuring aren't lost
+                let _: () = conn.hincr(keys::NAMESPACE_STORAGE_KEY, &namespace, measured_minus_counted).await?;
+            }
+            report.miscounted_storage.insert(namespace, -measured_minus_counted);
+        }
+
+        if !report.is_clean() {
+            warn!(
+                "Integrity check found {} dangling queue(s), {} dangling ID(s), {} mismatched ID(s), {} orphan(s), \
+                 {} miscounted namespace(s)",
+                report.dangling_queues.len(),
+                report.dangling_ids.values().map(Vec::len).sum::<usize>(),
+                report.mismatched_ids.values().map(Vec::len).sum::<usize>(),
+                report.orphaned_jobs.len(),
+                report.miscounted_storage.len(),
+            );
+        }
+        Ok(report)
+    }
+
+    /// Remove a job that was deleted by Redis rather than by Ocypod, e.g. evicted due to Redis' `maxmemory` policy,
+    /// from every status list and queue, returning the keys it was removed from. Nothing is removed if the job still
+    /// exists.
+    ///
+    /// The job's tags aren't known once it's gone, so it's left in them until tags are next pruned.
+    pub async fn remove_lost_job<C: ConnectionLike + Send>(conn: &mut C, job_id: u64) -> OcyResult<Vec<String>> {
+        let index_keys: Vec<&str> = INTEGRITY_INDEXES.iter().map(|(key, _)| *key).collect();
+        let mut queue_keys: Vec<String> = Vec::new();
+        for queue_name in Self::queue_names(conn).await? {
+            queue_keys.extend(RedisQueue::from_string(queue_name)?.queued_keys(conn).await?);
+        }
+        Ok(redis::Script::new(REMOVE_LOST_JOB_SCRIPT)
+            .key(RedisJob::new(job_id).key())
+            .key(&[keys::LIMBO_KEY, keys::SLA_DEADLINES_KEY, keys::SLA_BREACHED_KEY])
+            .key(index_keys)
+            .key(queue_keys)
+            .arg(job_id)
+            .invoke_async(conn)
+            .await?)
+    }
+
+    /// Remove the IDs of jobs that no longer exist from all tags, returning the IDs removed from each tag by tag name.
+    /// Redis deletes tags once they're empty.
+    ///
+    /// If `dry_run` is true, then IDs that would be removed are returned, but nothing is removed.
+    pub async fn prune_tags<C: ConnectionLike + Send>(
+        conn: &mut C,
+        dry_run: bool,
+    ) -> OcyResult<HashMap<String, Vec<u64>>> {
+        debug!("Pruning tags of jobs that no longer exist");
+        let mut pruned = HashMap::new();
+        for (tag_key, job_ids) in Self::dangling_tag_ids(conn).await? {
+            if !dry_run {
+                for job_id in &job_ids {
+                    Self::remove_index_entry(conn, &tag_key, *job_id, &job::ALL_STATUSES, None).await?;
                 }
             }
+            pruned.insert(tag_key[keys::TAG_PREFIX.len()..].to_owned(), job_ids);
+        }
+        Ok(pruned)
+    }
 
-            Some(())
-        });
+    /// Get the IDs of jobs that no longer exist from each tag that has any, along with the tag's key.
+    async fn dangling_tag_ids<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<Vec<(String, Vec<u64>)>> {
+        let mut tag_keys = Vec::new();
+        let mut iter: redis::AsyncIter<String> = conn.scan_match(format!("{}*", keys::TAG_PREFIX)).await?;
+        while let Some(tag_key) = iter.next_item().await {
+            tag_keys.push(tag_key);
+        }
+        tag_keys.sort_unstable();
+        tag_keys.dedup(); // SCAN may return a key more than once
 
+        let mut dangling = Vec::new();
+        for tag_key in tag_keys {
+            let ids: Vec<u64> = conn.smembers(&tag_key).await?;
+            let mut pipe = redis::pipe();
+            for job_id in &ids {
+                pipe.exists(RedisJob::new(*job_id).key());
+            }
+            let exists: Vec<bool> = vec_from_redis_pipe(conn, &pipe).await?;
+            let job_ids: Vec<u64> =
+                ids.into_iter().zip(exists).filter(|(_, exists)| !exists).map(|(job_id, _)| job_id).collect();
+            if !job_ids.is_empty() {
+                dangling.push((tag_key, job_ids));
+            }
+        }
+        Ok(dangling)
+    }
+
+    /// Remove a job's ID from given status list, queue, or tag, unless the job exists with one of the given statuses
+    /// (and on the given queue, if any).
+    async fn remove_index_entry<C: ConnectionLike + Send>(
+        conn: &mut C,
+        key: &str,
+        job_id: u64,
+        statuses: &[job::Status],
+        queue_name: Option<&str>,
+    ) -> OcyResult<()> {
+        let _: u64 = redis::Script::new(REMOVE_INDEX_ENTRY_SCRIPT)
+            .key(key)
+            .key(RedisJob::new(job_id).key())
+            .arg(job_id)
+            .arg(job::Field::Status)
+            .arg(job::Field::Queue)
+            .arg(queue_name.unwrap_or_default())
+            .arg(statuses)
+            .invoke_async(conn)
+            .await?;
+        info!("[{}{}] removed dangling entry from {}", keys::JOB_PREFIX, job_id, key);
+        Ok(())
+    }
+
+    /// Add a job that isn't in any status list or queue to the one matching its status, unless it's since changed
+    /// status or been added to one.
+    async fn restore_orphaned_job<C: ConnectionLike + Send>(
+        conn: &mut C,
+        job: &RedisJob,
+        status: &job::Status,
+        queue_name: Option<String>,
+        held: bool,
+        index_keys: &[&str],
+    ) -> OcyResult<()> {
+        let target_key = match status {
+            job::Status::Queued if held => keys::HELD_KEY.to_owned(),
+            job::Status::Queued => match queue_name.map(RedisQueue::from_string) {
+                Some(Ok(queue)) if queue.exists(conn).await? => {
+                    let list = job.queue_list(conn).await?;
+                    if list != job::QueueList::default() {
+                        // recording the list before the job is restored to it is harmless, since it's checked for jobs
+                        let _: () = queue.add_queue_list_in_pipe(&mut redis::pipe(), &list).query_async(conn).await?;
+                    }
+                    queue.queue_list_key(&list)
+                }
+                _ => {
+                    warn!("[{}] orphaned job's queue doesn't exist, not restoring", job.key());
+                    return Ok(());
+                }
+            },
+            job::Status::Running => keys::RUNNING_KEY.to_owned(),
+            job::Status::Failed | job::Status::TimedOut => keys::FAILED_KEY.to_owned(),
+            job::Status::Completed | job::Status::Cancelled => keys::ENDED_KEY.to_owned(),
+            job::Status::Quarantined => keys::QUARANTINED_KEY.to_owned(),
+        };
+
+        let restored: bool = redis::Script::new(RESTORE_ORPHANED_JOB_SCRIPT)
+            .key(job.key())
+            .key(&target_key)
+            .key(index_keys)
+            .arg(job.id())
+            .arg(job::Field::Status)
+            .arg(status)
+            .invoke_async(conn)
+            .await?;
+        if restored {
+            info!("[{}] orphaned job restored to {} by integrity check", job.key(), target_key);
+        }
         Ok(())
     }
 
+    /// Mark given worker as draining, so that it's not given any new jobs, but can finish those it's running. Returns
+    /// `false` if the worker was already draining.
+    pub async fn drain_worker<C: ConnectionLike + Send>(conn: &mut C, worker_id: &str) -> OcyResult<bool> {
+        Ok(conn.hset_nx(keys::DRAINING_WORKERS_KEY, worker_id, clock::now()).await?)
+    }
+
+    /// Stop given worker draining, so that it's given jobs again. Returns `false` if the worker wasn't draining.
+    pub async fn undrain_worker<C: ConnectionLike + Send>(conn: &mut C, worker_id: &str) -> OcyResult<bool> {
+        let removed: u64 = conn.hdel(keys::DRAINING_WORKERS_KEY, worker_id).await?;
+        Ok(removed > 0)
+    }
+
+    /// Get the drain status of given worker.
+    pub async fn worker_status<C: ConnectionLike + Send>(conn: &mut C, worker_id: &str) -> OcyResult<WorkerStatus> {
+        let draining_since: Option<DateTime> = conn.hget(keys::DRAINING_WORKERS_KEY, worker_id).await?;
+        Ok(WorkerStatus::new(worker_id, draining_since))
+    }
+
+    /// Get the drain status of all draining workers, ordered by worker ID.
+    pub async fn draining_workers<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<Vec<WorkerStatus>> {
+        let workers: BTreeMap<String, DateTime> = conn.hgetall(keys::DRAINING_WORKERS_KEY).await?;
+        Ok(workers.into_iter().map(|(id, since)| WorkerStatus::new(&id, Some(since))).collect())
+    }
+
     /// Check connection to Redis using ping command.
-    #[cfg_attr(feature = "cargo-clippy", allow(clippy::unit_arg))]
+    #[allow(clippy::unit_arg)]
     pub async fn check_ping<C: ConnectionLike>(conn: &mut C) -> OcyResult<()> {
         Ok(redis::cmd("PING").query_async(conn).await?)
     }
@@ -542,40 +1788,76 @@ impl RedisManager {
         conn: &mut C,
         queue_name: &str,
     ) -> OcyResult<Option<job::Payload>> {
-        debug!("Client requested job from queue={}", queue_name);
+        Self::next_routed_job(conn, queue_name, &[], None, None).await
+    }
+
+    /// Fetch the next job from given queue with any of the given routing keys, if any, falling back to jobs without a
+    /// routing key.
+    ///
+    /// Routing keys are tried in the order given, so a worker can list the keys it's best suited to first. If
+    /// `sticky_sessions` is given, jobs with a session key are tried before any others, preferring sessions whose
+    /// previous job was taken by the worker with given ID.
+    ///
+    /// # Returns
+    ///
+    /// A `job::Payload` if a job is found, or `None` if the queue has no jobs the worker can take.
+    pub async fn next_routed_job<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        routing_keys: &[String],
+        worker_id: Option<&str>,
+        sticky_sessions: Option<&StickySessionsConfig>,
+    ) -> OcyResult<Option<job::Payload>> {
+        debug!("Client requested job from queue={} with routing_keys={:?}", queue_name, routing_keys);
+        if let Some(routing_key) = routing_keys.iter().find(|key| !RedisQueue::is_valid_routing_key(key)) {
+            return Err(OcyError::bad_request(format!("Invalid routing key: {}", routing_key)));
+        }
         // queue can be deleted between these two calls, but will just return no job, so harmless
         let queue = RedisQueue::from_string(queue_name)?
             .ensure_exists(conn)
             .await?;
-        let job = match conn
-            .rpoplpush::<_, Option<u64>>(queue.jobs_key(), keys::LIMBO_KEY)
-            .await?
-        {
-            Some(job_id) => RedisJob::new(job_id),
+        if let Some(paused_until) = queue.paused_until(conn).await? {
+            debug!("[{}] paused until {}, not taking next job", queue.key, paused_until);
+            return Ok(None);
+        }
+        let taken = Self::take_next_job(conn, &queue, routing_keys, worker_id, sticky_sessions).await?;
+        let (job, source_key) = match taken {
+            Some((job_id, source_key)) => (RedisJob::new(job_id), source_key),
             None => return Ok(None),
         };
         debug!(
             "[{}{}] moved from {} -> {}",
             keys::JOB_PREFIX,
             job.id(),
-            queue.jobs_key(),
+            source_key,
             keys::LIMBO_KEY
         );
 
         // if Redis goes down before the following, job will be left in limbo, requeued at startup
         let job_payload: job::Payload = transaction_async!(conn, &[&job.key], {
-            let input: Option<String> = conn.hget(&job.key, job::Field::Input).await?;
+            let (input, created_at, queued_at): (Option<String>, Option<DateTime>, Option<DateTime>) =
+                conn.hget(&job.key, &[job::Field::Input, job::Field::CreatedAt, job::Field::QueuedAt]).await?;
+            let input = input.map(crypto::open).transpose()?;
             let payload =
                 job::Payload::new(job.id(), input.map(|s| serde_json::from_str(&s).unwrap()));
 
-            let result: Option<()> = redis::pipe()
+            let now = clock::now();
+            let mut pipe = redis::pipe();
+            let pipe_ref = pipe
                 .atomic()
                 .hset(&job.key, job::Field::Status, job::Status::Running)
-                .hset(&job.key, job::Field::StartedAt, DateTime::now())
+                .hset(&job.key, job::Field::StartedAt, &now)
+                .hdel(&job.key, job::Field::Progress)
                 .lrem(keys::LIMBO_KEY, 1, job.id())
-                .rpush(keys::RUNNING_KEY, job.id())
-                .query_async(conn)
-                .await?;
+                .rpush(keys::RUNNING_KEY, job.id());
+            if let Some(created_at) = created_at {
+                let wait = now.seconds_since(&created_at).max(0) as u64;
+                let queued_at = queued_at.unwrap_or(created_at);
+                let enqueue_to_start_ms = Some(now.millis_since(&queued_at).max(0) as u64);
+                let start = queue::Start { started_at: now.timestamp(), wait, enqueue_to_start_ms };
+                queue.record_start_in_pipe(pipe_ref, start);
+            }
+            let result: Option<()> = pipe.query_async(conn).await?;
             result.map(|_| payload)
         });
 
@@ -583,17 +1865,246 @@ impl RedisManager {
         Ok(Some(job_payload))
     }
 
-    /// Create a new job on given queue.
+    /// Move the next job a worker can take from given queue to limbo, trying jobs with a session key, then jobs with
+    /// each of the given routing keys in order, then jobs with a serialization key, and finally jobs without any key.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the job taken and the key of the list it was taken from, or `None` if there's no job to take.
+    async fn take_next_job<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue: &RedisQueue,
+        routing_keys: &[String],
+        worker_id: Option<&str>,
+        sticky_sessions: Option<&StickySessionsConfig>,
+    ) -> OcyResult<Option<(u64, String)>> {
+        if let Some(sticky_sessions) = sticky_sessions {
+            if let Some((job_id, session_key)) = queue.take_session_job(conn, worker_id, sticky_sessions).await? {
+                return Ok(Some((job_id, queue.session_jobs_key(&session_key))));
+            }
+        }
+        for routing_key in routing_keys {
+            let source_key = queue.routed_jobs_key(Some(routing_key));
+            if let Some(job_id) = conn.rpoplpush::<_, Option<u64>>(source_key.as_str(), keys::LIMBO_KEY).await? {
+                return Ok(Some((job_id, source_key)));
+            }
+        }
+        if let Some((job_id, serialization_key)) = queue.take_serialized_job(conn).await? {
+            return Ok(Some((job_id, queue.serialized_jobs_key(&serialization_key))));
+        }
+        let job_id: Option<u64> = conn.rpoplpush(queue.jobs_key(), keys::LIMBO_KEY).await?;
+        Ok(job_id.map(|job_id| (job_id, queue.jobs_key().to_owned())))
+    }
+
+    /// Get the existing job on given queue that should be returned instead of creating a new job from given request,
+    /// if any.
+    ///
+    /// This is the latest job created with the request's `unique_key`, if it hasn't ended yet, or if it completed
+    /// within the request's `reuse_completed_within`.
+    pub async fn unique_job<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        job_req: &job::CreateRequest,
+    ) -> OcyResult<Option<job::JobMeta>> {
+        let unique_key = match job_req.unique_key {
+            Some(ref unique_key) => unique_key,
+            None => return Ok(None),
+        };
+        let queue = RedisQueue::from_string(queue_name)?
+            .ensure_exists(conn)
+            .await?;
+        Self::reusable_job(conn, &queue, unique_key, job_req).await
+    }
+
+    /// Get the latest job on given queue with given unique key, if it can be returned instead of creating a new job
+    /// from given request.
+    async fn reusable_job<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue: &RedisQueue,
+        unique_key: &str,
+        job_req: &job::CreateRequest,
+    ) -> OcyResult<Option<job::JobMeta>> {
+        let job_id: Option<u64> = conn.hget(queue.unique_jobs_key(), unique_key).await?;
+        let job = match job_id {
+            Some(job_id) => match Self::job_fields(conn, job_id, None).await {
+                Ok(job) => job,
+                Err(OcyError::NoSuchJob(_)) => return Ok(None), // expired or deleted
+                Err(err) => return Err(err),
+            },
+            None => return Ok(None),
+        };
+
+        let reusable = match job.status() {
+            job::Status::Completed => match (&job_req.reuse_completed_within, job.ended_at()) {
+                (Some(window), Some(ended_at)) => {
+                    clock::now().seconds_since(&ended_at).max(0) as u64 <= window.as_secs()
+                }
+                _ => false,
+            },
+            _ => !job.ended(),
+        };
+        if reusable {
+            debug!("[{}] found job {} with unique key {}", queue.key, job.id(), unique_key);
+            Ok(Some(job))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Get the ID and output of the latest job on given queue that completed with given unique key, if it completed
+    /// within the queue's `result_ttl` and hasn't expired.
+    pub async fn job_result<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        unique_key: &str,
+    ) -> OcyResult<Option<(u64, serde_json::Value)>> {
+        let queue = RedisQueue::from_string(queue_name)?
+            .ensure_exists(conn)
+            .await?;
+        let job_id: Option<u64> = conn.hget(queue.results_key(), unique_key).await?;
+        let job = match job_id {
+            Some(job_id) => RedisJob::new(job_id),
+            None => return Ok(None),
+        };
+
+        let (status, ended_at): (Option<job::Status>, Option<DateTime>) =
+            conn.hget(job.key(), &[job::Field::Status, job::Field::EndedAt]).await?;
+        let result_ttl = queue.result_ttl(conn).await?;
+        let fresh = match (status, ended_at) {
+            // jobs retried since completing no longer have a result
+            (Some(job::Status::Completed), Some(ended_at)) => {
+                clock::now().seconds_since(&ended_at).max(0) as u64 <= result_ttl.as_secs()
+            }
+            _ => false,
+        };
+        if !fresh {
+            return Ok(None);
+        }
+
+        match job.output(conn).await {
+            Ok(output) => Ok(Some((job.id(), output))),
+            Err(OcyError::NoSuchJob(_)) => Ok(None), // expired since
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Create a new job on given queue, or get the existing job with the same `unique_key` that should be returned
+    /// instead, see `unique_job`.
     pub async fn create_job<C: ConnectionLike + Send>(
         conn: &mut C,
         queue_name: &str,
         job_req: &job::CreateRequest,
+    ) -> OcyResult<job::Created> {
+        Self::insert_job(conn, queue_name, job_req, None, None).await
+    }
+
+    /// Create a new job on given queue, unless its input would exceed the storage the queue's namespace can use on
+    /// this shard, which is checked and updated atomically, so concurrently created jobs can't exceed it together.
+    pub async fn create_job_within_limit<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        job_req: &job::CreateRequest,
+        storage_limit: Option<&quota::StorageLimit>,
+    ) -> OcyResult<job::Created> {
+        Self::insert_job(conn, queue_name, job_req, None, storage_limit).await
+    }
+
+    /// Create a shadow copy of job with ID `shadow_of` on given queue, from the request used to create that job.
+    pub async fn create_shadow_job<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        job_req: &job::CreateRequest,
+        shadow_of: u64,
     ) -> OcyResult<u64> {
+        Ok(Self::insert_job(conn, queue_name, job_req, Some(shadow_of), None).await?.id())
+    }
+
+    /// Get the name of the queue a new job on given queue should be mirrored to, if any.
+    ///
+    /// Each job is mirrored with a chance of the queue's `shadow_percent`.
+    pub async fn shadow_target<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+    ) -> OcyResult<Option<String>> {
+        let settings = Self::queue_settings(conn, queue_name).await?;
+        let shadow_percent = settings.shadow_percent;
+        Ok(settings
+            .shadow_to
+            .filter(|_| rand::thread_rng().gen_range(0, 100) < shadow_percent))
+    }
+
+    /// Create a new job on given queue, or get the existing job with the same `unique_key` that should be returned
+    /// instead.
+    ///
+    /// The unique key is claimed in the same transaction the job is created in, while watching the queue's unique
+    /// jobs, so that concurrent requests with the same key can't both create a job. If another job claims the key
+    /// first, the transaction is retried, returning that job instead.
+    async fn insert_job<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue_name: &str,
+        job_req: &job::CreateRequest,
+        shadow_of: Option<u64>,
+        storage_limit: Option<&quota::StorageLimit>,
+    ) -> OcyResult<job::Created> {
         // TODO: use transaction to ensure that queue isn't deleted partway through job creation
         let queue = RedisQueue::from_string(queue_name)?
             .ensure_exists(conn)
             .await?;
         let queue_settings = queue.settings(conn).await?;
+        if let Some(ref input) = job_req.input {
+            queue_settings.check_input_size(input)?;
+        }
+        if job_req.callback_url.as_deref().is_some_and(|url| !RedisQueue::is_valid_callback_url(url)) {
+            return Err(OcyError::bad_request("Invalid callback URL, must be an absolute http(s) URL"));
+        }
+        let list = job::QueueList {
+            routing_key: job_req.routing_key.clone(),
+            session_key: job_req.session_key.clone(),
+            serialization_key: job_req.serialization_key.clone(),
+        };
+        if list.routing_key.as_deref().is_some_and(|routing_key| !RedisQueue::is_valid_routing_key(routing_key)) {
+            return Err(OcyError::bad_request(limits::get().invalid_name_message("routing key")));
+        }
+        if list.session_key.as_deref().is_some_and(|session_key| !RedisQueue::is_valid_session_key(session_key)) {
+            return Err(OcyError::bad_request(limits::get().invalid_name_message("session key")));
+        }
+        if list.serialization_key.as_deref().is_some_and(|key| !RedisQueue::is_valid_serialization_key(key)) {
+            return Err(OcyError::bad_request(limits::get().invalid_name_message("serialization key")));
+        }
+        let num_keys =
+            [&list.routing_key, &list.session_key, &list.serialization_key].iter().filter(|key| key.is_some()).count();
+        if num_keys > 1 {
+            return Err(OcyError::bad_request("Jobs can only have one of a routing, session, or serialization key"));
+        }
+        let unique_key = job_req.unique_key.as_deref().filter(|_| shadow_of.is_none());
+        loop {
+            if let Some(unique_key) = unique_key {
+                let _: () = redis::cmd("WATCH").arg(queue.unique_jobs_key()).query_async(conn).await?;
+                if let Some(job) = Self::reusable_job(conn, &queue, unique_key, job_req).await? {
+                    let _: () = redis::cmd("UNWATCH").query_async(conn).await?;
+                    return Ok(job::Created::Existing(job));
+                }
+            }
+            let inserted =
+                Self::try_insert_job(conn, &queue, &queue_settings, &list, job_req, shadow_of, storage_limit).await?;
+            if let Some(job_id) = inserted {
+                return Ok(job::Created::New(job_id));
+            }
+            debug!("[{}] unique key {:?} changed while creating job, retrying", &queue.key, unique_key);
+        }
+    }
+
+    /// Create a new job on given queue in a single transaction, returning `None` if it was aborted because a key
+    /// being watched by `insert_job` changed.
+    async fn try_insert_job<C: ConnectionLike + Send>(
+        conn: &mut C,
+        queue: &RedisQueue,
+        queue_settings: &queue::Settings,
+        list: &job::QueueList,
+        job_req: &job::CreateRequest,
+        shadow_of: Option<u64>,
+        storage_limit: Option<&quota::StorageLimit>,
+    ) -> OcyResult<Option<u64>> {
         let timeout = job_req.timeout.as_ref().unwrap_or(&queue_settings.timeout);
         let heartbeat_timeout = job_req
             .heartbeat_timeout
@@ -604,12 +2115,23 @@ impl RedisManager {
             .as_ref()
             .unwrap_or(&queue_settings.expires_after);
         let retries = job_req.retries.unwrap_or(queue_settings.retries);
-        let retry_delays = match job_req.retry_delays.clone() {
-            Some(rd) => rd,
-            None => Vec::new(),
+        let retry_delays = job_req.retry_delays.clone().unwrap_or_default();
+        let quarantine_after = job_req.quarantine_after.unwrap_or(queue_settings.quarantine_after);
+        let quick_fail_window = job_req
+            .quick_fail_window
+            .as_ref()
+            .unwrap_or(&queue_settings.quick_fail_window);
+        let total_timeout = job_req.total_timeout.as_ref().or(queue_settings.total_timeout.as_ref());
+
+        let created_at = clock::now();
+        let deadline = match (&job_req.deadline, &queue_settings.sla) {
+            (Some(deadline), _) => Some(deadline.clone()),
+            (None, Some(sla)) => Some(created_at.plus(sla)),
+            (None, None) => None,
         };
 
-        let job = RedisJob::new(conn.incr(keys::JOB_ID_KEY, 1).await?);
+        let job_id: u64 = conn.incr(keys::JOB_ID_KEY, 1).await?;
+        let job = RedisJob::new(job_id);
         debug!(
             "Creating job with job_id={} on queue={}",
             job.id(),
@@ -622,28 +2144,68 @@ impl RedisManager {
             .hset(&job.key, job::Field::Id, job.id())
             .hset(&job.key, job::Field::Queue, &queue.name)
             .hset(&job.key, job::Field::Status, job::Status::Queued)
-            .hset(&job.key, job::Field::CreatedAt, DateTime::now())
+            .hset(&job.key, job::Field::CreatedAt, &created_at)
+            .hset(&job.key, job::Field::QueuedAt, &created_at)
             .hset(&job.key, job::Field::Timeout, timeout)
             .hset(&job.key, job::Field::HeartbeatTimeout, heartbeat_timeout)
             .hset(&job.key, job::Field::ExpiresAfter, expires_after)
             .hset(&job.key, job::Field::Retries, retries)
             .hset(&job.key, job::Field::RetriesAttempted, 0)
+            .hset(&job.key, job::Field::QuarantineAfter, quarantine_after)
+            .hset(&job.key, job::Field::QuickFailWindow, quick_fail_window)
+            .hset(&queue.key, queue::Field::LastJobAt, clock::now())
             .incr(keys::STAT_JOBS_CREATED_KEY, 1)
-            .lpush(queue.jobs_key(), job.id());
+            .lpush(queue.queue_list_key(list), job.id());
 
-        if let Some(ref input) = job_req.input {
-            pipe.hset(&job.key, job::Field::Input, input.to_string());
+        let input = job_req.input.as_ref().map(|input| crypto::seal(input.to_string()));
+        let input_len = input.as_ref().map_or(0, String::len) as i64;
+        if let Some(input) = input {
+            pipe.hset(&job.key, job::Field::Input, input);
         }
 
         if let Some(ref tags) = job_req.tags {
             let tags_json: serde_json::Value = tags.as_slice().into();
             pipe.hset(&job.key, job::Field::Tags, tags_json.to_string());
             for tag in tags {
-                let key = format!("{}{}", keys::TAG_PREFIX, tag);
-                pipe.sadd(key, job.id());
+                pipe.sadd(RedisTag::build_key(tag), job.id());
             }
         }
 
+        if let Some(total_timeout) = total_timeout {
+            pipe.hset(&job.key, job::Field::TotalTimeout, total_timeout);
+        }
+
+        if let Some(ref routing_key) = list.routing_key {
+            pipe.hset(&job.key, job::Field::RoutingKey, routing_key);
+        }
+        if let Some(ref session_key) = list.session_key {
+            pipe.hset(&job.key, job::Field::SessionKey, session_key);
+        }
+        if let Some(ref serialization_key) = list.serialization_key {
+            pipe.hset(&job.key, job::Field::SerializationKey, serialization_key);
+        }
+        queue.add_queue_list_in_pipe(pipe, list);
+
+        if let Some(ref deadline) = deadline {
+            pipe.hset(&job.key, job::Field::Deadline, deadline)
+                .zadd(keys::SLA_DEADLINES_KEY, job.id(), deadline.timestamp());
+        }
+
+        if let (None, Some(unique_key)) = (shadow_of, &job_req.unique_key) {
+            pipe.hset(&job.key, job::Field::UniqueKey, unique_key)
+                .hset(queue.unique_jobs_key(), unique_key, job.id());
+        }
+
+        match (shadow_of, &job_req.callback_url) {
+            (Some(shadow_of), _) => {
+                pipe.hset(&job.key, job::Field::ShadowOf, shadow_of);
+            }
+            (None, Some(callback_url)) => {
+                pipe.hset(&job.key, job::Field::CallbackUrl, callback_url);
+            }
+            (None, None) => (),
+        }
+
         if !retry_delays.is_empty() {
             let retry_delays_json: serde_json::Value = retry_delays.as_slice().into();
             pipe.hset(
@@ -653,9 +2215,39 @@ impl RedisManager {
             );
         }
 
-        pipe.query_async(conn).await?;
+        let reserved = match storage_limit {
+            Some(limit) if input_len > 0 => {
+                let reserved: bool = redis::Script::new(RESERVE_STORAGE_SCRIPT)
+                    .key(keys::NAMESPACE_STORAGE_KEY)
+                    .arg(&limit.namespace)
+                    .arg(input_len)
+                    .arg(limit.max_bytes)
+                    .invoke_async(conn)
+                    .await?;
+                if !reserved {
+                    if shadow_of.is_none() && job_req.unique_key.is_some() {
+                        let _: () = redis::cmd("UNWATCH").query_async(conn).await?;
+                    }
+                    return Err(limit.exceeded());
+                }
+                Some(&limit.namespace)
+            }
+            _ => {
+                record_storage_in_pipe(pipe, &queue.name, input_len);
+                None
+            }
+        };
+
+        // result is nil if the transaction was aborted
+        let res: RedisResult<Option<()>> = pipe.query_async(conn).await;
+        if let (Err(_) | Ok(None), Some(namespace)) = (&res, reserved) {
+            let _: RedisResult<()> = conn.hincr(keys::NAMESPACE_STORAGE_KEY, namespace, -input_len).await;
+        }
+        if res?.is_none() {
+            return Ok(None);
+        }
 
         info!("[{}] [{}] created", &queue.key, &job.key);
-        Ok(job.id())
+        Ok(Some(job.id()))
     }
 }
\ No newline at end of file
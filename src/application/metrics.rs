@@ -0,0 +1,698 @@
+//! Metrics describing the health of background tasks and the load on Redis, exposed in Prometheus text format, or
+//! exported to other systems (see `export`).
+//!
+//! Metrics are process wide, and monitors on every Redis shard record to the same metrics, so e.g. the last success
+//! of a monitor is the most recent success on any shard.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Content type of metrics in Prometheus text exposition format.
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Metrics recorded by this server.
+pub static METRICS: Metrics = Metrics::new();
+
+/// Background tasks that periodically check or process jobs.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Monitor {
+    /// Checks running jobs for timeouts and SLA breaches.
+    Timeout,
+
+    /// Checks failed jobs for retries and quarantining.
+    Retry,
+
+    /// Checks ended jobs for expiry, and purges deleted jobs.
+    Expiry,
+
+    /// Pushes queued jobs to callback URLs.
+    Push,
+
+    /// Replays job creation requests accepted while Redis was unavailable.
+    Replay,
+
+    /// Removes jobs that no longer exist from tags.
+    TagPrune,
+}
+
+/// All monitors, in the order their metrics are output.
+const ALL_MONITORS: [Monitor; 6] = [
+    Monitor::Timeout,
+    Monitor::Retry,
+    Monitor::Expiry,
+    Monitor::Push,
+    Monitor::Replay,
+    Monitor::TagPrune,
+];
+
+impl Monitor {
+    /// Get the name of this monitor, as used in metric labels.
+    pub fn label(self) -> &'static str {
+        match self {
+            Monitor::Timeout => "timeout",
+            Monitor::Retry => "retry",
+            Monitor::Expiry => "expiry",
+            Monitor::Push => "push",
+            Monitor::Replay => "replay",
+            Monitor::TagPrune => "tag_prune",
+        }
+    }
+}
+
+/// Logical operations Redis commands are sent for, so that Redis load can be attributed to API traffic.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum RedisOperation {
+    /// Creating jobs.
+    Create,
+
+    /// Taking jobs from queues.
+    Dequeue,
+
+    /// Sending heartbeats for running jobs.
+    Heartbeat,
+
+    /// Anything else, including background monitors.
+    Other,
+}
+
+/// All Redis operations, in the order their metrics are output.
+const ALL_REDIS_OPERATIONS: [RedisOperation; 4] = [
+    RedisOperation::Create,
+    RedisOperation::Dequeue,
+    RedisOperation::Heartbeat,
+    RedisOperation::Other,
+];
+
+impl RedisOperation {
+    /// Get the name of this operation, as used in metric labels.
+    pub fn label(self) -> &'static str {
+        match self {
+            RedisOperation::Create => "create",
+            RedisOperation::Dequeue => "dequeue",
+            RedisOperation::Heartbeat => "heartbeat",
+            RedisOperation::Other => "other",
+        }
+    }
+}
+
+/// Upper bounds of the Redis command latency histogram's buckets, in microseconds.
+const REDIS_LATENCY_BUCKETS: [u64; 11] = [
+    500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000,
+];
+
+/// Whether a metric only ever increases, may go up and down, or is part of a histogram.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    /// Total that only increases, unless the server is restarted.
+    Counter,
+
+    /// Current value, which may go up or down.
+    Gauge,
+
+    /// Bucket, sum, or count of a histogram, all of which only increase, unless the server is restarted.
+    Histogram,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        }
+    }
+}
+
+/// Names and values of the labels of a metric.
+pub type Labels = Vec<(&'static str, String)>;
+
+/// Current value of a single metric, for a single set of labels, e.g. a single monitor if it's a monitor metric.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// Name of the metric in Prometheus format, including the `_bucket`, `_sum`, or `_count` suffix of histograms.
+    pub name: &'static str,
+
+    /// Description of the metric.
+    pub help: &'static str,
+
+    /// Whether the metric is a counter, gauge, or histogram.
+    pub kind: Kind,
+
+    /// Names and values of the labels the value is for, e.g. the monitor if it's a monitor metric.
+    pub labels: Labels,
+
+    /// Current value of the metric.
+    pub value: String,
+}
+
+impl Sample {
+    /// Get the name of the metric this sample belongs to, which for histograms excludes the suffix of the sample.
+    pub fn family(&self) -> &'static str {
+        match self.kind {
+            Kind::Histogram => ["_bucket", "_sum", "_count"]
+                .iter()
+                .find_map(|suffix| self.name.strip_suffix(suffix))
+                .unwrap_or(self.name),
+            _ => self.name,
+        }
+    }
+}
+
+/// Metrics recorded for each pass of a single monitor.
+#[derive(Debug)]
+struct MonitorMetrics {
+    passes: AtomicU64,
+    failures: AtomicU64,
+    duration_micros: AtomicU64,
+    last_duration_micros: AtomicU64,
+    jobs_transitioned: AtomicU64,
+    last_jobs_transitioned: AtomicU64,
+    last_success: AtomicU64,
+    last_loop: AtomicU64,
+}
+
+impl MonitorMetrics {
+    const fn new() -> Self {
+        Self {
+            passes: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            duration_micros: AtomicU64::new(0),
+            last_duration_micros: AtomicU64::new(0),
+            jobs_transitioned: AtomicU64::new(0),
+            last_jobs_transitioned: AtomicU64::new(0),
+            last_success: AtomicU64::new(0),
+            last_loop: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Metrics for job creation requests written to disk, so they can be replayed if Redis is unavailable.
+#[derive(Debug)]
+struct FileMetrics {
+    writes: AtomicU64,
+    write_failures: AtomicU64,
+    replayed: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// Metrics for jobs removed by Redis itself rather than by Ocypod, detected from keyspace notifications.
+#[derive(Debug)]
+struct LostJobMetrics {
+    expired: AtomicU64,
+    evicted: AtomicU64,
+}
+
+/// Latency histogram of the Redis commands sent for a single operation.
+#[derive(Debug)]
+struct RedisOperationMetrics {
+    // counts of commands in each bucket alone, rather than cumulative counts
+    buckets: [AtomicU64; REDIS_LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    duration_micros: AtomicU64,
+}
+
+impl RedisOperationMetrics {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; REDIS_LATENCY_BUCKETS.len()],
+            count: AtomicU64::new(0),
+            duration_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Counters and gauges describing background tasks and Redis commands.
+#[derive(Debug)]
+pub struct Metrics {
+    file: FileMetrics,
+    lost_jobs: LostJobMetrics,
+    evicting_shards: AtomicU64,
+    heartbeat_tolerance: AtomicU64,
+    hooks_dropped: AtomicU64,
+    monitors: [MonitorMetrics; 6],
+    redis_operations: [RedisOperationMetrics; 4],
+    redis_commands: Mutex<BTreeMap<(RedisOperation, String), u64>>,
+}
+
+impl Metrics {
+    /// Create a new set of metrics, with nothing recorded yet.
+    pub const fn new() -> Self {
+        Self {
+            file: FileMetrics {
+                writes: AtomicU64::new(0),
+                write_failures: AtomicU64::new(0),
+                replayed: AtomicU64::new(0),
+                rejected: AtomicU64::new(0),
+            },
+            lost_jobs: LostJobMetrics {
+                expired: AtomicU64::new(0),
+                evicted: AtomicU64::new(0),
+            },
+            evicting_shards: AtomicU64::new(0),
+            heartbeat_tolerance: AtomicU64::new(1),
+            hooks_dropped: AtomicU64::new(0),
+            monitors: [
+                MonitorMetrics::new(),
+                MonitorMetrics::new(),
+                MonitorMetrics::new(),
+                MonitorMetrics::new(),
+                MonitorMetrics::new(),
+                MonitorMetrics::new(),
+            ],
+            redis_operations: [
+                RedisOperationMetrics::new(),
+                RedisOperationMetrics::new(),
+                RedisOperationMetrics::new(),
+                RedisOperationMetrics::new(),
+            ],
+            redis_commands: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Record an attempt to write a job creation request to disk.
+    pub fn record_file_write(&self, success: bool) {
+        let counter = if success { &self.file.writes } else { &self.file.write_failures };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a job creation request on disk being replayed, or rejected by Redis.
+    pub fn record_file_replay(&self, success: bool) {
+        let counter = if success { &self.file.replayed } else { &self.file.rejected };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a job being removed by Redis itself, either by being evicted, or by expiring.
+    pub fn record_lost_job(&self, evicted: bool) {
+        let counter = if evicted { &self.lost_jobs.evicted } else { &self.lost_jobs.expired };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the number of Redis shards whose `maxmemory-policy` may evict keys, as of their latest check.
+    pub fn record_evicting_shards(&self, shards: usize) {
+        self.evicting_shards.store(shards as u64, Ordering::Relaxed);
+    }
+
+    /// Record the factor heartbeat timeouts are currently multiplied by, due to this server being overloaded.
+    pub fn record_heartbeat_tolerance(&self, factor: u32) {
+        self.heartbeat_tolerance.store(factor.into(), Ordering::Relaxed);
+    }
+
+    /// Record a hook command not being run, due to too many hook commands already running.
+    pub fn record_hook_dropped(&self) {
+        self.hooks_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a Redis command with given name, or a pipeline of commands, sent for given operation and taking
+    /// `duration` to complete, whether or not it succeeded.
+    pub fn record_redis_command(&self, operation: RedisOperation, command: &str, duration: Duration) {
+        let micros = duration.as_micros() as u64;
+        let metrics = &self.redis_operations[operation as usize];
+        if let Some(bucket) = REDIS_LATENCY_BUCKETS.iter().position(|bound| micros <= *bound) {
+            metrics.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        metrics.count.fetch_add(1, Ordering::Relaxed);
+        metrics.duration_micros.fetch_add(micros, Ordering::Relaxed);
+
+        *self.redis_commands.lock().unwrap().entry((operation, command.to_owned())).or_default() += 1;
+    }
+
+    /// Record a single pass of a monitor, which took `duration`, and moved given number of jobs to a new status (or
+    /// otherwise processed them, e.g. pushed or replayed them).
+    ///
+    /// A pass that partly failed should still record any jobs it transitioned.
+    pub fn record_monitor_pass(&self, monitor: Monitor, duration: Duration, jobs_transitioned: usize, success: bool) {
+        let metrics = &self.monitors[monitor as usize];
+        metrics.passes.fetch_add(1, Ordering::Relaxed);
+        metrics.duration_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        metrics.last_duration_micros.store(duration.as_micros() as u64, Ordering::Relaxed);
+        metrics.jobs_transitioned.fetch_add(jobs_transitioned as u64, Ordering::Relaxed);
+        metrics.last_jobs_transitioned.store(jobs_transitioned as u64, Ordering::Relaxed);
+        if success {
+            metrics.last_success.fetch_max(unix_now(), Ordering::Relaxed);
+        } else {
+            metrics.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record that a monitor's loop is still running, at the start of each of its iterations, whether or not it
+    /// runs a pass.
+    pub fn record_monitor_loop(&self, monitor: Monitor) {
+        self.monitors[monitor as usize].last_loop.fetch_max(unix_now(), Ordering::Relaxed);
+    }
+
+    /// Get the monitors whose loops haven't started an iteration within their given maximum gap, along with the
+    /// number of seconds since they last did, as of Unix time `now`. Monitors whose loops haven't started yet are
+    /// ignored.
+    pub fn stalled_monitors(&self, max_gaps: &[(Monitor, Duration)], now: u64) -> Vec<(Monitor, u64)> {
+        max_gaps
+            .iter()
+            .filter_map(|(monitor, max_gap)| {
+                let last_loop = self.monitors[*monitor as usize].last_loop.load(Ordering::Relaxed);
+                let since = now.saturating_sub(last_loop);
+                if last_loop > 0 && since > max_gap.as_secs() {
+                    Some((*monitor, since))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Get all metrics in Prometheus text exposition format, along with the number of job creation requests waiting
+    /// on disk to be replayed, if known.
+    pub fn render(&self, pending_files: Option<usize>) -> String {
+        let mut out = String::new();
+        let mut previous = None;
+        for sample in self.samples(pending_files) {
+            let family = sample.family();
+            if previous != Some(family) {
+                writeln!(out, "# HELP {} {}", family, sample.help).unwrap();
+                writeln!(out, "# TYPE {} {}", family, sample.kind.as_str()).unwrap();
+                previous = Some(family);
+            }
+            if sample.labels.is_empty() {
+                writeln!(out, "{} {}", sample.name, sample.value).unwrap();
+            } else {
+                let labels: Vec<String> =
+                    sample.labels.iter().map(|(name, value)| format!("{}=\"{}\"", name, value)).collect();
+                writeln!(out, "{}{{{}}} {}", sample.name, labels.join(","), sample.value).unwrap();
+            }
+        }
+        out
+    }
+
+    /// Get the current values of all metrics, along with the number of job creation requests waiting on disk to be
+    /// replayed, if known. Samples of the same metric are adjacent.
+    pub fn samples(&self, pending_files: Option<usize>) -> Vec<Sample> {
+        let mut samples = Vec::new();
+        let file = &self.file;
+        counter(&mut samples, "ocypod_file_writes_total", "Job creation requests written to disk.", &file.writes);
+        counter(
+            &mut samples,
+            "ocypod_file_write_failures_total",
+            "Job creation requests that couldn't be written to disk.",
+            &file.write_failures,
+        );
+        counter(
+            &mut samples,
+            "ocypod_file_replayed_total",
+            "Job creation requests on disk replayed to Redis.",
+            &file.replayed,
+        );
+        counter(
+            &mut samples,
+            "ocypod_file_rejected_total",
+            "Job creation requests on disk rejected by Redis when replayed.",
+            &file.rejected,
+        );
+        if let Some(pending) = pending_files {
+            samples.push(Sample {
+                name: "ocypod_file_pending",
+                help: "Job creation requests on disk waiting to be replayed.",
+                kind: Kind::Gauge,
+                labels: Vec::new(),
+                value: pending.to_string(),
+            });
+        }
+
+        counter(
+            &mut samples,
+            "ocypod_jobs_expired_total",
+            "Jobs expired by Redis rather than removed by Ocypod.",
+            &self.lost_jobs.expired,
+        );
+        counter(
+            &mut samples,
+            "ocypod_jobs_evicted_total",
+            "Jobs evicted by Redis due to its maxmemory policy.",
+            &self.lost_jobs.evicted,
+        );
+        samples.push(Sample {
+            name: "ocypod_redis_evicting_shards",
+            help: "Redis shards whose maxmemory policy may evict jobs.",
+            kind: Kind::Gauge,
+            labels: Vec::new(),
+            value: self.evicting_shards.load(Ordering::Relaxed).to_string(),
+        });
+        samples.push(Sample {
+            name: "ocypod_heartbeat_tolerance_factor",
+            help: "Factor heartbeat timeouts are multiplied by while this server is overloaded.",
+            kind: Kind::Gauge,
+            labels: Vec::new(),
+            value: self.heartbeat_tolerance.load(Ordering::Relaxed).to_string(),
+        });
+        counter(
+            &mut samples,
+            "ocypod_hooks_dropped_total",
+            "Hook commands not run due to too many hook commands already running.",
+            &self.hooks_dropped,
+        );
+
+        self.monitor_metric(&mut samples, "ocypod_monitor_passes_total", "Monitor passes run.", Kind::Counter, |m| {
+            m.passes.load(Ordering::Relaxed).to_string()
+        });
+        self.monitor_metric(
+            &mut samples,
+            "ocypod_monitor_failures_total",
+            "Monitor passes that failed.",
+            Kind::Counter,
+            |m| m.failures.load(Ordering::Relaxed).to_string(),
+        );
+        self.monitor_metric(
+            &mut samples,
+            "ocypod_monitor_pass_duration_seconds_total",
+            "Total time spent running monitor passes.",
+            Kind::Counter,
+            |m| format!("{:.6}", m.duration_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0),
+        );
+        self.monitor_metric(
+            &mut samples,
+            "ocypod_monitor_last_pass_duration_seconds",
+            "Time taken by the most recent monitor pass.",
+            Kind::Gauge,
+            |m| format!("{:.6}", m.last_duration_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0),
+        );
+        self.monitor_metric(
+            &mut samples,
+            "ocypod_monitor_jobs_transitioned_total",
+            "Jobs transitioned by monitor passes.",
+            Kind::Counter,
+            |m| m.jobs_transitioned.load(Ordering::Relaxed).to_string(),
+        );
+        self.monitor_metric(
+            &mut samples,
+            "ocypod_monitor_last_jobs_transitioned",
+            "Jobs transitioned by the most recent monitor pass.",
+            Kind::Gauge,
+            |m| m.last_jobs_transitioned.load(Ordering::Relaxed).to_string(),
+        );
+        self.monitor_metric(
+            &mut samples,
+            "ocypod_monitor_last_success_timestamp_seconds",
+            "Unix time of the most recent successful monitor pass, 0 if none have succeeded.",
+            Kind::Gauge,
+            |m| m.last_success.load(Ordering::Relaxed).to_string(),
+        );
+        self.monitor_metric(
+            &mut samples,
+            "ocypod_monitor_last_loop_timestamp_seconds",
+            "Unix time the monitor's loop last started an iteration, 0 if it hasn't started.",
+            Kind::Gauge,
+            |m| m.last_loop.load(Ordering::Relaxed).to_string(),
+        );
+
+        self.redis_metrics(&mut samples);
+        samples
+    }
+
+    /// Add samples of the Redis command counts and latency histograms to given samples.
+    fn redis_metrics(&self, samples: &mut Vec<Sample>) {
+        for ((operation, command), count) in self.redis_commands.lock().unwrap().iter() {
+            samples.push(Sample {
+                name: "ocypod_redis_commands_total",
+                help: "Redis commands and pipelines sent, by operation and command.",
+                kind: Kind::Counter,
+                labels: vec![("operation", operation.label().to_owned()), ("command", command.to_owned())],
+                value: count.to_string(),
+            });
+        }
+
+        let help = "Time taken by Redis commands and pipelines, by operation.";
+        for operation in &ALL_REDIS_OPERATIONS {
+            let metrics = &self.redis_operations[*operation as usize];
+            let mut cumulative = 0;
+            for (bound, bucket) in REDIS_LATENCY_BUCKETS.iter().zip(&metrics.buckets) {
+                cumulative += bucket.load(Ordering::Relaxed);
+                samples.push(redis_latency_sample(*operation, help, Some(*bound), cumulative));
+            }
+            let count = metrics.count.load(Ordering::Relaxed);
+            samples.push(redis_latency_sample(*operation, help, None, count));
+        }
+        for operation in &ALL_REDIS_OPERATIONS {
+            let micros = self.redis_operations[*operation as usize].duration_micros.load(Ordering::Relaxed);
+            samples.push(Sample {
+                name: "ocypod_redis_command_duration_seconds_sum",
+                help,
+                kind: Kind::Histogram,
+                labels: vec![("operation", operation.label().to_owned())],
+                value: format!("{:.6}", micros as f64 / 1_000_000.0),
+            });
+        }
+        for operation in &ALL_REDIS_OPERATIONS {
+            samples.push(Sample {
+                name: "ocypod_redis_command_duration_seconds_count",
+                help,
+                kind: Kind::Histogram,
+                labels: vec![("operation", operation.label().to_owned())],
+                value: self.redis_operations[*operation as usize].count.load(Ordering::Relaxed).to_string(),
+            });
+        }
+    }
+
+    fn monitor_metric<F>(&self, samples: &mut Vec<Sample>, name: &'static str, help: &'static str, kind: Kind, value: F)
+    where
+        F: Fn(&MonitorMetrics) -> String,
+    {
+        for monitor in &ALL_MONITORS {
+            samples.push(Sample {
+                name,
+                help,
+                kind,
+                labels: vec![("monitor", monitor.label().to_owned())],
+                value: value(&self.monitors[*monitor as usize]),
+            });
+        }
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Get the current Unix time in seconds.
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Get a bucket of the Redis command latency histogram for given operation, with given upper bound in microseconds,
+/// or no bound for the bucket containing every command.
+fn redis_latency_sample(operation: RedisOperation, help: &'static str, bound: Option<u64>, count: u64) -> Sample {
+    let le = match bound {
+        Some(micros) => (micros as f64 / 1_000_000.0).to_string(),
+        None => "+Inf".to_owned(),
+    };
+    Sample {
+        name: "ocypod_redis_command_duration_seconds_bucket",
+        help,
+        kind: Kind::Histogram,
+        labels: vec![("operation", operation.label().to_owned()), ("le", le)],
+        value: count.to_string(),
+    }
+}
+
+fn counter(samples: &mut Vec<Sample>, name: &'static str, help: &'static str, value: &AtomicU64) {
+    samples.push(Sample {
+        name,
+        help,
+        kind: Kind::Counter,
+        labels: Vec::new(),
+        value: value.load(Ordering::Relaxed).to_string(),
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::new();
+        metrics.record_file_write(true);
+        metrics.record_file_write(true);
+        metrics.record_file_write(false);
+        metrics.record_file_replay(true);
+        metrics.record_lost_job(true);
+        metrics.record_evicting_shards(2);
+        metrics.record_heartbeat_tolerance(4);
+        metrics.record_hook_dropped();
+        metrics.record_monitor_pass(Monitor::Retry, Duration::from_millis(1500), 3, true);
+        metrics.record_monitor_pass(Monitor::Retry, Duration::from_millis(500), 0, false);
+
+        let out = metrics.render(Some(4));
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines.contains(&"# TYPE ocypod_file_writes_total counter"));
+        assert!(lines.contains(&"ocypod_file_writes_total 2"));
+        assert!(lines.contains(&"ocypod_file_write_failures_total 1"));
+        assert!(lines.contains(&"ocypod_file_replayed_total 1"));
+        assert!(lines.contains(&"ocypod_file_rejected_total 0"));
+        assert!(lines.contains(&"ocypod_file_pending 4"));
+        assert!(lines.contains(&"ocypod_jobs_expired_total 0"));
+        assert!(lines.contains(&"ocypod_jobs_evicted_total 1"));
+        assert!(lines.contains(&"ocypod_redis_evicting_shards 2"));
+        assert!(lines.contains(&"ocypod_heartbeat_tolerance_factor 4"));
+        assert!(lines.contains(&"ocypod_hooks_dropped_total 1"));
+        assert!(lines.contains(&"ocypod_monitor_passes_total{monitor=\"retry\"} 2"));
+        assert!(lines.contains(&"ocypod_monitor_passes_total{monitor=\"timeout\"} 0"));
+        assert!(lines.contains(&"ocypod_monitor_failures_total{monitor=\"retry\"} 1"));
+        assert!(lines.contains(&"ocypod_monitor_pass_duration_seconds_total{monitor=\"retry\"} 2.000000"));
+        assert!(lines.contains(&"ocypod_monitor_last_pass_duration_seconds{monitor=\"retry\"} 0.500000"));
+        assert!(lines.contains(&"ocypod_monitor_jobs_transitioned_total{monitor=\"retry\"} 3"));
+        assert!(lines.contains(&"ocypod_monitor_last_jobs_transitioned{monitor=\"retry\"} 0"));
+        assert!(lines.contains(&"ocypod_monitor_last_success_timestamp_seconds{monitor=\"expiry\"} 0"));
+        assert!(!lines.contains(&"ocypod_monitor_last_success_timestamp_seconds{monitor=\"retry\"} 0"));
+
+        assert!(!metrics.render(None).contains("ocypod_file_pending"));
+    }
+
+    #[test]
+    fn redis_commands() {
+        let metrics = Metrics::new();
+        metrics.record_redis_command(RedisOperation::Dequeue, "EVALSHA", Duration::from_micros(700));
+        metrics.record_redis_command(RedisOperation::Dequeue, "EVALSHA", Duration::from_millis(30));
+        metrics.record_redis_command(RedisOperation::Dequeue, "HGET", Duration::from_secs(2));
+        metrics.record_redis_command(RedisOperation::Create, "PIPELINE", Duration::from_micros(100));
+
+        let out = metrics.render(None);
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines.contains(&"# TYPE ocypod_redis_commands_total counter"));
+        assert!(lines.contains(&"ocypod_redis_commands_total{operation=\"create\",command=\"PIPELINE\"} 1"));
+        assert!(lines.contains(&"ocypod_redis_commands_total{operation=\"dequeue\",command=\"EVALSHA\"} 2"));
+        assert!(lines.contains(&"ocypod_redis_commands_total{operation=\"dequeue\",command=\"HGET\"} 1"));
+        assert!(!out.contains("ocypod_redis_commands_total{operation=\"heartbeat\""));
+
+        assert!(lines.contains(&"# TYPE ocypod_redis_command_duration_seconds histogram"));
+        assert_eq!(out.matches("# TYPE ocypod_redis_command_duration_seconds ").count(), 1);
+        let bucket = |operation: &str, le: &str| {
+            format!("ocypod_redis_command_duration_seconds_bucket{{operation=\"{}\",le=\"{}\"}}", operation, le)
+        };
+        assert!(lines.contains(&format!("{} 0", bucket("dequeue", "0.0005")).as_str()));
+        assert!(lines.contains(&format!("{} 1", bucket("dequeue", "0.001")).as_str()));
+        assert!(lines.contains(&format!("{} 2", bucket("dequeue", "0.05")).as_str()));
+        assert!(lines.contains(&format!("{} 2", bucket("dequeue", "1")).as_str()));
+        assert!(lines.contains(&format!("{} 3", bucket("dequeue", "+Inf")).as_str()));
+        assert!(lines.contains(&format!("{} 1", bucket("create", "0.0005")).as_str()));
+        assert!(lines.contains(&"ocypod_redis_command_duration_seconds_sum{operation=\"dequeue\"} 2.030700"));
+        assert!(lines.contains(&"ocypod_redis_command_duration_seconds_count{operation=\"dequeue\"} 3"));
+        assert!(lines.contains(&"ocypod_redis_command_duration_seconds_count{operation=\"other\"} 0"));
+    }
+
+    #[test]
+    fn stalled() {
+        let metrics = Metrics::new();
+        let max_gaps = [(Monitor::Timeout, Duration::from_secs(90)), (Monitor::Retry, Duration::from_secs(180))];
+        assert!(metrics.stalled_monitors(&max_gaps, unix_now()).is_empty());
+
+        let now = unix_now();
+        metrics.record_monitor_loop(Monitor::Timeout);
+        metrics.record_monitor_loop(Monitor::Retry);
+        assert!(metrics.stalled_monitors(&max_gaps, now + 90).is_empty());
+
+        let stalled: Vec<Monitor> = metrics.stalled_monitors(&max_gaps, now + 100).into_iter().map(|s| s.0).collect();
+        assert_eq!(stalled, vec![Monitor::Timeout]);
+    }
+}
@@ -1,14 +1,40 @@
 //! Main application logic, generally exposed via `RedisManager`.
 
+pub mod cache;
+pub mod clock;
+pub mod callback;
+#[cfg(feature = "testing")]
+pub mod chaos;
+pub mod crypto;
+pub mod drain;
+pub mod export;
 mod job;
 mod keys;
+pub mod keyspace;
+pub mod leader;
+pub mod limits;
 mod manager;
+pub mod metrics;
 pub mod monitor;
+pub mod notify;
+pub mod offload;
+pub mod pool;
+mod push;
 mod queue;
+pub mod reconcile;
+pub mod runner;
+mod scan;
+pub mod schema;
+pub mod shard;
+pub mod slowlog;
+pub mod system;
 mod tag;
+pub mod throttle;
+pub mod tolerance;
 pub mod file;
 
 pub use job::RedisJob;
 pub use manager::RedisManager;
-use queue::RedisQueue;
+pub use queue::MAX_SAMPLE_SIZE;
+pub(crate) use queue::RedisQueue;
 use tag::RedisTag;
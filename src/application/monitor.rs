@@ -1,67 +1,900 @@
 //! Defines actor for running periodic Redis tasks.
-use crate::application::RedisManager;
-use std::time::Duration;
+use crate::application::drain::Drain;
+use crate::application::export::{self, StatsdFormatter};
+use crate::application::leader::Leadership;
+use crate::application::pool::{PooledConnection, RedisPool};
+use crate::application::shard::RedisShards;
+use crate::application::metrics::{Monitor, METRICS, PROMETHEUS_CONTENT_TYPE};
+use crate::application::system::SystemQueue;
+use crate::application::{callback, clock, file, keyspace, notify, push, RedisManager};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use log::{error, info};
+use log::{debug, error, info, warn};
+use redis::IntoConnectionInfo;
+use tokio::sync::broadcast::RecvError;
 
-use crate::config::ServerConfig;
+use crate::application::tolerance::HEARTBEAT_TOLERANCE;
+use crate::config::{
+    AnomaliesConfig, AuthConfig, HeartbeatToleranceConfig, MetricsConfig, NotificationsConfig, RedisConfig,
+    ServerConfig,
+};
+use crate::events::anomaly::AnomalyDetector;
+use crate::events::notifications;
+use crate::events::{EventBus, EventKind};
+use crate::middleware::circuit_breaker::CircuitBreaker;
+use crate::models::{self, job, queue, OcyError, OcyResult};
 
-/// Start all background tasks that perform monitoring/cleanup.
-pub fn start_monitors(conn: redis::aio::ConnectionManager, config: &ServerConfig) {
-    start_timeout_monitor(conn.clone(), config.timeout_check_interval.0);
-    start_retry_monitor(conn.clone(), config.retry_check_interval.0);
-    start_expiry_monitor(conn, config.expiry_check_interval.0);
+/// Minimum time between timeout, retry, or expiry checks, avoids busy looping if a queue's check interval is 0.
+const MIN_CHECK_DELAY: Duration = Duration::from_secs(1);
+
+/// Time to wait before resubscribing to keyspace notifications after the subscription is lost.
+const KEYSPACE_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+/// Start all background tasks that perform monitoring/cleanup, for each Redis shard.
+///
+/// Timeout, retry, and expiry checks are skipped while this server isn't the leader.
+pub fn start_monitors(
+    shards: &RedisShards,
+    config: &ServerConfig,
+    events: &EventBus,
+    leadership: &Leadership,
+    drain: &Drain,
+) {
+    for pool in shards.all() {
+        start_shard_monitors(pool, config, events, leadership, drain);
+    }
+    start_stall_watchdog(config.clone());
+}
+
+/// Get the timeout, retry, and expiry monitors whose loops haven't run within `monitor_stall_tolerance` times their
+/// check interval, along with the number of seconds since they last did.
+///
+/// Monitors on every shard record to the same metrics, so a monitor is only considered stalled once it's stalled on
+/// every shard.
+pub fn stalled_monitors(config: &ServerConfig) -> Vec<(Monitor, u64)> {
+    if config.monitor_stall_tolerance == 0 {
+        return Vec::new();
+    }
+    let max_gaps: Vec<(Monitor, Duration)> = [
+        (Monitor::Timeout, config.timeout_check_interval.0),
+        (Monitor::Retry, config.retry_check_interval.0),
+        (Monitor::Expiry, config.expiry_check_interval.0),
+    ]
+    .iter()
+    .map(|(monitor, interval)| (*monitor, (*interval).max(MIN_CHECK_DELAY) * config.monitor_stall_tolerance))
+    .collect();
+    METRICS.stalled_monitors(&max_gaps, models::DateTime::now().timestamp() as u64)
+}
+
+/// Start periodic background task that logs a warning for each monitor that's stalled, e.g. due to its task having
+/// died or hung on a Redis command.
+fn start_stall_watchdog(config: ServerConfig) {
+    if config.monitor_stall_tolerance == 0 {
+        return;
+    }
+    let check_interval = config.timeout_check_interval.0.max(MIN_CHECK_DELAY);
+    actix_rt::spawn(async move {
+        loop {
+            actix_rt::time::delay_for(check_interval).await;
+            for (monitor, since) in stalled_monitors(&config) {
+                warn!("The {} monitor appears to be stalled, it last ran {}s ago", monitor.label(), since);
+            }
+        }
+    })
+}
+
+/// Limits on the batches of jobs the timeout, retry, and expiry monitors check at once, and how long each pass runs.
+#[derive(Clone, Copy, Debug)]
+struct BatchLimits {
+    batch_size: usize,
+    max_pass_duration: Option<Duration>,
+}
+
+impl BatchLimits {
+    /// Get batching for a pass started at given time.
+    fn for_pass(self, started: Instant) -> queue::Batching {
+        queue::Batching::new(self.batch_size, self.max_pass_duration, started)
+    }
 }
 
-/// Start periodic background task that checks jobs for timeouts.
-fn start_timeout_monitor(conn: redis::aio::ConnectionManager, check_interval: Duration) {
+/// Start all background tasks that perform monitoring/cleanup for a single Redis shard.
+fn start_shard_monitors(
+    pool: &RedisPool,
+    config: &ServerConfig,
+    events: &EventBus,
+    leadership: &Leadership,
+    drain: &Drain,
+) {
+    let batch_limits = BatchLimits {
+        batch_size: config.monitor_batch_size,
+        max_pass_duration: config.monitor_max_pass_duration.as_ref().map(|duration| duration.0),
+    };
+    start_timeout_monitor(
+        pool.get(),
+        config.timeout_check_interval.0,
+        batch_limits,
+        events.clone(),
+        leadership.clone(),
+    );
+    start_retry_monitor(
+        pool.get(),
+        config.retry_check_interval.0,
+        batch_limits,
+        events.clone(),
+        leadership.clone(),
+    );
+    start_expiry_monitor(
+        pool.get(),
+        config.expiry_check_interval.0,
+        config.expiry_check_statuses.clone(),
+        batch_limits,
+        leadership.clone(),
+    );
+    start_push_monitor(
+        pool.get(),
+        config.push_check_interval.0,
+        config.push_timeout.0,
+        config.push_retries,
+        config.push_concurrency,
+        events.clone(),
+        drain.clone(),
+    );
+    start_tag_prune_monitor(pool.get(), config.tag_prune_interval.0, leadership.clone());
+}
+
+/// Start periodic background task that checks jobs for timeouts, for SLA breaches once their deadline has passed, and
+/// for queued jobs that have waited longer than their queue's `max_queue_age`.
+///
+/// Each queue is checked on its own timeout check interval if it has one, otherwise the server's default interval is
+/// used.
+fn start_timeout_monitor(
+    conn: PooledConnection,
+    default_interval: Duration,
+    batch_limits: BatchLimits,
+    events: EventBus,
+    leadership: Leadership,
+) {
     info!(
-        "Checking job timeouts every {}",
-        humantime::format_duration(check_interval)
+        "Checking job timeouts every {} by default",
+        humantime::format_duration(default_interval)
     );
     actix_rt::spawn(async move {
-        let mut interval = actix_rt::time::interval(check_interval);
         let mut conn = conn;
+        let mut schedule = queue::CheckSchedule::new(default_interval);
         loop {
-            interval.tick().await;
-            if let Err(err) = RedisManager::check_job_timeouts(&mut conn).await {
-                error!("Job timeout monitoring failed: {}", err);
+            METRICS.record_monitor_loop(Monitor::Timeout);
+            if !leadership.is_leader() {
+                // check again on the next election
+                schedule.clear();
+                actix_rt::time::delay_for(MIN_CHECK_DELAY).await;
+                continue;
+            }
+
+            let now = Instant::now();
+            let intervals = queue_check_intervals(&mut conn, default_interval, |s| &s.timeout_check_interval).await;
+            let intervals = match intervals {
+                Ok(intervals) => intervals,
+                Err(err) => {
+                    error!("Job timeout monitoring failed: {}", err);
+                    METRICS.record_monitor_pass(Monitor::Timeout, now.elapsed(), 0, false);
+                    actix_rt::time::delay_for(default_interval).await;
+                    continue;
+                }
+            };
+
+            let mut sweep = schedule.sweep(&intervals, now);
+            sweep.batching = batch_limits.for_pass(now);
+            if !sweep.is_empty() {
+                let mut transitioned = 0;
+                let mut success = true;
+                match RedisManager::check_job_timeouts(&mut conn, &sweep).await {
+                    Ok(job_ids) => {
+                        transitioned = job_ids.len();
+                        for job_id in job_ids {
+                            events.job_event(EventKind::TimedOut, job_id, None);
+                        }
+                    }
+                    Err(err) => {
+                        error!("Job timeout monitoring failed: {}", err);
+                        success = false;
+                    }
+                }
+
+                if let Err(err) = RedisManager::check_sla_deadlines(&mut conn, &sweep).await {
+                    error!("Job SLA monitoring failed: {}", err);
+                    success = false;
+                }
+
+                match RedisManager::check_queue_ages(&mut conn, &sweep).await {
+                    Ok(job_ids) => {
+                        transitioned += job_ids.len();
+                        for job_id in job_ids {
+                            events.job_event(EventKind::Quarantined, job_id, None);
+                        }
+                    }
+                    Err(err) => {
+                        error!("Job queue age monitoring failed: {}", err);
+                        success = false;
+                    }
+                }
+                METRICS.record_monitor_pass(Monitor::Timeout, now.elapsed(), transitioned, success);
             }
+
+            let next_check = schedule.next_check(&intervals, now);
+            actix_rt::time::delay_for(next_check.max(MIN_CHECK_DELAY)).await;
         }
     })
 }
 
 /// Start periodic background task that checks for jobs that need retrying.
-fn start_retry_monitor(conn: redis::aio::ConnectionManager, check_interval: Duration) {
+///
+/// Each queue is checked on its own retry check interval if it has one, otherwise the server's default interval is
+/// used.
+fn start_retry_monitor(
+    conn: PooledConnection,
+    default_interval: Duration,
+    batch_limits: BatchLimits,
+    events: EventBus,
+    leadership: Leadership,
+) {
+    info!(
+        "Checking job retries every {} by default",
+        humantime::format_duration(default_interval)
+    );
+    actix_rt::spawn(async move {
+        let mut conn = conn;
+        let mut schedule = queue::CheckSchedule::new(default_interval);
+        loop {
+            METRICS.record_monitor_loop(Monitor::Retry);
+            if !leadership.is_leader() {
+                // check again on the next election
+                schedule.clear();
+                actix_rt::time::delay_for(MIN_CHECK_DELAY).await;
+                continue;
+            }
+
+            let now = Instant::now();
+            let intervals = queue_check_intervals(&mut conn, default_interval, |s| &s.retry_check_interval).await;
+            let intervals = match intervals {
+                Ok(intervals) => intervals,
+                Err(err) => {
+                    error!("Job retry monitoring failed: {}", err);
+                    METRICS.record_monitor_pass(Monitor::Retry, now.elapsed(), 0, false);
+                    actix_rt::time::delay_for(default_interval).await;
+                    continue;
+                }
+            };
+
+            let mut sweep = schedule.sweep(&intervals, now);
+            sweep.batching = batch_limits.for_pass(now);
+            if !sweep.is_empty() {
+                let mut transitioned = 0;
+                let mut success = true;
+                match RedisManager::check_job_quarantine(&mut conn, &sweep).await {
+                    Ok(job_ids) => {
+                        transitioned += job_ids.len();
+                        for job_id in job_ids {
+                            events.job_event(EventKind::Quarantined, job_id, None);
+                        }
+                    }
+                    Err(err) => {
+                        error!("Job quarantine monitoring failed: {}", err);
+                        success = false;
+                    }
+                }
+                match RedisManager::check_job_retries(&mut conn, &sweep).await {
+                    Ok(job_ids) => {
+                        transitioned += job_ids.len();
+                        for job_id in job_ids {
+                            events.job_event(EventKind::Retried, job_id, None);
+                        }
+                    }
+                    Err(err) => {
+                        error!("Job retry monitoring failed: {}", err);
+                        success = false;
+                    }
+                }
+                METRICS.record_monitor_pass(Monitor::Retry, now.elapsed(), transitioned, success);
+            }
+
+            let next_check = schedule.next_check(&intervals, now);
+            actix_rt::time::delay_for(next_check.max(MIN_CHECK_DELAY)).await;
+        }
+    })
+}
+
+/// Start periodic background that checks for expired jobs and cleans them up.
+///
+/// Each queue is checked on its own expiry check interval if it has one, otherwise the server's default interval is
+/// used.
+fn start_expiry_monitor(
+    conn: PooledConnection,
+    default_interval: Duration,
+    default_statuses: Vec<job::Status>,
+    batch_limits: BatchLimits,
+    leadership: Leadership,
+) {
+    info!(
+        "Checking job expiry every {} by default",
+        humantime::format_duration(default_interval)
+    );
+    actix_rt::spawn(async move {
+        let mut conn = conn;
+        let mut schedule = queue::CheckSchedule::new(default_interval);
+        loop {
+            METRICS.record_monitor_loop(Monitor::Expiry);
+            if !leadership.is_leader() {
+                // check again on the next election
+                schedule.clear();
+                actix_rt::time::delay_for(MIN_CHECK_DELAY).await;
+                continue;
+            }
+
+            let now = Instant::now();
+            let policies =
+                match RedisManager::queue_expiry_policies(&mut conn, &default_statuses, default_interval).await {
+                    Ok(policies) => policies,
+                    Err(err) => {
+                        error!("Job expiry monitoring failed: {}", err);
+                        METRICS.record_monitor_pass(Monitor::Expiry, now.elapsed(), 0, false);
+                        actix_rt::time::delay_for(default_interval).await;
+                        continue;
+                    }
+                };
+            let intervals: HashMap<String, Duration> = policies
+                .iter()
+                .map(|(name, policy)| (name.clone(), policy.check_interval))
+                .collect();
+
+            let due = schedule.sweep(&intervals, now);
+            let batching = batch_limits.for_pass(now);
+            let sweep = queue::ExpirySweep {
+                queues: policies
+                    .into_iter()
+                    .map(|(name, policy)| {
+                        let statuses = if due.includes(Some(&name)) { Some(policy.statuses) } else { None };
+                        (name, statuses)
+                    })
+                    .collect(),
+                default: if due.default { Some(default_statuses.clone()) } else { None },
+                batching,
+            };
+
+            let mut transitioned = 0;
+            let mut success = true;
+            if !sweep.is_empty() {
+                match RedisManager::check_job_expiry(&mut conn, &sweep).await {
+                    Ok(job_ids) => transitioned += job_ids.len(),
+                    Err(err) => {
+                        error!("Job expiry monitoring failed: {}", err);
+                        success = false;
+                    }
+                }
+            }
+
+            // deleted jobs aren't associated with any queue, so are purged on the default interval
+            if due.default {
+                match RedisManager::purge_trash(&mut conn, &batching).await {
+                    Ok(job_ids) => transitioned += job_ids.len(),
+                    Err(err) => {
+                        error!("Deleted job purging failed: {}", err);
+                        success = false;
+                    }
+                }
+            }
+            if !sweep.is_empty() || due.default {
+                METRICS.record_monitor_pass(Monitor::Expiry, now.elapsed(), transitioned, success);
+            }
+
+            let next_check = schedule.next_check(&intervals, now);
+            actix_rt::time::delay_for(next_check.max(MIN_CHECK_DELAY)).await;
+        }
+    })
+}
+
+/// Run a single pass of the timeout, retry, or expiry monitor on a shard now, checking every queue whatever its check
+/// interval, and whether or not this server is the leader. Jobs are checked in batches, but the pass always runs to
+/// completion, whatever `monitor_max_pass_duration` is.
+///
+/// Returns the number of jobs transitioned, or a bad request error for any other monitor.
+pub async fn force_pass(
+    conn: &mut PooledConnection,
+    monitor: Monitor,
+    config: &ServerConfig,
+    events: &EventBus,
+) -> OcyResult<usize> {
+    let batching = queue::Batching::new(config.monitor_batch_size, None, Instant::now());
+    let sweep = queue::CheckSweep { batching, ..queue::CheckSweep::all() };
+    match monitor {
+        Monitor::Timeout => {
+            let job_ids = RedisManager::check_job_timeouts(conn, &sweep).await?;
+            for job_id in &job_ids {
+                events.job_event(EventKind::TimedOut, *job_id, None);
+            }
+            RedisManager::check_sla_deadlines(conn, &sweep).await?;
+            let quarantined = RedisManager::check_queue_ages(conn, &sweep).await?;
+            for job_id in &quarantined {
+                events.job_event(EventKind::Quarantined, *job_id, None);
+            }
+            Ok(job_ids.len() + quarantined.len())
+        }
+        Monitor::Retry => {
+            let quarantined = RedisManager::check_job_quarantine(conn, &sweep).await?;
+            for job_id in &quarantined {
+                events.job_event(EventKind::Quarantined, *job_id, None);
+            }
+            let retried = RedisManager::check_job_retries(conn, &sweep).await?;
+            for job_id in &retried {
+                events.job_event(EventKind::Retried, *job_id, None);
+            }
+            Ok(quarantined.len() + retried.len())
+        }
+        Monitor::Expiry => {
+            let default_statuses = &config.expiry_check_statuses;
+            let policies =
+                RedisManager::queue_expiry_policies(conn, default_statuses, config.expiry_check_interval.0).await?;
+            let sweep = queue::ExpirySweep {
+                queues: policies.into_iter().map(|(name, policy)| (name, Some(policy.statuses))).collect(),
+                default: Some(default_statuses.clone()),
+                batching,
+            };
+            let expired = RedisManager::check_job_expiry(conn, &sweep).await?;
+            let purged = RedisManager::purge_trash(conn, &batching).await?;
+            Ok(expired.len() + purged.len())
+        }
+        _ => Err(OcyError::bad_request(format!("The {} monitor can't be forced to run", monitor.label()))),
+    }
+}
+
+/// Start periodic background task that removes jobs that no longer exist from tags.
+fn start_tag_prune_monitor(conn: PooledConnection, interval: Duration, leadership: Leadership) {
+    info!("Pruning tags every {}", humantime::format_duration(interval));
+    actix_rt::spawn(async move {
+        let mut conn = conn;
+        loop {
+            actix_rt::time::delay_for(interval.max(MIN_CHECK_DELAY)).await;
+            if !leadership.is_leader() {
+                continue;
+            }
+
+            let now = Instant::now();
+            match RedisManager::prune_tags(&mut conn, false).await {
+                Ok(pruned) => {
+                    let num_pruned = pruned.values().map(Vec::len).sum();
+                    if num_pruned > 0 {
+                        info!("Removed {} job(s) that no longer exist from {} tag(s)", num_pruned, pruned.len());
+                    }
+                    METRICS.record_monitor_pass(Monitor::TagPrune, now.elapsed(), num_pruned, true);
+                }
+                Err(err) => {
+                    error!("Tag pruning failed: {}", err);
+                    METRICS.record_monitor_pass(Monitor::TagPrune, now.elapsed(), 0, false);
+                }
+            }
+        }
+    })
+}
+
+/// Get the check interval for each queue, using given default for any queue that doesn't override it.
+async fn queue_check_intervals<F>(
+    conn: &mut PooledConnection,
+    default_interval: Duration,
+    interval: F,
+) -> OcyResult<HashMap<String, Duration>>
+where
+    F: Fn(&queue::Settings) -> &Option<models::Duration>,
+{
+    Ok(RedisManager::all_queue_settings(conn)
+        .await?
+        .into_iter()
+        .map(|(name, settings)| {
+            let check_interval = interval(&settings).as_ref().map_or(default_interval, |d| d.0);
+            (name, check_interval)
+        })
+        .collect())
+}
+
+/// Start periodic background task that pushes queued jobs to any registered callback URLs.
+fn start_push_monitor(
+    conn: PooledConnection,
+    check_interval: Duration,
+    timeout: Duration,
+    retries: u64,
+    concurrency: usize,
+    events: EventBus,
+    drain: Drain,
+) {
     info!(
-        "Checking job retries every {}",
+        "Checking for jobs to push every {}",
         humantime::format_duration(check_interval)
     );
     actix_rt::spawn(async move {
+        let client = actix_web::client::Client::builder().timeout(timeout).finish();
         let mut interval = actix_rt::time::interval(check_interval);
-        let mut conn = conn;
+        let mut suspensions = push::Suspensions::default();
         loop {
             interval.tick().await;
-            if let Err(err) = RedisManager::check_job_retries(&mut conn).await {
-                error!("Job retry monitoring failed: {}", err);
+            if drain.is_draining() {
+                continue;
+            }
+            let started = Instant::now();
+            match push::push_jobs(&conn, &client, retries, concurrency, &mut suspensions, &events).await {
+                Ok(job_ids) => METRICS.record_monitor_pass(Monitor::Push, started.elapsed(), job_ids.len(), true),
+                Err(err) => {
+                    error!("Job push delivery failed: {}", err);
+                    METRICS.record_monitor_pass(Monitor::Push, started.elapsed(), 0, false);
+                }
             }
         }
     })
 }
 
-/// Start periodic background that checks for expired jobs and cleans them up.
-fn start_expiry_monitor(conn: redis::aio::ConnectionManager, check_interval: Duration) {
+/// Start background task that checks the active Redis instance is still available, and fails over to another if not,
+/// when the first shard is configured with multiple Redis URLs.
+///
+/// Checks are made periodically, and immediately after any command fails due to a connection error.
+pub fn start_failover_monitor(shards: &RedisShards, check_interval: Duration) {
+    let pool = shards.primary().clone();
+    if !pool.has_failover() {
+        return;
+    }
+    info!(
+        "Checking Redis for failover every {}",
+        humantime::format_duration(check_interval)
+    );
+    actix_rt::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = actix_rt::time::delay_for(check_interval) => (),
+                _ = pool.connection_failed() => (),
+            }
+            if let Err(err) = pool.check_failover().await {
+                error!("Redis failover failed: {}", err);
+                // avoid busy looping on connection errors while no instance is available
+                actix_rt::time::delay_for(MIN_CHECK_DELAY).await;
+            }
+        }
+    })
+}
+
+/// Start periodic background task that replays job creation requests accepted while Redis was unavailable.
+pub fn start_replay_monitor(
+    shards: RedisShards,
+    breaker: Arc<CircuitBreaker>,
+    check_interval: Duration,
+    auth: AuthConfig,
+    events: EventBus,
+) {
     info!(
-        "Checking job expiry every {}",
+        "Replaying jobs accepted in degraded mode every {}",
         humantime::format_duration(check_interval)
     );
     actix_rt::spawn(async move {
         let mut interval = actix_rt::time::interval(check_interval);
-        let mut conn = conn;
         loop {
             interval.tick().await;
-            if let Err(err) = RedisManager::check_job_expiry(&mut conn).await {
-                error!("Job expiry monitoring failed: {}", err);
+            if breaker.retry_after().is_some() {
+                continue;
+            }
+            let started = Instant::now();
+            match file::replay_jobs(&shards, &auth, &events).await {
+                Ok(replayed) => {
+                    METRICS.record_monitor_pass(Monitor::Replay, started.elapsed(), replayed, true);
+                }
+                Err(err @ OcyError::RedisConnection(_)) => {
+                    warn!("Job replay postponed, Redis unavailable: {}", err);
+                    METRICS.record_monitor_pass(Monitor::Replay, started.elapsed(), 0, false);
+                }
+                Err(err) => {
+                    error!("Job replay failed: {}", err);
+                    METRICS.record_monitor_pass(Monitor::Replay, started.elapsed(), 0, false);
+                }
+            }
+        }
+    })
+}
+
+/// Start background task that counts job events in Redis, and periodically sends webhook notifications for any
+/// notification rules that have been triggered, along with any changes in queues' health as events are counted.
+///
+/// Counts are shared by all servers, so rules are evaluated against jobs finishing on any server, and each
+/// notification is only sent once, whichever server evaluates it first.
+pub fn start_notification_monitor(
+    shards: RedisShards,
+    config: &NotificationsConfig,
+    events: &EventBus,
+) {
+    let webhook_url = match &config.webhook_url {
+        Some(url) if !config.rules.is_empty() || !config.health_rules.is_empty() => url.clone(),
+        _ => return,
+    };
+    info!(
+        "Checking {} notification rule(s) and {} health rule(s) every {}",
+        config.rules.len(),
+        config.health_rules.len(),
+        humantime::format_duration(config.check_interval.0)
+    );
+
+    let rules = config.rules.clone();
+    let health_rules = config.health_rules.clone();
+    let retention = rules.iter().map(|rule| rule.window.0).max().unwrap_or_default();
+    let check_interval = config.check_interval.0;
+    let mut receiver = events.subscribe();
+    actix_rt::spawn(async move {
+        let client = actix_web::client::Client::default();
+        let mut interval = actix_rt::time::interval(check_interval);
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) if notifications::is_relevant(&event) => {
+                        let queue = match event.queue {
+                            Some(queue) => Some(queue),
+                            None => {
+                                let mut conn = shards.for_job(event.job_id).get();
+                                RedisManager::job_queue(&mut conn, event.job_id).await.ok()
+                            }
+                        };
+                        if let Some(queue) = queue {
+                            let mut conn = shards.primary().get();
+                            let timestamp = clock::now().timestamp();
+                            let recorded =
+                                notify::record_event(&mut conn, &queue, event.event, timestamp, retention, &health_rules);
+                            match recorded.await {
+                                Ok(messages) => {
+                                    for message in messages {
+                                        send_notification(&client, &webhook_url, message);
+                                    }
+                                }
+                                Err(err) => error!("[queue:{}] failed to count notification event: {}", &queue, err),
+                            }
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(RecvError::Lagged(missed)) => warn!("Notification monitor fell behind, {} event(s) dropped", missed),
+                    Err(RecvError::Closed) => break,
+                },
+                _ = interval.tick() => {
+                    let mut conn = shards.primary().get();
+                    match notify::evaluate(&mut conn, &rules, clock::now().timestamp()).await {
+                        Ok(messages) => {
+                            for message in messages {
+                                send_notification(&client, &webhook_url, message);
+                            }
+                        }
+                        Err(err) => error!("Failed to evaluate notification rules: {}", err),
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Send a message to the notifications webhook from a separate task, so that a slow webhook doesn't hold up the
+/// monitor sending it.
+fn send_notification(client: &actix_web::client::Client, webhook_url: &str, message: String) {
+    info!("Sending notification: {}", message);
+    let client = client.clone();
+    let webhook_url = webhook_url.to_owned();
+    actix_rt::spawn(async move {
+        match client.post(&webhook_url).send_json(&notifications::webhook_body(&message)).await {
+            Ok(res) if res.status().is_success() => (),
+            Ok(res) => error!("Notification webhook responded with {}", res.status()),
+            Err(err) => error!("Failed to send notification: {}", err),
+        }
+    })
+}
+
+/// Start background task that records job events, and checks each queue's failure rate for anomalies at the end of
+/// each window, logging any found, and sending them to the notifications webhook if configured to.
+pub fn start_anomaly_monitor(
+    shards: RedisShards,
+    detector: Arc<AnomalyDetector>,
+    config: &AnomaliesConfig,
+    webhook_url: Option<String>,
+    events: &EventBus,
+) {
+    if !config.enabled {
+        return;
+    }
+    let window = config.window.0;
+    info!("Checking queue failure rates for anomalies every {}", humantime::format_duration(window));
+
+    let webhook_url = webhook_url.filter(|_| config.notify);
+    let mut receiver = events.subscribe();
+    actix_rt::spawn(async move {
+        let client = actix_web::client::Client::default();
+        let mut interval = actix_rt::time::interval_at(actix_rt::time::Instant::now() + window, window);
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) if AnomalyDetector::is_relevant(&event.event) => {
+                        let queue = match event.queue {
+                            Some(queue) => Some(queue),
+                            None => {
+                                let mut conn = shards.for_job(event.job_id).get();
+                                RedisManager::job_queue(&mut conn, event.job_id).await.ok()
+                            }
+                        };
+                        if let Some(queue) = queue {
+                            detector.record(queue, event.event);
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(RecvError::Lagged(missed)) => warn!("Anomaly monitor fell behind, {} event(s) dropped", missed),
+                    Err(RecvError::Closed) => break,
+                },
+                _ = interval.tick() => {
+                    for (queue, anomaly) in detector.end_window(chrono::Utc::now()) {
+                        let message = anomaly.message(&queue, window);
+                        warn!("{}", message);
+                        if let Some(webhook_url) = &webhook_url {
+                            send_notification(&client, webhook_url, message);
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Start background task that queues deliveries of the results of jobs ending on this server to the callback URLs
+/// given when they were created, which are then sent by the system job executor.
+///
+/// Each delivery is queued by the server that handled the job ending, so deliveries of the results of jobs ending
+/// while a server is shutting down may not be queued.
+pub fn start_callback_monitor(shards: RedisShards, events: &EventBus) {
+    let mut receiver = events.subscribe();
+    actix_rt::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if callback::is_relevant(event.event) => {
+                    let mut conn = shards.for_job(event.job_id).get();
+                    let mut system_conn = shards.for_queue(SystemQueue::Callbacks.name()).get();
+                    actix_rt::spawn(async move {
+                        let queued = callback::queue_delivery(&mut conn, &mut system_conn, event.job_id).await;
+                        match queued {
+                            Ok(Some(delivery_id)) => {
+                                debug!("[job:{}] result delivery queued as job {}", event.job_id, delivery_id)
+                            }
+                            Ok(None) => (),
+                            Err(err) => error!("[job:{}] failed to queue result delivery: {}", event.job_id, err),
+                        }
+                    });
+                }
+                Ok(_) => (),
+                Err(RecvError::Lagged(missed)) => warn!("Callback monitor fell behind, {} event(s) dropped", missed),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+/// Start background task for each Redis shard that watches for jobs expired or evicted by Redis itself, if keyspace
+/// notifications are enabled.
+///
+/// Each shard's task subscribes to the Redis instance its pool is currently using, and resubscribes if the
+/// subscription is lost, e.g. after a failover.
+pub fn start_keyspace_monitor(shards: &RedisShards, config: &RedisConfig) {
+    if !config.keyspace_notifications {
+        return;
+    }
+    let urls = std::iter::once(&config.url).chain(&config.shard_urls);
+    for (pool, url) in shards.all().iter().zip(urls) {
+        let pool = pool.clone();
+        let url = url.clone();
+        actix_rt::spawn(async move {
+            loop {
+                let info = match pool.active_instance() {
+                    Some(info) => Ok(info),
+                    None => url.as_str().into_connection_info(),
+                };
+                let watched = match info {
+                    Ok(info) => keyspace::watch(&pool, info).await,
+                    Err(err) => Err(err.into()),
+                };
+                match watched {
+                    Ok(()) => warn!("Keyspace notification subscription closed, resubscribing"),
+                    Err(err) => error!("Failed to subscribe to keyspace notifications: {}", err),
+                }
+                actix_rt::time::delay_for(KEYSPACE_RESUBSCRIBE_DELAY).await;
+            }
+        });
+    }
+}
+
+/// Start periodic background task that checks whether any Redis shard's `maxmemory-policy` may evict jobs, logging a
+/// warning for each that does, unless checks are disabled by a zero interval.
+pub fn start_eviction_monitor(shards: &RedisShards, check_interval: Duration) {
+    if check_interval.as_secs() == 0 {
+        return;
+    }
+    let shards = shards.clone();
+    actix_rt::spawn(async move {
+        loop {
+            actix_rt::time::delay_for(check_interval).await;
+            match keyspace::evicting_shards(&shards).await {
+                Ok(evicting) => {
+                    for (shard, policy) in evicting {
+                        warn!("Redis shard {} uses maxmemory-policy {}, which may evict jobs", shard, policy);
+                    }
+                }
+                Err(err) => error!("Failed to check Redis maxmemory-policy: {}", err),
+            }
+        }
+    });
+}
+
+/// Start periodic background task that relaxes heartbeat timeouts while this server is overloaded, if enabled.
+pub fn start_heartbeat_tolerance_monitor(config: &HeartbeatToleranceConfig) {
+    if !config.enabled {
+        return;
+    }
+    HEARTBEAT_TOLERANCE.enable();
+    let config = config.clone();
+    let check_interval = config.check_interval.0.max(MIN_CHECK_DELAY);
+    actix_rt::spawn(async move {
+        loop {
+            actix_rt::time::delay_for(check_interval).await;
+            let (load, previous, factor) = HEARTBEAT_TOLERANCE.adjust(&config);
+            METRICS.record_heartbeat_tolerance(factor);
+            if factor > previous {
+                warn!(
+                    "Server overloaded (mean Redis latency {:?}, {} of {} request(s) failed), heartbeat timeouts \
+                     relaxed to {}x",
+                    load.mean_redis_latency().unwrap_or_default(),
+                    load.server_errors,
+                    load.requests,
+                    factor
+                );
+            } else if factor < previous {
+                info!("Server load reduced, heartbeat timeouts relaxed to {}x", factor);
+            }
+        }
+    });
+}
+
+/// Start periodic background task that pushes metrics to a Prometheus pushgateway, and/or sends them to a StatsD
+/// server, if either is configured.
+pub fn start_metrics_export(config: &MetricsConfig) {
+    let pushgateway_url = config.pushgateway_url.as_ref().map(|url| {
+        export::pushgateway_url(url, &config.pushgateway_job, &config.pushgateway_instance())
+    });
+    let mut statsd = config
+        .statsd_addr
+        .clone()
+        .map(|addr| (addr, StatsdFormatter::new(&config.statsd_prefix, config.statsd_format)));
+    if pushgateway_url.is_none() && statsd.is_none() {
+        return;
+    }
+
+    let export_interval = config.export_interval.0;
+    if let Some(url) = &pushgateway_url {
+        info!("Pushing metrics to {} every {}", url, humantime::format_duration(export_interval));
+    }
+    if let Some((addr, _)) = &statsd {
+        info!("Sending metrics to StatsD at {} every {}", addr, humantime::format_duration(export_interval));
+    }
+    actix_rt::spawn(async move {
+        let client = actix_web::client::Client::builder().timeout(export_interval).finish();
+        let mut interval = actix_rt::time::interval(export_interval);
+        loop {
+            interval.tick().await;
+            let pending_files = file::count_jobs();
+            if let Some(url) = &pushgateway_url {
+                let body = METRICS.render(pending_files);
+                match client.put(url).content_type(PROMETHEUS_CONTENT_TYPE).send_body(body).await {
+                    Ok(res) if res.status().is_success() => (),
+                    Ok(res) => error!("Pushgateway responded with {}", res.status()),
+                    Err(err) => error!("Failed to push metrics to pushgateway: {}", err),
+                }
+            }
+            if let Some((addr, formatter)) = &mut statsd {
+                let packets = formatter.packets(&METRICS.samples(pending_files));
+                if let Err(err) = export::send_statsd(addr, &packets).await {
+                    error!("Failed to send metrics to StatsD at {}: {}", addr, err);
+                }
             }
         }
     })
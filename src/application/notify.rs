@@ -0,0 +1,194 @@
+//! State for notification rules, shared in Redis by all servers.
+//!
+//! Each server counts the jobs finishing on it into short time buckets on the primary shard, so that rules are
+//! evaluated against every job finishing across all servers, rather than only those finishing on the server
+//! evaluating them. Queues' health is updated atomically as each job is counted, so each change in health is seen by
+//! exactly one server, and notification cooldowns are tracked by keys that only one server can set. Every server can
+//! therefore evaluate rules without the same notification being sent more than once.
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::time::Duration;
+
+use redis::aio::ConnectionLike;
+
+use super::keys;
+use crate::config::{HealthRule, NotificationRule};
+use crate::events::notifications::{self, Counts, HealthChange};
+use crate::events::EventKind;
+use crate::models::OcyResult;
+
+/// Length in seconds of the time buckets finished jobs are counted in. Rules' windows are rounded up to a whole number
+/// of buckets.
+const BUCKET_SECS: i64 = 10;
+
+/// Updates a queue's health as tracked by a health rule, given whether a job on it completed, and the number of jobs
+/// in a row that must complete for it to be healthy. Returns the kind of change and its count if the queue started
+/// failing or recovered, otherwise nil.
+const RECORD_HEALTH_SCRIPT: &str = r#"
+local successes = tonumber(redis.call("hget", KEYS[1], ARGV[1] .. ":successes") or 0)
+local healthy = redis.call("hget", KEYS[1], ARGV[1] .. ":healthy") == "1"
+local failures = redis.call("hget", KEYS[1], ARGV[1] .. ":failures")
+local change = false
+
+if ARGV[2] == "1" then
+    successes = successes + 1
+    if successes >= tonumber(ARGV[3]) then
+        if failures then
+            change = {"recovered", tonumber(failures)}
+            redis.call("hdel", KEYS[1], ARGV[1] .. ":failures")
+        end
+        healthy = true
+    end
+else
+    if healthy then
+        change = {"failing", successes}
+        healthy = false
+        failures = 0
+    end
+    if failures then
+        redis.call("hset", KEYS[1], ARGV[1] .. ":failures", tonumber(failures) + 1)
+    end
+    successes = 0
+end
+
+redis.call("hset", KEYS[1], ARGV[1] .. ":successes", successes, ARGV[1] .. ":healthy", healthy and "1" or "0")
+return change
+"#;
+
+/// Get the key counting jobs finishing in the bucket containing given Unix timestamp.
+fn bucket_key(timestamp: i64) -> String {
+    format!("{}{}", keys::NOTIFY_COUNTS_PREFIX, timestamp.div_euclid(BUCKET_SECS) * BUCKET_SECS)
+}
+
+/// Count a job on given queue finishing with given event at given Unix timestamp, keeping the count for at least
+/// `retention`, and update the queue's health for each health rule that applies to it.
+///
+/// Returns messages for any changes in the queue's health.
+pub async fn record_event<C: ConnectionLike + Send>(
+    conn: &mut C,
+    queue: &str,
+    event: EventKind,
+    timestamp: i64,
+    retention: Duration,
+    health_rules: &[HealthRule],
+) -> OcyResult<Vec<String>> {
+    if !retention.is_zero() {
+        let key = bucket_key(timestamp);
+        let expire_at = timestamp.div_euclid(BUCKET_SECS) * BUCKET_SECS + BUCKET_SECS + retention.as_secs() as i64;
+        redis::pipe()
+            .hincr(&key, format!("{}:{}", event.as_str(), queue), 1)
+            .ignore()
+            .expire_at(&key, expire_at as usize)
+            .ignore()
+            .query_async::<_, ()>(conn)
+            .await?;
+    }
+
+    let mut messages = Vec::new();
+    for (idx, rule) in health_rules.iter().enumerate() {
+        if rule.queue.as_deref().is_some_and(|rule_queue| rule_queue != queue) {
+            continue;
+        }
+        let change: Option<(String, u64)> = redis::Script::new(RECORD_HEALTH_SCRIPT)
+            .key(format!("{}{}", keys::NOTIFY_HEALTH_PREFIX, idx))
+            .arg(queue)
+            .arg(event == EventKind::Completed)
+            .arg(rule.successes)
+            .invoke_async(conn)
+            .await?;
+        let change = match change {
+            Some((kind, count)) if kind == "failing" => HealthChange::Failing(count),
+            Some((_, count)) => HealthChange::Recovered(count),
+            None => continue,
+        };
+        messages.push(notifications::health_message(rule, queue, change));
+    }
+    Ok(messages)
+}
+
+/// Get counts of the jobs that finished on each queue over given window, ending at given Unix timestamp.
+pub async fn window_counts<C: ConnectionLike + Send>(
+    conn: &mut C,
+    window: Duration,
+    timestamp: i64,
+) -> OcyResult<HashMap<String, Counts>> {
+    let mut pipe = redis::pipe();
+    let mut bucket = timestamp - window.as_secs() as i64;
+    while bucket.div_euclid(BUCKET_SECS) <= timestamp.div_euclid(BUCKET_SECS) {
+        pipe.hgetall(bucket_key(bucket));
+        bucket += BUCKET_SECS;
+    }
+    let buckets: Vec<HashMap<String, u64>> = pipe.query_async(conn).await?;
+
+    let mut counts: HashMap<String, Counts> = HashMap::new();
+    for (field, count) in buckets.into_iter().flatten() {
+        let (event, queue) = match field.split_once(':') {
+            Some(split) => split,
+            None => continue,
+        };
+        let event = match event {
+            "completed" => EventKind::Completed,
+            "failed" => EventKind::Failed,
+            "timed_out" => EventKind::TimedOut,
+            _ => continue,
+        };
+        counts.entry(queue.to_owned()).or_default().add(event, count);
+    }
+    Ok(counts)
+}
+
+/// Evaluate all rules against jobs finished up to given Unix timestamp, returning messages for any that have been
+/// triggered and aren't cooling down.
+///
+/// Rules are marked as triggered in Redis, so that the same rule triggered for the same queue is only notified once
+/// per cooldown, however many servers evaluate it.
+pub async fn evaluate<C: ConnectionLike + Send>(
+    conn: &mut C,
+    rules: &[NotificationRule],
+    timestamp: i64,
+) -> OcyResult<Vec<String>> {
+    let mut windows: HashMap<Duration, HashMap<String, Counts>> = HashMap::new();
+    let mut messages = Vec::new();
+    for (idx, rule) in rules.iter().enumerate() {
+        if let Entry::Vacant(entry) = windows.entry(rule.window.0) {
+            entry.insert(window_counts(conn, rule.window.0, timestamp).await?);
+        }
+        let counts = &windows[&rule.window.0];
+
+        let queues: Vec<&str> = match &rule.queue {
+            Some(queue) => vec![queue.as_str()],
+            None => counts.keys().map(String::as_str).collect(),
+        };
+        for queue in queues {
+            let value = match notifications::measure(rule, &counts.get(queue).copied().unwrap_or_default()) {
+                Some(value) if value >= rule.threshold => value,
+                _ => continue,
+            };
+            let cooldown = rule.cooldown.as_ref().map_or(rule.window.0, |cooldown| cooldown.0);
+            if claim_notification(conn, idx, queue, cooldown).await? {
+                messages.push(notifications::render(rule, queue, value));
+            }
+        }
+    }
+    Ok(messages)
+}
+
+/// Mark the rule at given position as triggered for given queue until the end of its cooldown, returning false if
+/// it's already cooling down.
+async fn claim_notification<C: ConnectionLike + Send>(
+    conn: &mut C,
+    idx: usize,
+    queue: &str,
+    cooldown: Duration,
+) -> OcyResult<bool> {
+    let key = format!("{}{}:{}", keys::NOTIFY_FIRED_PREFIX, idx, queue);
+    let result: Option<String> = redis::cmd("SET")
+        .arg(key)
+        .arg(1)
+        .arg("NX")
+        .arg("PX")
+        .arg((cooldown.as_millis() as u64).max(1))
+        .query_async(conn)
+        .await?;
+    Ok(result.is_some())
+}
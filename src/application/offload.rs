@@ -0,0 +1,68 @@
+//! Offloading of oversized job outputs to files, so that they aren't written to (and replicated by) Redis.
+//!
+//! Outputs are written to `<output_offload_dir>/<job_id>.json`, encrypted if encryption is enabled, and the job's
+//! output in Redis is replaced by a reference to the file. When running multiple servers, the directory should be
+//! shared between them. Offloaded files aren't removed when jobs expire or are deleted.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::application::crypto;
+use crate::models::{OcyError, OcyResult};
+
+/// Directory oversized outputs are written to, set once at startup.
+static OFFLOAD_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Set the directory oversized outputs are written to. Can only be set once, subsequent calls are ignored.
+pub fn init(dir: PathBuf) {
+    let _ = OFFLOAD_DIR.set(dir);
+}
+
+/// Check whether outputs can be offloaded, i.e. whether an offload directory is configured.
+pub fn is_enabled() -> bool {
+    OFFLOAD_DIR.get().is_some()
+}
+
+/// Write a job's output to the offload directory, returning a reference to it to store as the job's output.
+pub fn offload_output(job_id: u64, output: &serde_json::Value) -> OcyResult<serde_json::Value> {
+    let dir = OFFLOAD_DIR
+        .get()
+        .ok_or_else(|| OcyError::bad_request("Job output is too large, and no output_offload_dir is configured"))?;
+    let serialised = output.to_string();
+    let path = write_output(dir, job_id, &serialised)
+        .map_err(|err| OcyError::Internal(format!("Failed to offload job output: {}", err)))?;
+    Ok(serde_json::json!({
+        "offloaded_to": path.display().to_string(),
+        "size": serialised.len(),
+    }))
+}
+
+/// Write serialised output to a file in given directory, replacing any existing file atomically so that readers
+/// never see a partially written output.
+fn write_output(dir: &Path, job_id: u64, serialised: &str) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json", job_id));
+    let tmp_path = dir.join(format!(".{}.json.tmp", job_id));
+    fs::write(&tmp_path, crypto::seal(serialised.to_owned()))?;
+    fs::rename(&tmp_path, &path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn writing() {
+        let dir = std::env::temp_dir().join(format!("ocypod-offload-{}", std::process::id()));
+        let path = write_output(&dir, 12, r#"{"a":1}"#).unwrap();
+        assert_eq!(path, dir.join("12.json"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), r#"{"a":1}"#);
+
+        write_output(&dir, 12, r#"{"a":2}"#).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), r#"{"a":2}"#);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,501 @@
+//! Pool of Redis connections shared by HTTP handlers and background tasks.
+//!
+//! Each connection in the pool is a reconnecting `ConnectionManager`, and connections are handed out in turn, so that
+//! a slow command only holds up requests sharing its connection. Commands sent via a pooled connection fail with a
+//! timeout error if Redis doesn't respond in time, which is reported as Redis being unavailable.
+//!
+//! A pool may be given a list of Redis URLs to fail over between, in which case all of its connections are replaced
+//! with connections to another URL when the active one fails. Pooled connections always send commands via the pool's
+//! current connections, so connections held by long running tasks follow a failover too.
+//!
+//! Every command sent via a pooled connection is counted and timed in metrics, under the logical operation the
+//! connection was taken for (see `PooledConnection::for_operation`), and the outcome of commands sent to the primary is
+//! recorded by the pool's circuit breaker, if any, so that the breaker only opens when Redis itself fails to respond.
+
+use std::{fmt, io};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use futures::FutureExt;
+use log::{debug, info, warn};
+use redis::aio::{ConnectionLike, ConnectionManager};
+use redis::{Arg, Cmd, ConnectionInfo, IntoConnectionInfo, Pipeline, RedisError, RedisFuture, RedisResult, Value};
+use tokio::sync::Notify;
+
+use super::metrics::{RedisOperation, METRICS};
+use super::slowlog;
+use super::tolerance::HEARTBEAT_TOLERANCE;
+use crate::config::RedisConfig;
+use crate::middleware::circuit_breaker::CircuitBreaker;
+use crate::models::{OcyError, OcyResult};
+
+/// Delay before retrying the first failed connection attempt at startup.
+const MIN_STARTUP_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Maximum delay between connection attempts at startup.
+const MAX_STARTUP_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Fixed size pool of Redis connections, with optional connections to read replicas.
+#[derive(Clone)]
+pub struct RedisPool {
+    connections: Connections,
+    next: Arc<AtomicUsize>,
+    replicas: Connections,
+    next_replica: Arc<AtomicUsize>,
+    command_timeout: Duration,
+    failover: Option<Arc<Failover>>,
+    breaker: Option<Arc<CircuitBreaker>>,
+}
+
+/// Connections shared by a pool and all connections taken from it, which are replaced on failover.
+type Connections = Arc<RwLock<Vec<ConnectionManager>>>;
+
+/// Redis instances a pool can fail over between.
+struct Failover {
+    instances: Vec<ConnectionInfo>,
+    active: AtomicUsize,
+    pool_size: usize,
+    connect_timeout: Duration,
+    command_timeout: Duration,
+
+    // woken when a command fails due to a connection error, so the active instance is checked immediately
+    check: Notify,
+}
+
+impl RedisPool {
+    /// Open all connections in the pool, failing if any can't be established within the configured connect timeout.
+    ///
+    /// If any read replicas are configured, the same number of connections are spread across them.
+    pub async fn connect(client: &redis::Client, config: &RedisConfig) -> OcyResult<Self> {
+        let connections = open_connections(client, config.pool_size, config.connect_timeout.0).await?;
+        debug!("Opened {} Redis connection(s)", connections.len());
+
+        let mut replicas = Vec::new();
+        if !config.replica_urls.is_empty() {
+            let replica_clients = config
+                .replica_urls
+                .iter()
+                .map(|url| redis::Client::open(url.as_str()))
+                .collect::<Result<Vec<_>, _>>()?;
+            for i in 0..config.pool_size.max(replica_clients.len()) {
+                let client = &replica_clients[i % replica_clients.len()];
+                let conn = with_timeout(config.connect_timeout.0, client.get_tokio_connection_manager()).await?;
+                replicas.push(conn);
+            }
+            debug!(
+                "Opened {} connection(s) to {} Redis read replica(s)",
+                replicas.len(),
+                replica_clients.len()
+            );
+        }
+
+        Ok(Self {
+            connections: Arc::new(RwLock::new(connections)),
+            next: Arc::new(AtomicUsize::new(0)),
+            replicas: Arc::new(RwLock::new(replicas)),
+            next_replica: Arc::new(AtomicUsize::new(0)),
+            command_timeout: config.command_timeout.0,
+            failover: None,
+            breaker: None,
+        })
+    }
+
+    /// Open all connections in the pool to the first of the configured `urls` that's reachable, and is a primary
+    /// rather than a replica. The pool then fails over to another of the URLs if that one fails, see
+    /// `check_failover`.
+    pub async fn connect_failover(config: &RedisConfig) -> OcyResult<Self> {
+        let instances = config
+            .urls
+            .iter()
+            .map(|url| url.as_str().into_connection_info())
+            .collect::<RedisResult<Vec<_>>>()?;
+        let failover = Failover {
+            instances,
+            active: AtomicUsize::new(0),
+            pool_size: config.pool_size,
+            connect_timeout: config.connect_timeout.0,
+            command_timeout: config.command_timeout.0,
+            check: Notify::new(),
+        };
+
+        let active = failover.find_primary(0).await?;
+        let client = redis::Client::open(failover.instances[active].clone())?;
+        let mut pool = Self::connect(&client, config).await?;
+        info!("Connected to Redis at {}", failover.instances[active].addr);
+        failover.active.store(active, Ordering::SeqCst);
+        pool.failover = Some(Arc::new(failover));
+        Ok(pool)
+    }
+
+    /// Open all connections in the pool, retrying with exponential backoff for up to the configured startup wait if
+    /// Redis is unavailable.
+    pub async fn connect_with_retry(client: &redis::Client, config: &RedisConfig) -> OcyResult<Self> {
+        retry_at_startup(config, || Self::connect(client, config)).await
+    }
+
+    /// Open all connections in the pool to the first available of the configured `urls`, retrying with exponential
+    /// backoff for up to the configured startup wait if none are available.
+    pub async fn connect_failover_with_retry(config: &RedisConfig) -> OcyResult<Self> {
+        retry_at_startup(config, || Self::connect_failover(config)).await
+    }
+
+    /// Check that the active Redis instance is reachable and still a primary, failing over to the next one in the
+    /// configured `urls` that is if not. Each check opens a new connection, so hostnames are resolved again.
+    ///
+    /// Returns the address failed over to, if any. Does nothing for pools without a list of URLs to fail over
+    /// between.
+    pub async fn check_failover(&self) -> OcyResult<Option<String>> {
+        let failover = match &self.failover {
+            Some(failover) => failover,
+            None => return Ok(None),
+        };
+
+        let active = failover.active.load(Ordering::SeqCst);
+        let active_addr = &failover.instances[active].addr;
+        match failover.probe(active).await {
+            Ok(true) => return Ok(None),
+            Ok(false) => warn!("Redis at {} is no longer a primary, failing over", active_addr),
+            Err(err) => warn!("Redis at {} unavailable, failing over: {}", active_addr, err),
+        }
+
+        let next = failover.find_primary(active + 1).await?;
+        let client = redis::Client::open(failover.instances[next].clone())?;
+        let connections = open_connections(&client, failover.pool_size, failover.connect_timeout).await?;
+        *self.connections.write().unwrap() = connections;
+        failover.active.store(next, Ordering::SeqCst);
+
+        let next_addr = failover.instances[next].addr.to_string();
+        warn!("Redis failed over from {} to {}", active_addr, next_addr);
+        Ok(Some(next_addr))
+    }
+
+    /// Wait until a command sent via this pool fails due to a connection error. Never completes for pools without a
+    /// list of URLs to fail over between.
+    pub async fn connection_failed(&self) {
+        match &self.failover {
+            Some(failover) => failover.check.notified().await,
+            None => futures::future::pending().await,
+        }
+    }
+
+    /// Get the connection details of the Redis instance currently in use, for pools that fail over between multiple
+    /// instances.
+    pub fn active_instance(&self) -> Option<ConnectionInfo> {
+        self.failover
+            .as_ref()
+            .map(|failover| failover.instances[failover.active.load(Ordering::SeqCst)].clone())
+    }
+
+    /// Check whether this pool fails over between multiple Redis instances.
+    pub fn has_failover(&self) -> bool {
+        self.failover.is_some()
+    }
+
+    /// Record the outcome of every command sent to the primary via this pool with given circuit breaker, i.e. a
+    /// failure for connection errors and timeouts, and a success for any response from Redis. Commands sent to read
+    /// replicas aren't recorded, so that an unhealthy replica can't stop requests to a healthy primary.
+    pub fn with_circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    /// Get the next connection from the pool.
+    pub fn get(&self) -> PooledConnection {
+        let idx = self.next.fetch_add(1, Ordering::Relaxed) % self.size();
+        PooledConnection {
+            connections: self.connections.clone(),
+            idx,
+            command_timeout: self.command_timeout,
+            failover: self.failover.clone(),
+            breaker: self.breaker.clone(),
+            operation: RedisOperation::Other,
+        }
+    }
+
+    /// Get the next connection for read-only commands, which uses a read replica if any are configured, otherwise
+    /// the primary.
+    ///
+    /// Replicas may lag behind the primary, so this should only be used where slightly stale data is acceptable.
+    pub fn get_read_only(&self) -> PooledConnection {
+        let num_replicas = self.replicas.read().unwrap().len();
+        if num_replicas == 0 {
+            return self.get();
+        }
+
+        let idx = self.next_replica.fetch_add(1, Ordering::Relaxed) % num_replicas;
+        PooledConnection {
+            connections: self.replicas.clone(),
+            idx,
+            command_timeout: self.command_timeout,
+            failover: None,
+            breaker: None,
+            operation: RedisOperation::Other,
+        }
+    }
+
+    /// Get the number of connections in the pool.
+    pub fn size(&self) -> usize {
+        self.connections.read().unwrap().len()
+    }
+}
+
+impl fmt::Debug for RedisPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedisPool")
+            .field("size", &self.size())
+            .field("replicas", &self.replicas.read().unwrap().len())
+            .field("command_timeout", &self.command_timeout)
+            .field("failover", &self.failover)
+            .finish()
+    }
+}
+
+impl Failover {
+    /// Find the first instance that's reachable and a primary, starting from given index and wrapping around.
+    async fn find_primary(&self, start: usize) -> OcyResult<usize> {
+        let num_instances = self.instances.len();
+        for idx in (start..start + num_instances).map(|idx| idx % num_instances) {
+            match self.probe(idx).await {
+                Ok(true) => return Ok(idx),
+                Ok(false) => debug!("Redis at {} is a replica, skipping", self.instances[idx].addr),
+                Err(err) => debug!("Redis at {} unavailable: {}", self.instances[idx].addr, err),
+            }
+        }
+        Err(OcyError::RedisConnection(format!(
+            "no primary available among {} Redis URL(s)",
+            num_instances
+        )))
+    }
+
+    /// Check whether given instance is reachable and a primary, using a new connection.
+    ///
+    /// Instances that don't support the `ROLE` command are assumed to be primaries.
+    async fn probe(&self, idx: usize) -> OcyResult<bool> {
+        let client = redis::Client::open(self.instances[idx].clone())?;
+        let mut conn = with_timeout(self.connect_timeout, client.get_async_connection()).await?;
+        let role: RedisResult<Vec<Value>> =
+            with_timeout(self.command_timeout, redis::cmd("ROLE").query_async(&mut conn)).await;
+        match role.map_err(OcyError::from) {
+            Ok(role) => Ok(role.first() == Some(&Value::Data(b"master".to_vec()))),
+            Err(err @ OcyError::RedisConnection(_)) => Err(err),
+            Err(_) => Ok(true),
+        }
+    }
+}
+
+impl fmt::Debug for Failover {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Failover")
+            .field("instances", &self.instances.len())
+            .field("active", &self.active)
+            .finish()
+    }
+}
+
+/// Open given number of connections to Redis, failing if any can't be established within the timeout.
+async fn open_connections(
+    client: &redis::Client,
+    pool_size: usize,
+    connect_timeout: Duration,
+) -> OcyResult<Vec<ConnectionManager>> {
+    let mut connections = Vec::with_capacity(pool_size);
+    for _ in 0..pool_size {
+        connections.push(with_timeout(connect_timeout, client.get_tokio_connection_manager()).await?);
+    }
+    Ok(connections)
+}
+
+/// Attempt to connect a pool, retrying with exponential backoff for up to the configured startup wait if Redis is
+/// unavailable.
+async fn retry_at_startup<F, Fut>(config: &RedisConfig, connect: F) -> OcyResult<RedisPool>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = OcyResult<RedisPool>>,
+{
+    let deadline = Instant::now() + config.startup_wait.0;
+    let mut backoff = MIN_STARTUP_BACKOFF;
+    loop {
+        match connect().await {
+            Ok(pool) => return Ok(pool),
+            Err(err @ OcyError::RedisConnection(_)) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.as_nanos() == 0 {
+                    return Err(err);
+                }
+                let delay = backoff.min(remaining);
+                warn!(
+                    "Redis unavailable, retrying connection in {}: {}",
+                    humantime::format_duration(delay),
+                    err
+                );
+                tokio::time::delay_for(delay).await;
+                backoff = next_backoff(backoff);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Get the delay before the next connection attempt at startup, doubling the previous delay up to a maximum.
+fn next_backoff(backoff: Duration) -> Duration {
+    (backoff * 2).min(MAX_STARTUP_BACKOFF)
+}
+
+/// Connection taken from a `RedisPool`, which applies the pool's command timeout to every command sent.
+#[derive(Clone)]
+pub struct PooledConnection {
+    connections: Connections,
+    idx: usize,
+    command_timeout: Duration,
+    failover: Option<Arc<Failover>>,
+    breaker: Option<Arc<CircuitBreaker>>,
+    operation: RedisOperation,
+}
+
+impl PooledConnection {
+    /// Record commands sent via this connection in metrics under given operation, rather than as `other`.
+    pub fn for_operation(mut self, operation: RedisOperation) -> Self {
+        self.operation = operation;
+        self
+    }
+
+    /// Get the pool's current connection, which changes if the pool fails over.
+    fn conn(&self) -> ConnectionManager {
+        let connections = self.connections.read().unwrap();
+        connections[self.idx % connections.len()].clone()
+    }
+
+    /// Record given result with the circuit breaker, and trigger an immediate failover check if it's a connection
+    /// error.
+    fn check_result<T>(
+        failover: Option<Arc<Failover>>,
+        breaker: Option<Arc<CircuitBreaker>>,
+        result: RedisResult<T>,
+    ) -> RedisResult<T> {
+        let connection_failed = result.as_ref().err().is_some_and(|err| {
+            err.is_connection_dropped() || err.is_connection_refusal() || err.is_timeout() || err.is_io_error()
+        });
+        match (&breaker, connection_failed) {
+            (Some(breaker), true) => breaker.record_failure(),
+            (Some(breaker), false) => breaker.record_success(),
+            (None, _) => (),
+        }
+        if let (Some(failover), true) = (failover, connection_failed) {
+            failover.check.notify();
+        }
+        result
+    }
+}
+
+impl ConnectionLike for PooledConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        let timeout = self.command_timeout;
+        let failover = self.failover.clone();
+        let breaker = self.breaker.clone();
+        let operation = self.operation;
+        let mut conn = self.conn();
+        async move {
+            let started = Instant::now();
+            #[cfg(feature = "testing")]
+            if let Some(latency) = super::chaos::redis_latency() {
+                actix_rt::time::delay_for(latency).await;
+            }
+            let result = with_timeout(timeout, conn.req_packed_command(cmd)).await;
+            let name = command_name(cmd);
+            slowlog::record_command(&name, started.elapsed());
+            METRICS.record_redis_command(operation, &name, started.elapsed());
+            HEARTBEAT_TOLERANCE.record_redis_command(started.elapsed());
+            Self::check_result(failover, breaker, result)
+        }
+        .boxed()
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        let timeout = self.command_timeout;
+        let failover = self.failover.clone();
+        let breaker = self.breaker.clone();
+        let operation = self.operation;
+        let mut conn = self.conn();
+        async move {
+            let started = Instant::now();
+            #[cfg(feature = "testing")]
+            if let Some(latency) = super::chaos::redis_latency() {
+                actix_rt::time::delay_for(latency).await;
+            }
+            let result = with_timeout(timeout, conn.req_packed_commands(cmd, offset, count)).await;
+            slowlog::record_command("PIPELINE", started.elapsed());
+            METRICS.record_redis_command(operation, "PIPELINE", started.elapsed());
+            HEARTBEAT_TOLERANCE.record_redis_command(started.elapsed());
+            Self::check_result(failover, breaker, result)
+        }
+        .boxed()
+    }
+
+    fn get_db(&self) -> i64 {
+        self.conn().get_db()
+    }
+}
+
+impl fmt::Debug for PooledConnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PooledConnection")
+            .field("command_timeout", &self.command_timeout)
+            .field("operation", &self.operation)
+            .finish()
+    }
+}
+
+/// Get the name of given command, e.g. "GET" or "EVALSHA".
+fn command_name(cmd: &Cmd) -> String {
+    match cmd.args_iter().next() {
+        Some(Arg::Simple(name)) => String::from_utf8_lossy(name).to_uppercase(),
+        _ => "UNKNOWN".to_owned(),
+    }
+}
+
+/// Wait for given Redis future to complete, or fail with a timeout error. A timeout of 0 waits indefinitely.
+async fn with_timeout<T, F>(timeout: Duration, fut: F) -> RedisResult<T>
+where
+    F: std::future::Future<Output = RedisResult<T>>,
+{
+    if timeout.as_nanos() == 0 {
+        return fut.await;
+    }
+
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(result) => result,
+        Err(_) => {
+            let msg = format!("no response from Redis within {}", humantime::format_duration(timeout));
+            Err(RedisError::from(io::Error::new(io::ErrorKind::TimedOut, msg)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let mut backoff = MIN_STARTUP_BACKOFF;
+        let mut delays = Vec::new();
+        for _ in 0..8 {
+            delays.push(backoff.as_millis());
+            backoff = next_backoff(backoff);
+        }
+        assert_eq!(delays, vec![250, 500, 1000, 2000, 4000, 8000, 10_000, 10_000]);
+    }
+
+    #[test]
+    fn command_names() {
+        assert_eq!(command_name(redis::cmd("get").arg("key")), "GET");
+        assert_eq!(command_name(&redis::cmd("EVALSHA")), "EVALSHA");
+        assert_eq!(command_name(&Cmd::new()), "UNKNOWN");
+    }
+}
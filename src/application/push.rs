@@ -0,0 +1,231 @@
+//! Delivers jobs to workers that have registered a callback URL for a queue, rather than waiting for them to poll.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use actix_web::client::Client;
+use futures::stream::{self, StreamExt};
+use log::{debug, warn};
+
+use crate::application::pool::PooledConnection;
+use crate::application::RedisManager;
+use crate::events::{EventBus, EventKind};
+use crate::models::{job, OcyResult};
+
+/// Initial delay between delivery attempts, doubled after each failed attempt.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Initial time pushing to a queue is suspended for after failing to deliver a job to its callback URL, doubled after
+/// each consecutive failure.
+const SUSPEND_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Maximum time pushing to a queue is suspended for, however many consecutive failures its callback URL has had.
+const MAX_SUSPENSION: Duration = Duration::from_secs(300);
+
+/// Tracks queues whose callback URLs are failing, so that pushing to them is suspended for a while rather than
+/// retried on every check, leaving their jobs for workers polling the queue in the meantime.
+#[derive(Debug, Default)]
+pub struct Suspensions {
+    queues: HashMap<String, Suspension>,
+}
+
+#[derive(Debug)]
+struct Suspension {
+    url: String,
+    failures: u32,
+    until: Instant,
+}
+
+impl Suspensions {
+    /// Check whether pushing to given queue's callback URL is currently suspended. Registering a different URL for a
+    /// queue lifts its suspension.
+    fn is_suspended(&self, queue_name: &str, url: &str, now: Instant) -> bool {
+        self.queues
+            .get(queue_name)
+            .is_some_and(|suspension| suspension.url == url && suspension.until > now)
+    }
+
+    /// Record a failed delivery to given queue's callback URL, returning how long pushing to it is suspended for.
+    fn record_failure(&mut self, queue_name: &str, url: &str, now: Instant) -> Duration {
+        let suspension = self.queues.entry(queue_name.to_owned()).or_insert_with(|| Suspension {
+            url: url.to_owned(),
+            failures: 0,
+            until: now,
+        });
+        if suspension.url != url {
+            suspension.url = url.to_owned();
+            suspension.failures = 0;
+        }
+        let delay = SUSPEND_BACKOFF
+            .checked_mul(1 << suspension.failures.min(16))
+            .map_or(MAX_SUSPENSION, |delay| delay.min(MAX_SUSPENSION));
+        suspension.failures += 1;
+        suspension.until = now + delay;
+        delay
+    }
+
+    /// Record a successful delivery to given queue's callback URL, lifting any suspension.
+    fn record_success(&mut self, queue_name: &str) {
+        self.queues.remove(queue_name);
+    }
+
+    /// Forget suspensions of queues that no longer have a callback URL.
+    fn retain(&mut self, callbacks: &[(String, String)]) {
+        self.queues
+            .retain(|queue_name, _| callbacks.iter().any(|(name, _)| name == queue_name));
+    }
+}
+
+/// Push all queued jobs to their queue's callback URL, if one is registered.
+///
+/// Queues are pushed to concurrently, up to `concurrency` at once, while jobs on each queue are delivered one at a
+/// time. Delivery is at-least-once: jobs are marked as running before being sent, and are only released back to their
+/// queue if every attempt fails. In that case, pushing to that queue is suspended for a while, backing off further
+/// after each consecutive failure, leaving queued jobs available for workers to poll for.
+///
+/// Returns IDs of all jobs successfully delivered.
+pub async fn push_jobs(
+    conn: &PooledConnection,
+    client: &Client,
+    retries: u64,
+    concurrency: usize,
+    suspensions: &mut Suspensions,
+    events: &EventBus,
+) -> OcyResult<Vec<u64>> {
+    let now = Instant::now();
+    let callbacks = RedisManager::queue_callbacks(&mut conn.clone()).await?;
+    suspensions.retain(&callbacks);
+
+    let due: Vec<(String, String)> = callbacks
+        .into_iter()
+        .filter(|(queue_name, url)| !suspensions.is_suspended(queue_name, url, now))
+        .collect();
+    let results: Vec<_> = stream::iter(due)
+        .map(|(queue_name, url)| {
+            let mut conn = conn.clone();
+            async move {
+                let res = push_queue(&mut conn, client, &queue_name, &url, retries, events).await;
+                (queue_name, url, res)
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut delivered = Vec::new();
+    let mut first_err = None;
+    for (queue_name, url, res) in results {
+        match res {
+            Ok((job_ids, true)) => {
+                delivered.extend(job_ids);
+                let delay = suspensions.record_failure(&queue_name, &url, now);
+                warn!(
+                    "[queue:{}] suspending push delivery to {} for {}",
+                    &queue_name, &url, humantime::format_duration(delay)
+                );
+            }
+            Ok((job_ids, false)) => {
+                if !job_ids.is_empty() {
+                    suspensions.record_success(&queue_name);
+                }
+                delivered.extend(job_ids);
+            }
+            Err(err) => {
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+
+    match first_err {
+        Some(err) => Err(err),
+        None => Ok(delivered),
+    }
+}
+
+/// Push all queued jobs on given queue to its callback URL, stopping at the first job that can't be delivered.
+///
+/// Returns IDs of jobs successfully delivered, and whether delivery failed.
+async fn push_queue(
+    conn: &mut PooledConnection,
+    client: &Client,
+    queue_name: &str,
+    url: &str,
+    retries: u64,
+    events: &EventBus,
+) -> OcyResult<(Vec<u64>, bool)> {
+    let mut delivered = Vec::new();
+    while let Some(payload) = RedisManager::next_queued_job(conn, queue_name).await? {
+        match deliver(client, url, &payload, retries).await {
+            Ok(()) => {
+                debug!("[queue:{}] pushed job {} to {}", queue_name, payload.id(), url);
+                events.job_event(EventKind::Started, payload.id(), Some(queue_name));
+                delivered.push(payload.id());
+            }
+            Err(err) => {
+                warn!(
+                    "[queue:{}] failed to push job {} to {}, falling back to polling: {}",
+                    queue_name, payload.id(), url, err
+                );
+                RedisManager::release_job(conn, payload.id()).await?;
+                return Ok((delivered, true));
+            }
+        }
+    }
+    Ok((delivered, false))
+}
+
+/// POST given job payload to a callback URL, retrying with exponential backoff until it responds with a 2xx status.
+async fn deliver(client: &Client, url: &str, payload: &job::Payload, retries: u64) -> Result<(), String> {
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        let err = match client.post(url).send_json(payload).await {
+            Ok(res) if res.status().is_success() => return Ok(()),
+            Ok(res) => format!("callback responded with {}", res.status()),
+            Err(err) => err.to_string(),
+        };
+
+        if attempt >= retries {
+            return Err(err);
+        }
+        attempt += 1;
+        debug!("Push of job {} to {} failed, retrying in {:?}: {}", payload.id(), url, backoff, err);
+        tokio::time::delay_for(backoff).await;
+        backoff *= 2;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suspension_backoff() {
+        let now = Instant::now();
+        let mut suspensions = Suspensions::default();
+        assert!(!suspensions.is_suspended("a", "http://a", now));
+
+        assert_eq!(suspensions.record_failure("a", "http://a", now), Duration::from_secs(1));
+        assert!(suspensions.is_suspended("a", "http://a", now));
+        assert!(!suspensions.is_suspended("a", "http://a", now + Duration::from_secs(1)));
+        assert!(!suspensions.is_suspended("a", "http://other", now));
+        assert!(!suspensions.is_suspended("b", "http://a", now));
+
+        assert_eq!(suspensions.record_failure("a", "http://a", now), Duration::from_secs(2));
+        assert_eq!(suspensions.record_failure("a", "http://a", now), Duration::from_secs(4));
+        for _ in 0..20 {
+            suspensions.record_failure("a", "http://a", now);
+        }
+        assert_eq!(suspensions.record_failure("a", "http://a", now), MAX_SUSPENSION);
+
+        // a new URL starts backing off from scratch
+        assert_eq!(suspensions.record_failure("a", "http://other", now), Duration::from_secs(1));
+
+        suspensions.record_success("a");
+        assert!(!suspensions.is_suspended("a", "http://other", now));
+
+        suspensions.record_failure("b", "http://b", now);
+        suspensions.retain(&[("a".to_owned(), "http://a".to_owned())]);
+        assert!(!suspensions.is_suspended("b", "http://b", now));
+    }
+}
@@ -2,14 +2,158 @@
 
 use std::collections::HashMap;
 
-use log::{debug, info};
+use log::{debug, info, warn};
 use redis::{aio::ConnectionLike, AsyncCommands, RedisResult};
 
-use super::{keys, RedisJob, RedisTag};
-use crate::models::{job, queue, OcyError, OcyResult};
+use super::job::record_storage_in_pipe;
+use super::{clock, keys, limits, offload, system, RedisJob, RedisTag};
+use crate::config::StickySessionsConfig;
+use crate::models::{job, queue, DateTime, Duration, OcyError, OcyResult};
 use crate::redis_utils::vec_from_redis_pipe;
 use crate::transaction_async;
 
+/// Maximum number of jobs that can be sampled from a queue at once.
+pub const MAX_SAMPLE_SIZE: usize = 100;
+
+/// Fields of a queue's hash that make up its settings.
+const SETTINGS_FIELDS: &[queue::Field] = &[
+    queue::Field::Timeout,
+    queue::Field::HeartbeatTimeout,
+    queue::Field::ExpiresAfter,
+    queue::Field::Retries,
+    queue::Field::RetryDelays,
+    queue::Field::QuarantineAfter,
+    queue::Field::QuickFailWindow,
+    queue::Field::ExpiryCheckStatuses,
+    queue::Field::ExpiryCheckInterval,
+    queue::Field::TimeoutCheckInterval,
+    queue::Field::RetryCheckInterval,
+    queue::Field::Sla,
+    queue::Field::MaxInputSize,
+    queue::Field::MaxOutputSize,
+    queue::Field::RetryBudget,
+    queue::Field::RetryBudgetCooldown,
+    queue::Field::ShadowTo,
+    queue::Field::ShadowPercent,
+    queue::Field::OutputSizePolicy,
+    queue::Field::AllowedTransitions,
+    queue::Field::TotalTimeout,
+    queue::Field::ResultTtl,
+    queue::Field::MaxQueueAge,
+];
+
+/// Counts a retry against a queue's retry budget, pausing the queue if the budget is exceeded.
+///
+/// Retries are counted in a window starting from the first retry, lasting for a minute.
+const SPEND_RETRY_BUDGET_SCRIPT: &str = r#"
+local count = redis.call("incr", KEYS[1])
+if count == 1 then
+    redis.call("expire", KEYS[1], 60)
+end
+if count <= tonumber(ARGV[1]) then
+    return 1
+end
+redis.call("del", KEYS[1])
+redis.call("hset", KEYS[2], ARGV[2], ARGV[3])
+return 0
+"#;
+
+/// Reads a queue's settings and all of its jobs atomically, so that the result is a consistent snapshot.
+///
+/// Jobs still in the queue are always included, while jobs in the lists shared by all queues (e.g. running, ended)
+/// are only included if they belong to the queue. Returns nil if the queue doesn't exist.
+const SNAPSHOT_SCRIPT: &str = r#"
+if redis.call("exists", KEYS[1]) == 0 then
+    return nil
+end
+local num_settings = tonumber(ARGV[4])
+local settings = redis.call("hmget", KEYS[1], unpack(ARGV, 5, 4 + num_settings))
+local job_fields = {unpack(ARGV, 5 + num_settings)}
+local jobs = {}
+for i = 2, #KEYS do
+    for _, job_id in ipairs(redis.call("lrange", KEYS[i], 0, -1)) do
+        local job_key = ARGV[2] .. job_id
+        if i == 2 or redis.call("hget", job_key, ARGV[3]) == ARGV[1] then
+            table.insert(jobs, redis.call("hmget", job_key, unpack(job_fields)))
+        end
+    end
+end
+return {settings, jobs}
+"#;
+
+/// Moves the next job of a session that has no running job to limbo, preferring sessions whose last job was taken by
+/// the given worker (empty if unknown). Sessions last handled by another worker are skipped while that worker is still
+/// asking for jobs. Returns the job ID and session key, or nil if no session's jobs can be taken.
+const TAKE_SESSION_JOB_SCRIPT: &str = r#"
+local now = tonumber(ARGV[6])
+redis.call("zremrangebyscore", KEYS[2], "-inf", now - tonumber(ARGV[7]))
+if ARGV[5] ~= "" then
+    redis.call("zadd", KEYS[2], now, ARGV[5])
+end
+local function take(session)
+    local list_key = ARGV[1] .. session .. ARGV[2]
+    local job_id = redis.call("rpoplpush", list_key, KEYS[3])
+    if redis.call("llen", list_key) == 0 then
+        redis.call("srem", KEYS[1], session)
+    end
+    if not job_id then
+        return false
+    end
+    local state_key = ARGV[1] .. session
+    redis.call("hset", state_key, "job_id", job_id)
+    if ARGV[5] ~= "" then
+        redis.call("hset", state_key, "worker", ARGV[5])
+    else
+        redis.call("hdel", state_key, "worker")
+    end
+    redis.call("expire", state_key, ARGV[8])
+    return {job_id, session}
+end
+local available = {}
+for _, session in ipairs(redis.call("sort", KEYS[1], "alpha")) do
+    local state = redis.call("hmget", ARGV[1] .. session, "job_id", "worker")
+    local running = state[1] and redis.call("hget", ARGV[3] .. state[1], ARGV[4]) == ARGV[9]
+    if not running then
+        local worker = state[2]
+        if worker and worker == ARGV[5] then
+            local taken = take(session)
+            if taken then
+                return taken
+            end
+        elseif not worker or not redis.call("zscore", KEYS[2], worker) then
+            table.insert(available, session)
+        end
+    end
+end
+for _, session in ipairs(available) do
+    local taken = take(session)
+    if taken then
+        return taken
+    end
+end
+return false
+"#;
+
+/// Moves the next job with a serialization key whose previous job isn't running to limbo, recording it as the key's
+/// last started job. Returns the job ID and serialization key, or nil if no serialized jobs can be taken.
+const TAKE_SERIALIZED_JOB_SCRIPT: &str = r#"
+for _, serial_key in ipairs(redis.call("sort", KEYS[1], "alpha")) do
+    local last_job_id = redis.call("hget", KEYS[2], serial_key)
+    if not last_job_id or redis.call("hget", ARGV[3] .. last_job_id, ARGV[4]) ~= ARGV[5] then
+        local list_key = ARGV[1] .. serial_key .. ARGV[2]
+        local job_id = redis.call("rpoplpush", list_key, KEYS[3])
+        if redis.call("llen", list_key) == 0 then
+            redis.call("srem", KEYS[1], serial_key)
+        end
+        if job_id then
+            redis.call("hset", KEYS[2], serial_key, job_id)
+            return {job_id, serial_key}
+        end
+    end
+end
+return false
+"#;
+
 /// Interface to a queue in Redis. This consists of a list containing queued jobs, and a hash containing queue settings.
 ///
 /// Primarily used by RedisManager as a wrapper around some queue information.
@@ -29,7 +173,7 @@ impl RedisQueue {
     /// Get a new RedisQueue struct, ensuring its name is valid.
     pub fn from_string<S: Into<String>>(name: S) -> OcyResult<Self> {
         let name = name.into();
-        if Self::is_valid_name(&name) {
+        if Self::is_valid_name(&name) || Self::is_valid_system_name(&name) {
             let key = Self::build_key(&name);
             let jobs_key = Self::build_jobs_key(&name);
             Ok(Self {
@@ -38,24 +182,218 @@ impl RedisQueue {
                 jobs_key,
             })
         } else {
-            Err(OcyError::bad_request( "Invalid queue name, valid characters: a-zA-Z0-9_.-"))
+            Err(OcyError::bad_request(limits::get().invalid_name_message("queue name")))
         }
     }
 
     /// Get key for this queue's jobs list in Redis.
     ///
-    /// This contains queued job IDs.
+    /// This contains queued job IDs, other than those of jobs with a routing or session key, which are kept in separate
+    /// lists.
     pub fn jobs_key(&self) -> &str {
         &self.jobs_key
     }
 
-    // TODO: this list could probably be expanded a bit
-    /// Validate queue name, allowed chars for names are: [a-zA-Z0-9_.-].
+    /// Get key for the list of this queue's queued jobs with given routing key, or without one.
+    pub fn routed_jobs_key(&self, routing_key: Option<&str>) -> String {
+        Self::build_routed_jobs_key(&self.name, routing_key)
+    }
+
+    /// Get key for the set of routing keys this queue's jobs have been given.
+    pub fn routing_keys_key(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_ROUTING_KEYS_SUFFIX)
+    }
+
+    /// Get key for the list of this queue's queued jobs with given session key.
+    pub fn session_jobs_key(&self, session_key: &str) -> String {
+        format!("{}{}{}", self.session_key_prefix(), session_key, keys::QUEUE_JOBS_SUFFIX)
+    }
+
+    /// Get key for the hash recording the worker and ID of the last started job of given session.
+    pub fn session_state_key(&self, session_key: &str) -> String {
+        format!("{}{}", self.session_key_prefix(), session_key)
+    }
+
+    /// Get the prefix of keys for this queue's sessions.
+    fn session_key_prefix(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_SESSION_INFIX)
+    }
+
+    /// Get key for the set of this queue's sessions with queued jobs.
+    pub fn sessions_key(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_SESSIONS_SUFFIX)
+    }
+
+    /// Get key for the list of this queue's queued jobs with given serialization key.
+    pub fn serialized_jobs_key(&self, serialization_key: &str) -> String {
+        format!("{}{}{}", self.serial_key_prefix(), serialization_key, keys::QUEUE_JOBS_SUFFIX)
+    }
+
+    /// Get the prefix of keys for lists of this queue's jobs with a serialization key.
+    fn serial_key_prefix(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_SERIAL_INFIX)
+    }
+
+    /// Get key for the set of this queue's serialization keys with queued jobs.
+    pub fn serial_keys_key(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_SERIAL_KEYS_SUFFIX)
+    }
+
+    /// Get key for the hash of the ID of the last started job with each of this queue's serialization keys.
+    pub fn serial_jobs_key(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_SERIAL_JOBS_SUFFIX)
+    }
+
+    /// Get key for the hash of the ID of the latest job created on this queue with each unique key.
+    pub fn unique_jobs_key(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_UNIQUE_JOBS_SUFFIX)
+    }
+
+    /// Get key for the hash of the ID of the latest completed job on this queue with each unique key.
+    pub fn results_key(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_RESULTS_SUFFIX)
+    }
+
+    /// Get key for the sorted set of workers that have recently asked for jobs from this queue.
+    pub fn workers_key(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_WORKERS_SUFFIX)
+    }
+
+    /// Get key for the list given queued job is kept in.
+    pub fn queue_list_key(&self, list: &job::QueueList) -> String {
+        match (&list.session_key, &list.serialization_key) {
+            (Some(session_key), _) => self.session_jobs_key(session_key),
+            (None, Some(serialization_key)) => self.serialized_jobs_key(serialization_key),
+            (None, None) => self.routed_jobs_key(list.routing_key.as_deref()),
+        }
+    }
+
+    /// Add commands to a pipeline to record that this queue has jobs in given list, so that they're included when
+    /// counting or listing its queued jobs, and so that jobs with a session or serialization key can be found by
+    /// workers.
+    pub fn add_queue_list_in_pipe<'b>(
+        &self,
+        pipe: &'b mut redis::Pipeline,
+        list: &job::QueueList,
+    ) -> &'b mut redis::Pipeline {
+        if let Some(ref routing_key) = list.routing_key {
+            pipe.sadd(self.routing_keys_key(), routing_key).ignore();
+        }
+        if let Some(ref session_key) = list.session_key {
+            pipe.sadd(self.sessions_key(), session_key).ignore();
+        }
+        if let Some(ref serialization_key) = list.serialization_key {
+            pipe.sadd(self.serial_keys_key(), serialization_key).ignore();
+        }
+        pipe
+    }
+
+    /// Get the keys of all lists of this queue's queued jobs, starting with the list of jobs without a routing key,
+    /// followed by those of each routing key in order, then those of each session and serialization key with queued
+    /// jobs in order.
+    pub async fn queued_keys<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<Vec<String>> {
+        let (mut routing_keys, mut session_keys, mut serial_keys): (Vec<String>, Vec<String>, Vec<String>) =
+            redis::pipe()
+                .smembers(self.routing_keys_key())
+                .smembers(self.sessions_key())
+                .smembers(self.serial_keys_key())
+                .query_async(conn)
+                .await?;
+        routing_keys.sort();
+        session_keys.sort();
+        serial_keys.sort();
+        let mut queued_keys = vec![self.jobs_key.clone()];
+        queued_keys.extend(routing_keys.iter().map(|routing_key| self.routed_jobs_key(Some(routing_key))));
+        queued_keys.extend(session_keys.iter().map(|session_key| self.session_jobs_key(session_key)));
+        queued_keys.extend(serial_keys.iter().map(|serial_key| self.serialized_jobs_key(serial_key)));
+        Ok(queued_keys)
+    }
+
+    /// Move the next job of one of this queue's sessions to limbo, if any session without a running job has queued
+    /// jobs, recording that it was taken by given worker.
+    ///
+    /// Sessions whose last job was taken by the given worker are preferred, while sessions last handled by another
+    /// worker that has asked for a job from this queue within the configured `worker_timeout` are skipped.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the job taken and the key of its session, or `None` if no session's jobs can be taken.
+    pub async fn take_session_job<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        worker_id: Option<&str>,
+        config: &StickySessionsConfig,
+    ) -> OcyResult<Option<(u64, String)>> {
+        Ok(redis::Script::new(TAKE_SESSION_JOB_SCRIPT)
+            .key(self.sessions_key())
+            .key(self.workers_key())
+            .key(keys::LIMBO_KEY)
+            .arg(self.session_key_prefix())
+            .arg(keys::QUEUE_JOBS_SUFFIX)
+            .arg(keys::JOB_PREFIX)
+            .arg(job::Field::Status)
+            .arg(worker_id.unwrap_or_default())
+            .arg(clock::now().timestamp())
+            .arg(config.worker_timeout.as_secs())
+            .arg(config.session_expiry.as_secs().max(1))
+            .arg(job::Status::Running)
+            .invoke_async(conn)
+            .await?)
+    }
+
+    /// Move the next job with a serialization key to limbo, if the previous job with that key isn't running.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the job taken and its serialization key, or `None` if no serialized jobs can be taken.
+    pub async fn take_serialized_job<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+    ) -> OcyResult<Option<(u64, String)>> {
+        Ok(redis::Script::new(TAKE_SERIALIZED_JOB_SCRIPT)
+            .key(self.serial_keys_key())
+            .key(self.serial_jobs_key())
+            .key(keys::LIMBO_KEY)
+            .arg(self.serial_key_prefix())
+            .arg(keys::QUEUE_JOBS_SUFFIX)
+            .arg(keys::JOB_PREFIX)
+            .arg(job::Field::Status)
+            .arg(job::Status::Running)
+            .invoke_async(conn)
+            .await?)
+    }
+
+    /// Validate serialization key, allowed chars are the same as for queue names.
+    pub fn is_valid_serialization_key(serialization_key: &str) -> bool {
+        Self::is_valid_name(serialization_key)
+    }
+
+    /// Validate session key, allowed chars are the same as for queue names.
+    pub fn is_valid_session_key(session_key: &str) -> bool {
+        Self::is_valid_name(session_key)
+    }
+
+    /// Validate routing key, allowed chars are the same as for queue names.
+    pub fn is_valid_routing_key(routing_key: &str) -> bool {
+        Self::is_valid_name(routing_key)
+    }
+
+    /// Validate queue name, allowed chars for names are: [a-zA-Z0-9_.-] unless configured otherwise in `[limits]`.
     pub fn is_valid_name(name: &str) -> bool {
-        !name.is_empty()
-            && name
-                .chars()
-                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+        limits::get().is_valid_name(name)
+    }
+
+    /// Validate system queue name, which must be the system queue prefix followed by a valid queue name.
+    fn is_valid_system_name(name: &str) -> bool {
+        name.strip_prefix(system::SYSTEM_QUEUE_PREFIX).is_some_and(Self::is_valid_name)
+    }
+
+    /// Validate callback URL, only absolute HTTP(S) URLs are allowed.
+    pub fn is_valid_callback_url(url: &str) -> bool {
+        match url.parse::<actix_web::http::Uri>() {
+            Ok(uri) => matches!(uri.scheme_str(), Some("http") | Some("https")) && uri.host().is_some(),
+            Err(_) => false,
+        }
     }
 
     /// Create a new queue with given settings, or update settings for an existing queue.
@@ -67,11 +405,100 @@ impl RedisQueue {
         settings: &queue::Settings,
     ) -> OcyResult<bool> {
         debug!("[{}] writing settings: {:?}", &self.key, settings);
+        self.check_settings(settings)?;
 
         let mut pipeline = redis::pipe();
+        let (is_new,): (bool,) = self
+            .write_settings_in_pipe(pipeline.atomic(), settings)
+            .query_async(conn)
+            .await?;
+
+        // all fields are mandatory, so if 1st is updated, this is a new entry
+        info!(
+            "[{}] {}",
+            &self.key,
+            if is_new { "created" } else { "updated" }
+        );
+        Ok(is_new)
+    }
+
+    /// Create this queue with given settings, failing if it already exists.
+    pub async fn create<C: ConnectionLike + Send>(&self, conn: &mut C, settings: &queue::Settings) -> OcyResult<()> {
+        debug!("[{}] creating with settings: {:?}", &self.key, settings);
+        self.check_settings(settings)?;
 
+        transaction_async!(conn, &[&self.key], {
+            if self.exists(conn).await? {
+                return Err(OcyError::conflict(format!("Queue {} already exists", self.name)));
+            }
+
+            let result: Option<(bool,)> = self
+                .write_settings_in_pipe(redis::pipe().atomic(), settings)
+                .query_async(conn)
+                .await?;
+            result.map(|_| ())
+        });
+
+        info!("[{}] created", &self.key);
+        Ok(())
+    }
+
+    /// Update only the given settings of this existing queue, leaving any others unchanged.
+    ///
+    /// Returns the queue's settings after the update has been applied.
+    pub async fn update<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        update: &queue::SettingsUpdate,
+    ) -> OcyResult<queue::Settings> {
+        debug!("[{}] updating settings: {:?}", &self.key, update);
+
+        let settings: queue::Settings = transaction_async!(conn, &[&self.key], {
+            if !self.exists(conn).await? {
+                return Err(OcyError::NoSuchQueue(self.name.clone()));
+            }
+
+            let mut settings = self.settings(conn).await?;
+            update.apply(&mut settings);
+            self.check_settings(&settings)?;
+            let result: Option<(bool,)> = self
+                .write_settings_in_pipe(redis::pipe().atomic(), &settings)
+                .query_async(conn)
+                .await?;
+            result.map(|_| settings)
+        });
+
+        info!("[{}] updated", &self.key);
+        Ok(settings)
+    }
+
+    /// Check that given settings are valid for this queue.
+    fn check_settings(&self, settings: &queue::Settings) -> OcyResult<()> {
+        if let Some(ref shadow_to) = settings.shadow_to {
+            if !Self::is_valid_name(shadow_to) {
+                return Err(OcyError::bad_request(limits::get().invalid_name_message("shadow_to queue name")));
+            }
+        }
+        if settings.output_size_policy == queue::OutputSizePolicy::Offload && !offload::is_enabled() {
+            return Err(OcyError::bad_request(
+                "output_size_policy can't be \"offload\", no output_offload_dir is configured",
+            ));
+        }
+        if let Some(ref transitions) = settings.allowed_transitions {
+            job::check_allowed_transitions(transitions)?;
+        }
+        settings.check_shadow(&self.name)
+    }
+
+    /// Add commands to write all of given settings for this queue to a pipeline.
+    ///
+    /// The first command's result indicates whether this is a new queue, all others are ignored.
+    fn write_settings_in_pipe<'b>(
+        &self,
+        pipeline: &'b mut redis::Pipeline,
+        settings: &queue::Settings,
+    ) -> &'b mut redis::Pipeline {
         let pipe = pipeline
-            .atomic()
             .hset(&self.key, queue::Field::Timeout, &settings.timeout)
             .hset(
                 &self.key,
@@ -87,6 +514,18 @@ impl RedisQueue {
             .ignore()
             .hset(&self.key, queue::Field::Retries, settings.retries)
             .ignore()
+            .hset(&self.key, queue::Field::QuarantineAfter, settings.quarantine_after)
+            .ignore()
+            .hset(&self.key, queue::Field::QuickFailWindow, &settings.quick_fail_window)
+            .ignore()
+            .hset(&self.key, queue::Field::RetryBudgetCooldown, &settings.retry_budget_cooldown)
+            .ignore()
+            .hset(&self.key, queue::Field::ShadowPercent, settings.shadow_percent)
+            .ignore()
+            .hset(&self.key, queue::Field::OutputSizePolicy, settings.output_size_policy)
+            .ignore()
+            .hset(&self.key, queue::Field::ResultTtl, &settings.result_ttl)
+            .ignore()
             .sadd(keys::QUEUES_KEY, &self.name)
             .ignore();
 
@@ -102,47 +541,139 @@ impl RedisQueue {
             .ignore();
         }
 
-        let (is_new,): (bool,) = pipe.query_async(conn).await?;
+        match settings.expiry_check_statuses {
+            Some(ref statuses) => {
+                let statuses_json = serde_json::to_string(statuses).unwrap();
+                pipe.hset(&self.key, queue::Field::ExpiryCheckStatuses, statuses_json)
+                    .ignore()
+            }
+            None => pipe.hdel(&self.key, queue::Field::ExpiryCheckStatuses).ignore(),
+        };
 
-        // all fields are mandatory, so if 1st is updated, this is a new entry
-        info!(
-            "[{}] {}",
-            &self.key,
-            if is_new { "created" } else { "updated" }
-        );
-        Ok(is_new)
+        match settings.expiry_check_interval {
+            Some(ref interval) => pipe.hset(&self.key, queue::Field::ExpiryCheckInterval, interval).ignore(),
+            None => pipe.hdel(&self.key, queue::Field::ExpiryCheckInterval).ignore(),
+        };
+
+        match settings.timeout_check_interval {
+            Some(ref interval) => pipe.hset(&self.key, queue::Field::TimeoutCheckInterval, interval).ignore(),
+            None => pipe.hdel(&self.key, queue::Field::TimeoutCheckInterval).ignore(),
+        };
+
+        match settings.retry_check_interval {
+            Some(ref interval) => pipe.hset(&self.key, queue::Field::RetryCheckInterval, interval).ignore(),
+            None => pipe.hdel(&self.key, queue::Field::RetryCheckInterval).ignore(),
+        };
+
+        match settings.sla {
+            Some(ref sla) => pipe.hset(&self.key, queue::Field::Sla, sla).ignore(),
+            None => pipe.hdel(&self.key, queue::Field::Sla).ignore(),
+        };
+
+        match settings.total_timeout {
+            Some(ref total_timeout) => pipe.hset(&self.key, queue::Field::TotalTimeout, total_timeout).ignore(),
+            None => pipe.hdel(&self.key, queue::Field::TotalTimeout).ignore(),
+        };
+
+        match settings.max_queue_age {
+            Some(ref max_queue_age) => pipe.hset(&self.key, queue::Field::MaxQueueAge, max_queue_age).ignore(),
+            None => pipe.hdel(&self.key, queue::Field::MaxQueueAge).ignore(),
+        };
+
+        match settings.max_input_size {
+            Some(size) => pipe.hset(&self.key, queue::Field::MaxInputSize, size).ignore(),
+            None => pipe.hdel(&self.key, queue::Field::MaxInputSize).ignore(),
+        };
+
+        match settings.max_output_size {
+            Some(size) => pipe.hset(&self.key, queue::Field::MaxOutputSize, size).ignore(),
+            None => pipe.hdel(&self.key, queue::Field::MaxOutputSize).ignore(),
+        };
+
+        match settings.allowed_transitions {
+            Some(ref transitions) => {
+                let transitions_json = serde_json::to_string(transitions).unwrap();
+                pipe.hset(&self.key, queue::Field::AllowedTransitions, transitions_json).ignore()
+            }
+            None => pipe.hdel(&self.key, queue::Field::AllowedTransitions).ignore(),
+        };
+
+        match settings.retry_budget {
+            Some(budget) => pipe.hset(&self.key, queue::Field::RetryBudget, budget).ignore(),
+            None => pipe.hdel(&self.key, queue::Field::RetryBudget).ignore(),
+        };
+
+        match settings.shadow_to {
+            Some(ref shadow_to) => pipe.hset(&self.key, queue::Field::ShadowTo, shadow_to).ignore(),
+            None => pipe.hdel(&self.key, queue::Field::ShadowTo).ignore(),
+        };
+
+        pipe
     }
 
     /// Delete an existing queue, if it exists.
     ///
-    /// Deletes any jobs currently in the queue, but won't affected any running/completed jobs.
-    ///
-    /// Returns true if a queue was deleted, false otherwise.
-    pub async fn delete<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<bool> {
+    /// Deletes any jobs currently in the queue, but won't affected any running/completed jobs. Queues with queued
+    /// jobs are only deleted if `force` is true.
+    pub async fn delete<C: ConnectionLike + Send>(&self, conn: &mut C, force: bool) -> OcyResult<queue::Deletion> {
         debug!("Deleting queue '{}'", self.name);
-        let (queue_deleted, num_jobs_deleted): (bool, usize) =
-            transaction_async!(conn, &[&self.jobs_key], {
+        let queued_keys = self.queued_keys(conn).await?;
+        let mut watch_keys = queued_keys.clone();
+        watch_keys.extend(vec![self.routing_keys_key(), self.sessions_key(), self.serial_keys_key()]);
+        let deletion: queue::Deletion =
+            transaction_async!(conn, &watch_keys[..], {
+                let mut pipe = redis::pipe();
+                for key in &queued_keys {
+                    pipe.llen(key);
+                }
+                let num_queued: u64 = vec_from_redis_pipe::<C, u64>(conn, &pipe).await?.into_iter().sum();
+
                 // if queue has already been deleted, nothing to do
                 if !self.exists(conn).await? {
-                    Some((false, 0))
+                    Some(queue::Deletion::NotFound)
+                } else if !force && num_queued > 0 {
+                    Some(queue::Deletion::NotEmpty(queue::DeletedJobs { queued: num_queued }))
                 } else {
                     let mut keys_to_del: Vec<String> =
-                        vec![self.key.to_owned(), self.jobs_key.to_owned()];
+                        vec![self.key.to_owned(), self.retry_count_key(), self.starts_key(), self.workers_key()];
+                    keys_to_del.push(self.runs_key());
+                    keys_to_del.push(self.serial_jobs_key());
+                    keys_to_del.push(self.unique_jobs_key());
+                    keys_to_del.push(self.results_key());
+                    keys_to_del.extend(watch_keys.iter().cloned());
+                    // state of sessions without queued jobs is left to expire
+                    let session_keys: Vec<String> = conn.smembers(self.sessions_key()).await?;
+                    keys_to_del.extend(session_keys.iter().map(|session_key| self.session_state_key(session_key)));
 
                     // fetch all tags for all jobs to delete in separate non-atomic/transactional pipeline
                     let mut tag_pipeline = redis::pipe();
                     let tag_pipe = &mut tag_pipeline;
 
-                    let job_ids: Vec<u64> = conn.lrange(&self.jobs_key, 0, -1).await?;
+                    let mut storage_pipe = redis::pipe();
+
+                    let mut job_ids: Vec<u64> = Vec::new();
+                    for key in &queued_keys {
+                        job_ids.extend(conn.lrange::<_, Vec<u64>>(key, 0, -1).await?);
+                    }
                     for job_id in &job_ids {
                         let job_key = RedisJob::build_key(*job_id);
                         tag_pipe.hget(&job_key, &[job::Field::Id, job::Field::Tags]);
+                        storage_pipe
+                            .cmd("HSTRLEN")
+                            .arg(&job_key)
+                            .arg(job::Field::Input)
+                            .cmd("HSTRLEN")
+                            .arg(&job_key)
+                            .arg(job::Field::Output);
                         keys_to_del.push(job_key);
                     }
 
                     let mut pipe = redis::pipe();
                     let pipe_ref = pipe.atomic();
 
+                    let storage_bytes: i64 = vec_from_redis_pipe::<C, i64>(conn, &storage_pipe).await?.iter().sum();
+                    record_storage_in_pipe(pipe_ref, &self.name, -storage_bytes);
+
                     let tagged_jobs: Vec<(Option<u64>, Option<String>)> =
                         vec_from_redis_pipe(conn, tag_pipe).await?;
                     for (job_id, tags) in tagged_jobs {
@@ -158,19 +689,56 @@ impl RedisQueue {
                         .srem(keys::QUEUES_KEY, &self.name)
                         .query_async(conn)
                         .await?;
-                    result.map(|_| (true, job_ids.len()))
+                    result.map(|_| queue::Deletion::Deleted(queue::DeletedJobs { queued: job_ids.len() as u64 }))
                 }
             });
 
-        if queue_deleted {
-            info!(
-                "[{}] deleted ({} queued jobs deleted)",
-                &self.key, num_jobs_deleted
-            );
-            Ok(true)
-        } else {
-            Ok(false)
+        if let queue::Deletion::Deleted(ref deleted) = deletion {
+            info!("[{}] deleted ({} queued jobs deleted)", &self.key, deleted.queued);
         }
+        Ok(deletion)
+    }
+
+    /// Get a snapshot of this queue's settings and all of its jobs, read atomically so that it's consistent even
+    /// while jobs are being created or updated.
+    ///
+    /// Redis is blocked while the snapshot is read, for a time proportional to the number of jobs in every status.
+    pub async fn snapshot<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<queue::Snapshot> {
+        let queued_keys = self.queued_keys(conn).await?;
+        let job_fields: Vec<job::Field> =
+            job::Field::all_fields().iter().filter(|field| field.dependencies().is_empty()).cloned().collect();
+        let result: Option<(redis::Value, Vec<redis::Value>)> = redis::Script::new(SNAPSHOT_SCRIPT)
+            .key(&self.key)
+            .key(&self.jobs_key)
+            .key(&[
+                keys::LIMBO_KEY,
+                keys::FAILED_KEY,
+                keys::ENDED_KEY,
+                keys::RUNNING_KEY,
+                keys::TIMEDOUT_KEY,
+                keys::QUARANTINED_KEY,
+                keys::HELD_KEY,
+            ])
+            .key(&queued_keys[1..])
+            .arg(&self.name)
+            .arg(keys::JOB_PREFIX)
+            .arg(job::Field::Queue)
+            .arg(SETTINGS_FIELDS.len())
+            .arg(SETTINGS_FIELDS)
+            .arg(job_fields.as_slice())
+            .invoke_async(conn)
+            .await?;
+        let (settings, jobs) = result.ok_or_else(|| OcyError::NoSuchQueue(self.name.clone()))?;
+        let jobs = jobs
+            .iter()
+            .map(|job| job::JobMeta::from_redis_value(&job_fields, job, &[]))
+            .collect::<RedisResult<Vec<job::JobMeta>>>()?;
+        Ok(queue::Snapshot {
+            queue: self.name.clone(),
+            taken_at: clock::now(),
+            settings: redis::from_redis_value(&settings)?,
+            jobs,
+        })
     }
 
     pub async fn job_ids<C: ConnectionLike + Send>(
@@ -184,14 +752,21 @@ impl RedisQueue {
             job_ids.insert(status.clone(), Vec::new());
         }
 
-        for queue_key in &[
-            &self.jobs_key,
-            keys::FAILED_KEY,
-            keys::ENDED_KEY,
-            keys::RUNNING_KEY,
-            keys::TIMEDOUT_KEY,
-        ] {
-            for job_id in conn.lrange::<_, Vec<u64>>(*queue_key, 0, -1).await? {
+        let mut list_keys = self.queued_keys(conn).await?;
+        list_keys.extend(
+            [
+                keys::FAILED_KEY,
+                keys::ENDED_KEY,
+                keys::RUNNING_KEY,
+                keys::TIMEDOUT_KEY,
+                keys::QUARANTINED_KEY,
+                keys::HELD_KEY,
+            ]
+            .iter()
+            .map(|key| key.to_string()),
+        );
+        for list_key in &list_keys {
+            for job_id in conn.lrange::<_, Vec<u64>>(list_key, 0, -1).await? {
                 pipe.hget(
                     RedisJob::new(job_id).key(),
                     &[job::Field::Id, job::Field::Queue, job::Field::Status],
@@ -223,19 +798,88 @@ impl RedisQueue {
         Ok(job_ids)
     }
 
+    /// Get the IDs of up to `n` randomly chosen jobs in this queue with given status.
+    ///
+    /// Queued jobs are sampled by random index, so this is cheap even for large backlogs. Jobs with other statuses
+    /// are kept in lists shared by all queues, so every job in the relevant lists is checked.
+    pub async fn sample_job_ids<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        status: &job::Status,
+        n: usize,
+    ) -> OcyResult<Vec<u64>> {
+        let list_keys: &[&str] = match status {
+            job::Status::Queued => {
+                let queued_keys = self.queued_keys(conn).await?;
+                let mut pipe = redis::pipe();
+                for key in &queued_keys {
+                    pipe.llen(key);
+                }
+                let lens: Vec<usize> = vec_from_redis_pipe(conn, &pipe).await?;
+                let len = lens.iter().sum();
+                let mut pipeline = redis::pipe();
+                let pipe = &mut pipeline;
+                for mut index in rand::seq::sample_indices(&mut rand::thread_rng(), len, n.min(len)) {
+                    // find which list the index falls in, treating the lists as if they were concatenated
+                    for (key, key_len) in queued_keys.iter().zip(&lens) {
+                        if index < *key_len {
+                            pipe.lindex(key, index as isize);
+                            break;
+                        }
+                        index -= key_len;
+                    }
+                }
+                // jobs may have been taken from the queue in the meantime, leaving nothing at some indexes
+                let job_ids: Vec<Option<u64>> = pipe.query_async(conn).await?;
+                return Ok(job_ids.into_iter().flatten().collect());
+            }
+            job::Status::Running => &[keys::RUNNING_KEY],
+            job::Status::Failed => &[keys::FAILED_KEY, keys::ENDED_KEY],
+            job::Status::Completed | job::Status::Cancelled => &[keys::ENDED_KEY],
+            job::Status::TimedOut => &[keys::FAILED_KEY, keys::TIMEDOUT_KEY],
+            job::Status::Quarantined => &[keys::QUARANTINED_KEY],
+        };
+
+        let mut pipeline = redis::pipe();
+        let pipe = &mut pipeline;
+        for list_key in list_keys {
+            for job_id in conn.lrange::<_, Vec<u64>>(*list_key, 0, -1).await? {
+                pipe.hget(RedisJob::new(job_id).key(), &[job::Field::Id, job::Field::Queue, job::Field::Status]);
+            }
+        }
+        let job_ids = vec_from_redis_pipe::<C, (Option<u64>, Option<String>, Option<job::Status>)>(conn, pipe)
+            .await?
+            .into_iter()
+            .filter(|(_, queue_name, job_status)| {
+                queue_name.as_ref() == Some(&self.name) && job_status.as_ref() == Some(status)
+            })
+            .filter_map(|(job_id, _, _)| job_id);
+        Ok(rand::seq::sample_iter(&mut rand::thread_rng(), job_ids, n).unwrap_or_else(|job_ids| job_ids))
+    }
+
     /// Get number of jobs currently queued.
-    pub async fn size<C: ConnectionLike>(&self, conn: &mut C) -> OcyResult<u64> {
-        let (exists, size): (bool, u64) = transaction_async!(conn, &[&self.key, &self.jobs_key], {
-            redis::pipe()
-                .atomic()
-                .exists(&self.key) // check queue settings exist
-                .llen(&self.jobs_key) // check length of queued jobs
-                .query_async(conn)
-                .await?
+    pub async fn size<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<u64> {
+        let queued_keys = self.queued_keys(conn).await?;
+        let mut watch_keys = vec![&self.key];
+        watch_keys.extend(&queued_keys);
+        let (exists, sizes): (bool, Vec<u64>) = transaction_async!(conn, &watch_keys[..], {
+            let mut pipe = redis::pipe();
+            pipe.atomic().exists(&self.key); // check queue settings exist
+            for key in &queued_keys {
+                pipe.llen(key); // check length of queued jobs
+            }
+            let values: Option<Vec<redis::Value>> = pipe.query_async(conn).await?;
+            match values {
+                Some(values) => Some((
+                    redis::from_redis_value(&values[0])?,
+                    values[1..].iter().map(redis::from_redis_value).collect::<RedisResult<_>>()?,
+                )),
+                None => None,
+            }
         });
 
         if exists {
-            Ok(size)
+            Ok(sizes.iter().sum())
         } else {
             Err(OcyError::NoSuchQueue(self.name.to_owned()))
         }
@@ -246,18 +890,267 @@ impl RedisQueue {
         &self,
         conn: &mut C,
     ) -> OcyResult<queue::Settings> {
-        Ok(conn
-            .hget(
-                &self.key,
-                &[
-                    queue::Field::Timeout,
-                    queue::Field::HeartbeatTimeout,
-                    queue::Field::ExpiresAfter,
-                    queue::Field::Retries,
-                    queue::Field::RetryDelays,
-                ],
-            )
-            .await?)
+        Ok(conn.hget(&self.key, SETTINGS_FIELDS).await?)
+    }
+
+    /// Add commands to a pipeline to get whether this queue exists, its settings, and the ID of the next job to be
+    /// taken from it.
+    ///
+    /// Settings should only be parsed if the queue exists, since they're missing otherwise. Only jobs without a routing
+    /// key are considered for the next job, since which routed job is taken next depends on the worker.
+    pub fn summary_in_pipe<'b>(&self, pipe: &'b mut redis::Pipeline) -> &'b mut redis::Pipeline {
+        pipe.exists(&self.key)
+            .hget(&self.key, SETTINGS_FIELDS)
+            .lindex(&self.jobs_key, -1)
+            .hget(&self.key, queue::Field::PausedUntil)
+    }
+
+    /// Get key used to record this queue's recently started jobs.
+    pub fn starts_key(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_STARTS_SUFFIX)
+    }
+
+    /// Add commands to a pipeline to record a job being started from this queue, keeping only the most recent starts.
+    pub fn record_start_in_pipe<'b>(
+        &self,
+        pipe: &'b mut redis::Pipeline,
+        start: queue::Start,
+    ) -> &'b mut redis::Pipeline {
+        let key = self.starts_key();
+        pipe.lpush(&key, start.to_record())
+            .ignore()
+            .ltrim(&key, 0, queue::MAX_RECORDED_STARTS as isize - 1)
+            .ignore()
+    }
+
+    /// Get key used to record this queue's recently completed jobs.
+    pub fn runs_key(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_RUNS_SUFFIX)
+    }
+
+    /// Add commands to a pipeline to record a job from this queue being completed, keeping only the most recent runs.
+    pub fn record_run_in_pipe<'b>(&self, pipe: &'b mut redis::Pipeline, run: queue::Run) -> &'b mut redis::Pipeline {
+        let key = self.runs_key();
+        pipe.lpush(&key, run.to_record())
+            .ignore()
+            .ltrim(&key, 0, queue::MAX_RECORDED_STARTS as isize - 1)
+            .ignore()
+    }
+
+    /// Get the numbers of this queue's queued and running jobs, and the age of its oldest queued job.
+    ///
+    /// Running jobs are counted by checking the queue of every running job, so this is slower the more jobs are
+    /// running across all queues.
+    pub async fn backlog<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<queue::Backlog> {
+        let (queued, oldest_created_at) = self.queued_count_and_oldest(conn).await?;
+        let running_ids: Vec<u64> = conn.lrange(keys::RUNNING_KEY, 0, -1).await?;
+
+        let mut pipe = redis::pipe();
+        for job_id in &running_ids {
+            pipe.hget(RedisJob::build_key(*job_id), job::Field::Queue);
+        }
+        let running_queues: Vec<Option<String>> = vec_from_redis_pipe(conn, &pipe).await?;
+        let running = running_queues.iter().filter(|queue| queue.as_deref() == Some(self.name.as_str())).count();
+
+        let oldest_age_seconds = oldest_created_at.map_or(0, |created_at| {
+            clock::now().seconds_since(&created_at).max(0) as u64
+        });
+        Ok(queue::Backlog {
+            queued,
+            running: running as u64,
+            oldest_age_seconds,
+        })
+    }
+
+    /// Estimate how long jobs wait on this queue, and how long they run for, from its queued jobs and the jobs started
+    /// from it and completed within given window.
+    pub async fn latency<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        window: std::time::Duration,
+    ) -> OcyResult<queue::Latency> {
+        let (queued, oldest_created_at) = self.queued_count_and_oldest(conn).await?;
+        let records: Vec<String> = conn.lrange(self.starts_key(), 0, -1).await?;
+        let run_records: Vec<String> = conn.lrange(self.runs_key(), 0, -1).await?;
+
+        let now = clock::now();
+        let oldest_queued_age =
+            oldest_created_at.map(|created_at| Duration::from_secs(now.seconds_since(&created_at).max(0) as u64));
+        let starts: Vec<queue::Start> = records.iter().filter_map(|record| queue::Start::from_record(record)).collect();
+        let runs: Vec<queue::Run> = run_records.iter().filter_map(|record| queue::Run::from_record(record)).collect();
+        Ok(queue::Latency::new(queued, oldest_queued_age, &starts, &runs, window, now.timestamp()))
+    }
+
+    /// Get the number of this queue's queued jobs, and the creation date/time of the oldest of the jobs next to be
+    /// taken from each of its lists of queued jobs.
+    async fn queued_count_and_oldest<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+    ) -> OcyResult<(u64, Option<DateTime>)> {
+        let queued_keys = self.queued_keys(conn).await?;
+        let mut pipe = redis::pipe();
+        for key in &queued_keys {
+            pipe.llen(key).lindex(key, -1);
+        }
+        let values: Vec<redis::Value> = pipe.query_async(conn).await?;
+
+        let mut queued = 0;
+        let mut pipe = redis::pipe();
+        for values in values.chunks(2) {
+            queued += redis::from_redis_value::<u64>(&values[0])?;
+            if let Some(job_id) = redis::from_redis_value::<Option<u64>>(&values[1])? {
+                pipe.hget(RedisJob::build_key(job_id), job::Field::CreatedAt);
+            }
+        }
+        let created_ats: Vec<Option<DateTime>> = vec_from_redis_pipe(conn, &pipe).await?;
+        let oldest = created_ats.into_iter().flatten().fold(None, |oldest: Option<DateTime>, created_at| match oldest {
+            Some(oldest) if oldest <= created_at => Some(oldest),
+            _ => Some(created_at),
+        });
+        Ok((queued, oldest))
+    }
+
+    /// Get key used to count this queue's recent retries against its retry budget.
+    fn retry_count_key(&self) -> String {
+        format!("{}{}", self.key, keys::QUEUE_RETRY_COUNT_SUFFIX)
+    }
+
+    /// Count a retry against this queue's retry budget of `budget` retries per minute.
+    ///
+    /// Returns false if the budget has been exceeded, in which case the job shouldn't be retried, and this queue
+    /// is paused for `cooldown`.
+    pub async fn spend_retry_budget<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        budget: u64,
+        cooldown: &Duration,
+    ) -> OcyResult<bool> {
+        let allowed: bool = redis::Script::new(SPEND_RETRY_BUDGET_SCRIPT)
+            .key(self.retry_count_key())
+            .key(&self.key)
+            .arg(budget)
+            .arg(queue::Field::PausedUntil)
+            .arg(clock::now().plus(cooldown))
+            .invoke_async(conn)
+            .await?;
+
+        if !allowed {
+            warn!(
+                "[{}] retry budget of {} per minute exceeded, pausing for {}",
+                &self.key, budget, cooldown
+            );
+        }
+        Ok(allowed)
+    }
+
+    /// Get the time until which no jobs will be taken from this queue, if it's currently paused.
+    pub async fn paused_until<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<Option<DateTime>> {
+        let paused_until: Option<DateTime> = conn.hget(&self.key, queue::Field::PausedUntil).await?;
+        Ok(paused_until.filter(|paused_until| paused_until > &clock::now()))
+    }
+
+    /// Get how long the outputs of this queue's completed jobs can be fetched by their unique keys for.
+    pub async fn result_ttl<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<Duration> {
+        let result_ttl: Option<Duration> = conn.hget(&self.key, queue::Field::ResultTtl).await?;
+        Ok(result_ttl.unwrap_or_else(|| queue::Settings::default().result_ttl))
+    }
+
+    /// Check that given job input is within this queue's maximum input size, if any.
+    pub async fn check_input_size<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        input: &serde_json::Value,
+    ) -> OcyResult<()> {
+        let max_size: Option<u64> = conn.hget(&self.key, queue::Field::MaxInputSize).await?;
+        queue::check_size("input", input, max_size)
+    }
+
+    /// Check that given output of a job is within this queue's maximum output size, if any, applying this queue's
+    /// output size policy if not.
+    ///
+    /// Returns the output to store in place of the given output if it was truncated or offloaded, or `None` if it's
+    /// within the maximum size.
+    pub async fn fit_output<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        job_id: u64,
+        output: &serde_json::Value,
+    ) -> OcyResult<Option<serde_json::Value>> {
+        let (max_size, policy): (Option<u64>, Option<queue::OutputSizePolicy>) =
+            conn.hget(&self.key, &[queue::Field::MaxOutputSize, queue::Field::OutputSizePolicy]).await?;
+        let err = match queue::check_size("output", output, max_size) {
+            Ok(()) => return Ok(None),
+            Err(err) => err,
+        };
+
+        match (policy.unwrap_or_default(), max_size) {
+            (queue::OutputSizePolicy::Truncate, Some(max_size)) => {
+                warn!("[{}] truncating output of job {} to {} bytes", &self.key, job_id, max_size);
+                Ok(Some(queue::truncate_output(output, max_size)))
+            }
+            (queue::OutputSizePolicy::Offload, _) => {
+                warn!("[{}] offloading oversized output of job {}", &self.key, job_id);
+                offload::offload_output(job_id, output).map(Some)
+            }
+            _ => Err(err),
+        }
+    }
+
+    /// Check that a job on this queue can change status by given transition, based on this queue's allowed
+    /// transitions.
+    pub async fn check_transition<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        transition: &job::Transition,
+    ) -> OcyResult<()> {
+        let allowed: Option<String> = conn.hget(&self.key, queue::Field::AllowedTransitions).await?;
+        let allowed: Option<Vec<job::Transition>> = allowed.map(|s| serde_json::from_str(&s).unwrap());
+        transition.check(allowed.as_deref())
+    }
+
+    /// Get the amount of time since a job was last created on this queue, or `None` if no jobs have been created
+    /// since the last job creation time started being recorded.
+    pub async fn idle_time<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+    ) -> OcyResult<Option<std::time::Duration>> {
+        let last_job_at: Option<DateTime> = conn.hget(&self.key, queue::Field::LastJobAt).await?;
+        Ok(last_job_at.map(|dt| std::time::Duration::from_secs(clock::now().seconds_since(&dt).max(0) as u64)))
+    }
+
+    /// Suggest how long clients should wait before polling a queue again, given how long it's been idle for.
+    ///
+    /// Recently active queues get a hint of 1 second, increasing by 1 second per minute of inactivity, up to given
+    /// maximum. Queues with unknown activity get the maximum.
+    pub fn poll_hint(idle_time: Option<std::time::Duration>, max: std::time::Duration) -> std::time::Duration {
+        let min = std::time::Duration::from_secs(1).min(max);
+        match idle_time {
+            Some(idle_time) => std::time::Duration::from_secs(idle_time.as_secs() / 60).clamp(min, max),
+            None => max,
+        }
+    }
+
+    /// Get the URL jobs on this queue are pushed to, if any.
+    pub async fn callback<C: ConnectionLike + Send>(&self, conn: &mut C) -> OcyResult<Option<String>> {
+        Ok(conn.hget(&self.key, queue::Field::CallbackUrl).await?)
+    }
+
+    /// Set or clear the URL jobs on this queue are pushed to.
+    pub async fn set_callback<C: ConnectionLike + Send>(&self, conn: &mut C, url: Option<&str>) -> OcyResult<()> {
+        match url {
+            Some(url) => {
+                if !Self::is_valid_callback_url(url) {
+                    return Err(OcyError::bad_request("Invalid callback URL, must be an absolute http(s) URL"));
+                }
+                let _: () = conn.hset(&self.key, queue::Field::CallbackUrl, url).await?;
+                info!("[{}] push delivery to {} enabled", &self.key, url);
+            }
+            None => {
+                let _: () = conn.hdel(&self.key, queue::Field::CallbackUrl).await?;
+                info!("[{}] push delivery disabled", &self.key);
+            }
+        }
+        Ok(())
     }
 
     /// Check whether this queue exists in Redis or not.
@@ -285,6 +1178,21 @@ impl RedisQueue {
     pub fn build_jobs_key(name: &str) -> String {
         format!("{}{}{}", keys::QUEUE_PREFIX, name, keys::QUEUE_JOBS_SUFFIX)
     }
+
+    /// Generate a Redis key to use for this queue's job IDs with given routing key, or without one.
+    pub fn build_routed_jobs_key(name: &str, routing_key: Option<&str>) -> String {
+        match routing_key {
+            Some(routing_key) => format!(
+                "{}{}{}{}{}",
+                keys::QUEUE_PREFIX,
+                name,
+                keys::QUEUE_ROUTED_INFIX,
+                routing_key,
+                keys::QUEUE_JOBS_SUFFIX
+            ),
+            None => Self::build_jobs_key(name),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -312,5 +1220,51 @@ mod test {
         assert!(!RedisQueue::is_valid_name("x'y"));
         assert!(!RedisQueue::is_valid_name("⨀⨁⨂"));
         assert!(!RedisQueue::is_valid_name("nâme"));
+
+        assert!(RedisQueue::from_string("ocypod:callbacks").is_ok());
+        assert!(RedisQueue::from_string("ocypod:").is_err());
+        assert!(RedisQueue::from_string("a:callbacks").is_err());
+    }
+
+    #[test]
+    fn routed_jobs_keys() {
+        assert_eq!(RedisQueue::build_routed_jobs_key("name", None), RedisQueue::build_jobs_key("name"));
+        assert_eq!(RedisQueue::build_routed_jobs_key("name", Some("gpu")), "ocypod:queue:name:routed:gpu:jobs");
+
+        let queue = RedisQueue::from_string("name").unwrap();
+        assert_eq!(queue.routing_keys_key(), "ocypod:queue:name:routing_keys");
+        assert_eq!(queue.session_jobs_key("abc"), "ocypod:queue:name:session:abc:jobs");
+        assert_eq!(queue.session_state_key("abc"), "ocypod:queue:name:session:abc");
+
+        let list = job::QueueList { session_key: Some("abc".to_owned()), ..Default::default() };
+        assert_eq!(queue.queue_list_key(&list), queue.session_jobs_key("abc"));
+        let list = job::QueueList { serialization_key: Some("abc".to_owned()), ..Default::default() };
+        assert_eq!(queue.queue_list_key(&list), "ocypod:queue:name:serial:abc:jobs");
+        assert_eq!(queue.queue_list_key(&job::QueueList::default()), queue.jobs_key());
+        assert!(RedisQueue::is_valid_routing_key("shard-1"));
+        assert!(!RedisQueue::is_valid_routing_key("shard:1"));
+    }
+
+    #[test]
+    fn callback_url_validation() {
+        assert!(RedisQueue::is_valid_callback_url("http://worker.local/jobs"));
+        assert!(RedisQueue::is_valid_callback_url("https://example.com:8443/hook?queue=a"));
+
+        assert!(!RedisQueue::is_valid_callback_url(""));
+        assert!(!RedisQueue::is_valid_callback_url("/relative/path"));
+        assert!(!RedisQueue::is_valid_callback_url("ftp://example.com/jobs"));
+        assert!(!RedisQueue::is_valid_callback_url("not a url"));
+    }
+
+    #[test]
+    fn poll_hint() {
+        let max = std::time::Duration::from_secs(5);
+        let hint = |secs| RedisQueue::poll_hint(Some(std::time::Duration::from_secs(secs)), max).as_secs();
+        assert_eq!(hint(0), 1);
+        assert_eq!(hint(59), 1);
+        assert_eq!(hint(180), 3);
+        assert_eq!(hint(3600), 5);
+        assert_eq!(RedisQueue::poll_hint(None, max), max);
+        assert_eq!(RedisQueue::poll_hint(Some(std::time::Duration::from_secs(0)), std::time::Duration::from_secs(0)).as_secs(), 0);
     }
 }
@@ -0,0 +1,141 @@
+//! Reconciliation of the queues stored in Redis with those defined in the configuration file.
+//!
+//! Queues defined in the configuration file are created if they don't exist, and have their settings corrected if
+//! they've drifted, e.g. after being changed by `PATCH /queue/{queue_name}`. If reconciliation is enabled, the
+//! configuration file is treated as the source of truth, so any other queues are reported as unmanaged, and may be
+//! deleted. This happens at startup, and whenever the configuration file is reloaded after a `SIGHUP`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use log::{debug, error, info, warn};
+
+use crate::application::shard::RedisShards;
+use crate::application::{system, RedisManager};
+use crate::config::{Config, ReconcileConfig};
+use crate::models::{queue, OcyError, OcyResult};
+
+/// Changes made to queues while reconciling them with the configuration file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reconciliation {
+    /// Queues from the configuration file that didn't exist.
+    pub created: Vec<String>,
+
+    /// Queues from the configuration file whose settings differed from it.
+    pub updated: Vec<String>,
+
+    /// Queues not defined in the configuration file that weren't deleted, only found if reconciliation is enabled.
+    pub unmanaged: Vec<String>,
+
+    /// Queues not defined in the configuration file that were deleted.
+    pub deleted: Vec<String>,
+}
+
+/// Create or update queues defined in the configuration file, and if reconciliation is enabled, find or delete any
+/// other queues, depending on its configuration.
+///
+/// Unmanaged queues are only deleted if they have no queued jobs, since those would otherwise be lost.
+pub async fn reconcile_queues(
+    shards: &RedisShards,
+    queues: &HashMap<String, queue::Settings>,
+    config: &ReconcileConfig,
+) -> OcyResult<Reconciliation> {
+    let mut reconciliation = Reconciliation::default();
+    debug!("Ensuring that {} queue(s) from configuration file exist", queues.len());
+    for (name, settings) in queues {
+        let mut conn = shards.for_queue(name).get();
+        match RedisManager::queue_settings(&mut conn, name).await {
+            Ok(ref existing_settings) if settings == existing_settings => {
+                debug!("Queue \"{}\" already exists with configured settings, nothing to create", name);
+                continue;
+            }
+            Ok(_) => reconciliation.updated.push(name.clone()),
+            Err(OcyError::NoSuchQueue(_)) => reconciliation.created.push(name.clone()),
+            Err(err) => return Err(err),
+        }
+        RedisManager::create_or_update_queue(&mut conn, name, settings).await?;
+    }
+
+    if !config.enabled {
+        return Ok(reconciliation);
+    }
+    for pool in shards.all() {
+        let mut conn = pool.get();
+        for name in RedisManager::queue_names(&mut conn).await? {
+            // system queues are managed by the server itself
+            if queues.contains_key(&name) || system::is_system_queue(&name) {
+                continue;
+            }
+            if !config.delete_unmanaged {
+                reconciliation.unmanaged.push(name);
+                continue;
+            }
+            match RedisManager::delete_queue(&mut conn, &name, false).await? {
+                queue::Deletion::Deleted(_) => reconciliation.deleted.push(name),
+                queue::Deletion::NotFound => (),
+                queue::Deletion::NotEmpty(jobs) => {
+                    warn!("Not deleting unmanaged queue \"{}\", it has {} queued job(s)", name, jobs.queued);
+                    reconciliation.unmanaged.push(name);
+                }
+            }
+        }
+    }
+    Ok(reconciliation)
+}
+
+/// Log the changes made while reconciling queues with the configuration file.
+pub fn log_reconciliation(reconciliation: &Reconciliation) {
+    for name in &reconciliation.created {
+        info!("Created queue \"{}\" from configuration file", name);
+    }
+    for name in &reconciliation.updated {
+        info!("Updated settings of queue \"{}\" to match configuration file", name);
+    }
+    for name in &reconciliation.deleted {
+        info!("Deleted queue \"{}\", which isn't in configuration file", name);
+    }
+    if !reconciliation.unmanaged.is_empty() {
+        warn!(
+            "Found {} queue(s) not in configuration file: {}",
+            reconciliation.unmanaged.len(),
+            reconciliation.unmanaged.join(", ")
+        );
+    }
+}
+
+/// Start background task that reloads queues from the configuration file at given path whenever the server receives
+/// a `SIGHUP`, and reconciles them. Reconciliation is enabled if `force_enabled` is set, e.g. by a command line
+/// option, or by the reloaded configuration file.
+///
+/// Only queues and reconciliation settings are reloaded, any other changes to the configuration file need a restart.
+#[cfg(unix)]
+pub fn start_reload_on_hangup(shards: RedisShards, path: PathBuf, force_enabled: bool) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            error!("Failed to listen for SIGHUP, queues won't be reloaded: {}", err);
+            return;
+        }
+    };
+    actix_rt::spawn(async move {
+        while hangups.recv().await.is_some() {
+            info!("Received SIGHUP, reloading queues from {}", path.display());
+            let config = match Config::from_file(&path) {
+                Ok(config) => config,
+                Err(err) => {
+                    error!("Failed to reload configuration file {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+            let mut reconcile_config = config.reconcile;
+            reconcile_config.enabled |= force_enabled;
+            let queues = config.queue.unwrap_or_default();
+            match reconcile_queues(&shards, &queues, &reconcile_config).await {
+                Ok(reconciliation) => log_reconciliation(&reconciliation),
+                Err(err) => error!("Failed to reconcile queues with configuration file: {}", err),
+            }
+        }
+    });
+}
@@ -0,0 +1,336 @@
+//! Runs jobs on the server itself, for queues configured with a command to run for each job, so that small
+//! deployments don't need a separate worker service.
+//!
+//! Each job's input is written to its command's stdin as JSON, and anything the command writes to stdout is stored
+//! as the job's output, parsed as JSON if possible or as a string otherwise. Jobs are completed if their command exits
+//! successfully, and failed with its exit code and stderr otherwise. Heartbeats are sent while commands run, and
+//! commands are killed if their job stops running, e.g. because it was cancelled or timed out.
+
+use std::collections::HashMap;
+use std::io;
+use std::process::{ExitStatus, Stdio};
+
+use log::{debug, error, info, warn};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+use crate::application::drain::Drain;
+use crate::application::pool::PooledConnection;
+use crate::application::shard::RedisShards;
+use crate::application::RedisManager;
+use crate::config::RunnerConfig;
+use crate::events::{EventBus, EventKind};
+use crate::models::{job, OcyError};
+
+/// Maximum number of bytes of a failed command's stderr stored in its job's `error_details`. Earlier output is
+/// dropped, since the end of stderr is most likely to explain the failure.
+const MAX_STDERR_LEN: usize = 4096;
+
+/// Error collecting the result of a job's command.
+#[derive(Debug)]
+enum RunError {
+    Io(io::Error),
+
+    /// The command wrote more than the given maximum number of bytes to stdout.
+    OutputTooLarge(usize),
+}
+
+impl From<io::Error> for RunError {
+    fn from(err: io::Error) -> Self {
+        RunError::Io(err)
+    }
+}
+
+/// Start tasks running jobs from each configured runner queue, as many for each queue as its concurrency.
+///
+/// Commands may write up to their queue's `max_output_size` to stdout, or `max_body_size` bytes for queues without
+/// one. Returns an error if any runner is misconfigured.
+pub fn start_runners(
+    shards: &RedisShards,
+    runners: &HashMap<String, RunnerConfig>,
+    max_body_size: usize,
+    events: &EventBus,
+    drain: &Drain,
+) -> Result<(), String> {
+    for (queue_name, config) in runners {
+        check_config(queue_name, config)?;
+    }
+    for (queue_name, config) in runners {
+        info!(
+            "Running jobs from queue \"{}\" with `{}`, up to {} at once",
+            queue_name,
+            config.command.join(" "),
+            config.concurrency
+        );
+        for _ in 0..config.concurrency {
+            let conn = shards.for_queue(queue_name).get();
+            let runner = Runner { queue_name: queue_name.clone(), config: config.clone(), max_body_size };
+            start_runner(conn, runner, events.clone(), drain.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Check that a runner has a command to run, and can run at least one job at once.
+fn check_config(queue_name: &str, config: &RunnerConfig) -> Result<(), String> {
+    if config.command.is_empty() {
+        return Err(format!("Runner for queue \"{}\" has no command", queue_name));
+    }
+    if config.concurrency == 0 {
+        return Err(format!("Runner for queue \"{}\" must have a concurrency of at least 1", queue_name));
+    }
+    Ok(())
+}
+
+/// Queue a runner takes jobs from, and how it runs them.
+struct Runner {
+    queue_name: String,
+    config: RunnerConfig,
+    max_body_size: usize,
+}
+
+/// Start background task that repeatedly takes the next job from a queue and runs it, waiting for the runner's poll
+/// interval whenever the queue is empty. No jobs are taken while the server is draining.
+fn start_runner(mut conn: PooledConnection, runner: Runner, events: EventBus, drain: Drain) {
+    actix_rt::spawn(async move {
+        let Runner { queue_name, config, .. } = &runner;
+        loop {
+            if drain.is_draining() {
+                actix_rt::time::delay_for(config.poll_interval.0).await;
+                continue;
+            }
+            match RedisManager::next_queued_job(&mut conn, queue_name).await {
+                Ok(Some(payload)) => {
+                    events.job_event(EventKind::Started, payload.id(), Some(queue_name));
+                    run_job(&mut conn, &runner, &payload, &events).await;
+                }
+                Ok(None) => actix_rt::time::delay_for(config.poll_interval.0).await,
+                Err(OcyError::NoSuchQueue(_)) => {
+                    debug!("[queue:{}] runner queue doesn't exist, waiting for it to be created", queue_name);
+                    actix_rt::time::delay_for(config.poll_interval.0).await;
+                }
+                Err(err) => {
+                    error!("[queue:{}] runner failed to fetch next job: {}", queue_name, err);
+                    actix_rt::time::delay_for(config.poll_interval.0).await;
+                }
+            }
+        }
+    })
+}
+
+/// Run the runner's command for a job, sending heartbeats until it exits, then update the job with its result.
+async fn run_job(conn: &mut PooledConnection, runner: &Runner, payload: &job::Payload, events: &EventBus) {
+    let job_id = payload.id();
+    let config = &runner.config;
+    let max_output_size = match RedisManager::queue_settings(conn, &runner.queue_name).await {
+        Ok(settings) => settings.max_output_size.map_or(runner.max_body_size, |size| size as usize),
+        Err(err) => {
+            warn!("[job:{}] failed to get queue's max_output_size, limiting output to max_body_size: {}", job_id, err);
+            runner.max_body_size
+        }
+    };
+
+    let spawned = Command::new(&config.command[0])
+        .args(&config.command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(err) => {
+            let details = serde_json::json!({ "error": err.to_string() });
+            update_job(conn, job_id, failed("spawn_failed", details, None), events).await;
+            return;
+        }
+    };
+
+    // write input separately from reading output, so that commands writing output before they've read all of their
+    // input can't deadlock
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = serde_json::to_vec(payload.input()).unwrap();
+        actix_rt::spawn(async move {
+            if let Err(err) = stdin.write_all(&input).await {
+                debug!("[job:{}] command didn't read all of its input: {}", job_id, err);
+            }
+        });
+    }
+
+    // stdout and stderr are read as they're written, so that a command can't fill its pipes and block, stopping as
+    // soon as stdout exceeds its limit, and dropping this future kills the command if its job stops running first
+    let stdout = read_stdout(child.stdout.take().unwrap(), max_output_size);
+    let stderr = read_stderr(child.stderr.take().unwrap(), MAX_STDERR_LEN);
+    let output = async { tokio::try_join!(async { Ok((&mut child).await?) }, stdout, stderr) };
+    tokio::pin!(output);
+    let mut heartbeat = actix_rt::time::interval(config.heartbeat_interval.0);
+    let output = loop {
+        tokio::select! {
+            output = &mut output => break output,
+            _ = heartbeat.tick() => match RedisManager::update_job_heartbeat(conn, job_id).await {
+                Ok(()) => (),
+                Err(OcyError::Conflict(_)) | Err(OcyError::NoSuchJob(_)) => {
+                    info!("[job:{}] job is no longer running, killing its command", job_id);
+                    return;
+                }
+                Err(err) => warn!("[job:{}] failed to send heartbeat for running command: {}", job_id, err),
+            },
+        }
+    };
+
+    let update_req = match output {
+        Ok((status, stdout, _)) if status.success() => job::UpdateRequest {
+            status: Some(job::Status::Completed),
+            output: parse_output(&stdout),
+            ..Default::default()
+        },
+        Ok((status, stdout, stderr)) => failed("command_failed", exit_details(status, &stderr), parse_output(&stdout)),
+        Err(RunError::OutputTooLarge(max_len)) => {
+            let msg = format!("Command wrote more than the maximum output size of {} bytes to stdout", max_len);
+            failed("invalid_output", serde_json::json!({ "error": msg }), None)
+        }
+        Err(RunError::Io(err)) => failed("command_failed", serde_json::json!({ "error": err.to_string() }), None),
+    };
+    update_job(conn, job_id, update_req, events).await;
+}
+
+/// Read all of a command's stdout, failing once it's written more than `max_len` bytes.
+async fn read_stdout<R: AsyncRead + Unpin>(mut stdout: R, max_len: usize) -> Result<Vec<u8>, RunError> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        let len = stdout.read(&mut chunk).await?;
+        if len == 0 {
+            return Ok(buf);
+        }
+        if buf.len() + len > max_len {
+            return Err(RunError::OutputTooLarge(max_len));
+        }
+        buf.extend_from_slice(&chunk[..len]);
+    }
+}
+
+/// Read all of a command's stderr, keeping only the last `max_len` bytes.
+async fn read_stderr<R: AsyncRead + Unpin>(mut stderr: R, max_len: usize) -> Result<Vec<u8>, RunError> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        let len = stderr.read(&mut chunk).await?;
+        if len == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..len]);
+        // drop earlier output in batches, rather than on every read
+        if buf.len() > max_len * 2 {
+            buf.drain(..buf.len() - max_len);
+        }
+    }
+    let start = buf.len().saturating_sub(max_len);
+    buf.drain(..start);
+    Ok(buf)
+}
+
+/// Update a job with its command's result, failing it instead if its output is rejected, e.g. for being too large.
+async fn update_job(conn: &mut PooledConnection, job_id: u64, update_req: job::UpdateRequest, events: &EventBus) {
+    let update_req = match RedisManager::update_job(conn, job_id, &update_req).await {
+        Ok(()) => update_req,
+        Err(OcyError::BadRequest(msg)) if update_req.output.is_some() => {
+            let update_req = failed("invalid_output", serde_json::json!({ "error": msg }), None);
+            if let Err(err) = RedisManager::update_job(conn, job_id, &update_req).await {
+                error!("[job:{}] failed to fail job with invalid output: {}", job_id, err);
+                return;
+            }
+            update_req
+        }
+        Err(OcyError::Conflict(msg)) => {
+            info!("[job:{}] command's result discarded, job is no longer running: {}", job_id, msg);
+            return;
+        }
+        Err(err) => {
+            error!("[job:{}] failed to store command's result: {}", job_id, err);
+            return;
+        }
+    };
+
+    if let Some(event) = update_req.status.as_ref().and_then(EventKind::from_status) {
+        events.job_event(event, job_id, None);
+    }
+}
+
+/// Get an update request failing a job with given error code and details.
+fn failed(error_code: &str, error_details: serde_json::Value, output: Option<serde_json::Value>) -> job::UpdateRequest {
+    job::UpdateRequest {
+        status: Some(job::Status::Failed),
+        output,
+        error_code: Some(error_code.to_owned()),
+        error_details: Some(error_details),
+        ..Default::default()
+    }
+}
+
+/// Get a job's output from its command's stdout, parsed as JSON if possible, or as a string otherwise. Commands that
+/// write nothing to stdout have no output.
+fn parse_output(stdout: &[u8]) -> Option<serde_json::Value> {
+    if stdout.iter().all(u8::is_ascii_whitespace) {
+        return None;
+    }
+    Some(
+        serde_json::from_slice(stdout)
+            .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(stdout).trim_end().to_owned())),
+    )
+}
+
+/// Get the error details of a job whose command exited unsuccessfully, from its exit status and the end of its
+/// stderr.
+fn exit_details(status: ExitStatus, stderr: &[u8]) -> serde_json::Value {
+    let start = stderr.len().saturating_sub(MAX_STDERR_LEN);
+    serde_json::json!({
+        "exit_code": status.code(),
+        "stderr": String::from_utf8_lossy(&stderr[start..]).trim_end(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config() {
+        let mut config = RunnerConfig::default();
+        assert!(check_config("a", &config).is_err());
+        config.command = vec!["true".to_owned()];
+        assert!(check_config("a", &config).is_ok());
+        config.concurrency = 0;
+        assert!(check_config("a", &config).is_err());
+    }
+
+    #[test]
+    fn output() {
+        assert_eq!(parse_output(b""), None);
+        assert_eq!(parse_output(b" \n"), None);
+        assert_eq!(parse_output(b"{\"a\": 1}\n"), Some(serde_json::json!({"a": 1})));
+        assert_eq!(parse_output(b"done\n"), Some(serde_json::json!("done")));
+    }
+
+    #[actix_rt::test]
+    async fn bounded_output() {
+        assert_eq!(read_stdout(&b"abcd"[..], 4).await.unwrap(), b"abcd".to_vec());
+        assert!(matches!(read_stdout(&b"abcde"[..], 4).await, Err(RunError::OutputTooLarge(4))));
+
+        let stderr = format!("{}end", "x".repeat(20_000));
+        assert_eq!(read_stderr(stderr.as_bytes(), 5).await.unwrap(), b"xxend".to_vec());
+        assert_eq!(read_stderr(&b"short"[..], 4096).await.unwrap(), b"short".to_vec());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn details() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let stderr = format!("{}\nend\n", "x".repeat(MAX_STDERR_LEN));
+        let details = exit_details(ExitStatus::from_raw(2 << 8), stderr.as_bytes());
+        assert_eq!(details["exit_code"], 2);
+        assert_eq!(details["stderr"].as_str().unwrap().len(), MAX_STDERR_LEN - 1);
+        assert!(details["stderr"].as_str().unwrap().ends_with("x\nend"));
+    }
+}
@@ -0,0 +1,107 @@
+//! Scanning the lists and sorted sets of job IDs checked by monitors a batch at a time, so that checks over many
+//! jobs don't send Redis a single huge pipeline, or hold up other tasks for the whole check.
+//!
+//! Jobs are scanned from the end of each list or set towards its start. Removing a job doesn't move any jobs before
+//! it, so jobs removed as they're checked don't cause others to be skipped. Jobs added to the start of a list during a
+//! scan (e.g. completed jobs added to the ended list) move the jobs after them along, so a few jobs may be left to the
+//! next check, as are jobs added during a scan.
+
+use std::time::Instant;
+
+use log::debug;
+use redis::{aio::ConnectionLike, AsyncCommands};
+
+use crate::models::{queue, OcyResult};
+
+/// Position of a scan over job IDs, which yields them in batches.
+#[derive(Debug)]
+pub struct JobScan {
+    key: String,
+    sorted: bool,
+    end: isize,
+    batching: queue::Batching,
+    started: bool,
+}
+
+impl JobScan {
+    /// Start scanning all job IDs in the list with given key.
+    pub async fn list<C: ConnectionLike + Send>(
+        conn: &mut C,
+        key: &str,
+        batching: &queue::Batching,
+    ) -> OcyResult<Self> {
+        let len: isize = conn.llen(key).await?;
+        Ok(Self::new(key, false, len, batching))
+    }
+
+    /// Start scanning job IDs in the sorted set with given key whose scores are at most `max_score`.
+    pub async fn sorted_set<C: ConnectionLike + Send>(
+        conn: &mut C,
+        key: &str,
+        max_score: i64,
+        batching: &queue::Batching,
+    ) -> OcyResult<Self> {
+        let len: isize = conn.zcount(key, "-inf", max_score).await?;
+        Ok(Self::new(key, true, len, batching))
+    }
+
+    fn new(key: &str, sorted: bool, len: isize, batching: &queue::Batching) -> Self {
+        Self {
+            key: key.to_owned(),
+            sorted,
+            end: len - 1,
+            batching: *batching,
+            started: false,
+        }
+    }
+
+    /// Get the next batch of job IDs, or `None` once all have been scanned, or the scan's deadline has passed.
+    ///
+    /// Yields to other tasks before fetching every batch after the first.
+    pub async fn next_batch<C: ConnectionLike + Send>(&mut self, conn: &mut C) -> OcyResult<Option<Vec<u64>>> {
+        if self.end < 0 {
+            return Ok(None);
+        }
+        if self.started {
+            // tokio marks the output of yield_now as must_use, though there's nothing to use
+            let () = tokio::task::yield_now().await;
+            if self.batching.is_overdue(Instant::now()) {
+                debug!("Stopping check of {} early, leaving {} job(s) to the next check", self.key, self.end + 1);
+                self.end = -1;
+                return Ok(None);
+            }
+        }
+        self.started = true;
+
+        let start = batch_start(self.end, self.batching.batch_size);
+        let job_ids: Vec<u64> = if self.sorted {
+            conn.zrange(&self.key, start, self.end).await?
+        } else {
+            conn.lrange(&self.key, start, self.end).await?
+        };
+        self.end = start - 1;
+        Ok(Some(job_ids))
+    }
+}
+
+/// Get the index of the first job in the batch ending at index `end`.
+fn batch_start(end: isize, batch_size: usize) -> isize {
+    if batch_size == 0 {
+        0
+    } else {
+        (end + 1 - batch_size as isize).max(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn batch_starts() {
+        assert_eq!(batch_start(9, 4), 6);
+        assert_eq!(batch_start(5, 4), 2);
+        assert_eq!(batch_start(1, 4), 0);
+        assert_eq!(batch_start(9, 0), 0);
+    }
+}
@@ -0,0 +1,116 @@
+//! Versioning of the layout of Ocypod's keys in Redis, and migrations between versions.
+//!
+//! Each Redis instance records the schema version its data is stored in. Servers refuse to start against data in any
+//! other version, which must first be upgraded by running `ocypod-server --migrate`. Data written before versioning was
+//! added is treated as version 0, and a Redis instance with no Ocypod data is set to the current version.
+
+use log::{debug, info};
+use redis::{aio::ConnectionLike, AsyncCommands};
+
+use super::{keys, RedisManager};
+use crate::models::{queue, OcyError, OcyResult};
+
+/// Schema version used by this release.
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// Description of each migration, by the version it upgrades to.
+const MIGRATIONS: &[(u64, &str)] = &[(1, "store default values of queue settings missing from older queues")];
+
+/// Get the schema version of data stored in Redis, initialising it to the current version if no data is stored yet.
+pub async fn version<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<u64> {
+    if let Some(version) = conn.get::<_, Option<u64>>(keys::SCHEMA_VERSION_KEY).await? {
+        return Ok(version);
+    }
+
+    let populated: bool = conn.exists(&[keys::QUEUES_KEY, keys::STAT_JOBS_CREATED_KEY]).await?;
+    if populated {
+        return Ok(0);
+    }
+
+    // another server may be initialising the version at the same time
+    let _: bool = conn.set_nx(keys::SCHEMA_VERSION_KEY, SCHEMA_VERSION).await?;
+    let version: u64 = conn.get(keys::SCHEMA_VERSION_KEY).await?;
+    debug!("Initialised Redis schema version to {}", version);
+    Ok(version)
+}
+
+/// Check that data stored in Redis uses the schema version this server supports.
+pub async fn check_version<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<()> {
+    let version = version(conn).await?;
+    if version > SCHEMA_VERSION {
+        Err(OcyError::conflict(format!(
+            "Redis schema version {} is newer than supported version {}, upgrade ocypod-server to use it",
+            version, SCHEMA_VERSION
+        )))
+    } else if version < SCHEMA_VERSION {
+        Err(OcyError::conflict(format!(
+            "Redis schema version {} is older than supported version {}, run ocypod-server --migrate to upgrade it",
+            version, SCHEMA_VERSION
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Upgrade data stored in Redis to the current schema version, applying each migration in turn.
+///
+/// Returns the versions migrated to, which is empty if data was already at the current version. Each migration can
+/// safely be applied again if interrupted.
+pub async fn migrate<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<Vec<u64>> {
+    let version = version(conn).await?;
+    if version > SCHEMA_VERSION {
+        return Err(OcyError::conflict(format!(
+            "Redis schema version {} is newer than supported version {}, can't migrate",
+            version, SCHEMA_VERSION
+        )));
+    }
+
+    let mut migrated = Vec::new();
+    for (to_version, description) in MIGRATIONS.iter().filter(|(v, _)| *v > version) {
+        info!("Migrating Redis schema to version {}: {}", to_version, description);
+        apply_migration(conn, *to_version).await?;
+        let _: () = conn.set(keys::SCHEMA_VERSION_KEY, *to_version).await?;
+        migrated.push(*to_version);
+    }
+    Ok(migrated)
+}
+
+/// Apply the migration that upgrades data to given version from the version before it.
+async fn apply_migration<C: ConnectionLike + Send>(conn: &mut C, to_version: u64) -> OcyResult<()> {
+    match to_version {
+        1 => store_default_queue_settings(conn).await,
+        _ => Err(OcyError::Internal(format!("No migration to schema version {}", to_version))),
+    }
+}
+
+/// Store default values of queue settings added after a queue was created, which are otherwise only filled in when
+/// the queue's settings are read.
+async fn store_default_queue_settings<C: ConnectionLike + Send>(conn: &mut C) -> OcyResult<()> {
+    let defaults = queue::Settings::default();
+    let mut pipe = redis::pipe();
+    for queue_name in RedisManager::queue_names(conn).await? {
+        let key = format!("{}{}", keys::QUEUE_PREFIX, queue_name);
+        pipe.hset_nx(&key, queue::Field::QuarantineAfter, defaults.quarantine_after)
+            .ignore()
+            .hset_nx(&key, queue::Field::QuickFailWindow, &defaults.quick_fail_window)
+            .ignore()
+            .hset_nx(&key, queue::Field::RetryBudgetCooldown, &defaults.retry_budget_cooldown)
+            .ignore()
+            .hset_nx(&key, queue::Field::ShadowPercent, defaults.shadow_percent)
+            .ignore();
+    }
+    let _: () = pipe.query_async(conn).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn migrations_in_order() {
+        let versions: Vec<u64> = MIGRATIONS.iter().map(|(version, _)| *version).collect();
+        let expected: Vec<u64> = (1..=SCHEMA_VERSION).collect();
+        assert_eq!(versions, expected);
+    }
+}
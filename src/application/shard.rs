@@ -0,0 +1,516 @@
+//! Sharding of queues across multiple Redis instances.
+//!
+//! Each shard is a separate Redis instance holding a complete set of Ocypod keys for the queues mapped to it. Queues
+//! are mapped to shards either explicitly in the configuration, or by rendezvous hashing of the queue name, so that
+//! adding a shard only moves a small proportion of queues.
+//!
+//! Each shard's job IDs are prefixed by the shard's index in their upper bits, so job IDs are unique across all shards,
+//! and a job's shard can be determined from its ID alone, without depending on the number of shards. Adding a shard
+//! therefore doesn't change where existing jobs are looked up, and jobs created before sharding was enabled are all
+//! on the first shard, whose IDs have no prefix.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use log::debug;
+
+use super::metrics::RedisOperation;
+use super::pool::RedisPool;
+use super::{keys, RedisManager};
+use crate::config::RedisConfig;
+use crate::middleware::circuit_breaker::CircuitBreaker;
+use crate::models::{
+    job, queue, quota, tag, Duration, IntegrityReport, OcyError, OcyResult, Role, ServerInfo, Tenant,
+};
+
+/// Maximum number of shards, limited so that job IDs stay below 2^53, and so can be represented exactly by clients
+/// parsing JSON numbers as doubles.
+pub const MAX_SHARDS: usize = 32;
+
+/// Number of lower bits of a job ID used by each shard's job ID counter, with the upper bits holding the shard's index.
+const SHARD_ID_BITS: u32 = 48;
+
+/// Moves a shard's job ID counter into that shard's range of IDs, if it isn't already.
+const INIT_JOB_IDS_SCRIPT: &str = r#"
+local first_id = tonumber(ARGV[1])
+local job_id = tonumber(redis.call("get", KEYS[1])) or 0
+if job_id < first_id then
+    redis.call("set", KEYS[1], first_id)
+end
+"#;
+
+/// Connection pools for each Redis shard, along with the mapping of queues to shards.
+#[derive(Clone, Debug)]
+pub struct RedisShards {
+    pools: Vec<RedisPool>,
+    queue_shards: HashMap<String, usize>,
+}
+
+impl RedisShards {
+    /// Connect to all configured shards, retrying each for up to the configured startup wait if Redis is
+    /// unavailable.
+    ///
+    /// Read replicas and failover are only used for the first shard, i.e. the one configured by `url` or `urls`.
+    pub async fn connect_with_retry(config: &RedisConfig) -> OcyResult<Self> {
+        let primary = if config.urls.is_empty() {
+            let client = redis::Client::open(config.url.as_str())?;
+            RedisPool::connect_with_retry(&client, config).await?
+        } else {
+            RedisPool::connect_failover_with_retry(config).await?
+        };
+        let mut pools = vec![primary];
+
+        let shard_config = RedisConfig {
+            replica_urls: Vec::new(),
+            ..config.clone()
+        };
+        for url in &config.shard_urls {
+            let client = redis::Client::open(url.as_str())?;
+            pools.push(RedisPool::connect_with_retry(&client, &shard_config).await?);
+        }
+        debug!("Connected to {} Redis shard(s)", pools.len());
+
+        let shards = Self {
+            pools,
+            queue_shards: config.queue_shards.clone(),
+        };
+        shards.init_job_ids().await?;
+        for pool in &shards.pools {
+            RedisManager::init_namespace_storage(&mut pool.get()).await?;
+        }
+        Ok(shards)
+    }
+
+    /// Record the outcome of every command sent to any shard with given circuit breaker.
+    pub fn with_circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.pools = self.pools.into_iter().map(|pool| pool.with_circuit_breaker(breaker.clone())).collect();
+        self
+    }
+
+    /// Get the first shard, which holds data that isn't specific to any queue, such as the leader lock.
+    pub fn primary(&self) -> &RedisPool {
+        &self.pools[0]
+    }
+
+    /// Get all shards.
+    pub fn all(&self) -> &[RedisPool] {
+        &self.pools
+    }
+
+    /// Get the shard given queue's jobs are stored on.
+    pub fn for_queue(&self, queue_name: &str) -> &RedisPool {
+        &self.pools[self.queue_shard(queue_name)]
+    }
+
+    /// Get the shard given job is stored on. IDs belonging to shards that aren't configured are looked up on the
+    /// first shard, where they won't be found.
+    pub fn for_job(&self, job_id: u64) -> &RedisPool {
+        &self.pools[self.job_shard(job_id)]
+    }
+
+    /// Get list of all queue names across all shards.
+    pub async fn queue_names(&self) -> OcyResult<Vec<String>> {
+        let mut names = Vec::new();
+        for pool in &self.pools {
+            names.extend(RedisManager::queue_names(&mut pool.get()).await?);
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Get the resources used by queues in given namespace across all shards.
+    pub async fn namespace_usage(&self, namespace: &str) -> OcyResult<quota::Usage> {
+        let mut usage = quota::Usage::default();
+        for pool in &self.pools {
+            usage.merge(&RedisManager::namespace_usage(&mut pool.get_read_only(), namespace).await?);
+        }
+        Ok(usage)
+    }
+
+    /// Check that adding resources to given namespace wouldn't exceed given quota.
+    ///
+    /// This is checked separately from adding the resources, so concurrent requests may exceed the quota together,
+    /// other than storage, which is also checked atomically when creating jobs (see `storage_limit`).
+    pub async fn check_quota(&self, namespace: &str, quota: &quota::Quota, added: &quota::Usage) -> OcyResult<()> {
+        let usage = self.namespace_usage(namespace).await?;
+        quota.check(&usage, added)
+    }
+
+    /// Create a job on given queue, checking it against given namespace quota if any, the same way as jobs created by
+    /// clients.
+    pub async fn create_job_within_quota(
+        &self,
+        queue_name: &str,
+        job_req: &job::CreateRequest,
+        quota: Option<(&str, &quota::Quota)>,
+    ) -> OcyResult<job::Created> {
+        let storage_limit = match quota {
+            Some((namespace, quota)) => {
+                self.check_quota(namespace, quota, &quota::Usage::job(job_req.input.as_ref())).await?;
+                self.storage_limit(namespace, quota, queue_name).await?
+            }
+            None => None,
+        };
+        let mut conn = self.for_queue(queue_name).get();
+        RedisManager::create_job_within_limit(&mut conn, queue_name, job_req, storage_limit.as_ref()).await
+    }
+
+    /// Get the storage given namespace can use on the shard given queue is on, if its quota limits storage, given the
+    /// storage it's using on other shards.
+    pub async fn storage_limit(
+        &self,
+        namespace: &str,
+        quota: &quota::Quota,
+        queue_name: &str,
+    ) -> OcyResult<Option<quota::StorageLimit>> {
+        let quota_bytes = match quota.max_storage_bytes {
+            Some(quota_bytes) => quota_bytes,
+            None => return Ok(None),
+        };
+        let queue_shard = self.queue_shard(queue_name);
+        let mut other_bytes = 0;
+        for (_, pool) in self.pools.iter().enumerate().filter(|(shard, _)| *shard != queue_shard) {
+            other_bytes += RedisManager::namespace_storage(&mut pool.get_read_only(), namespace).await?;
+        }
+        Ok(Some(quota::StorageLimit {
+            namespace: namespace.to_owned(),
+            max_bytes: quota_bytes.saturating_sub(other_bytes),
+            quota_bytes,
+        }))
+    }
+
+    /// Get a list of job IDs with given tag name across all shards.
+    pub async fn tagged_job_ids(&self, tag_name: &str) -> OcyResult<Vec<u64>> {
+        let mut job_ids = Vec::new();
+        for pool in &self.pools {
+            job_ids.extend(RedisManager::tagged_job_ids(&mut pool.get_read_only(), tag_name).await?);
+        }
+        job_ids.sort();
+        Ok(job_ids)
+    }
+
+    /// Attach given tag to the jobs selected by given request, on whichever shards they're stored on. Jobs on queues
+    /// outside given tenant's namespace, or outside the queues its admin role is scoped to, are treated as not
+    /// existing.
+    pub async fn apply_tag(
+        &self,
+        tag_name: &str,
+        apply_req: &tag::ApplyRequest,
+        tenant: &Tenant,
+    ) -> OcyResult<tag::ApplyResults> {
+        apply_req.validate()?;
+        let job_ids = match (&apply_req.job_ids, &apply_req.queue) {
+            (_, Some(queue_name)) => {
+                let mut conn = self.for_queue(queue_name).get();
+                RedisManager::filtered_queue_job_ids(&mut conn, queue_name, apply_req.status.as_ref()).await?
+            }
+            (Some(job_ids), None) => job_ids.clone(),
+            (None, None) => Vec::new(),
+        };
+
+        let mut by_shard: Vec<Vec<u64>> = vec![Vec::new(); self.pools.len()];
+        for job_id in job_ids {
+            by_shard[self.job_shard(job_id)].push(job_id);
+        }
+        let namespace = tenant.namespace();
+        let queues = tenant.scope(Role::Admin);
+        let mut results = tag::ApplyResults::default();
+        for (pool, job_ids) in self.pools.iter().zip(by_shard) {
+            let shard_results =
+                RedisManager::apply_tag(&mut pool.get(), tag_name, &job_ids, namespace, queues.as_deref()).await?;
+            results.merge(shard_results);
+        }
+        Ok(results)
+    }
+
+    /// Retry, cancel or delete every job with given tag across all shards. Jobs on queues outside given tenant's
+    /// namespace, or outside the queues its admin role is scoped to, are treated as not existing.
+    pub async fn bulk_tag_operation(
+        &self,
+        tag_name: &str,
+        operation: tag::BulkOperation,
+        tenant: &Tenant,
+        recovery_window: &Duration,
+    ) -> OcyResult<tag::BulkResults> {
+        let namespace = tenant.namespace();
+        let queues = tenant.scope(Role::Admin);
+        let mut results = tag::BulkResults::default();
+        for pool in &self.pools {
+            let shard_results = RedisManager::bulk_tag_operation(
+                &mut pool.get(),
+                tag_name,
+                operation,
+                namespace,
+                queues.as_deref(),
+                recovery_window,
+            )
+            .await?;
+            results.merge(shard_results);
+        }
+        Ok(results)
+    }
+
+    /// Get summary of server and queue data, combined across all shards.
+    pub async fn server_info(&self) -> OcyResult<ServerInfo> {
+        let mut info = ServerInfo::default();
+        for pool in &self.pools {
+            info.merge(RedisManager::server_info(&mut pool.get_read_only()).await?);
+        }
+        Ok(info)
+    }
+
+    /// Get a summary of each queue, combined across all shards.
+    pub async fn queue_summaries(&self) -> OcyResult<HashMap<String, queue::Summary>> {
+        let mut summaries: HashMap<String, queue::Summary> = HashMap::new();
+        for pool in &self.pools {
+            for (queue_name, summary) in RedisManager::queue_summaries(&mut pool.get_read_only()).await? {
+                match summaries.get_mut(&queue_name) {
+                    Some(existing) => existing.jobs.merge(&summary.jobs),
+                    None => {
+                        summaries.insert(queue_name, summary);
+                    }
+                }
+            }
+        }
+        Ok(summaries)
+    }
+
+    /// Search for jobs across all shards, returning a page of matching jobs, newest first.
+    pub async fn search_jobs(&self, query: &job::SearchQuery) -> OcyResult<job::SearchResults> {
+        // get one more than requested from each shard, to determine whether there's another page
+        let limit = query.limit();
+        let mut job_ids = Vec::new();
+        for pool in &self.pools {
+            job_ids.extend(RedisManager::search_job_ids(&mut pool.get_read_only(), query, limit + 1).await?);
+        }
+        job_ids.sort_unstable_by(|a, b| b.cmp(a));
+        let next_cursor = if job_ids.len() > limit {
+            job_ids.truncate(limit);
+            job_ids.last().copied()
+        } else {
+            None
+        };
+
+        let mut jobs = Vec::with_capacity(job_ids.len());
+        for job_id in job_ids {
+            let mut conn = self.for_job(job_id).get_read_only();
+            match RedisManager::job_fields(&mut conn, job_id, Some(job::SEARCH_FIELDS)).await {
+                Ok(job) => jobs.push(job),
+                Err(OcyError::NoSuchJob(_)) => continue, // deleted in the meantime
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(job::SearchResults { jobs, next_cursor })
+    }
+
+    /// Update the heartbeats (and progress) of given jobs, in one round trip to each shard they're stored on. Jobs on
+    /// queues outside given tenant's namespace, or outside the queues its worker role is scoped to, are treated as not
+    /// existing.
+    pub async fn heartbeat_jobs(
+        &self,
+        heartbeats: &[job::Heartbeat],
+        tenant: &Tenant,
+    ) -> OcyResult<job::HeartbeatResults> {
+        if heartbeats.len() > job::MAX_HEARTBEAT_BATCH {
+            return Err(OcyError::bad_request(format!(
+                "Cannot heartbeat more than {} jobs in a single request",
+                job::MAX_HEARTBEAT_BATCH
+            )));
+        }
+
+        let mut by_shard: Vec<Vec<job::Heartbeat>> = vec![Vec::new(); self.pools.len()];
+        for heartbeat in heartbeats {
+            by_shard[self.job_shard(heartbeat.id())].push(heartbeat.clone());
+        }
+        let namespace = tenant.namespace();
+        let queues = tenant.scope(Role::Worker);
+        let mut results = job::HeartbeatResults::default();
+        for (pool, heartbeats) in self.pools.iter().zip(by_shard) {
+            let mut conn = pool.get().for_operation(RedisOperation::Heartbeat);
+            let shard_results =
+                RedisManager::update_job_heartbeats(&mut conn, &heartbeats, namespace, queues.as_deref()).await?;
+            results.merge(shard_results);
+        }
+        Ok(results)
+    }
+
+    /// Create a new queue with the same settings as an existing queue, optionally copying its queued jobs as new
+    /// jobs. The queues may be on different shards.
+    ///
+    /// Returns IDs of the jobs created on the new queue, in the order they'll be taken from it.
+    ///
+    /// If a namespace and its quota are given, checks that the new queue and its jobs wouldn't exceed the quota.
+    pub async fn clone_queue(
+        &self,
+        queue_name: &str,
+        clone_req: &queue::CloneRequest,
+        quota: Option<(&str, &quota::Quota)>,
+    ) -> OcyResult<Vec<u64>> {
+        let mut conn = self.for_queue(queue_name).get();
+        let settings = RedisManager::queue_settings(&mut conn, queue_name).await?;
+        let job_reqs = if clone_req.include_jobs {
+            RedisManager::queued_job_copies(&mut conn, queue_name).await?
+        } else {
+            Vec::new()
+        };
+
+        if let Some((namespace, quota)) = quota {
+            let mut added = quota::Usage::queue();
+            for job_req in &job_reqs {
+                added.merge(&quota::Usage::job(job_req.input.as_ref()));
+            }
+            self.check_quota(namespace, quota, &added).await?;
+        }
+
+        let mut conn = self.for_queue(&clone_req.name).get();
+        RedisManager::create_queue(&mut conn, &clone_req.name, &settings).await?;
+        let mut job_ids = Vec::with_capacity(job_reqs.len());
+        for job_req in &job_reqs {
+            job_ids.push(RedisManager::create_job(&mut conn, &clone_req.name, job_req).await?.id());
+        }
+        Ok(job_ids)
+    }
+
+    /// Mirror a job just created on given queue to the queue's `shadow_to` queue, if any, which may be on a different
+    /// shard. Whether a job's mirrored is based on the queue's `shadow_percent`.
+    ///
+    /// Returns the name of the shadow queue and ID of the shadow job if one was created.
+    pub async fn shadow_job(
+        &self,
+        queue_name: &str,
+        job_id: u64,
+        job_req: &job::CreateRequest,
+    ) -> OcyResult<Option<(String, u64)>> {
+        let shadow_to = match RedisManager::shadow_target(&mut self.for_queue(queue_name).get(), queue_name).await? {
+            Some(shadow_to) => shadow_to,
+            None => return Ok(None),
+        };
+
+        let mut conn = self.for_queue(&shadow_to).get();
+        let shadow_id = RedisManager::create_shadow_job(&mut conn, &shadow_to, job_req, job_id).await?;
+        Ok(Some((shadow_to, shadow_id)))
+    }
+
+    /// Check the consistency of data on all shards, optionally repairing any problems found.
+    pub async fn check_integrity(&self, repair: bool) -> OcyResult<IntegrityReport> {
+        let mut report = IntegrityReport { repaired: repair, ..Default::default() };
+        for pool in &self.pools {
+            report.merge(RedisManager::check_integrity(&mut pool.get(), repair).await?);
+        }
+        Ok(report)
+    }
+
+    /// Remove the IDs of jobs that no longer exist from all tags across all shards, returning the IDs removed from
+    /// each tag.
+    pub async fn prune_tags(&self, dry_run: bool) -> OcyResult<HashMap<String, Vec<u64>>> {
+        let mut pruned: HashMap<String, Vec<u64>> = HashMap::new();
+        for pool in &self.pools {
+            for (tag, job_ids) in RedisManager::prune_tags(&mut pool.get(), dry_run).await? {
+                pruned.entry(tag).or_default().extend(job_ids);
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Get the index of the shard given queue is mapped to.
+    fn queue_shard(&self, queue_name: &str) -> usize {
+        match self.queue_shards.get(queue_name) {
+            Some(shard) => *shard,
+            None => hash_shard(queue_name, self.pools.len()),
+        }
+    }
+
+    /// Get the index of the shard given job is stored on, or the first shard if its ID belongs to a shard that isn't
+    /// configured.
+    fn job_shard(&self, job_id: u64) -> usize {
+        let shard = job_shard(job_id);
+        if shard < self.pools.len() {
+            shard
+        } else {
+            0
+        }
+    }
+
+    /// Ensure that each shard generates job IDs that map back to that shard.
+    async fn init_job_ids(&self) -> OcyResult<()> {
+        let script = redis::Script::new(INIT_JOB_IDS_SCRIPT);
+        for (shard, pool) in self.pools.iter().enumerate() {
+            let _: () = script
+                .key(keys::JOB_ID_KEY)
+                .arg(first_job_id(shard))
+                .invoke_async(&mut pool.get())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the index of the shard a job with given ID was created on, from the upper bits of its ID.
+fn job_shard(job_id: u64) -> usize {
+    (job_id >> SHARD_ID_BITS) as usize
+}
+
+/// Get the value given shard's job ID counter starts from, so that all its job IDs belong to it.
+fn first_job_id(shard: usize) -> u64 {
+    (shard as u64) << SHARD_ID_BITS
+}
+
+/// Get the index of the shard a queue is mapped to by rendezvous hashing, i.e. the shard with the highest hash when
+/// combined with the queue name.
+fn hash_shard(queue_name: &str, num_shards: usize) -> usize {
+    (0..num_shards)
+        .max_by_key(|shard| stable_hash(queue_name.as_bytes(), *shard as u64))
+        .unwrap_or(0)
+}
+
+/// FNV-1a hash of given bytes and seed, followed by a final mixing step so that similar inputs are spread evenly. Used rather
+/// than `DefaultHasher`, since its output must be stable between Rust versions for queues to stay on the same shard.
+fn stable_hash(bytes: &[u8], seed: u64) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in seed.to_le_bytes().iter().chain(bytes) {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn job_shards() {
+        assert_eq!(job_shard(123), 0);
+        assert_eq!(first_job_id(0), 0);
+        for shard in 1..MAX_SHARDS {
+            assert_eq!(job_shard(first_job_id(shard) + 1), shard);
+            assert_eq!(job_shard(first_job_id(shard) - 1), shard - 1);
+        }
+        assert!(first_job_id(MAX_SHARDS) <= 1 << 53);
+    }
+
+    #[test]
+    fn hash_shards() {
+        let queue_names: Vec<String> = (0..1000).map(|i| format!("queue-{}", i)).collect();
+        assert!(queue_names.iter().all(|name| hash_shard(name, 1) == 0));
+
+        // queues should be spread fairly evenly
+        let mut counts = [0; 4];
+        for name in &queue_names {
+            counts[hash_shard(name, 4)] += 1;
+        }
+        assert!(counts.iter().all(|count| *count > 150), "{:?}", counts);
+
+        // adding a shard should only move queues to the new shard
+        for name in &queue_names {
+            let shard = hash_shard(name, 5);
+            assert!(shard == 4 || shard == hash_shard(name, 4));
+        }
+    }
+}
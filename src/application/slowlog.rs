@@ -0,0 +1,231 @@
+//! Log of requests that took longer than a threshold to handle, similar to Redis' `SLOWLOG`.
+//!
+//! The most recent slow requests are kept in memory, along with the time taken by each Redis command they sent, to
+//! help diagnose intermittent latency spikes. Commands are recorded by `PooledConnection` into a task local set up for
+//! each request by `track_commands`, so commands sent by background monitors aren't recorded.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Maximum number of Redis commands recorded for each request, any further commands are counted but not recorded.
+const MAX_COMMANDS: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_COMMANDS: RefCell<Commands>;
+}
+
+/// Redis commands sent while handling a single request.
+#[derive(Debug, Default)]
+struct Commands {
+    recorded: Vec<CommandTiming>,
+    total: usize,
+}
+
+/// Time taken by a single Redis command.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CommandTiming {
+    /// Name of the command, or "PIPELINE" for a pipeline of commands.
+    pub command: String,
+
+    /// Time taken for Redis to respond, in milliseconds.
+    pub duration_ms: f64,
+}
+
+/// A request that took longer than the slow log's threshold to handle.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SlowLogEntry {
+    /// Unique ID of this entry, increasing for each entry recorded.
+    pub id: u64,
+
+    /// Time the request was received.
+    pub time: DateTime<Utc>,
+
+    /// HTTP method of the request.
+    pub method: String,
+
+    /// Path of the request, including any query string.
+    pub path: String,
+
+    /// HTTP status code of the response.
+    pub status: u16,
+
+    /// Time taken to handle the request, in milliseconds.
+    pub duration_ms: f64,
+
+    /// Redis commands sent while handling the request, in the order they were sent.
+    pub commands: Vec<CommandTiming>,
+
+    /// Total number of Redis commands sent, which may be more than the number recorded.
+    pub total_commands: usize,
+}
+
+/// Bounded log of the most recent slow requests.
+#[derive(Debug)]
+pub struct SlowLog {
+    threshold: Option<Duration>,
+    max_entries: usize,
+    next_id: AtomicU64,
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+}
+
+impl SlowLog {
+    /// Create a slow log recording requests that take longer than `threshold` (if any), keeping up to `max_entries`
+    /// of the most recent.
+    pub fn new(threshold: Option<Duration>, max_entries: usize) -> Self {
+        Self {
+            threshold,
+            max_entries,
+            next_id: AtomicU64::new(0),
+            entries: Mutex::new(VecDeque::with_capacity(max_entries)),
+        }
+    }
+
+    /// Whether requests are being recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.threshold.is_some() && self.max_entries > 0
+    }
+
+    /// Get the threshold requests must exceed to be recorded, if enabled.
+    pub fn threshold(&self) -> Option<Duration> {
+        self.threshold.filter(|_| self.is_enabled())
+    }
+
+    /// Get recorded requests, most recent first.
+    pub fn entries(&self) -> Vec<SlowLogEntry> {
+        self.entries.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    /// Remove all recorded requests.
+    pub fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Run given request handling future, recording the Redis commands it sends. If the request takes longer than
+    /// the threshold, it's added to the log with the status returned by `status`.
+    pub async fn track<F, T, S>(&self, method: String, path: String, fut: F, status: S) -> T
+    where
+        F: Future<Output = T>,
+        S: FnOnce(&T) -> u16,
+    {
+        let threshold = match self.threshold() {
+            Some(threshold) => threshold,
+            None => return fut.await,
+        };
+
+        let time = Utc::now();
+        let started = std::time::Instant::now();
+        let (output, commands) = REQUEST_COMMANDS
+            .scope(RefCell::new(Commands::default()), async {
+                let output = fut.await;
+                (output, REQUEST_COMMANDS.with(|commands| commands.take()))
+            })
+            .await;
+        let duration = started.elapsed();
+        if duration > threshold {
+            self.push(SlowLogEntry {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                time,
+                method,
+                path,
+                status: status(&output),
+                duration_ms: millis(duration),
+                commands: commands.recorded,
+                total_commands: commands.total,
+            });
+        }
+        output
+    }
+
+    fn push(&self, entry: SlowLogEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+/// Record a Redis command sent while handling a request, if its commands are being tracked.
+pub fn record_command(command: &str, duration: Duration) {
+    let _ = REQUEST_COMMANDS.try_with(|commands| {
+        let mut commands = commands.borrow_mut();
+        commands.total += 1;
+        if commands.recorded.len() < MAX_COMMANDS {
+            commands.recorded.push(CommandTiming {
+                command: command.to_owned(),
+                duration_ms: millis(duration),
+            });
+        }
+    });
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn handle(delay: Duration, num_commands: usize) -> u16 {
+        for _ in 0..num_commands {
+            record_command("GET", Duration::from_millis(2));
+        }
+        tokio::time::delay_for(delay).await;
+        200
+    }
+
+    #[actix_rt::test]
+    async fn records_slow_requests() {
+        let slow_log = SlowLog::new(Some(Duration::from_millis(20)), 2);
+        let track = |path: &str, delay: u64, num_commands: usize| {
+            slow_log.track(
+                "GET".to_owned(),
+                path.to_owned(),
+                handle(Duration::from_millis(delay), num_commands),
+                |status| *status,
+            )
+        };
+
+        assert_eq!(track("/fast", 0, 1).await, 200);
+        assert!(slow_log.entries().is_empty());
+
+        track("/slow/1", 30, 1).await;
+        track("/slow/2", 30, 100).await;
+        track("/slow/3", 30, 0).await;
+        let entries = slow_log.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "/slow/3");
+        assert_eq!(entries[0].id, 2);
+        assert!(entries[0].commands.is_empty());
+        assert_eq!(entries[1].path, "/slow/2");
+        assert_eq!(entries[1].status, 200);
+        assert!(entries[1].duration_ms >= 30.0);
+        assert_eq!(entries[1].commands.len(), MAX_COMMANDS);
+        assert_eq!(entries[1].commands[0], CommandTiming { command: "GET".to_owned(), duration_ms: 2.0 });
+        assert_eq!(entries[1].total_commands, 100);
+
+        slow_log.reset();
+        assert!(slow_log.entries().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn disabled() {
+        let slow_log = SlowLog::new(None, 10);
+        assert!(!slow_log.is_enabled());
+        slow_log
+            .track("GET".to_owned(), "/".to_owned(), handle(Duration::from_millis(5), 1), |status| *status)
+            .await;
+        assert!(slow_log.entries().is_empty());
+
+        // commands sent outside of a request are ignored
+        record_command("GET", Duration::from_millis(1));
+    }
+}
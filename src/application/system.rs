@@ -0,0 +1,203 @@
+//! Reserved system queues, holding internal jobs that are run by the server's own executor rather than by workers,
+//! e.g. deliveries of jobs' results to their callback URLs.
+//!
+//! System queue names start with `ocypod:`, which clients can't create queues with. System queues and their jobs can
+//! be read through the API like any other queue and job, for observability, but requests that would change them are
+//! rejected, so they're only changed by the executor and the job monitors. Every server runs an executor, taking jobs
+//! from system queues like any other worker, so jobs are retried using their queue's settings if they fail, or if a
+//! server stops while running them.
+
+use log::{debug, error, info};
+
+use crate::application::callback::{self, Delivery};
+use crate::application::drain::Drain;
+use crate::application::pool::PooledConnection;
+use crate::application::shard::RedisShards;
+use crate::application::RedisManager;
+use crate::config::Config;
+use crate::models::{job, queue, Duration, OcyError, OcyResult};
+
+/// Prefix of the names of system queues.
+pub const SYSTEM_QUEUE_PREFIX: &str = "ocypod:";
+
+/// Time to wait before checking a system queue for jobs again once it's empty.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Time that ended system jobs are kept for, so that recent ones can be inspected.
+const EXPIRES_AFTER: Duration = Duration(std::time::Duration::from_secs(3600));
+
+/// Maximum delay between retries of a failed system job, which are otherwise doubled after each attempt.
+const MAX_RETRY_DELAY_SECS: u64 = 300;
+
+/// Queues of internal jobs run by the server's own executor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SystemQueue {
+    /// Deliveries of jobs' results to the callback URLs given when they were created.
+    Callbacks,
+}
+
+impl SystemQueue {
+    /// Every system queue.
+    pub const ALL: [SystemQueue; 1] = [SystemQueue::Callbacks];
+
+    /// Get this queue's name, which starts with the system queue prefix.
+    pub fn name(self) -> &'static str {
+        match self {
+            SystemQueue::Callbacks => "ocypod:callbacks",
+        }
+    }
+
+    /// Get the settings this queue is created with, given the server's configuration.
+    pub fn settings(self, config: &Config) -> queue::Settings {
+        match self {
+            SystemQueue::Callbacks => queue::Settings {
+                // each attempt is limited by the callback timeout, so allow for some time to fetch the job's result
+                timeout: Duration(config.callbacks.timeout.0 * 2),
+                expires_after: EXPIRES_AFTER,
+                retries: config.callbacks.retries,
+                retry_delays: retry_delays(config.callbacks.retries),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Get the number of jobs on this queue each server runs at once.
+    fn concurrency(self, config: &Config) -> usize {
+        match self {
+            SystemQueue::Callbacks => config.callbacks.concurrency,
+        }
+    }
+}
+
+/// Check whether given queue name is the name of a system queue.
+pub fn is_system_queue(queue_name: &str) -> bool {
+    queue_name.starts_with(SYSTEM_QUEUE_PREFIX)
+}
+
+/// Get delays before each retry of a failed system job, starting at 1 second and doubling after each attempt.
+fn retry_delays(retries: u64) -> Vec<Duration> {
+    (0..retries.min(16))
+        .map(|attempt| Duration::from_secs((1u64 << attempt).min(MAX_RETRY_DELAY_SECS)))
+        .collect()
+}
+
+/// Create the system queues if they don't exist, and update their settings if they've changed along with the
+/// server's configuration.
+pub async fn create_queues(shards: &RedisShards, config: &Config) -> OcyResult<()> {
+    for system_queue in SystemQueue::ALL.iter() {
+        let name = system_queue.name();
+        let settings = system_queue.settings(config);
+        let mut conn = shards.for_queue(name).get();
+        match RedisManager::queue_settings(&mut conn, name).await {
+            Ok(ref existing_settings) if existing_settings == &settings => continue,
+            Ok(_) | Err(OcyError::NoSuchQueue(_)) => (),
+            Err(err) => return Err(err),
+        }
+        if RedisManager::create_or_update_queue(&mut conn, name, &settings).await? {
+            info!("Created system queue \"{}\"", name);
+        }
+    }
+    Ok(())
+}
+
+/// Start tasks running jobs from each system queue, as many for each queue as its concurrency. No jobs are taken while
+/// the server is draining.
+pub fn start_executor(shards: &RedisShards, config: &Config, drain: &Drain) {
+    for system_queue in SystemQueue::ALL.iter() {
+        for _ in 0..system_queue.concurrency(config) {
+            let conn = shards.for_queue(system_queue.name()).get();
+            start_executor_task(conn, *system_queue, shards.clone(), config.clone(), drain.clone());
+        }
+    }
+}
+
+/// Start background task that repeatedly takes the next job from a system queue and runs it, waiting whenever the
+/// queue is empty.
+fn start_executor_task(
+    mut conn: PooledConnection,
+    system_queue: SystemQueue,
+    shards: RedisShards,
+    config: Config,
+    drain: Drain,
+) {
+    actix_rt::spawn(async move {
+        let client = actix_web::client::Client::builder().timeout(config.callbacks.timeout.0).finish();
+        loop {
+            if drain.is_draining() {
+                actix_rt::time::delay_for(POLL_INTERVAL).await;
+                continue;
+            }
+            let payload = match RedisManager::next_queued_job(&mut conn, system_queue.name()).await {
+                Ok(Some(payload)) => payload,
+                Ok(None) => {
+                    actix_rt::time::delay_for(POLL_INTERVAL).await;
+                    continue;
+                }
+                Err(err) => {
+                    error!("[queue:{}] executor failed to fetch next job: {}", system_queue.name(), err);
+                    actix_rt::time::delay_for(POLL_INTERVAL).await;
+                    continue;
+                }
+            };
+
+            let job_id = payload.id();
+            let input = payload.input().clone().unwrap_or_default();
+            let result = match system_queue {
+                SystemQueue::Callbacks => match serde_json::from_value::<Delivery>(input) {
+                    Ok(delivery) => {
+                        let mut job_conn = shards.for_job(delivery.job_id).get();
+                        let secret = config.callbacks.secret.as_deref();
+                        callback::send_result(&mut job_conn, &client, delivery.job_id, secret)
+                            .await
+                            .map(|delivered| serde_json::json!({ "delivered": delivered }))
+                    }
+                    Err(err) => Err(format!("Invalid delivery: {}", err)),
+                },
+            };
+
+            let update_req = match result {
+                Ok(output) => job::UpdateRequest {
+                    status: Some(job::Status::Completed),
+                    output: Some(output),
+                    ..Default::default()
+                },
+                Err(msg) => {
+                    debug!("[job:{}] system job failed: {}", job_id, msg);
+                    job::UpdateRequest {
+                        status: Some(job::Status::Failed),
+                        error_code: Some("system_job_failed".to_owned()),
+                        error_details: Some(serde_json::json!({ "error": msg })),
+                        ..Default::default()
+                    }
+                }
+            };
+            match RedisManager::update_job(&mut conn, job_id, &update_req).await {
+                Ok(()) => (),
+                Err(OcyError::Conflict(msg)) => info!("[job:{}] system job is no longer running: {}", job_id, msg),
+                Err(err) => error!("[job:{}] failed to store system job's result: {}", job_id, err),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn system_queues() {
+        for system_queue in SystemQueue::ALL.iter() {
+            assert!(is_system_queue(system_queue.name()));
+        }
+        assert!(!is_system_queue("callbacks"));
+        assert!(!is_system_queue("team-a.ocypod:callbacks"));
+
+        let mut config = Config::default();
+        config.callbacks.retries = 12;
+        let settings = SystemQueue::Callbacks.settings(&config);
+        assert_eq!(settings.retries, 12);
+        let first_delays = [Duration::from_secs(1), Duration::from_secs(2), Duration::from_secs(4)];
+        assert_eq!(settings.retry_delays[..3], first_delays);
+        assert_eq!(settings.retry_delays[11], Duration::from_secs(MAX_RETRY_DELAY_SECS));
+    }
+}
@@ -1,13 +1,18 @@
 //! Defines convenience interface to a tag in Redis.
 
+use log::info;
 use redis::{aio::ConnectionLike, AsyncCommands};
 
-use crate::models::{OcyError, OcyResult};
+use super::{keys, limits, system, RedisJob};
+use crate::models::{job, tag, Duration, OcyError, OcyResult};
+use crate::redis_utils::vec_from_redis_pipe;
+use crate::transaction_async;
 
 /// Represents a tag that can be attached to jobs in Redis.
 ///
 /// Mostly used as convenient way of operating on a tag with a key.
 pub struct RedisTag {
+    name: String,
     key: String,
 }
 
@@ -16,10 +21,11 @@ impl RedisTag {
     pub fn from_str(tag: &str) -> OcyResult<Self> {
         if Self::is_valid_tag(tag) {
             Ok(Self {
+                name: tag.to_owned(),
                 key: Self::build_key(tag),
             })
         } else {
-            Err(OcyError::bad_request("Invalid tag name, valid characters: a-zA-Z0-9_.-"))
+            Err(OcyError::bad_request(limits::get().invalid_name_message("tag name")))
         }
     }
 
@@ -30,7 +36,7 @@ impl RedisTag {
 
     /// Get Redis key to add tagged jobs under.
     pub fn build_key(tag: &str) -> String {
-        format!("tag:{}", tag)
+        format!("{}{}", keys::TAG_PREFIX, tag)
     }
 
     /// Get list of job IDs with this tag.
@@ -43,14 +49,134 @@ impl RedisTag {
         Ok(job_ids)
     }
 
-    // TODO: extend range of valid chars?
-    /// Check whether a given string representation of a tag is valid.
+    /// Attach this tag to given jobs, tagging a batch of jobs in each transaction.
+    ///
+    /// Jobs on queues outside given namespace, or outside the given queues if any, are treated as not existing.
+    pub async fn apply<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        job_ids: &[u64],
+        queue_prefix: &str,
+        queues: Option<&[String]>,
+    ) -> OcyResult<tag::ApplyResults> {
+        let mut results = tag::ApplyResults::default();
+        for batch in job_ids.chunks(tag::BATCH_SIZE) {
+            let job_keys: Vec<String> = batch.iter().map(|job_id| RedisJob::build_key(*job_id)).collect();
+            let batch_results: tag::ApplyResults = transaction_async!(conn, &job_keys[..], {
+                let mut read_pipe = redis::pipe();
+                for job_key in &job_keys {
+                    read_pipe.hget(job_key, &[job::Field::Queue, job::Field::Tags]);
+                }
+                let jobs: Vec<(Option<String>, Option<String>)> = vec_from_redis_pipe(conn, &read_pipe).await?;
+
+                let mut batch_results = tag::ApplyResults::default();
+                let mut pipe = redis::pipe();
+                let pipe_ref = pipe.atomic();
+                for ((job_id, job_key), (queue, tags)) in batch.iter().zip(&job_keys).zip(jobs) {
+                    if !queue.is_some_and(|queue| is_accessible(&queue, queue_prefix, queues)) {
+                        batch_results.not_found.push(*job_id);
+                        continue;
+                    }
+
+                    let mut tags: Vec<String> =
+                        tags.map(|tags| serde_json::from_str(&tags).unwrap()).unwrap_or_default();
+                    if !tags.contains(&self.name) {
+                        tags.push(self.name.clone());
+                        pipe_ref
+                            .hset(job_key, job::Field::Tags, serde_json::to_string(&tags).unwrap())
+                            .ignore()
+                            .sadd(&self.key, *job_id)
+                            .ignore();
+                    }
+                    batch_results.tagged.push(*job_id);
+                }
+
+                let result: Option<()> = pipe_ref.query_async(conn).await?;
+                result.map(|_| batch_results)
+            });
+            results.merge(batch_results);
+        }
+
+        info!("[{}] applied to {} job(s)", &self.key, results.tagged.len());
+        Ok(results)
+    }
+
+    /// Retry, cancel or delete every job with this tag. The statuses of a batch of jobs are checked at a time in a
+    /// pipeline, then each job the operation applies to is changed in its own transaction.
+    ///
+    /// Deleted jobs are moved to the trash instead if `recovery_window` isn't zero. Jobs on queues outside given
+    /// namespace, or outside the given queues if any, are treated as not existing.
+    pub async fn bulk<C: ConnectionLike + Send>(
+        &self,
+        conn: &mut C,
+        operation: tag::BulkOperation,
+        queue_prefix: &str,
+        queues: Option<&[String]>,
+        recovery_window: &Duration,
+    ) -> OcyResult<tag::BulkResults> {
+        let mut results = tag::BulkResults::default();
+        let job_ids = self.tagged_job_ids(conn).await?;
+        for batch in job_ids.chunks(tag::BATCH_SIZE) {
+            let mut pipe = redis::pipe();
+            for job_id in batch {
+                pipe.hget(RedisJob::build_key(*job_id), &[job::Field::Queue, job::Field::Status]);
+            }
+            let jobs: Vec<(Option<String>, Option<job::Status>)> = vec_from_redis_pipe(conn, &pipe).await?;
+
+            for (job_id, (queue, status)) in batch.iter().zip(jobs) {
+                let status = match (queue, status) {
+                    (Some(queue), Some(status)) if is_accessible(&queue, queue_prefix, queues) => status,
+                    _ => {
+                        results.not_found += 1;
+                        continue;
+                    }
+                };
+                if !operation.applies_to(&status) {
+                    results.skipped += 1;
+                    continue;
+                }
+
+                let job = RedisJob::new(*job_id);
+                let outcome = match operation.target_status() {
+                    Some(target) => job.set_status(conn, &target).await.map(|_| true),
+                    None if recovery_window.is_zero() => job.delete(conn).await,
+                    None => job.trash(conn, recovery_window).await,
+                };
+                match outcome {
+                    Ok(true) => {
+                        results.succeeded += 1;
+                        results.job_ids.push(*job_id);
+                    }
+                    Ok(false) | Err(OcyError::NoSuchJob(_)) => results.not_found += 1,
+                    // status changed since it was checked, job's queue doesn't allow the change, or no longer exists
+                    Err(OcyError::Conflict(_)) | Err(OcyError::NoSuchQueue(_)) => results.skipped += 1,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        info!(
+            "[{}] {} applied to {} job(s), {} skipped",
+            &self.key,
+            operation.label(),
+            results.succeeded,
+            results.skipped
+        );
+        Ok(results)
+    }
+
+    /// Check whether a given string representation of a tag is valid, using the same rules as queue names.
     pub fn is_valid_tag(tag: &str) -> bool {
-        !tag.is_empty()
-            && tag
-                .chars()
-                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+        limits::get().is_valid_name(tag)
     }
 }
 
+/// Check whether a client with access to queues starting with given prefix, and only to the given queues if any, has
+/// access to jobs on given queue. Jobs on system queues can't be changed by clients.
+fn is_accessible(queue: &str, queue_prefix: &str, queues: Option<&[String]>) -> bool {
+    queue.starts_with(queue_prefix)
+        && !system::is_system_queue(queue)
+        && queues.is_none_or(|queues| queues.iter().any(|scoped| scoped == queue))
+}
+
 // TODO: character validity tests
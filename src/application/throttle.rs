@@ -0,0 +1,191 @@
+//! Throttling of workers polling empty queues.
+//!
+//! When many workers poll a queue much faster than jobs arrive on it, most polls find it empty, and only add load to
+//! Redis. The rate of polls finding each queue empty is tracked, and while it's above the configured limit, a
+//! fraction of polls of that queue are rejected with a 429 before Redis is queried, telling workers when to retry.
+//! Rates are measured by this server alone, so the limit applies to each server separately.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::config::PollThrottleConfig;
+
+/// Counts of empty polls of a single queue, in the current and previous windows.
+#[derive(Debug)]
+struct EmptyPolls {
+    window_start: Instant,
+    current: u64,
+    previous: u64,
+}
+
+impl EmptyPolls {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            current: 0,
+            previous: 0,
+        }
+    }
+
+    /// Move to the window containing given time, if the current window has ended.
+    fn advance(&mut self, now: Instant, window: Duration) {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < window {
+            return;
+        }
+        self.previous = if elapsed < window * 2 { self.current } else { 0 };
+        self.current = 0;
+        self.window_start += window * (elapsed.as_secs_f64() / window.as_secs_f64()).floor() as u32;
+    }
+
+    /// Estimate the number of empty polls per second over the last window, weighting the previous window's count by
+    /// how much of it overlaps the last window.
+    fn rate(&self, now: Instant, window: Duration) -> f64 {
+        let elapsed = now.saturating_duration_since(self.window_start).as_secs_f64() / window.as_secs_f64();
+        let previous_weight = (1.0 - elapsed).max(0.0);
+        (self.previous as f64 * previous_weight + self.current as f64) / window.as_secs_f64()
+    }
+}
+
+/// Tracks how often each queue is polled while empty, and decides which polls to reject.
+#[derive(Debug)]
+pub struct PollThrottle {
+    config: PollThrottleConfig,
+    queues: Mutex<HashMap<String, EmptyPolls>>,
+}
+
+impl PollThrottle {
+    /// Create a throttle with no polls recorded.
+    pub fn new(config: &PollThrottleConfig) -> Self {
+        Self {
+            config: config.clone(),
+            queues: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check whether any polls may be throttled.
+    pub fn is_enabled(&self) -> bool {
+        self.config.max_empty_poll_rate.is_some() && self.config.fraction > 0.0
+    }
+
+    /// Record a poll of given queue that found it empty.
+    pub fn record_empty_poll(&self, queue: &str, now: Instant) {
+        if !self.is_enabled() {
+            return;
+        }
+        let window = self.config.window.0;
+        let mut queues = self.queues.lock().unwrap();
+        match queues.get_mut(queue) {
+            Some(polls) => {
+                polls.advance(now, window);
+                polls.current += 1;
+            }
+            None => {
+                let mut polls = EmptyPolls::new(now);
+                polls.current = 1;
+                queues.insert(queue.to_owned(), polls);
+            }
+        }
+    }
+
+    /// Record a poll of given queue that got a job, so it's no longer throttled.
+    pub fn record_job(&self, queue: &str) {
+        if self.is_enabled() {
+            self.queues.lock().unwrap().remove(queue);
+        }
+    }
+
+    /// Check whether given queue is being polled while empty faster than the configured limit.
+    pub fn is_hot(&self, queue: &str, now: Instant) -> bool {
+        let max_rate = match self.config.max_empty_poll_rate {
+            Some(max_rate) => max_rate,
+            None => return false,
+        };
+        let window = self.config.window.0;
+        let mut queues = self.queues.lock().unwrap();
+        let polls = match queues.get_mut(queue) {
+            Some(polls) => polls,
+            None => return false,
+        };
+        polls.advance(now, window);
+        if polls.current == 0 && polls.previous == 0 {
+            queues.remove(queue);
+            return false;
+        }
+        polls.rate(now, window) >= max_rate
+    }
+
+    /// Decide whether to reject a poll of given queue, returning how long the client should wait before polling again
+    /// if so.
+    pub fn check(&self, queue: &str, now: Instant) -> Option<Duration> {
+        if self.is_enabled() && self.is_hot(queue, now) && rand::thread_rng().gen::<f64>() < self.config.fraction {
+            Some(self.config.retry_after.0)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models;
+
+    fn config(max_empty_poll_rate: Option<f64>, fraction: f64) -> PollThrottleConfig {
+        PollThrottleConfig {
+            max_empty_poll_rate,
+            window: models::Duration::from_secs(10),
+            fraction,
+            retry_after: models::Duration::from_secs(2),
+        }
+    }
+
+    #[test]
+    fn hot_queue() {
+        let throttle = PollThrottle::new(&config(Some(5.0), 1.0));
+        let start = Instant::now();
+        for _ in 0..49 {
+            throttle.record_empty_poll("a", start);
+        }
+        assert!(!throttle.is_hot("a", start));
+        assert_eq!(throttle.check("a", start), None);
+
+        throttle.record_empty_poll("a", start);
+        assert!(throttle.is_hot("a", start));
+        assert!(!throttle.is_hot("b", start));
+        assert_eq!(throttle.check("a", start), Some(Duration::from_secs(2)));
+
+        // previous window's polls count less as the window moves on
+        assert!(throttle.is_hot("a", start + Duration::from_secs(10)));
+        assert!(!throttle.is_hot("a", start + Duration::from_secs(15)));
+        assert!(!throttle.is_hot("a", start + Duration::from_secs(30)));
+        assert!(throttle.queues.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn job_found() {
+        let throttle = PollThrottle::new(&config(Some(1.0), 1.0));
+        let now = Instant::now();
+        for _ in 0..20 {
+            throttle.record_empty_poll("a", now);
+        }
+        assert!(throttle.is_hot("a", now));
+        throttle.record_job("a");
+        assert!(!throttle.is_hot("a", now));
+    }
+
+    #[test]
+    fn disabled() {
+        let now = Instant::now();
+        for throttle in &[PollThrottle::new(&config(None, 1.0)), PollThrottle::new(&config(Some(1.0), 0.0))] {
+            for _ in 0..20 {
+                throttle.record_empty_poll("a", now);
+            }
+            assert_eq!(throttle.check("a", now), None);
+            assert!(throttle.queues.lock().unwrap().is_empty());
+        }
+    }
+}
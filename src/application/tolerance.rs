@@ -0,0 +1,208 @@
+//! Relaxing of heartbeat timeouts while this server is overloaded.
+//!
+//! When Redis is slow to respond, or many requests fail, workers' heartbeats may be delayed or rejected through no
+//! fault of their own, and timing out their jobs would only add a wave of retries to the load. The mean latency of
+//! Redis commands and the rate of server errors are measured over each check interval, and while either is above its
+//! threshold, the factor heartbeat timeouts are multiplied by doubles each interval, up to a limit. Once load is back
+//! to normal, the factor halves each interval until heartbeat timeouts are back to their configured values.
+//!
+//! Load is measured by this server alone, so only jobs checked by this server's timeout monitors are given longer to
+//! send heartbeats.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::config::HeartbeatToleranceConfig;
+
+/// Load measured by this server, and the resulting heartbeat timeout factor.
+pub static HEARTBEAT_TOLERANCE: HeartbeatTolerance = HeartbeatTolerance::new();
+
+/// Load measured over a single check interval.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LoadSample {
+    /// Number of Redis commands or pipelines sent.
+    pub redis_commands: u64,
+
+    /// Total time taken by Redis commands, in microseconds.
+    pub redis_micros: u64,
+
+    /// Number of HTTP requests handled.
+    pub requests: u64,
+
+    /// Number of HTTP requests that failed with a server error.
+    pub server_errors: u64,
+}
+
+impl LoadSample {
+    /// Get the mean time taken by Redis commands, if any were sent.
+    pub fn mean_redis_latency(&self) -> Option<Duration> {
+        self.redis_micros.checked_div(self.redis_commands).map(Duration::from_micros)
+    }
+
+    /// Get the fraction of requests that failed with a server error, if enough requests were handled.
+    pub fn error_rate(&self, min_requests: u64) -> Option<f64> {
+        if self.requests == 0 || self.requests < min_requests {
+            None
+        } else {
+            Some(self.server_errors as f64 / self.requests as f64)
+        }
+    }
+
+    /// Check whether this load is above either of the configured thresholds.
+    pub fn is_overloaded(&self, config: &HeartbeatToleranceConfig) -> bool {
+        let max_latency = config.max_redis_latency.0;
+        let slow = max_latency.as_nanos() > 0 && self.mean_redis_latency().is_some_and(|mean| mean > max_latency);
+        let failing = config.max_error_rate > 0.0
+            && self.error_rate(config.min_requests).is_some_and(|rate| rate > config.max_error_rate);
+        slow || failing
+    }
+}
+
+/// Tracks load on this server, and the factor heartbeat timeouts are multiplied by.
+#[derive(Debug)]
+pub struct HeartbeatTolerance {
+    enabled: AtomicBool,
+    redis_commands: AtomicU64,
+    redis_micros: AtomicU64,
+    requests: AtomicU64,
+    server_errors: AtomicU64,
+    factor: AtomicU32,
+}
+
+impl HeartbeatTolerance {
+    /// Create a tracker with no load recorded, which doesn't record any until it's enabled.
+    pub const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            redis_commands: AtomicU64::new(0),
+            redis_micros: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+            server_errors: AtomicU64::new(0),
+            factor: AtomicU32::new(1),
+        }
+    }
+
+    /// Start recording load.
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Check whether load is being recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Record a Redis command or pipeline that took given time to respond to.
+    pub fn record_redis_command(&self, duration: Duration) {
+        if self.is_enabled() {
+            self.redis_commands.fetch_add(1, Ordering::Relaxed);
+            self.redis_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Record a handled HTTP request with given status code.
+    pub fn record_response(&self, status: u16) {
+        if self.is_enabled() {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            if status >= 500 {
+                self.server_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Get the factor heartbeat timeouts are currently multiplied by, 1 if they're not relaxed.
+    pub fn factor(&self) -> u32 {
+        self.factor.load(Ordering::Relaxed)
+    }
+
+    /// Take the load recorded since the last call, and adjust the heartbeat timeout factor based on it. Returns the
+    /// load, and the previous and new factors.
+    pub fn adjust(&self, config: &HeartbeatToleranceConfig) -> (LoadSample, u32, u32) {
+        let load = LoadSample {
+            redis_commands: self.redis_commands.swap(0, Ordering::Relaxed),
+            redis_micros: self.redis_micros.swap(0, Ordering::Relaxed),
+            requests: self.requests.swap(0, Ordering::Relaxed),
+            server_errors: self.server_errors.swap(0, Ordering::Relaxed),
+        };
+        let previous = self.factor();
+        let factor = next_factor(previous, load.is_overloaded(config), config.max_factor);
+        self.factor.store(factor, Ordering::Relaxed);
+        (load, previous, factor)
+    }
+}
+
+impl Default for HeartbeatTolerance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Get the heartbeat timeout factor following given factor, doubling it up to `max_factor` if overloaded, otherwise
+/// halving it down to 1.
+fn next_factor(factor: u32, overloaded: bool, max_factor: u32) -> u32 {
+    if overloaded {
+        factor.saturating_mul(2).min(max_factor).max(1)
+    } else {
+        (factor / 2).max(1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models;
+
+    #[test]
+    fn factors() {
+        assert_eq!(next_factor(1, true, 8), 2);
+        assert_eq!(next_factor(4, true, 8), 8);
+        assert_eq!(next_factor(8, true, 8), 8);
+        assert_eq!(next_factor(1, true, 0), 1);
+        assert_eq!(next_factor(8, false, 8), 4);
+        assert_eq!(next_factor(1, false, 8), 1);
+    }
+
+    #[test]
+    fn overload() {
+        let config = HeartbeatToleranceConfig::default();
+        let load = |redis_micros, requests, server_errors| LoadSample {
+            redis_commands: 10,
+            redis_micros,
+            requests,
+            server_errors,
+        };
+        assert!(!load(500_000, 100, 5).is_overloaded(&config));
+        assert!(load(1_500_000, 100, 0).is_overloaded(&config));
+        assert!(load(0, 100, 6).is_overloaded(&config));
+        assert!(!load(0, 10, 10).is_overloaded(&config));
+        assert!(!LoadSample::default().is_overloaded(&config));
+
+        let config = HeartbeatToleranceConfig {
+            max_redis_latency: models::Duration::from_secs(0),
+            max_error_rate: 0.0,
+            ..config
+        };
+        assert!(!load(1_500_000, 100, 100).is_overloaded(&config));
+    }
+
+    #[test]
+    fn adjust() {
+        let tolerance = HeartbeatTolerance::new();
+        let config = HeartbeatToleranceConfig::default();
+        tolerance.record_response(500);
+        assert_eq!(tolerance.adjust(&config).0, LoadSample::default());
+
+        tolerance.enable();
+        for _ in 0..20 {
+            tolerance.record_response(503);
+        }
+        let (load, previous, factor) = tolerance.adjust(&config);
+        assert_eq!((load.requests, load.server_errors, previous, factor), (20, 20, 1, 2));
+        assert_eq!(tolerance.factor(), 2);
+
+        tolerance.record_redis_command(Duration::from_millis(1));
+        let (load, previous, factor) = tolerance.adjust(&config);
+        assert_eq!(load.mean_redis_latency(), Some(Duration::from_millis(1)));
+        assert_eq!((previous, factor), (2, 1));
+    }
+}
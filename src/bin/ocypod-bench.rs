@@ -0,0 +1,219 @@
+//! Load generator for Ocypod, creating, taking, and completing jobs on a queue against a running server, then reporting
+//! the throughput and latency percentiles of each.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use structopt::StructOpt;
+
+use ocypod::client::{Created, OcypodClient};
+use ocypod::models::{job, queue};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "ocypod-bench")]
+struct BenchOpts {
+    /// Base URL of the server to benchmark
+    #[structopt(long, default_value = "http://127.0.0.1:8023")]
+    url: String,
+
+    /// API key to authenticate with, if the server requires one
+    #[structopt(long)]
+    api_key: Option<String>,
+
+    /// Queue to create jobs on, which is created if needed, and deleted afterwards unless --keep-queue is given
+    #[structopt(long, default_value = "ocypod-bench")]
+    queue: String,
+
+    /// Total number of jobs to create
+    #[structopt(long, default_value = "1000")]
+    jobs: u64,
+
+    /// Number of concurrent clients creating jobs
+    #[structopt(long, default_value = "4")]
+    producers: u64,
+
+    /// Number of concurrent clients taking and completing jobs, or 0 to only create jobs
+    #[structopt(long, default_value = "4")]
+    workers: u64,
+
+    /// Size of each job's input in bytes
+    #[structopt(long, default_value = "100")]
+    input_size: usize,
+
+    /// Leave the queue and its jobs in place afterwards
+    #[structopt(long)]
+    keep_queue: bool,
+}
+
+/// Latencies of successful requests for an operation, and the number that failed.
+#[derive(Debug, Default)]
+struct Stats {
+    latencies: Vec<Duration>,
+    errors: u64,
+}
+
+impl Stats {
+    fn record<T, E>(&mut self, started: Instant, result: &Result<T, E>) {
+        match result {
+            Ok(_) => self.latencies.push(started.elapsed()),
+            Err(_) => self.errors += 1,
+        }
+    }
+
+    fn merge(&mut self, other: Stats) {
+        self.latencies.extend(other.latencies);
+        self.errors += other.errors;
+    }
+
+    /// Print a row of the report for this operation, given how long the whole benchmark ran for.
+    fn report(&self, operation: &str, elapsed: Duration) {
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        println!(
+            "{:<10} {:>8} {:>7} {:>10.1} {:>9.2} {:>9.2} {:>9.2} {:>9.2}",
+            operation,
+            sorted.len(),
+            self.errors,
+            sorted.len() as f64 / elapsed.as_secs_f64(),
+            millis(percentile(&sorted, 50.0)),
+            millis(percentile(&sorted, 90.0)),
+            millis(percentile(&sorted, 99.0)),
+            millis(sorted.last().copied().unwrap_or_default()),
+        );
+    }
+}
+
+/// Get the latency below which given percentage of sorted latencies fall.
+fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::from_secs(0);
+    }
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Create jobs until the shared count of jobs left to create runs out.
+async fn produce(client: OcypodClient, queue_name: Arc<String>, remaining: Arc<AtomicU64>, input_size: usize) -> Stats {
+    let job_req = job::CreateRequest { input: Some(serde_json::json!("x".repeat(input_size))), ..Default::default() };
+    let mut stats = Stats::default();
+    while remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1)).is_ok() {
+        let started = Instant::now();
+        let result = client.create_job(&queue_name, &job_req).await;
+        if let Ok(Created::Accepted(_)) = result {
+            eprintln!("Job accepted for replay rather than created, Redis may be unavailable");
+        }
+        stats.record(started, &result);
+    }
+    stats
+}
+
+/// Take and complete jobs until given number of jobs have been finished by all workers.
+async fn work(client: OcypodClient, queue_name: Arc<String>, finished: Arc<AtomicU64>, jobs: u64) -> (Stats, Stats) {
+    let mut dequeue_stats = Stats::default();
+    let mut complete_stats = Stats::default();
+    while finished.load(Ordering::SeqCst) < jobs {
+        let started = Instant::now();
+        let result = client.next_job(&queue_name).await;
+        let payload = match result {
+            Ok(Some(payload)) => payload,
+            Ok(None) | Err(_) => {
+                if result.is_err() {
+                    dequeue_stats.errors += 1;
+                }
+                // wait for producers, or for the server to recover
+                tokio::time::delay_for(Duration::from_millis(10)).await;
+                continue;
+            }
+        };
+        dequeue_stats.latencies.push(started.elapsed());
+
+        let started = Instant::now();
+        let result = client.complete_job(payload.id(), None).await;
+        complete_stats.record(started, &result);
+        finished.fetch_add(1, Ordering::SeqCst);
+    }
+    (dequeue_stats, complete_stats)
+}
+
+#[tokio::main]
+async fn main() {
+    let opts = BenchOpts::from_args();
+    let mut client = OcypodClient::new(opts.url.as_str());
+    if let Some(api_key) = &opts.api_key {
+        client = client.with_api_key(api_key.as_str());
+    }
+
+    if let Err(err) = client.create_queue(&opts.queue, &queue::Settings::default()).await {
+        eprintln!("Failed to create queue {}: {}", opts.queue, err);
+        std::process::exit(1);
+    }
+
+    let queue_name = Arc::new(opts.queue.clone());
+    let remaining = Arc::new(AtomicU64::new(opts.jobs));
+    let finished = Arc::new(AtomicU64::new(0));
+    println!(
+        "Benchmarking {} with {} jobs, {} producers, and {} workers",
+        opts.url, opts.jobs, opts.producers, opts.workers
+    );
+
+    let started = Instant::now();
+    let producers: Vec<_> = (0..opts.producers.max(1))
+        .map(|_| tokio::spawn(produce(client.clone(), queue_name.clone(), remaining.clone(), opts.input_size)))
+        .collect();
+    let workers: Vec<_> = (0..opts.workers)
+        .map(|_| tokio::spawn(work(client.clone(), queue_name.clone(), finished.clone(), opts.jobs)))
+        .collect();
+
+    let mut create_stats = Stats::default();
+    for stats in futures::future::join_all(producers).await {
+        create_stats.merge(stats.expect("producer panicked"));
+    }
+    // jobs that failed to be created will never be finished
+    finished.fetch_add(create_stats.errors, Ordering::SeqCst);
+
+    let mut dequeue_stats = Stats::default();
+    let mut complete_stats = Stats::default();
+    for stats in futures::future::join_all(workers).await {
+        let (dequeued, completed) = stats.expect("worker panicked");
+        dequeue_stats.merge(dequeued);
+        complete_stats.merge(completed);
+    }
+    let elapsed = started.elapsed();
+
+    println!("Finished in {:.2}s", elapsed.as_secs_f64());
+    println!(
+        "{:<10} {:>8} {:>7} {:>10} {:>9} {:>9} {:>9} {:>9}",
+        "operation", "ok", "errors", "per sec", "p50 ms", "p90 ms", "p99 ms", "max ms"
+    );
+    create_stats.report("create", elapsed);
+    if opts.workers > 0 {
+        dequeue_stats.report("dequeue", elapsed);
+        complete_stats.report("complete", elapsed);
+    }
+
+    if !opts.keep_queue {
+        if let Err(err) = client.delete_queue(&opts.queue, true).await {
+            eprintln!("Failed to delete queue {}: {}", opts.queue, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let sorted: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&sorted, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&sorted, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile(&sorted[..1], 50.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::from_secs(0));
+    }
+}
@@ -1,151 +1,252 @@
-use std::collections::HashMap;
+use std::sync::Arc;
+use actix_web::http::Method;
 use actix_web::{web, App, HttpServer};
-use log::{debug, info};
+use log::{debug, info, warn};
 
+use ocypod::events::EventBus;
 use ocypod::handlers;
-use ocypod::application::RedisManager;
-use ocypod::models::OcyError;
+use ocypod::logging::LogSettings;
+use ocypod::middleware::access_log::{AccessLog, AccessLogMiddleware};
+use ocypod::middleware::auth::{ApiKeys, AuthMiddleware};
+use ocypod::middleware::circuit_breaker::{CircuitBreaker, CircuitBreakerMiddleware};
+use ocypod::middleware::concurrency::{ConcurrencyLimitMiddleware, ConcurrencyLimits};
+use ocypod::middleware::ip_filter::{IpFilter, IpFilterMiddleware};
+use ocypod::middleware::load::LoadMiddleware;
+use ocypod::middleware::slowlog::SlowLogMiddleware;
+use ocypod::middleware::system::SystemQueueMiddleware;
+use ocypod::middleware::timeout::{RequestTimeoutMiddleware, RequestTimeouts};
+use ocypod::application::crypto::{self, PayloadCipher};
+use ocypod::application::drain::Drain;
+use ocypod::application::{keyspace, reconcile, schema};
+use ocypod::application::shard::RedisShards;
+use ocypod::application::slowlog::SlowLog;
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     // Parse CLI config, or exit with non-zero status code on error.
-    let config = ocypod::config::parse_config_from_cli_args();
+    let (opts, config) = ocypod::config::parse_cli_args();
 
-    // TODO: is env_logger the best choice here, or would slog be preferable?
-    {
-        let log_settings = format!(
-            "ocypod={},ocypod-server={}",
-            config.server.log_level, config.server.log_level
-        );
-        env_logger::Builder::new()
-            .parse_filters(&log_settings)
-            .format_module_path(false)
-            .init();
-        debug!("Log initialised using: {}", &log_settings);
+    // Log at the configured level, which can be changed later by `PUT /admin/log_level`.
+    let log_filter = match ocypod::logging::init(LogSettings::new(config.server.log_level)) {
+        Ok(log_filter) => log_filter,
+        Err(err) => {
+            eprintln!("Failed to initialise logging: {}", err);
+            std::process::exit(1);
+        }
+    };
+    debug!("Log initialised using: {:?}", log_filter.settings());
+
+    // Validate queue and tag names against the configured rules, before any names are validated.
+    ocypod::application::limits::init(config.limits.clone());
+
+    // Write oversized job outputs to files rather than Redis for queues that offload them, if configured.
+    if let Some(ref dir) = config.server.output_offload_dir {
+        ocypod::application::offload::init(dir.clone());
+        info!("Offloading oversized job outputs to {}", dir.display());
+    }
+
+    // Encrypt job inputs and outputs stored in Redis if a key is configured.
+    match PayloadCipher::from_config(&config.encryption) {
+        Ok(Some(cipher)) => {
+            crypto::init(cipher);
+            info!("Encrypting job inputs and outputs stored in Redis");
+        }
+        Ok(None) => (),
+        Err(err) => {
+            eprintln!("Failed to initialise encryption: {}", err);
+            std::process::exit(1);
+        }
     }
 
     let redis_url = config.redis_url();
 
-    let redis_client = match redis::Client::open(redis_url) {
-        Ok(client) => client,
+    // every Redis command's outcome is recorded by the circuit breaker, so it opens whenever Redis stops responding
+    let circuit_breaker = Arc::new(CircuitBreaker::new(
+        config.redis.breaker_threshold,
+        config.redis.breaker_cooldown.0,
+    ));
+    let redis_shards = match RedisShards::connect_with_retry(&config.redis).await {
+        Ok(shards) => shards.with_circuit_breaker(circuit_breaker.clone()),
         Err(err) => {
-            eprintln!("Failed to initialise Redis client: {}", err);
+            eprintln!("Failed to initialise Redis connection pool for {}: {}", redis_url, err);
             std::process::exit(1);
         }
     };
+    debug!(
+        "Initialised Redis connection pool of size {} to {}",
+        redis_shards.primary().size(),
+        redis_url
+    );
+    if redis_shards.all().len() > 1 {
+        info!("Sharding queues across {} Redis instances", redis_shards.all().len());
+    }
+    if !config.redis.replica_urls.is_empty() {
+        info!("Routing read-only requests to {} Redis read replica(s)", config.redis.replica_urls.len());
+    }
 
-    let redis_manager = match redis_client.get_tokio_connection_manager().await {
-        Ok(rm) => rm,
+    if opts.migrate {
+        match migrate_schemas(&redis_shards).await {
+            Ok(()) => std::process::exit(0),
+            Err(err) => {
+                eprintln!("Failed to migrate Redis schema: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Refuse to start against data stored in a different key layout.
+    if let Err(err) = check_schemas(&redis_shards).await {
+        eprintln!("Incompatible Redis data: {}", err);
+        std::process::exit(1);
+    }
+
+    // Warn about, or refuse to start with, Redis instances that may evict jobs when they run out of memory.
+    if let Err(err) = check_eviction_policies(&redis_shards, config.redis.refuse_evicting_policy).await {
+        eprintln!("Unsafe Redis configuration: {}", err);
+        std::process::exit(1);
+    }
+
+    // Create/update any queues found in the config file, unless they already exist with the same settings, and if
+    // reconciling, report or delete any other queues.
+    let mut reconcile_config = config.reconcile.clone();
+    reconcile_config.enabled |= opts.reconcile;
+    let config_queues = config.queue.clone().unwrap_or_default();
+    match reconcile::reconcile_queues(&redis_shards, &config_queues, &reconcile_config).await {
+        Ok(reconciliation) => reconcile::log_reconciliation(&reconciliation),
         Err(err) => {
-            eprintln!("Failed to initialise Redis connection manager for {}: {}", redis_url, err);
+            eprintln!("Failed to initialise queues from configuration file: {}", err);
             std::process::exit(1);
         }
-    };
-    debug!("Initialised Redis connection pool to {}", redis_url);
+    }
 
-    // Create/update any queues found in the config file, unless they already exist with the same settings.
-    if let Err(err) = create_queues_from_config(redis_manager.clone(), &config.queue).await {
-        eprintln!("Failed to initialise queues from configuration file: {}", err);
+    // Create queues of internal jobs run by the server itself, or update them to match the configuration.
+    if let Err(err) = ocypod::application::system::create_queues(&redis_shards, &config).await {
+        eprintln!("Failed to initialise system queues: {}", err);
         std::process::exit(1);
     }
 
     let http_server_addr = config.server_addr();
+    let events = EventBus::new();
+    if let Err(err) = ocypod::events::start_sinks(&config.events, &events, &redis_shards) {
+        eprintln!("Failed to initialise event publishing: {}", err);
+        std::process::exit(1);
+    }
+    let hooks = ocypod::events::hooks::Hooks::new(&config.hooks);
+    hooks.start(&events, &redis_shards);
+
+    let slow_log = Arc::new(SlowLog::new(
+        config.server.slow_log.threshold.as_ref().map(|threshold| threshold.0),
+        config.server.slow_log.max_entries,
+    ));
+
+    let drain = Drain::new();
+    let anomalies = Arc::new(ocypod::events::anomaly::AnomalyDetector::new(&config.anomalies));
     let app_state = web::Data::new(ocypod::models::ApplicationState {
-        redis_conn_manager: redis_manager.clone(),
+        redis_shards: redis_shards.clone(),
         config: config.clone(),
+        circuit_breaker: circuit_breaker.clone(),
+        events: events.clone(),
+        hooks,
+        log_filter,
+        slow_log: slow_log.clone(),
+        drain: drain.clone(),
+        anomalies: anomalies.clone(),
+        poll_throttle: ocypod::application::throttle::PollThrottle::new(&config.server.poll_throttle),
+        response_cache: ocypod::application::cache::ResponseCache::new(&config.server.response_cache),
     });
 
-    // Use 0 to signal that default should be used. This configured the max size that POST endpoints
-    // will accept.
-    let max_body_size = if let Some(size) = config.server.max_body_size {
-        debug!("Setting max body size to {} bytes", size);
-        size
+    // This configures the max size that POST endpoints will accept.
+    let max_body_size = config.server.json_limit();
+    debug!("Setting max body size to {} bytes", max_body_size);
+
+    let degraded_mode = config.persistence.degraded_mode;
+    let breaker = circuit_breaker.clone();
+    let request_timeouts = Arc::new(RequestTimeouts::new(
+        config.server.request_timeout.as_ref().map(|timeout| timeout.0),
+        config.server.route_timeouts.iter().map(|(route, timeout)| (route.clone(), timeout.0)).collect(),
+    ));
+
+    let concurrency = &config.server.concurrency;
+    let concurrency_limits = Arc::new(
+        concurrency
+            .classes
+            .iter()
+            .fold(
+                ConcurrencyLimits::new(concurrency.max_requests, concurrency.retry_after.0),
+                |limits, (name, class)| limits.class(name, class.routes.iter().cloned(), class.max_requests),
+            )
+            .priority(concurrency.priority_routes.iter().cloned(), concurrency.priority_reserve_percent),
+    );
+
+    let access_log = if config.server.access_log.enabled {
+        match AccessLog::open(&config.server.access_log) {
+            Ok(access_log) => Some(Arc::new(access_log)),
+            Err(err) => {
+                eprintln!("Failed to open access log: {}", err);
+                std::process::exit(1);
+            }
+        }
     } else {
-        0
+        None
     };
 
-    let mut http_server = HttpServer::new(move || {
+    let ip_filter = Arc::new(IpFilter::new(&config.server.allowed_ips));
+    let api_keys = Arc::new(ApiKeys::new(&config.auth));
+    let auth_shards = redis_shards.clone();
+    let system_shards = redis_shards.clone();
+    let public_status_page = config.server.status_page.is_public();
+
+    let http_server = HttpServer::new(move || {
         App::new()
-            // add middleware logger for access log, if required
-            .wrap(actix_web::middleware::Logger::default())
-            .app_data(app_state.clone())
-            .app_data(web::JsonConfig::default().limit(if max_body_size > 0 {
-                max_body_size
-            } else {
-                1024
+            // reject requests that would change system queues or their jobs, once clients are authenticated
+            .wrap(SystemQueueMiddleware::new(system_shards.clone()))
+            // authenticate clients by API key if any are configured, restricting namespace keys to their own queues,
+            // jobs and tags, and letting anyone view the status page unless it's configured to require a key
+            .wrap(AuthMiddleware::new(api_keys.clone(), auth_shards.clone()).exempt(move |req| {
+                public_status_page && req.path() == "/status"
             }))
-            .service(
-                web::scope("/info")
-                    // get current server version
-                    .service(web::resource("/version").to(handlers::info::version))
-                    // get summary of system/queue information
-                    .service(web::resource("").to(handlers::info::index)),
-            )
-            // Run basic health check by PINGing Redis.
-            .route("/health", web::get().to(handlers::health::index))
-            // Get list of job IDs for a given tag.
-            .route("/tag/{name}", web::get().to(handlers::tag::tagged_jobs))
-            .service(
-                web::scope("/job")
-                    // Get current status of job with given ID.
-                    .service(web::resource("/{id}/status").to(handlers::job::status))
-                    // Get or set a job's output.
-                    .service(
-                        web::resource("/{id}/output")
-                            .route(web::get().to(handlers::job::output))
-                            .route(web::put().to(handlers::job::set_output)),
-                    )
-                    // Update a job's last heartbeat date/time.
-                    .service(
-                        web::resource("/{id}/heartbeat")
-                            .route(web::put().to(handlers::job::heartbeat)),
-                    )
-                    .service(
-                        web::resource("/{id}/retry")
-                            .route(web::put().to(handlers::job::retry)),
-                    )
-                    .service(
-                        web::resource("/{id}")
-                            // Get all metadata about a job with given ID.
-                            .route(web::get().to(handlers::job::index))
-                            // Update one or more fields (including status) of a job.
-                            .route(web::patch().to(handlers::job::update))
-                            // Delete a job from the queue DB.
-                            .route(web::delete().to(handlers::job::delete)),
-                    ),
-            )
-            .service(
-                web::scope("/queue")
-                    // Job IDs by state.
-                    .service(web::resource("/{name}/job_ids").to(handlers::queue::job_ids))
-                    .service(
-                        web::resource("/{name}/job")
-                            // Get the next job to work on from given queue.
-                            .route(web::get().to(handlers::queue::next_job))
-                            // Create a new job on given queue.
-                            .route(web::post().to(handlers::queue::create_job)),
-                    )
-                    .service(
-                        // Reattmped a failed attempt
-                        web::resource("/{name}/reattempt/{timestamp}")
-                            .route(web::get().to(handlers::queue::reattempt_job)),
-                    )
-                    // Get queue size.
-                    .service(web::resource("/{name}/size").to(handlers::queue::size))
-                    .service(
-                        web::resource("/{name}")
-                            // Get queue's settings.
-                            .route(web::get().to(handlers::queue::settings))
-                            // Create a new queue, or update an existing one with given settings.
-                            .route(web::put().to(handlers::queue::create_or_update))
-                            // Delete a queue and all currently queued jobs on it.
-                            .route(web::delete().to(handlers::queue::delete)),
-                    )
-                    // Get a list of all queue names.
-                    .service(web::resource("").to(handlers::queue::index)),
-            )
-    })
-    .bind(&http_server_addr)?;
+            // fail fast while Redis is unavailable, unless creating jobs that can be persisted for later replay, or
+            // reporting metrics or configuration or using admin endpoints, which don't depend on Redis, or viewing the
+            // status page, which shows Redis being unavailable
+            .wrap(CircuitBreakerMiddleware::new(breaker.clone()).exempt(move |req| {
+                req.path() == "/metrics"
+                    || req.path() == "/config"
+                    || req.path() == "/status"
+                    || req.path().starts_with("/admin/")
+                    || (degraded_mode
+                        && req.method() == Method::POST
+                        && req.match_pattern().as_deref() == Some("/queue/{name}/job"))
+            }))
+            // respond with a 504 to requests that take too long, wrapping the circuit breaker so that a timed out
+            // probe request lets another request probe Redis
+            .wrap(RequestTimeoutMiddleware::new(request_timeouts.clone()))
+            // record slow requests and their Redis commands, including those that time out
+            .wrap(SlowLogMiddleware::new(slow_log.clone()))
+            // shed load beyond the configured number of requests in flight, other than health checks, metrics, and
+            // admin endpoints, which are needed to diagnose overload
+            .wrap(ConcurrencyLimitMiddleware::new(concurrency_limits.clone()).exempt(|req| {
+                req.path().starts_with("/health") || req.path() == "/metrics" || req.path().starts_with("/admin/")
+            }))
+            // reject requests from client addresses that aren't allowed before doing any other work
+            .wrap(IpFilterMiddleware::new(ip_filter.clone()))
+            // record response statuses to detect overload, if heartbeat timeouts are relaxed under load
+            .wrap(LoadMiddleware)
+            // write requests to the access log if enabled, wrapping other middleware so rejected requests are included
+            .wrap(AccessLogMiddleware::new(access_log.clone()))
+            .app_data(app_state.clone())
+            .app_data(web::JsonConfig::default().limit(max_body_size))
+            // Register all API endpoints.
+            .configure(handlers::configure)
+    });
+
+    // listen on sockets passed by systemd socket activation if there are any, otherwise bind to configured address
+    let listeners = ocypod::systemd::listeners();
+    let mut http_server = if listeners.is_empty() {
+        http_server.bind(&http_server_addr)?
+    } else {
+        info!("Using {} socket(s) passed by systemd instead of {}", listeners.len(), &http_server_addr);
+        listeners.into_iter().try_fold(http_server, |server, listener| server.listen(listener))?
+    };
 
     // set number of worker threads if configured, or default to number of logical CPUs
     if let Some(num_workers) = config.server.threads {
@@ -159,38 +260,102 @@ async fn main() -> std::io::Result<()> {
     }
 
     debug!("Starting background monitor tasks");
-    ocypod::application::monitor::start_monitors(redis_manager.clone(), &config.server);
+    ocypod::application::monitor::start_failover_monitor(&redis_shards, config.redis.failover_check_interval.0);
+    ocypod::application::monitor::start_keyspace_monitor(&redis_shards, &config.redis);
+    ocypod::application::monitor::start_eviction_monitor(&redis_shards, config.redis.eviction_check_interval.0);
+    ocypod::application::monitor::start_heartbeat_tolerance_monitor(&config.server.heartbeat_tolerance);
+    #[cfg(unix)]
+    if let Some(path) = opts.config_path() {
+        reconcile::start_reload_on_hangup(redis_shards.clone(), path.to_owned(), opts.reconcile);
+    }
+    let leadership = if config.coordination.leader_election {
+        let instance_id = config
+            .coordination
+            .instance_id
+            .clone()
+            .unwrap_or_else(ocypod::application::leader::default_instance_id);
+        ocypod::application::leader::start_leader_election(
+            redis_shards.primary().get(),
+            instance_id,
+            config.coordination.lock_ttl.0,
+        )
+    } else {
+        ocypod::application::leader::Leadership::always()
+    };
+    ocypod::application::monitor::start_monitors(&redis_shards, &config.server, &events, &leadership, &drain);
+    ocypod::application::monitor::start_notification_monitor(redis_shards.clone(), &config.notifications, &events);
+    ocypod::application::monitor::start_callback_monitor(redis_shards.clone(), &events);
+    ocypod::application::system::start_executor(&redis_shards, &config, &drain);
+    ocypod::application::monitor::start_anomaly_monitor(
+        redis_shards.clone(),
+        anomalies,
+        &config.anomalies,
+        config.notifications.webhook_url.clone(),
+        &events,
+    );
+    ocypod::application::monitor::start_metrics_export(&config.metrics);
+    if let Err(err) = ocypod::application::runner::start_runners(
+        &redis_shards,
+        &config.runner,
+        config.server.json_limit(),
+        &events,
+        &drain,
+    ) {
+        eprintln!("Failed to start job runners: {}", err);
+        std::process::exit(1);
+    }
+    if degraded_mode {
+        ocypod::application::monitor::start_replay_monitor(
+            redis_shards,
+            circuit_breaker,
+            config.persistence.replay_interval.0,
+            config.auth.clone(),
+            events,
+        );
+    }
 
     // Start HTTP server.
     info!("Starting queue server at: {}", &http_server_addr);
-    http_server.run().await
+    let server = http_server.run();
+    ocypod::systemd::notify_ready();
+    ocypod::systemd::start_watchdog();
+    server.await
 }
 
-/// Creates any queues found in
-async fn create_queues_from_config(
-    mut conn: redis::aio::ConnectionManager,
-    queues: &Option<HashMap<String, ocypod::models::queue::Settings>>,
-) -> ocypod::models::OcyResult<()> {
-    let queues = match queues {
-        Some(queues) => queues,
-        None => return Ok(()),
-    };
+/// Upgrades data on each shard to the current schema version.
+async fn migrate_schemas(shards: &RedisShards) -> ocypod::models::OcyResult<()> {
+    for (shard, pool) in shards.all().iter().enumerate() {
+        let migrated = schema::migrate(&mut pool.get()).await?;
+        match migrated.last() {
+            Some(version) => info!("Migrated Redis shard {} to schema version {}", shard, version),
+            None => info!("Redis shard {} already at schema version {}", shard, schema::SCHEMA_VERSION),
+        }
+    }
+    Ok(())
+}
+
+/// Checks that data on each shard uses the current schema version.
+async fn check_schemas(shards: &RedisShards) -> ocypod::models::OcyResult<()> {
+    for pool in shards.all() {
+        schema::check_version(&mut pool.get()).await?;
+    }
+    debug!("Redis schema version {} verified", schema::SCHEMA_VERSION);
+    Ok(())
+}
 
-    debug!("Ensuring that {} queue(s) from configuration file exist", queues.len());
-    for (name, settings) in queues {
-        match RedisManager::queue_settings(&mut conn, name).await {
-            Ok(ref existing_settings) => {
-                if settings != existing_settings {
-                    RedisManager::create_or_update_queue(&mut conn, name, settings).await?;
-                } else {
-                    debug!("Queue \"{}\" already exists with configured settings, nothing to create", name);
-                }
-            },
-            Err(OcyError::NoSuchQueue(_)) => {
-                RedisManager::create_or_update_queue(&mut conn, name, settings).await?;
-            },
-            Err(err) => return Err(err),
+/// Checks whether each shard's Redis `maxmemory-policy` may evict jobs, logging a warning for each that does, or
+/// failing if `refuse` is set.
+async fn check_eviction_policies(shards: &RedisShards, refuse: bool) -> Result<(), String> {
+    let evicting = keyspace::evicting_shards(shards).await.map_err(|err| err.to_string())?;
+    for (shard, policy) in evicting {
+        let msg = format!(
+            "Redis shard {} uses maxmemory-policy {}, which may evict jobs, corrupting queues (use noeviction instead)",
+            shard, policy
+        );
+        if refuse {
+            return Err(msg);
         }
+        warn!("{}", msg);
     }
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,229 @@
+//! Async HTTP client for Ocypod, for Rust workers and job producers. Only built with the "client" feature.
+//!
+//! Requests and responses use the same models as the server, so they can't drift from what the server accepts.
+
+use std::fmt;
+
+use reqwest::{header, Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+
+use crate::models::job::{self, CreateRequest, Payload, UpdateRequest};
+use crate::models::queue::{Settings, SettingsUpdate};
+
+/// Result type returned by client requests.
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// Error returned by a client request.
+#[derive(Debug)]
+pub enum ClientError {
+    /// Request couldn't be sent, or its response couldn't be read.
+    Http(reqwest::Error),
+
+    /// Server responded with an unexpected status, along with the body of its response.
+    Status(StatusCode, String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Http(err) => write!(f, "HTTP error: {}", err),
+            ClientError::Status(status, body) if body.is_empty() => write!(f, "Unexpected response: {}", status),
+            ClientError::Status(status, body) => write!(f, "Unexpected response: {}: {}", status, body),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(err: reqwest::Error) -> Self {
+        ClientError::Http(err)
+    }
+}
+
+/// Outcome of a request to create a job.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Created {
+    /// New job was created with this ID.
+    Job(u64),
+
+    /// Existing job with the same `unique_key` was returned instead of creating a new one.
+    Existing(u64),
+
+    /// Job was persisted to disk while Redis was unavailable, with this provisional ID, and will be created once
+    /// Redis recovers.
+    Accepted(i64),
+}
+
+/// Client for an Ocypod server.
+#[derive(Clone, Debug)]
+pub struct OcypodClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl OcypodClient {
+    /// Create a client for the server at given base URL, e.g. `http://localhost:8023`.
+    pub fn new<S: Into<String>>(base_url: S) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    /// Create a client for the server at given base URL, sending requests with given HTTP client, e.g. one
+    /// configured with timeouts or TLS settings.
+    pub fn with_http_client<S: Into<String>>(http: reqwest::Client, base_url: S) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_owned();
+        Self { http, base_url, api_key: None }
+    }
+
+    /// Authenticate all requests with given API key.
+    pub fn with_api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Create a queue with given settings, or replace the settings of an existing queue.
+    ///
+    /// Returns `true` if the queue was created, or `false` if it already existed.
+    pub async fn create_queue(&self, queue_name: &str, settings: &Settings) -> ClientResult<bool> {
+        let path = format!("/queue/{}", queue_name);
+        let response = self.send(self.request(Method::PUT, &path).json(settings)).await?;
+        Ok(response.status() == StatusCode::CREATED)
+    }
+
+    /// Get a queue's settings.
+    pub async fn queue_settings(&self, queue_name: &str) -> ClientResult<Settings> {
+        self.get_json(&format!("/queue/{}", queue_name)).await
+    }
+
+    /// Change only the given settings of a queue, returning its updated settings.
+    pub async fn update_queue(&self, queue_name: &str, update: &SettingsUpdate) -> ClientResult<Settings> {
+        let path = format!("/queue/{}", queue_name);
+        let response = self.send(self.request(Method::PATCH, &path).json(update)).await?;
+        Ok(response.json().await?)
+    }
+
+    /// Delete a queue. Fails with a 409 response if it still has queued jobs, unless `force` is set, in which case
+    /// they're deleted too.
+    pub async fn delete_queue(&self, queue_name: &str, force: bool) -> ClientResult<()> {
+        let path = format!("/queue/{}?force={}", queue_name, force);
+        self.send(self.request(Method::DELETE, &path)).await?;
+        Ok(())
+    }
+
+    /// Get the number of jobs waiting in a queue.
+    pub async fn queue_size(&self, queue_name: &str) -> ClientResult<u64> {
+        self.get_json(&format!("/queue/{}/size", queue_name)).await
+    }
+
+    /// Create a job on given queue.
+    pub async fn create_job(&self, queue_name: &str, job: &CreateRequest) -> ClientResult<Created> {
+        let path = format!("/queue/{}/job", queue_name);
+        let response = self.send(self.request(Method::POST, &path).json(job)).await?;
+        let status = response.status();
+        let location_id = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| location.rsplit('/').next())
+            .and_then(|id| id.parse().ok());
+        match (status, location_id) {
+            (StatusCode::CREATED, Some(id)) => Ok(Created::Job(id)),
+            (StatusCode::OK, Some(id)) => Ok(Created::Existing(id)),
+            (StatusCode::ACCEPTED, _) => Ok(Created::Accepted(response.json().await?)),
+            (status, _) => Err(ClientError::Status(status, "Job created without a valid Location".to_owned())),
+        }
+    }
+
+    /// Take the next job from given queue, marking it as running, or get `None` if there are no jobs waiting.
+    pub async fn next_job(&self, queue_name: &str) -> ClientResult<Option<Payload>> {
+        let response = self.send(self.request(Method::GET, &format!("/queue/{}/job", queue_name))).await?;
+        if response.status() == StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        Ok(Some(response.json().await?))
+    }
+
+    /// Get a job's current status.
+    pub async fn job_status(&self, job_id: u64) -> ClientResult<job::Status> {
+        self.get_json(&format!("/job/{}/status", job_id)).await
+    }
+
+    /// Get a job's output, if any.
+    pub async fn job_output(&self, job_id: u64) -> ClientResult<serde_json::Value> {
+        self.get_json(&format!("/job/{}/output", job_id)).await
+    }
+
+    /// Update a job's status and/or output.
+    pub async fn update_job(&self, job_id: u64, update: &UpdateRequest) -> ClientResult<()> {
+        self.send(self.request(Method::PATCH, &format!("/job/{}", job_id)).json(update)).await?;
+        Ok(())
+    }
+
+    /// Mark a running job as completed, with given output.
+    pub async fn complete_job(&self, job_id: u64, output: Option<serde_json::Value>) -> ClientResult<()> {
+        let update = UpdateRequest { status: Some(job::Status::Completed), output, ..Default::default() };
+        self.update_job(job_id, &update).await
+    }
+
+    /// Mark a running job as failed, with given output.
+    pub async fn fail_job(&self, job_id: u64, output: Option<serde_json::Value>) -> ClientResult<()> {
+        let update = UpdateRequest { status: Some(job::Status::Failed), output, ..Default::default() };
+        self.update_job(job_id, &update).await
+    }
+
+    /// Send a heartbeat for a running job, so that it isn't timed out.
+    pub async fn heartbeat(&self, job_id: u64) -> ClientResult<()> {
+        self.send(self.request(Method::PUT, &format!("/job/{}/heartbeat", job_id))).await?;
+        Ok(())
+    }
+
+    /// Delete a job, regardless of its status.
+    pub async fn delete_job(&self, job_id: u64) -> ClientResult<()> {
+        self.send(self.request(Method::DELETE, &format!("/job/{}", job_id))).await?;
+        Ok(())
+    }
+
+    /// Start building a request to given path on the server, authenticated if the client has an API key.
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.http.request(method, &format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    /// Send a request, returning an error if the server responded with anything other than success.
+    async fn send(&self, request: RequestBuilder) -> ClientResult<Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            let body = response.text().await.unwrap_or_default();
+            Err(ClientError::Status(status, body))
+        }
+    }
+
+    /// Get given path on the server, parsing the response as JSON.
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> ClientResult<T> {
+        let response = self.send(self.request(Method::GET, path)).await?;
+        Ok(response.json().await?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn base_url() {
+        let client = OcypodClient::new("http://localhost:8023/");
+        let request = client.request(Method::GET, "/queue/a/size").build().unwrap();
+        assert_eq!(request.url().as_str(), "http://localhost:8023/queue/a/size");
+        assert!(request.headers().get(header::AUTHORIZATION).is_none());
+
+        let request = client.with_api_key("key").request(Method::GET, "/queue").build().unwrap();
+        assert_eq!(request.headers()[header::AUTHORIZATION], "Bearer key");
+    }
+}
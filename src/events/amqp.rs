@@ -0,0 +1,78 @@
+//! Publishes job lifecycle events to an AMQP exchange, e.g. on RabbitMQ.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use lapin::options::BasicPublishOptions;
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties};
+use log::info;
+
+use super::{Event, EventSink, Serialization};
+use crate::config::AmqpConfig;
+
+/// AMQP delivery mode for messages that should survive a broker restart.
+const PERSISTENT: u8 = 2;
+
+/// Sink publishing each event to an exchange with the routing key `<routing_key_prefix>.<event>`,
+/// e.g. "ocypod.job.completed".
+///
+/// Connects lazily on first publish, and reconnects on the next publish after any failure.
+pub struct AmqpSink {
+    url: String,
+    exchange: String,
+    routing_key_prefix: String,
+    serialization: Serialization,
+    connection: Option<(Connection, Channel)>,
+}
+
+impl AmqpSink {
+    pub fn new(config: &AmqpConfig) -> Self {
+        info!(
+            "Publishing job events to AMQP exchange \"{}\" with routing keys \"{}.*\"",
+            &config.exchange, &config.routing_key_prefix
+        );
+        Self {
+            url: config.url.clone(),
+            exchange: config.exchange.clone(),
+            routing_key_prefix: config.routing_key_prefix.clone(),
+            serialization: config.serialization,
+            connection: None,
+        }
+    }
+
+    async fn channel(&mut self) -> lapin::Result<&Channel> {
+        if self.connection.is_none() {
+            let connection = Connection::connect(&self.url, ConnectionProperties::default()).await?;
+            let channel = connection.create_channel().await?;
+            self.connection = Some((connection, channel));
+        }
+        Ok(&self.connection.as_ref().unwrap().1)
+    }
+
+    async fn try_publish(&mut self, event: &Event) -> lapin::Result<()> {
+        let routing_key = format!("{}.{}", self.routing_key_prefix, event.event.as_str());
+        let payload = event.serialize(self.serialization);
+        let exchange = self.exchange.clone();
+        let properties = BasicProperties::default()
+            .with_content_type("application/json".into())
+            .with_delivery_mode(PERSISTENT);
+        self.channel()
+            .await?
+            .basic_publish(&exchange, &routing_key, BasicPublishOptions::default(), &payload, properties)
+            .await?;
+        Ok(())
+    }
+}
+
+impl EventSink for AmqpSink {
+    fn publish<'a>(&'a mut self, event: &'a Event) -> Pin<Box<dyn Future<Output = Result<(), String>> + 'a>> {
+        Box::pin(async move {
+            let result = self.try_publish(event).await;
+            if result.is_err() {
+                // drop connection so that it's re-established on next publish
+                self.connection = None;
+            }
+            result.map_err(|err| err.to_string())
+        })
+    }
+}
@@ -0,0 +1,307 @@
+//! Detection of anomalous spikes in queues' failure rates.
+//!
+//! Finished job events are counted per queue in fixed length windows. When each window ends, its failure rate is
+//! compared against the failure rate over a number of preceding windows (the baseline), and flagged as anomalous if
+//! it's significantly higher than the baseline's, measured by how many standard deviations above the baseline rate
+//! it is, given the number of jobs in the window.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::EventKind;
+use crate::config::AnomaliesConfig;
+
+/// Maximum number of anomalies kept for each queue, older anomalies are discarded.
+const MAX_ANOMALIES: usize = 20;
+
+/// Lowest (and 1 minus the highest) baseline failure rate used when calculating how unusual a failure rate is, so
+/// that a queue that's never failed before isn't flagged as anomalous for a single failure.
+const MIN_BASELINE_RATE: f64 = 0.01;
+
+/// Numbers of jobs finished and failed in a window.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Counts {
+    finished: u64,
+    failed: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        self.finished += other.finished;
+        self.failed += other.failed;
+    }
+
+    fn rate(&self) -> Option<f64> {
+        if self.finished == 0 {
+            None
+        } else {
+            Some(self.failed as f64 / self.finished as f64)
+        }
+    }
+}
+
+/// Window in which a queue's failure rate was significantly higher than its baseline.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Anomaly {
+    /// Time the window ended, and the anomaly was detected.
+    pub detected_at: DateTime<Utc>,
+
+    /// Fraction of jobs finished in the window that failed or timed out.
+    pub failure_rate: f64,
+
+    /// Fraction of jobs finished in the baseline windows that failed or timed out.
+    pub baseline_failure_rate: f64,
+
+    /// Number of jobs finished in the window.
+    pub jobs: u64,
+
+    /// Number of standard deviations the failure rate was above the baseline failure rate.
+    pub z_score: f64,
+}
+
+impl Anomaly {
+    /// Get a human readable message describing this anomaly.
+    pub fn message(&self, queue: &str, window: std::time::Duration) -> String {
+        format!(
+            "Ocypod queue \"{}\": failure rate is {:.0}% over the last {}, compared to a baseline of {:.0}% \
+             ({:.1} standard deviations above normal)",
+            queue,
+            self.failure_rate * 100.0,
+            humantime::format_duration(window),
+            self.baseline_failure_rate * 100.0,
+            self.z_score
+        )
+    }
+}
+
+/// Current failure rates of a queue, and its recent anomalies.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct QueueAnomalies {
+    /// Fraction of jobs finished so far in the current window that failed or timed out, if any have finished.
+    pub failure_rate: Option<f64>,
+
+    /// Number of jobs finished so far in the current window.
+    pub jobs: u64,
+
+    /// Fraction of jobs finished in the baseline windows that failed or timed out, if any have finished.
+    pub baseline_failure_rate: Option<f64>,
+
+    /// Number of jobs finished in the baseline windows.
+    pub baseline_jobs: u64,
+
+    /// Recent anomalies, most recent first.
+    pub anomalies: Vec<Anomaly>,
+}
+
+/// History of a single queue's finished jobs.
+#[derive(Debug, Default)]
+struct QueueHistory {
+    current: Counts,
+    baseline: VecDeque<Counts>,
+    anomalies: VecDeque<Anomaly>,
+}
+
+impl QueueHistory {
+    fn baseline_counts(&self) -> Counts {
+        let mut counts = Counts::default();
+        self.baseline.iter().for_each(|window| counts.add(window));
+        counts
+    }
+
+    fn is_empty(&self) -> bool {
+        self.current.finished == 0
+            && self.baseline.iter().all(|window| window.finished == 0)
+            && self.anomalies.is_empty()
+    }
+}
+
+/// Tracks rolling failure rates of each queue, and detects anomalous spikes in them.
+#[derive(Debug)]
+pub struct AnomalyDetector {
+    config: AnomaliesConfig,
+    queues: Mutex<HashMap<String, QueueHistory>>,
+}
+
+impl AnomalyDetector {
+    /// Create a detector with no history.
+    pub fn new(config: &AnomaliesConfig) -> Self {
+        Self {
+            config: config.clone(),
+            queues: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether given event is relevant to failure rates.
+    pub fn is_relevant(event: &EventKind) -> bool {
+        matches!(event, EventKind::Completed | EventKind::Failed | EventKind::TimedOut)
+    }
+
+    /// Record a job finishing on given queue.
+    pub fn record(&self, queue: String, event: EventKind) {
+        let mut queues = self.queues.lock().unwrap();
+        let counts = &mut queues.entry(queue).or_default().current;
+        match event {
+            EventKind::Completed => counts.finished += 1,
+            EventKind::Failed | EventKind::TimedOut => {
+                counts.finished += 1;
+                counts.failed += 1;
+            }
+            _ => (),
+        }
+    }
+
+    /// End the current window of every queue, returning any anomalies detected in it, along with the queue they
+    /// were detected in.
+    pub fn end_window(&self, now: DateTime<Utc>) -> Vec<(String, Anomaly)> {
+        let mut detected = Vec::new();
+        let mut queues = self.queues.lock().unwrap();
+        for (queue, history) in queues.iter_mut() {
+            let current = std::mem::take(&mut history.current);
+            if let Some(anomaly) = self.check(&current, &history.baseline_counts(), now) {
+                history.anomalies.push_front(anomaly.clone());
+                history.anomalies.truncate(MAX_ANOMALIES);
+                detected.push((queue.clone(), anomaly));
+            }
+
+            // anomalous windows are included in the baseline, so a lasting change becomes the new normal
+            history.baseline.push_back(current);
+            while history.baseline.len() > self.config.baseline_windows {
+                history.baseline.pop_front();
+            }
+        }
+        queues.retain(|_, history| !history.is_empty());
+        detected
+    }
+
+    /// Check whether the failure rate of given window is anomalous compared to given baseline.
+    fn check(&self, window: &Counts, baseline: &Counts, now: DateTime<Utc>) -> Option<Anomaly> {
+        if window.finished < self.config.min_jobs || baseline.finished < self.config.min_jobs {
+            return None;
+        }
+        let failure_rate = window.rate()?;
+        let baseline_rate = baseline.rate()?;
+
+        let expected_rate = baseline_rate.clamp(MIN_BASELINE_RATE, 1.0 - MIN_BASELINE_RATE);
+        let std_dev = (expected_rate * (1.0 - expected_rate) / window.finished as f64).sqrt();
+        let z_score = (failure_rate - baseline_rate) / std_dev;
+        if z_score >= self.config.threshold && failure_rate - baseline_rate >= self.config.min_increase {
+            Some(Anomaly {
+                detected_at: now,
+                failure_rate,
+                baseline_failure_rate: baseline_rate,
+                jobs: window.finished,
+                z_score,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Get current failure rates of given queue, and its recent anomalies.
+    pub fn queue(&self, queue: &str) -> QueueAnomalies {
+        let queues = self.queues.lock().unwrap();
+        let history = match queues.get(queue) {
+            Some(history) => history,
+            None => return QueueAnomalies::default(),
+        };
+        let baseline = history.baseline_counts();
+        QueueAnomalies {
+            failure_rate: history.current.rate(),
+            jobs: history.current.finished,
+            baseline_failure_rate: baseline.rate(),
+            baseline_jobs: baseline.finished,
+            anomalies: history.anomalies.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(detector: &AnomalyDetector, queue: &str, completed: u64, failed: u64) {
+        for _ in 0..completed {
+            detector.record(queue.to_owned(), EventKind::Completed);
+        }
+        for _ in 0..failed {
+            detector.record(queue.to_owned(), EventKind::Failed);
+        }
+    }
+
+    #[test]
+    fn spike() {
+        let detector = AnomalyDetector::new(&AnomaliesConfig::default());
+        let now = Utc::now();
+        for _ in 0..4 {
+            record(&detector, "a", 95, 5);
+            assert!(detector.end_window(now).is_empty());
+        }
+
+        record(&detector, "a", 60, 40);
+        let detected = detector.end_window(now);
+        assert_eq!(detected.len(), 1);
+        let (queue, anomaly) = &detected[0];
+        assert_eq!(queue, "a");
+        assert_eq!(anomaly.jobs, 100);
+        assert!((anomaly.failure_rate - 0.4).abs() < 1e-9);
+        assert!((anomaly.baseline_failure_rate - 0.05).abs() < 1e-9);
+        assert!(anomaly.z_score > 10.0);
+
+        let summary = detector.queue("a");
+        assert_eq!(summary.anomalies, vec![anomaly.clone()]);
+        assert_eq!(summary.jobs, 0);
+        assert_eq!(summary.baseline_jobs, 500);
+        assert_eq!(detector.queue("b"), QueueAnomalies::default());
+    }
+
+    #[test]
+    fn normal_variation() {
+        let detector = AnomalyDetector::new(&AnomaliesConfig::default());
+        let now = Utc::now();
+        record(&detector, "a", 90, 10);
+        detector.end_window(now);
+
+        // slightly higher than usual, but not significantly
+        record(&detector, "a", 86, 14);
+        assert!(detector.end_window(now).is_empty());
+
+        // significant, but too few jobs to measure
+        record(&detector, "a", 5, 10);
+        assert!(detector.end_window(now).is_empty());
+        assert!(detector.queue("a").anomalies.is_empty());
+    }
+
+    #[test]
+    fn no_previous_failures() {
+        let detector = AnomalyDetector::new(&AnomaliesConfig::default());
+        let now = Utc::now();
+        record(&detector, "a", 100, 0);
+        detector.end_window(now);
+
+        record(&detector, "a", 99, 1);
+        assert!(detector.end_window(now).is_empty());
+        record(&detector, "a", 70, 30);
+        assert_eq!(detector.end_window(now).len(), 1);
+    }
+
+    #[test]
+    fn history_expiry() {
+        let config = AnomaliesConfig {
+            baseline_windows: 2,
+            ..Default::default()
+        };
+        let detector = AnomalyDetector::new(&config);
+        let now = Utc::now();
+        record(&detector, "a", 50, 0);
+        detector.end_window(now);
+        assert_eq!(detector.queue("a").baseline_jobs, 50);
+
+        for _ in 0..2 {
+            detector.end_window(now);
+        }
+        assert!(detector.queues.lock().unwrap().is_empty());
+    }
+}
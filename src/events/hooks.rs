@@ -0,0 +1,166 @@
+//! Runs external commands when lifecycle events occur, as a simple integration path for deployments without webhook
+//! receivers or message brokers.
+//!
+//! Each event is written to its hook's stdin as JSON. Commands are run in the background, so slow or failing hooks
+//! never hold up the server, and are killed if they run for longer than the configured timeout. Only a limited number
+//! of commands run at once, and events arriving while that many are running are dropped, so that a burst of failures
+//! can't exhaust the server's processes.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use log::{debug, error, warn};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+use super::{Event, EventBus, EventKind, EventSink};
+use crate::application::metrics::METRICS;
+use crate::application::shard::RedisShards;
+use crate::config::HooksConfig;
+use crate::models::DateTime;
+
+/// Event raised when a queue is created, in the same form as job events.
+#[derive(Debug, Serialize)]
+struct QueueEvent<'a> {
+    event: &'static str,
+    queue: &'a str,
+    timestamp: DateTime,
+}
+
+/// Runs the commands configured for each hook.
+#[derive(Clone)]
+pub struct Hooks {
+    config: Arc<HooksConfig>,
+    running: Arc<Semaphore>,
+}
+
+impl Hooks {
+    pub fn new(config: &HooksConfig) -> Self {
+        Self {
+            config: Arc::new(config.clone()),
+            running: Arc::new(Semaphore::new(config.max_running)),
+        }
+    }
+
+    /// Start running the job hooks for events published on given bus, if any are configured.
+    pub fn start(&self, bus: &EventBus, shards: &RedisShards) {
+        if !self.config.job_failed.is_empty() || !self.config.dlq_receive.is_empty() {
+            super::spawn_sink("hooks", self.clone(), bus, &[], shards);
+        }
+    }
+
+    /// Run the `queue_created` hook for a newly created queue.
+    pub fn queue_created(&self, queue_name: &str) {
+        let event = QueueEvent { event: "queue_created", queue: queue_name, timestamp: DateTime::now() };
+        self.run("queue_created", &self.config.queue_created, serde_json::to_vec(&event).unwrap());
+    }
+
+    /// Get the name and command of the hook run for given job event, if any.
+    fn job_hook(&self, event: EventKind) -> Option<(&'static str, &[String])> {
+        match event {
+            EventKind::Failed => Some(("job_failed", &self.config.job_failed)),
+            EventKind::Quarantined => Some(("dlq_receive", &self.config.dlq_receive)),
+            _ => None,
+        }
+    }
+
+    /// Run a hook's command in the background with given input, doing nothing if it has no command, or if too many
+    /// commands are already running.
+    fn run(&self, name: &'static str, command: &[String], input: Vec<u8>) {
+        if command.is_empty() {
+            return;
+        }
+        let permit = match self.running.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                warn!("{} hook not run, {} hook commands already running", name, self.config.max_running);
+                METRICS.record_hook_dropped();
+                return;
+            }
+        };
+        let command = command.to_vec();
+        let timeout = self.config.timeout.0;
+        actix_rt::spawn(async move {
+            // held until the command finishes or is killed
+            let _permit = permit;
+            let spawned = Command::new(&command[0])
+                .args(&command[1..])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn();
+            let mut child = match spawned {
+                Ok(child) => child,
+                Err(err) => {
+                    error!("Failed to run {} hook: {}", name, err);
+                    return;
+                }
+            };
+            let stdin = child.stdin.take();
+
+            // a command that doesn't read its input is killed once it times out, like one that doesn't exit
+            let finished = tokio::time::timeout(timeout, async move {
+                // stdin is closed once written, so that commands reading until EOF can finish
+                if let Some(mut stdin) = stdin {
+                    if let Err(err) = stdin.write_all(&input).await {
+                        debug!("{} hook didn't read all of its input: {}", name, err);
+                    }
+                }
+                child.wait_with_output().await
+            });
+            match finished.await {
+                Ok(Ok(output)) if output.status.success() => debug!("{} hook finished", name),
+                Ok(Ok(output)) => warn!(
+                    "{} hook failed with {}: {}",
+                    name,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Ok(Err(err)) => error!("Failed to wait for {} hook: {}", name, err),
+                Err(_) => warn!("{} hook killed after running for longer than {:?}", name, timeout),
+            }
+        });
+    }
+}
+
+impl EventSink for Hooks {
+    fn publish<'a>(&'a mut self, event: &'a Event) -> Pin<Box<dyn Future<Output = Result<(), String>> + 'a>> {
+        if let Some((name, command)) = self.job_hook(event.event) {
+            self.run(name, command, serde_json::to_vec(event).unwrap());
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn job_hooks() {
+        let hooks = Hooks::new(&HooksConfig { dlq_receive: vec!["cat".to_owned()], ..Default::default() });
+        assert_eq!(hooks.job_hook(EventKind::Failed), Some(("job_failed", &[][..])));
+        assert_eq!(hooks.job_hook(EventKind::Quarantined), Some(("dlq_receive", &["cat".to_owned()][..])));
+        assert_eq!(hooks.job_hook(EventKind::Completed), None);
+    }
+
+    #[actix_rt::test]
+    async fn bounded() {
+        let hooks = Hooks::new(&HooksConfig { max_running: 1, ..Default::default() });
+        hooks.run("queue_created", &["sleep".to_owned(), "1".to_owned()], Vec::new());
+        assert_eq!(hooks.running.available_permits(), 0);
+
+        // dropped rather than run while the first command is running
+        hooks.run("queue_created", &["true".to_owned()], Vec::new());
+        assert_eq!(hooks.running.available_permits(), 0);
+
+        // commands that are never run don't use a permit
+        hooks.run("queue_created", &[], Vec::new());
+        assert_eq!(hooks.running.available_permits(), 0);
+    }
+}
@@ -0,0 +1,58 @@
+//! Publishes job lifecycle events to a Kafka topic.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use futures::future::ready;
+use log::info;
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{BaseRecord, DefaultProducerContext, ThreadedProducer};
+
+use super::{Event, EventSink, Serialization};
+use crate::config::KafkaConfig;
+
+/// Sink publishing events to a Kafka topic.
+///
+/// Messages are keyed by job ID, so that all events for a job are written to the same partition in order.
+pub struct KafkaSink {
+    producer: ThreadedProducer<DefaultProducerContext>,
+    topic: String,
+    serialization: Serialization,
+}
+
+impl KafkaSink {
+    pub fn new(config: &KafkaConfig) -> Result<Self, String> {
+        let mut client_config = ClientConfig::new();
+        client_config.set("bootstrap.servers", &config.brokers);
+        for (key, value) in &config.properties {
+            client_config.set(key, value);
+        }
+
+        let producer = client_config
+            .create()
+            .map_err(|err| format!("Failed to create Kafka producer: {}", err))?;
+
+        info!(
+            "Publishing job events to Kafka topic \"{}\" on {}",
+            &config.topic, &config.brokers
+        );
+        Ok(Self {
+            producer,
+            topic: config.topic.clone(),
+            serialization: config.serialization,
+        })
+    }
+}
+
+impl EventSink for KafkaSink {
+    fn publish<'a>(&'a mut self, event: &'a Event) -> Pin<Box<dyn Future<Output = Result<(), String>> + 'a>> {
+        // producer queues messages and sends them from a background thread, so this doesn't block
+        let key = event.job_id.to_string();
+        let payload = event.serialize(self.serialization);
+        let result = self
+            .producer
+            .send(BaseRecord::to(&self.topic).key(&key).payload(&payload))
+            .map_err(|(err, _)| err.to_string());
+        Box::pin(ready(result))
+    }
+}
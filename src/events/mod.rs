@@ -0,0 +1,315 @@
+//! Job lifecycle events, published on an internal bus and forwarded to any configured external sinks, so that
+//! consumers can follow job history without polling the API.
+
+#[cfg(feature = "amqp")]
+pub mod amqp;
+pub mod anomaly;
+pub mod hooks;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod notifications;
+
+use std::future::Future;
+use std::pin::Pin;
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, RecvError};
+
+use crate::application::shard::RedisShards;
+use crate::application::RedisManager;
+use crate::config::EventsConfig;
+use crate::models::{job, DateTime};
+
+/// Number of events buffered per subscriber before the slowest subscriber starts missing events.
+const BUS_CAPACITY: usize = 1024;
+
+/// Type of change in a job's lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Created,
+    Started,
+    Completed,
+    Failed,
+    TimedOut,
+    Cancelled,
+    Retried,
+    Quarantined,
+}
+
+impl EventKind {
+    /// Get the event corresponding to a job being moved to given status, if any.
+    pub fn from_status(status: &job::Status) -> Option<Self> {
+        match status {
+            job::Status::Running => Some(EventKind::Started),
+            job::Status::Completed => Some(EventKind::Completed),
+            job::Status::Failed => Some(EventKind::Failed),
+            job::Status::TimedOut => Some(EventKind::TimedOut),
+            job::Status::Cancelled => Some(EventKind::Cancelled),
+            job::Status::Quarantined => Some(EventKind::Quarantined),
+            job::Status::Queued => None,
+        }
+    }
+
+    /// Get the name of this event, e.g. "created".
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Created => "created",
+            EventKind::Started => "started",
+            EventKind::Completed => "completed",
+            EventKind::Failed => "failed",
+            EventKind::TimedOut => "timed_out",
+            EventKind::Cancelled => "cancelled",
+            EventKind::Retried => "retried",
+            EventKind::Quarantined => "quarantined",
+        }
+    }
+
+    /// Get a dot separated type name for this event, e.g. "ocypod.job.created".
+    pub fn type_name(self) -> String {
+        format!("ocypod.job.{}", self.as_str())
+    }
+}
+
+/// A single job lifecycle event.
+#[derive(Clone, Debug, Serialize)]
+pub struct Event {
+    pub event: EventKind,
+    pub job_id: u64,
+
+    /// Queue the job belongs to, if known at the point the event was raised.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue: Option<String>,
+
+    pub timestamp: DateTime,
+}
+
+impl Event {
+    pub fn new(event: EventKind, job_id: u64, queue: Option<&str>) -> Self {
+        Self {
+            event,
+            job_id,
+            queue: queue.map(str::to_owned),
+            timestamp: DateTime::now(),
+        }
+    }
+
+    /// Serialize this event in given format for publishing to an external sink.
+    pub fn serialize(&self, format: Serialization) -> Vec<u8> {
+        let value = match format {
+            Serialization::Json => serde_json::to_value(self),
+            Serialization::CloudEvents => serde_json::to_value(CloudEvent {
+                specversion: "1.0",
+                id: format!("{}-{}", self.job_id, self.timestamp),
+                source: "ocypod",
+                event_type: self.event.type_name(),
+                subject: self.job_id.to_string(),
+                time: &self.timestamp,
+                datacontenttype: "application/json",
+                data: self,
+            }),
+        };
+        value.unwrap().to_string().into_bytes()
+    }
+}
+
+/// Format events are serialized in when published to external sinks.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Serialization {
+    /// Plain JSON representation of `Event`.
+    #[default]
+    Json,
+
+    /// JSON in a CloudEvents 1.0 envelope, with the plain JSON event as its data.
+    CloudEvents,
+}
+
+/// CloudEvents 1.0 structured mode envelope.
+#[derive(Serialize)]
+struct CloudEvent<'a> {
+    specversion: &'static str,
+    id: String,
+    source: &'static str,
+    #[serde(rename = "type")]
+    event_type: String,
+    subject: String,
+    time: &'a DateTime,
+    datacontenttype: &'static str,
+    data: &'a Event,
+}
+
+/// Internal bus that job lifecycle events are published to, and that sinks subscribe to.
+///
+/// Publishing never blocks or fails, events are dropped if nothing is subscribed.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BUS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Publish an event to all current subscribers.
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Convenience function for publishing a new event.
+    pub fn job_event(&self, event: EventKind, job_id: u64, queue: Option<&str>) {
+        self.publish(Event::new(event, job_id, queue));
+    }
+
+    /// Get a receiver for all events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Destination that job events are forwarded to, e.g. a message broker.
+pub trait EventSink {
+    /// Publish a single event, returning a description of the error on failure.
+    fn publish<'a>(&'a mut self, event: &'a Event) -> Pin<Box<dyn Future<Output = Result<(), String>> + 'a>>;
+}
+
+/// Start all event sinks found in given configuration.
+///
+/// Returns an error if a sink can't be created, or if a configured sink requires a feature this wasn't built with.
+#[cfg_attr(not(any(feature = "kafka", feature = "nats", feature = "amqp")), allow(unused_variables))]
+pub fn start_sinks(
+    config: &EventsConfig,
+    bus: &EventBus,
+    shards: &RedisShards,
+) -> Result<(), String> {
+    if let Some(kafka_config) = &config.kafka {
+        #[cfg(feature = "kafka")]
+        spawn_sink("Kafka", kafka::KafkaSink::new(kafka_config)?, bus, &kafka_config.queues, shards);
+        #[cfg(not(feature = "kafka"))]
+        {
+            let _ = kafka_config;
+            return Err(missing_feature("Kafka", "kafka"));
+        }
+    }
+
+    if let Some(nats_config) = &config.nats {
+        #[cfg(feature = "nats")]
+        spawn_sink("NATS", nats::NatsSink::new(nats_config)?, bus, &nats_config.queues, shards);
+        #[cfg(not(feature = "nats"))]
+        {
+            let _ = nats_config;
+            return Err(missing_feature("NATS", "nats"));
+        }
+    }
+
+    if let Some(amqp_config) = &config.amqp {
+        #[cfg(feature = "amqp")]
+        spawn_sink("AMQP", amqp::AmqpSink::new(amqp_config), bus, &amqp_config.queues, shards);
+        #[cfg(not(feature = "amqp"))]
+        {
+            let _ = amqp_config;
+            return Err(missing_feature("AMQP", "amqp"));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(any(not(feature = "kafka"), not(feature = "nats"), not(feature = "amqp")))]
+fn missing_feature(sink: &str, feature: &str) -> String {
+    format!(
+        "{} events are configured, but Ocypod was built without the \"{}\" feature",
+        sink, feature
+    )
+}
+
+/// Start forwarding events published on given bus to a sink.
+///
+/// If any queues are given, only events for jobs on those queues are forwarded. Events raised without a known queue
+/// have it looked up from Redis in that case.
+pub fn spawn_sink<S: EventSink + 'static>(
+    name: &'static str,
+    mut sink: S,
+    bus: &EventBus,
+    queues: &[String],
+    shards: &RedisShards,
+) {
+    let mut receiver = bus.subscribe();
+    let queues = queues.to_vec();
+    let shards = shards.clone();
+    actix_rt::spawn(async move {
+        loop {
+            let mut event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("{} event publisher fell behind, {} event(s) dropped", name, missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            if !queues.is_empty() {
+                if event.queue.is_none() {
+                    let mut conn = shards.for_job(event.job_id).get();
+                    event.queue = RedisManager::job_queue(&mut conn, event.job_id).await.ok();
+                }
+                if !event.queue.as_ref().is_some_and(|queue| queues.contains(queue)) {
+                    continue;
+                }
+            }
+
+            if let Err(err) = sink.publish(&event).await {
+                error!("Failed to publish job {} event to {}: {}", event.job_id, name, err);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serialize_json() {
+        let event = Event::new(EventKind::TimedOut, 123, Some("default"));
+        let json: serde_json::Value = serde_json::from_slice(&event.serialize(Serialization::Json)).unwrap();
+        assert_eq!(json["event"], "timed_out");
+        assert_eq!(json["job_id"], 123);
+        assert_eq!(json["queue"], "default");
+    }
+
+    #[test]
+    fn serialize_cloud_events() {
+        let event = Event::new(EventKind::Created, 5, None);
+        let json: serde_json::Value = serde_json::from_slice(&event.serialize(Serialization::CloudEvents)).unwrap();
+        assert_eq!(json["specversion"], "1.0");
+        assert_eq!(json["type"], "ocypod.job.created");
+        assert_eq!(json["subject"], "5");
+        assert_eq!(json["data"]["job_id"], 5);
+        assert!(json["data"].get("queue").is_none());
+    }
+
+    #[test]
+    fn bus_delivers_to_subscribers() {
+        let bus = EventBus::new();
+        bus.job_event(EventKind::Created, 1, None); // no subscribers, dropped
+
+        let mut receiver = bus.subscribe();
+        bus.job_event(EventKind::Started, 2, Some("a"));
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(event.event, EventKind::Started);
+        assert_eq!(event.job_id, 2);
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Publishes job lifecycle events to NATS subjects.
+//!
+//! Uses the `async-nats` client, which handles TLS, authentication, and reconnecting. It runs on a Tokio 1 runtime, so
+//! is driven from a dedicated thread, which events are passed to through a bounded channel.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use async_nats::{ConnectOptions, ServerAddr};
+use futures::future::ready;
+use log::{error, info, warn};
+use tokio1::sync::mpsc;
+
+use super::{Event, EventSink, Serialization};
+use crate::config::NatsConfig;
+
+/// Number of messages buffered while the connection to NATS is down, before events are dropped.
+const BUFFER_SIZE: usize = 1024;
+
+/// Time to wait before retrying a message the client failed to publish.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Sink publishing each event to the subject `<subject_prefix>.<event>`, e.g. "ocypod.job.completed".
+pub struct NatsSink {
+    sender: mpsc::Sender<(String, Vec<u8>)>,
+    subject_prefix: String,
+    serialization: Serialization,
+}
+
+impl NatsSink {
+    pub fn new(config: &NatsConfig) -> Result<Self, String> {
+        let server = ServerAddr::from_str(&config.url).map_err(|err| format!("Invalid NATS URL: {}", err))?;
+        let mut options = ConnectOptions::new()
+            .name("ocypod")
+            .retry_on_initial_connect()
+            .require_tls(server.tls_required());
+        options = match (server.username(), server.password()) {
+            (Some(user), Some(pass)) => options.user_and_password(user.to_owned(), pass.to_owned()),
+            (Some(token), None) => options.token(token.to_owned()),
+            _ => options,
+        };
+        info!(
+            "Publishing job events to NATS subjects \"{}.*\" on {}:{}",
+            &config.subject_prefix,
+            server.host(),
+            server.port()
+        );
+
+        let runtime = tokio1::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|err| format!("Failed to start NATS runtime: {}", err))?;
+        let (sender, receiver) = mpsc::channel(BUFFER_SIZE);
+        thread::Builder::new()
+            .name("ocypod-nats".to_owned())
+            .spawn(move || runtime.block_on(run_client(server, options, receiver)))
+            .map_err(|err| format!("Failed to start NATS thread: {}", err))?;
+        Ok(Self {
+            sender,
+            subject_prefix: config.subject_prefix.clone(),
+            serialization: config.serialization,
+        })
+    }
+}
+
+impl EventSink for NatsSink {
+    fn publish<'a>(&'a mut self, event: &'a Event) -> Pin<Box<dyn Future<Output = Result<(), String>> + 'a>> {
+        let subject = format!("{}.{}", self.subject_prefix, event.event.as_str());
+        let result = self
+            .sender
+            .try_send((subject, event.serialize(self.serialization)))
+            .map_err(|_| "NATS connection unavailable and buffer full, event dropped".to_owned());
+        Box::pin(ready(result))
+    }
+}
+
+/// Connect to the NATS server, publishing messages from the receiver until all senders are dropped.
+///
+/// The client buffers messages and reconnects itself while the server is unavailable. A message it fails to publish is
+/// retried until it succeeds rather than being dropped, so that events are published in order, with any new events
+/// dropped by the sink once the channel is full.
+async fn run_client(server: ServerAddr, options: ConnectOptions, mut receiver: mpsc::Receiver<(String, Vec<u8>)>) {
+    let client = match options.connect(server).await {
+        Ok(client) => client,
+        Err(err) => {
+            error!("Failed to connect to NATS: {}", err);
+            return;
+        }
+    };
+
+    while let Some((subject, payload)) = receiver.recv().await {
+        while let Err(err) = client.publish(subject.clone(), payload.clone().into()).await {
+            warn!("Failed to publish event to NATS, retrying in {:?}: {}", RETRY_DELAY, err);
+            tokio1::time::sleep(RETRY_DELAY).await;
+        }
+    }
+    if let Err(err) = client.flush().await {
+        warn!("Failed to flush events to NATS: {}", err);
+    }
+}
@@ -0,0 +1,199 @@
+//! Rule engine for notifying a Slack-compatible webhook when a queue's failures spike, or its health changes.
+//!
+//! Rules are periodically evaluated by the notification monitor against counts of the jobs that have finished on each
+//! queue over their window, while health rules are checked as each job finishes. Counts and queues' health are shared
+//! by all servers in Redis, see `application::notify`.
+
+use serde::{Deserialize, Serialize};
+
+use super::{Event, EventKind};
+use crate::config::{HealthRule, NotificationRule};
+
+/// Message used for rules without a custom template.
+const DEFAULT_TEMPLATE: &str =
+    "Ocypod queue \"{queue}\": {metric} is {value} over the last {window} (threshold: {threshold})";
+
+/// Message used for health rules without a custom failing template.
+const DEFAULT_FAILING_TEMPLATE: &str = "Ocypod queue \"{queue}\" is failing, after {count} jobs in a row completed";
+
+/// Message used for health rules without a custom recovered template.
+const DEFAULT_RECOVERED_TEMPLATE: &str =
+    "Ocypod queue \"{queue}\" has recovered, after {count} jobs failed or timed out";
+
+/// Measure of a queue's failures that a rule is evaluated against.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Number of jobs that failed.
+    Failed,
+
+    /// Number of jobs that timed out.
+    TimedOut,
+
+    /// Fraction of finished jobs (completed, failed or timed out) that failed or timed out, between 0 and 1.
+    FailureRate,
+}
+
+impl Metric {
+    fn as_str(self) -> &'static str {
+        match self {
+            Metric::Failed => "failed",
+            Metric::TimedOut => "timed_out",
+            Metric::FailureRate => "failure_rate",
+        }
+    }
+}
+
+/// Number of jobs on a queue that finished each way over a rule's window.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Counts {
+    pub completed: u64,
+    pub failed: u64,
+    pub timed_out: u64,
+}
+
+impl Counts {
+    /// Add given number of events of given kind to these counts.
+    pub fn add(&mut self, event: EventKind, count: u64) {
+        match event {
+            EventKind::Completed => self.completed += count,
+            EventKind::Failed => self.failed += count,
+            EventKind::TimedOut => self.timed_out += count,
+            _ => (),
+        }
+    }
+}
+
+/// Change in a queue's health, as tracked by a health rule.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HealthChange {
+    /// A healthy queue has started failing, after given number of jobs in a row completed.
+    Failing(u64),
+
+    /// A failing queue has recovered, after given number of jobs failed or timed out.
+    Recovered(u64),
+}
+
+/// Whether given event is relevant to notification rules.
+pub fn is_relevant(event: &Event) -> bool {
+    matches!(event.event, EventKind::Completed | EventKind::Failed | EventKind::TimedOut)
+}
+
+/// Get the value of a rule's metric for given counts, or `None` if there are too few jobs to measure a rate.
+pub fn measure(rule: &NotificationRule, counts: &Counts) -> Option<f64> {
+    match rule.metric {
+        Metric::Failed => Some(counts.failed as f64),
+        Metric::TimedOut => Some(counts.timed_out as f64),
+        Metric::FailureRate => {
+            let total = counts.completed + counts.failed + counts.timed_out;
+            if total == 0 || total < rule.min_jobs {
+                None
+            } else {
+                Some((counts.failed + counts.timed_out) as f64 / total as f64)
+            }
+        }
+    }
+}
+
+/// Get the message to send for a change in given queue's health.
+pub fn health_message(rule: &HealthRule, queue: &str, change: HealthChange) -> String {
+    match change {
+        HealthChange::Failing(count) => {
+            let template = rule.failing_template.as_deref().unwrap_or(DEFAULT_FAILING_TEMPLATE);
+            render_health(template, queue, count)
+        }
+        HealthChange::Recovered(count) => {
+            let template = rule.recovered_template.as_deref().unwrap_or(DEFAULT_RECOVERED_TEMPLATE);
+            render_health(template, queue, count)
+        }
+    }
+}
+
+/// Fill in a rule's message template.
+pub fn render(rule: &NotificationRule, queue: &str, value: f64) -> String {
+    let format_value = |value: f64| match rule.metric {
+        Metric::FailureRate => format!("{:.0}%", value * 100.0),
+        _ => format!("{}", value),
+    };
+    rule.template
+        .as_deref()
+        .unwrap_or(DEFAULT_TEMPLATE)
+        .replace("{queue}", queue)
+        .replace("{metric}", rule.metric.as_str())
+        .replace("{value}", &format_value(value))
+        .replace("{threshold}", &format_value(rule.threshold))
+        .replace("{window}", &humantime::format_duration(rule.window.0).to_string())
+}
+
+/// Fill in a health rule's message template.
+fn render_health(template: &str, queue: &str, count: u64) -> String {
+    template.replace("{queue}", queue).replace("{count}", &count.to_string())
+}
+
+/// Get the JSON body to send to a Slack-compatible webhook for given message.
+pub fn webhook_body(message: &str) -> serde_json::Value {
+    serde_json::json!({ "text": message })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::Duration;
+
+    fn rule(metric: Metric, threshold: f64) -> NotificationRule {
+        NotificationRule {
+            queue: None,
+            metric,
+            threshold,
+            window: Duration::from_secs(300),
+            cooldown: None,
+            min_jobs: 1,
+            template: None,
+        }
+    }
+
+    #[test]
+    fn failure_count() {
+        let rule = rule(Metric::Failed, 2.0);
+        let mut counts = Counts::default();
+        counts.add(EventKind::Failed, 2);
+        counts.add(EventKind::TimedOut, 1);
+        counts.add(EventKind::Started, 1);
+        assert_eq!(counts, Counts { completed: 0, failed: 2, timed_out: 1 });
+        assert_eq!(measure(&rule, &counts), Some(2.0));
+        assert_eq!(
+            render(&rule, "a", 2.0),
+            "Ocypod queue \"a\": failed is 2 over the last 5m (threshold: 2)".to_owned()
+        );
+    }
+
+    #[test]
+    fn failure_rate() {
+        let mut rate_rule = rule(Metric::FailureRate, 0.5);
+        rate_rule.min_jobs = 4;
+        rate_rule.template = Some("{queue} {metric} {value}".to_owned());
+
+        let mut counts = Counts { completed: 1, failed: 1, timed_out: 1 };
+        assert_eq!(measure(&rate_rule, &counts), None); // too few jobs
+        assert_eq!(measure(&rate_rule, &Counts::default()), None);
+
+        counts.add(EventKind::Completed, 1);
+        assert_eq!(measure(&rate_rule, &counts), Some(0.5));
+        assert_eq!(render(&rate_rule, "a", 0.5), "a failure_rate 50%".to_owned());
+    }
+
+    #[test]
+    fn health_messages() {
+        let rule = HealthRule {
+            queue: None,
+            successes: 3,
+            failing_template: None,
+            recovered_template: Some("{queue} recovered after {count}".to_owned()),
+        };
+        assert_eq!(
+            health_message(&rule, "a", HealthChange::Failing(4)),
+            "Ocypod queue \"a\" is failing, after 4 jobs in a row completed".to_owned()
+        );
+        assert_eq!(health_message(&rule, "a", HealthChange::Recovered(3)), "a recovered after 3".to_owned());
+    }
+}
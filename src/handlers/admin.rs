@@ -0,0 +1,99 @@
+//! HTTP handlers for the `/admin` endpoints.
+
+use actix_web::{web, HttpResponse, Responder};
+use log::info;
+use serde::Serialize;
+
+use crate::application::slowlog::SlowLogEntry;
+use crate::logging::LogSettings;
+use crate::models::{ApplicationState, Duration, OcyError};
+
+#[derive(Serialize)]
+struct SlowLogResponse {
+    threshold: Option<Duration>,
+    entries: Vec<SlowLogEntry>,
+}
+
+/// Handles `GET /admin/log_level` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON containing the current log level, and log levels of any specific modules
+pub async fn log_level(data: web::Data<ApplicationState>) -> impl Responder {
+    HttpResponse::Ok().json(data.log_filter.settings())
+}
+
+/// Handles `PUT /admin/log_level` requests.
+///
+/// Replaces the current log level, and log levels of specific modules, taking effect immediately. Settings aren't
+/// persisted, so are reset to the configured log level when the server restarts.
+///
+/// # Returns
+///
+/// * 200 - JSON containing the new log settings
+/// * 400 - invalid log level or module name given
+pub async fn set_log_level(json: web::Json<LogSettings>, data: web::Data<ApplicationState>) -> impl Responder {
+    let settings = json.into_inner();
+    match data.log_filter.set(settings.clone()) {
+        Ok(()) => {
+            info!("Log level set to {} (modules: {:?})", settings.level, settings.modules);
+            HttpResponse::Ok().json(settings)
+        }
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(err) => HttpResponse::InternalServerError().body(err),
+    }
+}
+
+/// Handles `GET /admin/slowlog` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON containing the slow log threshold, and recorded slow requests, most recent first
+pub async fn slowlog(data: web::Data<ApplicationState>) -> impl Responder {
+    HttpResponse::Ok().json(SlowLogResponse {
+        threshold: data.slow_log.threshold().map(Duration),
+        entries: data.slow_log.entries(),
+    })
+}
+
+/// Handles `DELETE /admin/slowlog` requests.
+///
+/// Removes all recorded slow requests.
+///
+/// # Returns
+///
+/// * 204 - slow log cleared
+pub async fn reset_slowlog(data: web::Data<ApplicationState>) -> impl Responder {
+    data.slow_log.reset();
+    HttpResponse::NoContent()
+}
+
+/// Handles `POST /admin/drain` requests.
+///
+/// Puts the server into drain mode, e.g. from a Kubernetes `preStop` hook before it's shut down. While draining, no
+/// jobs are handed out to workers or pushed to callback URLs, and `/health/ready` reports the server isn't ready, but
+/// other requests are handled as usual so that running jobs can be finished.
+///
+/// # Returns
+///
+/// * 204 - server is draining
+pub async fn drain(data: web::Data<ApplicationState>) -> impl Responder {
+    if !data.drain.set(true) {
+        info!("Draining, no longer handing out jobs");
+    }
+    HttpResponse::NoContent()
+}
+
+/// Handles `POST /admin/undrain` requests.
+///
+/// Takes the server out of drain mode, so that it hands out jobs again.
+///
+/// # Returns
+///
+/// * 204 - server is no longer draining
+pub async fn undrain(data: web::Data<ApplicationState>) -> impl Responder {
+    if data.drain.set(false) {
+        info!("No longer draining, handing out jobs");
+    }
+    HttpResponse::NoContent()
+}
@@ -0,0 +1,100 @@
+//! HTTP handlers for the `/backup` endpoints.
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::error;
+
+use crate::application::RedisManager;
+use crate::models::{ApplicationState, OcyError};
+
+/// Handles `GET /backup/queue/{queue_name}` requests.
+///
+/// Exports a snapshot of the queue's settings and all of its jobs, read atomically so that it's internally consistent
+/// even if the queue is being written to. If the client accepts gzip encoding, the snapshot is compressed as it's
+/// serialised.
+///
+/// # Returns
+///
+/// * 200 - JSON snapshot of the queue
+/// * 400 - invalid queue name
+/// * 404 - queue not found
+pub async fn queue(req: HttpRequest, path: web::Path<String>, data: web::Data<ApplicationState>) -> impl Responder {
+    let queue_name = path.into_inner();
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
+
+    match RedisManager::queue_snapshot(&mut conn, &queue_name).await {
+        Ok(snapshot) if accepts_gzip(&req) => match gzip_json(&snapshot) {
+            Ok(body) => HttpResponse::Ok()
+                .content_type("application/json")
+                .header(header::CONTENT_ENCODING, "gzip")
+                .body(body),
+            Err(err) => {
+                error!("[queue:{}] failed to compress snapshot: {}", &queue_name, err);
+                HttpResponse::InternalServerError().body(err.to_string())
+            }
+        },
+        Ok(snapshot) => HttpResponse::Ok().json(snapshot),
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to export snapshot: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to export snapshot: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Check whether a request's `Accept-Encoding` header allows gzip encoded responses.
+fn accepts_gzip(req: &HttpRequest) -> bool {
+    let accept_encoding = match req.headers().get(header::ACCEPT_ENCODING).and_then(|value| value.to_str().ok()) {
+        Some(accept_encoding) => accept_encoding,
+        None => return false,
+    };
+
+    accept_encoding.split(',').any(|encoding| {
+        let mut params = encoding.split(';').map(str::trim);
+        let name = params.next().unwrap_or_default();
+        let refused = params.any(|param| param.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0));
+        name.eq_ignore_ascii_case("gzip") && !refused
+    })
+}
+
+/// Serialise given value as gzip compressed JSON, compressing it as it's written rather than after.
+fn gzip_json<T: serde::Serialize>(value: &T) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut encoder, value)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use actix_web::test::TestRequest;
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    #[test]
+    fn gzip_accepted() {
+        let accepts = |value: &str| accepts_gzip(&TestRequest::with_header("accept-encoding", value).to_http_request());
+        assert!(accepts("gzip"));
+        assert!(accepts("br, GZIP;q=0.5"));
+        assert!(!accepts("gzip;q=0"));
+        assert!(!accepts("deflate, br"));
+        assert!(!accepts_gzip(&TestRequest::default().to_http_request()));
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        let value = serde_json::json!({"queue": "a", "jobs": [{"id": 1}, {"id": 2}]});
+        let mut json = String::new();
+        GzDecoder::new(&gzip_json(&value).unwrap()[..]).read_to_string(&mut json).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), value);
+    }
+}
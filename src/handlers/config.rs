@@ -0,0 +1,54 @@
+//! HTTP handlers for the `/config` endpoints, exporting the configuration this server is running with, so that it can
+//! be compared against the desired state, e.g. by infrastructure-as-code tooling.
+
+use std::collections::BTreeMap;
+
+use actix_web::{web, HttpResponse, Responder};
+use log::error;
+
+use crate::application::RedisManager;
+use crate::models::{ApplicationState, OcyError};
+
+/// Handles `GET /config` requests.
+///
+/// Returns the server's configuration, including default values of any settings not given in its configuration
+/// file, and the current log level, which may have been changed since startup. Secrets such as API keys and
+/// passwords in URLs are redacted.
+///
+/// # Returns
+///
+/// * 200 - JSON containing the server's configuration
+pub async fn index(data: web::Data<ApplicationState>) -> impl Responder {
+    let mut config = match serde_json::to_value(&data.config) {
+        Ok(config) => config,
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+    config["server"]["log_level"] = data.log_filter.settings().level.into();
+    HttpResponse::Ok().json(config)
+}
+
+/// Handles `GET /config/queues` requests.
+///
+/// Returns the settings of every queue currently stored in Redis, across all shards, with default values of any
+/// settings not given when the queue was created.
+///
+/// # Returns
+///
+/// * 200 - JSON object mapping queue names to their settings
+pub async fn queues(data: web::Data<ApplicationState>) -> impl Responder {
+    let mut all_settings = BTreeMap::new();
+    for pool in data.redis_shards.all() {
+        match RedisManager::all_queue_settings(&mut pool.get()).await {
+            Ok(settings) => all_settings.extend(settings),
+            Err(OcyError::RedisConnection(err)) => {
+                error!("Failed to fetch queue settings: {}", err);
+                return HttpResponse::ServiceUnavailable().body(err);
+            }
+            Err(err) => {
+                error!("Failed to fetch queue settings: {}", err);
+                return HttpResponse::InternalServerError().body(err);
+            }
+        }
+    }
+    HttpResponse::Ok().json(all_settings)
+}
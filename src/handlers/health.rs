@@ -3,6 +3,8 @@
 use actix_web::{web, HttpResponse, Responder};
 use serde::Serialize;
 
+use crate::application::monitor;
+use crate::middleware::circuit_breaker::BreakerState;
 use crate::models::ApplicationState;
 
 #[derive(Serialize)]
@@ -37,19 +39,78 @@ impl Health {
 }
 
 pub async fn index(data: web::Data<ApplicationState>) -> impl Responder {
-    let mut conn = data.redis_conn_manager.clone();
-
-    let reply: String = match redis::cmd("PING").query_async(&mut conn).await {
-        Ok(s) => s,
-        Err(err) => return HttpResponse::Ok().json(Health::new_from_error(err.to_string())),
-    };
-
-    match reply.as_ref() {
-        "PONG" => HttpResponse::Ok().json(Health::new_healthy()),
-        other => HttpResponse::Ok().json(Health::new_from_error(format!(
-            "unexpected PING response from Redis: {}",
-            other
-        ))),
+    // every shard must be reachable for the server to be healthy
+    for pool in data.redis_shards.all() {
+        let reply: String = match redis::cmd("PING").query_async(&mut pool.get()).await {
+            Ok(s) => s,
+            Err(err) => return HttpResponse::Ok().json(Health::new_from_error(err.to_string())),
+        };
+
+        if reply != "PONG" {
+            return HttpResponse::Ok().json(Health::new_from_error(format!(
+                "unexpected PING response from Redis: {}",
+                reply
+            )));
+        }
+    }
+    HttpResponse::Ok().json(Health::new_healthy())
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    status: HealthStatus,
+    breaker: BreakerState,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    draining: bool,
+
+    /// Names of background monitors whose loops haven't run recently.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stalled_monitors: Vec<&'static str>,
+}
+
+/// Handles `GET /health/ready` requests. Reports whether the server is ready to handle requests,
+/// based on the state of the Redis circuit breaker, whether the server is draining, and whether its
+/// timeout, retry, and expiry monitors are still running.
+///
+/// # Returns
+///
+/// * 200 - server is ready, circuit breaker closed or half open
+/// * 503 - circuit breaker is open, with `Retry-After` header set, server is draining, or a monitor is stalled
+pub async fn ready(data: web::Data<ApplicationState>) -> impl Responder {
+    let breaker = &data.circuit_breaker;
+    let draining = data.drain.is_draining();
+    let stalled_monitors: Vec<&'static str> = monitor::stalled_monitors(&data.config.server)
+        .into_iter()
+        .map(|(monitor, _)| monitor.label())
+        .collect();
+    match breaker.retry_after_secs() {
+        Some(secs) => HttpResponse::ServiceUnavailable()
+            .header("Retry-After", secs.to_string())
+            .json(Readiness {
+                status: HealthStatus::Unhealthy,
+                breaker: BreakerState::Open,
+                retry_after: Some(secs),
+                draining,
+                stalled_monitors,
+            }),
+        None if draining || !stalled_monitors.is_empty() => HttpResponse::ServiceUnavailable().json(Readiness {
+            status: HealthStatus::Unhealthy,
+            breaker: breaker.state(),
+            retry_after: None,
+            draining,
+            stalled_monitors,
+        }),
+        None => HttpResponse::Ok().json(Readiness {
+            status: HealthStatus::Healthy,
+            breaker: breaker.state(),
+            retry_after: None,
+            draining,
+            stalled_monitors,
+        }),
     }
 }
 
@@ -71,5 +132,41 @@ mod test {
             serde_json::to_string(&h).unwrap(),
             "{\"status\":\"unhealthy\",\"error\":\"message\"}"
         );
+
+        let r = Readiness {
+            status: HealthStatus::Healthy,
+            breaker: BreakerState::HalfOpen,
+            retry_after: None,
+            draining: false,
+            stalled_monitors: Vec::new(),
+        };
+        assert_eq!(
+            serde_json::to_string(&r).unwrap(),
+            "{\"status\":\"healthy\",\"breaker\":\"half_open\"}"
+        );
+
+        let r = Readiness {
+            status: HealthStatus::Unhealthy,
+            breaker: BreakerState::Closed,
+            retry_after: None,
+            draining: true,
+            stalled_monitors: Vec::new(),
+        };
+        assert_eq!(
+            serde_json::to_string(&r).unwrap(),
+            "{\"status\":\"unhealthy\",\"breaker\":\"closed\",\"draining\":true}"
+        );
+
+        let r = Readiness {
+            status: HealthStatus::Unhealthy,
+            breaker: BreakerState::Closed,
+            retry_after: None,
+            draining: false,
+            stalled_monitors: vec!["retry"],
+        };
+        assert_eq!(
+            serde_json::to_string(&r).unwrap(),
+            "{\"status\":\"unhealthy\",\"breaker\":\"closed\",\"stalled_monitors\":[\"retry\"]}"
+        );
     }
 }
@@ -1,24 +1,29 @@
 //! Handlers for getting general information about the Ocypod server as a whole.
 
+use std::time::Instant;
+
 use actix_web::{web, HttpResponse, Responder};
 use log::error;
 
-use crate::application::RedisManager;
 use crate::models::ApplicationState;
-use crate::models::OcyError;
+use crate::models::{Features, OcyError};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-/// Handles `GET /info` requests.
+/// Handles `GET /info` requests. Responses may be cached for the configured `response_cache.info` time.
 ///
 /// # Returns
 ///
 /// * 200 - JSON containing summary of server information
 pub async fn index(data: web::Data<ApplicationState>) -> impl Responder {
-    let mut conn = data.redis_conn_manager.clone();
-
-    match RedisManager::server_info(&mut conn).await {
-        Ok(info) => HttpResponse::Ok().json(info),
+    if let Some(info) = data.response_cache.info.get_single(Instant::now()) {
+        return HttpResponse::Ok().json(info);
+    }
+    match data.redis_shards.server_info().await {
+        Ok(info) => {
+            data.response_cache.info.insert_single(info.clone(), Instant::now());
+            HttpResponse::Ok().json(info)
+        }
         Err(OcyError::RedisConnection(err)) => {
             error!("Failed to fetch summary data: {}", err);
             HttpResponse::ServiceUnavailable().body(err)
@@ -30,6 +35,16 @@ pub async fn index(data: web::Data<ApplicationState>) -> impl Responder {
     }
 }
 
+/// Handles `GET /info/features` requests. This returns the version of this server, along with the optional
+/// subsystems enabled on it, and limits on requests to it, so clients can adapt to them.
+///
+/// # Returns
+///
+/// * 200 - JSON containing server features
+pub async fn features(data: web::Data<ApplicationState>) -> impl Responder {
+    HttpResponse::Ok().json(Features::new(&data.config, data.redis_shards.all().len()))
+}
+
 /// Handles `GET /info/version` requests. This returns the version number of this server.
 pub async fn version() -> impl Responder {
     HttpResponse::Ok().json(VERSION)
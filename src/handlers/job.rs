@@ -6,14 +6,51 @@ use log::error;
 use serde::Deserialize;
 use actix_web::{web, HttpResponse, Responder};
 
+use crate::application::metrics::RedisOperation;
 use crate::application::RedisManager;
-use crate::models::{job, ApplicationState, OcyError};
+use crate::events::EventKind;
+use crate::models::{job, ApplicationState, OcyError, Tenant};
 
 #[derive(Deserialize)]
 pub struct JobFields {
     fields: Option<String>,
 }
 
+/// Handles `GET /job` requests, searching for jobs across all queues, or all queues in the client's namespace.
+///
+/// # Returns
+///
+/// * 200 - JSON response containing a page of matching jobs, newest first, and cursor for the next page
+/// * 400 - bad request error if an invalid queue or tag name was given
+/// * 500 - unexpected internal error
+/// * 503 - Redis connection unavailable
+pub async fn search(
+    query: web::Query<job::SearchQuery>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let mut query = query.into_inner();
+    query.queue = query.queue.map(|queue| tenant.qualify(&queue));
+    query.tag = query.tag.map(|tag| tenant.qualify(&tag));
+    query.namespace = tenant.namespace().map(str::to_owned);
+
+    match data.redis_shards.search_jobs(&query).await {
+        Ok(mut results) => {
+            results.jobs.iter_mut().for_each(|job| tenant.scope_job(job));
+            HttpResponse::Ok().json(results)
+        }
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("Failed to search jobs: {}", err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("Failed to search jobs: {}", err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
 /// Handles `GET /job/{job_id}` requests.
 ///
 /// # Returns
@@ -26,6 +63,7 @@ pub struct JobFields {
 pub async fn index(
     path: web::Path<u64>,
     query: web::Query<JobFields>,
+    tenant: Tenant,
     data: web::Data<ApplicationState>,
 ) -> impl Responder {
     let job_id = path.into_inner();
@@ -46,10 +84,13 @@ pub async fn index(
         None => None,
     };
 
-    let mut conn = data.redis_conn_manager.clone();
+    let mut conn = data.redis_shards.for_job(job_id).get();
 
     match RedisManager::job_fields(&mut conn, job_id, fields.as_deref()).await {
-        Ok(job) => HttpResponse::Ok().json(job),
+        Ok(mut job) => {
+            tenant.scope_job(&mut job);
+            HttpResponse::Ok().json(job)
+        }
         Err(OcyError::NoSuchJob(_)) => HttpResponse::NotFound().into(),
         Err(OcyError::RedisConnection(err)) => {
             error!("[job:{}] failed to fetch metadata fields: {}", job_id, err);
@@ -72,7 +113,7 @@ pub async fn index(
 /// * 503 - Redis connection unavailable
 pub async fn status(path: web::Path<u64>, data: web::Data<ApplicationState>) -> impl Responder {
     let job_id = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+    let mut conn = data.redis_shards.for_job(job_id).get_read_only();
 
     match RedisManager::job_status(&mut conn, job_id).await {
         Ok(status) => HttpResponse::Ok().json(status),
@@ -106,10 +147,15 @@ pub async fn update(
 ) -> impl Responder {
     let job_id = path.into_inner();
     let update_req = json.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+    let mut conn = data.redis_shards.for_job(job_id).get();
 
     match RedisManager::update_job(&mut conn, job_id, &update_req).await {
-        Ok(_) => HttpResponse::NoContent().into(),
+        Ok(_) => {
+            if let Some(event) = update_req.status.as_ref().and_then(EventKind::from_status) {
+                data.events.job_event(event, job_id, None);
+            }
+            HttpResponse::NoContent().into()
+        }
         Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
         Err(OcyError::Conflict(msg)) => HttpResponse::Conflict().body(msg),
         Err(OcyError::NoSuchJob(_)) => HttpResponse::NotFound().into(),
@@ -136,7 +182,7 @@ pub async fn update(
 /// * 503 - Redis connection unavailable
 pub async fn heartbeat(path: web::Path<u64>, data: web::Data<ApplicationState>) -> impl Responder {
     let job_id = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+    let mut conn = data.redis_shards.for_job(job_id).get().for_operation(RedisOperation::Heartbeat);
 
     match RedisManager::update_job_heartbeat(&mut conn, job_id).await {
         Ok(_) => HttpResponse::NoContent()
@@ -155,6 +201,37 @@ pub async fn heartbeat(path: web::Path<u64>, data: web::Data<ApplicationState>)
     }
 }
 
+/// Handles `PUT /job/heartbeat` requests. This endpoint updates the last heartbeat time, and optionally the progress,
+/// of many jobs at once, for workers running many jobs concurrently.
+///
+/// Expects a JSON array of job IDs, or objects with an `id` and `progress`. Jobs that aren't running are left
+/// unchanged rather than failing the whole request.
+///
+/// # Returns
+///
+/// * 200 - JSON response listing which jobs were updated, which weren't running, and which weren't found
+/// * 400 - bad request error if too many jobs were given
+/// * 500 - unexpected internal error
+/// * 503 - Redis connection unavailable
+pub async fn heartbeat_many(
+    json: web::Json<Vec<job::Heartbeat>>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    match data.redis_shards.heartbeat_jobs(&json, &tenant).await {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("Failed to update heartbeats of {} jobs: {}", json.len(), err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("Failed to update heartbeats of {} jobs: {}", json.len(), err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
 /// Handles `DELETE /job/{job_id}` requests. This endpoint deletes a job from the DB regardless of the
 /// state of execution it's in.
 ///
@@ -163,6 +240,9 @@ pub async fn heartbeat(path: web::Path<u64>, data: web::Data<ApplicationState>)
 ///
 /// Running/queued jobs can be more gracefully removed by updating the job's status to `cancelled`.
 ///
+/// If a delete recovery window is configured, the job is moved to the trash rather than deleted immediately, and
+/// can be restored using `POST /job/{job_id}/undelete` until the window has elapsed.
+///
 /// # Returns
 ///
 /// * 204 - update successfully performed
@@ -171,9 +251,16 @@ pub async fn heartbeat(path: web::Path<u64>, data: web::Data<ApplicationState>)
 /// * 503 - Redis connection unavailable
 pub async fn delete(path: web::Path<u64>, data: web::Data<ApplicationState>) -> impl Responder {
     let job_id = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+    let mut conn = data.redis_shards.for_job(job_id).get();
+    let recovery_window = &data.config.server.delete_recovery_window;
+
+    let result = if recovery_window.is_zero() {
+        RedisManager::delete_job(&mut conn, job_id).await
+    } else {
+        RedisManager::trash_job(&mut conn, job_id, recovery_window).await
+    };
 
-    match RedisManager::delete_job(&mut conn, job_id).await {
+    match result {
         Ok(true) => HttpResponse::NoContent().reason("Job deleted").finish(),
         Ok(false) => HttpResponse::NotFound().into(),
         Err(OcyError::RedisConnection(err)) => {
@@ -187,6 +274,34 @@ pub async fn delete(path: web::Path<u64>, data: web::Data<ApplicationState>) ->
     }
 }
 
+/// Handles `POST /job/{job_id}/undelete` requests. Restores a deleted job from the trash, as long as its recovery
+/// window hasn't elapsed.
+///
+/// # Returns
+///
+/// * 204 - job successfully restored
+/// * 404 - not found error if no deleted job with given `job_id` is found, or job is queued and its queue no longer
+///   exists
+/// * 500 - unexpected internal error
+/// * 503 - Redis connection unavailable
+pub async fn undelete(path: web::Path<u64>, data: web::Data<ApplicationState>) -> impl Responder {
+    let job_id = path.into_inner();
+    let mut conn = data.redis_shards.for_job(job_id).get();
+
+    match RedisManager::restore_job(&mut conn, job_id).await {
+        Ok(_) => HttpResponse::NoContent().reason("Job restored").finish(),
+        Err(OcyError::NoSuchJob(_)) | Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[job:{}] failed to restore: {}", job_id, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[job:{}] failed to restore: {}", job_id, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
 /// Handles `GET /job/{job_id}/output` requests. Gets the current output for a given job.
 ///
 /// # Returns
@@ -197,7 +312,7 @@ pub async fn delete(path: web::Path<u64>, data: web::Data<ApplicationState>) ->
 /// * 503 - Redis connection unavailable
 pub async fn output(path: web::Path<u64>, data: web::Data<ApplicationState>) -> impl Responder {
     let job_id = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+    let mut conn = data.redis_shards.for_job(job_id).get();
 
     match RedisManager::job_output(&mut conn, job_id).await {
         Ok(v) => HttpResponse::Ok().json(v),
@@ -229,7 +344,7 @@ pub async fn set_output(
 ) -> impl Responder {
     let job_id = path.into_inner();
     let value = json.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+    let mut conn = data.redis_shards.for_job(job_id).get();
 
     match RedisManager::set_job_output(&mut conn, job_id, &value).await {
         Ok(_) => HttpResponse::NoContent().into(),
@@ -247,6 +362,97 @@ pub async fn set_output(
     }
 }
 
+/// Handles `PATCH /job/{job_id}/input` requests. Replaces the input of a job that's still queued with given JSON.
+///
+/// # Returns
+///
+/// * 204 - if input was successfully updated
+/// * 404 - not found error if no job with given `job_id` is found
+/// * 409 - job not in "queued" state, so input cannot be updated
+/// * 500 - unexpected internal error
+/// * 503 - Redis connection unavailable
+pub async fn set_input(
+    path: web::Path<u64>,
+    json: web::Json<serde_json::Value>,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let job_id = path.into_inner();
+    let value = json.into_inner();
+    let mut conn = data.redis_shards.for_job(job_id).get();
+
+    match RedisManager::set_job_input(&mut conn, job_id, &value).await {
+        Ok(_) => HttpResponse::NoContent().into(),
+        Err(OcyError::NoSuchJob(_)) => HttpResponse::NotFound().reason("Job Not Found").finish(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::Conflict(msg)) => HttpResponse::Conflict().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[job:{}] failed to set input: {}", job_id, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[job:{}] failed to set input: {}", job_id, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `PUT /job/{job_id}/hold` requests. Moves a queued job out of its queue, so that it's not given to workers
+/// until released.
+///
+/// # Returns
+///
+/// * 204 - job successfully put on hold
+/// * 404 - not found error if no job with given `job_id` is found
+/// * 409 - unable to hold job that isn't waiting in its queue
+/// * 500 - unexpected internal error
+/// * 503 - Redis connection unavailable
+pub async fn hold(path: web::Path<u64>, data: web::Data<ApplicationState>) -> impl Responder {
+    let job_id = path.into_inner();
+    let mut conn = data.redis_shards.for_job(job_id).get();
+
+    match RedisManager::hold_job(&mut conn, job_id).await {
+        Ok(_) => HttpResponse::NoContent().reason("Job held").finish(),
+        Err(OcyError::NoSuchJob(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::Conflict(msg)) => HttpResponse::Conflict().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[job:{}] failed to hold: {}", job_id, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[job:{}] failed to hold: {}", job_id, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `DELETE /job/{job_id}/hold` requests. Releases a held job, moving it to the back of its queue.
+///
+/// # Returns
+///
+/// * 204 - job successfully released
+/// * 404 - not found error if no job with given `job_id` is found, or its queue no longer exists
+/// * 409 - unable to release job that isn't on hold
+/// * 500 - unexpected internal error
+/// * 503 - Redis connection unavailable
+pub async fn release_hold(path: web::Path<u64>, data: web::Data<ApplicationState>) -> impl Responder {
+    let job_id = path.into_inner();
+    let mut conn = data.redis_shards.for_job(job_id).get();
+
+    match RedisManager::release_held_job(&mut conn, job_id).await {
+        Ok(_) => HttpResponse::NoContent().reason("Job released").finish(),
+        Err(OcyError::NoSuchJob(_)) | Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::Conflict(msg)) => HttpResponse::Conflict().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[job:{}] failed to release from hold: {}", job_id, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[job:{}] failed to release from hold: {}", job_id, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
 /// Handles `PUT /job/{job_id}/retry` requests. This endpoint retries a job
 ///
 /// # Returns
@@ -258,10 +464,13 @@ pub async fn set_output(
 /// * 503 - Redis connection unavailable
 pub async fn retry(path: web::Path<u64>, data: web::Data<ApplicationState>) -> impl Responder {
     let job_id = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+    let mut conn = data.redis_shards.for_job(job_id).get();
 
     match RedisManager::retry_job(&mut conn, job_id).await {
-        Ok(job) => HttpResponse::Ok().json(job),
+        Ok(job) => {
+            data.events.job_event(EventKind::Retried, job_id, None);
+            HttpResponse::Ok().json(job)
+        }
         Err(OcyError::NoSuchJob(_)) => HttpResponse::NotFound().into(),
         Err(OcyError::Conflict(msg)) => HttpResponse::Conflict().body(msg),
         Err(OcyError::RedisConnection(err)) => {
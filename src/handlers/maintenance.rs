@@ -0,0 +1,64 @@
+//! HTTP handlers for the `/maintenance` endpoints.
+
+use actix_web::{web, HttpResponse, Responder};
+use log::error;
+use serde::Deserialize;
+
+use crate::models::{ApplicationState, OcyError};
+
+#[derive(Deserialize)]
+pub struct RepairQuery {
+    #[serde(default)]
+    repair: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DryRun {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Handles `POST /maintenance/check_integrity` requests.
+///
+/// Checks the consistency of jobs, queues and their indexes across all shards. If `repair=true` is given, any problems
+/// found are also repaired.
+///
+/// # Returns
+///
+/// * 200 - JSON report of problems found
+pub async fn check_integrity(query: web::Query<RepairQuery>, data: web::Data<ApplicationState>) -> impl Responder {
+    match data.redis_shards.check_integrity(query.into_inner().repair).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("Failed to check integrity: {}", err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("Failed to check integrity: {}", err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `POST /maintenance/prune_tags[?dry_run=true]` requests.
+///
+/// Immediately removes IDs of jobs that no longer exist from all tags across all shards, rather than waiting for the
+/// next tag prune check.
+///
+/// # Returns
+///
+/// * 200 - JSON object of tag names mapped to the job IDs removed from them, or that would be removed if this is a
+///   dry run
+pub async fn prune_tags(query: web::Query<DryRun>, data: web::Data<ApplicationState>) -> impl Responder {
+    match data.redis_shards.prune_tags(query.into_inner().dry_run).await {
+        Ok(pruned) => HttpResponse::Ok().json(pruned),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("Failed to prune tags: {}", err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("Failed to prune tags: {}", err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
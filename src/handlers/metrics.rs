@@ -0,0 +1,17 @@
+//! Defines handler for exposing metrics to Prometheus.
+
+use actix_web::{HttpResponse, Responder};
+
+use crate::application::file;
+use crate::application::metrics::{METRICS, PROMETHEUS_CONTENT_TYPE};
+
+/// Handles `GET /metrics` requests.
+///
+/// # Returns
+///
+/// * 200 - metrics in Prometheus text format
+pub async fn index() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(PROMETHEUS_CONTENT_TYPE)
+        .body(METRICS.render(file::count_jobs()))
+}
@@ -1,8 +1,252 @@
-//! Module containing HTTP handlers. Mapping to these from various routes is configured in
-//! `ocypod-server.rs`.
+//! Module containing HTTP handlers, and the routes mapping to them.
 
+use actix_web::web;
+
+pub mod admin;
+pub mod backup;
+pub mod config;
 pub mod health;
 pub mod info;
 pub mod job;
+pub mod maintenance;
+pub mod metrics;
 pub mod queue;
+pub mod quota;
+pub mod status;
 pub mod tag;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod worker;
+
+/// Register all API endpoints.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(
+            web::scope("/info")
+                // get current server version
+                .service(web::resource("/version").to(info::version))
+                // get server version, enabled subsystems, and request limits
+                .service(web::resource("/features").to(info::features))
+                // get summary of system/queue information
+                .service(web::resource("").to(info::index)),
+        )
+        // Run basic health check by PINGing Redis.
+        .route("/health", web::get().to(health::index))
+        // Check whether server is ready to accept requests, based on circuit breaker state.
+        .route("/health/ready", web::get().to(health::ready))
+        // Get HTML page summarising queues and server health, if enabled.
+        .route("/status", web::get().to(status::index))
+        // Get metrics for file persistence and background monitors in Prometheus format.
+        .route("/metrics", web::get().to(metrics::index))
+        // Get or change log levels, optionally for specific modules, without restarting the server.
+        .service(
+            web::scope("/admin")
+                .service(
+                    web::resource("/log_level")
+                        .route(web::get().to(admin::log_level))
+                        .route(web::put().to(admin::set_log_level)),
+                )
+                // Get or clear recent requests that took longer than the slow log threshold.
+                .service(
+                    web::resource("/slowlog")
+                        .route(web::get().to(admin::slowlog))
+                        .route(web::delete().to(admin::reset_slowlog)),
+                )
+                // Stop or resume handing out jobs, e.g. before shutting down during a rolling deploy.
+                .service(web::resource("/drain").route(web::post().to(admin::drain)))
+                .service(web::resource("/undrain").route(web::post().to(admin::undrain))),
+        )
+        .service(
+            web::scope("/maintenance")
+                // Check consistency of jobs and their indexes, optionally repairing any problems found.
+                .service(
+                    web::resource("/check_integrity")
+                        .route(web::post().to(maintenance::check_integrity)),
+                )
+                // Remove jobs that no longer exist from tags.
+                .service(web::resource("/prune_tags").route(web::post().to(maintenance::prune_tags))),
+        )
+        // Export the server's configuration, and the settings of every queue, with secrets redacted.
+        .service(
+            web::scope("/config")
+                .service(web::resource("/queues").route(web::get().to(config::queues)))
+                .service(web::resource("").route(web::get().to(config::index))),
+        )
+        // Export a consistent snapshot of a queue's settings and jobs.
+        .route("/backup/queue/{name}", web::get().to(backup::queue))
+        // Get a namespace's quota and current usage.
+        .route("/quota", web::get().to(quota::index))
+        // Attach a tag to many jobs at once, given by ID, or by queue and status.
+        .route("/tag/{name}/apply", web::post().to(tag::apply))
+        // Retry, cancel or delete every job with a tag.
+        .route("/tag/{name}/retry", web::post().to(tag::retry))
+        .route("/tag/{name}/cancel", web::post().to(tag::cancel))
+        .route("/tag/{name}/delete", web::post().to(tag::delete))
+        // Get list of job IDs for a given tag.
+        .route("/tag/{name}", web::get().to(tag::tagged_jobs))
+        .service(
+            web::scope("/worker")
+                // Get the statuses of all draining workers.
+                .service(web::resource("").route(web::get().to(worker::index)))
+                // Get a worker's drain status.
+                .service(web::resource("/{id}").route(web::get().to(worker::status)))
+                // Stop or resume giving a worker new jobs, e.g. while restarting it during a rolling deploy.
+                .service(
+                    web::resource("/{id}/drain")
+                        .route(web::post().to(worker::drain))
+                        .route(web::delete().to(worker::undrain)),
+                ),
+        )
+        .service(
+            web::scope("/job")
+                // Get current status of job with given ID.
+                .service(web::resource("/{id}/status").to(job::status))
+                // Get or set a job's output.
+                .service(
+                    web::resource("/{id}/output")
+                        .route(web::get().to(job::output))
+                        .route(web::put().to(job::set_output)),
+                )
+                // Replace the input of a job that's still queued.
+                .service(
+                    web::resource("/{id}/input")
+                        .route(web::patch().to(job::set_input)),
+                )
+                // Update the last heartbeat date/time and progress of many jobs at once.
+                .service(web::resource("/heartbeat").route(web::put().to(job::heartbeat_many)))
+                // Update a job's last heartbeat date/time.
+                .service(
+                    web::resource("/{id}/heartbeat")
+                        .route(web::put().to(job::heartbeat)),
+                )
+                .service(
+                    web::resource("/{id}/hold")
+                        // Put a queued job on hold, so it's not given to workers.
+                        .route(web::put().to(job::hold))
+                        // Release a held job back to its queue.
+                        .route(web::delete().to(job::release_hold)),
+                )
+                // Restore a deleted job from the trash.
+                .service(
+                    web::resource("/{id}/undelete")
+                        .route(web::post().to(job::undelete)),
+                )
+                .service(
+                    web::resource("/{id}/retry")
+                        .route(web::put().to(job::retry)),
+                )
+                .service(
+                    web::resource("/{id}")
+                        // Get all metadata about a job with given ID.
+                        .route(web::get().to(job::index))
+                        // Update one or more fields (including status) of a job.
+                        .route(web::patch().to(job::update))
+                        // Delete a job from the queue DB.
+                        .route(web::delete().to(job::delete)),
+                )
+                // Search for jobs by status, tag, and queue.
+                .service(web::resource("").route(web::get().to(job::search))),
+        )
+        .service(
+            web::scope("/queue")
+                // Job IDs by state.
+                .service(web::resource("/{name}/job_ids").to(queue::job_ids))
+                // Jobs set aside after repeatedly timing out or failing shortly after starting.
+                .service(
+                    web::resource("/{name}/quarantined")
+                        .route(web::get().to(queue::quarantined)),
+                )
+                // Failed jobs grouped by the reason they failed.
+                .service(
+                    web::resource("/{name}/failures/summary")
+                        .route(web::get().to(queue::failure_summary)),
+                )
+                // Running jobs that haven't sent a heartbeat for a while, but haven't timed out.
+                .service(
+                    web::resource("/{name}/stuck")
+                        .route(web::get().to(queue::stuck)),
+                )
+                // Recent anomalous spikes in the queue's failure rate.
+                .service(
+                    web::resource("/{name}/anomalies")
+                        .route(web::get().to(queue::anomalies)),
+                )
+                // Queued and running job counts for autoscalers.
+                .service(
+                    web::resource("/{name}/backlog")
+                        .route(web::get().to(queue::backlog)),
+                )
+                // Oldest queued job age, recent waits and throughput, and how long queued jobs will take to start.
+                .service(
+                    web::resource("/{name}/latency")
+                        .route(web::get().to(queue::latency)),
+                )
+                // Output of the latest recently completed job with a given unique key.
+                .service(
+                    web::resource("/{name}/result")
+                        .route(web::get().to(queue::result)),
+                )
+                // Random sample of jobs with a given status, for inspecting large backlogs.
+                .service(
+                    web::resource("/{name}/sample")
+                        .route(web::get().to(queue::sample)),
+                )
+                // Jobs that weren't completed by their SLA deadline.
+                .service(
+                    web::resource("/{name}/sla_breaches")
+                        .route(web::get().to(queue::sla_breaches)),
+                )
+                // Expire ended jobs now, rather than waiting for the next expiry check.
+                .service(web::resource("/{name}/expire").route(web::post().to(queue::expire)))
+                // Delete all ended jobs, whether or not they've expired.
+                .service(web::resource("/{name}/purge").route(web::post().to(queue::purge)))
+                // Create a new queue with the same settings, optionally copying queued jobs.
+                .service(web::resource("/{name}/clone").route(web::post().to(queue::clone_queue)))
+                .service(
+                    web::resource("/{name}/job")
+                        // Get the next job to work on from given queue.
+                        .route(web::get().to(queue::next_job))
+                        // Create a new job on given queue.
+                        .route(web::post().to(queue::create_job)),
+                )
+                .service(
+                    // Reattmped a failed attempt
+                    web::resource("/{name}/reattempt/{timestamp}")
+                        .route(web::get().to(queue::reattempt_job)),
+                )
+                .service(
+                    web::resource("/{name}/callback")
+                        // Get the URL jobs on this queue are pushed to.
+                        .route(web::get().to(queue::callback))
+                        // Register a URL to push jobs on this queue to.
+                        .route(web::put().to(queue::set_callback))
+                        // Stop pushing jobs on this queue.
+                        .route(web::delete().to(queue::delete_callback)),
+                )
+                // Get queue size.
+                .service(web::resource("/{name}/size").to(queue::size))
+                .service(
+                    web::resource("/{name}")
+                        // Get queue's settings.
+                        .route(web::get().to(queue::settings))
+                        // Create a new queue, or update an existing one with given settings.
+                        .route(web::put().to(queue::create_or_update))
+                        // Update only the given settings of an existing queue.
+                        .route(web::patch().to(queue::update))
+                        // Delete a queue and all currently queued jobs on it.
+                        .route(web::delete().to(queue::delete)),
+                )
+                // Get a list of all queue names, or a summary of each queue.
+                .service(web::resource("").to(queue::index)),
+        );
+    // Inject failures and force monitor passes, if built for testing clients against.
+    configure_testing(cfg);
+}
+
+/// Register the `/testing` endpoints, which are only available when built with the "testing" feature.
+pub fn configure_testing(cfg: &mut web::ServiceConfig) {
+    #[cfg(feature = "testing")]
+    testing::configure(cfg);
+    #[cfg(not(feature = "testing"))]
+    let _ = cfg;
+}
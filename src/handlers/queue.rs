@@ -1,21 +1,114 @@
 //! HTTP handlers for the `/queue` endpoints.
 
-use actix_web::{web, HttpResponse, Responder};
-use log::{debug, error};
+use std::collections::HashMap;
+use std::time::Instant;
 
-use crate::application::{RedisManager, file};
-use crate::models::{job, queue, ApplicationState, OcyError};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use log::{debug, error, warn};
+use serde::Deserialize;
+
+use crate::application::metrics::RedisOperation;
+use crate::application::{limits, RedisManager, file};
+use crate::config::Durability;
+use crate::events::EventKind;
+use crate::models::{job, queue, quota, ApplicationState, Duration, OcyError, OcyResult, Tenant};
+
+#[derive(Deserialize)]
+pub struct DryRun {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Deserialize)]
+pub struct IndexQuery {
+    #[serde(default)]
+    summary: bool,
+}
+
+#[derive(Deserialize)]
+pub struct StuckQuery {
+    running_longer_than: Duration,
+}
+
+#[derive(Deserialize)]
+pub struct LatencyQuery {
+    window: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+pub struct ResultQuery {
+    unique_key: String,
+}
+
+#[derive(Deserialize)]
+pub struct NextJobQuery {
+    /// Comma separated routing keys of jobs the worker can take, in order of preference.
+    routing_keys: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SampleQuery {
+    #[serde(default = "default_sample_status")]
+    status: job::Status,
+    #[serde(default = "default_sample_size")]
+    n: usize,
+}
+
+fn default_sample_status() -> job::Status {
+    job::Status::Queued
+}
+
+fn default_sample_size() -> usize {
+    10
+}
 
 /// Handle `GET /queue` requests to get a JSON list of all existing queues.
 ///
+/// If `summary=true` is given, gets a JSON object summarising each queue by name instead. Only queues in the
+/// client's namespace are included. Responses may be cached for the configured `response_cache.queue_index` time.
+///
 /// # Returns
 ///
-/// * 200 - JSON response containing list of queue names.
-pub async fn index(data: web::Data<ApplicationState>) -> impl Responder {
-    let mut conn = data.redis_conn_manager.clone();
+/// * 200 - JSON response containing list of queue names, or summary of each queue.
+pub async fn index(query: web::Query<IndexQuery>, tenant: Tenant, data: web::Data<ApplicationState>) -> impl Responder {
+    let cache = &data.response_cache;
+    if query.summary {
+        return match cached_queue_summaries(&data).await {
+            Ok(summaries) => {
+                let summaries: HashMap<String, queue::Summary> = summaries
+                    .into_iter()
+                    .filter_map(|(name, summary)| Some((tenant.unqualify(&name)?.to_owned(), summary)))
+                    .collect();
+                HttpResponse::Ok().json(summaries)
+            }
+            Err(OcyError::RedisConnection(err)) => {
+                error!("Failed to fetch queue summaries: {}", err);
+                HttpResponse::ServiceUnavailable().body(err)
+            }
+            Err(err) => {
+                error!("Failed to fetch queue summaries: {}", err);
+                HttpResponse::InternalServerError().body(err)
+            }
+        };
+    }
 
-    match RedisManager::queue_names(&mut conn).await {
-        Ok(queue_names) => HttpResponse::Ok().json(queue_names),
+    let queue_names = match cache.queue_names.get_single(Instant::now()) {
+        Some(queue_names) => Ok(queue_names),
+        None => data.redis_shards.queue_names().await.inspect(|queue_names| {
+            cache.queue_names.insert_single(queue_names.clone(), Instant::now());
+        }),
+    };
+    match queue_names {
+        Ok(queue_names) => {
+            let queue_names: Vec<&str> = queue_names.iter().filter_map(|name| tenant.unqualify(name)).collect();
+            HttpResponse::Ok().json(queue_names)
+        }
         Err(OcyError::RedisConnection(err)) => {
             error!("Failed to fetch queue names: {}", err);
             HttpResponse::ServiceUnavailable().body(err)
@@ -27,23 +120,61 @@ pub async fn index(data: web::Data<ApplicationState>) -> impl Responder {
     }
 }
 
+/// Get a summary of every queue, from the response cache if it's cached, caching it otherwise.
+pub(crate) async fn cached_queue_summaries(data: &ApplicationState) -> OcyResult<HashMap<String, queue::Summary>> {
+    let cache = &data.response_cache.queue_summaries;
+    if let Some(summaries) = cache.get_single(Instant::now()) {
+        return Ok(summaries);
+    }
+    data.redis_shards.queue_summaries().await.inspect(|summaries| {
+        cache.insert_single(summaries.clone(), Instant::now());
+    })
+}
+
 /// Handles `PUT /queue/{queue_name}` requests.
+///
+/// # Returns
+///
+/// * 201 - queue created
+/// * 204 - existing queue's settings updated
+/// * 400 - invalid queue name or settings
+/// * 403 - creating the queue would exceed the namespace's queue quota
 pub async fn create_or_update(
     path: web::Path<String>,
     json: web::Json<queue::Settings>,
+    tenant: Tenant,
     data: web::Data<ApplicationState>,
 ) -> impl Responder {
-    let queue_name = path.into_inner();
-    let queue_settings = json.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+    let name = path.into_inner();
+    let queue_name = tenant.qualify(&name);
+    if let Err(msg) = limits::get().check_unreserved("queue name", &queue_name) {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let mut queue_settings = json.into_inner();
+    tenant.qualify_settings(&mut queue_settings);
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
+
+    if let Some((namespace, quota)) = data.config.auth.quota(&tenant) {
+        let res = match RedisManager::queue_exists(&mut conn, &queue_name).await {
+            Ok(true) => Ok(()),
+            Ok(false) => data.redis_shards.check_quota(namespace, quota, &quota::Usage::queue()).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = res {
+            return quota_rejected(&queue_name, err);
+        }
+    }
 
     match RedisManager::create_or_update_queue(&mut conn, &queue_name, &queue_settings).await {
-        Ok(true) => HttpResponse::Created()
-            .header("Location", format!("/queue/{}", queue_name))
-            .finish(),
+        Ok(true) => {
+            data.hooks.queue_created(&queue_name);
+            HttpResponse::Created()
+                .header("Location", format!("/queue/{}", name))
+                .finish()
+        }
         Ok(false) => HttpResponse::NoContent()
             .reason("Queue setting updated")
-            .header("Location", format!("/queue/{}", queue_name))
+            .header("Location", format!("/queue/{}", name))
             .finish(),
         Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
         Err(OcyError::RedisConnection(err)) => {
@@ -63,13 +194,131 @@ pub async fn create_or_update(
     }
 }
 
-pub async fn delete(path: web::Path<String>, data: web::Data<ApplicationState>) -> impl Responder {
-    let queue_name = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+pub async fn update(
+    path: web::Path<String>,
+    json: web::Json<queue::SettingsUpdate>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut update = json.into_inner();
+    if let Some(Some(shadow_to)) = &update.shadow_to {
+        update.shadow_to = Some(Some(tenant.qualify(shadow_to)));
+    }
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
 
-    match RedisManager::delete_queue(&mut conn, &queue_name).await {
-        Ok(true) => HttpResponse::NoContent().reason("Queue deleted").finish(),
-        Ok(false) => HttpResponse::NotFound().reason("Queue not found").finish(),
+    match RedisManager::update_queue(&mut conn, &queue_name, &update).await {
+        Ok(mut settings) => {
+            tenant.scope_settings(&mut settings);
+            HttpResponse::Ok().json(settings)
+        }
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to update queue: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to update queue: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `POST /queue/{queue_name}/clone` requests.
+///
+/// Creates a new queue with the same settings as this queue, optionally copying its queued jobs.
+///
+/// # Returns
+///
+/// * 201 - JSON list of IDs of jobs copied to the new queue
+/// * 400 - invalid queue name given
+/// * 403 - creating the queue would exceed the namespace's queue quota
+/// * 404 - queue not found
+/// * 409 - queue with the new name already exists
+/// * 429 - copying the queue's jobs would exceed the namespace's job or storage quota
+pub async fn clone_queue(
+    path: web::Path<String>,
+    json: web::Json<queue::CloneRequest>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut clone_req = json.into_inner();
+    let clone_name = clone_req.name;
+    clone_req.name = tenant.qualify(&clone_name);
+    if let Err(msg) = limits::get().check_unreserved("queue name", &clone_req.name) {
+        return HttpResponse::BadRequest().body(msg);
+    }
+
+    match data.redis_shards.clone_queue(&queue_name, &clone_req, data.config.auth.quota(&tenant)).await {
+        Ok(job_ids) => {
+            data.hooks.queue_created(&clone_req.name);
+            for job_id in &job_ids {
+                data.events.job_event(EventKind::Created, *job_id, Some(&clone_req.name));
+            }
+            HttpResponse::Created()
+                .header("Location", format!("/queue/{}", clone_name))
+                .json(job_ids)
+        }
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::Conflict(msg)) => HttpResponse::Conflict().body(msg),
+        Err(OcyError::Forbidden(msg)) => HttpResponse::Forbidden().body(msg),
+        Err(OcyError::QuotaExceeded(msg)) => HttpResponse::TooManyRequests().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to clone queue: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to clone queue: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `DELETE /queue/{queue_name}[?force=true]` requests.
+///
+/// Queues with queued jobs are only deleted if `force=true` is given, since their jobs are deleted with them. Forced
+/// deletions are logged with details of the request and the jobs deleted, for auditing.
+///
+/// # Returns
+///
+/// * 204 - queue deleted
+/// * 400 - invalid queue name
+/// * 404 - queue not found
+/// * 409 - queue has queued jobs and deletion wasn't forced, with counts of the jobs that would be deleted
+pub async fn delete(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<DeleteQuery>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let force = query.into_inner().force;
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
+
+    match RedisManager::delete_queue(&mut conn, &queue_name, force).await {
+        Ok(queue::Deletion::Deleted(deleted)) => {
+            if force {
+                let identity = req
+                    .headers()
+                    .get(data.config.server.access_log.identity_header.as_str())
+                    .and_then(|value| value.to_str().ok());
+                warn!(
+                    "[queue:{}] force deleted with {} queued jobs (namespace: {}, client: {}, identity: {})",
+                    &queue_name,
+                    deleted.queued,
+                    tenant.namespace().unwrap_or("-"),
+                    req.peer_addr().map_or_else(|| "-".to_owned(), |addr| addr.ip().to_string()),
+                    identity.unwrap_or("-"),
+                );
+            }
+            HttpResponse::NoContent().reason("Queue deleted").finish()
+        }
+        Ok(queue::Deletion::NotFound) => HttpResponse::NotFound().reason("Queue not found").finish(),
+        Ok(queue::Deletion::NotEmpty(jobs)) => HttpResponse::Conflict().json(jobs),
         Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
         Err(OcyError::RedisConnection(err)) => {
             error!("[queue:{}] failed to delete queue: {}", &queue_name, err);
@@ -84,13 +333,17 @@ pub async fn delete(path: web::Path<String>, data: web::Data<ApplicationState>)
 
 pub async fn settings(
     path: web::Path<String>,
+    tenant: Tenant,
     data: web::Data<ApplicationState>,
 ) -> impl Responder {
-    let queue_name = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
 
     match RedisManager::queue_settings(&mut conn, &queue_name).await {
-        Ok(summary) => HttpResponse::Ok().json(summary),
+        Ok(mut settings) => {
+            tenant.scope_settings(&mut settings);
+            HttpResponse::Ok().json(settings)
+        }
         Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
         Err(OcyError::RedisConnection(err)) => {
             error!(
@@ -109,12 +362,116 @@ pub async fn settings(
     }
 }
 
-pub async fn size(path: web::Path<String>, data: web::Data<ApplicationState>) -> impl Responder {
-    let queue_name = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+/// Handles `GET /queue/{queue_name}/callback` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON containing the callback URL jobs on this queue are pushed to
+/// * 404 - queue not found, or no callback URL registered
+pub async fn callback(
+    path: web::Path<String>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
+
+    match RedisManager::queue_callback(&mut conn, &queue_name).await {
+        Ok(Some(url)) => HttpResponse::Ok().json(queue::Callback { url }),
+        Ok(None) => HttpResponse::NotFound().reason("No Callback Registered").finish(),
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().reason("Queue Not Found").finish(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to fetch callback: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to fetch callback: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `PUT /queue/{queue_name}/callback` requests, enabling push delivery of jobs on this queue.
+///
+/// # Returns
+///
+/// * 204 - callback URL registered
+/// * 400 - invalid queue name or callback URL
+/// * 404 - queue not found
+pub async fn set_callback(
+    path: web::Path<String>,
+    json: web::Json<queue::Callback>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let callback = json.into_inner();
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
+
+    match RedisManager::set_queue_callback(&mut conn, &queue_name, Some(&callback.url)).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().reason("Queue Not Found").finish(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to set callback: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to set callback: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `DELETE /queue/{queue_name}/callback` requests, returning this queue to pull mode.
+///
+/// # Returns
+///
+/// * 204 - callback URL removed, or none was registered
+/// * 404 - queue not found
+pub async fn delete_callback(
+    path: web::Path<String>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
+
+    match RedisManager::set_queue_callback(&mut conn, &queue_name, None).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().reason("Queue Not Found").finish(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to delete callback: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to delete callback: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `GET /queue/{queue_name}/size` requests. Responses may be cached for the configured
+/// `response_cache.queue_size` time.
+///
+/// # Returns
+///
+/// * 200 - JSON number of jobs queued on the queue
+/// * 404 - queue not found
+pub async fn size(path: web::Path<String>, tenant: Tenant, data: web::Data<ApplicationState>) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    if let Some(size) = data.response_cache.queue_sizes.get(&queue_name, Instant::now()) {
+        return HttpResponse::Ok().json(size);
+    }
+    let mut conn = data.redis_shards.for_queue(&queue_name).get_read_only();
 
     match RedisManager::queue_size(&mut conn, &queue_name).await {
-        Ok(size) => HttpResponse::Ok().json(size),
+        Ok(size) => {
+            data.response_cache.queue_sizes.insert(&queue_name, size, Instant::now());
+            HttpResponse::Ok().json(size)
+        }
         Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
         Err(OcyError::RedisConnection(err)) => {
             error!(
@@ -133,9 +490,9 @@ pub async fn size(path: web::Path<String>, data: web::Data<ApplicationState>) ->
     }
 }
 
-pub async fn job_ids(path: web::Path<String>, data: web::Data<ApplicationState>) -> impl Responder {
-    let queue_name = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+pub async fn job_ids(path: web::Path<String>, tenant: Tenant, data: web::Data<ApplicationState>) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
 
     match RedisManager::queue_job_ids(&mut conn, &queue_name).await {
         Ok(size) => HttpResponse::Ok().json(size),
@@ -157,22 +514,483 @@ pub async fn job_ids(path: web::Path<String>, data: web::Data<ApplicationState>)
     }
 }
 
+/// Handles `GET /queue/{queue_name}/quarantined` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON list of quarantined jobs, with the reason each was quarantined
+/// * 404 - queue not found
+pub async fn quarantined(path: web::Path<String>, tenant: Tenant, data: web::Data<ApplicationState>) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
+
+    match RedisManager::quarantined_jobs(&mut conn, &queue_name).await {
+        Ok(mut jobs) => {
+            jobs.iter_mut().for_each(|job| tenant.scope_job(job));
+            HttpResponse::Ok().json(jobs)
+        }
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!(
+                "[queue:{}] failed to fetch quarantined jobs: {}",
+                &queue_name, err
+            );
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!(
+                "[queue:{}] failed to fetch quarantined jobs: {}",
+                &queue_name, err
+            );
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `GET /queue/{queue_name}/failures/summary` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON summary of failed jobs, grouped by the reason they failed
+/// * 404 - queue not found
+pub async fn failure_summary(
+    path: web::Path<String>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut conn = data.redis_shards.for_queue(&queue_name).get_read_only();
+
+    match RedisManager::failure_summary(&mut conn, &queue_name).await {
+        Ok(summary) => HttpResponse::Ok().json(summary),
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to summarise failures: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to summarise failures: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `GET /queue/{queue_name}/stuck?running_longer_than=<duration>` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON list of running jobs with no heartbeat (or start, if they've sent no heartbeats) within the threshold
+/// * 404 - queue not found
+pub async fn stuck(
+    path: web::Path<String>,
+    query: web::Query<StuckQuery>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let running_longer_than = query.into_inner().running_longer_than;
+    let mut conn = data.redis_shards.for_queue(&queue_name).get_read_only();
+
+    match RedisManager::stuck_jobs(&mut conn, &queue_name, &running_longer_than).await {
+        Ok(mut jobs) => {
+            jobs.iter_mut().for_each(|job| tenant.scope_job(job));
+            HttpResponse::Ok().json(jobs)
+        }
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!(
+                "[queue:{}] failed to fetch stuck jobs: {}",
+                &queue_name, err
+            );
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!(
+                "[queue:{}] failed to fetch stuck jobs: {}",
+                &queue_name, err
+            );
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `GET /queue/{queue_name}/anomalies` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON object containing the queue's current and baseline failure rates, and its recent anomalies
+/// * 404 - queue not found
+pub async fn anomalies(path: web::Path<String>, tenant: Tenant, data: web::Data<ApplicationState>) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut conn = data.redis_shards.for_queue(&queue_name).get_read_only();
+
+    match RedisManager::queue_exists(&mut conn, &queue_name).await {
+        Ok(true) => HttpResponse::Ok().json(data.anomalies.queue(&queue_name)),
+        Ok(false) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to fetch anomalies: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to fetch anomalies: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `GET /queue/{queue_name}/backlog` requests. This endpoint is intended to be polled frequently by
+/// autoscalers, so responses are cached for the configured `response_cache.queue_backlog` time (2 seconds by
+/// default).
+///
+/// # Returns
+///
+/// * 200 - JSON object containing the queue's numbers of queued and running jobs, and its oldest queued job's age
+/// * 400 - invalid queue name
+/// * 404 - queue not found
+pub async fn backlog(path: web::Path<String>, tenant: Tenant, data: web::Data<ApplicationState>) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    if let Some(backlog) = data.response_cache.queue_backlogs.get(&queue_name, Instant::now()) {
+        return HttpResponse::Ok().json(backlog);
+    }
+    let mut conn = data.redis_shards.for_queue(&queue_name).get_read_only();
+
+    match RedisManager::queue_backlog(&mut conn, &queue_name).await {
+        Ok(backlog) => {
+            data.response_cache.queue_backlogs.insert(&queue_name, backlog.clone(), Instant::now());
+            HttpResponse::Ok().json(backlog)
+        }
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to fetch backlog: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to fetch backlog: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `GET /queue/{queue_name}/latency?window=<duration>` requests.
+///
+/// Reports the age of the queue's oldest queued job, along with the median wait and throughput of jobs started from
+/// it within the window (5 minutes by default), and an estimate of how long its queued jobs will take to start.
+///
+/// # Returns
+///
+/// * 200 - JSON object containing the queue's latency estimates
+/// * 400 - window is zero
+/// * 404 - queue not found
+pub async fn latency(
+    path: web::Path<String>,
+    query: web::Query<LatencyQuery>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let window = query.into_inner().window.map_or(queue::DEFAULT_LATENCY_WINDOW, |window| window.0);
+    if window.as_secs() == 0 {
+        return HttpResponse::BadRequest().body("Latency window must be at least 1 second");
+    }
+    let mut conn = data.redis_shards.for_queue(&queue_name).get_read_only();
+
+    match RedisManager::queue_latency(&mut conn, &queue_name, window).await {
+        Ok(latency) => HttpResponse::Ok().json(latency),
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to estimate latency: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to estimate latency: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `GET /queue/{queue_name}/result?unique_key=<unique_key>` requests.
+///
+/// Gets the output of the latest job created with given unique key that completed within the queue's `result_ttl`,
+/// so that clients can reuse results without keeping track of job IDs.
+///
+/// # Returns
+///
+/// * 200 - JSON output of the job, and location of job in `location` header
+/// * 400 - invalid queue name
+/// * 404 - queue not found, or no recently completed job with given unique key
+pub async fn result(
+    path: web::Path<String>,
+    query: web::Query<ResultQuery>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut conn = data.redis_shards.for_queue(&queue_name).get_read_only();
+
+    match RedisManager::job_result(&mut conn, &queue_name, &query.unique_key).await {
+        Ok(Some((job_id, output))) => HttpResponse::Ok()
+            .header("Location", format!("/job/{}", job_id))
+            .json(output),
+        Ok(None) => HttpResponse::NotFound().reason("Result Not Found").finish(),
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().reason("Queue Not Found").finish(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to fetch result: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to fetch result: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `GET /queue/{queue_name}/sample?status=<status>&n=<n>` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON list of up to `n` randomly chosen jobs with given status, with all their fields
+/// * 400 - `n` is larger than the maximum sample size
+/// * 404 - queue not found
+pub async fn sample(
+    path: web::Path<String>,
+    query: web::Query<SampleQuery>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let SampleQuery { status, n } = query.into_inner();
+    let mut conn = data.redis_shards.for_queue(&queue_name).get_read_only();
+
+    match RedisManager::sample_jobs(&mut conn, &queue_name, &status, n).await {
+        Ok(mut jobs) => {
+            jobs.iter_mut().for_each(|job| tenant.scope_job(job));
+            HttpResponse::Ok().json(jobs)
+        }
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to sample jobs: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to sample jobs: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `GET /queue/{queue_name}/sla_breaches` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON list of jobs that weren't completed by their deadline
+/// * 404 - queue not found
+pub async fn sla_breaches(
+    path: web::Path<String>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let mut conn = data.redis_shards.for_queue(&queue_name).get_read_only();
+
+    match RedisManager::sla_breaches(&mut conn, &queue_name).await {
+        Ok(mut jobs) => {
+            jobs.iter_mut().for_each(|job| tenant.scope_job(job));
+            HttpResponse::Ok().json(jobs)
+        }
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!(
+                "[queue:{}] failed to fetch SLA breaches: {}",
+                &queue_name, err
+            );
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!(
+                "[queue:{}] failed to fetch SLA breaches: {}",
+                &queue_name, err
+            );
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `POST /queue/{queue_name}/expire[?dry_run=true]` requests.
+///
+/// Immediately removes any of the queue's ended jobs that have expired, rather than waiting for the next expiry check.
+///
+/// # Returns
+///
+/// * 200 - JSON list of expired job IDs, or IDs of jobs that would be expired if this is a dry run
+/// * 404 - queue not found
+pub async fn expire(
+    path: web::Path<String>,
+    query: web::Query<DryRun>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let dry_run = query.into_inner().dry_run;
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
+    let default_statuses = &data.config.server.expiry_check_statuses;
+
+    match RedisManager::expire_queue(&mut conn, &queue_name, default_statuses, dry_run).await {
+        Ok(job_ids) => HttpResponse::Ok().json(job_ids),
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to expire jobs: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to expire jobs: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `POST /queue/{queue_name}/purge[?dry_run=true]` requests.
+///
+/// Deletes all of the queue's ended jobs, whether or not they've expired.
+///
+/// # Returns
+///
+/// * 200 - JSON list of deleted job IDs, or IDs of jobs that would be deleted if this is a dry run
+/// * 404 - queue not found
+pub async fn purge(
+    path: web::Path<String>,
+    query: web::Query<DryRun>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let queue_name = tenant.qualify(&path.into_inner());
+    let dry_run = query.into_inner().dry_run;
+    let mut conn = data.redis_shards.for_queue(&queue_name).get();
+
+    match RedisManager::purge_queue(&mut conn, &queue_name, dry_run).await {
+        Ok(job_ids) => HttpResponse::Ok().json(job_ids),
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to purge jobs: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[queue:{}] failed to purge jobs: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `POST /queue/{queue_name}/job` requests.
+///
+/// # Returns
+///
+/// * 200 - existing job with the same `unique_key` that hasn't ended, or recently completed, returned instead
+/// * 201 - ID of the created job
+/// * 202 - job persisted to disk while Redis is unavailable, to be created once it recovers
+/// * 400 - invalid job request
+/// * 403 - `callback_url` given by a namespace key
+/// * 404 - queue not found
+/// * 429 - creating the job would exceed the namespace's job or storage quota
 pub async fn create_job(
     path: web::Path<String>,
     json: web::Json<job::CreateRequest>,
+    tenant: Tenant,
     data: web::Data<ApplicationState>,
 ) -> impl Responder {
-    let queue_name = path.into_inner();
-    let job_req = json.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
+    let name = path.into_inner();
+    let queue_name = tenant.qualify(&name);
+    let mut job_req = json.into_inner();
+    // like registering a queue's callback URL, this makes the server send requests to any URL given
+    if job_req.callback_url.is_some() && !tenant.is_admin() {
+        return HttpResponse::Forbidden().body("Only admin keys can set a job's callback_url");
+    }
+    if let Some(tags) = &mut job_req.tags {
+        tags.iter_mut().for_each(|tag| *tag = tenant.qualify(tag));
+        if let Err(msg) = tags.iter().try_for_each(|tag| limits::get().check_unreserved("tag name", tag)) {
+            return HttpResponse::BadRequest().body(msg);
+        }
+    }
+    let mut conn = data.redis_shards.for_queue(&queue_name).get().for_operation(RedisOperation::Create);
+    let degraded_mode = data.config.persistence.degraded_mode;
 
-    let job_write_res = file::write_job(&queue_name, &job_req).unwrap();
+    // the unique key is claimed atomically when the job is created, this just avoids checking quotas and writing the
+    // job to disk when an existing job will be returned
+    if !(degraded_mode && data.circuit_breaker.retry_after().is_some()) {
+        match RedisManager::unique_job(&mut conn, &queue_name, &job_req).await {
+            Ok(Some(job)) => return existing_job(&tenant, job),
+            Ok(None) => (),
+            Err(OcyError::NoSuchQueue(_)) => return HttpResponse::NotFound().reason("Queue Not Found").finish(),
+            // a duplicate job is preferable to rejecting the job while Redis is down
+            Err(OcyError::RedisConnection(_)) if degraded_mode => (),
+            Err(OcyError::RedisConnection(err)) => {
+                error!("[queue:{}] failed to check for job with same unique key: {}", &queue_name, err);
+                return HttpResponse::ServiceUnavailable().body(err);
+            }
+            Err(err) => {
+                error!("[queue:{}] failed to check for job with same unique key: {}", &queue_name, err);
+                return HttpResponse::InternalServerError().body(err);
+            }
+        }
+    }
 
-    match RedisManager::create_job(&mut conn, &queue_name, &job_req).await {
-        Ok(job_id) => {
-            let job_attempt = file::get_job(&queue_name, job_write_res.1);
-            debug!("deleting job attempt {:?}", job_attempt);
-            let _del = file::delete_job(&queue_name, job_write_res.1);
+    // quotas can't be checked while Redis is down, so jobs accepted in degraded mode aren't limited by them
+    let mut storage_limit = None;
+    if let Some((namespace, quota)) = data.config.auth.quota(&tenant) {
+        if !(degraded_mode && data.circuit_breaker.retry_after().is_some()) {
+            let added = quota::Usage::job(job_req.input.as_ref());
+            let res = match data.redis_shards.check_quota(namespace, quota, &added).await {
+                Ok(()) => data.redis_shards.storage_limit(namespace, quota, &queue_name).await,
+                Err(err) => Err(err),
+            };
+            match res {
+                Ok(limit) => storage_limit = limit,
+                Err(OcyError::RedisConnection(_)) if degraded_mode => (),
+                Err(err) => return quota_rejected(&queue_name, err),
+            }
+        }
+    }
+
+    let job_write_res = match file::write_job(&queue_name, &job_req, data.config.persistence.durability) {
+        Ok(job_write_res) => job_write_res,
+        Err(err) => {
+            error!("[queue:{}] failed to write job attempt to disk: {}", &queue_name, err);
+            return HttpResponse::InternalServerError().body(err.to_string());
+        }
+    };
+
+    // don't wait on Redis while it's known to be down, the job will be replayed from disk once it recovers
+    if degraded_mode && data.circuit_breaker.retry_after().is_some() {
+        return accepted(&name, &queue_name, job_write_res.1, data.config.persistence.durability);
+    }
+
+    match RedisManager::create_job_within_limit(&mut conn, &queue_name, &job_req, storage_limit.as_ref()).await {
+        Ok(job::Created::Existing(job)) => {
+            let _del = file::delete_job(&queue_name, job_write_res.1, false);
+            existing_job(&tenant, job)
+        }
+        Ok(job::Created::New(job_id)) => {
+            data.events.job_event(EventKind::Created, job_id, Some(&queue_name));
+            // shadow jobs are informational only, so failing to create one doesn't fail the request
+            match data.redis_shards.shadow_job(&queue_name, job_id, &job_req).await {
+                Ok(Some((shadow_to, shadow_id))) => {
+                    data.events.job_event(EventKind::Created, shadow_id, Some(&shadow_to));
+                }
+                Ok(None) => (),
+                Err(err) => warn!("[queue:{}] failed to create shadow job: {}", &queue_name, err),
+            }
+            debug!("deleting job attempt {}", job_write_res.1);
+            let _del = file::delete_job(&queue_name, job_write_res.1, false);
             HttpResponse::Created()
                 .header("Location", format!("/job/{}", job_id))
                 .json(job_id)
@@ -181,6 +999,11 @@ pub async fn create_job(
             HttpResponse::NotFound().reason("Queue Not Found").finish()
         }
         Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::QuotaExceeded(msg)) => HttpResponse::TooManyRequests().body(msg),
+        Err(OcyError::RedisConnection(err)) if degraded_mode => {
+            warn!("[queue:{}] Redis unavailable, accepting job creation for replay: {}", &queue_name, err);
+            accepted(&name, &queue_name, job_write_res.1, data.config.persistence.durability)
+        }
         Err(OcyError::RedisConnection(err)) => {
             error!("[queue:{}] failed to create new job: {}", &queue_name, err);
             HttpResponse::ServiceUnavailable().body(err)
@@ -192,23 +1015,129 @@ pub async fn create_job(
     }
 }
 
+/// Response for a job creation request returning an existing job with the same `unique_key`, instead of creating a
+/// new job.
+fn existing_job(tenant: &Tenant, mut job: job::JobMeta) -> HttpResponse {
+    tenant.scope_job(&mut job);
+    HttpResponse::Ok()
+        .header("Location", format!("/job/{}", job.id()))
+        .json(job)
+}
+
+/// Response for a request rejected by the client's quota, or that failed while checking it.
+fn quota_rejected(queue_name: &str, err: OcyError) -> HttpResponse {
+    match err {
+        OcyError::Forbidden(msg) => HttpResponse::Forbidden().body(msg),
+        OcyError::QuotaExceeded(msg) => HttpResponse::TooManyRequests().body(msg),
+        OcyError::BadRequest(msg) => HttpResponse::BadRequest().body(msg),
+        OcyError::RedisConnection(err) => {
+            error!("[queue:{}] failed to check quota: {}", queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        err => {
+            error!("[queue:{}] failed to check quota: {}", queue_name, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Response for a job creation request that has been persisted to disk, but not yet created in Redis, once it's been
+/// marked to be replayed when Redis recovers.
+///
+/// The provisional ID is the ID of the attempt the request was written under, which can be used to reattempt it.
+fn accepted(name: &str, queue_name: &str, attempt_id: i64, durability: Durability) -> HttpResponse {
+    if let Err(err) = file::mark_pending(queue_name, attempt_id, durability) {
+        error!("[queue:{}] failed to mark job attempt {} for replay: {}", queue_name, attempt_id, err);
+        return HttpResponse::InternalServerError().body(err.to_string());
+    }
+    HttpResponse::Accepted()
+        .header("Location", format!("/queue/{}/reattempt/{}", name, attempt_id))
+        .json(attempt_id)
+}
+
 pub async fn next_job(
+    req: HttpRequest,
     path: web::Path<String>,
+    query: web::Query<NextJobQuery>,
+    tenant: Tenant,
     data: web::Data<ApplicationState>,
 ) -> impl Responder {
-    let queue_name = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
-
-    match RedisManager::next_queued_job(&mut conn, &queue_name).await {
-        Ok(Some(job)) => HttpResponse::Ok().json(job),
-        Ok(None) => match &data.config.server.next_job_delay {
-            Some(delay) if !delay.is_zero() => {
-                tokio::time::delay_for(delay.0).await;
-                HttpResponse::NoContent().into()
+    let queue_name = tenant.qualify(&path.into_inner());
+    let routing_keys: Vec<String> = query
+        .routing_keys
+        .as_deref()
+        .map(|keys| keys.split(',').map(str::trim).filter(|key| !key.is_empty()).map(str::to_owned).collect())
+        .unwrap_or_default();
+    if data.drain.is_draining() {
+        // workers should get their next job from another server
+        return HttpResponse::NoContent().finish();
+    }
+    #[cfg(feature = "testing")]
+    if crate::application::chaos::fail_dequeue() {
+        return HttpResponse::ServiceUnavailable().body("Injected dequeue failure");
+    }
+    let worker_id = req
+        .headers()
+        .get(data.config.server.access_log.identity_header.as_str())
+        .and_then(|value| value.to_str().ok());
+    if let Some(worker_id) = worker_id {
+        let mut conn = data.redis_shards.primary().get().for_operation(RedisOperation::Dequeue);
+        match RedisManager::worker_status(&mut conn, worker_id).await {
+            // draining workers should finish the jobs they're running, but not start any more
+            Ok(status) if status.draining => return HttpResponse::NoContent().finish(),
+            Ok(_) => (),
+            Err(OcyError::RedisConnection(err)) => {
+                error!("[queue:{}] failed to check whether worker {} is draining: {}", &queue_name, worker_id, err);
+                return HttpResponse::ServiceUnavailable().body(err);
             }
-            _ => HttpResponse::NoContent().into(),
-        },
+            Err(err) => {
+                error!("[queue:{}] failed to check whether worker {} is draining: {}", &queue_name, worker_id, err);
+                return HttpResponse::InternalServerError().body(err);
+            }
+        }
+    }
+    if let Some(retry_after) = data.poll_throttle.check(&queue_name, Instant::now()) {
+        debug!("[queue:{}] throttling poll of frequently empty queue", &queue_name);
+        return HttpResponse::TooManyRequests()
+            .header("Retry-After", retry_after.as_secs().max(1).to_string())
+            .finish();
+    }
+    let mut conn = data.redis_shards.for_queue(&queue_name).get().for_operation(RedisOperation::Dequeue);
+
+    let sticky_sessions = Some(&data.config.server.sticky_sessions);
+    match RedisManager::next_routed_job(&mut conn, &queue_name, &routing_keys, worker_id, sticky_sessions).await {
+        Ok(Some(job)) => {
+            data.poll_throttle.record_job(&queue_name);
+            data.events.job_event(EventKind::Started, job.id(), Some(&queue_name));
+            HttpResponse::Ok().json(job)
+        }
+        Ok(None) => {
+            data.poll_throttle.record_empty_poll(&queue_name, Instant::now());
+            if let Some(delay) = &data.config.server.next_job_delay {
+                if !delay.is_zero() {
+                    tokio::time::delay_for(delay.0).await;
+                }
+            }
+
+            let max_poll_hint = &data.config.server.max_poll_hint;
+            if max_poll_hint.is_zero() {
+                return HttpResponse::NoContent().finish();
+            }
+            match RedisManager::queue_poll_hint(&mut conn, &queue_name, max_poll_hint.0).await {
+                Ok(hint) => {
+                    HttpResponse::NoContent()
+                        .header("Retry-After", hint.as_secs().to_string())
+                        .finish()
+                }
+                Err(err) => {
+                    // hint is only advisory, so don't fail the request over it
+                    debug!("[queue:{}] unable to calculate polling hint: {}", &queue_name, err);
+                    HttpResponse::NoContent().finish()
+                }
+            }
+        }
         Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().into(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
         Err(OcyError::RedisConnection(err)) => {
             error!("[queue:{}] failed to fetch next job: {}", &queue_name, err);
             HttpResponse::ServiceUnavailable().body(err)
@@ -221,49 +1150,56 @@ pub async fn next_job(
 }
 
 pub async fn reattempt_job(
-    web::Path((queue_name, timestamp)): web::Path<(String, i64)>,
+    web::Path((queue_name, attempt_id)): web::Path<(String, i64)>,
+    tenant: Tenant,
     data: web::Data<ApplicationState>,
 ) -> impl Responder {
+    let queue_name = tenant.qualify(&queue_name);
 
-    let mut conn = data.redis_conn_manager.clone();
+    debug!("attempting to reattempt {:?} on {}", attempt_id, &queue_name);
 
-    debug!("attempting to reattempt {:?} on {}", timestamp, &queue_name);
+    // claimed so that the attempt isn't also created by a concurrent reattempt, or by replay if it's pending
+    let (mut job_req, pending) = match file::claim_job(&queue_name, attempt_id) {
+        Ok(Some(claimed)) => claimed,
+        Ok(None) => return HttpResponse::NotFound().reason("Job Attempt Not Found").finish(),
+        Err(err) => {
+            error!("[queue:{}] failed to reattempt failed job creation: {}", &queue_name, err);
+            return HttpResponse::InternalServerError()
+                .body(format!("[queue:{}] failed to reattempt failed job creation: {}", &queue_name, err));
+        }
+    };
 
-    match file::get_job(&queue_name, timestamp) {
-        Ok(mut job_req) => {
-            debug!("attempting to reattempt {:?} on {}", job_req, timestamp);
-            //this will not work in the input value is not an object
-            if let Some(serde_json::Value::Object(input)) = &mut job_req.input {
-                input.extend([ // note: requires fairly recent (stable) Rust, otherwise arrays are not `IntoIterator`
-                    ("attempted_on".to_owned(), timestamp.into()),
-                ]);
-            }
-            match RedisManager::create_job(&mut conn, &queue_name, &job_req).await {
-                Ok(job_id) => {
-                    debug!("deleting job attempt {:?} on {}", job_req, timestamp);
-                    let _del = file::delete_job(&queue_name, timestamp);
-                    HttpResponse::Created()
-                        .header("Location", format!("/job/{}", job_id))
-                        .json(job_id)
-                },
-                Err(OcyError::NoSuchQueue(_)) => {
-                    HttpResponse::NotFound().reason("Queue Not Found").finish()
-                }
-                Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
-                Err(OcyError::RedisConnection(err)) => {
-                    error!("[queue:{}] failed to reattempt creating new job: {}", &queue_name, err);
-                    HttpResponse::ServiceUnavailable().body(err)
-                }
-                Err(err) => {
-                    error!("[queue:{}] failed to reattempt creating new job: {}", &queue_name, err);
-                    HttpResponse::InternalServerError().body(err)
-                }
-            }
+    debug!("attempting to reattempt {:?} on {}", job_req, attempt_id);
+    file::mark_attempted(&mut job_req, attempt_id);
+    // checked against quotas like any other job, so namespaces can't exceed them by reattempting jobs
+    let quota = data.config.auth.quota(&tenant);
+    let res = data.redis_shards.create_job_within_quota(&queue_name, &job_req, quota).await;
+    if res.is_ok() {
+        debug!("deleting job attempt {:?} on {}", job_req, attempt_id);
+        let _del = file::delete_job(&queue_name, attempt_id, pending);
+    } else if let Err(err) = file::release_job(&queue_name, attempt_id, pending) {
+        error!("[queue:{}] failed to release job attempt {}: {}", &queue_name, attempt_id, err);
+    }
+    match res {
+        Ok(job::Created::Existing(job)) => existing_job(&tenant, job),
+        Ok(job::Created::New(job_id)) => {
+            data.events.job_event(EventKind::Created, job_id, Some(&queue_name));
+            HttpResponse::Created()
+                .header("Location", format!("/job/{}", job_id))
+                .json(job_id)
         },
+        Err(OcyError::NoSuchQueue(_)) => {
+            HttpResponse::NotFound().reason("Queue Not Found").finish()
+        }
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::QuotaExceeded(msg)) => HttpResponse::TooManyRequests().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[queue:{}] failed to reattempt creating new job: {}", &queue_name, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
         Err(err) => {
-            error!("[queue:{}] failed to reattempt failed job creation: {}", &queue_name, err);
-            HttpResponse::InternalServerError().body(format!("[queue:{}] failed to reattempt failed job creation: {}", &queue_name, err))
+            error!("[queue:{}] failed to reattempt creating new job: {}", &queue_name, err);
+            HttpResponse::InternalServerError().body(err)
         }
     }
 }
-
@@ -0,0 +1,54 @@
+//! HTTP handlers for the `/quota` endpoint.
+
+use actix_web::{web, HttpResponse, Responder};
+use log::error;
+use serde::Deserialize;
+
+use crate::application::limits;
+use crate::models::{quota, ApplicationState, OcyError, Tenant};
+
+#[derive(Deserialize)]
+pub struct QuotaQuery {
+    namespace: Option<String>,
+}
+
+/// Handles `GET /quota[?namespace=<namespace>]` requests.
+///
+/// Clients with a namespace key get their own namespace's quota and usage, while admin clients must give the
+/// namespace to get.
+///
+/// # Returns
+///
+/// * 200 - JSON containing the namespace's quota, and the resources it's currently using
+/// * 400 - no namespace given by an admin client, or invalid namespace given
+/// * 403 - namespace key used to get a different namespace
+pub async fn index(
+    query: web::Query<QuotaQuery>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let namespace = match (tenant.namespace(), query.into_inner().namespace) {
+        (Some(own), Some(namespace)) if own != namespace => {
+            return HttpResponse::Forbidden().body("API key doesn't have access to this namespace")
+        }
+        (Some(own), _) => own.to_owned(),
+        (None, Some(namespace)) if Tenant::is_valid_namespace(&namespace) => namespace,
+        (None, Some(_)) => return HttpResponse::BadRequest().body(limits::get().invalid_namespace_message()),
+        (None, None) => return HttpResponse::BadRequest().body("Namespace must be given"),
+    };
+
+    match data.redis_shards.namespace_usage(&namespace).await {
+        Ok(usage) => {
+            let quota = data.config.auth.quotas.get(&namespace).cloned().unwrap_or_default();
+            HttpResponse::Ok().json(quota::Report { namespace, quota, usage })
+        }
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[namespace:{}] failed to fetch quota usage: {}", namespace, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[namespace:{}] failed to fetch quota usage: {}", namespace, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Defines the handler for the HTML status page, summarising queues and server health for wall displays.
+//!
+//! The page is rendered server-side from the same data as `GET /queue?summary=true`, and is self-contained (no
+//! scripts, stylesheets or images are loaded from elsewhere), so it can be shown by any browser that can reach the
+//! server. It reloads itself periodically using a `<meta http-equiv="refresh">` tag.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use actix_web::{web, HttpResponse, Responder};
+use log::error;
+
+use super::queue::cached_queue_summaries;
+use crate::middleware::circuit_breaker::BreakerState;
+use crate::models::{queue, ApplicationState, DateTime};
+
+/// Styles applied to the status page.
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;background:#111;color:#eee}\
+    table{border-collapse:collapse;width:100%}th,td{padding:.4em .8em;text-align:right;border-bottom:1px solid #333}\
+    th:first-child,td:first-child{text-align:left}.ok{color:#4c4}.bad{color:#e44}.warn{color:#eb4}";
+
+/// Health of the server shown at the top of the status page.
+struct ServerHealth {
+    breaker: BreakerState,
+    draining: bool,
+    error: Option<String>,
+}
+
+impl ServerHealth {
+    fn is_healthy(&self) -> bool {
+        self.breaker == BreakerState::Closed && !self.draining && self.error.is_none()
+    }
+}
+
+/// Handles `GET /status` requests, if the status page is enabled.
+///
+/// This bypasses the circuit breaker, so that the page shows Redis being unavailable rather than failing, and doesn't
+/// query Redis unless the breaker is closed.
+///
+/// # Returns
+///
+/// * 200 - HTML page summarising each queue, and the server's health
+/// * 404 - status page is disabled
+pub async fn index(data: web::Data<ApplicationState>) -> impl Responder {
+    let config = &data.config.server.status_page;
+    if !config.enabled {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let breaker = data.circuit_breaker.state();
+    let (summaries, error) = if breaker != BreakerState::Closed {
+        (HashMap::new(), None)
+    } else {
+        match cached_queue_summaries(&data).await {
+            Ok(summaries) => (summaries, None),
+            Err(err) => {
+                error!("Failed to fetch queue summaries for status page: {}", err);
+                (HashMap::new(), Some(err.to_string()))
+            }
+        }
+    };
+    let health = ServerHealth {
+        breaker,
+        draining: data.drain.is_draining(),
+        error,
+    };
+    let page = render(&config.title, config.refresh.as_secs(), &health, &summaries, &DateTime::now());
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(page)
+}
+
+/// Render the status page for given queue summaries.
+fn render(
+    title: &str,
+    refresh_secs: u64,
+    health: &ServerHealth,
+    summaries: &HashMap<String, queue::Summary>,
+    now: &DateTime,
+) -> String {
+    let title = escape(title);
+    let mut page = String::new();
+    let _ = write!(
+        page,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"{}\">\
+         <title>{}</title><style>{}</style></head><body><h1>{}</h1>",
+        refresh_secs.max(1),
+        title,
+        STYLE,
+        title
+    );
+
+    let (class, status) = if health.is_healthy() { ("ok", "Healthy") } else { ("bad", "Unhealthy") };
+    let _ = write!(page, "<p>Server: <strong class=\"{}\">{}</strong>", class, status);
+    match health.breaker {
+        BreakerState::Closed => (),
+        BreakerState::Open => page.push_str(" &middot; Redis circuit breaker open"),
+        BreakerState::HalfOpen => page.push_str(" &middot; Redis circuit breaker half-open"),
+    }
+    if health.draining {
+        page.push_str(" &middot; draining");
+    }
+    if let Some(error) = &health.error {
+        let _ = write!(page, " &middot; {}", escape(error));
+    }
+    let _ = write!(page, "</p><p>Updated {}</p>", now);
+
+    page.push_str(
+        "<table><tr><th>Queue</th><th>Queued</th><th>Oldest queued</th><th>Running</th><th>Failed</th>\
+         <th>Timed out</th><th>Quarantined</th><th>Completed</th><th>Paused until</th></tr>",
+    );
+    let mut names: Vec<&String> = summaries.keys().collect();
+    names.sort();
+    for name in names {
+        let summary = &summaries[name];
+        let jobs = &summary.jobs;
+        let oldest = summary.oldest_queued_age.as_ref().map(|age| age.to_string()).unwrap_or_default();
+        let (paused_class, paused_until) = match &summary.paused_until {
+            Some(paused_until) => ("warn", paused_until.to_string()),
+            None => ("", String::new()),
+        };
+        let failed_class = if jobs.failed + jobs.timed_out + jobs.quarantined > 0 { "bad" } else { "" };
+        let _ = write!(
+            page,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td class=\"{}\">{}</td>\
+             <td class=\"{}\">{}</td><td>{}</td><td class=\"{}\">{}</td></tr>",
+            escape(name),
+            jobs.queued,
+            oldest,
+            jobs.running,
+            failed_class,
+            jobs.failed,
+            failed_class,
+            jobs.timed_out,
+            failed_class,
+            jobs.quarantined,
+            jobs.completed,
+            paused_class,
+            paused_until
+        );
+    }
+    page.push_str("</table></body></html>");
+    page
+}
+
+/// Escape given text for including in HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{Duration, QueueInfo};
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("<b>\"Tom\" & 'Jerry'</b>"), "&lt;b&gt;&quot;Tom&quot; &amp; &#39;Jerry&#39;&lt;/b&gt;");
+        assert_eq!(escape("emails"), "emails");
+    }
+
+    #[test]
+    fn rendering() {
+        let jobs = QueueInfo {
+            queued: 12,
+            running: 3,
+            failed: 1,
+            ..Default::default()
+        };
+        let mut summaries = HashMap::new();
+        summaries.insert(
+            "emails".to_owned(),
+            queue::Summary::new(jobs, queue::Settings::default(), Some(Duration::from_secs(90)), None),
+        );
+        let health = ServerHealth {
+            breaker: BreakerState::Closed,
+            draining: false,
+            error: None,
+        };
+
+        let page = render("Prod <jobs>", 15, &health, &summaries, &DateTime::now());
+        assert!(page.contains("content=\"15\""));
+        assert!(page.contains("<title>Prod &lt;jobs&gt;</title>"));
+        assert!(page.contains("class=\"ok\">Healthy"));
+        assert!(page.contains("<td>emails</td><td>12</td><td>1m 30s</td><td>3</td><td class=\"bad\">1</td>"));
+
+        let health = ServerHealth {
+            breaker: BreakerState::Open,
+            draining: false,
+            error: Some("connection refused".to_owned()),
+        };
+        let page = render("Ocypod", 0, &health, &HashMap::new(), &DateTime::now());
+        assert!(page.contains("content=\"1\""));
+        assert!(page.contains("class=\"bad\">Unhealthy"));
+        assert!(page.contains("circuit breaker open"));
+        assert!(page.contains("connection refused"));
+    }
+}
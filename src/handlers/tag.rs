@@ -2,17 +2,17 @@ use log::error;
 
 use actix_web::{web, HttpResponse, Responder};
 
-use crate::application::RedisManager;
-use crate::models::{ApplicationState, OcyError};
+use crate::application::limits;
+use crate::events::EventKind;
+use crate::models::{tag, ApplicationState, OcyError, Tenant};
 
 pub async fn tagged_jobs(
     path: web::Path<String>,
+    tenant: Tenant,
     data: web::Data<ApplicationState>,
 ) -> impl Responder {
-    let tag = path.into_inner();
-    let mut conn = data.redis_conn_manager.clone();
-
-    match RedisManager::tagged_job_ids(&mut conn, &tag).await {
+    let tag = tenant.qualify(&path.into_inner());
+    match data.redis_shards.tagged_job_ids(&tag).await {
         Ok(tags) => HttpResponse::Ok().json(tags),
         Err(OcyError::RedisConnection(err)) => {
             error!("Failed to read tag data: {}", err);
@@ -24,3 +24,119 @@ pub async fn tagged_jobs(
         }
     }
 }
+
+/// Handles `POST /tag/{tag_name}/apply` requests, attaching a tag to many jobs at once.
+///
+/// # Returns
+///
+/// * 200 - JSON object listing the IDs of jobs that were `tagged`, or were `not_found`
+/// * 400 - invalid tag name, or invalid request
+/// * 404 - queue to tag jobs from not found
+pub async fn apply(
+    path: web::Path<String>,
+    json: web::Json<tag::ApplyRequest>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    let tag = tenant.qualify(&path.into_inner());
+    if let Err(msg) = limits::get().check_unreserved("tag name", &tag) {
+        return HttpResponse::BadRequest().body(msg);
+    }
+    let mut apply_req = json.into_inner();
+    apply_req.queue = apply_req.queue.map(|queue| tenant.qualify(&queue));
+
+    match data.redis_shards.apply_tag(&tag, &apply_req, &tenant).await {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(OcyError::NoSuchQueue(_)) => HttpResponse::NotFound().reason("Queue Not Found").finish(),
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[tag:{}] failed to apply tag: {}", &tag, err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[tag:{}] failed to apply tag: {}", &tag, err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
+
+/// Handles `POST /tag/{tag_name}/retry` requests, re-queueing every failed, timed out, cancelled or quarantined job
+/// with a tag.
+///
+/// # Returns
+///
+/// * 200 - JSON object counting the jobs that `succeeded`, were `skipped`, or were `not_found`
+/// * 400 - invalid tag name
+pub async fn retry(
+    path: web::Path<String>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    bulk_operation(path, tag::BulkOperation::Retry, tenant, data).await
+}
+
+/// Handles `POST /tag/{tag_name}/cancel` requests, cancelling every queued, running, failed or quarantined job with a
+/// tag.
+///
+/// # Returns
+///
+/// * 200 - JSON object counting the jobs that `succeeded`, were `skipped`, or were `not_found`
+/// * 400 - invalid tag name
+pub async fn cancel(
+    path: web::Path<String>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    bulk_operation(path, tag::BulkOperation::Cancel, tenant, data).await
+}
+
+/// Handles `POST /tag/{tag_name}/delete` requests, deleting every job with a tag, or moving them to the trash if a
+/// delete recovery window is configured.
+///
+/// # Returns
+///
+/// * 200 - JSON object counting the jobs that `succeeded`, were `skipped`, or were `not_found`
+/// * 400 - invalid tag name
+pub async fn delete(
+    path: web::Path<String>,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> impl Responder {
+    bulk_operation(path, tag::BulkOperation::Delete, tenant, data).await
+}
+
+/// Apply given operation to every job with the tag in given path, publishing an event for each job changed.
+async fn bulk_operation(
+    path: web::Path<String>,
+    operation: tag::BulkOperation,
+    tenant: Tenant,
+    data: web::Data<ApplicationState>,
+) -> HttpResponse {
+    let tag = tenant.qualify(&path.into_inner());
+    let recovery_window = &data.config.server.delete_recovery_window;
+
+    match data.redis_shards.bulk_tag_operation(&tag, operation, &tenant, recovery_window).await {
+        Ok(results) => {
+            let event = match operation {
+                tag::BulkOperation::Retry => Some(EventKind::Retried),
+                tag::BulkOperation::Cancel => Some(EventKind::Cancelled),
+                tag::BulkOperation::Delete => None,
+            };
+            if let Some(event) = event {
+                for job_id in &results.job_ids {
+                    data.events.job_event(event, *job_id, None);
+                }
+            }
+            HttpResponse::Ok().json(results)
+        }
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(OcyError::RedisConnection(err)) => {
+            error!("[tag:{}] failed to {} jobs: {}", &tag, operation.label(), err);
+            HttpResponse::ServiceUnavailable().body(err)
+        }
+        Err(err) => {
+            error!("[tag:{}] failed to {} jobs: {}", &tag, operation.label(), err);
+            HttpResponse::InternalServerError().body(err)
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! HTTP handlers for the `/testing` endpoints, which inject failures and force monitor passes so that clients can be
+//! tested against them. Only available when built with the "testing" feature.
+
+use actix_web::{web, HttpResponse, Responder};
+use log::{error, warn};
+
+use crate::application::chaos::Chaos;
+use crate::application::metrics::Monitor;
+use crate::application::monitor;
+use crate::models::{ApplicationState, OcyError};
+
+/// Register the `/testing` endpoints.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/testing")
+            .service(
+                web::resource("/chaos")
+                    // Get the failures currently being injected.
+                    .route(web::get().to(chaos))
+                    // Replace the failures being injected.
+                    .route(web::put().to(set_chaos))
+                    // Stop injecting any failures.
+                    .route(web::delete().to(clear_chaos)),
+            )
+            // Run a monitor's checks now.
+            .route("/monitor/{monitor}", web::post().to(run_monitor)),
+    );
+}
+
+/// Handles `GET /testing/chaos` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON object describing the failures currently being injected
+pub async fn chaos() -> impl Responder {
+    HttpResponse::Ok().json(Chaos::current())
+}
+
+/// Handles `PUT /testing/chaos` requests. Replaces all injected failures, any not given are no longer injected.
+///
+/// # Returns
+///
+/// * 204 - failures updated
+/// * 400 - invalid failures given
+pub async fn set_chaos(json: web::Json<Chaos>) -> impl Responder {
+    let chaos = json.into_inner();
+    match Chaos::set(chaos.clone()) {
+        Ok(()) => {
+            warn!("Injecting failures: {:?}", chaos);
+            HttpResponse::NoContent().finish()
+        }
+        Err(OcyError::BadRequest(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(err) => HttpResponse::InternalServerError().body(err),
+    }
+}
+
+/// Handles `DELETE /testing/chaos` requests.
+///
+/// # Returns
+///
+/// * 204 - no failures are injected any more
+pub async fn clear_chaos() -> impl Responder {
+    match Chaos::set(Chaos::default()) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(err) => HttpResponse::InternalServerError().body(err),
+    }
+}
+
+/// Handles `POST /testing/monitor/{monitor}` requests.
+///
+/// Runs a pass of the timeout, retry, or expiry monitor on every shard now, whatever the queues' check intervals.
+///
+/// # Returns
+///
+/// * 200 - JSON object containing the number of jobs transitioned by the pass
+/// * 404 - no such monitor, or it can't be forced to run
+/// * 500 - unexpected internal error
+/// * 503 - Redis connection unavailable
+pub async fn run_monitor(path: web::Path<String>, data: web::Data<ApplicationState>) -> impl Responder {
+    let monitor = match path.as_str() {
+        "timeout" => Monitor::Timeout,
+        "retry" => Monitor::Retry,
+        "expiry" => Monitor::Expiry,
+        _ => return HttpResponse::NotFound().reason("Monitor Not Found").finish(),
+    };
+
+    let mut transitioned = 0;
+    for pool in data.redis_shards.all() {
+        match monitor::force_pass(&mut pool.get(), monitor, &data.config.server, &data.events).await {
+            Ok(count) => transitioned += count,
+            Err(OcyError::RedisConnection(err)) => {
+                error!("Failed to force {} monitor pass: {}", monitor.label(), err);
+                return HttpResponse::ServiceUnavailable().body(err);
+            }
+            Err(err) => {
+                error!("Failed to force {} monitor pass: {}", monitor.label(), err);
+                return HttpResponse::InternalServerError().body(err);
+            }
+        }
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "transitioned": transitioned }))
+}
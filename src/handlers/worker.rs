@@ -0,0 +1,84 @@
+//! HTTP handlers for the `/worker` endpoints.
+//!
+//! Workers are identified by the ID they send in the server's identity header (`X-Worker-Id` by default, see
+//! `server.access_log.identity_header`), and don't need to be registered before they're drained.
+
+use actix_web::{web, HttpResponse, Responder};
+use log::{error, info};
+
+use crate::application::RedisManager;
+use crate::models::{ApplicationState, OcyError};
+
+/// Handles `GET /worker` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON list of the statuses of all draining workers, ordered by worker ID
+pub async fn index(data: web::Data<ApplicationState>) -> impl Responder {
+    match RedisManager::draining_workers(&mut data.redis_shards.primary().get()).await {
+        Ok(workers) => HttpResponse::Ok().json(workers),
+        Err(err) => worker_error("failed to get draining workers", err),
+    }
+}
+
+/// Handles `GET /worker/{id}` requests.
+///
+/// # Returns
+///
+/// * 200 - JSON containing the worker's drain status
+pub async fn status(path: web::Path<String>, data: web::Data<ApplicationState>) -> impl Responder {
+    let worker_id = path.into_inner();
+    match RedisManager::worker_status(&mut data.redis_shards.primary().get(), &worker_id).await {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(err) => worker_error(&format!("[worker:{}] failed to get status", worker_id), err),
+    }
+}
+
+/// Handles `POST /worker/{id}/drain` requests.
+///
+/// Marks the worker as draining, e.g. before it's restarted during a rolling deploy. Draining workers aren't given any
+/// new jobs by `GET /queue/{queue_name}/job`, but can finish and report on the jobs they're already running.
+///
+/// # Returns
+///
+/// * 204 - worker is draining
+pub async fn drain(path: web::Path<String>, data: web::Data<ApplicationState>) -> impl Responder {
+    let worker_id = path.into_inner();
+    match RedisManager::drain_worker(&mut data.redis_shards.primary().get(), &worker_id).await {
+        Ok(drained) => {
+            if drained {
+                info!("[worker:{}] draining, no longer giving it jobs", worker_id);
+            }
+            HttpResponse::NoContent().finish()
+        }
+        Err(err) => worker_error(&format!("[worker:{}] failed to drain", worker_id), err),
+    }
+}
+
+/// Handles `DELETE /worker/{id}/drain` requests.
+///
+/// Stops the worker draining, so that it's given jobs again, e.g. once it's been restarted.
+///
+/// # Returns
+///
+/// * 204 - worker is no longer draining
+pub async fn undrain(path: web::Path<String>, data: web::Data<ApplicationState>) -> impl Responder {
+    let worker_id = path.into_inner();
+    match RedisManager::undrain_worker(&mut data.redis_shards.primary().get(), &worker_id).await {
+        Ok(undrained) => {
+            if undrained {
+                info!("[worker:{}] no longer draining, giving it jobs", worker_id);
+            }
+            HttpResponse::NoContent().finish()
+        }
+        Err(err) => worker_error(&format!("[worker:{}] failed to stop draining", worker_id), err),
+    }
+}
+
+fn worker_error(msg: &str, err: OcyError) -> HttpResponse {
+    error!("{}: {}", msg, err);
+    match err {
+        OcyError::RedisConnection(err) => HttpResponse::ServiceUnavailable().body(err),
+        err => HttpResponse::InternalServerError().body(err),
+    }
+}
@@ -11,9 +11,16 @@
     unused_import_braces,
     unused_qualifications
 )]
-
 pub mod application;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod config;
+pub mod events;
 pub mod handlers;
+pub mod logging;
+pub mod middleware;
 pub mod models;
 pub mod redis_utils;
+pub mod systemd;
+#[cfg(feature = "test-util")]
+pub mod test_util;
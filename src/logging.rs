@@ -0,0 +1,201 @@
+//! Logging, with filters that can be changed while the server is running.
+//!
+//! Log lines are written in the same format as `env_logger`, but rather than fixing its filters at startup, the
+//! current filters are kept in a `LogFilter`, which can be replaced at any time, e.g. to temporarily log at debug
+//! level while reproducing an issue.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use env_logger::filter::{Builder, Filter};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{OcyError, OcyResult};
+
+/// Modules logged at the base log level.
+const OCYPOD_MODULES: [&str; 2] = ["ocypod", "ocypod-server"];
+
+/// Log levels to use, as configured at startup or set by `PUT /admin/log_level`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LogSettings {
+    /// Log level for all of Ocypod's modules, e.g. "debug".
+    pub level: String,
+
+    /// Log levels for specific modules, overriding `level`, e.g. `{"ocypod::application::monitor": "trace"}`.
+    /// Modules of dependencies can also be given, e.g. "actix_web" or "redis", which otherwise aren't logged.
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+}
+
+impl LogSettings {
+    /// Get settings logging all of Ocypod's modules at given level.
+    pub fn new(level: log::Level) -> Self {
+        Self {
+            level: level.to_string().to_lowercase(),
+            modules: BTreeMap::new(),
+        }
+    }
+
+    /// Get these settings as `env_logger` filter directives, or an error if any levels or module names are invalid.
+    fn directives(&self) -> OcyResult<String> {
+        let level = parse_level(&self.level)?;
+        let mut directives: Vec<String> = OCYPOD_MODULES
+            .iter()
+            .map(|module| format!("{}={}", module, level))
+            .collect();
+        for (module, level) in &self.modules {
+            if module.is_empty() || module.contains(|c: char| c == ',' || c == '=' || c == '/' || c.is_whitespace()) {
+                return Err(OcyError::bad_request(format!("Invalid module name: {:?}", module)));
+            }
+            directives.push(format!("{}={}", module, parse_level(level)?));
+        }
+        Ok(directives.join(","))
+    }
+
+    fn filter(&self) -> OcyResult<Filter> {
+        Ok(Builder::new().parse(&self.directives()?).build())
+    }
+}
+
+fn parse_level(level: &str) -> OcyResult<LevelFilter> {
+    LevelFilter::from_str(level).map_err(|_| {
+        OcyError::bad_request(format!(
+            "Invalid log level {:?}, expected one of: off, error, warn, info, debug, trace",
+            level
+        ))
+    })
+}
+
+/// Current log filters, shared between the logger and the handlers that change them.
+#[derive(Debug)]
+pub struct LogFilter {
+    current: RwLock<(LogSettings, Filter)>,
+}
+
+impl LogFilter {
+    /// Create a filter with given settings, without initialising a logger that uses it, e.g. for servers embedded in
+    /// tests, which leave logging to the test itself.
+    pub fn new(settings: LogSettings) -> OcyResult<Self> {
+        let filter = settings.filter()?;
+        Ok(Self {
+            current: RwLock::new((settings, filter)),
+        })
+    }
+
+    /// Get the current log settings.
+    pub fn settings(&self) -> LogSettings {
+        self.current.read().unwrap().0.clone()
+    }
+
+    /// Replace the current log settings, taking effect immediately.
+    ///
+    /// Returns a `BadRequest` error if any levels or module names are invalid, in which case the current settings are
+    /// left unchanged.
+    pub fn set(&self, settings: LogSettings) -> OcyResult<()> {
+        let filter = settings.filter()?;
+        let max_level = filter.filter();
+        *self.current.write().unwrap() = (settings, filter);
+        log::set_max_level(max_level);
+        Ok(())
+    }
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.current.read().unwrap().1.enabled(metadata)
+    }
+
+    fn matches(&self, record: &Record) -> bool {
+        self.current.read().unwrap().1.matches(record)
+    }
+}
+
+/// Logger checking records against the current `LogFilter`, before writing them using `env_logger`.
+#[derive(Debug)]
+struct ReloadableLogger {
+    filter: Arc<LogFilter>,
+    writer: env_logger::Logger,
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.filter.matches(record) {
+            self.writer.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.writer.flush();
+    }
+}
+
+/// Initialise logging with given settings, returning the filter used, which can be changed later.
+///
+/// Panics if a logger has already been initialised.
+pub fn init(settings: LogSettings) -> OcyResult<Arc<LogFilter>> {
+    let filter = Arc::new(LogFilter::new(settings)?);
+    let max_level = filter.current.read().unwrap().1.filter();
+
+    // filtering is done by the current `LogFilter`, so the writer itself must allow everything through
+    let writer = env_logger::Builder::new()
+        .filter_level(LevelFilter::Trace)
+        .format_module_path(false)
+        .build();
+    log::set_boxed_logger(Box::new(ReloadableLogger {
+        filter: filter.clone(),
+        writer,
+    }))
+    .expect("logger already initialised");
+    log::set_max_level(max_level);
+    Ok(filter)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn directives() {
+        let mut settings = LogSettings::new(log::Level::Info);
+        assert_eq!(settings.directives().unwrap(), "ocypod=INFO,ocypod-server=INFO");
+
+        settings.level = "debug".to_owned();
+        settings.modules.insert("ocypod::application::monitor".to_owned(), "trace".to_owned());
+        settings.modules.insert("redis".to_owned(), "WARN".to_owned());
+        assert_eq!(
+            settings.directives().unwrap(),
+            "ocypod=DEBUG,ocypod-server=DEBUG,ocypod::application::monitor=TRACE,redis=WARN"
+        );
+
+        let filter = settings.filter().unwrap();
+        assert_eq!(filter.filter(), LevelFilter::Trace);
+        let enabled = |target: &str, level: log::Level| {
+            filter.enabled(&Metadata::builder().target(target).level(level).build())
+        };
+        assert!(enabled("ocypod::application::monitor", log::Level::Trace));
+        assert!(enabled("ocypod::application::pool", log::Level::Debug));
+        assert!(!enabled("ocypod::application::pool", log::Level::Trace));
+        assert!(enabled("redis::aio", log::Level::Warn));
+        assert!(!enabled("redis::aio", log::Level::Info));
+        assert!(!enabled("actix_web", log::Level::Error));
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let mut settings = LogSettings::new(log::Level::Info);
+        settings.level = "verbose".to_owned();
+        assert!(settings.directives().is_err());
+
+        let mut settings = LogSettings::new(log::Level::Info);
+        settings.modules.insert("redis".to_owned(), "loud".to_owned());
+        assert!(settings.directives().is_err());
+
+        let mut settings = LogSettings::new(log::Level::Info);
+        settings.modules.insert("redis=trace,actix".to_owned(), "debug".to_owned());
+        assert!(settings.directives().is_err());
+    }
+}
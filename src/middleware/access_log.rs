@@ -0,0 +1,276 @@
+//! Middleware writing a line to the access log for each HTTP request.
+//!
+//! Lines are written in Common Log Format, Combined Log Format, or as JSON objects, to stdout or a file. The client's
+//! identity is taken from a configurable request header (e.g. one set by workers, or by a proxy that authenticates
+//! API keys), since Ocypod itself doesn't authenticate clients.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::Error;
+use chrono::{DateTime, Utc};
+use futures::future::{ok, Future, Ready};
+use log::error;
+use serde::Serialize;
+
+use crate::config::{AccessLogConfig, AccessLogFormat};
+
+/// Destination access log lines are written to.
+pub struct AccessLog {
+    format: AccessLogFormat,
+    identity_header: String,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    /// Open the access log, appending to the configured file (creating it if necessary), or stdout if none is
+    /// configured.
+    pub fn open(config: &AccessLogConfig) -> io::Result<Self> {
+        let out: Box<dyn Write + Send> = match &config.path {
+            Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+            None => Box::new(io::stdout()),
+        };
+        Ok(Self {
+            format: config.format,
+            identity_header: config.identity_header.clone(),
+            out: Mutex::new(out),
+        })
+    }
+
+    fn write(&self, entry: &Entry) {
+        let mut line = entry.format(self.format);
+        line.push('\n');
+        if let Err(err) = self.out.lock().unwrap().write_all(line.as_bytes()) {
+            error!("Failed to write to access log: {}", err);
+        }
+    }
+}
+
+impl std::fmt::Debug for AccessLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("AccessLog")
+            .field("format", &self.format)
+            .field("identity_header", &self.identity_header)
+            .finish()
+    }
+}
+
+/// Details of a single request, as written to the access log.
+#[derive(Debug, Serialize)]
+struct Entry {
+    time: DateTime<Utc>,
+    remote_addr: Option<String>,
+    identity: Option<String>,
+    method: String,
+    path: String,
+    version: String,
+    status: u16,
+    body_size: Option<u64>,
+    latency_ms: f64,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl Entry {
+    fn new(req: &ServiceRequest, identity_header: &str, time: DateTime<Utc>) -> Self {
+        let get_header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_owned())
+        };
+        Self {
+            time,
+            remote_addr: req.peer_addr().map(|addr| addr.ip().to_string()),
+            identity: get_header(identity_header),
+            method: req.method().to_string(),
+            path: req.uri().path_and_query().map_or_else(|| req.path().to_owned(), |pq| pq.to_string()),
+            version: format!("{:?}", req.version()),
+            status: 0,
+            body_size: None,
+            latency_ms: 0.0,
+            referer: get_header(header::REFERER.as_str()),
+            user_agent: get_header(header::USER_AGENT.as_str()),
+        }
+    }
+
+    fn finish(&mut self, status: u16, body_size: Option<u64>, latency: Duration) {
+        self.status = status;
+        self.body_size = body_size;
+        self.latency_ms = latency.as_secs_f64() * 1000.0;
+    }
+
+    fn format(&self, format: AccessLogFormat) -> String {
+        let common = || {
+            format!(
+                "{} - {} [{}] \"{} {} {}\" {} {}",
+                self.remote_addr.as_deref().unwrap_or("-"),
+                self.identity.as_deref().unwrap_or("-"),
+                self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+                self.method,
+                self.path,
+                self.version,
+                self.status,
+                self.body_size.map_or_else(|| "-".to_owned(), |size| size.to_string()),
+            )
+        };
+        match format {
+            AccessLogFormat::Common => format!("{} {:.3}", common(), self.latency_ms / 1000.0),
+            AccessLogFormat::Combined => format!(
+                "{} \"{}\" \"{}\" {:.3}",
+                common(),
+                self.referer.as_deref().unwrap_or("-"),
+                self.user_agent.as_deref().unwrap_or("-"),
+                self.latency_ms / 1000.0
+            ),
+            AccessLogFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+}
+
+/// Middleware that writes each request to an access log, if one is given.
+///
+/// This should wrap all other middleware, so that requests rejected by them are logged too.
+pub struct AccessLogMiddleware {
+    log: Option<Arc<AccessLog>>,
+}
+
+impl AccessLogMiddleware {
+    pub fn new(log: Option<Arc<AccessLog>>) -> Self {
+        Self { log }
+    }
+}
+
+impl<S, B> Transform<S> for AccessLogMiddleware
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AccessLogService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AccessLogService {
+            service,
+            log: self.log.clone(),
+        })
+    }
+}
+
+pub struct AccessLogService<S> {
+    service: S,
+    log: Option<Arc<AccessLog>>,
+}
+
+impl<S, B> Service for AccessLogService<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let log = match &self.log {
+            Some(log) => log.clone(),
+            None => return Box::pin(self.service.call(req)),
+        };
+
+        let mut entry = Entry::new(&req, &log.identity_header, Utc::now());
+        let started = Instant::now();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await;
+            match &res {
+                Ok(res) => {
+                    let body_size = match res.response().body().size() {
+                        BodySize::Sized(size) => Some(size),
+                        BodySize::Empty | BodySize::None => Some(0),
+                        BodySize::Stream => None,
+                    };
+                    entry.finish(res.status().as_u16(), body_size, started.elapsed());
+                }
+                // errors are turned into responses by the server after all middleware has run
+                Err(err) => entry.finish(err.as_response_error().status_code().as_u16(), None, started.elapsed()),
+            }
+            log.write(&entry);
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry() -> Entry {
+        Entry {
+            time: Utc.ymd(2020, 10, 2).and_hms(13, 55, 36),
+            remote_addr: Some("127.0.0.1".to_owned()),
+            identity: Some("worker-1".to_owned()),
+            method: "GET".to_owned(),
+            path: "/queue/default/job?fields=id".to_owned(),
+            version: "HTTP/1.1".to_owned(),
+            status: 200,
+            body_size: Some(25),
+            latency_ms: 1.5,
+            referer: None,
+            user_agent: Some("curl/7.68.0".to_owned()),
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let entry = entry();
+        assert_eq!(
+            entry.format(AccessLogFormat::Common),
+            "127.0.0.1 - worker-1 [02/Oct/2020:13:55:36 +0000] \"GET /queue/default/job?fields=id HTTP/1.1\" 200 25 \
+             0.002"
+        );
+        assert_eq!(
+            entry.format(AccessLogFormat::Combined),
+            "127.0.0.1 - worker-1 [02/Oct/2020:13:55:36 +0000] \"GET /queue/default/job?fields=id HTTP/1.1\" 200 25 \
+             \"-\" \"curl/7.68.0\" 0.002"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&entry.format(AccessLogFormat::Json)).unwrap();
+        assert_eq!(json["identity"], "worker-1");
+        assert_eq!(json["method"], "GET");
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["body_size"], 25);
+        assert_eq!(json["latency_ms"], 1.5);
+        assert_eq!(json["referer"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn missing_fields() {
+        let entry = Entry {
+            remote_addr: None,
+            identity: None,
+            body_size: None,
+            ..entry()
+        };
+        assert!(entry.format(AccessLogFormat::Common).starts_with("- - - ["));
+        assert!(entry.format(AccessLogFormat::Common).ends_with(" 200 - 0.002"));
+    }
+}
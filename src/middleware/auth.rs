@@ -0,0 +1,381 @@
+//! Middleware authenticating clients by API key, and restricting them to their namespace.
+//!
+//! Clients give their API key using either an `Authorization: Bearer <key>` header, or an `X-Api-Key` header. Admin
+//! keys have access to everything, while namespace keys only have access to the `/queue`, `/job`, `/tag` and `/quota`
+//! endpoints, only to jobs on queues in their namespace, and only to operations allowed by their roles. Handlers scope
+//! queue and tag names to the namespace using the `Tenant` set on each request, while this middleware checks roles,
+//! the queues each role is scoped to, and access to jobs by ID, so that handlers don't need to.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, Method};
+use actix_web::{Error, HttpMessage, HttpResponse};
+use futures::future::{ok, Future, Ready};
+use log::error;
+
+use crate::application::shard::RedisShards;
+use crate::application::RedisManager;
+use crate::config::AuthConfig;
+use crate::models::{OcyError, Role, Tenant};
+
+/// Header API keys can be given in, as an alternative to `Authorization: Bearer <key>`.
+const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Endpoints that clients with a namespace key have access to. Server features aren't namespaced, but are needed by
+/// every client.
+const NAMESPACED_PATHS: [&str; 5] = ["/queue", "/job", "/tag", "/quota", "/info/features"];
+
+/// API keys clients can authenticate with, and the tenant each authenticates as.
+#[derive(Debug, Default)]
+pub struct ApiKeys {
+    admin_keys: HashSet<String>,
+    namespace_keys: HashMap<String, Tenant>,
+}
+
+impl ApiKeys {
+    /// Get the API keys configured in given auth configuration.
+    pub fn new(config: &AuthConfig) -> Self {
+        Self {
+            admin_keys: config.admin_keys.iter().cloned().collect(),
+            namespace_keys: config
+                .api_keys
+                .iter()
+                .map(|(key, conf)| {
+                    let tenant = Tenant::with_namespace(conf.namespace())
+                        .with_roles(conf.roles())
+                        .with_scopes(conf.scopes());
+                    (key.clone(), tenant)
+                })
+                .collect(),
+        }
+    }
+
+    /// Check whether clients must authenticate, i.e. whether any keys are configured.
+    pub fn is_enabled(&self) -> bool {
+        !self.admin_keys.is_empty() || !self.namespace_keys.is_empty()
+    }
+
+    /// Get the tenant given API key authenticates as, if it's valid.
+    pub fn tenant(&self, key: &str) -> Option<Tenant> {
+        if self.admin_keys.contains(key) {
+            Some(Tenant::admin())
+        } else {
+            self.namespace_keys.get(key).cloned()
+        }
+    }
+}
+
+/// Get the API key given in a request's headers, if any.
+fn request_key(req: &ServiceRequest) -> Option<&str> {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    bearer
+        .or_else(|| req.headers().get(API_KEY_HEADER).and_then(|value| value.to_str().ok()))
+        .map(str::trim)
+}
+
+/// Check whether a client with a namespace key has access to an endpoint with given path.
+fn is_namespaced_path(path: &str) -> bool {
+    NAMESPACED_PATHS
+        .iter()
+        .any(|prefix| path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+}
+
+/// Get the role needed to make a request with given method to the route matching given pattern.
+///
+/// Getting information only needs the reader role, other than taking a job from a queue, which like reporting a job's
+/// progress and results needs the worker role. Creating jobs needs the submitter role, while anything else, such as
+/// managing queues, or deleting, retrying or restoring jobs, needs the admin role.
+pub(crate) fn required_role(method: &Method, pattern: Option<&str>) -> Role {
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        // requests that don't match a route just get a 404
+        None => return Role::Reader,
+    };
+    match (method.as_str(), pattern) {
+        ("GET", "/queue/{name}/job") => Role::Worker,
+        ("GET", "/queue/{name}/reattempt/{timestamp}") => Role::Submitter,
+        ("GET", _) | ("HEAD", _) => Role::Reader,
+        ("POST", "/queue/{name}/job") | ("PATCH", "/job/{id}/input") => Role::Submitter,
+        ("PATCH", "/job/{id}") | ("PUT", "/job/{id}/heartbeat") | ("PUT", "/job/{id}/output") => Role::Worker,
+        ("PUT", "/job/heartbeat") => Role::Worker,
+        _ => Role::Admin,
+    }
+}
+
+/// Check whether a request with given method to the route matching given pattern needs an admin key, even for clients
+/// whose namespace key has the admin role.
+///
+/// Registering a callback URL makes the server send requests to any URL given, so is restricted to admin keys to stop
+/// namespace keys using it to reach internal services.
+pub(crate) fn is_admin_key_only(method: &Method, pattern: Option<&str>) -> bool {
+    matches!((method.as_str(), pattern), ("PUT", Some("/queue/{name}/callback")))
+}
+
+/// Get the name of the queue a request with given path is for, if any.
+pub(crate) fn path_queue_name(path: &str) -> Option<&str> {
+    path.strip_prefix("/queue/")?.split('/').next().filter(|name| !name.is_empty())
+}
+
+/// Get the ID of the job a request with given path is for, if any.
+pub(crate) fn path_job_id(path: &str) -> Option<u64> {
+    path.strip_prefix("/job/")?.split('/').next()?.parse().ok()
+}
+
+/// Middleware that rejects requests without a valid API key, and sets the `Tenant` each request is made on behalf
+/// of. Requests to `/health` endpoints, and any requests matching an exemption, don't require a key.
+pub struct AuthMiddleware {
+    keys: Arc<ApiKeys>,
+    shards: RedisShards,
+    exempt: Option<Exemption>,
+}
+
+/// Predicate for requests that don't require an API key.
+type Exemption = Rc<dyn Fn(&ServiceRequest) -> bool>;
+
+impl AuthMiddleware {
+    pub fn new(keys: Arc<ApiKeys>, shards: RedisShards) -> Self {
+        Self {
+            keys,
+            shards,
+            exempt: None,
+        }
+    }
+
+    /// Let requests matching given predicate through without an API key.
+    pub fn exempt<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> bool + 'static,
+    {
+        self.exempt = Some(Rc::new(f));
+        self
+    }
+}
+
+impl<S, B> Transform<S> for AuthMiddleware
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AuthService {
+            service: Rc::new(RefCell::new(service)),
+            keys: self.keys.clone(),
+            shards: self.shards.clone(),
+            exempt: self.exempt.clone(),
+        })
+    }
+}
+
+pub struct AuthService<S> {
+    // shared with response futures, since job access is checked before calling the wrapped service
+    service: Rc<RefCell<S>>,
+    keys: Arc<ApiKeys>,
+    shards: RedisShards,
+    exempt: Option<Exemption>,
+}
+
+impl<S, B> Service for AuthService<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if !self.keys.is_enabled()
+            || req.path().starts_with("/health")
+            || self.exempt.as_ref().is_some_and(|f| f(&req))
+        {
+            return Box::pin(self.service.borrow_mut().call(req));
+        }
+
+        let tenant = match request_key(&req).and_then(|key| self.keys.tenant(key)) {
+            Some(tenant) => tenant,
+            None => {
+                let res = HttpResponse::Unauthorized()
+                    .header(header::WWW_AUTHENTICATE, "Bearer")
+                    .body("Missing or invalid API key")
+                    .into_body();
+                return Box::pin(ok(req.into_response(res)));
+            }
+        };
+
+        if tenant.is_admin() {
+            req.extensions_mut().insert(tenant);
+            return Box::pin(self.service.borrow_mut().call(req));
+        }
+
+        if !is_namespaced_path(req.path()) {
+            let res = HttpResponse::Forbidden().body("API key doesn't have access to this endpoint").into_body();
+            return Box::pin(ok(req.into_response(res)));
+        }
+
+        if is_admin_key_only(req.method(), req.match_pattern().as_deref()) {
+            let res = HttpResponse::Forbidden().body("Only admin keys can make this request").into_body();
+            return Box::pin(ok(req.into_response(res)));
+        }
+
+        let role = required_role(req.method(), req.match_pattern().as_deref());
+        if !tenant.has_role(role) {
+            let msg = format!("API key doesn't have the {} role needed for this request", role);
+            return Box::pin(ok(req.into_response(HttpResponse::Forbidden().body(msg).into_body())));
+        }
+
+        if let Some(queue_name) = path_queue_name(req.path()) {
+            if !tenant.in_scope(role, &tenant.qualify(queue_name)) {
+                let msg = format!("API key's {} role doesn't have access to queue \"{}\"", role, queue_name);
+                return Box::pin(ok(req.into_response(HttpResponse::Forbidden().body(msg).into_body())));
+            }
+        }
+
+        let job_id = path_job_id(req.path());
+        req.extensions_mut().insert(tenant.clone());
+        let service = self.service.clone();
+        let shards = self.shards.clone();
+        Box::pin(async move {
+            if let Some(job_id) = job_id {
+                let mut conn = shards.for_job(job_id).get();
+                match RedisManager::job_queue_including_trash(&mut conn, job_id).await {
+                    Ok(Some(queue)) if tenant.in_scope(role, &queue) => (),
+                    Ok(Some(queue)) if tenant.owns(&queue) => {
+                        let msg = format!("API key's {} role doesn't have access to this job's queue", role);
+                        return Ok(req.into_response(HttpResponse::Forbidden().body(msg).into_body()));
+                    }
+                    // jobs in other namespaces are indistinguishable from jobs that don't exist
+                    Ok(_) => return Ok(req.into_response(HttpResponse::NotFound().finish().into_body())),
+                    Err(OcyError::RedisConnection(err)) => {
+                        error!("[job:{}] failed to check job access: {}", job_id, err);
+                        let res = HttpResponse::ServiceUnavailable().body(err).into_body();
+                        return Ok(req.into_response(res));
+                    }
+                    Err(err) => {
+                        error!("[job:{}] failed to check job access: {}", job_id, err);
+                        let res = HttpResponse::InternalServerError().body(err).into_body();
+                        return Ok(req.into_response(res));
+                    }
+                }
+            }
+            let fut = service.borrow_mut().call(req);
+            fut.await
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ApiKeyConfig;
+
+    #[test]
+    fn api_keys() {
+        let mut config = AuthConfig::default();
+        assert!(!ApiKeys::new(&config).is_enabled());
+
+        config.admin_keys.push("admin".to_owned());
+        config.api_keys.insert("team-a-key".to_owned(), ApiKeyConfig::Namespace("team-a".to_owned()));
+        config.api_keys.insert(
+            "team-a-worker".to_owned(),
+            ApiKeyConfig::WithRoles {
+                namespace: "team-a".to_owned(),
+                roles: vec![Role::Worker],
+                scopes: HashMap::new(),
+            },
+        );
+        let mut scopes = HashMap::new();
+        scopes.insert(Role::Submitter, vec!["emails".to_owned()]);
+        config.api_keys.insert(
+            "team-a-mailer".to_owned(),
+            ApiKeyConfig::WithRoles {
+                namespace: "team-a".to_owned(),
+                roles: vec![Role::Submitter],
+                scopes: scopes.clone(),
+            },
+        );
+        let keys = ApiKeys::new(&config);
+        assert!(keys.is_enabled());
+        assert_eq!(keys.tenant("admin"), Some(Tenant::admin()));
+        let tenant = Tenant::with_namespace("team-a");
+        assert_eq!(keys.tenant("team-a-key"), Some(tenant.clone().with_roles(Role::DEFAULT)));
+        assert_eq!(keys.tenant("team-a-worker"), Some(tenant.clone().with_roles(&[Role::Worker])));
+        let mailer = keys.tenant("team-a-mailer").unwrap();
+        assert_eq!(mailer, tenant.with_roles(&[Role::Submitter]).with_scopes(scopes));
+        assert!(mailer.in_scope(Role::Submitter, "team-a.emails"));
+        assert!(!mailer.in_scope(Role::Submitter, "team-a.reports"));
+        assert_eq!(keys.tenant("team-a"), None);
+        assert_eq!(keys.tenant(""), None);
+    }
+
+    #[test]
+    fn paths() {
+        assert!(is_namespaced_path("/queue"));
+        assert!(is_namespaced_path("/queue/emails/job"));
+        assert!(is_namespaced_path("/job"));
+        assert!(is_namespaced_path("/tag/batch-1"));
+        assert!(is_namespaced_path("/quota"));
+        assert!(!is_namespaced_path("/queues"));
+        assert!(!is_namespaced_path("/info"));
+        assert!(is_namespaced_path("/info/features"));
+        assert!(!is_namespaced_path("/admin/log_level"));
+
+        assert_eq!(path_queue_name("/queue/emails"), Some("emails"));
+        assert_eq!(path_queue_name("/queue/emails/job"), Some("emails"));
+        assert_eq!(path_queue_name("/queue"), None);
+        assert_eq!(path_queue_name("/queue/"), None);
+        assert_eq!(path_queue_name("/job/123"), None);
+
+        assert_eq!(path_job_id("/job/123"), Some(123));
+        assert_eq!(path_job_id("/job/123/heartbeat"), Some(123));
+        assert_eq!(path_job_id("/job"), None);
+        assert_eq!(path_job_id("/job/abc"), None);
+        assert_eq!(path_job_id("/queue/123"), None);
+    }
+
+    #[test]
+    fn roles() {
+        assert_eq!(required_role(&Method::GET, Some("/queue/{name}")), Role::Reader);
+        assert_eq!(required_role(&Method::GET, Some("/job")), Role::Reader);
+        assert_eq!(required_role(&Method::GET, Some("/queue/{name}/job")), Role::Worker);
+        assert_eq!(required_role(&Method::POST, Some("/queue/{name}/job")), Role::Submitter);
+        assert_eq!(required_role(&Method::PATCH, Some("/job/{id}")), Role::Worker);
+        assert_eq!(required_role(&Method::PUT, Some("/job/{id}/heartbeat")), Role::Worker);
+        assert_eq!(required_role(&Method::PUT, Some("/job/heartbeat")), Role::Worker);
+        assert_eq!(required_role(&Method::DELETE, Some("/queue/{name}")), Role::Admin);
+        assert_eq!(required_role(&Method::POST, Some("/queue/{name}/purge")), Role::Admin);
+        assert_eq!(required_role(&Method::POST, Some("/job/{id}/undelete")), Role::Admin);
+        assert_eq!(required_role(&Method::PUT, Some("/queue/{name}")), Role::Admin);
+        assert_eq!(required_role(&Method::POST, Some("/tag/{name}/apply")), Role::Admin);
+        assert_eq!(required_role(&Method::POST, Some("/tag/{name}/delete")), Role::Admin);
+        assert_eq!(required_role(&Method::DELETE, None), Role::Reader);
+
+        assert!(is_admin_key_only(&Method::PUT, Some("/queue/{name}/callback")));
+        assert!(!is_admin_key_only(&Method::GET, Some("/queue/{name}/callback")));
+        assert!(!is_admin_key_only(&Method::DELETE, Some("/queue/{name}/callback")));
+        assert!(!is_admin_key_only(&Method::PUT, Some("/queue/{name}")));
+    }
+}
@@ -0,0 +1,313 @@
+//! Circuit breaker used to fail fast while Redis is unavailable.
+//!
+//! After a configured number of consecutive Redis connection failures, the breaker opens and all
+//! requests are rejected immediately with a `503` and `Retry-After` header, rather than each being
+//! left to wait on Redis timeouts. Once the cooldown has elapsed, the breaker becomes half open and
+//! lets a single probe request through at a time: the first success closes it, the first failure
+//! reopens it.
+//!
+//! Successes and failures are recorded by Redis connections as each command completes (see
+//! `RedisPool::with_circuit_breaker`), rather than from response statuses, so requests that never
+//! reach Redis, e.g. those rejected by authentication, don't affect the breaker.
+
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpResponse};
+use futures::future::{ok, Future, Ready};
+use log::{info, warn};
+use serde::Serialize;
+
+/// Current state of a `CircuitBreaker`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Requests are handled normally.
+    Closed,
+
+    /// Requests are rejected without contacting Redis.
+    Open,
+
+    /// Cooldown has elapsed, requests are let through to probe whether Redis has recovered.
+    HalfOpen,
+}
+
+/// Tracks consecutive Redis failures, and determines whether requests should be rejected.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    failures: AtomicU32,
+    opened_at: Mutex<Option<Instant>>,
+    probing: AtomicBool,
+}
+
+/// Probe request let through while a `CircuitBreaker` is half open, which lets another request through once dropped.
+#[derive(Debug)]
+pub struct Probe {
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        self.breaker.probing.store(false, Ordering::SeqCst);
+    }
+}
+
+impl CircuitBreaker {
+    /// Create a new closed breaker, which will open after `threshold` consecutive failures.
+    ///
+    /// A threshold of 0 disables the breaker, so that it never opens.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            failures: AtomicU32::new(0),
+            opened_at: Mutex::new(None),
+            probing: AtomicBool::new(false),
+        }
+    }
+
+    /// Get the current state of this breaker.
+    pub fn state(&self) -> BreakerState {
+        match *self.opened_at.lock().unwrap() {
+            None => BreakerState::Closed,
+            Some(opened_at) if opened_at.elapsed() < self.cooldown => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    /// Get the remaining cooldown time if this breaker is open, or `None` if requests may proceed.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match *self.opened_at.lock().unwrap() {
+            Some(opened_at) => self.cooldown.checked_sub(opened_at.elapsed()),
+            None => None,
+        }
+    }
+
+    /// Get the remaining cooldown time in whole seconds (rounded up), suitable for a `Retry-After` header.
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        self.retry_after()
+            .map(|d| d.as_secs() + u64::from(d.subsec_nanos() > 0))
+    }
+
+    /// Determine whether a request may proceed, returning the number of seconds until it should be retried if not.
+    ///
+    /// While half open, only one request is let through at a time, returning a `Probe` that must be held until
+    /// the request completes.
+    pub fn admit(self: &Arc<Self>) -> Result<Option<Probe>, u64> {
+        match self.state() {
+            BreakerState::Closed => Ok(None),
+            BreakerState::Open => Err(self.retry_after_secs().unwrap_or(1)),
+            BreakerState::HalfOpen => {
+                match self.probing.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst) {
+                    Ok(_) => Ok(Some(Probe { breaker: self.clone() })),
+                    Err(_) => Err(1),
+                }
+            }
+        }
+    }
+
+    /// Record a successful interaction with Redis, closing the breaker if it was open.
+    pub fn record_success(&self) {
+        self.failures.store(0, Ordering::SeqCst);
+        let mut opened_at = self.opened_at.lock().unwrap();
+        if opened_at.take().is_some() {
+            info!("Redis connection recovered, circuit breaker closed");
+        }
+    }
+
+    /// Record a failed interaction with Redis, opening the breaker if the failure threshold is reached.
+    pub fn record_failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut opened_at = self.opened_at.lock().unwrap();
+        let half_open = matches!(*opened_at, Some(dt) if dt.elapsed() >= self.cooldown);
+        if (opened_at.is_none() && failures >= self.threshold) || half_open {
+            warn!(
+                "Redis unavailable after {} consecutive failure(s), circuit breaker open for {}",
+                failures,
+                humantime::format_duration(self.cooldown)
+            );
+            *opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// Middleware that rejects requests while a `CircuitBreaker` is open, or while another request is probing Redis
+/// when it's half open.
+///
+/// Requests to `/health` endpoints, and any requests matching an exemption, are always let through.
+pub struct CircuitBreakerMiddleware {
+    breaker: Arc<CircuitBreaker>,
+    exempt: Option<Exemption>,
+}
+
+/// Predicate determining whether a request bypasses the circuit breaker.
+type Exemption = Rc<dyn Fn(&ServiceRequest) -> bool>;
+
+impl CircuitBreakerMiddleware {
+    pub fn new(breaker: Arc<CircuitBreaker>) -> Self {
+        Self { breaker, exempt: None }
+    }
+
+    /// Let requests matching given predicate bypass the breaker.
+    pub fn exempt<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> bool + 'static,
+    {
+        self.exempt = Some(Rc::new(f));
+        self
+    }
+}
+
+impl<S, B> Transform<S> for CircuitBreakerMiddleware
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CircuitBreakerService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CircuitBreakerService {
+            service,
+            breaker: self.breaker.clone(),
+            exempt: self.exempt.clone(),
+        })
+    }
+}
+
+pub struct CircuitBreakerService<S> {
+    service: S,
+    breaker: Arc<CircuitBreaker>,
+    exempt: Option<Exemption>,
+}
+
+impl<S, B> Service for CircuitBreakerService<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if req.path().starts_with("/health") || self.exempt.as_ref().is_some_and(|f| f(&req)) {
+            return Box::pin(self.service.call(req));
+        }
+
+        let probe = match self.breaker.admit() {
+            Ok(probe) => probe,
+            Err(secs) => {
+                let res = HttpResponse::ServiceUnavailable()
+                    .header("Retry-After", secs.to_string())
+                    .body("Redis unavailable, circuit breaker open")
+                    .into_body();
+                return Box::pin(ok(req.into_response(res)));
+            }
+        };
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await;
+            drop(probe);
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn opens_after_threshold() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.retry_after().is_none());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(breaker.retry_after().unwrap() <= Duration::from_secs(60));
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn success_resets_failure_count() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn half_open_after_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(0));
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(breaker.retry_after().is_none());
+
+        // failure while half open reopens the breaker
+        breaker.record_failure();
+        assert_ne!(breaker.state(), BreakerState::Closed);
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn single_probe_while_half_open() {
+        let breaker = Arc::new(CircuitBreaker::new(1, Duration::from_secs(0)));
+        assert!(matches!(breaker.admit(), Ok(None)));
+
+        breaker.record_failure();
+        let probe = breaker.admit().unwrap();
+        assert!(probe.is_some());
+        assert_eq!(breaker.admit().unwrap_err(), 1);
+
+        // probe finishing without reaching Redis lets another request probe
+        drop(probe);
+        let probe = breaker.admit().unwrap();
+        assert!(probe.is_some());
+        breaker.record_success();
+        drop(probe);
+        assert!(matches!(breaker.admit(), Ok(None)));
+    }
+
+    #[test]
+    fn disabled() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(60));
+        for _ in 0..100 {
+            breaker.record_failure();
+        }
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+}
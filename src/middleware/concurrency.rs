@@ -0,0 +1,401 @@
+//! Middleware limiting the number of requests handled at once.
+//!
+//! Requests beyond a limit are rejected immediately with a `503` and a `Retry-After` header, rather than waiting to be
+//! handled, so that a burst of requests (e.g. workers retrying during an incident) can't build up an unbounded
+//! backlog of requests in memory. Limits apply across all routes, and to classes of routes, e.g. to stop polling for
+//! jobs starving other requests.
+//!
+//! Part of each limit can be reserved for priority routes, e.g. heartbeats and job updates, so that running jobs can
+//! still report they're alive and finish while other requests are being shed, rather than being falsely timed out.
+
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::{Error, HttpResponse};
+use futures::future::{ok, Future, Ready};
+use log::debug;
+use serde::Serialize;
+
+/// Name reported when the limit across all routes is exceeded.
+const GLOBAL_LIMIT: &str = "global";
+
+/// Whether a request is admitted ahead of others when limits are close to being reached.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Lane {
+    /// Request to a priority route, which may use the part of each limit reserved for priority requests.
+    Priority,
+
+    /// Any other request.
+    Normal,
+}
+
+/// Number of requests currently being handled, and the maximum allowed.
+#[derive(Debug)]
+struct Limit {
+    name: String,
+    max_requests: usize,
+    in_flight: AtomicUsize,
+}
+
+impl Limit {
+    fn new(name: &str, max_requests: usize) -> Self {
+        Self {
+            name: name.to_owned(),
+            max_requests,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Count a new request against this limit, returning false if the limit's already been reached. Requests in the
+    /// normal lane are only counted while there's more than `priority_reserve_percent` of the limit left.
+    fn try_acquire(&self, lane: Lane, priority_reserve_percent: u8) -> bool {
+        let max_requests = match lane {
+            Lane::Priority => self.max_requests,
+            Lane::Normal => self.max_requests - reserved(self.max_requests, priority_reserve_percent),
+        };
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |in_flight| {
+                if in_flight < max_requests {
+                    Some(in_flight + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+
+    fn release(&self) {
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Get the number of requests reserved for priority requests out of a limit of `max_requests`, leaving at least one
+/// for other requests.
+fn reserved(max_requests: usize, priority_reserve_percent: u8) -> usize {
+    (max_requests * usize::from(priority_reserve_percent.min(100)) / 100).min(max_requests.saturating_sub(1))
+}
+
+/// Limits on the number of requests handled at once, across all routes, and by class of route.
+#[derive(Debug)]
+pub struct ConcurrencyLimits {
+    global: Option<Limit>,
+    classes: Vec<Limit>,
+    routes: HashMap<String, usize>,
+    priority_routes: HashSet<String>,
+    priority_reserve_percent: u8,
+    retry_after: Duration,
+}
+
+/// Body of the response to requests rejected for exceeding a limit.
+#[derive(Debug, Serialize)]
+struct ShedResponse<'a> {
+    error: &'static str,
+    limit: &'a str,
+    max_requests: usize,
+}
+
+impl ConcurrencyLimits {
+    /// Create limits allowing up to `max_requests` requests at once across all routes (if given), telling rejected
+    /// clients to retry after `retry_after`.
+    pub fn new(max_requests: Option<usize>, retry_after: Duration) -> Self {
+        Self {
+            global: max_requests.map(|max_requests| Limit::new(GLOBAL_LIMIT, max_requests)),
+            classes: Vec::new(),
+            routes: HashMap::new(),
+            priority_routes: HashSet::new(),
+            priority_reserve_percent: 0,
+            retry_after,
+        }
+    }
+
+    /// Reserve `reserve_percent` of every limit for requests to given priority routes, so they're admitted ahead of
+    /// other requests. Routes are keyed as for `class`.
+    pub fn priority<I, R>(mut self, routes: I, reserve_percent: u8) -> Self
+    where
+        I: IntoIterator<Item = R>,
+        R: Into<String>,
+    {
+        self.priority_routes.extend(routes.into_iter().map(Into::into));
+        self.priority_reserve_percent = reserve_percent;
+        self
+    }
+
+    /// Add a class of routes sharing a limit of `max_requests` at once. Routes are keyed by their pattern, optionally
+    /// prefixed by HTTP method, e.g. `GET /queue/{name}/job`.
+    ///
+    /// If a route is in more than one class, the class added last is used.
+    pub fn class<I, R>(mut self, name: &str, routes: I, max_requests: usize) -> Self
+    where
+        I: IntoIterator<Item = R>,
+        R: Into<String>,
+    {
+        let class = self.classes.len();
+        self.classes.push(Limit::new(name, max_requests));
+        for route in routes {
+            self.routes.insert(route.into(), class);
+        }
+        self
+    }
+
+    /// Get the index of the class a request with given method and route pattern belongs to, if any.
+    fn route_class(&self, method: &Method, pattern: Option<&str>) -> Option<usize> {
+        let pattern = pattern?;
+        self.routes
+            .get(&format!("{} {}", method, pattern))
+            .or_else(|| self.routes.get(pattern))
+            .copied()
+    }
+
+    /// Get the lane a request with given method and route pattern is admitted in.
+    fn route_lane(&self, method: &Method, pattern: Option<&str>) -> Lane {
+        match pattern {
+            Some(pattern)
+                if self.priority_routes.contains(&format!("{} {}", method, pattern))
+                    || self.priority_routes.contains(pattern) =>
+            {
+                Lane::Priority
+            }
+            _ => Lane::Normal,
+        }
+    }
+
+    /// Count a new request against all limits that apply to it, returning a permit which releases the request from
+    /// those limits when dropped, or the limit that's already been reached.
+    fn try_acquire(self: &Arc<Self>, method: &Method, pattern: Option<&str>) -> Result<Permit, &Limit> {
+        let class = self.route_class(method, pattern);
+        let lane = self.route_lane(method, pattern);
+        if let Some(global) = &self.global {
+            if !global.try_acquire(lane, self.priority_reserve_percent) {
+                return Err(global);
+            }
+        }
+        // releases the global limit if the class limit has been reached
+        let mut permit = Permit {
+            limits: self.clone(),
+            class: None,
+        };
+        match class {
+            Some(class) if !self.classes[class].try_acquire(lane, self.priority_reserve_percent) => {
+                Err(&self.classes[class])
+            }
+            _ => {
+                permit.class = class;
+                Ok(permit)
+            }
+        }
+    }
+
+    fn shed_response(&self, limit: &Limit) -> HttpResponse {
+        let retry_after = (self.retry_after.as_millis() as f64 / 1000.0).ceil().max(1.0) as u64;
+        HttpResponse::ServiceUnavailable()
+            .header("Retry-After", retry_after.to_string())
+            .json(ShedResponse {
+                error: "too many requests in flight",
+                limit: &limit.name,
+                max_requests: limit.max_requests,
+            })
+    }
+}
+
+/// Request counted against limits, releasing it from them when dropped.
+struct Permit {
+    limits: Arc<ConcurrencyLimits>,
+    class: Option<usize>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(global) = &self.limits.global {
+            global.release();
+        }
+        if let Some(class) = self.class {
+            self.limits.classes[class].release();
+        }
+    }
+}
+
+/// Middleware that rejects requests exceeding `ConcurrencyLimits` with a `503`.
+///
+/// Requests matching an exemption, e.g. health checks, are always let through, and aren't counted against limits.
+pub struct ConcurrencyLimitMiddleware {
+    limits: Arc<ConcurrencyLimits>,
+    exempt: Option<Exemption>,
+}
+
+/// Predicate determining whether a request bypasses concurrency limits.
+type Exemption = Rc<dyn Fn(&ServiceRequest) -> bool>;
+
+impl ConcurrencyLimitMiddleware {
+    pub fn new(limits: Arc<ConcurrencyLimits>) -> Self {
+        Self { limits, exempt: None }
+    }
+
+    /// Let requests matching given predicate bypass all limits.
+    pub fn exempt<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> bool + 'static,
+    {
+        self.exempt = Some(Rc::new(f));
+        self
+    }
+}
+
+impl<S, B> Transform<S> for ConcurrencyLimitMiddleware
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ConcurrencyLimitService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ConcurrencyLimitService {
+            service,
+            limits: self.limits.clone(),
+            exempt: self.exempt.clone(),
+        })
+    }
+}
+
+pub struct ConcurrencyLimitService<S> {
+    service: S,
+    limits: Arc<ConcurrencyLimits>,
+    exempt: Option<Exemption>,
+}
+
+impl<S, B> Service for ConcurrencyLimitService<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if self.exempt.as_ref().is_some_and(|f| f(&req)) {
+            return Box::pin(self.service.call(req));
+        }
+
+        let permit = match self.limits.try_acquire(req.method(), req.match_pattern().as_deref()) {
+            Ok(permit) => permit,
+            Err(limit) => {
+                // logged at debug level, since logging every rejected request would add to the load being shed
+                debug!("\"{} {}\" 503 exceeded {} concurrency limit", req.method(), req.path(), limit.name);
+                let res = self.limits.shed_response(limit).into_body();
+                return Box::pin(ok(req.into_response(res)));
+            }
+        };
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await;
+            drop(permit);
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn in_flight(limit: &Limit) -> usize {
+        limit.in_flight.load(Ordering::Acquire)
+    }
+
+    #[test]
+    fn limits() {
+        let limits = Arc::new(ConcurrencyLimits::new(Some(3), Duration::from_secs(1)).class(
+            "polling",
+            vec!["GET /queue/{name}/job", "/job/{id}/heartbeat"],
+            1,
+        ));
+        let global = limits.global.as_ref().unwrap();
+
+        let poll = limits.try_acquire(&Method::GET, Some("/queue/{name}/job")).unwrap();
+        assert_eq!(poll.class, Some(0));
+        assert_eq!(in_flight(global), 1);
+
+        // polling class is full, and rejected requests shouldn't be counted against the global limit
+        let err = limits.try_acquire(&Method::PUT, Some("/job/{id}/heartbeat")).err().unwrap();
+        assert_eq!(err.name, "polling");
+        assert_eq!(in_flight(global), 1);
+
+        let create = limits.try_acquire(&Method::POST, Some("/queue/{name}/job")).unwrap();
+        assert_eq!(create.class, None);
+        let other = limits.try_acquire(&Method::GET, None).unwrap();
+        assert_eq!(limits.try_acquire(&Method::GET, Some("/info")).err().unwrap().name, GLOBAL_LIMIT);
+        assert_eq!(in_flight(global), 3);
+
+        drop(poll);
+        drop(create);
+        drop(other);
+        assert_eq!(in_flight(global), 0);
+        assert_eq!(in_flight(&limits.classes[0]), 0);
+        assert!(limits.try_acquire(&Method::PUT, Some("/job/{id}/heartbeat")).is_ok());
+    }
+
+    #[test]
+    fn priority_lanes() {
+        let limits = Arc::new(
+            ConcurrencyLimits::new(Some(10), Duration::from_secs(1))
+                .class("polling", vec!["GET /queue/{name}/job", "/job/{id}/heartbeat"], 4)
+                .priority(vec!["PUT /job/{id}/heartbeat", "PATCH /job/{id}"], 20),
+        );
+        assert_eq!(limits.route_lane(&Method::PATCH, Some("/job/{id}")), Lane::Priority);
+        assert_eq!(limits.route_lane(&Method::GET, Some("/job/{id}")), Lane::Normal);
+        assert_eq!(limits.route_lane(&Method::GET, None), Lane::Normal);
+
+        // 2 of the 10 global requests are reserved for priority requests
+        let mut permits: Vec<Permit> =
+            (0..8).map(|_| limits.try_acquire(&Method::POST, Some("/queue/{name}/job")).ok().unwrap()).collect();
+        assert_eq!(limits.try_acquire(&Method::POST, Some("/queue/{name}/job")).err().unwrap().name, GLOBAL_LIMIT);
+        permits.push(limits.try_acquire(&Method::PATCH, Some("/job/{id}")).ok().unwrap());
+        permits.push(limits.try_acquire(&Method::PUT, Some("/job/{id}/heartbeat")).ok().unwrap());
+        assert!(limits.try_acquire(&Method::PATCH, Some("/job/{id}")).is_err());
+        permits.clear();
+
+        // reserves apply to classes too, 4 * 20% rounds down to no reserve
+        let polls: Vec<Permit> =
+            (0..4).map(|_| limits.try_acquire(&Method::GET, Some("/queue/{name}/job")).ok().unwrap()).collect();
+        assert_eq!(limits.try_acquire(&Method::PUT, Some("/job/{id}/heartbeat")).err().unwrap().name, "polling");
+        drop(polls);
+    }
+
+    #[test]
+    fn reserves() {
+        assert_eq!(reserved(100, 10), 10);
+        assert_eq!(reserved(5, 10), 0);
+        assert_eq!(reserved(5, 100), 4);
+        assert_eq!(reserved(1, 50), 0);
+        assert_eq!(reserved(0, 50), 0);
+    }
+
+    #[test]
+    fn no_limits() {
+        let limits = Arc::new(ConcurrencyLimits::new(None, Duration::from_secs(1)));
+        let permits: Vec<Permit> = (0..100)
+            .map(|_| limits.try_acquire(&Method::GET, Some("/queue/{name}/job")).ok().unwrap())
+            .collect();
+        assert_eq!(permits.len(), 100);
+    }
+}
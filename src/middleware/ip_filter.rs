@@ -0,0 +1,198 @@
+//! Middleware rejecting requests from client IP addresses that aren't allowed to connect.
+//!
+//! The client's address is the address of the connection's peer, unless the peer is a trusted proxy, in which case
+//! it's the last address in the `X-Forwarded-For` header that isn't also a trusted proxy. This prevents clients from
+//! spoofing their address by adding their own `X-Forwarded-For` header.
+
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpResponse};
+use futures::future::{ok, Future, Ready};
+use log::debug;
+
+use crate::config::AllowedIpsConfig;
+use crate::models::Cidr;
+
+/// Header proxies give the addresses of the clients they forward requests for in.
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// Lists of IP address ranges clients are allowed or denied from.
+#[derive(Debug, Default)]
+pub struct IpFilter {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+    trusted_proxies: Vec<Cidr>,
+}
+
+impl IpFilter {
+    pub fn new(config: &AllowedIpsConfig) -> Self {
+        Self {
+            allow: config.allow.clone(),
+            deny: config.deny.clone(),
+            trusted_proxies: config.trusted_proxies.clone(),
+        }
+    }
+
+    /// Check whether any addresses are filtered.
+    pub fn is_enabled(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    /// Check whether a client with given address is allowed. Denied ranges take precedence over allowed ones, and
+    /// all addresses that aren't denied are allowed if no allowed ranges are given.
+    pub fn is_allowed(&self, addr: &IpAddr) -> bool {
+        !self.deny.iter().any(|cidr| cidr.contains(addr))
+            && (self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(addr)))
+    }
+
+    /// Get the address of the client making a request, given the connection's peer address, and the request's
+    /// `X-Forwarded-For` header.
+    pub fn client_addr(&self, peer: IpAddr, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let mut addr = peer;
+        let mut forwarded = forwarded_for.unwrap_or_default().rsplit(',').map(str::trim).filter(|addr| !addr.is_empty());
+        while self.is_trusted_proxy(&addr) {
+            match forwarded.next() {
+                // an invalid forwarded address means the client's address can't be determined
+                Some(forwarded) => addr = forwarded.parse().ok()?,
+                None => break,
+            }
+        }
+        Some(addr)
+    }
+
+    fn is_trusted_proxy(&self, addr: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|cidr| cidr.contains(addr))
+    }
+}
+
+/// Middleware that rejects requests from clients that aren't allowed by its `IpFilter` with a 403.
+pub struct IpFilterMiddleware {
+    filter: Arc<IpFilter>,
+}
+
+impl IpFilterMiddleware {
+    pub fn new(filter: Arc<IpFilter>) -> Self {
+        Self { filter }
+    }
+}
+
+impl<S, B> Transform<S> for IpFilterMiddleware
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = IpFilterService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(IpFilterService {
+            service,
+            filter: self.filter.clone(),
+        })
+    }
+}
+
+pub struct IpFilterService<S> {
+    service: S,
+    filter: Arc<IpFilter>,
+}
+
+impl<S, B> Service for IpFilterService<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if !self.filter.is_enabled() {
+            return Box::pin(self.service.call(req));
+        }
+
+        let forwarded_for = req.headers().get(FORWARDED_FOR_HEADER).and_then(|value| value.to_str().ok());
+        let client_addr = req.peer_addr().and_then(|peer| self.filter.client_addr(peer.ip(), forwarded_for));
+        match client_addr {
+            Some(addr) if self.filter.is_allowed(&addr) => Box::pin(self.service.call(req)),
+            addr => {
+                debug!("Rejecting request from disallowed address {:?}", addr);
+                Box::pin(ok(req.into_response(HttpResponse::Forbidden().finish().into_body())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn cidrs(cidrs: &[&str]) -> Vec<Cidr> {
+        cidrs.iter().map(|cidr| cidr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let filter = IpFilter::new(&AllowedIpsConfig::default());
+        assert!(!filter.is_enabled());
+        assert!(filter.is_allowed(&ip("8.8.8.8")));
+
+        let filter = IpFilter::new(&AllowedIpsConfig {
+            allow: cidrs(&["10.0.0.0/8", "::1"]),
+            deny: cidrs(&["10.9.0.0/16"]),
+            trusted_proxies: Vec::new(),
+        });
+        assert!(filter.is_enabled());
+        assert!(filter.is_allowed(&ip("10.1.2.3")));
+        assert!(filter.is_allowed(&ip("::1")));
+        assert!(!filter.is_allowed(&ip("10.9.2.3")));
+        assert!(!filter.is_allowed(&ip("192.168.0.1")));
+
+        let filter = IpFilter::new(&AllowedIpsConfig {
+            allow: Vec::new(),
+            deny: cidrs(&["192.168.0.0/16"]),
+            trusted_proxies: Vec::new(),
+        });
+        assert!(filter.is_allowed(&ip("10.1.2.3")));
+        assert!(!filter.is_allowed(&ip("192.168.0.1")));
+    }
+
+    #[test]
+    fn client_addr() {
+        let filter = IpFilter::new(&AllowedIpsConfig {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            trusted_proxies: cidrs(&["10.0.0.0/24"]),
+        });
+
+        // forwarded addresses are ignored unless the peer is a trusted proxy
+        assert_eq!(filter.client_addr(ip("192.168.0.1"), Some("10.1.1.1")), Some(ip("192.168.0.1")));
+        assert_eq!(filter.client_addr(ip("10.0.0.1"), None), Some(ip("10.0.0.1")));
+        assert_eq!(filter.client_addr(ip("10.0.0.1"), Some("192.168.0.1")), Some(ip("192.168.0.1")));
+
+        // addresses added by clients before reaching trusted proxies are ignored
+        let forwarded = Some("1.2.3.4, 192.168.0.1, 10.0.0.2");
+        assert_eq!(filter.client_addr(ip("10.0.0.1"), forwarded), Some(ip("192.168.0.1")));
+        assert_eq!(filter.client_addr(ip("10.0.0.1"), Some("10.0.0.2")), Some(ip("10.0.0.2")));
+        assert_eq!(filter.client_addr(ip("10.0.0.1"), Some("not-an-ip")), None);
+    }
+}
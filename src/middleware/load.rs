@@ -0,0 +1,68 @@
+//! Middleware recording the status of each response, used to detect overload when relaxing heartbeat timeouts.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+use futures::future::{ok, Future, Ready};
+
+use crate::application::tolerance::HEARTBEAT_TOLERANCE;
+
+/// Middleware that records each response's status in `HEARTBEAT_TOLERANCE`, if relaxing heartbeat timeouts is enabled.
+pub struct LoadMiddleware;
+
+impl<S, B> Transform<S> for LoadMiddleware
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = LoadService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(LoadService { service })
+    }
+}
+
+pub struct LoadService<S> {
+    service: S,
+}
+
+impl<S, B> Service for LoadService<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if !HEARTBEAT_TOLERANCE.is_enabled() {
+            return Box::pin(self.service.call(req));
+        }
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await;
+            let status = match &res {
+                Ok(res) => res.status(),
+                Err(err) => err.as_response_error().status_code(),
+            };
+            HEARTBEAT_TOLERANCE.record_response(status.as_u16());
+            res
+        })
+    }
+}
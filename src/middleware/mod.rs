@@ -0,0 +1,11 @@
+//! HTTP middleware wrapped around all handlers. Registration is configured in `ocypod-server.rs`.
+
+pub mod access_log;
+pub mod auth;
+pub mod circuit_breaker;
+pub mod concurrency;
+pub mod ip_filter;
+pub mod load;
+pub mod slowlog;
+pub mod system;
+pub mod timeout;
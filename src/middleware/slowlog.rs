@@ -0,0 +1,83 @@
+//! Middleware recording requests that take longer than a threshold to handle in the `SlowLog`.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+use futures::future::{ok, Future, Ready};
+
+use crate::application::slowlog::SlowLog;
+
+/// Middleware that records slow requests, along with the Redis commands they sent, if the slow log is enabled.
+pub struct SlowLogMiddleware {
+    slow_log: Arc<SlowLog>,
+}
+
+impl SlowLogMiddleware {
+    pub fn new(slow_log: Arc<SlowLog>) -> Self {
+        Self { slow_log }
+    }
+}
+
+impl<S, B> Transform<S> for SlowLogMiddleware
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = SlowLogService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(SlowLogService {
+            service,
+            slow_log: self.slow_log.clone(),
+        })
+    }
+}
+
+pub struct SlowLogService<S> {
+    service: S,
+    slow_log: Arc<SlowLog>,
+}
+
+impl<S, B> Service for SlowLogService<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if !self.slow_log.is_enabled() {
+            return Box::pin(self.service.call(req));
+        }
+
+        let method = req.method().to_string();
+        let path = req.uri().path_and_query().map_or_else(|| req.path().to_owned(), |pq| pq.to_string());
+        let slow_log = self.slow_log.clone();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            slow_log
+                .track(method, path, fut, |res| match res {
+                    Ok(res) => res.status().as_u16(),
+                    Err(err) => err.as_response_error().status_code().as_u16(),
+                })
+                .await
+        })
+    }
+}
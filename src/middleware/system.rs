@@ -0,0 +1,120 @@
+//! Middleware making system queues, and the jobs on them, read-only to clients, so that their jobs are only changed
+//! by the server's own executor and job monitors.
+//!
+//! Requests needing more than the reader role (see `auth::required_role`) are rejected with a 403 if the queue in
+//! their path is a system queue, or if the job in their path is on one, other than heartbeats, which don't change a
+//! job and are sent too often to look up every job's queue.
+
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpResponse};
+use futures::future::{ok, Future, Ready};
+use log::error;
+
+use crate::application::shard::RedisShards;
+use crate::application::{system, RedisManager};
+use crate::middleware::auth::{path_job_id, path_queue_name, required_role};
+use crate::models::{OcyError, Role};
+
+/// Route of heartbeats for a single job, which aren't checked.
+const HEARTBEAT_ROUTE: &str = "/job/{id}/heartbeat";
+
+/// Middleware rejecting requests that would change system queues, or jobs on them.
+pub struct SystemQueueMiddleware {
+    shards: RedisShards,
+}
+
+impl SystemQueueMiddleware {
+    pub fn new(shards: RedisShards) -> Self {
+        Self { shards }
+    }
+}
+
+impl<S, B> Transform<S> for SystemQueueMiddleware
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = SystemQueueService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(SystemQueueService {
+            service: Rc::new(RefCell::new(service)),
+            shards: self.shards.clone(),
+        })
+    }
+}
+
+pub struct SystemQueueService<S> {
+    // shared with response futures, since a job's queue is checked before calling the wrapped service
+    service: Rc<RefCell<S>>,
+    shards: RedisShards,
+}
+
+impl<S, B> Service for SystemQueueService<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let pattern = req.match_pattern();
+        if required_role(req.method(), pattern.as_deref()) == Role::Reader {
+            return Box::pin(self.service.borrow_mut().call(req));
+        }
+
+        if path_queue_name(req.path()).is_some_and(system::is_system_queue) {
+            let res = HttpResponse::Forbidden().body("System queues are read-only").into_body();
+            return Box::pin(ok(req.into_response(res)));
+        }
+
+        let job_id = match path_job_id(req.path()) {
+            Some(job_id) if pattern.as_deref() != Some(HEARTBEAT_ROUTE) => job_id,
+            _ => return Box::pin(self.service.borrow_mut().call(req)),
+        };
+        let service = self.service.clone();
+        let shards = self.shards.clone();
+        Box::pin(async move {
+            let mut conn = shards.for_job(job_id).get();
+            match RedisManager::job_queue_including_trash(&mut conn, job_id).await {
+                Ok(Some(queue)) if system::is_system_queue(&queue) => {
+                    let res = HttpResponse::Forbidden().body("Jobs on system queues are read-only").into_body();
+                    return Ok(req.into_response(res));
+                }
+                // handlers respond to jobs that don't exist
+                Ok(_) => (),
+                Err(OcyError::RedisConnection(err)) => {
+                    error!("[job:{}] failed to check job's queue: {}", job_id, err);
+                    let res = HttpResponse::ServiceUnavailable().body(err).into_body();
+                    return Ok(req.into_response(res));
+                }
+                Err(err) => {
+                    error!("[job:{}] failed to check job's queue: {}", job_id, err);
+                    let res = HttpResponse::InternalServerError().body(err).into_body();
+                    return Ok(req.into_response(res));
+                }
+            }
+            let fut = service.borrow_mut().call(req);
+            fut.await
+        })
+    }
+}
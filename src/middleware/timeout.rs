@@ -0,0 +1,176 @@
+//! Middleware limiting how long requests can take to be handled.
+//!
+//! Each request is given the timeout configured for its route, or the default timeout if its route has none. If a
+//! request isn't handled in time, handling is abandoned and a `504` is returned with a JSON error. Any Redis commands
+//! already sent may still complete, so e.g. a job may still be created after its request has timed out.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{Error, HttpResponse, ResponseError};
+use futures::future::{ok, Future, Ready};
+use log::warn;
+use serde::Serialize;
+
+/// Timeouts for handling requests, by route.
+#[derive(Clone, Debug, Default)]
+pub struct RequestTimeouts {
+    default: Option<Duration>,
+    routes: HashMap<String, Duration>,
+}
+
+/// Error returned when a request times out, which is rendered as a `504` with a JSON body.
+#[derive(Debug, Serialize)]
+struct TimeoutError {
+    error: &'static str,
+    timeout_ms: u64,
+    elapsed_ms: u64,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "request timed out after {}ms", self.elapsed_ms)
+    }
+}
+
+impl ResponseError for TimeoutError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::GATEWAY_TIMEOUT
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::GatewayTimeout().json(self)
+    }
+}
+
+impl RequestTimeouts {
+    /// Create timeouts using given default for all routes, other than those with their own timeout. Routes are keyed
+    /// by their pattern, optionally prefixed by HTTP method, e.g. `GET /queue/{name}/job`.
+    ///
+    /// A timeout of 0 means no timeout, e.g. to exempt a route from the default.
+    pub fn new(default: Option<Duration>, routes: HashMap<String, Duration>) -> Self {
+        Self { default, routes }
+    }
+
+    /// Get the timeout for a request with given method and route pattern, if any.
+    pub fn timeout(&self, method: &Method, pattern: Option<&str>) -> Option<Duration> {
+        let route_timeout = pattern.and_then(|pattern| {
+            self.routes
+                .get(&format!("{} {}", method, pattern))
+                .or_else(|| self.routes.get(pattern))
+        });
+        route_timeout
+            .or(self.default.as_ref())
+            .copied()
+            .filter(|timeout| timeout.as_nanos() > 0)
+    }
+}
+
+/// Middleware that responds with a `504` to requests that aren't handled within their route's timeout.
+pub struct RequestTimeoutMiddleware {
+    timeouts: Arc<RequestTimeouts>,
+}
+
+impl RequestTimeoutMiddleware {
+    pub fn new(timeouts: Arc<RequestTimeouts>) -> Self {
+        Self { timeouts }
+    }
+}
+
+impl<S, B> Transform<S> for RequestTimeoutMiddleware
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestTimeoutService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestTimeoutService {
+            service,
+            timeouts: self.timeouts.clone(),
+        })
+    }
+}
+
+pub struct RequestTimeoutService<S> {
+    service: S,
+    timeouts: Arc<RequestTimeouts>,
+}
+
+impl<S, B> Service for RequestTimeoutService<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let timeout = match self.timeouts.timeout(req.method(), req.match_pattern().as_deref()) {
+            Some(timeout) => timeout,
+            None => return Box::pin(self.service.call(req)),
+        };
+
+        // the request itself can't be kept to build a response from, since handlers require sole ownership of it
+        let request_line = format!("{} {}", req.method(), req.path());
+        let started = Instant::now();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            match actix_rt::time::timeout(timeout, fut).await {
+                Ok(res) => res,
+                Err(_) => {
+                    let elapsed = started.elapsed();
+                    warn!("\"{}\" 504 timed out after {}", request_line, humantime::format_duration(elapsed));
+                    Err(TimeoutError {
+                        error: "request timed out",
+                        timeout_ms: timeout.as_millis() as u64,
+                        elapsed_ms: elapsed.as_millis() as u64,
+                    }
+                    .into())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn route_timeouts() {
+        let mut routes = HashMap::new();
+        routes.insert("GET /queue/{name}/job".to_owned(), Duration::from_secs(2));
+        routes.insert("/job/{id}".to_owned(), Duration::from_secs(5));
+        routes.insert("/info".to_owned(), Duration::from_secs(0));
+        let timeouts = RequestTimeouts::new(Some(Duration::from_secs(10)), routes);
+
+        assert_eq!(timeouts.timeout(&Method::GET, Some("/queue/{name}/job")), Some(Duration::from_secs(2)));
+        assert_eq!(timeouts.timeout(&Method::POST, Some("/queue/{name}/job")), Some(Duration::from_secs(10)));
+        assert_eq!(timeouts.timeout(&Method::PATCH, Some("/job/{id}")), Some(Duration::from_secs(5)));
+        assert_eq!(timeouts.timeout(&Method::GET, Some("/info")), None);
+        assert_eq!(timeouts.timeout(&Method::GET, None), Some(Duration::from_secs(10)));
+
+        let timeouts = RequestTimeouts::default();
+        assert_eq!(timeouts.timeout(&Method::GET, Some("/queue/{name}/job")), None);
+    }
+}
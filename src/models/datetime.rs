@@ -3,10 +3,12 @@
 use std::fmt;
 
 use redis::{self, FromRedisValue, RedisResult, RedisWrite, ToRedisArgs};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use crate::models::Duration;
 
 /// Thin wrapper around a `chrono::DateTime<Utc>` with functions for custom (de)serialisation.
-#[derive(Clone, Debug, PartialEq, PartialOrd, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, PartialOrd, Serialize)]
 pub struct DateTime(chrono::DateTime<chrono::Utc>);
 
 impl DateTime {
@@ -19,6 +21,21 @@ impl DateTime {
     pub fn seconds_since(&self, other: &DateTime) -> i64 {
         self.0.signed_duration_since(other.0).num_seconds()
     }
+
+    /// Get number of milliseconds since another given date/time.
+    pub fn millis_since(&self, other: &DateTime) -> i64 {
+        self.0.signed_duration_since(other.0).num_milliseconds()
+    }
+
+    /// Get the date/time given duration after this one.
+    pub fn plus(&self, duration: &Duration) -> Self {
+        DateTime(self.0 + chrono::Duration::seconds(duration.as_secs() as i64))
+    }
+
+    /// Get number of seconds since the Unix epoch.
+    pub fn timestamp(&self) -> i64 {
+        self.0.timestamp()
+    }
 }
 
 impl FromRedisValue for DateTime {
@@ -34,9 +51,15 @@ impl FromRedisValue for DateTime {
 }
 
 impl ToRedisArgs for DateTime {
-    /// Format this struct as an RFC3339 date string for storage in Redis.
+    /// Format this struct as an RFC3339 date string with millisecond precision for storage in Redis.
+    fn write_redis_args<W: ?Sized + RedisWrite>(&self, out: &mut W) {
+        self.0.to_rfc3339_opts(chrono::SecondsFormat::Millis, false).write_redis_args(out)
+    }
+}
+
+impl ToRedisArgs for &DateTime {
     fn write_redis_args<W: ?Sized + RedisWrite>(&self, out: &mut W) {
-        self.0.to_rfc3339().write_redis_args(out)
+        (*self).write_redis_args(out)
     }
 }
 
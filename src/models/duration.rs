@@ -46,7 +46,7 @@ impl ToRedisArgs for Duration {
     }
 }
 
-impl<'a> ToRedisArgs for &'a Duration {
+impl ToRedisArgs for &Duration {
     fn write_redis_args<W>(&self, out: &mut W)
     where
         W: ?Sized + RedisWrite,
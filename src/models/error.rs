@@ -28,6 +28,12 @@ pub enum OcyError {
     /// Request was not valid due to current state of some resource(s).
     Conflict(String),
 
+    /// Client doesn't have permission to perform the request.
+    Forbidden(String),
+
+    /// Request would exceed a namespace's quota, may succeed once existing jobs have been processed.
+    QuotaExceeded(String),
+
     /// Internal application error, e.g. actor mailbox full.
     Internal(String),
 
@@ -45,11 +51,30 @@ impl OcyError {
     pub fn bad_request<S: Into<String>>(msg: S) -> Self {
         OcyError::BadRequest(msg.into())
     }
+
+    /// Construct a new OcyError::Forbidden with given message.
+    pub fn forbidden<S: Into<String>>(msg: S) -> Self {
+        OcyError::Forbidden(msg.into())
+    }
+
+    /// Construct a new OcyError::QuotaExceeded with given message.
+    pub fn quota_exceeded<S: Into<String>>(msg: S) -> Self {
+        OcyError::QuotaExceeded(msg.into())
+    }
 }
 
 impl From<RedisError> for OcyError {
     fn from(err: RedisError) -> Self {
-        OcyError::Redis(err)
+        // connection level errors are reported separately, so they can be surfaced as 503s
+        if err.is_connection_dropped()
+            || err.is_connection_refusal()
+            || err.is_timeout()
+            || err.is_io_error()
+        {
+            OcyError::RedisConnection(err.to_string())
+        } else {
+            OcyError::Redis(err)
+        }
     }
 }
 
@@ -73,9 +98,11 @@ impl fmt::Display for OcyError {
             OcyError::NoSuchQueue(queue) => write!(f, "Queue '{}' does not exist", queue),
             OcyError::NoSuchJob(job_id) => write!(f, "Job with ID {} does not exist", job_id),
             OcyError::ParseError(msg) => write!(f, "Parse error: {}", msg),
-            OcyError::BadRequest(msg) | OcyError::Conflict(msg) | OcyError::Internal(msg) => {
-                write!(f, "{}", msg)
-            }
+            OcyError::BadRequest(msg)
+            | OcyError::Conflict(msg)
+            | OcyError::Forbidden(msg)
+            | OcyError::QuotaExceeded(msg)
+            | OcyError::Internal(msg) => write!(f, "{}", msg),
         }
     }
 }
@@ -0,0 +1,154 @@
+//! Describes the optional features and limits of this server, so that clients can adapt to them.
+
+use serde::Serialize;
+
+use crate::application::MAX_SAMPLE_SIZE;
+use crate::config::Config;
+use crate::models::job::{MAX_HEARTBEAT_BATCH, MAX_SEARCH_LIMIT};
+
+/// Version of this server, along with the optional subsystems enabled on it, and limits on requests to it.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Features {
+    pub version: &'static str,
+    pub backend: Backend,
+    pub subsystems: Subsystems,
+    pub limits: Limits,
+}
+
+/// Storage used by this server.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Backend {
+    /// Type of storage, currently always "redis".
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+
+    /// Number of Redis instances queues are sharded across.
+    pub shards: usize,
+
+    /// Whether read-only requests may be served by Redis replicas.
+    pub replicas: bool,
+}
+
+/// Optional subsystems, and whether they're enabled.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Subsystems {
+    /// Whether clients must authenticate with an API key.
+    pub auth: bool,
+
+    /// Whether job inputs and outputs are encrypted at rest.
+    pub encryption: bool,
+
+    /// Whether jobs created while Redis is unavailable are persisted to disk for replay.
+    pub degraded_mode: bool,
+
+    /// Whether metrics are exported to a Prometheus pushgateway or StatsD.
+    pub metrics_export: bool,
+
+    /// Whether notifications are sent to a webhook.
+    pub webhooks: bool,
+
+    /// Whether queues' failure rates are checked for anomalies.
+    pub anomalies: bool,
+
+    /// Whether requests to jobs' callback URLs are signed.
+    pub signed_callbacks: bool,
+
+    /// Whether polls of queues polled too often while empty may be rejected.
+    pub poll_throttle: bool,
+
+    /// External systems job lifecycle events are published to.
+    pub event_sinks: Vec<&'static str>,
+
+    /// Whether the `/testing` endpoints for injecting failures are available.
+    pub testing: bool,
+}
+
+/// Limits on requests to this server.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Limits {
+    /// Maximum size of JSON request bodies in bytes.
+    pub max_body_size: usize,
+
+    /// Maximum number of jobs in a single `PUT /job/heartbeat` request.
+    pub max_heartbeat_batch: usize,
+
+    /// Maximum number of jobs returned by a single `GET /job` search.
+    pub max_search_limit: usize,
+
+    /// Maximum number of jobs returned by a single `GET /queue/{name}/sample` request.
+    pub max_sample_size: usize,
+}
+
+impl Features {
+    /// Get the features of a server with given configuration, sharded across given number of Redis instances.
+    pub fn new(config: &Config, shards: usize) -> Self {
+        let encryption = &config.encryption;
+        let metrics = &config.metrics;
+        let events = &config.events;
+        let event_sinks = [
+            ("kafka", events.kafka.is_some()),
+            ("nats", events.nats.is_some()),
+            ("amqp", events.amqp.is_some()),
+        ];
+        Self {
+            version: crate::handlers::info::VERSION,
+            backend: Backend {
+                kind: "redis",
+                shards,
+                replicas: !config.redis.replica_urls.is_empty(),
+            },
+            subsystems: Subsystems {
+                auth: config.auth.is_enabled(),
+                encryption: encryption.key.is_some() || encryption.key_env.is_some() || encryption.key_file.is_some(),
+                degraded_mode: config.persistence.degraded_mode,
+                metrics_export: metrics.pushgateway_url.is_some() || metrics.statsd_addr.is_some(),
+                webhooks: config.notifications.webhook_url.is_some(),
+                anomalies: config.anomalies.enabled,
+                signed_callbacks: config.callbacks.secret.is_some(),
+                poll_throttle: config.server.poll_throttle.max_empty_poll_rate.is_some(),
+                event_sinks: event_sinks.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect(),
+                testing: cfg!(feature = "testing"),
+            },
+            limits: Limits {
+                max_body_size: config.server.json_limit(),
+                max_heartbeat_batch: MAX_HEARTBEAT_BATCH,
+                max_search_limit: MAX_SEARCH_LIMIT,
+                max_sample_size: MAX_SAMPLE_SIZE,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn features() {
+        let features = Features::new(&Config::default(), 1);
+        assert_eq!(features.backend.shards, 1);
+        assert!(!features.backend.replicas);
+        assert!(!features.subsystems.auth);
+        assert!(features.subsystems.anomalies);
+        assert!(features.subsystems.event_sinks.is_empty());
+        assert_eq!(features.limits.max_heartbeat_batch, 1000);
+
+        let config: Config = toml::from_str(
+            r#"
+[server]
+max_body_size = "1MiB"
+
+[auth]
+admin_keys = ["secret"]
+
+[events.nats]
+url = "nats://localhost:4222"
+"#,
+        )
+        .unwrap();
+        let features = Features::new(&config, 2);
+        assert!(features.subsystems.auth);
+        assert_eq!(features.subsystems.event_sinks, vec!["nats"]);
+        assert_eq!(features.limits.max_body_size, 1024 * 1024);
+    }
+}
@@ -0,0 +1,55 @@
+//! Defines the report produced by checking the consistency of data stored in Redis.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+/// Problems found by an integrity check, where jobs or indexes have been left inconsistent, e.g. by a partially
+/// applied update.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct IntegrityReport {
+    /// Queues listed as existing, but with no settings stored.
+    pub dangling_queues: Vec<String>,
+
+    /// IDs of jobs that no longer exist, but are still in a status list, queue, or tag, by Redis key.
+    pub dangling_ids: BTreeMap<String, Vec<u64>>,
+
+    /// IDs of jobs in a status list or queue that doesn't match the job's current status or queue, by Redis key.
+    pub mismatched_ids: BTreeMap<String, Vec<u64>>,
+
+    /// IDs of jobs that aren't in any status list or queue, so will never be processed.
+    pub orphaned_jobs: Vec<u64>,
+
+    /// Amount each namespace's storage usage counter differs from the storage its jobs actually use, by namespace.
+    pub miscounted_storage: BTreeMap<String, i64>,
+
+    /// Whether the problems found were repaired.
+    pub repaired: bool,
+}
+
+impl IntegrityReport {
+    /// Check whether no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.dangling_queues.is_empty()
+            && self.dangling_ids.is_empty()
+            && self.mismatched_ids.is_empty()
+            && self.orphaned_jobs.is_empty()
+            && self.miscounted_storage.is_empty()
+    }
+
+    /// Add problems found on another Redis shard to this.
+    pub fn merge(&mut self, other: IntegrityReport) {
+        self.dangling_queues.extend(other.dangling_queues);
+        for (key, job_ids) in other.dangling_ids {
+            self.dangling_ids.entry(key).or_default().extend(job_ids);
+        }
+        for (key, job_ids) in other.mismatched_ids {
+            self.mismatched_ids.entry(key).or_default().extend(job_ids);
+        }
+        self.orphaned_jobs.extend(other.orphaned_jobs);
+        self.orphaned_jobs.sort_unstable();
+        for (namespace, difference) in other.miscounted_storage {
+            *self.miscounted_storage.entry(namespace).or_default() += difference;
+        }
+    }
+}
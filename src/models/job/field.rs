@@ -9,18 +9,45 @@ const QUEUE_FIELD: &str = "queue";
 const STATUS_FIELD: &str = "status";
 const TAGS_FIELD: &str = "tags";
 const CREATED_AT_FIELD: &str = "created_at";
+const QUEUED_AT_FIELD: &str = "queued_at";
 const STARTED_AT_FIELD: &str = "started_at";
 const ENDED_AT_FIELD: &str = "ended_at";
 const LAST_HEARTBEAT_FIELD: &str = "last_heartbeat";
+const PROGRESS_FIELD: &str = "progress";
 const INPUT_FIELD: &str = "input";
 const OUTPUT_FIELD: &str = "output";
+const OUTPUT_TRUNCATED_FIELD: &str = "output_truncated";
 const TIMEOUT_FIELD: &str = "timeout";
 const HEARTBEAT_TIMEOUT_FIELD: &str = "heartbeat_timeout";
 const EXPIRES_AFTER_FIELD: &str = "expires_after";
 const RETRIES_FIELD: &str = "retries";
 const RETRIES_ATTEMPTED_FIELD: &str = "retries_attempted";
 const RETRY_DELAYS_FIELD: &str = "retry_delays";
+const RETRY_AFTER_FIELD: &str = "retry_after";
+const QUARANTINE_AFTER_FIELD: &str = "quarantine_after";
+const QUICK_FAIL_WINDOW_FIELD: &str = "quick_fail_window";
+const TOTAL_TIMEOUT_FIELD: &str = "total_timeout";
+const POISON_STRIKES_FIELD: &str = "poison_strikes";
+const QUARANTINE_REASON_FIELD: &str = "quarantine_reason";
+const HELD_FIELD: &str = "held";
+const DEADLINE_FIELD: &str = "deadline";
+const SLA_BREACHED_FIELD: &str = "sla_breached";
+const ERROR_CODE_FIELD: &str = "error_code";
+const ERROR_DETAILS_FIELD: &str = "error_details";
+const SHADOW_OF_FIELD: &str = "shadow_of";
+const CALLBACK_URL_FIELD: &str = "callback_url";
+const ROUTING_KEY_FIELD: &str = "routing_key";
+const SESSION_KEY_FIELD: &str = "session_key";
+const SERIALIZATION_KEY_FIELD: &str = "serialization_key";
+const UNIQUE_KEY_FIELD: &str = "unique_key";
 const ENDED_FIELD: &str = "ended";
+const QUEUED_TIME_FIELD: &str = "queued_time";
+const RUN_TIME_FIELD: &str = "run_time";
+const TOTAL_TIME_FIELD: &str = "total_time";
+const HEARTBEAT_AGE_FIELD: &str = "heartbeat_age";
+const ATTEMPTS_FIELD: &str = "attempts";
+const ENQUEUE_TO_START_MS_FIELD: &str = "enqueue_to_start_ms";
+const START_TO_END_MS_FIELD: &str = "start_to_end_ms";
 
 /// Represents a job field that's stored in a Redis hash.
 #[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize)]
@@ -31,44 +58,120 @@ pub enum Field {
     Status,
     Tags,
     CreatedAt,
+    QueuedAt,
     StartedAt,
     EndedAt,
     LastHeartbeat,
+    Progress,
     Input,
     Output,
+    OutputTruncated,
     Timeout,
     HeartbeatTimeout,
     ExpiresAfter,
     Retries,
     RetriesAttempted,
     RetryDelays,
+    RetryAfter,
+    QuarantineAfter,
+    QuickFailWindow,
+    TotalTimeout,
+    PoisonStrikes,
+    QuarantineReason,
+    Held,
+    Deadline,
+    SlaBreached,
+    ErrorCode,
+    ErrorDetails,
+    ShadowOf,
+    CallbackUrl,
+    RoutingKey,
+    SessionKey,
+    SerializationKey,
+    UniqueKey,
     Ended,
+    QueuedTime,
+    RunTime,
+    TotalTime,
+    HeartbeatAge,
+    Attempts,
+    EnqueueToStartMs,
+    StartToEndMs,
 }
 
 impl Field {
     pub fn all_fields() -> &'static [Field] {
-        static ALL_FIELDS: [Field; 17] = [
+        static ALL_FIELDS: [Field; 44] = [
             Field::Id,
             Field::Queue,
             Field::Status,
             Field::Tags,
             Field::CreatedAt,
+            Field::QueuedAt,
             Field::StartedAt,
             Field::EndedAt,
             Field::LastHeartbeat,
+            Field::Progress,
             Field::Input,
             Field::Output,
+            Field::OutputTruncated,
             Field::Timeout,
             Field::HeartbeatTimeout,
             Field::ExpiresAfter,
             Field::Retries,
             Field::RetriesAttempted,
             Field::RetryDelays,
+            Field::RetryAfter,
+            Field::QuarantineAfter,
+            Field::QuickFailWindow,
+            Field::TotalTimeout,
+            Field::PoisonStrikes,
+            Field::QuarantineReason,
+            Field::Held,
+            Field::Deadline,
+            Field::SlaBreached,
+            Field::ErrorCode,
+            Field::ErrorDetails,
+            Field::ShadowOf,
+            Field::CallbackUrl,
+            Field::RoutingKey,
+            Field::SessionKey,
+            Field::SerializationKey,
+            Field::UniqueKey,
             Field::Ended,
+            Field::QueuedTime,
+            Field::RunTime,
+            Field::TotalTime,
+            Field::HeartbeatAge,
+            Field::Attempts,
+            Field::EnqueueToStartMs,
+            Field::StartToEndMs,
         ];
 
         &ALL_FIELDS
     }
+
+    /// Get the stored fields a derived field is computed from, which must be fetched along with it. Fields stored
+    /// in Redis have no dependencies.
+    pub fn dependencies(&self) -> &'static [Field] {
+        match self {
+            Field::Ended => &[
+                Field::Retries,
+                Field::RetriesAttempted,
+                Field::Status,
+                Field::CreatedAt,
+                Field::TotalTimeout,
+            ],
+            Field::QueuedTime => &[Field::CreatedAt, Field::StartedAt],
+            Field::RunTime => &[Field::StartedAt, Field::EndedAt],
+            Field::TotalTime => &[Field::CreatedAt, Field::EndedAt],
+            Field::HeartbeatAge => &[Field::Status, Field::LastHeartbeat],
+            Field::Attempts => &[Field::RetriesAttempted, Field::StartedAt],
+            Field::EnqueueToStartMs => &[Field::CreatedAt, Field::QueuedAt, Field::StartedAt],
+            Field::StartToEndMs => &[Field::StartedAt, Field::EndedAt],
+            _ => &[],
+        }
+    }
 }
 
 impl fmt::Display for Field {
@@ -85,18 +188,45 @@ impl AsRef<str> for Field {
             Field::Status => STATUS_FIELD,
             Field::Tags => TAGS_FIELD,
             Field::CreatedAt => CREATED_AT_FIELD,
+            Field::QueuedAt => QUEUED_AT_FIELD,
             Field::StartedAt => STARTED_AT_FIELD,
             Field::EndedAt => ENDED_AT_FIELD,
             Field::LastHeartbeat => LAST_HEARTBEAT_FIELD,
+            Field::Progress => PROGRESS_FIELD,
             Field::Input => INPUT_FIELD,
             Field::Output => OUTPUT_FIELD,
+            Field::OutputTruncated => OUTPUT_TRUNCATED_FIELD,
             Field::Timeout => TIMEOUT_FIELD,
             Field::HeartbeatTimeout => HEARTBEAT_TIMEOUT_FIELD,
             Field::ExpiresAfter => EXPIRES_AFTER_FIELD,
             Field::Retries => RETRIES_FIELD,
             Field::RetriesAttempted => RETRIES_ATTEMPTED_FIELD,
             Field::RetryDelays => RETRY_DELAYS_FIELD,
+            Field::RetryAfter => RETRY_AFTER_FIELD,
+            Field::QuarantineAfter => QUARANTINE_AFTER_FIELD,
+            Field::QuickFailWindow => QUICK_FAIL_WINDOW_FIELD,
+            Field::TotalTimeout => TOTAL_TIMEOUT_FIELD,
+            Field::PoisonStrikes => POISON_STRIKES_FIELD,
+            Field::QuarantineReason => QUARANTINE_REASON_FIELD,
+            Field::Held => HELD_FIELD,
+            Field::Deadline => DEADLINE_FIELD,
+            Field::SlaBreached => SLA_BREACHED_FIELD,
+            Field::ErrorCode => ERROR_CODE_FIELD,
+            Field::ErrorDetails => ERROR_DETAILS_FIELD,
+            Field::ShadowOf => SHADOW_OF_FIELD,
+            Field::CallbackUrl => CALLBACK_URL_FIELD,
+            Field::RoutingKey => ROUTING_KEY_FIELD,
+            Field::SessionKey => SESSION_KEY_FIELD,
+            Field::SerializationKey => SERIALIZATION_KEY_FIELD,
+            Field::UniqueKey => UNIQUE_KEY_FIELD,
             Field::Ended => ENDED_FIELD,
+            Field::QueuedTime => QUEUED_TIME_FIELD,
+            Field::RunTime => RUN_TIME_FIELD,
+            Field::TotalTime => TOTAL_TIME_FIELD,
+            Field::HeartbeatAge => HEARTBEAT_AGE_FIELD,
+            Field::Attempts => ATTEMPTS_FIELD,
+            Field::EnqueueToStartMs => ENQUEUE_TO_START_MS_FIELD,
+            Field::StartToEndMs => START_TO_END_MS_FIELD,
         }
     }
 }
@@ -111,18 +241,45 @@ impl FromStr for Field {
             STATUS_FIELD => Ok(Field::Status),
             TAGS_FIELD => Ok(Field::Tags),
             CREATED_AT_FIELD => Ok(Field::CreatedAt),
+            QUEUED_AT_FIELD => Ok(Field::QueuedAt),
             STARTED_AT_FIELD => Ok(Field::StartedAt),
             ENDED_AT_FIELD => Ok(Field::EndedAt),
             LAST_HEARTBEAT_FIELD => Ok(Field::LastHeartbeat),
+            PROGRESS_FIELD => Ok(Field::Progress),
             INPUT_FIELD => Ok(Field::Input),
             OUTPUT_FIELD => Ok(Field::Output),
+            OUTPUT_TRUNCATED_FIELD => Ok(Field::OutputTruncated),
             TIMEOUT_FIELD => Ok(Field::Timeout),
             HEARTBEAT_TIMEOUT_FIELD => Ok(Field::HeartbeatTimeout),
             EXPIRES_AFTER_FIELD => Ok(Field::ExpiresAfter),
             RETRIES_FIELD => Ok(Field::Retries),
             RETRIES_ATTEMPTED_FIELD => Ok(Field::RetriesAttempted),
             RETRY_DELAYS_FIELD => Ok(Field::RetryDelays),
+            RETRY_AFTER_FIELD => Ok(Field::RetryAfter),
+            QUARANTINE_AFTER_FIELD => Ok(Field::QuarantineAfter),
+            QUICK_FAIL_WINDOW_FIELD => Ok(Field::QuickFailWindow),
+            TOTAL_TIMEOUT_FIELD => Ok(Field::TotalTimeout),
+            POISON_STRIKES_FIELD => Ok(Field::PoisonStrikes),
+            QUARANTINE_REASON_FIELD => Ok(Field::QuarantineReason),
+            HELD_FIELD => Ok(Field::Held),
+            DEADLINE_FIELD => Ok(Field::Deadline),
+            SLA_BREACHED_FIELD => Ok(Field::SlaBreached),
+            ERROR_CODE_FIELD => Ok(Field::ErrorCode),
+            ERROR_DETAILS_FIELD => Ok(Field::ErrorDetails),
+            SHADOW_OF_FIELD => Ok(Field::ShadowOf),
+            CALLBACK_URL_FIELD => Ok(Field::CallbackUrl),
+            ROUTING_KEY_FIELD => Ok(Field::RoutingKey),
+            SESSION_KEY_FIELD => Ok(Field::SessionKey),
+            SERIALIZATION_KEY_FIELD => Ok(Field::SerializationKey),
+            UNIQUE_KEY_FIELD => Ok(Field::UniqueKey),
             ENDED_FIELD => Ok(Field::Ended),
+            QUEUED_TIME_FIELD => Ok(Field::QueuedTime),
+            RUN_TIME_FIELD => Ok(Field::RunTime),
+            TOTAL_TIME_FIELD => Ok(Field::TotalTime),
+            HEARTBEAT_AGE_FIELD => Ok(Field::HeartbeatAge),
+            ATTEMPTS_FIELD => Ok(Field::Attempts),
+            ENQUEUE_TO_START_MS_FIELD => Ok(Field::EnqueueToStartMs),
+            START_TO_END_MS_FIELD => Ok(Field::StartToEndMs),
             _ => Err(()),
         }
     }
@@ -154,18 +311,45 @@ mod test {
             Field::Status,
             Field::Tags,
             Field::CreatedAt,
+            Field::QueuedAt,
             Field::StartedAt,
             Field::EndedAt,
             Field::LastHeartbeat,
+            Field::Progress,
             Field::Input,
             Field::Output,
+            Field::OutputTruncated,
             Field::Timeout,
             Field::HeartbeatTimeout,
             Field::ExpiresAfter,
             Field::Retries,
             Field::RetriesAttempted,
             Field::RetryDelays,
+            Field::RetryAfter,
+            Field::QuarantineAfter,
+            Field::QuickFailWindow,
+            Field::TotalTimeout,
+            Field::PoisonStrikes,
+            Field::QuarantineReason,
+            Field::Held,
+            Field::Deadline,
+            Field::SlaBreached,
+            Field::ErrorCode,
+            Field::ErrorDetails,
+            Field::ShadowOf,
+            Field::CallbackUrl,
+            Field::RoutingKey,
+            Field::SessionKey,
+            Field::SerializationKey,
+            Field::UniqueKey,
             Field::Ended,
+            Field::QueuedTime,
+            Field::RunTime,
+            Field::TotalTime,
+            Field::HeartbeatAge,
+            Field::Attempts,
+            Field::EnqueueToStartMs,
+            Field::StartToEndMs,
         ];
 
         for field in all_fields {
@@ -0,0 +1,81 @@
+//! Defines structs used to send heartbeats for many jobs in a single request.
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of jobs that can be given in a single batch heartbeat request.
+pub const MAX_HEARTBEAT_BATCH: usize = 1000;
+
+/// Heartbeat for a single job in a batch heartbeat request, given either as just the job's ID, or as an object with
+/// the job's ID and its current progress.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Heartbeat {
+    Id(u64),
+    WithProgress {
+        id: u64,
+
+        /// Any JSON describing how far through the job the worker is, e.g. `0.5` or `{"done": 10, "total": 20}`.
+        /// Replaces any progress previously reported for the job's current attempt.
+        progress: Option<serde_json::Value>,
+    },
+}
+
+impl Heartbeat {
+    /// Get the ID of the job this heartbeat is for.
+    pub fn id(&self) -> u64 {
+        match self {
+            Heartbeat::Id(id) | Heartbeat::WithProgress { id, .. } => *id,
+        }
+    }
+
+    /// Get the job's progress given with this heartbeat, if any.
+    pub fn progress(&self) -> Option<&serde_json::Value> {
+        match self {
+            Heartbeat::Id(_) => None,
+            Heartbeat::WithProgress { progress, .. } => progress.as_ref(),
+        }
+    }
+}
+
+/// Outcome of a batch heartbeat request, giving the IDs of the jobs in it by whether their heartbeat was updated.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct HeartbeatResults {
+    /// Jobs whose heartbeat (and progress, if given) was updated.
+    pub updated: Vec<u64>,
+
+    /// Jobs that exist, but aren't running, so weren't updated. Workers should generally stop working on these.
+    pub not_running: Vec<u64>,
+
+    /// Jobs that don't exist.
+    pub not_found: Vec<u64>,
+}
+
+impl HeartbeatResults {
+    /// Add the results of another batch to these results, keeping each list sorted by job ID.
+    pub fn merge(&mut self, other: HeartbeatResults) {
+        self.updated.extend(other.updated);
+        self.not_running.extend(other.not_running);
+        self.not_found.extend(other.not_found);
+        self.updated.sort_unstable();
+        self.not_running.sort_unstable();
+        self.not_found.sort_unstable();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_heartbeats() {
+        let heartbeats: Vec<Heartbeat> =
+            serde_json::from_str(r#"[12, {"id": 13}, {"id": 14, "progress": {"done": 3, "total": 10}}]"#).unwrap();
+        assert_eq!(heartbeats.iter().map(Heartbeat::id).collect::<Vec<_>>(), vec![12, 13, 14]);
+        assert_eq!(heartbeats[0].progress(), None);
+        assert_eq!(heartbeats[1].progress(), None);
+        assert_eq!(heartbeats[2].progress(), Some(&serde_json::json!({"done": 3, "total": 10})));
+
+        assert!(serde_json::from_str::<Vec<Heartbeat>>(r#"[{"progress": 0.5}]"#).is_err());
+        assert!(serde_json::from_str::<Vec<Heartbeat>>(r#"["12"]"#).is_err());
+    }
+}
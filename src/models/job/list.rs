@@ -0,0 +1,15 @@
+/// Identifies which of its queue's lists a queued job is kept in.
+///
+/// Jobs without a routing, session, or serialization key are kept in the queue's main list, while jobs with one are
+/// kept in a separate list for each key, so that they're only taken when they can be run. A job can only have one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueueList {
+    /// Routing key the job was created with, if any.
+    pub routing_key: Option<String>,
+
+    /// Session key the job was created with, if any.
+    pub session_key: Option<String>,
+
+    /// Serialization key the job was created with, if any.
+    pub serialization_key: Option<String>,
+}
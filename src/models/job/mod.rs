@@ -1,14 +1,24 @@
 mod field;
+mod heartbeat;
+mod list;
 mod payload;
 mod request;
+mod search;
 mod status;
+mod transition;
 
 pub use self::field::Field;
+pub use self::heartbeat::{Heartbeat, HeartbeatResults, MAX_HEARTBEAT_BATCH};
+pub use self::list::QueueList;
 pub use self::payload::Payload;
-pub use self::request::{CreateRequest, UpdateRequest};
+pub use self::request::{CreateRequest, UpdateRequest, COPY_FIELDS};
+pub use self::search::{SearchQuery, SearchResults, MAX_SEARCH_LIMIT, SEARCH_FIELDS};
 pub use self::status::{Status, ALL_STATUSES};
+pub use self::transition::{check_allowed_transitions, Transition};
 
+use crate::application::{clock, crypto};
 use crate::models::{DateTime, Duration, OcyResult};
+use log::error;
 use redis::{self, aio::ConnectionLike, AsyncCommands, FromRedisValue, ToRedisArgs};
 use serde::ser::{Serialize, SerializeMap, Serializer};
 use std::collections::{HashMap, HashSet};
@@ -37,18 +47,45 @@ impl Serialize for JobMeta {
                 Field::Status => map.serialize_entry(field, &self.status())?,
                 Field::Tags => map.serialize_entry(field, &self.tags())?,
                 Field::CreatedAt => map.serialize_entry(field, &self.created_at())?,
+                Field::QueuedAt => map.serialize_entry(field, &self.queued_at())?,
                 Field::StartedAt => map.serialize_entry(field, &self.started_at())?,
                 Field::EndedAt => map.serialize_entry(field, &self.ended_at())?,
                 Field::LastHeartbeat => map.serialize_entry(field, &self.last_heartbeat())?,
+                Field::Progress => map.serialize_entry(field, &self.progress())?,
                 Field::Input => map.serialize_entry(field, &self.input())?,
                 Field::Output => map.serialize_entry(field, &self.output())?,
+                Field::OutputTruncated => map.serialize_entry(field, &self.output_truncated())?,
                 Field::Timeout => map.serialize_entry(field, &self.timeout())?,
                 Field::HeartbeatTimeout => map.serialize_entry(field, &self.heartbeat_timeout())?,
                 Field::ExpiresAfter => map.serialize_entry(field, &self.expires_after())?,
                 Field::Retries => map.serialize_entry(field, &self.retries())?,
                 Field::RetriesAttempted => map.serialize_entry(field, &self.retries_attempted())?,
                 Field::RetryDelays => map.serialize_entry(field, &self.retry_delays())?,
+                Field::RetryAfter => map.serialize_entry(field, &self.retry_after())?,
+                Field::QuarantineAfter => map.serialize_entry(field, &self.quarantine_after())?,
+                Field::QuickFailWindow => map.serialize_entry(field, &self.quick_fail_window())?,
+                Field::TotalTimeout => map.serialize_entry(field, &self.total_timeout())?,
+                Field::PoisonStrikes => map.serialize_entry(field, &self.poison_strikes())?,
+                Field::QuarantineReason => map.serialize_entry(field, &self.quarantine_reason())?,
+                Field::Held => map.serialize_entry(field, &self.held())?,
+                Field::Deadline => map.serialize_entry(field, &self.deadline())?,
+                Field::SlaBreached => map.serialize_entry(field, &self.sla_breached())?,
+                Field::ErrorCode => map.serialize_entry(field, &self.error_code())?,
+                Field::ErrorDetails => map.serialize_entry(field, &self.error_details())?,
+                Field::ShadowOf => map.serialize_entry(field, &self.shadow_of())?,
+                Field::CallbackUrl => map.serialize_entry(field, &self.callback_url())?,
+                Field::RoutingKey => map.serialize_entry(field, &self.routing_key())?,
+                Field::SessionKey => map.serialize_entry(field, &self.session_key())?,
+                Field::SerializationKey => map.serialize_entry(field, &self.serialization_key())?,
+                Field::UniqueKey => map.serialize_entry(field, &self.unique_key())?,
                 Field::Ended => map.serialize_entry(field, &self.ended())?,
+                Field::QueuedTime => map.serialize_entry(field, &self.queued_time())?,
+                Field::RunTime => map.serialize_entry(field, &self.run_time())?,
+                Field::TotalTime => map.serialize_entry(field, &self.total_time())?,
+                Field::HeartbeatAge => map.serialize_entry(field, &self.heartbeat_age())?,
+                Field::Attempts => map.serialize_entry(field, &self.attempts())?,
+                Field::EnqueueToStartMs => map.serialize_entry(field, &self.enqueue_to_start_ms())?,
+                Field::StartToEndMs => map.serialize_entry(field, &self.start_to_end_ms())?,
             }
         }
 
@@ -118,7 +155,7 @@ impl JobMeta {
     /// Get a mandatory field value from this struct's map. Caller must ensure that field is present.
     fn get_mandatory_field<T: redis::FromRedisValue>(&self, field: &Field) -> T {
         redis::from_redis_value(
-            &self
+            self
                 .map
                 .get(field)
                 .unwrap_or_else(|| panic!("failed to get: {}", field)),
@@ -133,6 +170,32 @@ impl JobMeta {
             .map(|v| redis::from_redis_value(v).unwrap())
     }
 
+    /// Get a JSON input or output field, decrypting it if it's encrypted. Undecryptable values are logged and
+    /// treated as missing.
+    fn get_payload_field(&self, field: &Field) -> Option<serde_json::Value> {
+        let stored = self.get_optional_field::<String>(field)?;
+        match crypto::open(stored) {
+            Ok(s) => Some(serde_json::from_str(&s).unwrap()),
+            Err(err) => {
+                error!("Failed to read job {}: {}", field, err);
+                None
+            }
+        }
+    }
+
+    /// Rewrite this job's queue name and tags (if fetched) using given function, e.g. to remove a tenant's namespace
+    /// from them.
+    pub fn map_names<F: Fn(&str) -> String>(&mut self, f: F) {
+        if let Some(queue) = self.get_optional_field::<String>(&Field::Queue) {
+            self.map.insert(Field::Queue, redis::Value::Data(f(&queue).into_bytes()));
+        }
+        if let Some(tags) = self.tags() {
+            let tags: Vec<String> = tags.iter().map(|tag| f(tag)).collect();
+            let tags_json = serde_json::to_string(&tags).unwrap();
+            self.map.insert(Field::Tags, redis::Value::Data(tags_json.into_bytes()));
+        }
+    }
+
     pub fn id(&self) -> u64 {
         self.get_mandatory_field(&Field::Id)
     }
@@ -154,6 +217,12 @@ impl JobMeta {
         self.get_mandatory_field(&Field::CreatedAt)
     }
 
+    /// Get when this job was last put on its queue, which is only recorded for jobs queued since upgrading to a
+    /// version tracking it.
+    pub fn queued_at(&self) -> Option<DateTime> {
+        self.get_optional_field(&Field::QueuedAt)
+    }
+
     pub fn started_at(&self) -> Option<DateTime> {
         self.get_optional_field(&Field::StartedAt)
     }
@@ -166,14 +235,23 @@ impl JobMeta {
         self.get_optional_field(&Field::LastHeartbeat)
     }
 
-    pub fn input(&self) -> Option<serde_json::Value> {
-        self.get_optional_field::<String>(&Field::Input)
+    pub fn progress(&self) -> Option<serde_json::Value> {
+        self.get_optional_field::<String>(&Field::Progress)
             .map(|s| serde_json::from_str(&s).unwrap())
     }
 
+    pub fn input(&self) -> Option<serde_json::Value> {
+        self.get_payload_field(&Field::Input)
+    }
+
     pub fn output(&self) -> Option<serde_json::Value> {
-        self.get_optional_field::<String>(&Field::Output)
-            .map(|s| serde_json::from_str(&s).unwrap())
+        self.get_payload_field(&Field::Output)
+    }
+
+    /// Whether the output set by the worker exceeded its queue's maximum output size, and was truncated or
+    /// offloaded rather than stored as it was.
+    pub fn output_truncated(&self) -> bool {
+        self.get_optional_field(&Field::OutputTruncated).unwrap_or_default()
     }
 
     pub fn timeout(&self) -> Duration {
@@ -201,16 +279,188 @@ impl JobMeta {
             .map(|s| serde_json::from_str(&s).unwrap())
     }
 
+    /// Delay before retrying this job given by the worker when it last failed it, overriding its retry delays.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.get_optional_field(&Field::RetryAfter)
+    }
+
+    /// Number of poison strikes after which this job is quarantined, jobs created before quarantining was
+    /// supported are never quarantined.
+    pub fn quarantine_after(&self) -> u64 {
+        self.get_optional_field(&Field::QuarantineAfter).unwrap_or_default()
+    }
+
+    pub fn quick_fail_window(&self) -> Duration {
+        self.get_optional_field(&Field::QuickFailWindow)
+            .unwrap_or_else(|| Duration::from_secs(0))
+    }
+
+    pub fn total_timeout(&self) -> Option<Duration> {
+        self.get_optional_field(&Field::TotalTimeout)
+    }
+
+    /// Check whether more than this job's total timeout has passed since it was created, in which case it shouldn't
+    /// be run or retried any more. Always false for jobs with no total timeout.
+    pub fn has_exceeded_total_timeout(&self) -> bool {
+        match self.total_timeout() {
+            Some(total_timeout) if total_timeout.as_secs() > 0 => {
+                clock::now().seconds_since(&self.created_at()).max(0) as u64 > total_timeout.as_secs()
+            }
+            _ => false,
+        }
+    }
+
+    pub fn poison_strikes(&self) -> u64 {
+        self.get_optional_field(&Field::PoisonStrikes).unwrap_or_default()
+    }
+
+    pub fn quarantine_reason(&self) -> Option<String> {
+        self.get_optional_field(&Field::QuarantineReason)
+    }
+
+    pub fn held(&self) -> bool {
+        self.get_optional_field(&Field::Held).unwrap_or_default()
+    }
+
+    pub fn deadline(&self) -> Option<DateTime> {
+        self.get_optional_field(&Field::Deadline)
+    }
+
+    pub fn sla_breached(&self) -> bool {
+        self.get_optional_field(&Field::SlaBreached).unwrap_or_default()
+    }
+
+    pub fn error_code(&self) -> Option<String> {
+        self.get_optional_field(&Field::ErrorCode)
+    }
+
+    pub fn error_details(&self) -> Option<serde_json::Value> {
+        self.get_optional_field::<String>(&Field::ErrorDetails)
+            .map(|s| serde_json::from_str(&s).unwrap())
+    }
+
+    pub fn shadow_of(&self) -> Option<u64> {
+        self.get_optional_field(&Field::ShadowOf)
+    }
+
+    pub fn callback_url(&self) -> Option<String> {
+        self.get_optional_field(&Field::CallbackUrl)
+    }
+
+    /// Get the routing key this job was created with, if any, which only workers advertising it can take the job.
+    pub fn routing_key(&self) -> Option<String> {
+        self.get_optional_field(&Field::RoutingKey)
+    }
+
+    /// Get the session key this job was created with, if any, which its session's jobs are run one at a time by.
+    pub fn session_key(&self) -> Option<String> {
+        self.get_optional_field(&Field::SessionKey)
+    }
+
+    /// Get the serialization key this job was created with, if any, jobs with the same key never run at the same time.
+    pub fn serialization_key(&self) -> Option<String> {
+        self.get_optional_field(&Field::SerializationKey)
+    }
+
+    /// Get the unique key this job was created with, if any, which no other job on its queue is created with while
+    /// this job hasn't ended.
+    pub fn unique_key(&self) -> Option<String> {
+        self.get_optional_field(&Field::UniqueKey)
+    }
+
+    /// Get which of its queue's lists this job is kept in while queued, requires the `routing_key`, `session_key`, and
+    /// `serialization_key` fields.
+    pub fn queue_list(&self) -> QueueList {
+        QueueList {
+            routing_key: self.routing_key(),
+            session_key: self.session_key(),
+            serialization_key: self.serialization_key(),
+        }
+    }
+
     pub fn ended(&self) -> bool {
         match self.status() {
             Status::Running | Status::Queued => false,
             Status::TimedOut | Status::Failed => {
                 let retries = self.retries();
-                retries == 0 || retries == self.retries_attempted()
+                retries == 0 || retries == self.retries_attempted() || self.has_exceeded_total_timeout()
             },
-            Status::Completed | Status::Cancelled => true,
+            Status::Completed | Status::Cancelled | Status::Quarantined => true,
         }
     }
+
+    /// Get how long this job was queued before its current attempt started, or has been queued so far if it hasn't
+    /// started. Includes time spent on earlier attempts and waiting to be retried.
+    pub fn queued_time(&self) -> Duration {
+        let until = self.started_at().unwrap_or_else(clock::now);
+        elapsed(&self.created_at(), &until)
+    }
+
+    /// Get how long this job's current attempt ran for, or has been running so far if it's still running.
+    pub fn run_time(&self) -> Option<Duration> {
+        let started_at = self.started_at()?;
+        let until = self.ended_at().unwrap_or_else(clock::now);
+        Some(elapsed(&started_at, &until))
+    }
+
+    /// Get how long it's been between this job being created and it ending, or until now if it hasn't ended.
+    pub fn total_time(&self) -> Duration {
+        let until = self.ended_at().unwrap_or_else(clock::now);
+        elapsed(&self.created_at(), &until)
+    }
+
+    /// Get how long it's been since this job's last heartbeat, if it's running and has sent one.
+    pub fn heartbeat_age(&self) -> Option<Duration> {
+        match self.status() {
+            Status::Running => Some(elapsed(&self.last_heartbeat()?, &clock::now())),
+            _ => None,
+        }
+    }
+
+    /// Get the milliseconds between this job last being put on its queue and its current attempt starting, if it's
+    /// started. Falls back to its creation time for jobs queued before queue times were recorded.
+    pub fn enqueue_to_start_ms(&self) -> Option<u64> {
+        let started_at = self.started_at()?;
+        let queued_at = self.queued_at().unwrap_or_else(|| self.created_at());
+        Some(started_at.millis_since(&queued_at).max(0) as u64)
+    }
+
+    /// Get the milliseconds between this job's current attempt starting and ending, if it's ended.
+    pub fn start_to_end_ms(&self) -> Option<u64> {
+        let started_at = self.started_at()?;
+        Some(self.ended_at()?.millis_since(&started_at).max(0) as u64)
+    }
+
+    /// Get the number of times this job has been started, including its current attempt.
+    pub fn attempts(&self) -> u64 {
+        // started_at is cleared when a job is requeued, and retries_attempted incremented
+        self.retries_attempted() + u64::from(self.started_at().is_some())
+    }
+}
+
+/// Outcome of a request to create a job.
+#[derive(Debug, PartialEq)]
+pub enum Created {
+    /// New job was created with this ID.
+    New(u64),
+
+    /// Existing job with the same `unique_key` was returned instead of creating a new one.
+    Existing(JobMeta),
+}
+
+impl Created {
+    /// Get the ID of the created or existing job.
+    pub fn id(&self) -> u64 {
+        match self {
+            Created::New(job_id) => *job_id,
+            Created::Existing(job) => job.id(),
+        }
+    }
+}
+
+/// Get the time elapsed between two date/times, treating clock skew making `to` earlier than `from` as no time.
+fn elapsed(from: &DateTime, to: &DateTime) -> Duration {
+    Duration::from_secs(to.seconds_since(from).max(0) as u64)
 }
 
 /// Subset of job data used for determining whether a job should be timed out.
@@ -241,7 +491,18 @@ impl TimeoutMeta {
         self.0.id()
     }
 
-    pub fn has_timed_out(&self) -> bool {
+    /// Get the name of the queue this job was created in, or `None` if the job has been deleted.
+    pub fn queue(&self) -> Option<String> {
+        if self.0.exists() {
+            Some(self.0.queue())
+        } else {
+            None
+        }
+    }
+
+    /// Check whether this job has timed out, with its heartbeat timeout multiplied by given factor, e.g. to give jobs
+    /// longer to send heartbeats while the server is overloaded.
+    pub fn has_timed_out(&self, heartbeat_factor: u32) -> bool {
         // no timeout metadata means that job has been deleted
         if !self.0.exists() {
             return false;
@@ -253,13 +514,13 @@ impl TimeoutMeta {
         }
 
         let timeout_seconds = self.0.timeout().as_secs();
-        let heartbeat_timeout_seconds = self.0.heartbeat_timeout().as_secs();
+        let heartbeat_timeout_seconds = self.0.heartbeat_timeout().as_secs().saturating_mul(heartbeat_factor.into());
         let last_heartbeat = self.0.last_heartbeat();
         let started_at = self.0.started_at().unwrap();
 
         if heartbeat_timeout_seconds > 0 {
             let hb = last_heartbeat.as_ref().unwrap_or(&started_at);
-            let duration_seconds = DateTime::now().seconds_since(hb);
+            let duration_seconds = clock::now().seconds_since(hb);
             assert!(duration_seconds >= 0); // TODO: error handling here?
             if duration_seconds as u64 > heartbeat_timeout_seconds {
                 return true;
@@ -267,24 +528,27 @@ impl TimeoutMeta {
         }
 
         if timeout_seconds > 0 {
-            let duration_seconds = DateTime::now().seconds_since(&started_at);
+            let duration_seconds = clock::now().seconds_since(&started_at);
             assert!(duration_seconds >= 0);
             if duration_seconds as u64 > timeout_seconds {
                 return true;
             }
         }
 
-        false
+        self.0.has_exceeded_total_timeout()
     }
 
     pub fn fields() -> &'static [Field] {
-        static FIELDS: [Field; 6] = [
+        static FIELDS: [Field; 9] = [
             Field::Id,
+            Field::Queue,
             Field::Status,
             Field::Timeout,
             Field::HeartbeatTimeout,
             Field::LastHeartbeat,
             Field::StartedAt,
+            Field::CreatedAt,
+            Field::TotalTimeout,
         ];
         &FIELDS
     }
@@ -319,21 +583,29 @@ impl ExpiryMeta {
         self.0.id()
     }
 
+    /// Get the name of the queue this job was created in, or `None` if the job has been deleted.
+    pub fn queue(&self) -> Option<String> {
+        if self.0.exists() {
+            Some(self.0.queue())
+        } else {
+            None
+        }
+    }
+
     pub fn fields() -> &'static [Field] {
-        static FIELDS: [Field; 4] = [Field::Id, Field::EndedAt, Field::ExpiresAfter, Field::Status];
+        static FIELDS: [Field; 5] = [Field::Id, Field::Queue, Field::EndedAt, Field::ExpiresAfter, Field::Status];
         &FIELDS
     }
 
-    pub fn should_expire(&self) -> bool {
+    /// Check whether this job has expired, only jobs with one of the given statuses can expire.
+    pub fn should_expire(&self, statuses: &[Status]) -> bool {
         // no retry metadata means that job has been deleted
         if !self.0.exists() {
             return false;
         }
 
-        let config = crate::config::parse_config_from_cli_args();
-
         let job_status = self.0.status();
-        if !config.server.expiry_check_statuses.contains(&job_status) {
+        if !statuses.contains(&job_status) {
             return false;
         }
 
@@ -343,7 +615,10 @@ impl ExpiryMeta {
         }
 
         if let Some(end_dt) = self.0.ended_at() {
-            let now = DateTime::now();
+            #[cfg(feature = "testing")]
+            let now = crate::application::chaos::expiry_now();
+            #[cfg(not(feature = "testing"))]
+            let now = clock::now();
             let duration_seconds = now.seconds_since(&end_dt);
             assert!(duration_seconds >= 0); // TODO: error handling here?
             if duration_seconds as u64 > expires_after_seconds {
@@ -362,6 +637,9 @@ pub enum RetryAction {
     /// Move job to ended queue, no further automatic retries are possible.
     End,
 
+    /// Move job to quarantine, as it has repeatedly timed out or failed shortly after starting.
+    Quarantine,
+
     /// Do nothing, as job has either already been moved/deleted, or has a retry delay.
     None,
 }
@@ -396,22 +674,47 @@ impl RetryMeta {
     }
 
     pub fn fields() -> &'static [Field] {
-        static FIELDS: [Field; 5] = [
+        static FIELDS: [Field; 11] = [
             Field::Id,
+            Field::Queue,
             Field::EndedAt,
             Field::Retries,
             Field::RetriesAttempted,
             Field::RetryDelays,
+            Field::RetryAfter,
+            Field::QuarantineAfter,
+            Field::PoisonStrikes,
+            Field::CreatedAt,
+            Field::TotalTimeout,
         ];
         &FIELDS
     }
 
+    /// Get the name of the queue this job was created in, or `None` if the job has been deleted.
+    pub fn queue(&self) -> Option<String> {
+        if self.0.exists() {
+            Some(self.0.queue())
+        } else {
+            None
+        }
+    }
+
+    pub fn poison_strikes(&self) -> u64 {
+        self.0.poison_strikes()
+    }
+
     pub fn retry_action(&self) -> RetryAction {
         // no retry metadata means that job has been deleted
         if !self.0.exists() {
             return RetryAction::None;
         }
 
+        // 0 means that quarantining is disabled for this job
+        let quarantine_after = self.0.quarantine_after();
+        if quarantine_after > 0 && self.0.poison_strikes() >= quarantine_after {
+            return RetryAction::Quarantine;
+        }
+
         // 0 means that retries are disabled for this job
         let retries = self.0.retries();
         if retries == 0 {
@@ -424,6 +727,19 @@ impl RetryMeta {
             return RetryAction::End;
         }
 
+        // no time left to retry in
+        if self.0.has_exceeded_total_timeout() {
+            return RetryAction::End;
+        }
+
+        // a delay given by the worker when failing the job takes precedence over the job's retry delays
+        if let Some(retry_after) = self.0.retry_after() {
+            if (clock::now().seconds_since(&self.0.ended_at().unwrap()) as u64) < retry_after.as_secs() {
+                return RetryAction::End;
+            }
+            return RetryAction::Retry;
+        }
+
         // check whether enough time has passed between retry delays
         if let Some(retry_delays) = self.0.retry_delays() {
             if !retry_delays.is_empty() {
@@ -434,7 +750,7 @@ impl RetryMeta {
                     retry_delays[retry_attempt - 1].as_secs()
                 };
 
-                if (DateTime::now().seconds_since(&self.0.ended_at().unwrap()) as u64) < delay_secs
+                if (clock::now().seconds_since(&self.0.ended_at().unwrap()) as u64) < delay_secs
                 {
                     return RetryAction::End;
                 }
@@ -444,3 +760,140 @@ impl RetryMeta {
         RetryAction::Retry
     }
 }
+
+/// Outcome of checking a job against its SLA deadline once the deadline has passed.
+#[derive(Debug, PartialEq)]
+pub enum SlaOutcome {
+    /// Job was completed or cancelled before its deadline.
+    Met,
+
+    /// Job hadn't been completed or cancelled by its deadline.
+    Breached,
+
+    /// Job no longer exists, or has no deadline.
+    None,
+}
+
+/// Subset of job data used for determining whether a job has breached its SLA.
+pub struct SlaMeta(JobMeta);
+
+impl FromRedisValue for SlaMeta {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        Ok(SlaMeta(JobMeta::from_redis_value(
+            SlaMeta::fields(),
+            v,
+            &[],
+        )?))
+    }
+}
+
+impl SlaMeta {
+    pub async fn from_conn<C, K>(conn: &mut C, key: K) -> redis::RedisResult<Self>
+    where
+        C: ConnectionLike + Send,
+        K: ToRedisArgs + Send + Sync,
+    {
+        let fields = SlaMeta::fields();
+        let v: redis::Value = conn.hget(key, fields).await?;
+        Ok(SlaMeta(JobMeta::from_redis_value(fields, &v, &[])?))
+    }
+
+    pub fn id(&self) -> u64 {
+        self.0.id()
+    }
+
+    /// Get the name of the queue this job was created in, or `None` if the job has been deleted.
+    pub fn queue(&self) -> Option<String> {
+        if self.0.exists() {
+            Some(self.0.queue())
+        } else {
+            None
+        }
+    }
+
+    /// Determine whether this job met its SLA, assuming that its deadline has passed.
+    pub fn sla_outcome(&self) -> SlaOutcome {
+        // no metadata means that job has been deleted
+        if !self.0.exists() {
+            return SlaOutcome::None;
+        }
+
+        let deadline = match self.0.deadline() {
+            Some(deadline) => deadline,
+            None => return SlaOutcome::None,
+        };
+
+        match (self.0.status(), self.0.ended_at()) {
+            (Status::Completed, Some(ended_at)) | (Status::Cancelled, Some(ended_at)) if ended_at <= deadline => {
+                SlaOutcome::Met
+            }
+            _ => SlaOutcome::Breached,
+        }
+    }
+
+    pub fn fields() -> &'static [Field] {
+        static FIELDS: [Field; 5] = [
+            Field::Id,
+            Field::Queue,
+            Field::Status,
+            Field::EndedAt,
+            Field::Deadline,
+        ];
+        &FIELDS
+    }
+}
+
+/// Reason recorded for jobs quarantined because they weren't started within their queue's `max_queue_age`.
+pub const EXPIRED_IN_QUEUE_REASON: &str = "expired_in_queue";
+
+/// Subset of job data used for determining whether a queued job has waited too long to be started.
+pub struct QueueAgeMeta(JobMeta);
+
+impl FromRedisValue for QueueAgeMeta {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        Ok(QueueAgeMeta(JobMeta::from_redis_value(
+            QueueAgeMeta::fields(),
+            v,
+            &[],
+        )?))
+    }
+}
+
+impl QueueAgeMeta {
+    pub async fn from_conn<C, K>(conn: &mut C, key: K) -> redis::RedisResult<Self>
+    where
+        C: ConnectionLike + Send,
+        K: ToRedisArgs + Send + Sync,
+    {
+        let fields = QueueAgeMeta::fields();
+        let v: redis::Value = conn.hget(key, fields).await?;
+        Ok(QueueAgeMeta(JobMeta::from_redis_value(fields, &v, &[])?))
+    }
+
+    pub fn id(&self) -> u64 {
+        self.0.id()
+    }
+
+    /// Determine whether this job is still queued, and has been since longer ago than given maximum age.
+    ///
+    /// Jobs queued before queue times were recorded are aged from when they were created.
+    pub fn has_expired_in_queue(&self, max_age: &Duration) -> bool {
+        // no metadata means that job has been deleted
+        if !self.0.exists() || self.0.status() != Status::Queued {
+            return false;
+        }
+
+        let queued_at = self.0.queued_at().unwrap_or_else(|| self.0.created_at());
+        elapsed(&queued_at, &clock::now()).as_secs() > max_age.as_secs()
+    }
+
+    pub fn fields() -> &'static [Field] {
+        static FIELDS: [Field; 4] = [
+            Field::Id,
+            Field::Status,
+            Field::CreatedAt,
+            Field::QueuedAt,
+        ];
+        &FIELDS
+    }
+}
@@ -1,7 +1,7 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Job definition delivered to clients when they take a job from a queue.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Payload {
     id: u64,
     input: Option<serde_json::Value>,
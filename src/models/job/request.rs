@@ -1,6 +1,25 @@
 use serde::{Serialize, Deserialize};
 
-use crate::models::{job::Status, Duration};
+use crate::models::job::{Field, JobMeta, Status};
+use crate::models::{DateTime, Duration};
+
+/// Fields needed to create a copy of an existing job with `CreateRequest::from_job`.
+pub const COPY_FIELDS: &[Field] = &[
+    Field::Input,
+    Field::Tags,
+    Field::Timeout,
+    Field::HeartbeatTimeout,
+    Field::ExpiresAfter,
+    Field::Retries,
+    Field::RetryDelays,
+    Field::QuarantineAfter,
+    Field::QuickFailWindow,
+    Field::TotalTimeout,
+    Field::Deadline,
+    Field::RoutingKey,
+    Field::SessionKey,
+    Field::SerializationKey,
+];
 
 /// Request to create a new job.
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -12,12 +31,19 @@ pub struct CreateRequest {
     /// of related jobs, adding a user/owner field to a job, labelling the host/process that created it, etc.
     pub tags: Option<Vec<String>>,
 
-    /// Total execution time of this job before it's marked as timed out. If not specified, then the queue's
-    /// timeout will be used.
+    /// Execution time of each attempt of this job before it's marked as timed out. If not specified, then the
+    /// queue's timeout will be used. Can also be given as `attempt_timeout`.
     ///
     /// Set to 0 for no timeout.
+    #[serde(alias = "attempt_timeout")]
     pub timeout: Option<Duration>,
 
+    /// Maximum time after creation, across all attempts and the delays between them, before this job is timed out
+    /// without being retried again. If not specified, then the queue's total timeout will be used.
+    ///
+    /// Set to 0 for no total timeout.
+    pub total_timeout: Option<Duration>,
+
     /// Maximum time between heartbeats before a job is marked as timed out. If not specified, then the queue's
     /// heartbeat timeout will be used.
     ///
@@ -45,14 +71,91 @@ pub struct CreateRequest {
     /// E.g. with retries=5 and retry_delays=[10, 10, 20, 40], then the first two retries will be delayed by
     /// 10 seconds, the third by 20 seconds, and the fourth and fifth by 40 seconds.
     pub retry_delays: Option<Vec<Duration>>,
+
+    /// Number of times this job can time out, or fail within `quick_fail_window` of starting, before it's
+    /// quarantined instead of being retried. If not specified, then the queue's quarantine setting will be used.
+    ///
+    /// Set to 0 to disable quarantining.
+    pub quarantine_after: Option<u64>,
+
+    /// Failures reported within this amount of time after a job starts count towards quarantining it. If not
+    /// specified, then the queue's quick fail window will be used.
+    pub quick_fail_window: Option<Duration>,
+
+    /// Time by which this job should have completed. Jobs that haven't completed by their deadline are marked as
+    /// having breached their SLA. If not specified, then the queue's SLA will be used to calculate a deadline
+    /// from the job's creation time.
+    pub deadline: Option<DateTime>,
+
+    /// URL the job's final status and output are POSTed to once it's ended, so that the submitter doesn't need to
+    /// poll for its result. Not copied to copies or shadows of the job.
+    pub callback_url: Option<String>,
+
+    /// Key routing this job to workers able to run it, e.g. those with a GPU, or access to a data shard. Jobs with a
+    /// routing key are only given to workers that advertise it when getting their next job.
+    pub routing_key: Option<String>,
+
+    /// Key of the session this job belongs to. Jobs in the same session are run one at a time, and preferably by the
+    /// worker that ran the session's previous job, so that it can reuse any state it kept for the session.
+    pub session_key: Option<String>,
+
+    /// Key of the group this job is serialized with. Jobs with the same serialization key never run at the same time,
+    /// each waiting in the queue until the previous one has ended.
+    pub serialization_key: Option<String>,
+
+    /// Key identifying the work this job does, e.g. a hash of its input. While a job with the same unique key on the
+    /// queue hasn't ended, that job is returned instead of creating a new one. Not copied to copies or shadows of the
+    /// job.
+    pub unique_key: Option<String>,
+
+    /// Time within which a completed job with the same unique key is returned, along with its output, instead of
+    /// creating a new job. If not specified, completed jobs are never returned instead.
+    pub reuse_completed_within: Option<Duration>,
+}
+
+impl CreateRequest {
+    /// Create a request for a new job with the same input and settings as an existing job, fetched with
+    /// `COPY_FIELDS`.
+    pub fn from_job(job: &JobMeta) -> Self {
+        Self {
+            input: job.input(),
+            tags: job.tags(),
+            timeout: Some(job.timeout()),
+            heartbeat_timeout: Some(job.heartbeat_timeout()),
+            expires_after: Some(job.expires_after()),
+            retries: Some(job.retries()),
+            retry_delays: job.retry_delays(),
+            quarantine_after: Some(job.quarantine_after()),
+            quick_fail_window: Some(job.quick_fail_window()),
+            total_timeout: job.total_timeout(),
+            deadline: job.deadline(),
+            callback_url: None,
+            routing_key: job.routing_key(),
+            session_key: job.session_key(),
+            serialization_key: job.serialization_key(),
+            unique_key: None,
+            reuse_completed_within: None,
+        }
+    }
 }
 
 /// Request to update an existing job with new data.
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct UpdateRequest {
     /// The new status of the job.
     pub status: Option<Status>,
 
     /// The new output JSON to store as part of the job.
     pub output: Option<serde_json::Value>,
+
+    /// Machine readable code identifying why the job failed, e.g. `"connection_refused"`. Can only be given when
+    /// failing a job.
+    pub error_code: Option<String>,
+
+    /// Any further structured information about why the job failed. Can only be given when failing a job.
+    pub error_details: Option<serde_json::Value>,
+
+    /// Minimum time to wait before retrying the job, overriding its retry delays for this attempt, e.g. when an
+    /// upstream service is rate limiting the worker. Can only be given when failing a job.
+    pub retry_after: Option<Duration>,
 }
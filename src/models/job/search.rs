@@ -0,0 +1,90 @@
+//! Defines structs used to search for jobs across all queues.
+
+use serde::{Deserialize, Serialize};
+
+use super::{Field, JobMeta, Status};
+use crate::models::Tenant;
+
+/// Default number of jobs returned by a search.
+pub const DEFAULT_SEARCH_LIMIT: usize = 100;
+
+/// Maximum number of jobs that can be returned by a single search.
+pub const MAX_SEARCH_LIMIT: usize = 1000;
+
+/// Fields returned for each job found by a search.
+pub const SEARCH_FIELDS: &[Field] = &[
+    Field::Id,
+    Field::Queue,
+    Field::Status,
+    Field::Tags,
+    Field::CreatedAt,
+    Field::StartedAt,
+    Field::EndedAt,
+];
+
+/// Criteria to search for jobs by, all of which must match.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct SearchQuery {
+    pub status: Option<Status>,
+    pub tag: Option<String>,
+    pub queue: Option<String>,
+
+    /// Maximum number of jobs to return, defaults to `DEFAULT_SEARCH_LIMIT`.
+    pub limit: Option<usize>,
+
+    /// Only return jobs with IDs lower than this, used to get the next page of results.
+    pub cursor: Option<u64>,
+
+    /// Only return jobs on queues in this namespace, set from the tenant making the search rather than the query.
+    #[serde(skip)]
+    pub namespace: Option<String>,
+}
+
+impl SearchQuery {
+    /// Get the maximum number of jobs to return, capped at `MAX_SEARCH_LIMIT`.
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT)
+    }
+
+    /// Check whether a job on given queue with given status matches this search.
+    pub fn matches(&self, queue: &str, status: &Status) -> bool {
+        self.queue.as_ref().is_none_or(|q| q == queue)
+            && self.status.as_ref().is_none_or(|s| s == status)
+            && self.namespace.as_ref().is_none_or(|ns| Tenant::with_namespace(ns.as_str()).owns(queue))
+    }
+}
+
+/// Page of jobs found by a search, newest first.
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    pub jobs: Vec<JobMeta>,
+
+    /// Cursor to pass to get the next page of results, `None` if there are no more results.
+    pub next_cursor: Option<u64>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn search_query() {
+        let query: SearchQuery = serde_json::from_str(r#"{"status": "failed", "queue": "emails", "limit": 5000}"#).unwrap();
+        assert_eq!(query.limit(), MAX_SEARCH_LIMIT);
+        assert!(query.matches("emails", &Status::Failed));
+        assert!(!query.matches("emails", &Status::Completed));
+        assert!(!query.matches("other", &Status::Failed));
+
+        let query = SearchQuery::default();
+        assert_eq!(query.limit(), DEFAULT_SEARCH_LIMIT);
+        assert!(query.matches("other", &Status::Queued));
+
+        let query = SearchQuery {
+            namespace: Some("team-a".to_owned()),
+            ..Default::default()
+        };
+        assert!(query.matches("team-a.emails", &Status::Queued));
+        assert!(!query.matches("team-b.emails", &Status::Queued));
+        assert!(!query.matches("emails", &Status::Queued));
+    }
+}
@@ -12,6 +12,7 @@ const FAILED_STATUS: &str = "failed";
 const COMPLETED_STATUS: &str = "completed";
 const CANCELLED_STATUS: &str = "cancelled";
 const TIMED_OUT_STATUS: &str = "timed_out";
+const QUARANTINED_STATUS: &str = "quarantined";
 
 /// Status of a job that exists in Redis.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -34,15 +35,20 @@ pub enum Status {
 
     /// Job was marked as timed out by server, due to no completion or heartbeat by worker.
     TimedOut,
+
+    /// Job repeatedly timed out or failed shortly after starting, and was set aside by the server rather than
+    /// being retried again.
+    Quarantined,
 }
 
-pub const ALL_STATUSES: [Status; 6] = [
+pub const ALL_STATUSES: [Status; 7] = [
     Status::Queued,
     Status::Running,
     Status::Failed,
     Status::Completed,
     Status::Cancelled,
     Status::TimedOut,
+    Status::Quarantined,
 ];
 
 impl fmt::Display for Status {
@@ -60,6 +66,7 @@ impl AsRef<str> for Status {
             Status::Completed => COMPLETED_STATUS,
             Status::Cancelled => CANCELLED_STATUS,
             Status::TimedOut => TIMED_OUT_STATUS,
+            Status::Quarantined => QUARANTINED_STATUS,
         }
     }
 }
@@ -75,6 +82,7 @@ impl FromStr for Status {
             COMPLETED_STATUS => Ok(Status::Completed),
             CANCELLED_STATUS => Ok(Status::Cancelled),
             TIMED_OUT_STATUS => Ok(Status::TimedOut),
+            QUARANTINED_STATUS => Ok(Status::Quarantined),
             _ => Err(()),
         }
     }
@@ -93,7 +101,7 @@ impl FromRedisValue for Status {
     }
 }
 
-impl<'a> ToRedisArgs for &'a Status {
+impl ToRedisArgs for &Status {
     fn write_redis_args<W: ?Sized + RedisWrite>(&self, out: &mut W) {
         self.as_ref().write_redis_args(out)
     }
@@ -138,5 +146,9 @@ mod test {
             serde_json::to_string(&Status::TimedOut).unwrap(),
             "\"timed_out\""
         );
+        assert_eq!(
+            serde_json::to_string(&Status::Quarantined).unwrap(),
+            "\"quarantined\""
+        );
     }
 }
@@ -0,0 +1,99 @@
+//! Defines the changes of status clients can make to jobs, and the checks applied to them.
+
+use serde::{Deserialize, Serialize};
+
+use super::Status;
+use crate::models::{OcyError, OcyResult};
+
+/// Every status transition clients can request, by updating a job's status or retrying it. Queues can restrict
+/// their jobs to a subset of these with their `allowed_transitions` setting.
+const VALID_TRANSITIONS: [(Status, Status); 11] = [
+    (Status::Running, Status::Completed),
+    (Status::Running, Status::Failed),
+    (Status::Running, Status::TimedOut),
+    (Status::Running, Status::Cancelled),
+    (Status::Queued, Status::Cancelled),
+    (Status::Failed, Status::Cancelled),
+    (Status::Quarantined, Status::Cancelled),
+    (Status::Failed, Status::Queued),
+    (Status::TimedOut, Status::Queued),
+    (Status::Cancelled, Status::Queued),
+    (Status::Quarantined, Status::Queued),
+];
+
+/// Change of a job's status requested by a client.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transition {
+    pub from: Status,
+    pub to: Status,
+}
+
+impl Transition {
+    pub fn new(from: Status, to: Status) -> Self {
+        Self { from, to }
+    }
+
+    /// Check whether this transition is one clients can ever request, regardless of any queue's restrictions.
+    pub fn is_valid(&self) -> bool {
+        VALID_TRANSITIONS.iter().any(|(from, to)| from == &self.from && to == &self.to)
+    }
+
+    /// Check that this transition can be made for a job on a queue that only allows given transitions, or allows
+    /// all valid transitions if `None`.
+    pub fn check(&self, allowed: Option<&[Transition]>) -> OcyResult<()> {
+        if !self.is_valid() {
+            return Err(OcyError::conflict(format!("Cannot change status from {} to {}", self.from, self.to)));
+        }
+        match allowed {
+            Some(allowed) if !allowed.contains(self) => Err(OcyError::conflict(format!(
+                "Job's queue doesn't allow changing status from {} to {}",
+                self.from, self.to
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Check that every transition a queue allows is a valid transition.
+pub fn check_allowed_transitions(allowed: &[Transition]) -> OcyResult<()> {
+    match allowed.iter().find(|transition| !transition.is_valid()) {
+        Some(transition) => Err(OcyError::bad_request(format!(
+            "Invalid allowed_transitions, can't change status from {} to {}",
+            transition.from, transition.to
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checks() {
+        let requeue = Transition::new(Status::Failed, Status::Queued);
+        assert!(requeue.check(None).is_ok());
+        assert!(requeue.check(Some(std::slice::from_ref(&requeue))).is_ok());
+        match requeue.check(Some(&[Transition::new(Status::Running, Status::Cancelled)])) {
+            Err(OcyError::Conflict(msg)) => assert!(msg.contains("queue doesn't allow"), "{}", msg),
+            x => panic!("Unexpected result checking disallowed transition: {:?}", x),
+        }
+
+        let resurrect = Transition::new(Status::Completed, Status::Queued);
+        assert!(!resurrect.is_valid());
+        assert!(resurrect.check(None).is_err());
+        assert!(resurrect.check(Some(std::slice::from_ref(&resurrect))).is_err());
+    }
+
+    #[test]
+    fn allowed_transitions() {
+        assert!(check_allowed_transitions(&[]).is_ok());
+        assert!(check_allowed_transitions(&[Transition::new(Status::Running, Status::Completed)]).is_ok());
+        assert!(check_allowed_transitions(&[Transition::new(Status::Completed, Status::Queued)]).is_err());
+
+        let transition: Transition = serde_json::from_str(r#"{"from": "timed_out", "to": "queued"}"#).unwrap();
+        assert_eq!(transition, Transition::new(Status::TimedOut, Status::Queued));
+        assert!(serde_json::from_str::<Transition>(r#"{"from": "failed", "to": "queued", "x": 1}"#).is_err());
+    }
+}
@@ -1,16 +1,28 @@
 //! Data structures used throughout the application.
 
+mod cidr;
 mod datetime;
 mod duration;
 mod error;
+mod features;
+mod integrity;
 pub mod job;
 pub mod queue;
+pub mod quota;
 mod state;
+pub mod tag;
+mod tenant;
+mod worker;
 
+pub use cidr::Cidr;
 pub use datetime::DateTime;
 pub use duration::Duration;
 pub use error::{OcyError, OcyResult};
+pub use features::Features;
+pub use integrity::IntegrityReport;
 pub use state::ApplicationState;
+pub use tenant::{Role, Tenant, NAMESPACE_SEPARATOR};
+pub use worker::WorkerStatus;
 
 use std::collections::HashMap;
 
@@ -18,22 +30,23 @@ use redis::{self, FromRedisValue, RedisResult};
 use serde::Serialize;
 
 // TODO: add redis stats, e.g. memory used etc.
-#[derive(Debug, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
 pub struct ServerInfo {
     pub queues: HashMap<String, QueueInfo>,
     pub statistics: JobStats,
 }
 
-impl Default for ServerInfo {
-    fn default() -> Self {
-        ServerInfo {
-            queues: HashMap::new(),
-            statistics: JobStats::default(),
+impl ServerInfo {
+    /// Add information from another Redis shard to this.
+    pub fn merge(&mut self, other: ServerInfo) {
+        for (queue_name, queue_info) in other.queues {
+            self.queues.entry(queue_name).or_default().merge(&queue_info);
         }
+        self.statistics.merge(&other.statistics);
     }
 }
 
-#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
 pub struct QueueInfo {
     pub queued: u64,
     pub running: u64,
@@ -41,6 +54,8 @@ pub struct QueueInfo {
     pub completed: u64,
     pub cancelled: u64,
     pub timed_out: u64,
+    pub quarantined: u64,
+    pub held: u64,
 }
 
 impl QueueInfo {
@@ -52,11 +67,24 @@ impl QueueInfo {
             job::Status::Completed => self.completed += 1,
             job::Status::Cancelled => self.cancelled += 1,
             job::Status::TimedOut => self.timed_out += 1,
+            job::Status::Quarantined => self.quarantined += 1,
         }
     }
+
+    /// Add counts for the same queue from another Redis shard to this.
+    pub fn merge(&mut self, other: &QueueInfo) {
+        self.queued += other.queued;
+        self.running += other.running;
+        self.failed += other.failed;
+        self.completed += other.completed;
+        self.cancelled += other.cancelled;
+        self.timed_out += other.timed_out;
+        self.quarantined += other.quarantined;
+        self.held += other.held;
+    }
 }
 
-#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
 pub struct JobStats {
     pub total_jobs_created: u64,
     pub total_jobs_completed: u64,
@@ -64,11 +92,27 @@ pub struct JobStats {
     pub total_jobs_failed: u64,
     pub total_jobs_timed_out: u64,
     pub total_jobs_cancelled: u64,
+    pub total_sla_breaches: u64,
+}
+
+impl JobStats {
+    /// Add statistics from another Redis shard to this.
+    pub fn merge(&mut self, other: &JobStats) {
+        self.total_jobs_created += other.total_jobs_created;
+        self.total_jobs_completed += other.total_jobs_completed;
+        self.total_jobs_retried += other.total_jobs_retried;
+        self.total_jobs_failed += other.total_jobs_failed;
+        self.total_jobs_timed_out += other.total_jobs_timed_out;
+        self.total_jobs_cancelled += other.total_jobs_cancelled;
+        self.total_sla_breaches += other.total_sla_breaches;
+    }
 }
 
 impl FromRedisValue for JobStats {
+    #[allow(clippy::type_complexity)]
     fn from_redis_value(v: &redis::Value) -> RedisResult<Self> {
-        let (created, completed, retried, failed, timed_out, cancelled): (
+        let (created, completed, retried, failed, timed_out, cancelled, sla_breaches): (
+            Option<u64>,
             Option<u64>,
             Option<u64>,
             Option<u64>,
@@ -84,6 +128,7 @@ impl FromRedisValue for JobStats {
             total_jobs_failed: failed.unwrap_or_default(),
             total_jobs_timed_out: timed_out.unwrap_or_default(),
             total_jobs_cancelled: cancelled.unwrap_or_default(),
+            total_sla_breaches: sla_breaches.unwrap_or_default(),
         })
     }
 }
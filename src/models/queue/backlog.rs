@@ -0,0 +1,17 @@
+//! Defines the minimal view of a queue's backlog polled by autoscalers.
+
+use serde::Serialize;
+
+/// Numbers of a queue's queued and running jobs, and how long the oldest queued job has been waiting, for deciding
+/// how many workers a queue needs.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Backlog {
+    /// Number of jobs waiting to be taken from the queue.
+    pub queued: u64,
+
+    /// Number of jobs taken from the queue that are still running.
+    pub running: u64,
+
+    /// Seconds since the next job to be taken from the queue was created, 0 if no jobs are queued.
+    pub oldest_age_seconds: u64,
+}
@@ -0,0 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+/// Callback URL registered by workers to have jobs pushed to them, rather than polling for them.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Callback {
+    pub url: String,
+}
@@ -0,0 +1,15 @@
+//! Defines the request used to create a new queue from an existing one.
+
+use serde::Deserialize;
+
+/// Request to create a new queue with the same settings as an existing queue.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CloneRequest {
+    /// Name of the new queue.
+    pub name: String,
+
+    /// Whether to copy the existing queue's queued jobs to the new queue, as new jobs.
+    #[serde(default)]
+    pub include_jobs: bool,
+}
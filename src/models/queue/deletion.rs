@@ -0,0 +1,23 @@
+//! Defines the outcome of requests to delete a queue.
+
+use serde::Serialize;
+
+/// Outcome of a request to delete a queue.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Deletion {
+    /// Queue was deleted, along with any jobs queued on it.
+    Deleted(DeletedJobs),
+
+    /// No queue with given name exists.
+    NotFound,
+
+    /// Queue wasn't deleted because it still has queued jobs, and deletion wasn't forced.
+    NotEmpty(DeletedJobs),
+}
+
+/// Numbers of jobs destroyed by deleting a queue, or that would be destroyed if deletion is forced.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct DeletedJobs {
+    /// Number of jobs waiting to be taken from the queue.
+    pub queued: u64,
+}
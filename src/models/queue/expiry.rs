@@ -0,0 +1,114 @@
+//! Defines structs used to decide which ended jobs are checked for expiry.
+
+use std::collections::HashMap;
+use std::time;
+
+use super::{Batching, Settings};
+use crate::models::job;
+
+/// A queue's expiry settings, after merging any per-queue overrides with server-wide defaults.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExpiryPolicy {
+    /// Statuses of ended jobs that expire.
+    pub statuses: Vec<job::Status>,
+
+    /// How often ended jobs are checked for expiry.
+    pub check_interval: time::Duration,
+}
+
+impl ExpiryPolicy {
+    /// Get the expiry policy for a queue with given settings, falling back to given defaults for anything the queue
+    /// doesn't override.
+    pub fn merge(settings: &Settings, default_statuses: &[job::Status], default_interval: time::Duration) -> Self {
+        Self {
+            statuses: settings
+                .expiry_check_statuses
+                .clone()
+                .unwrap_or_else(|| default_statuses.to_vec()),
+            check_interval: settings
+                .expiry_check_interval
+                .as_ref()
+                .map_or(default_interval, |d| d.0),
+        }
+    }
+}
+
+/// Statuses of jobs to expire during a single expiry check, by queue.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExpirySweep {
+    /// Statuses to expire for each known queue, or `None` for queues that aren't due to be checked.
+    pub queues: HashMap<String, Option<Vec<job::Status>>>,
+
+    /// Statuses to expire for jobs on any other queue (e.g. one that's since been deleted), or `None` if these
+    /// jobs aren't due to be checked.
+    pub default: Option<Vec<job::Status>>,
+
+    /// Limits on how many jobs are checked at once, and for how long.
+    pub batching: Batching,
+}
+
+impl ExpirySweep {
+    /// Get a sweep that checks jobs on all queues, expiring jobs with any of given statuses.
+    pub fn all(statuses: Vec<job::Status>) -> Self {
+        Self {
+            queues: HashMap::new(),
+            default: Some(statuses),
+            batching: Batching::default(),
+        }
+    }
+
+    /// Get statuses to expire for jobs on given queue, or `None` if the queue isn't due to be checked.
+    pub fn statuses(&self, queue: &str) -> Option<&[job::Status]> {
+        match self.queues.get(queue) {
+            Some(statuses) => statuses.as_deref(),
+            None => self.default.as_deref(),
+        }
+    }
+
+    /// Check whether any jobs are due to be checked by this sweep.
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.queues.values().all(Option::is_none)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::Duration;
+
+    #[test]
+    fn merge() {
+        let default_statuses = [job::Status::Completed, job::Status::Cancelled];
+        let default_interval = time::Duration::from_secs(300);
+
+        let policy = ExpiryPolicy::merge(&Settings::default(), &default_statuses, default_interval);
+        assert_eq!(policy.statuses, default_statuses.to_vec());
+        assert_eq!(policy.check_interval, default_interval);
+
+        let settings = Settings {
+            expiry_check_statuses: Some(vec![job::Status::Completed]),
+            expiry_check_interval: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        let policy = ExpiryPolicy::merge(&settings, &default_statuses, default_interval);
+        assert_eq!(policy.statuses, vec![job::Status::Completed]);
+        assert_eq!(policy.check_interval, time::Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn sweep_statuses() {
+        let mut sweep = ExpirySweep::default();
+        assert!(sweep.is_empty());
+        sweep.queues.insert("a".to_owned(), Some(vec![job::Status::Failed]));
+        sweep.queues.insert("b".to_owned(), None);
+        assert!(!sweep.is_empty());
+
+        assert_eq!(sweep.statuses("a"), Some(&[job::Status::Failed][..]));
+        assert_eq!(sweep.statuses("b"), None);
+        assert_eq!(sweep.statuses("deleted"), None);
+
+        sweep.default = Some(vec![job::Status::Completed]);
+        assert_eq!(sweep.statuses("b"), None);
+        assert_eq!(sweep.statuses("deleted"), Some(&[job::Status::Completed][..]));
+    }
+}
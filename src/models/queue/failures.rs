@@ -0,0 +1,130 @@
+//! Defines structs used to summarise why a queue's jobs failed.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/// Maximum number of example job IDs given for each failure reason.
+const MAX_SAMPLE_JOBS: usize = 5;
+
+/// Maximum length of a failure reason, longer reasons are truncated.
+const MAX_REASON_LEN: usize = 200;
+
+/// Reason given for failures that didn't set any output.
+const NO_OUTPUT_REASON: &str = "(no output)";
+
+/// Failed jobs on a queue, grouped by the reason they failed.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct FailureSummary {
+    /// Total number of failed jobs.
+    pub total: u64,
+
+    /// Failure reasons, most common first.
+    pub reasons: Vec<FailureReason>,
+}
+
+/// Number of jobs that failed for the same reason, along with a sample of them.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FailureReason {
+    pub reason: String,
+    pub count: u64,
+    pub sample_job_ids: Vec<u64>,
+}
+
+impl FailureSummary {
+    /// Group failed jobs by reason, given each job's ID, error code, and output.
+    pub fn from_failures<I>(failures: I) -> Self
+    where
+        I: IntoIterator<Item = (u64, Option<String>, Option<serde_json::Value>)>,
+    {
+        let mut summary = Self::default();
+        let mut indexes: HashMap<String, usize> = HashMap::new();
+        for (job_id, error_code, output) in failures {
+            summary.total += 1;
+            let reason = match error_code {
+                Some(error_code) => error_code,
+                None => failure_reason(output.as_ref()),
+            };
+            let idx = *indexes.entry(reason.clone()).or_insert_with(|| {
+                summary.reasons.push(FailureReason {
+                    reason,
+                    count: 0,
+                    sample_job_ids: Vec::new(),
+                });
+                summary.reasons.len() - 1
+            });
+
+            let failure_reason = &mut summary.reasons[idx];
+            failure_reason.count += 1;
+            if failure_reason.sample_job_ids.len() < MAX_SAMPLE_JOBS {
+                failure_reason.sample_job_ids.push(job_id);
+            }
+        }
+
+        // stable sort, so reasons with equal counts stay in order of first occurrence
+        summary.reasons.sort_by_key(|reason| Reverse(reason.count));
+        summary
+    }
+}
+
+/// Get the reason a job that failed without an error code failed from its output.
+///
+/// This is either the output itself if it's a string, or its `error` field if it's an object with one, otherwise the
+/// output's JSON.
+fn failure_reason(output: Option<&serde_json::Value>) -> String {
+    let reason = match output {
+        None | Some(serde_json::Value::Null) => return NO_OUTPUT_REASON.to_owned(),
+        Some(serde_json::Value::String(s)) => s.trim().to_owned(),
+        Some(serde_json::Value::Object(map)) => match map.get("error") {
+            Some(serde_json::Value::String(s)) => s.trim().to_owned(),
+            _ => serde_json::Value::Object(map.clone()).to_string(),
+        },
+        Some(value) => value.to_string(),
+    };
+    match reason.char_indices().nth(MAX_REASON_LEN) {
+        Some((idx, _)) => reason[..idx].to_owned(),
+        None => reason,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reasons() {
+        assert_eq!(failure_reason(None), NO_OUTPUT_REASON);
+        assert_eq!(failure_reason(Some(&json!(" connection refused\n"))), "connection refused");
+        assert_eq!(failure_reason(Some(&json!({"error": "timeout", "attempt": 2}))), "timeout");
+        assert_eq!(failure_reason(Some(&json!({"code": 500}))), r#"{"code":500}"#);
+        assert_eq!(failure_reason(Some(&json!("x".repeat(500)))).len(), MAX_REASON_LEN);
+    }
+
+    #[test]
+    fn summary() {
+        let failures = (1..=8).map(|job_id| match job_id % 4 {
+            0 => (job_id, Some("bad_input".to_owned()), None),
+            _ => (job_id, None, Some(json!({"error": "connection refused"}))),
+        });
+        let summary = FailureSummary::from_failures(failures);
+        assert_eq!(summary.total, 8);
+        assert_eq!(
+            summary.reasons,
+            vec![
+                FailureReason {
+                    reason: "connection refused".to_owned(),
+                    count: 6,
+                    sample_job_ids: vec![1, 2, 3, 5, 6],
+                },
+                FailureReason {
+                    reason: "bad_input".to_owned(),
+                    count: 2,
+                    sample_job_ids: vec![4, 8],
+                },
+            ]
+        );
+    }
+}
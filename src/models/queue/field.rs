@@ -7,6 +7,27 @@ const HEARTBEAT_TIMEOUT_FIELD: &str = "heartbeat_timeout";
 const EXPIRES_AFTER_FIELD: &str = "expires_after";
 const RETRIES_FIELD: &str = "retries";
 const RETRY_DELAYS_FIELD: &str = "retry_delays";
+const QUARANTINE_AFTER_FIELD: &str = "quarantine_after";
+const QUICK_FAIL_WINDOW_FIELD: &str = "quick_fail_window";
+const EXPIRY_CHECK_STATUSES_FIELD: &str = "expiry_check_statuses";
+const EXPIRY_CHECK_INTERVAL_FIELD: &str = "expiry_check_interval";
+const TIMEOUT_CHECK_INTERVAL_FIELD: &str = "timeout_check_interval";
+const RETRY_CHECK_INTERVAL_FIELD: &str = "retry_check_interval";
+const SLA_FIELD: &str = "sla";
+const TOTAL_TIMEOUT_FIELD: &str = "total_timeout";
+const MAX_INPUT_SIZE_FIELD: &str = "max_input_size";
+const MAX_OUTPUT_SIZE_FIELD: &str = "max_output_size";
+const OUTPUT_SIZE_POLICY_FIELD: &str = "output_size_policy";
+const ALLOWED_TRANSITIONS_FIELD: &str = "allowed_transitions";
+const RETRY_BUDGET_FIELD: &str = "retry_budget";
+const RETRY_BUDGET_COOLDOWN_FIELD: &str = "retry_budget_cooldown";
+const PAUSED_UNTIL_FIELD: &str = "paused_until";
+const SHADOW_TO_FIELD: &str = "shadow_to";
+const SHADOW_PERCENT_FIELD: &str = "shadow_percent";
+const RESULT_TTL_FIELD: &str = "result_ttl";
+const MAX_QUEUE_AGE_FIELD: &str = "max_queue_age";
+const LAST_JOB_AT_FIELD: &str = "last_job_at";
+const CALLBACK_URL_FIELD: &str = "callback_url";
 
 #[derive(Debug, PartialEq)]
 pub enum Field {
@@ -15,6 +36,27 @@ pub enum Field {
     ExpiresAfter,
     Retries,
     RetryDelays,
+    QuarantineAfter,
+    QuickFailWindow,
+    ExpiryCheckStatuses,
+    ExpiryCheckInterval,
+    TimeoutCheckInterval,
+    RetryCheckInterval,
+    Sla,
+    TotalTimeout,
+    MaxInputSize,
+    MaxOutputSize,
+    OutputSizePolicy,
+    AllowedTransitions,
+    RetryBudget,
+    RetryBudgetCooldown,
+    PausedUntil,
+    ShadowTo,
+    ShadowPercent,
+    ResultTtl,
+    MaxQueueAge,
+    LastJobAt,
+    CallbackUrl,
 }
 
 impl fmt::Display for Field {
@@ -31,6 +73,27 @@ impl AsRef<str> for Field {
             Field::ExpiresAfter => EXPIRES_AFTER_FIELD,
             Field::Retries => RETRIES_FIELD,
             Field::RetryDelays => RETRY_DELAYS_FIELD,
+            Field::QuarantineAfter => QUARANTINE_AFTER_FIELD,
+            Field::QuickFailWindow => QUICK_FAIL_WINDOW_FIELD,
+            Field::ExpiryCheckStatuses => EXPIRY_CHECK_STATUSES_FIELD,
+            Field::ExpiryCheckInterval => EXPIRY_CHECK_INTERVAL_FIELD,
+            Field::TimeoutCheckInterval => TIMEOUT_CHECK_INTERVAL_FIELD,
+            Field::RetryCheckInterval => RETRY_CHECK_INTERVAL_FIELD,
+            Field::Sla => SLA_FIELD,
+            Field::TotalTimeout => TOTAL_TIMEOUT_FIELD,
+            Field::MaxInputSize => MAX_INPUT_SIZE_FIELD,
+            Field::MaxOutputSize => MAX_OUTPUT_SIZE_FIELD,
+            Field::OutputSizePolicy => OUTPUT_SIZE_POLICY_FIELD,
+            Field::AllowedTransitions => ALLOWED_TRANSITIONS_FIELD,
+            Field::RetryBudget => RETRY_BUDGET_FIELD,
+            Field::RetryBudgetCooldown => RETRY_BUDGET_COOLDOWN_FIELD,
+            Field::PausedUntil => PAUSED_UNTIL_FIELD,
+            Field::ShadowTo => SHADOW_TO_FIELD,
+            Field::ShadowPercent => SHADOW_PERCENT_FIELD,
+            Field::ResultTtl => RESULT_TTL_FIELD,
+            Field::MaxQueueAge => MAX_QUEUE_AGE_FIELD,
+            Field::LastJobAt => LAST_JOB_AT_FIELD,
+            Field::CallbackUrl => CALLBACK_URL_FIELD,
         }
     }
 }
@@ -45,6 +108,27 @@ impl FromStr for Field {
             EXPIRES_AFTER_FIELD => Ok(Field::ExpiresAfter),
             RETRIES_FIELD => Ok(Field::Retries),
             RETRY_DELAYS_FIELD => Ok(Field::RetryDelays),
+            QUARANTINE_AFTER_FIELD => Ok(Field::QuarantineAfter),
+            QUICK_FAIL_WINDOW_FIELD => Ok(Field::QuickFailWindow),
+            EXPIRY_CHECK_STATUSES_FIELD => Ok(Field::ExpiryCheckStatuses),
+            EXPIRY_CHECK_INTERVAL_FIELD => Ok(Field::ExpiryCheckInterval),
+            TIMEOUT_CHECK_INTERVAL_FIELD => Ok(Field::TimeoutCheckInterval),
+            RETRY_CHECK_INTERVAL_FIELD => Ok(Field::RetryCheckInterval),
+            SLA_FIELD => Ok(Field::Sla),
+            TOTAL_TIMEOUT_FIELD => Ok(Field::TotalTimeout),
+            MAX_INPUT_SIZE_FIELD => Ok(Field::MaxInputSize),
+            MAX_OUTPUT_SIZE_FIELD => Ok(Field::MaxOutputSize),
+            OUTPUT_SIZE_POLICY_FIELD => Ok(Field::OutputSizePolicy),
+            ALLOWED_TRANSITIONS_FIELD => Ok(Field::AllowedTransitions),
+            RETRY_BUDGET_FIELD => Ok(Field::RetryBudget),
+            RETRY_BUDGET_COOLDOWN_FIELD => Ok(Field::RetryBudgetCooldown),
+            PAUSED_UNTIL_FIELD => Ok(Field::PausedUntil),
+            SHADOW_TO_FIELD => Ok(Field::ShadowTo),
+            SHADOW_PERCENT_FIELD => Ok(Field::ShadowPercent),
+            RESULT_TTL_FIELD => Ok(Field::ResultTtl),
+            MAX_QUEUE_AGE_FIELD => Ok(Field::MaxQueueAge),
+            LAST_JOB_AT_FIELD => Ok(Field::LastJobAt),
+            CALLBACK_URL_FIELD => Ok(Field::CallbackUrl),
             _ => Err(()),
         }
     }
@@ -76,6 +160,27 @@ mod test {
             Field::ExpiresAfter,
             Field::Retries,
             Field::RetryDelays,
+            Field::QuarantineAfter,
+            Field::QuickFailWindow,
+            Field::ExpiryCheckStatuses,
+            Field::ExpiryCheckInterval,
+            Field::TimeoutCheckInterval,
+            Field::RetryCheckInterval,
+            Field::Sla,
+            Field::TotalTimeout,
+            Field::MaxInputSize,
+            Field::MaxOutputSize,
+            Field::OutputSizePolicy,
+            Field::AllowedTransitions,
+            Field::RetryBudget,
+            Field::RetryBudgetCooldown,
+            Field::PausedUntil,
+            Field::ShadowTo,
+            Field::ShadowPercent,
+            Field::ResultTtl,
+            Field::MaxQueueAge,
+            Field::LastJobAt,
+            Field::CallbackUrl,
         ];
 
         for field in all_fields {
@@ -0,0 +1,260 @@
+//! Defines estimates of how long jobs wait on a queue before they're started, based on recently started jobs, and how
+//! long they take to run, based on recently completed jobs.
+
+use serde::Serialize;
+
+use crate::models::Duration;
+
+/// Maximum number of recent job starts, and of recent completed runs, recorded for each queue, older records are
+/// discarded.
+pub const MAX_RECORDED_STARTS: usize = 1000;
+
+/// Window recent job starts are considered over if none is given.
+pub const DEFAULT_LATENCY_WINDOW: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Record of a job being taken from a queue, stored in Redis as
+/// `<started_at timestamp>:<wait in seconds>:<enqueue to start in milliseconds>`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Start {
+    /// Unix timestamp the job was started at.
+    pub started_at: i64,
+
+    /// Number of seconds between the job being created and started.
+    pub wait: u64,
+
+    /// Number of milliseconds between the job last being put on the queue and started, `None` for starts recorded
+    /// before this was.
+    pub enqueue_to_start_ms: Option<u64>,
+}
+
+impl Start {
+    /// Get the form this start is stored in.
+    pub fn to_record(self) -> String {
+        match self.enqueue_to_start_ms {
+            Some(ms) => format!("{}:{}:{}", self.started_at, self.wait, ms),
+            None => format!("{}:{}", self.started_at, self.wait),
+        }
+    }
+
+    /// Parse a stored start, returning `None` if it's invalid.
+    pub fn from_record(record: &str) -> Option<Self> {
+        let mut parts = record.splitn(3, ':');
+        let started_at = parts.next()?.parse().ok()?;
+        let wait = parts.next()?.parse().ok()?;
+        let enqueue_to_start_ms = match parts.next() {
+            Some(ms) => Some(ms.parse().ok()?),
+            None => None,
+        };
+        Some(Self { started_at, wait, enqueue_to_start_ms })
+    }
+}
+
+/// Record of a job completing, stored in Redis as `<ended_at timestamp>:<start to end in milliseconds>`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Run {
+    /// Unix timestamp the job ended at.
+    pub ended_at: i64,
+
+    /// Number of milliseconds between the job's last attempt starting and ending.
+    pub start_to_end_ms: u64,
+}
+
+impl Run {
+    /// Get the form this run is stored in.
+    pub fn to_record(self) -> String {
+        format!("{}:{}", self.ended_at, self.start_to_end_ms)
+    }
+
+    /// Parse a stored run, returning `None` if it's invalid.
+    pub fn from_record(record: &str) -> Option<Self> {
+        let (ended_at, start_to_end_ms) = record.split_once(':')?;
+        Some(Self {
+            ended_at: ended_at.parse().ok()?,
+            start_to_end_ms: start_to_end_ms.parse().ok()?,
+        })
+    }
+}
+
+/// How long jobs are waiting on a queue, and how long the currently queued jobs are expected to wait.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Latency {
+    /// Number of jobs currently queued.
+    pub queued: u64,
+
+    /// Time since the next job to be taken from this queue was created, `None` if no jobs are queued.
+    pub oldest_queued_age: Option<Duration>,
+
+    /// Window recently started jobs were considered over.
+    pub window: Duration,
+
+    /// Number of jobs started in the window.
+    pub started: u64,
+
+    /// Median time jobs started in the window waited between being created and started, `None` if none were started.
+    pub median_wait: Option<Duration>,
+
+    /// Median milliseconds jobs started in the window waited between last being put on the queue and started, `None`
+    /// if none were started.
+    pub median_enqueue_to_start_ms: Option<u64>,
+
+    /// Number of jobs completed in the window.
+    pub completed: u64,
+
+    /// Median milliseconds jobs completed in the window ran for, `None` if none were completed.
+    pub median_start_to_end_ms: Option<u64>,
+
+    /// Number of jobs started per second over the window.
+    pub throughput: f64,
+
+    /// Estimated time until every currently queued job has been started at the recent throughput, `None` if jobs are
+    /// queued but none were started in the window.
+    pub eta: Option<Duration>,
+}
+
+impl Latency {
+    /// Estimate a queue's latency from its queued job count, the age of its oldest queued job, and its recorded job
+    /// starts and completed runs, most recent first.
+    ///
+    /// If as many starts are recorded as are kept, then older starts in the window may have been discarded, so the
+    /// throughput is measured over the time since the oldest recorded start instead.
+    pub fn new(
+        queued: u64,
+        oldest_queued_age: Option<Duration>,
+        starts: &[Start],
+        runs: &[Run],
+        window: std::time::Duration,
+        now: i64,
+    ) -> Self {
+        let since = now - window.as_secs() as i64;
+        let recent_starts: Vec<&Start> = starts.iter().filter(|start| start.started_at > since).collect();
+        let waits: Vec<u64> = recent_starts.iter().map(|start| start.wait).collect();
+        let median_wait = median(waits.clone());
+        let median_enqueue_to_start_ms =
+            median(recent_starts.iter().filter_map(|start| start.enqueue_to_start_ms).collect());
+        let run_times: Vec<u64> =
+            runs.iter().filter(|run| run.ended_at > since).map(|run| run.start_to_end_ms).collect();
+        let completed = run_times.len() as u64;
+        let median_start_to_end_ms = median(run_times);
+
+        let mut span = window.as_secs();
+        if starts.len() >= MAX_RECORDED_STARTS {
+            if let Some(oldest) = starts.last().filter(|oldest| oldest.started_at > since) {
+                span = (now - oldest.started_at).max(1) as u64;
+            }
+        }
+        let throughput = if span == 0 { 0.0 } else { waits.len() as f64 / span as f64 };
+
+        let eta = if queued == 0 {
+            Some(Duration::from_secs(0))
+        } else if throughput > 0.0 {
+            Some(Duration::from_secs((queued as f64 / throughput).ceil() as u64))
+        } else {
+            None
+        };
+
+        Self {
+            queued,
+            oldest_queued_age,
+            window: Duration(window),
+            started: waits.len() as u64,
+            median_wait: median_wait.map(Duration::from_secs),
+            median_enqueue_to_start_ms,
+            completed,
+            median_start_to_end_ms,
+            throughput,
+            eta,
+        }
+    }
+}
+
+/// Get the median of given values, or `None` if there are none.
+fn median(mut values: Vec<u64>) -> Option<u64> {
+    values.sort_unstable();
+    match values.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((values[len / 2 - 1] + values[len / 2]) / 2),
+        len => Some(values[len / 2]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn start(started_at: i64, wait: u64) -> Start {
+        Start { started_at, wait, enqueue_to_start_ms: None }
+    }
+
+    fn run(ended_at: i64, start_to_end_ms: u64) -> Run {
+        Run { ended_at, start_to_end_ms }
+    }
+
+    #[test]
+    fn records() {
+        assert_eq!(start(1000, 5).to_record(), "1000:5");
+        assert_eq!(Start::from_record("1000:5"), Some(start(1000, 5)));
+        assert_eq!(Start::from_record("1000"), None);
+        assert_eq!(Start::from_record("a:5"), None);
+
+        let timed = Start { enqueue_to_start_ms: Some(1250), ..start(1000, 5) };
+        assert_eq!(timed.to_record(), "1000:5:1250");
+        assert_eq!(Start::from_record("1000:5:1250"), Some(timed));
+        assert_eq!(Start::from_record("1000:5:a"), None);
+
+        assert_eq!(run(1000, 250).to_record(), "1000:250");
+        assert_eq!(Run::from_record("1000:250"), Some(run(1000, 250)));
+        assert_eq!(Run::from_record("1000"), None);
+    }
+
+    #[test]
+    fn estimates() {
+        let window = std::time::Duration::from_secs(100);
+        let starts = [start(1000, 4), start(990, 10), start(950, 2), start(900, 50), start(800, 1)];
+        let latency = Latency::new(20, Some(Duration::from_secs(30)), &starts, &[], window, 1000);
+        assert_eq!(latency.started, 3);
+        assert_eq!(latency.median_wait, Some(Duration::from_secs(4)));
+        assert!((latency.throughput - 0.03).abs() < 1e-9);
+        assert_eq!(latency.eta, Some(Duration::from_secs(667)));
+
+        let latency = Latency::new(0, None, &starts[..2], &[], window, 1000);
+        assert_eq!(latency.median_wait, Some(Duration::from_secs(7)));
+        assert_eq!(latency.eta, Some(Duration::from_secs(0)));
+
+        let latency = Latency::new(5, Some(Duration::from_secs(30)), &[], &[], window, 1000);
+        assert_eq!(latency.started, 0);
+        assert_eq!(latency.median_wait, None);
+        assert_eq!(latency.throughput, 0.0);
+        assert_eq!(latency.eta, None);
+    }
+
+    #[test]
+    fn millisecond_latencies() {
+        let window = std::time::Duration::from_secs(100);
+        let starts = [
+            Start { enqueue_to_start_ms: Some(40), ..start(1000, 0) },
+            start(990, 3), // recorded before enqueue to start times were
+            Start { enqueue_to_start_ms: Some(10), ..start(980, 0) },
+            Start { enqueue_to_start_ms: Some(900), ..start(800, 0) },
+        ];
+        let runs = [run(1000, 120), run(995, 80), run(970, 500), run(850, 5)];
+        let latency = Latency::new(0, None, &starts, &runs, window, 1000);
+        assert_eq!(latency.started, 3);
+        assert_eq!(latency.median_enqueue_to_start_ms, Some(25));
+        assert_eq!(latency.completed, 3);
+        assert_eq!(latency.median_start_to_end_ms, Some(120));
+
+        let latency = Latency::new(0, None, &[], &[], window, 1000);
+        assert_eq!(latency.median_enqueue_to_start_ms, None);
+        assert_eq!(latency.completed, 0);
+        assert_eq!(latency.median_start_to_end_ms, None);
+    }
+
+    #[test]
+    fn truncated_starts() {
+        // starts were discarded, so throughput is measured since the oldest recorded start
+        let starts: Vec<Start> = (0..MAX_RECORDED_STARTS as i64).map(|i| start(1000 - i / 10, 1)).collect();
+        let latency = Latency::new(0, None, &starts, &[], std::time::Duration::from_secs(300), 1000);
+        assert_eq!(latency.started, MAX_RECORDED_STARTS as u64);
+        assert!((latency.throughput - 10.1).abs() < 0.01);
+    }
+}
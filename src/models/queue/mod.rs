@@ -1,5 +1,27 @@
+mod backlog;
+mod callback;
+mod clone;
+mod deletion;
+mod expiry;
+mod failures;
 mod field;
+mod latency;
+mod output;
+mod schedule;
 mod settings;
+mod snapshot;
+mod summary;
 
+pub use self::backlog::Backlog;
+pub use self::callback::Callback;
+pub use self::clone::CloneRequest;
+pub use self::deletion::{DeletedJobs, Deletion};
+pub use self::expiry::{ExpiryPolicy, ExpirySweep};
+pub use self::failures::{FailureReason, FailureSummary};
 pub use self::field::Field;
-pub use self::settings::Settings;
+pub use self::latency::{Latency, Run, Start, DEFAULT_LATENCY_WINDOW, MAX_RECORDED_STARTS};
+pub use self::output::{truncate_output, OutputSizePolicy};
+pub use self::schedule::{Batching, CheckSchedule, CheckSweep, DEFAULT_MONITOR_BATCH_SIZE};
+pub use self::settings::{check_size, Settings, SettingsUpdate};
+pub use self::snapshot::Snapshot;
+pub use self::summary::Summary;
@@ -0,0 +1,116 @@
+//! Defines what's done with job outputs exceeding a queue's maximum output size.
+
+use std::fmt;
+use std::str::FromStr;
+
+use redis::{self, FromRedisValue, RedisWrite, ToRedisArgs};
+use serde::{Deserialize, Serialize};
+
+const REJECT_POLICY: &str = "reject";
+const TRUNCATE_POLICY: &str = "truncate";
+const OFFLOAD_POLICY: &str = "offload";
+
+/// What's done when a worker sets a job output larger than its queue's `max_output_size`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputSizePolicy {
+    /// The output is rejected with a 400, leaving the job's existing output unchanged.
+    #[default]
+    Reject,
+
+    /// The output is replaced by a JSON string of as much of its serialised form as fits.
+    Truncate,
+
+    /// The output is written to a file in the server's `output_offload_dir`, and replaced by a reference to it.
+    Offload,
+}
+
+impl fmt::Display for OutputSizePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+impl AsRef<str> for OutputSizePolicy {
+    fn as_ref(&self) -> &str {
+        match self {
+            OutputSizePolicy::Reject => REJECT_POLICY,
+            OutputSizePolicy::Truncate => TRUNCATE_POLICY,
+            OutputSizePolicy::Offload => OFFLOAD_POLICY,
+        }
+    }
+}
+
+impl FromStr for OutputSizePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            REJECT_POLICY => Ok(OutputSizePolicy::Reject),
+            TRUNCATE_POLICY => Ok(OutputSizePolicy::Truncate),
+            OFFLOAD_POLICY => Ok(OutputSizePolicy::Offload),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToRedisArgs for OutputSizePolicy {
+    fn write_redis_args<W: ?Sized + RedisWrite>(&self, out: &mut W) {
+        self.as_ref().write_redis_args(out)
+    }
+}
+
+impl FromRedisValue for OutputSizePolicy {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        let s = String::from_redis_value(v)?;
+        Self::from_str(&s).map_err(|_| (redis::ErrorKind::TypeError, "Invalid output size policy").into())
+    }
+}
+
+/// Truncate given output to a JSON string of the start of its serialised form, such that the truncated output's
+/// serialised size is at most `max_size` bytes.
+pub fn truncate_output(output: &serde_json::Value, max_size: u64) -> serde_json::Value {
+    let serialised = output.to_string();
+    let max_size = max_size as usize;
+    // leave room for the quotes around the string, then shrink further if escaping makes it too long
+    let mut len = serialised.len().min(max_size.saturating_sub(2));
+    loop {
+        while !serialised.is_char_boundary(len) {
+            len -= 1;
+        }
+        let truncated = serde_json::Value::String(serialised[..len].to_owned());
+        let size = truncated.to_string().len();
+        if size <= max_size || len == 0 {
+            return truncated;
+        }
+        len = len.saturating_sub(size - max_size);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn policy_to_from_str() {
+        for policy in &[OutputSizePolicy::Reject, OutputSizePolicy::Truncate, OutputSizePolicy::Offload] {
+            assert_eq!(policy, &OutputSizePolicy::from_str(policy.as_ref()).unwrap());
+        }
+        assert_eq!(serde_json::to_string(&OutputSizePolicy::Offload).unwrap(), r#""offload""#);
+    }
+
+    #[test]
+    fn truncation() {
+        let output = json!({"rows": [1, 2, 3, 4, 5]});
+        let truncated = truncate_output(&output, 12);
+        assert_eq!(truncated, json!("{\"rows\":"));
+        assert!(truncated.to_string().len() <= 12);
+
+        let truncated = truncate_output(&json!("ééééé"), 8);
+        assert_eq!(truncated, json!("\"éé"));
+        assert!(truncated.to_string().len() <= 8);
+
+        assert_eq!(truncate_output(&output, 0), json!(""));
+    }
+}
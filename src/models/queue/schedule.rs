@@ -0,0 +1,217 @@
+//! Defines structs used to check each queue's jobs on its own interval, a batch at a time.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Default maximum number of jobs fetched and checked at once by a timeout, retry, or expiry check.
+pub const DEFAULT_MONITOR_BATCH_SIZE: usize = 1000;
+
+/// Limits on how many jobs a single timeout, retry, or expiry check handles at once, and for how long, so that checks
+/// over many jobs don't hold up requests to Redis from other clients.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Batching {
+    /// Maximum number of jobs fetched and checked at once, before yielding to other tasks. 0 checks all jobs at once.
+    pub batch_size: usize,
+
+    /// Time after which no more batches are started, leaving any jobs not yet checked to the next check.
+    pub deadline: Option<Instant>,
+}
+
+impl Batching {
+    /// Get batching for a check started at given time, which stops starting new batches once it's run for
+    /// `max_duration`, if given.
+    pub fn new(batch_size: usize, max_duration: Option<Duration>, started: Instant) -> Self {
+        Self {
+            batch_size,
+            deadline: max_duration.map(|max_duration| started + max_duration),
+        }
+    }
+
+    /// Check whether the check has run past its deadline as of given time.
+    pub fn is_overdue(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| now >= deadline)
+    }
+}
+
+impl Default for Batching {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_MONITOR_BATCH_SIZE,
+            deadline: None,
+        }
+    }
+}
+
+/// Queues whose jobs are due to be checked during a single timeout, retry, or expiry check.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheckSweep {
+    /// Whether each known queue is due to be checked.
+    pub queues: HashMap<String, bool>,
+
+    /// Whether jobs on any other queue (e.g. one that's since been deleted) are due to be checked.
+    pub default: bool,
+
+    /// Limits on how many jobs are checked at once, and for how long.
+    pub batching: Batching,
+}
+
+impl CheckSweep {
+    /// Get a sweep that checks jobs on all queues.
+    pub fn all() -> Self {
+        Self {
+            queues: HashMap::new(),
+            default: true,
+            batching: Batching::default(),
+        }
+    }
+
+    /// Check whether jobs on given queue are due to be checked.
+    pub fn includes(&self, queue: Option<&str>) -> bool {
+        match queue.and_then(|queue| self.queues.get(queue)) {
+            Some(due) => *due,
+            None => self.default,
+        }
+    }
+
+    /// Check whether any jobs are due to be checked by this sweep.
+    pub fn is_empty(&self) -> bool {
+        !self.default && !self.queues.values().any(|due| *due)
+    }
+}
+
+/// Tracks when each queue was last checked by a monitor, so that each queue can be checked on its own interval.
+#[derive(Debug)]
+pub struct CheckSchedule {
+    default_interval: Duration,
+
+    // time each queue was last checked, `None` is used for jobs on queues that no longer exist
+    last_checked: HashMap<Option<String>, Instant>,
+}
+
+impl CheckSchedule {
+    /// Create a schedule where nothing has been checked yet, using given interval for jobs on unknown queues.
+    pub fn new(default_interval: Duration) -> Self {
+        Self {
+            default_interval,
+            last_checked: HashMap::new(),
+        }
+    }
+
+    /// Get which queues are due to be checked at `now`, given each queue's check interval, and record them as
+    /// checked.
+    pub fn sweep(&mut self, intervals: &HashMap<String, Duration>, now: Instant) -> CheckSweep {
+        let mut sweep = CheckSweep::default();
+        for (queue_name, interval) in intervals {
+            let due = self.is_due(&Some(queue_name.clone()), *interval, now);
+            sweep.queues.insert(queue_name.clone(), due);
+        }
+        sweep.default = self.is_due(&None, self.default_interval, now);
+
+        // forget about deleted queues
+        self.last_checked
+            .retain(|key, _| key.as_ref().is_none_or(|name| intervals.contains_key(name)));
+        for (queue_name, due) in &sweep.queues {
+            if *due {
+                self.last_checked.insert(Some(queue_name.clone()), now);
+            }
+        }
+        if sweep.default {
+            self.last_checked.insert(None, now);
+        }
+        sweep
+    }
+
+    /// Get the amount of time from `now` until the next queue is due to be checked.
+    pub fn next_check(&self, intervals: &HashMap<String, Duration>, now: Instant) -> Duration {
+        intervals
+            .iter()
+            .map(|(name, interval)| (Some(name.clone()), *interval))
+            .chain(std::iter::once((None, self.default_interval)))
+            .map(|(key, interval)| {
+                let elapsed = self
+                    .last_checked
+                    .get(&key)
+                    .map_or(interval, |checked| now.duration_since(*checked));
+                interval.saturating_sub(elapsed)
+            })
+            .min()
+            .unwrap_or(self.default_interval)
+    }
+
+    /// Forget when all queues were last checked, so that they're all checked again immediately.
+    pub fn clear(&mut self) {
+        self.last_checked.clear();
+    }
+
+    fn is_due(&self, key: &Option<String>, interval: Duration, now: Instant) -> bool {
+        self.last_checked
+            .get(key)
+            .is_none_or(|checked| now.duration_since(*checked) >= interval)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sweep_includes() {
+        let mut sweep = CheckSweep::default();
+        assert!(sweep.is_empty());
+        sweep.queues.insert("a".to_owned(), true);
+        sweep.queues.insert("b".to_owned(), false);
+        assert!(!sweep.is_empty());
+
+        assert!(sweep.includes(Some("a")));
+        assert!(!sweep.includes(Some("b")));
+        assert!(!sweep.includes(Some("deleted")));
+        assert!(!sweep.includes(None));
+
+        sweep.default = true;
+        assert!(!sweep.includes(Some("b")));
+        assert!(sweep.includes(Some("deleted")));
+        assert!(sweep.includes(None));
+        assert!(CheckSweep::all().includes(Some("a")));
+    }
+
+    #[test]
+    fn batching_deadline() {
+        let started = Instant::now();
+        let batching = Batching::new(100, Some(Duration::from_secs(10)), started);
+        assert!(!batching.is_overdue(started + Duration::from_secs(9)));
+        assert!(batching.is_overdue(started + Duration::from_secs(10)));
+
+        let unlimited = Batching::new(100, None, started);
+        assert!(!unlimited.is_overdue(started + Duration::from_secs(3600)));
+        assert_eq!(Batching::default().batch_size, DEFAULT_MONITOR_BATCH_SIZE);
+    }
+
+    #[test]
+    fn schedule_per_queue() {
+        let mut intervals = HashMap::new();
+        intervals.insert("fast".to_owned(), Duration::from_secs(5));
+        intervals.insert("slow".to_owned(), Duration::from_secs(300));
+        let mut schedule = CheckSchedule::new(Duration::from_secs(60));
+
+        // everything is due on the first check
+        let start = Instant::now();
+        let sweep = schedule.sweep(&intervals, start);
+        assert!(sweep.includes(Some("fast")));
+        assert!(sweep.includes(Some("slow")));
+        assert!(sweep.default);
+        assert_eq!(schedule.next_check(&intervals, start), Duration::from_secs(5));
+
+        let now = start + Duration::from_secs(5);
+        let sweep = schedule.sweep(&intervals, now);
+        assert!(sweep.includes(Some("fast")));
+        assert!(!sweep.includes(Some("slow")));
+        assert!(!sweep.default);
+
+        let now = start + Duration::from_secs(7);
+        assert!(schedule.sweep(&intervals, now).is_empty());
+        assert_eq!(schedule.next_check(&intervals, now), Duration::from_secs(3));
+
+        schedule.clear();
+        assert!(schedule.sweep(&intervals, now).includes(Some("slow")));
+    }
+}
@@ -1,37 +1,214 @@
 use redis::{self, from_redis_value, FromRedisValue, RedisResult};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::models::Duration;
+use super::OutputSizePolicy;
+use crate::models::{job, Duration, OcyError, OcyResult};
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 #[serde(default)]
 pub struct Settings {
+    /// Execution time of each attempt of this queue's jobs before they're timed out. Can also be given as
+    /// `attempt_timeout`.
+    #[serde(alias = "attempt_timeout")]
     pub timeout: Duration,
     pub heartbeat_timeout: Duration,
     pub expires_after: Duration,
     pub retries: u64,
     pub retry_delays: Vec<Duration>,
+    pub quarantine_after: u64,
+    pub quick_fail_window: Duration,
+
+    /// Statuses of ended jobs that expire on this queue, uses the server's setting if not specified.
+    pub expiry_check_statuses: Option<Vec<job::Status>>,
+
+    /// How often this queue's ended jobs are checked for expiry, uses the server's setting if not specified.
+    pub expiry_check_interval: Option<Duration>,
+
+    /// How often this queue's running jobs are checked for timeouts, uses the server's setting if not specified.
+    pub timeout_check_interval: Option<Duration>,
+
+    /// How often this queue's failed jobs are checked for retries, uses the server's setting if not specified.
+    pub retry_check_interval: Option<Duration>,
+
+    /// Time after creation by which this queue's jobs should have completed, jobs that miss this deadline are marked
+    /// as having breached their SLA. No SLA is applied if not specified.
+    pub sla: Option<Duration>,
+
+    /// Maximum time after creation, across all attempts and the delays between them, before this queue's jobs are
+    /// timed out without being retried again. Jobs are only limited by `timeout` and `retries` if not specified.
+    pub total_timeout: Option<Duration>,
+
+    /// Maximum time this queue's jobs can wait to be started since they were last queued. Jobs left waiting longer
+    /// are quarantined with the reason `expired_in_queue`, rather than being left queued. Jobs can wait indefinitely
+    /// if not specified.
+    pub max_queue_age: Option<Duration>,
+
+    /// Maximum size in bytes of the JSON input of jobs created on this queue. Only the server's `max_body_size`
+    /// applies if not specified.
+    pub max_input_size: Option<u64>,
+
+    /// Maximum size in bytes of the JSON output set by workers for this queue's jobs. Only the server's
+    /// `max_body_size` applies if not specified.
+    pub max_output_size: Option<u64>,
+
+    /// What's done with job outputs exceeding `max_output_size`.
+    pub output_size_policy: OutputSizePolicy,
+
+    /// Status transitions clients can make to this queue's jobs, by updating their status or retrying them. All
+    /// valid transitions are allowed if not specified.
+    pub allowed_transitions: Option<Vec<job::Transition>>,
+
+    /// Maximum number of this queue's failed jobs that are automatically retried per minute. Once exceeded, no jobs
+    /// are taken from this queue for `retry_budget_cooldown`. Retries are unlimited if not specified.
+    pub retry_budget: Option<u64>,
+
+    /// Amount of time no jobs are taken from this queue for after its retry budget is exceeded.
+    pub retry_budget_cooldown: Duration,
+
+    /// Name of a queue to mirror a copy of this queue's new jobs to, marked as shadow jobs. Used to try out new
+    /// workers against real traffic. No jobs are mirrored if not specified.
+    pub shadow_to: Option<String>,
+
+    /// Percentage of this queue's new jobs that are mirrored to the `shadow_to` queue.
+    pub shadow_percent: u64,
+
+    /// Amount of time after completing that the outputs of this queue's jobs with a unique key can be fetched by that
+    /// key, as long as the jobs haven't expired.
+    pub result_ttl: Duration,
+}
+
+impl Settings {
+    /// Check that a job's input is within this queue's maximum input size.
+    pub fn check_input_size(&self, input: &serde_json::Value) -> OcyResult<()> {
+        check_size("input", input, self.max_input_size)
+    }
+
+    /// Check that a job's output is within this queue's maximum output size.
+    pub fn check_output_size(&self, output: &serde_json::Value) -> OcyResult<()> {
+        check_size("output", output, self.max_output_size)
+    }
+
+    /// Check that this queue's shadow settings are valid for a queue with given name.
+    pub fn check_shadow(&self, queue_name: &str) -> OcyResult<()> {
+        if self.shadow_percent > 100 {
+            return Err(OcyError::bad_request("Invalid shadow_percent, must be between 0 and 100"));
+        }
+        if self.shadow_to.as_deref() == Some(queue_name) {
+            return Err(OcyError::bad_request("Queue can't shadow_to itself"));
+        }
+        Ok(())
+    }
+}
+
+/// Check that the serialised size of given JSON is within given limit, if any.
+pub fn check_size(name: &str, value: &serde_json::Value, max_size: Option<u64>) -> OcyResult<()> {
+    let max_size = match max_size {
+        Some(max_size) => max_size,
+        None => return Ok(()),
+    };
+
+    let size = value.to_string().len() as u64;
+    if size > max_size {
+        return Err(OcyError::bad_request(format!(
+            "Job {} is {} bytes, exceeding queue's maximum of {} bytes",
+            name, size, max_size
+        )));
+    }
+    Ok(())
 }
 
 impl FromRedisValue for Settings {
+    #[allow(clippy::type_complexity)]
     fn from_redis_value(v: &redis::Value) -> RedisResult<Self> {
-        let (timeout, heartbeat_timeout, expires_after, retries, retry_delays): (
+        // tuples are only converted from up to 12 values, so fields beyond that are converted separately
+        let (values, extra_values) = match v {
+            redis::Value::Bulk(values) if values.len() > 12 => values.split_at(12),
+            _ => return Err((redis::ErrorKind::TypeError, "Unexpected number of queue settings").into()),
+        };
+        let (
+            max_input_size,
+            max_output_size,
+            retry_budget,
+            retry_budget_cooldown,
+            shadow_to,
+            shadow_percent,
+            output_size_policy,
+            allowed_transitions,
+            total_timeout,
+            result_ttl,
+            max_queue_age,
+        ): (
+            Option<u64>,
+            Option<u64>,
+            Option<u64>,
+            Option<Duration>,
+            Option<String>,
+            Option<u64>,
+            Option<OutputSizePolicy>,
+            Option<String>,
+            Option<Duration>,
+            Option<Duration>,
+            Option<Duration>,
+        ) = from_redis_value(&redis::Value::Bulk(extra_values.to_vec()))?;
+        let (
+            timeout,
+            heartbeat_timeout,
+            expires_after,
+            retries,
+            retry_delays,
+            quarantine_after,
+            quick_fail_window,
+            expiry_check_statuses,
+            expiry_check_interval,
+            timeout_check_interval,
+            retry_check_interval,
+            sla,
+        ): (
             Duration,
             Duration,
             Duration,
             u64,
             Option<String>,
-        ) = from_redis_value(v)?;
+            Option<u64>,
+            Option<Duration>,
+            Option<String>,
+            Option<Duration>,
+            Option<Duration>,
+            Option<Duration>,
+            Option<Duration>,
+        ) = from_redis_value(&redis::Value::Bulk(values.to_vec()))?;
         let retry_delays = match retry_delays {
             Some(s) => serde_json::from_str(&s).unwrap(),
             None => Vec::new(),
         };
+
+        // queues created before quarantining, retry budgets, shadowing, output size policies or result caching were
+        // supported won't have these fields
+        let defaults = Self::default();
         Ok(Self {
             timeout,
             heartbeat_timeout,
             expires_after,
             retries,
             retry_delays,
+            quarantine_after: quarantine_after.unwrap_or(defaults.quarantine_after),
+            quick_fail_window: quick_fail_window.unwrap_or(defaults.quick_fail_window),
+            expiry_check_statuses: expiry_check_statuses.map(|s| serde_json::from_str(&s).unwrap()),
+            expiry_check_interval,
+            timeout_check_interval,
+            retry_check_interval,
+            sla,
+            total_timeout,
+            max_queue_age,
+            max_input_size,
+            max_output_size,
+            output_size_policy: output_size_policy.unwrap_or(defaults.output_size_policy),
+            allowed_transitions: allowed_transitions.map(|s| serde_json::from_str(&s).unwrap()),
+            retry_budget,
+            retry_budget_cooldown: retry_budget_cooldown.unwrap_or(defaults.retry_budget_cooldown),
+            shadow_to,
+            shadow_percent: shadow_percent.unwrap_or(defaults.shadow_percent),
+            result_ttl: result_ttl.unwrap_or(defaults.result_ttl),
         })
     }
 }
@@ -44,6 +221,188 @@ impl Default for Settings {
             expires_after: Duration::from_secs(300),
             retries: 0,
             retry_delays: Vec::new(),
+            quarantine_after: 0,
+            quick_fail_window: Duration::from_secs(10),
+            expiry_check_statuses: None,
+            expiry_check_interval: None,
+            timeout_check_interval: None,
+            retry_check_interval: None,
+            sla: None,
+            total_timeout: None,
+            max_queue_age: None,
+            max_input_size: None,
+            max_output_size: None,
+            output_size_policy: OutputSizePolicy::Reject,
+            allowed_transitions: None,
+            retry_budget: None,
+            retry_budget_cooldown: Duration::from_secs(300),
+            shadow_to: None,
+            shadow_percent: 100,
+            result_ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// Partial update to a queue's settings, where only the fields given are changed.
+///
+/// Settings that fall back to the server's settings when not specified can be reset by setting them to `null`,
+/// hence being wrapped in a second `Option`. Fields not given are left out when serialized.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SettingsUpdate {
+    #[serde(alias = "attempt_timeout", skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat_timeout: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_after: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_delays: Option<Vec<Duration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quarantine_after: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quick_fail_window: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub expiry_check_statuses: Option<Option<Vec<job::Status>>>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub expiry_check_interval: Option<Option<Duration>>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub timeout_check_interval: Option<Option<Duration>>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub retry_check_interval: Option<Option<Duration>>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub sla: Option<Option<Duration>>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub total_timeout: Option<Option<Duration>>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub max_queue_age: Option<Option<Duration>>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub max_input_size: Option<Option<u64>>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub max_output_size: Option<Option<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_size_policy: Option<OutputSizePolicy>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub allowed_transitions: Option<Option<Vec<job::Transition>>>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub retry_budget: Option<Option<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_budget_cooldown: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_nullable", skip_serializing_if = "Option::is_none")]
+    pub shadow_to: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow_percent: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_ttl: Option<Duration>,
+}
+
+impl SettingsUpdate {
+    /// Apply this update to given settings.
+    pub fn apply(&self, settings: &mut Settings) {
+        fn set<T: Clone>(setting: &mut T, update: &Option<T>) {
+            if let Some(value) = update {
+                *setting = value.clone();
+            }
+        }
+
+        set(&mut settings.timeout, &self.timeout);
+        set(&mut settings.heartbeat_timeout, &self.heartbeat_timeout);
+        set(&mut settings.expires_after, &self.expires_after);
+        set(&mut settings.retries, &self.retries);
+        set(&mut settings.retry_delays, &self.retry_delays);
+        set(&mut settings.quarantine_after, &self.quarantine_after);
+        set(&mut settings.quick_fail_window, &self.quick_fail_window);
+        set(&mut settings.expiry_check_statuses, &self.expiry_check_statuses);
+        set(&mut settings.expiry_check_interval, &self.expiry_check_interval);
+        set(&mut settings.timeout_check_interval, &self.timeout_check_interval);
+        set(&mut settings.retry_check_interval, &self.retry_check_interval);
+        set(&mut settings.sla, &self.sla);
+        set(&mut settings.total_timeout, &self.total_timeout);
+        set(&mut settings.max_queue_age, &self.max_queue_age);
+        set(&mut settings.max_input_size, &self.max_input_size);
+        set(&mut settings.max_output_size, &self.max_output_size);
+        set(&mut settings.output_size_policy, &self.output_size_policy);
+        set(&mut settings.allowed_transitions, &self.allowed_transitions);
+        set(&mut settings.retry_budget, &self.retry_budget);
+        set(&mut settings.retry_budget_cooldown, &self.retry_budget_cooldown);
+        set(&mut settings.shadow_to, &self.shadow_to);
+        set(&mut settings.shadow_percent, &self.shadow_percent);
+        set(&mut settings.result_ttl, &self.result_ttl);
+    }
+}
+
+/// Deserialize a field that's present (whether `null` or not) as `Some`, so that it can be distinguished from a
+/// missing field.
+fn deserialize_nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn partial_update() {
+        let mut settings = Settings {
+            expiry_check_interval: Some(Duration::from_secs(60)),
+            sla: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        let update: SettingsUpdate =
+            serde_json::from_str(r#"{"retries": 3, "expiry_check_interval": null}"#).unwrap();
+        update.apply(&mut settings);
+
+        assert_eq!(settings.retries, 3);
+        assert_eq!(settings.expiry_check_interval, None);
+        assert_eq!(settings.sla, Some(Duration::from_secs(3600)));
+        assert_eq!(settings.timeout, Settings::default().timeout);
+
+        assert!(serde_json::from_str::<SettingsUpdate>(r#"{"retires": 3}"#).is_err());
+
+        let update: SettingsUpdate =
+            serde_json::from_str(r#"{"attempt_timeout": "1m", "total_timeout": "1h", "max_queue_age": "1d"}"#).unwrap();
+        update.apply(&mut settings);
+        assert_eq!(settings.timeout, Duration::from_secs(60));
+        assert_eq!(settings.total_timeout, Some(Duration::from_secs(3600)));
+        assert_eq!(settings.max_queue_age, Some(Duration::from_secs(86400)));
+
+        // only given fields are sent, so that clients don't reset settings they didn't mean to change
+        let update = SettingsUpdate { retries: Some(3), sla: Some(None), ..Default::default() };
+        assert_eq!(serde_json::to_string(&update).unwrap(), r#"{"retries":3,"sla":null}"#);
+    }
+
+    #[test]
+    fn size_limits() {
+        let mut settings = Settings::default();
+        let value = serde_json::json!({"a": "12345"});
+        assert!(settings.check_input_size(&value).is_ok());
+
+        settings.max_input_size = Some(13);
+        settings.max_output_size = Some(12);
+        assert!(settings.check_input_size(&value).is_ok());
+        match settings.check_output_size(&value) {
+            Err(OcyError::BadRequest(msg)) => assert!(msg.contains("13 bytes"), "{}", msg),
+            x => panic!("Unexpected result checking output size: {:?}", x),
         }
     }
+
+    #[test]
+    fn shadow() {
+        let mut settings = Settings::default();
+        assert!(settings.check_shadow("a").is_ok());
+
+        settings.shadow_to = Some("b".to_owned());
+        settings.shadow_percent = 10;
+        assert!(settings.check_shadow("a").is_ok());
+        assert!(settings.check_shadow("b").is_err());
+
+        settings.shadow_percent = 101;
+        assert!(settings.check_shadow("a").is_err());
+    }
 }
@@ -0,0 +1,22 @@
+//! Defines point-in-time snapshots of a queue, used for backups.
+
+use serde::Serialize;
+
+use super::Settings;
+use crate::models::{job, DateTime};
+
+/// A queue's settings and all of its jobs, read at the same point in time so that they're consistent with each other
+/// even while the queue is being written to.
+#[derive(Debug, Serialize)]
+pub struct Snapshot {
+    /// Name of the queue.
+    pub queue: String,
+
+    /// Time the snapshot was taken.
+    pub taken_at: DateTime,
+
+    pub settings: Settings,
+
+    /// Every job created on the queue that still exists, whatever its status, with all of its stored fields.
+    pub jobs: Vec<job::JobMeta>,
+}
@@ -0,0 +1,46 @@
+//! Defines the overview of a queue returned when summarising all queues.
+
+use serde::Serialize;
+
+use super::Settings;
+use crate::models::{DateTime, Duration, QueueInfo};
+
+/// Summary of a queue's jobs and main settings, so that all queues can be monitored in a single request.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Summary {
+    /// Number of this queue's jobs in each status.
+    pub jobs: QueueInfo,
+
+    pub timeout: Duration,
+    pub heartbeat_timeout: Duration,
+    pub expires_after: Duration,
+    pub retries: u64,
+    pub sla: Option<Duration>,
+
+    /// Time since the next job to be taken from this queue was created, `None` if no jobs are queued.
+    pub oldest_queued_age: Option<Duration>,
+
+    /// Time until which no jobs will be taken from this queue, due to its retry budget being exceeded.
+    pub paused_until: Option<DateTime>,
+}
+
+impl Summary {
+    /// Create a summary from a queue's job counts and settings.
+    pub fn new(
+        jobs: QueueInfo,
+        settings: Settings,
+        oldest_queued_age: Option<Duration>,
+        paused_until: Option<DateTime>,
+    ) -> Self {
+        Self {
+            jobs,
+            timeout: settings.timeout,
+            heartbeat_timeout: settings.heartbeat_timeout,
+            expires_after: settings.expires_after,
+            retries: settings.retries,
+            sla: settings.sla,
+            oldest_queued_age,
+            paused_until,
+        }
+    }
+}
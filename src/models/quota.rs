@@ -0,0 +1,164 @@
+//! Defines quotas limiting the resources a namespace can use, and the resources it's currently using.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{OcyError, OcyResult};
+
+/// Limits on the resources a namespace can use. Each limit is unlimited if not specified.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Quota {
+    /// Maximum number of queues in the namespace.
+    pub max_queues: Option<u64>,
+
+    /// Maximum total number of queued jobs across the namespace's queues.
+    pub max_queued_jobs: Option<u64>,
+
+    /// Maximum total size in bytes of the inputs and outputs of the namespace's jobs.
+    pub max_storage_bytes: Option<u64>,
+}
+
+impl Quota {
+    /// Check whether this quota limits anything.
+    pub fn is_limited(&self) -> bool {
+        self.max_queues.is_some() || self.max_queued_jobs.is_some() || self.max_storage_bytes.is_some()
+    }
+
+    /// Check that adding resources to a namespace's current usage wouldn't exceed this quota.
+    ///
+    /// Exceeding the maximum number of queues is forbidden, while exceeding limits on jobs may be retried once
+    /// existing jobs have been processed, so is reported separately.
+    pub fn check(&self, usage: &Usage, added: &Usage) -> OcyResult<()> {
+        if added.queues > 0 && exceeds(usage.queues + added.queues, self.max_queues) {
+            return Err(OcyError::forbidden(format!(
+                "Queue quota exceeded, namespace is limited to {} queues",
+                self.max_queues.unwrap_or_default()
+            )));
+        }
+        if added.queued_jobs > 0 && exceeds(usage.queued_jobs + added.queued_jobs, self.max_queued_jobs) {
+            return Err(OcyError::quota_exceeded(format!(
+                "Queued job quota exceeded, namespace is limited to {} queued jobs",
+                self.max_queued_jobs.unwrap_or_default()
+            )));
+        }
+        if added.storage_bytes > 0 && exceeds(usage.storage_bytes + added.storage_bytes, self.max_storage_bytes) {
+            return Err(OcyError::quota_exceeded(format!(
+                "Storage quota exceeded, namespace is limited to {} bytes",
+                self.max_storage_bytes.unwrap_or_default()
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn exceeds(value: u64, max: Option<u64>) -> bool {
+    max.is_some_and(|max| value > max)
+}
+
+/// Resources used by a namespace.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Usage {
+    /// Number of queues in the namespace.
+    pub queues: u64,
+
+    /// Total number of queued jobs across the namespace's queues.
+    pub queued_jobs: u64,
+
+    /// Total size in bytes of the inputs and outputs of the namespace's jobs.
+    pub storage_bytes: u64,
+}
+
+impl Usage {
+    /// Get the resources used by a single new, empty queue.
+    pub fn queue() -> Self {
+        Self {
+            queues: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Get the resources used by a single new queued job with given input.
+    pub fn job(input: Option<&serde_json::Value>) -> Self {
+        Self {
+            queues: 0,
+            queued_jobs: 1,
+            storage_bytes: input.map_or(0, |input| input.to_string().len() as u64),
+        }
+    }
+
+    /// Add usage from another Redis shard to this.
+    pub fn merge(&mut self, other: &Usage) {
+        self.queues += other.queues;
+        self.queued_jobs += other.queued_jobs;
+        self.storage_bytes += other.storage_bytes;
+    }
+}
+
+/// Storage a namespace can use on a single Redis shard, given its quota and the storage it's using on other shards.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageLimit {
+    /// Namespace the limit applies to.
+    pub namespace: String,
+
+    /// Maximum total size in bytes of the inputs and outputs of the namespace's jobs on the shard.
+    pub max_bytes: u64,
+
+    /// The namespace's `max_storage_bytes` quota across all shards.
+    pub quota_bytes: u64,
+}
+
+impl StorageLimit {
+    /// Get the error for a job rejected by this limit.
+    pub fn exceeded(&self) -> OcyError {
+        OcyError::quota_exceeded(format!(
+            "Storage quota exceeded, namespace is limited to {} bytes",
+            self.quota_bytes
+        ))
+    }
+}
+
+/// A namespace's quota, and the resources it's currently using.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub namespace: String,
+    pub quota: Quota,
+    pub usage: Usage,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let quota = Quota {
+            max_queues: Some(2),
+            max_queued_jobs: Some(10),
+            max_storage_bytes: None,
+        };
+        let usage = Usage {
+            queues: 2,
+            queued_jobs: 9,
+            storage_bytes: 1 << 30,
+        };
+        let new_queue = Usage::queue();
+
+        assert!(matches!(quota.check(&usage, &new_queue), Err(OcyError::Forbidden(_))));
+        assert_eq!(quota.check(&usage, &Usage::job(Some(&serde_json::json!({"a": 1})))), Ok(()));
+        assert_eq!(quota.check(&usage, &Usage::default()), Ok(()));
+
+        let usage = Usage {
+            queued_jobs: 10,
+            ..usage
+        };
+        assert!(matches!(quota.check(&usage, &Usage::job(None)), Err(OcyError::QuotaExceeded(_))));
+        assert!(!Quota::default().is_limited());
+        assert_eq!(Quota::default().check(&usage, &new_queue), Ok(()));
+    }
+
+    #[test]
+    fn job_usage() {
+        assert_eq!(Usage::job(None).storage_bytes, 0);
+        assert_eq!(Usage::job(Some(&serde_json::json!({"key": "value"}))).storage_bytes, 15);
+    }
+}
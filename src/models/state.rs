@@ -1,6 +1,28 @@
 //! Defines server state, typically passed to HTTP handlers by Actix web as required.
 
+use std::sync::Arc;
+
+use crate::application::cache::ResponseCache;
+use crate::application::drain::Drain;
+use crate::application::shard::RedisShards;
+use crate::application::slowlog::SlowLog;
+use crate::application::throttle::PollThrottle;
+use crate::events::anomaly::AnomalyDetector;
+use crate::events::hooks::Hooks;
+use crate::events::EventBus;
+use crate::logging::LogFilter;
+use crate::middleware::circuit_breaker::CircuitBreaker;
+
 pub struct ApplicationState {
-    pub redis_conn_manager: redis::aio::ConnectionManager,
+    pub redis_shards: RedisShards,
     pub config: crate::config::Config,
+    pub circuit_breaker: Arc<CircuitBreaker>,
+    pub events: EventBus,
+    pub hooks: Hooks,
+    pub log_filter: Arc<LogFilter>,
+    pub slow_log: Arc<SlowLog>,
+    pub drain: Drain,
+    pub anomalies: Arc<AnomalyDetector>,
+    pub poll_throttle: PollThrottle,
+    pub response_cache: ResponseCache,
 }
@@ -0,0 +1,175 @@
+//! Defines structs used to attach a tag to many jobs at once, and to operate on all jobs with a tag.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{job, OcyError, OcyResult};
+
+/// Maximum number of job IDs that can be given in a single request to apply a tag.
+pub const MAX_APPLY_JOB_IDS: usize = 10_000;
+
+/// Number of jobs handled in each batch when applying a tag, or operating on all jobs with a tag.
+pub const BATCH_SIZE: usize = 500;
+
+/// Jobs to attach a tag to, given either as a list of job IDs, or as a queue whose jobs (optionally only those with a
+/// given status) are all tagged.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApplyRequest {
+    /// IDs of the jobs to tag.
+    pub job_ids: Option<Vec<u64>>,
+
+    /// Name of a queue whose jobs are tagged, instead of giving their IDs.
+    pub queue: Option<String>,
+
+    /// Only tag jobs on `queue` with this status.
+    pub status: Option<job::Status>,
+}
+
+impl ApplyRequest {
+    /// Check that this request selects jobs either by ID or by queue.
+    pub fn validate(&self) -> OcyResult<()> {
+        match (&self.job_ids, &self.queue) {
+            (Some(_), Some(_)) | (None, None) => {
+                Err(OcyError::bad_request("Either job_ids or queue must be given, but not both"))
+            }
+            (Some(job_ids), None) if job_ids.len() > MAX_APPLY_JOB_IDS => Err(OcyError::bad_request(format!(
+                "Cannot tag more than {} jobs by ID in a single request",
+                MAX_APPLY_JOB_IDS
+            ))),
+            (Some(_), None) if self.status.is_some() => {
+                Err(OcyError::bad_request("A status can only be given along with a queue"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Outcome of applying a tag to many jobs, giving the IDs of the jobs by whether they now have the tag.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ApplyResults {
+    /// Jobs that now have the tag, including any that already had it.
+    pub tagged: Vec<u64>,
+
+    /// Jobs that don't exist, or that the client doesn't have access to.
+    pub not_found: Vec<u64>,
+}
+
+impl ApplyResults {
+    /// Add the results of another batch to these results, keeping each list sorted by job ID.
+    pub fn merge(&mut self, other: ApplyResults) {
+        self.tagged.extend(other.tagged);
+        self.not_found.extend(other.not_found);
+        self.tagged.sort_unstable();
+        self.not_found.sort_unstable();
+    }
+}
+
+/// Operation applied to every job with a tag.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BulkOperation {
+    /// Re-queue failed, timed out, cancelled and quarantined jobs.
+    Retry,
+
+    /// Cancel queued, running, failed and quarantined jobs.
+    Cancel,
+
+    /// Delete jobs, whatever their status.
+    Delete,
+}
+
+impl BulkOperation {
+    /// Get the name of this operation, e.g. "retry".
+    pub fn label(self) -> &'static str {
+        match self {
+            BulkOperation::Retry => "retry",
+            BulkOperation::Cancel => "cancel",
+            BulkOperation::Delete => "delete",
+        }
+    }
+
+    /// Get the status jobs are moved to by this operation, or `None` if they're deleted.
+    pub fn target_status(self) -> Option<job::Status> {
+        match self {
+            BulkOperation::Retry => Some(job::Status::Queued),
+            BulkOperation::Cancel => Some(job::Status::Cancelled),
+            BulkOperation::Delete => None,
+        }
+    }
+
+    /// Check whether this operation applies to a job with given status, i.e. whether clients can move it to this
+    /// operation's target status.
+    pub fn applies_to(self, status: &job::Status) -> bool {
+        match self.target_status() {
+            Some(target) => job::Transition::new(status.clone(), target).is_valid(),
+            None => true,
+        }
+    }
+}
+
+/// Outcome of an operation on all jobs with a tag, counting the jobs by whether they were changed.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct BulkResults {
+    /// Number of jobs that were retried, cancelled or deleted.
+    pub succeeded: u64,
+
+    /// Number of jobs left unchanged, as the operation didn't apply to their status, or their queue doesn't allow it.
+    pub skipped: u64,
+
+    /// Number of jobs that no longer exist, or that the client doesn't have access to.
+    pub not_found: u64,
+
+    /// IDs of the jobs that were changed, used to publish their events, but not returned to clients.
+    #[serde(skip)]
+    pub job_ids: Vec<u64>,
+}
+
+impl BulkResults {
+    /// Add the results of another batch to these results.
+    pub fn merge(&mut self, other: BulkResults) {
+        self.succeeded += other.succeeded;
+        self.skipped += other.skipped;
+        self.not_found += other.not_found;
+        self.job_ids.extend(other.job_ids);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate_apply() {
+        let apply_req: ApplyRequest = serde_json::from_str(r#"{"job_ids": [1, 2]}"#).unwrap();
+        assert!(apply_req.validate().is_ok());
+        let apply_req: ApplyRequest = serde_json::from_str(r#"{"queue": "a", "status": "failed"}"#).unwrap();
+        assert_eq!(apply_req.status, Some(job::Status::Failed));
+        assert!(apply_req.validate().is_ok());
+
+        assert!(ApplyRequest::default().validate().is_err());
+        let apply_req: ApplyRequest = serde_json::from_str(r#"{"job_ids": [1], "queue": "a"}"#).unwrap();
+        assert!(apply_req.validate().is_err());
+        let apply_req: ApplyRequest = serde_json::from_str(r#"{"job_ids": [1], "status": "failed"}"#).unwrap();
+        assert!(apply_req.validate().is_err());
+        let apply_req = ApplyRequest { job_ids: Some(vec![1; MAX_APPLY_JOB_IDS + 1]), ..Default::default() };
+        assert!(apply_req.validate().is_err());
+
+        assert!(serde_json::from_str::<ApplyRequest>(r#"{"jobs": [1]}"#).is_err());
+    }
+
+    #[test]
+    fn bulk_operations() {
+        assert!(BulkOperation::Retry.applies_to(&job::Status::Failed));
+        assert!(BulkOperation::Retry.applies_to(&job::Status::Quarantined));
+        assert!(!BulkOperation::Retry.applies_to(&job::Status::Running));
+        assert!(!BulkOperation::Retry.applies_to(&job::Status::Completed));
+        assert!(BulkOperation::Cancel.applies_to(&job::Status::Queued));
+        assert!(!BulkOperation::Cancel.applies_to(&job::Status::Cancelled));
+        assert!(BulkOperation::Delete.applies_to(&job::Status::Completed));
+
+        let mut results = BulkResults { succeeded: 2, job_ids: vec![1, 2], ..Default::default() };
+        results.merge(BulkResults { succeeded: 1, skipped: 3, not_found: 1, job_ids: vec![4] });
+        assert_eq!(results.succeeded, 3);
+        assert_eq!(results.job_ids, vec![1, 2, 4]);
+        assert_eq!(serde_json::to_string(&results).unwrap(), r#"{"succeeded":3,"skipped":3,"not_found":1}"#);
+    }
+}
@@ -0,0 +1,271 @@
+//! Defines the tenant a request is made on behalf of, which determines the queues, jobs and tags it can access, and
+//! the operations it can perform on them.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use actix_web::dev::Payload;
+use actix_web::{Error, FromRequest, HttpRequest};
+use futures::future::{ok, Ready};
+use serde::{Deserialize, Serialize};
+
+use crate::application::limits;
+use crate::models::job::JobMeta;
+use crate::models::queue::Settings;
+
+/// Separator between a tenant's namespace and the names of its queues and tags, e.g. queue "emails" in namespace
+/// "team-a" is stored as "team-a.emails".
+pub const NAMESPACE_SEPARATOR: char = '.';
+
+/// Roles given to API keys, determining the operations clients using them can perform.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can get information about queues and jobs, every role includes this.
+    Reader,
+
+    /// Can create jobs.
+    Submitter,
+
+    /// Can take jobs from queues, and report their progress and results.
+    Worker,
+
+    /// Can perform any operation, including deleting or purging queues and restoring deleted jobs.
+    Admin,
+}
+
+impl Role {
+    /// Roles given to namespace keys configured without any specific roles.
+    pub const DEFAULT: &'static [Role] = &[Role::Reader, Role::Submitter, Role::Worker];
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Role::Reader => "reader",
+            Role::Submitter => "submitter",
+            Role::Worker => "worker",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+/// Client a request is made on behalf of, as determined by its API key.
+///
+/// Tenants with a namespace only have access to queues and tags in that namespace, and jobs on those queues. Names are
+/// given by, and returned to, these tenants without their namespace, and are qualified with it when stored. Tenants
+/// without a namespace (i.e. using an admin key, or when authentication is disabled) have access to everything, and
+/// see names as stored.
+///
+/// Operations needing a given role may also be restricted to specific queues within the namespace, e.g. so that a key
+/// which can both submit and take jobs can only take them from some queues.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tenant {
+    namespace: Option<String>,
+    roles: Vec<Role>,
+    scopes: HashMap<Role, Vec<String>>,
+}
+
+impl Tenant {
+    /// Get a tenant with access to everything.
+    pub fn admin() -> Self {
+        Self {
+            namespace: None,
+            roles: vec![Role::Admin],
+            scopes: HashMap::new(),
+        }
+    }
+
+    /// Get a tenant with access to given namespace only, with the admin role within it.
+    pub fn with_namespace<S: Into<String>>(namespace: S) -> Self {
+        Self {
+            namespace: Some(namespace.into()),
+            roles: vec![Role::Admin],
+            scopes: HashMap::new(),
+        }
+    }
+
+    /// Replace this tenant's roles.
+    pub fn with_roles(mut self, roles: &[Role]) -> Self {
+        self.roles = roles.to_vec();
+        self
+    }
+
+    /// Restrict operations needing each given role to the given queues, named without the namespace.
+    pub fn with_scopes(mut self, scopes: HashMap<Role, Vec<String>>) -> Self {
+        self.scopes = scopes;
+        self
+    }
+
+    /// Check whether given namespace name is valid, allowed chars are: [a-zA-Z0-9_-] unless configured otherwise in
+    /// `[limits]`.
+    pub fn is_valid_namespace(namespace: &str) -> bool {
+        limits::get().is_valid_namespace(namespace)
+    }
+
+    /// Get the namespace a queue with given stored name is in, if any.
+    pub fn namespace_of(name: &str) -> Option<&str> {
+        name.split_once(NAMESPACE_SEPARATOR).map(|(namespace, _)| namespace)
+    }
+
+    /// Get this tenant's namespace, if any.
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// Check whether this tenant has access to everything.
+    pub fn is_admin(&self) -> bool {
+        self.namespace.is_none()
+    }
+
+    /// Check whether this tenant can perform operations requiring given role. The admin role can perform any
+    /// operation, and any role can perform operations requiring the reader role.
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&Role::Admin)
+            || self.roles.contains(&role)
+            || (role == Role::Reader && !self.roles.is_empty())
+    }
+
+    /// Check whether this tenant can perform operations needing given role on the queue with given stored name. Reading
+    /// is never restricted to specific queues, so only requires the queue to be in this tenant's namespace.
+    pub fn in_scope(&self, role: Role, queue: &str) -> bool {
+        match (self.unqualify(queue), self.scopes.get(&role)) {
+            (Some(name), Some(queues)) if role != Role::Reader => queues.iter().any(|scoped| scoped == name),
+            (name, _) => name.is_some(),
+        }
+    }
+
+    /// Get the stored names of the queues this tenant can perform operations needing given role on, or `None` if it
+    /// isn't restricted to specific queues.
+    pub fn scope(&self, role: Role) -> Option<Vec<String>> {
+        let queues = self.scopes.get(&role).filter(|_| role != Role::Reader)?;
+        Some(queues.iter().map(|queue| self.qualify(queue)).collect())
+    }
+
+    /// Get the stored name of a queue or tag with given name in this tenant's namespace.
+    pub fn qualify(&self, name: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, name),
+            None => name.to_owned(),
+        }
+    }
+
+    /// Get the name this tenant knows a queue or tag by from its stored name, or `None` if it's not in this tenant's
+    /// namespace.
+    pub fn unqualify<'a>(&self, name: &'a str) -> Option<&'a str> {
+        match &self.namespace {
+            Some(namespace) => name
+                .strip_prefix(namespace.as_str())
+                .and_then(|name| name.strip_prefix(NAMESPACE_SEPARATOR)),
+            None => Some(name),
+        }
+    }
+
+    /// Check whether a queue or tag with given stored name is in this tenant's namespace.
+    pub fn owns(&self, name: &str) -> bool {
+        self.unqualify(name).is_some()
+    }
+
+    /// Qualify the names of queues referred to by given queue settings with this tenant's namespace.
+    pub fn qualify_settings(&self, settings: &mut Settings) {
+        if let Some(shadow_to) = &settings.shadow_to {
+            settings.shadow_to = Some(self.qualify(shadow_to));
+        }
+    }
+
+    /// Rewrite the names of queues referred to by given queue settings to the names this tenant knows them by.
+    pub fn scope_settings(&self, settings: &mut Settings) {
+        if let Some(shadow_to) = &settings.shadow_to {
+            settings.shadow_to = Some(self.unqualify(shadow_to).unwrap_or(shadow_to).to_owned());
+        }
+    }
+
+    /// Rewrite a job's queue name and tags to the names this tenant knows them by.
+    pub fn scope_job(&self, job: &mut JobMeta) {
+        if !self.is_admin() {
+            job.map_names(|name| self.unqualify(name).unwrap_or(name).to_owned());
+        }
+    }
+}
+
+impl Default for Tenant {
+    fn default() -> Self {
+        Self::admin()
+    }
+}
+
+/// Extracts the tenant set on a request by `AuthMiddleware`, or an admin tenant if authentication is disabled.
+impl FromRequest for Tenant {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ok(req.extensions().get::<Tenant>().cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn namespaces() {
+        assert!(Tenant::is_valid_namespace("team-a_1"));
+        assert!(!Tenant::is_valid_namespace("team.a"));
+        assert!(!Tenant::is_valid_namespace(""));
+
+        let tenant = Tenant::with_namespace("team-a");
+        assert!(!tenant.is_admin());
+        assert_eq!(tenant.qualify("emails"), "team-a.emails");
+        assert_eq!(tenant.unqualify("team-a.emails"), Some("emails"));
+        assert_eq!(tenant.unqualify("team-a.nested.name"), Some("nested.name"));
+        assert_eq!(tenant.unqualify("team-ab.emails"), None);
+        assert_eq!(tenant.unqualify("team-a"), None);
+        assert!(!tenant.owns("emails"));
+
+        let admin = Tenant::default();
+        assert!(admin.is_admin());
+        assert_eq!(admin.qualify("team-a.emails"), "team-a.emails");
+        assert_eq!(admin.unqualify("team-a.emails"), Some("team-a.emails"));
+        assert!(admin.owns("emails"));
+    }
+
+    #[test]
+    fn roles() {
+        let admin = Tenant::admin();
+        assert!(admin.has_role(Role::Admin));
+        assert!(admin.has_role(Role::Worker));
+
+        let tenant = Tenant::with_namespace("team-a").with_roles(&[Role::Worker]);
+        assert!(tenant.has_role(Role::Worker));
+        assert!(tenant.has_role(Role::Reader));
+        assert!(!tenant.has_role(Role::Submitter));
+        assert!(!tenant.has_role(Role::Admin));
+
+        let tenant = tenant.with_roles(Role::DEFAULT);
+        assert!(tenant.has_role(Role::Submitter));
+        assert!(!tenant.has_role(Role::Admin));
+
+        assert!(!tenant.with_roles(&[]).has_role(Role::Reader));
+    }
+
+    #[test]
+    fn scopes() {
+        let mut scopes = HashMap::new();
+        scopes.insert(Role::Worker, vec!["emails".to_owned()]);
+        let tenant = Tenant::with_namespace("team-a").with_roles(Role::DEFAULT).with_scopes(scopes);
+        assert!(tenant.in_scope(Role::Worker, "team-a.emails"));
+        assert!(!tenant.in_scope(Role::Worker, "team-a.reports"));
+        assert!(!tenant.in_scope(Role::Worker, "team-b.emails"));
+        assert!(tenant.in_scope(Role::Submitter, "team-a.reports"));
+        assert!(!tenant.in_scope(Role::Submitter, "team-b.reports"));
+        assert!(tenant.in_scope(Role::Reader, "team-a.reports"));
+        assert_eq!(tenant.scope(Role::Worker), Some(vec!["team-a.emails".to_owned()]));
+        assert_eq!(tenant.scope(Role::Submitter), None);
+
+        let admin = Tenant::admin();
+        assert!(admin.in_scope(Role::Worker, "team-a.emails"));
+        assert_eq!(admin.scope(Role::Worker), None);
+    }
+}
@@ -0,0 +1,29 @@
+//! Defines the drain status of workers, used to stop giving them new jobs before they're restarted.
+
+use serde::Serialize;
+
+use crate::models::DateTime;
+
+/// Drain status of a single worker, identified by the ID it sends in the server's identity header.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WorkerStatus {
+    /// ID of the worker.
+    pub id: String,
+
+    /// Whether the worker is draining, in which case it's not given any new jobs.
+    pub draining: bool,
+
+    /// Date/time the worker started draining, if it's draining.
+    pub draining_since: Option<DateTime>,
+}
+
+impl WorkerStatus {
+    /// Create the status of given worker, which is draining if it has a date/time it started draining.
+    pub fn new(id: &str, draining_since: Option<DateTime>) -> Self {
+        Self {
+            id: id.to_owned(),
+            draining: draining_since.is_some(),
+            draining_since,
+        }
+    }
+}
@@ -28,10 +28,10 @@ pub async fn vec_from_redis_pipe<C: ConnectionLike, T: FromRedisValue>(
 macro_rules! transaction_async {
     ($conn:expr, $keys:expr, $body:expr) => {
         loop {
-            redis::cmd("WATCH").arg($keys).query_async($conn).await?;
+            let _: () = redis::cmd("WATCH").arg($keys).query_async($conn).await?;
 
             if let Some(response) = $body {
-                redis::cmd("UNWATCH").query_async($conn).await?;
+                let _: () = redis::cmd("UNWATCH").query_async($conn).await?;
                 break response;
             }
         }
@@ -0,0 +1,184 @@
+//! Integration with systemd's service manager, for socket activation, readiness notification, and watchdog pings.
+//!
+//! All functions are no-ops when not running under systemd, i.e. when the relevant environment variables aren't set,
+//! or on platforms other than Unix.
+
+use std::env;
+use std::io;
+use std::net::TcpListener;
+use std::time::Duration;
+
+use log::{debug, error, info};
+
+/// File descriptor of the first socket passed by systemd, see `sd_listen_fds(3)`.
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Get the listening sockets passed to this process by systemd socket activation, if any.
+///
+/// Environment variables describing the sockets are removed, so that they aren't inherited by child processes.
+pub fn listeners() -> Vec<TcpListener> {
+    let num_fds = listen_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    (0..num_fds).filter_map(listener_from_fd).collect()
+}
+
+/// Get the number of sockets passed to the process with given ID, given the values of the `LISTEN_PID` and
+/// `LISTEN_FDS` environment variables.
+fn listen_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    match (listen_pid.and_then(|p| p.parse::<u32>().ok()), listen_fds.and_then(|n| n.parse().ok())) {
+        (Some(listen_pid), Some(num_fds)) if listen_pid == pid => num_fds,
+        _ => 0,
+    }
+}
+
+#[cfg(unix)]
+#[allow(unsafe_code)]
+fn listener_from_fd(offset: usize) -> Option<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let fd = LISTEN_FDS_START + offset as i32;
+    // safe since systemd passes ownership of these descriptors to this process, and they're only taken once
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    match listener.local_addr() {
+        Ok(addr) => {
+            debug!("Using socket {} passed by systemd, listening on {}", fd, addr);
+            Some(listener)
+        }
+        Err(err) => {
+            error!("Ignoring socket {} passed by systemd, it's not a TCP listener: {}", fd, err);
+            None
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn listener_from_fd(_offset: usize) -> Option<TcpListener> {
+    None
+}
+
+/// Notify systemd that the server has finished starting up.
+pub fn notify_ready() {
+    notify_or_log("READY=1");
+}
+
+fn notify_or_log(state: &str) {
+    if let Err(err) = notify(state) {
+        error!("Failed to notify systemd of {}: {}", state, err);
+    }
+}
+
+/// Send given state to systemd's notification socket, returning false if not running under systemd.
+pub fn notify(state: &str) -> io::Result<bool> {
+    match env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => send_notification(&socket, state).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+#[cfg(unix)]
+fn send_notification(socket: &std::ffi::OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let sock = UnixDatagram::unbound()?;
+    match socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            sock.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => return Err(io::Error::new(io::ErrorKind::Unsupported, "abstract sockets not supported")),
+        None => {
+            sock.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_notification(_socket: &std::ffi::OsStr, _state: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// Get the interval systemd expects watchdog pings at, if it's enabled for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    watchdog_timeout(
+        env::var("WATCHDOG_USEC").ok().as_deref(),
+        env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+    .map(|timeout| timeout / 2) // ping well within the timeout, as recommended by `sd_watchdog_enabled(3)`
+}
+
+/// Get the watchdog timeout for the process with given ID, given the values of the `WATCHDOG_USEC` and
+/// `WATCHDOG_PID` environment variables.
+fn watchdog_timeout(usec: Option<&str>, watchdog_pid: Option<&str>, pid: u32) -> Option<Duration> {
+    let usec: u64 = usec?.parse().ok().filter(|usec| *usec > 0)?;
+    match watchdog_pid {
+        Some(watchdog_pid) if watchdog_pid.parse() != Ok(pid) => None,
+        _ => Some(Duration::from_micros(usec)),
+    }
+}
+
+/// Start periodic background task that sends watchdog pings to systemd, if its watchdog is enabled. Pings stop if
+/// the server's event loop hangs, so that systemd can restart it.
+pub fn start_watchdog() {
+    let interval = match watchdog_interval() {
+        Some(interval) => interval,
+        None => return,
+    };
+    info!("Sending systemd watchdog pings every {}", humantime::format_duration(interval));
+    actix_rt::spawn(async move {
+        loop {
+            notify_or_log("WATCHDOG=1");
+            actix_rt::time::delay_for(interval).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_listen_fds() {
+        assert_eq!(listen_fds(Some("123"), Some("2"), 123), 2);
+        assert_eq!(listen_fds(Some("456"), Some("2"), 123), 0);
+        assert_eq!(listen_fds(None, Some("2"), 123), 0);
+        assert_eq!(listen_fds(Some("123"), None, 123), 0);
+        assert_eq!(listen_fds(Some("123"), Some("x"), 123), 0);
+    }
+
+    #[test]
+    fn parse_watchdog() {
+        assert_eq!(watchdog_timeout(Some("30000000"), None, 123), Some(Duration::from_secs(30)));
+        assert_eq!(watchdog_timeout(Some("30000000"), Some("123"), 123), Some(Duration::from_secs(30)));
+        assert_eq!(watchdog_timeout(Some("30000000"), Some("456"), 123), None);
+        assert_eq!(watchdog_timeout(Some("0"), None, 123), None);
+        assert_eq!(watchdog_timeout(None, None, 123), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn send() {
+        let path = env::temp_dir().join(format!("ocypod-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        send_notification(path.as_os_str(), "READY=1").unwrap();
+        let mut buf = [0; 16];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+//! Ocypod server run in-process, for integration tests of workers and other clients. Only built with the "test-util"
+//! feature.
+//!
+//! Each `TestServer` listens on a random port, and stores its data in its own `redis-server` started on another
+//! random port with persistence disabled, so all data is kept in memory and discarded once the server is dropped.
+//! This lets tests run in parallel without Docker or a shared Redis instance, though the `redis-server` binary must
+//! be installed.
+
+use std::io;
+use std::net::TcpListener;
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+
+use actix_web::{dev::Server, web, App, HttpServer};
+
+use crate::application::cache::ResponseCache;
+use crate::application::drain::Drain;
+use crate::application::leader::Leadership;
+use crate::application::shard::RedisShards;
+use crate::application::slowlog::SlowLog;
+use crate::application::throttle::PollThrottle;
+use crate::application::{monitor, reconcile};
+use crate::config::{Config, ReconcileConfig};
+use crate::events::anomaly::AnomalyDetector;
+use crate::events::hooks::Hooks;
+use crate::events::EventBus;
+use crate::handlers;
+use crate::logging::{LogFilter, LogSettings};
+use crate::middleware::auth::{ApiKeys, AuthMiddleware};
+use crate::middleware::circuit_breaker::CircuitBreaker;
+use crate::models::{ApplicationState, Duration};
+
+/// Ocypod server running in this process, which is stopped along with its Redis server when dropped.
+#[derive(Debug)]
+pub struct TestServer {
+    url: String,
+    server: Server,
+    redis: Child,
+}
+
+impl TestServer {
+    /// Start a server using the default configuration.
+    ///
+    /// Must be called from within an actix system, e.g. in a test annotated with `#[actix_rt::test]`.
+    pub async fn start() -> io::Result<Self> {
+        Self::start_with_config(Config::default()).await
+    }
+
+    /// Start a server using given configuration, creating any queues it defines, and requiring any API keys it
+    /// configures. Its Redis and HTTP server addresses are ignored, and only the job monitors are run in the
+    /// background.
+    ///
+    /// Must be called from within an actix system, e.g. in a test annotated with `#[actix_rt::test]`.
+    pub async fn start_with_config(mut config: Config) -> io::Result<Self> {
+        let redis_port = free_port()?;
+        let mut redis = Command::new("redis-server")
+            .args(["--bind", "127.0.0.1", "--port", &redis_port.to_string(), "--save", "", "--appendonly", "no"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        config.redis.url = format!("redis://127.0.0.1:{}", redis_port);
+        config.redis.urls.clear();
+        config.redis.replica_urls.clear();
+        if config.redis.startup_wait.is_zero() {
+            // give the Redis server time to start listening
+            config.redis.startup_wait = Duration::from_secs(5);
+        }
+
+        match Self::serve(config).await {
+            Ok((url, server)) => Ok(Self { url, server, redis }),
+            Err(err) => {
+                let _ = redis.kill();
+                let _ = redis.wait();
+                Err(err)
+            }
+        }
+    }
+
+    /// Base URL of this server, e.g. `http://127.0.0.1:41234`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Get a client for this server.
+    #[cfg(feature = "client")]
+    pub fn client(&self) -> crate::client::OcypodClient {
+        crate::client::OcypodClient::new(self.url.as_str())
+    }
+
+    /// Connect to Redis, then start the job monitors and an HTTP server on a random port, returning its URL.
+    async fn serve(config: Config) -> io::Result<(String, Server)> {
+        let to_io_error = |err: crate::models::OcyError| io::Error::other(err.to_string());
+        let circuit_breaker =
+            Arc::new(CircuitBreaker::new(config.redis.breaker_threshold, config.redis.breaker_cooldown.0));
+        let redis_shards = RedisShards::connect_with_retry(&config.redis)
+            .await
+            .map_err(to_io_error)?
+            .with_circuit_breaker(circuit_breaker.clone());
+        let queues = config.queue.clone().unwrap_or_default();
+        reconcile::reconcile_queues(&redis_shards, &queues, &ReconcileConfig::default())
+            .await
+            .map_err(to_io_error)?;
+
+        let events = EventBus::new();
+        let drain = Drain::new();
+        monitor::start_monitors(&redis_shards, &config.server, &events, &Leadership::always(), &drain);
+
+        let max_body_size = config.server.json_limit();
+        let api_keys = Arc::new(ApiKeys::new(&config.auth));
+        let auth_shards = redis_shards.clone();
+        let app_state = web::Data::new(ApplicationState {
+            redis_shards,
+            circuit_breaker,
+            events,
+            hooks: Hooks::new(&config.hooks),
+            log_filter: Arc::new(LogFilter::new(LogSettings::new(config.server.log_level)).map_err(to_io_error)?),
+            slow_log: Arc::new(SlowLog::new(None, 0)),
+            drain,
+            anomalies: Arc::new(AnomalyDetector::new(&config.anomalies)),
+            poll_throttle: PollThrottle::new(&config.server.poll_throttle),
+            response_cache: ResponseCache::new(&config.server.response_cache),
+            config,
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let server = HttpServer::new(move || {
+            App::new()
+                .wrap(AuthMiddleware::new(api_keys.clone(), auth_shards.clone()))
+                .app_data(app_state.clone())
+                .app_data(web::JsonConfig::default().limit(max_body_size))
+                .configure(handlers::configure)
+        })
+        .workers(1)
+        .disable_signals()
+        .listen(listener)?
+        .run();
+        Ok((url, server))
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        actix_rt::spawn(self.server.stop(false));
+        let _ = self.redis.kill();
+        let _ = self.redis.wait();
+    }
+}
+
+/// Find a port that's currently free to listen on. Another process could take it before it's used, but that's
+/// unlikely enough for tests.
+fn free_port() -> io::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
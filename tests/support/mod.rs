@@ -1,6 +1,6 @@
 // Copied from: https://github.com/mitsuhiko/redis-rs/blob/master/tests/support/mod.rs (with some minor tweaks).
 
-#![allow(dead_code)]
+#![allow(dead_code, unexpected_cfgs, clippy::needless_borrows_for_generic_args)]
 
 use std::{
     env, fs,
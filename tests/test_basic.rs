@@ -3,11 +3,15 @@
 //! Requires Redis to be installed, so that the tests can start/stop Redis servers as necessary
 //! using the `redis-server` binary.
 
+#![allow(clippy::bool_assert_comparison, clippy::assertions_on_constants, clippy::field_reassign_with_default)]
+
 use std::time;
 use std::collections::HashMap;
 use redis::aio::Connection;
-use ocypod::application::RedisManager;
-use ocypod::models::{queue, job, ServerInfo, Duration, OcyError};
+use ocypod::application::system::SystemQueue;
+use ocypod::application::{schema, RedisManager};
+use ocypod::config::{Config, StickySessionsConfig};
+use ocypod::models::{queue, job, quota, tag, ServerInfo, Duration, IntegrityReport, OcyError, QueueInfo};
 use crate::support::*;
 
 mod support;
@@ -53,7 +57,7 @@ impl QueueWrapper {
     }
 
     async fn new_job(&self, conn: &mut Connection, job_req: &job::CreateRequest) -> job::JobMeta {
-        let job_id = RedisManager::create_job(conn, &self.queue_name, job_req).await.unwrap();
+        let job_id = RedisManager::create_job(conn, &self.queue_name, job_req).await.unwrap().id();
         let job_info = self.job_meta(conn, job_id).await;
         assert_eq!(job_info.status(), job::Status::Queued);
         job_info
@@ -77,7 +81,7 @@ impl QueueWrapper {
     }
 
     async fn fail_job(&self, conn: &mut Connection, job_id: u64) -> job::JobMeta {
-        let update_req = job::UpdateRequest { status: Some(job::Status::Failed), output: None };
+        let update_req = job::UpdateRequest { status: Some(job::Status::Failed), ..Default::default() };
         RedisManager::update_job(conn, job_id, &update_req).await.unwrap();
         let job_info = self.job_meta(conn, job_id).await;
         assert_eq!(job_info.status(), job::Status::Failed);
@@ -85,7 +89,7 @@ impl QueueWrapper {
     }
 
     async fn complete_job(&self, conn: &mut Connection, job_id: u64) -> job::JobMeta {
-        let update_req = job::UpdateRequest { status: Some(job::Status::Completed), output: None };
+        let update_req = job::UpdateRequest { status: Some(job::Status::Completed), ..Default::default() };
         RedisManager::update_job(conn, job_id, &update_req).await.unwrap();
         let job_info = self.job_meta(conn, job_id).await;
         assert_eq!(job_info.status(), job::Status::Completed);
@@ -121,10 +125,13 @@ async fn queue_create_delete() {
     let (_ctx, mut conn) = init().await;
 
     let queue_settings = queue::Settings::default();
-    assert_eq!(RedisManager::delete_queue(&mut conn, DEFAULT_QUEUE).await.unwrap(), false);
+    assert_eq!(RedisManager::delete_queue(&mut conn, DEFAULT_QUEUE, false).await.unwrap(), queue::Deletion::NotFound);
     assert_eq!(RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &queue_settings).await.unwrap(), true);
     assert_eq!(RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &queue_settings).await.unwrap(), false);
-    assert_eq!(RedisManager::delete_queue(&mut conn, DEFAULT_QUEUE).await.unwrap(), true);
+    assert_eq!(
+        RedisManager::delete_queue(&mut conn, DEFAULT_QUEUE, false).await.unwrap(),
+        queue::Deletion::Deleted(queue::DeletedJobs { queued: 0 })
+    );
 }
 
 #[tokio::test]
@@ -143,7 +150,7 @@ async fn queue_delete_jobs() {
 
     // create completed job
     let job_payload = qw.next_job(&mut conn).await;
-    let update_req = job::UpdateRequest { status: Some(job::Status::Completed), output: None };
+    let update_req = job::UpdateRequest { status: Some(job::Status::Completed), ..Default::default() };
     RedisManager::update_job(&mut conn, job_payload.id(), &update_req).await.unwrap();
     assert_eq!(qw.queue_size(&mut conn).await, 8);
 
@@ -154,11 +161,18 @@ async fn queue_delete_jobs() {
 
     // create cancelled job
     let job_payload = qw.next_job(&mut conn).await;
-    let update_req = job::UpdateRequest { status: Some(job::Status::Cancelled), output: None };
+    let update_req = job::UpdateRequest { status: Some(job::Status::Cancelled), ..Default::default() };
     RedisManager::update_job(&mut conn, job_payload.id(), &update_req).await.unwrap();
     assert_eq!(qw.queue_size(&mut conn).await, 6);
 
-    assert_eq!(RedisManager::delete_queue(&mut conn, DEFAULT_QUEUE).await, Ok(true));
+    // queue still has queued jobs, so can't be deleted without forcing it
+    let queued = queue::DeletedJobs { queued: 6 };
+    assert_eq!(
+        RedisManager::delete_queue(&mut conn, DEFAULT_QUEUE, false).await,
+        Ok(queue::Deletion::NotEmpty(queued.clone()))
+    );
+    assert_eq!(qw.queue_size(&mut conn).await, 6);
+    assert_eq!(RedisManager::delete_queue(&mut conn, DEFAULT_QUEUE, true).await, Ok(queue::Deletion::Deleted(queued)));
     assert_eq!(qw.job_status(&mut conn, 1).await, job::Status::Running);
     assert_eq!(qw.job_status(&mut conn, 2).await, job::Status::Completed);
     assert_eq!(qw.job_status(&mut conn, 3).await, job::Status::Failed);
@@ -170,6 +184,39 @@ async fn queue_delete_jobs() {
     }
 }
 
+#[tokio::test]
+async fn queue_snapshot() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let other_qw = QueueWrapper::new("other");
+    other_qw.create_queue(&mut conn).await;
+
+    assert_eq!(
+        RedisManager::queue_snapshot(&mut conn, "missing").await.unwrap_err(),
+        OcyError::NoSuchQueue("missing".to_owned())
+    );
+
+    let queued_id = qw.new_default_job(&mut conn).await.id();
+    let running_id = qw.new_running_default_job(&mut conn).await.id();
+    let completed_id = qw.new_running_default_job(&mut conn).await.id();
+    qw.complete_job(&mut conn, completed_id).await;
+    other_qw.new_running_default_job(&mut conn).await;
+
+    let snapshot = RedisManager::queue_snapshot(&mut conn, DEFAULT_QUEUE).await.unwrap();
+    assert_eq!(snapshot.queue, DEFAULT_QUEUE);
+    assert_eq!(snapshot.settings, queue::Settings::default());
+    let mut jobs: Vec<(u64, job::Status)> = snapshot.jobs.iter().map(|job| (job.id(), job.status())).collect();
+    jobs.sort_by_key(|(job_id, _)| *job_id);
+    assert_eq!(
+        jobs,
+        vec![
+            (queued_id, job::Status::Queued),
+            (running_id, job::Status::Running),
+            (completed_id, job::Status::Completed),
+        ]
+    );
+}
+
 #[tokio::test]
 async fn queue_names() {
     let (_ctx, mut conn) = init().await;
@@ -193,6 +240,24 @@ async fn queue_settings() {
         expires_after: Duration::from_secs(86400),
         retries: 0,
         retry_delays: Vec::new(),
+        quarantine_after: 3,
+        quick_fail_window: Duration::from_secs(5),
+        expiry_check_statuses: Some(vec![job::Status::Completed]),
+        expiry_check_interval: Some(Duration::from_secs(3600)),
+        timeout_check_interval: Some(Duration::from_secs(5)),
+        retry_check_interval: None,
+        sla: Some(Duration::from_secs(3600)),
+        total_timeout: Some(Duration::from_secs(7200)),
+        max_queue_age: Some(Duration::from_secs(86400)),
+        max_input_size: Some(1024),
+        max_output_size: None,
+        output_size_policy: queue::OutputSizePolicy::Truncate,
+        allowed_transitions: Some(vec![job::Transition::new(job::Status::Running, job::Status::Completed)]),
+        retry_budget: Some(10),
+        retry_budget_cooldown: Duration::from_secs(60),
+        shadow_to: Some("b".to_owned()),
+        shadow_percent: 50,
+        result_ttl: Duration::from_secs(600),
     };
     assert_eq!(RedisManager::queue_settings(&mut conn, queue_name).await, Err(OcyError::NoSuchQueue(queue_name.to_owned())));
     assert_eq!(RedisManager::create_or_update_queue(&mut conn, queue_name, &settings).await, Ok(true));
@@ -209,6 +274,72 @@ async fn queue_settings() {
     settings.expires_after = Duration::from_secs(1234);
     assert_eq!(RedisManager::create_or_update_queue(&mut conn, queue_name, &settings).await, Ok(false));
     assert_eq!(RedisManager::queue_settings(&mut conn, queue_name).await.unwrap(), settings);
+
+    settings.timeout_check_interval = None;
+    settings.retry_check_interval = Some(Duration::from_secs(300));
+    settings.sla = None;
+    settings.max_input_size = None;
+    settings.max_output_size = Some(2048);
+    settings.retry_budget = None;
+    settings.shadow_to = None;
+    assert_eq!(RedisManager::create_or_update_queue(&mut conn, queue_name, &settings).await, Ok(false));
+    assert_eq!(RedisManager::queue_settings(&mut conn, queue_name).await.unwrap(), settings);
+}
+
+#[tokio::test]
+async fn queue_settings_update() {
+    let (_ctx, mut conn) = init().await;
+    let queue_name = "a";
+    let mut settings = queue::Settings {
+        retries: 2,
+        expiry_check_interval: Some(Duration::from_secs(3600)),
+        sla: Some(Duration::from_secs(600)),
+        ..Default::default()
+    };
+    let mut update = queue::SettingsUpdate {
+        timeout: Some(Duration::from_secs(30)),
+        ..Default::default()
+    };
+    assert_eq!(RedisManager::update_queue(&mut conn, queue_name, &update).await, Err(OcyError::NoSuchQueue(queue_name.to_owned())));
+    assert_eq!(RedisManager::create_or_update_queue(&mut conn, queue_name, &settings).await, Ok(true));
+
+    // only given fields are changed
+    settings.timeout = Duration::from_secs(30);
+    assert_eq!(RedisManager::update_queue(&mut conn, queue_name, &update).await.unwrap(), settings);
+    assert_eq!(RedisManager::queue_settings(&mut conn, queue_name).await.unwrap(), settings);
+
+    // nullable fields can be reset to use the server's settings
+    update = queue::SettingsUpdate {
+        retries: Some(5),
+        expiry_check_interval: Some(None),
+        ..Default::default()
+    };
+    settings.retries = 5;
+    settings.expiry_check_interval = None;
+    assert_eq!(RedisManager::update_queue(&mut conn, queue_name, &update).await.unwrap(), settings);
+    assert_eq!(RedisManager::queue_settings(&mut conn, queue_name).await.unwrap(), settings);
+}
+
+#[tokio::test]
+async fn queue_summaries() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let empty = QueueWrapper::new("empty");
+    empty.create_queue(&mut conn).await;
+
+    qw.new_default_job(&mut conn).await;
+    qw.new_default_job(&mut conn).await;
+    qw.new_running_default_job(&mut conn).await;
+
+    let summaries = RedisManager::queue_summaries(&mut conn).await.unwrap();
+    assert_eq!(summaries.len(), 2);
+    let summary = &summaries[DEFAULT_QUEUE];
+    assert_eq!(summary.jobs.queued, 2);
+    assert_eq!(summary.jobs.running, 1);
+    assert_eq!(summary.timeout, queue::Settings::default().timeout);
+    assert!(summary.oldest_queued_age.is_some());
+    assert_eq!(summaries["empty"].jobs, QueueInfo::default());
+    assert_eq!(summaries["empty"].oldest_queued_age, None);
 }
 
 #[tokio::test]
@@ -350,6 +481,136 @@ async fn job_deletion() {
     assert!(!RedisManager::delete_job(&mut conn, job_id_queued).await.unwrap());
 }
 
+#[tokio::test]
+async fn apply_tag() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+
+    let job_req = job::CreateRequest { tags: Some(vec!["existing".to_string()]), ..Default::default() };
+    let tagged = qw.new_job(&mut conn, &job_req).await.id();
+    let untagged = qw.new_default_job(&mut conn).await.id();
+
+    let results = RedisManager::apply_tag(&mut conn, "incident", &[untagged, tagged, 999], None, None).await.unwrap();
+    assert_eq!(results.tagged, vec![tagged, untagged]);
+    assert_eq!(results.not_found, vec![999]);
+    assert_eq!(RedisManager::tagged_job_ids(&mut conn, "incident").await.unwrap(), vec![tagged, untagged]);
+    assert_eq!(qw.job_meta(&mut conn, tagged).await.tags(), Some(vec!["existing".to_string(), "incident".to_string()]));
+    assert_eq!(qw.job_meta(&mut conn, untagged).await.tags(), Some(vec!["incident".to_string()]));
+
+    // applying a tag again leaves jobs as they are
+    let results = RedisManager::apply_tag(&mut conn, "incident", &[tagged], None, None).await.unwrap();
+    assert_eq!(results.tagged, vec![tagged]);
+    assert_eq!(qw.job_meta(&mut conn, tagged).await.tags().unwrap().len(), 2);
+
+    // jobs on queues outside the client's namespace or scope aren't tagged
+    let results = RedisManager::apply_tag(&mut conn, "other", &[tagged], Some("ns"), None).await.unwrap();
+    assert_eq!(results.not_found, vec![tagged]);
+    let queues = vec!["b".to_string()];
+    let results = RedisManager::apply_tag(&mut conn, "other", &[tagged], None, Some(&queues)).await.unwrap();
+    assert_eq!(results.not_found, vec![tagged]);
+    assert!(RedisManager::tagged_job_ids(&mut conn, "other").await.unwrap().is_empty());
+
+    assert!(RedisManager::apply_tag(&mut conn, "bad tag", &[tagged], None, None).await.is_err());
+    let job_ids = RedisManager::filtered_queue_job_ids(&mut conn, DEFAULT_QUEUE, Some(&job::Status::Queued)).await;
+    assert_eq!(job_ids.unwrap(), vec![tagged, untagged]);
+}
+
+#[tokio::test]
+async fn system_queue() {
+    let (_ctx, mut conn) = init().await;
+    let queue_name = SystemQueue::Callbacks.name();
+    let settings = SystemQueue::Callbacks.settings(&Config::default());
+    assert!(RedisManager::create_or_update_queue(&mut conn, queue_name, &settings).await.unwrap());
+    assert_eq!(RedisManager::queue_settings(&mut conn, queue_name).await.unwrap(), settings);
+
+    let job_id = RedisManager::create_job(&mut conn, queue_name, &job::CreateRequest::default()).await.unwrap().id();
+    assert_eq!(RedisManager::next_queued_job(&mut conn, queue_name).await.unwrap().unwrap().id(), job_id);
+
+    // clients can't tag jobs on system queues
+    let results = RedisManager::apply_tag(&mut conn, "a", &[job_id], None, None).await.unwrap();
+    assert_eq!(results.not_found, vec![job_id]);
+}
+
+#[tokio::test]
+async fn bulk_tag_operations() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let recovery_window = Duration::from_secs(0);
+
+    let job_req = job::CreateRequest { tags: Some(vec!["incident".to_string()]), ..Default::default() };
+    let failed = qw.new_running_job(&mut conn, &job_req).await.id();
+    qw.fail_job(&mut conn, failed).await;
+    let completed = qw.new_running_job(&mut conn, &job_req).await.id();
+    qw.complete_job(&mut conn, completed).await;
+    let queued = qw.new_job(&mut conn, &job_req).await.id();
+
+    // only failed jobs are retried, completed and queued jobs are skipped
+    let results = RedisManager::bulk_tag_operation(
+        &mut conn, "incident", tag::BulkOperation::Retry, None, None, &recovery_window,
+    ).await.unwrap();
+    assert_eq!((results.succeeded, results.skipped, results.not_found), (1, 2, 0));
+    assert_eq!(results.job_ids, vec![failed]);
+    assert_eq!(qw.job_status(&mut conn, failed).await, job::Status::Queued);
+
+    // jobs outside the client's namespace aren't changed
+    let results = RedisManager::bulk_tag_operation(
+        &mut conn, "incident", tag::BulkOperation::Cancel, Some("ns"), None, &recovery_window,
+    ).await.unwrap();
+    assert_eq!((results.succeeded, results.skipped, results.not_found), (0, 0, 3));
+
+    let results = RedisManager::bulk_tag_operation(
+        &mut conn, "incident", tag::BulkOperation::Cancel, None, None, &recovery_window,
+    ).await.unwrap();
+    assert_eq!((results.succeeded, results.skipped, results.not_found), (2, 1, 0));
+    assert_eq!(qw.job_status(&mut conn, queued).await, job::Status::Cancelled);
+    assert_eq!(qw.queue_size(&mut conn).await, 0);
+
+    let results = RedisManager::bulk_tag_operation(
+        &mut conn, "incident", tag::BulkOperation::Delete, None, None, &recovery_window,
+    ).await.unwrap();
+    assert_eq!((results.succeeded, results.skipped, results.not_found), (3, 0, 0));
+    assert_eq!(RedisManager::job_status(&mut conn, completed).await, Err(OcyError::NoSuchJob(completed)));
+    assert!(RedisManager::tagged_job_ids(&mut conn, "incident").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn job_trash() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let recovery_window = Duration::from_secs(3600);
+
+    let job_req = job::CreateRequest { tags: Some(vec!["a".to_owned()]), ..Default::default() };
+    let queued = qw.new_job(&mut conn, &job_req).await.id();
+    assert_eq!(RedisManager::trash_job(&mut conn, queued, &recovery_window).await, Ok(true));
+    assert_eq!(RedisManager::trash_job(&mut conn, queued, &recovery_window).await, Ok(false));
+    assert_eq!(RedisManager::job_status(&mut conn, queued).await, Err(OcyError::NoSuchJob(queued)));
+    assert_eq!(qw.queue_size(&mut conn).await, 0);
+    assert!(RedisManager::tagged_job_ids(&mut conn, "a").await.unwrap().is_empty());
+
+    // restored jobs are added back to the queues and tags they were removed from
+    RedisManager::restore_job(&mut conn, queued).await.unwrap();
+    assert_eq!(qw.job_status(&mut conn, queued).await, job::Status::Queued);
+    assert_eq!(qw.queue_size(&mut conn).await, 1);
+    assert_eq!(RedisManager::tagged_job_ids(&mut conn, "a").await.unwrap(), vec![queued]);
+    assert_eq!(RedisManager::restore_job(&mut conn, queued).await, Err(OcyError::NoSuchJob(queued)));
+
+    let completed = qw.new_running_default_job(&mut conn).await.id();
+    qw.complete_job(&mut conn, completed).await;
+    assert_eq!(RedisManager::ended_queue_size(&mut conn).await, Ok(1));
+    assert_eq!(RedisManager::trash_job(&mut conn, completed, &recovery_window).await, Ok(true));
+    assert_eq!(RedisManager::ended_queue_size(&mut conn).await, Ok(0));
+    RedisManager::restore_job(&mut conn, completed).await.unwrap();
+    assert_eq!(qw.job_status(&mut conn, completed).await, job::Status::Completed);
+    assert_eq!(RedisManager::ended_queue_size(&mut conn).await, Ok(1));
+
+    // jobs can no longer be restored once purged
+    let empty: Vec<u64> = Vec::new();
+    assert_eq!(RedisManager::purge_trash(&mut conn, &queue::Batching::default()).await.unwrap(), empty);
+    assert_eq!(RedisManager::trash_job(&mut conn, completed, &Duration::from_secs(0)).await, Ok(true));
+    assert_eq!(RedisManager::purge_trash(&mut conn, &queue::Batching::default()).await.unwrap(), vec![completed]);
+    assert_eq!(RedisManager::restore_job(&mut conn, completed).await, Err(OcyError::NoSuchJob(completed)));
+}
+
 #[tokio::test]
 async fn job_retry_no_queue() {
     let (_ctx, mut conn) = init().await;
@@ -377,10 +638,13 @@ async fn job_retry_no_queue() {
     assert_eq!(job_info.retry_delays(), None);
 
     // delete job's original queue
-    assert!(RedisManager::delete_queue(&mut conn, DEFAULT_QUEUE).await.unwrap());
+    assert!(matches!(
+        RedisManager::delete_queue(&mut conn, DEFAULT_QUEUE, false).await.unwrap(),
+        queue::Deletion::Deleted(_)
+    ));
 
     // 1st retry
-    assert_eq!(RedisManager::check_job_retries(&mut conn).await.unwrap(), Vec::<u64>::new());
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), Vec::<u64>::new());
     let job_info = qw.job_meta(&mut conn, job_id).await;
     assert_eq!(job_info.status(), job::Status::Failed);
     assert_eq!(job_info.retries_attempted(), 0);
@@ -413,23 +677,23 @@ async fn job_retries() {
     assert_eq!(job_info.retry_delays(), None);
 
     // 1st retry
-    assert_eq!(RedisManager::check_job_retries(&mut conn).await.unwrap(), vec![job_id]);
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id]);
     let job_info = qw.job_meta(&mut conn, job_id).await;
     assert_eq!(job_info.status(), job::Status::Queued);
     assert_eq!(job_info.retries_attempted(), 1);
 
     // ensure it doesn't get retried while already running
     let empty: Vec<u64> = Vec::new();
-    assert_eq!(RedisManager::check_job_retries(&mut conn).await.unwrap(), empty);
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
 
     // 2nd failure: job timeout
     let job_id = qw.next_job(&mut conn).await.id();
     tokio::time::delay_for(time::Duration::from_secs(2)).await;
-    assert_eq!(RedisManager::check_job_timeouts(&mut conn).await.unwrap(), vec![job_id]);
+    assert_eq!(RedisManager::check_job_timeouts(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id]);
     assert_eq!(qw.job_status(&mut conn, job_id).await, job::Status::TimedOut);
 
     // 2nd retry
-    assert_eq!(RedisManager::check_job_retries(&mut conn).await.unwrap(), vec![job_id]);
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id]);
     let job_info = qw.job_meta(&mut conn, job_id).await;
     assert_eq!(job_info.status(), job::Status::Queued);
     assert_eq!(job_info.retries_attempted(), 2);
@@ -439,7 +703,7 @@ async fn job_retries() {
     qw.fail_job(&mut conn, job_id).await;
 
     // 3rd retry
-    assert_eq!(RedisManager::check_job_retries(&mut conn).await.unwrap(), vec![job_id]);
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id]);
     let job_info = qw.job_meta(&mut conn, job_id).await;
     assert_eq!(job_info.status(), job::Status::Queued);
     assert_eq!(job_info.retries_attempted(), 3);
@@ -449,12 +713,750 @@ async fn job_retries() {
     qw.fail_job(&mut conn, job_id).await;
 
     // ensure it doesn't get retries any more
-    assert_eq!(RedisManager::check_job_retries(&mut conn).await.unwrap(), empty);
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
     let job_info = qw.job_meta(&mut conn, job_id).await;
     assert_eq!(job_info.status(), job::Status::Failed);
     assert_eq!(job_info.retries_attempted(), 3);
 }
 
+#[tokio::test]
+async fn job_quarantine() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::new(DEFAULT_QUEUE);
+    let settings = queue::Settings { retries: 3, quarantine_after: 2, ..Default::default() };
+    assert!(RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap());
+
+    let empty: Vec<u64> = Vec::new();
+
+    // 1st quick failure, counts as a strike but job is still retried
+    let job_id = qw.new_running_default_job(&mut conn).await.id();
+    let job_info = qw.fail_job(&mut conn, job_id).await;
+    assert_eq!(job_info.quarantine_after(), 2);
+    assert_eq!(job_info.poison_strikes(), 1);
+    assert_eq!(RedisManager::check_job_quarantine(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id]);
+
+    // 2nd quick failure, job is quarantined rather than retried, ending when it's quarantined
+    let job_id = qw.next_job(&mut conn).await.id();
+    let failed_info = qw.fail_job(&mut conn, job_id).await;
+    assert_eq!(failed_info.poison_strikes(), 2);
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
+    tokio::time::delay_for(time::Duration::from_millis(10)).await;
+    assert_eq!(RedisManager::check_job_quarantine(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id]);
+    let job_info = qw.job_meta(&mut conn, job_id).await;
+    assert_eq!(job_info.status(), job::Status::Quarantined);
+    assert!(job_info.quarantine_reason().is_some());
+    assert!(job_info.ended());
+    assert!(job_info.ended_at().unwrap().millis_since(&failed_info.ended_at().unwrap()) > 0);
+
+    let quarantined = RedisManager::quarantined_jobs(&mut conn, DEFAULT_QUEUE).await.unwrap();
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].id(), job_id);
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
+
+    // manually re-queueing a job clears its strikes
+    RedisManager::set_job_status(&mut conn, job_id, &job::Status::Queued).await.unwrap();
+    let job_info = qw.job_meta(&mut conn, job_id).await;
+    assert_eq!(job_info.status(), job::Status::Queued);
+    assert_eq!(job_info.poison_strikes(), 0);
+    assert_eq!(job_info.quarantine_reason(), None);
+    assert!(RedisManager::quarantined_jobs(&mut conn, DEFAULT_QUEUE).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn job_max_queue_age() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::new(DEFAULT_QUEUE);
+    let settings = queue::Settings { max_queue_age: Some(Duration::from_secs(1)), ..Default::default() };
+    assert!(RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap());
+
+    let queued = qw.new_default_job(&mut conn).await.id();
+    let running = qw.new_running_default_job(&mut conn).await.id();
+
+    let empty: Vec<u64> = Vec::new();
+    assert_eq!(RedisManager::check_queue_ages(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
+
+    // only jobs still waiting to be started are quarantined
+    tokio::time::delay_for(time::Duration::from_secs(2)).await;
+    assert_eq!(RedisManager::check_queue_ages(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![queued]);
+    let job_info = qw.job_meta(&mut conn, queued).await;
+    assert_eq!(job_info.status(), job::Status::Quarantined);
+    assert_eq!(job_info.quarantine_reason().as_deref(), Some(job::EXPIRED_IN_QUEUE_REASON));
+    assert!(job_info.ended_at().is_some());
+    assert_eq!(qw.job_meta(&mut conn, running).await.status(), job::Status::Running);
+    assert_eq!(RedisManager::queue_size(&mut conn, DEFAULT_QUEUE).await.unwrap(), 0);
+
+    let quarantined = RedisManager::quarantined_jobs(&mut conn, DEFAULT_QUEUE).await.unwrap();
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].id(), queued);
+    assert_eq!(RedisManager::check_queue_ages(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
+}
+
+#[tokio::test]
+async fn job_sla_breaches() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::new(DEFAULT_QUEUE);
+    let settings = queue::Settings { sla: Some(Duration::from_secs(1)), ..Default::default() };
+    assert!(RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap());
+
+    // completed before its deadline, still queued at its deadline, and with an explicit deadline far in the future
+    let completed = qw.new_running_default_job(&mut conn).await.id();
+    qw.complete_job(&mut conn, completed).await;
+    let queued = qw.new_default_job(&mut conn).await.id();
+    let job_req = job::CreateRequest {
+        deadline: Some(serde_json::from_str("\"2999-01-01T00:00:00Z\"").unwrap()),
+        ..Default::default()
+    };
+    let future = qw.new_job(&mut conn, &job_req).await.id();
+    assert!(qw.job_meta(&mut conn, queued).await.deadline().is_some());
+
+    let empty: Vec<u64> = Vec::new();
+    assert_eq!(RedisManager::check_sla_deadlines(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
+
+    tokio::time::delay_for(time::Duration::from_secs(2)).await;
+    assert_eq!(RedisManager::check_sla_deadlines(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![queued]);
+    assert!(qw.job_meta(&mut conn, queued).await.sla_breached());
+    assert!(!qw.job_meta(&mut conn, completed).await.sla_breached());
+    assert!(!qw.job_meta(&mut conn, future).await.sla_breached());
+
+    // each job is only checked once
+    assert_eq!(RedisManager::check_sla_deadlines(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
+
+    let breaches = RedisManager::sla_breaches(&mut conn, DEFAULT_QUEUE).await.unwrap();
+    assert_eq!(breaches.len(), 1);
+    assert_eq!(breaches[0].id(), queued);
+    assert_eq!(RedisManager::server_info(&mut conn).await.unwrap().statistics.total_sla_breaches, 1);
+
+    RedisManager::delete_job(&mut conn, queued).await.unwrap();
+    assert!(RedisManager::sla_breaches(&mut conn, DEFAULT_QUEUE).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn stuck_jobs() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let threshold = Duration::from_secs(1);
+
+    let job_id = qw.new_running_default_job(&mut conn).await.id();
+    let heartbeat_id = qw.new_running_default_job(&mut conn).await.id();
+    qw.new_default_job(&mut conn).await;
+    assert!(RedisManager::stuck_jobs(&mut conn, DEFAULT_QUEUE, &threshold).await.unwrap().is_empty());
+
+    tokio::time::delay_for(time::Duration::from_secs(2)).await;
+    RedisManager::update_job_heartbeat(&mut conn, heartbeat_id).await.unwrap();
+    let stuck = RedisManager::stuck_jobs(&mut conn, DEFAULT_QUEUE, &threshold).await.unwrap();
+    assert_eq!(stuck.len(), 1);
+    assert_eq!(stuck[0].id(), job_id);
+
+    let threshold = Duration::from_secs(3600);
+    assert!(RedisManager::stuck_jobs(&mut conn, DEFAULT_QUEUE, &threshold).await.unwrap().is_empty());
+    assert_eq!(
+        RedisManager::stuck_jobs(&mut conn, "missing", &threshold).await,
+        Err(OcyError::NoSuchQueue("missing".to_owned()))
+    );
+}
+
+#[tokio::test]
+async fn queue_backlog() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let other = QueueWrapper::new("other");
+    RedisManager::create_or_update_queue(&mut conn, "other", &queue::Settings::default()).await.unwrap();
+
+    assert_eq!(RedisManager::queue_backlog(&mut conn, DEFAULT_QUEUE).await.unwrap(), queue::Backlog::default());
+    qw.new_running_default_job(&mut conn).await;
+    qw.new_default_job(&mut conn).await;
+    qw.new_default_job(&mut conn).await;
+    other.new_running_default_job(&mut conn).await;
+
+    let backlog = RedisManager::queue_backlog(&mut conn, DEFAULT_QUEUE).await.unwrap();
+    assert_eq!(backlog.queued, 2);
+    assert_eq!(backlog.running, 1);
+    assert_eq!(
+        RedisManager::queue_backlog(&mut conn, "missing").await,
+        Err(OcyError::NoSuchQueue("missing".to_owned()))
+    );
+}
+
+#[tokio::test]
+async fn queue_latency() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let window = time::Duration::from_secs(300);
+
+    let latency = RedisManager::queue_latency(&mut conn, DEFAULT_QUEUE, window).await.unwrap();
+    assert_eq!(latency.queued, 0);
+    assert_eq!(latency.oldest_queued_age, None);
+    assert_eq!(latency.started, 0);
+    assert_eq!(latency.eta, Some(Duration::from_secs(0)));
+
+    qw.new_running_default_job(&mut conn).await;
+    qw.new_default_job(&mut conn).await;
+    let latency = RedisManager::queue_latency(&mut conn, DEFAULT_QUEUE, window).await.unwrap();
+    assert_eq!(latency.queued, 1);
+    assert!(latency.oldest_queued_age.is_some());
+    assert_eq!(latency.started, 1);
+    assert_eq!(latency.median_wait, Some(Duration::from_secs(0)));
+    assert!(latency.eta.is_some());
+
+    assert_eq!(
+        RedisManager::queue_latency(&mut conn, "missing", window).await,
+        Err(OcyError::NoSuchQueue("missing".to_owned()))
+    );
+}
+
+#[tokio::test]
+async fn sample_jobs() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let queued = job::Status::Queued;
+
+    assert!(RedisManager::sample_jobs(&mut conn, DEFAULT_QUEUE, &queued, 10).await.unwrap().is_empty());
+    let running_id = qw.new_running_default_job(&mut conn).await.id();
+    let mut queued_ids = Vec::new();
+    for _ in 0..4 {
+        queued_ids.push(qw.new_default_job(&mut conn).await.id());
+    }
+
+    let sample = RedisManager::sample_jobs(&mut conn, DEFAULT_QUEUE, &queued, 3).await.unwrap();
+    assert_eq!(sample.len(), 3);
+    assert!(sample.iter().all(|job| queued_ids.contains(&job.id()) && job.status() == queued));
+    let sample = RedisManager::sample_jobs(&mut conn, DEFAULT_QUEUE, &queued, 10).await.unwrap();
+    assert_eq!(sample.len(), 4);
+
+    let sample = RedisManager::sample_jobs(&mut conn, DEFAULT_QUEUE, &job::Status::Running, 10).await.unwrap();
+    assert_eq!(sample.iter().map(|job| job.id()).collect::<Vec<_>>(), vec![running_id]);
+    assert!(RedisManager::sample_jobs(&mut conn, DEFAULT_QUEUE, &job::Status::Failed, 10).await.unwrap().is_empty());
+
+    match RedisManager::sample_jobs(&mut conn, DEFAULT_QUEUE, &queued, 1000).await {
+        Err(OcyError::BadRequest(_)) => (),
+        x => assert!(false, "Unexpected result when sampling too many jobs: {:?}", x),
+    }
+    assert_eq!(
+        RedisManager::sample_jobs(&mut conn, "missing", &queued, 10).await,
+        Err(OcyError::NoSuchQueue("missing".to_owned()))
+    );
+}
+
+#[tokio::test]
+async fn job_set_input() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+
+    let job_req = job::CreateRequest { input: Some("typo".into()), ..Default::default() };
+    let job_id = qw.new_job(&mut conn, &job_req).await.id();
+    RedisManager::set_job_input(&mut conn, job_id, &"fixed".into()).await.unwrap();
+    assert_eq!(qw.job_meta(&mut conn, job_id).await.input(), Some("fixed".into()));
+
+    RedisManager::set_job_input(&mut conn, job_id, &serde_json::Value::Null).await.unwrap();
+    assert_eq!(qw.job_meta(&mut conn, job_id).await.input(), None);
+    RedisManager::set_job_input(&mut conn, job_id, &serde_json::json!({"a": 1})).await.unwrap();
+    assert_eq!(qw.next_job(&mut conn).await.input(), &Some(serde_json::json!({"a": 1})));
+
+    // input can't be changed once the job has started
+    match RedisManager::set_job_input(&mut conn, job_id, &"late".into()).await {
+        Err(OcyError::Conflict(_)) => (),
+        x => assert!(false, "Unexpected result when setting input of running job: {:?}", x),
+    }
+    assert_eq!(RedisManager::set_job_input(&mut conn, 12345, &"x".into()).await, Err(OcyError::NoSuchJob(12345)));
+}
+
+#[tokio::test]
+async fn job_size_limits() {
+    let (_ctx, mut conn) = init().await;
+    let settings = queue::Settings { max_input_size: Some(10), max_output_size: Some(10), ..Default::default() };
+    let qw = QueueWrapper::new(DEFAULT_QUEUE);
+    assert!(RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap());
+
+    let job_req = job::CreateRequest { input: Some("too long input".into()), ..Default::default() };
+    match RedisManager::create_job(&mut conn, DEFAULT_QUEUE, &job_req).await {
+        Err(OcyError::BadRequest(_)) => (),
+        x => assert!(false, "Unexpected result when creating job with large input: {:?}", x),
+    }
+
+    let job_req = job::CreateRequest { input: Some("short".into()), ..Default::default() };
+    let job_id = qw.new_job(&mut conn, &job_req).await.id();
+    match RedisManager::set_job_input(&mut conn, job_id, &"too long input".into()).await {
+        Err(OcyError::BadRequest(_)) => (),
+        x => assert!(false, "Unexpected result when setting large input: {:?}", x),
+    }
+
+    qw.next_job(&mut conn).await;
+    match RedisManager::set_job_output(&mut conn, job_id, &"too long output".into()).await {
+        Err(OcyError::BadRequest(_)) => (),
+        x => assert!(false, "Unexpected result when setting large output: {:?}", x),
+    }
+    RedisManager::set_job_output(&mut conn, job_id, &"short".into()).await.unwrap();
+}
+
+#[tokio::test]
+async fn job_output_truncation() {
+    let (_ctx, mut conn) = init().await;
+    let settings = queue::Settings {
+        max_output_size: Some(10),
+        output_size_policy: queue::OutputSizePolicy::Truncate,
+        ..Default::default()
+    };
+    let qw = QueueWrapper::new(DEFAULT_QUEUE);
+    assert!(RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap());
+    let job_id = qw.new_running_default_job(&mut conn).await.id();
+    let fields = &[job::Field::Output, job::Field::OutputTruncated];
+
+    RedisManager::set_job_output(&mut conn, job_id, &"too long output".into()).await.unwrap();
+    let job = qw.job_fields(&mut conn, job_id, fields).await;
+    assert_eq!(job.output(), Some("\"too lo".into()));
+    assert!(job.output_truncated());
+
+    // output within the limit replaces the truncated output
+    RedisManager::set_job_output(&mut conn, job_id, &"short".into()).await.unwrap();
+    let job = qw.job_fields(&mut conn, job_id, fields).await;
+    assert_eq!(job.output(), Some("short".into()));
+    assert!(!job.output_truncated());
+
+    // outputs can't be offloaded unless an offload directory is configured
+    let update = queue::SettingsUpdate {
+        output_size_policy: Some(queue::OutputSizePolicy::Offload),
+        ..Default::default()
+    };
+    match RedisManager::update_queue(&mut conn, DEFAULT_QUEUE, &update).await {
+        Err(OcyError::BadRequest(_)) => (),
+        x => assert!(false, "Unexpected result when offloading without a directory: {:?}", x),
+    }
+}
+
+#[tokio::test]
+async fn job_allowed_transitions() {
+    let (_ctx, mut conn) = init().await;
+    let settings = queue::Settings {
+        allowed_transitions: Some(vec![
+            job::Transition::new(job::Status::Running, job::Status::Failed),
+            job::Transition::new(job::Status::Failed, job::Status::Cancelled),
+        ]),
+        ..Default::default()
+    };
+    let qw = QueueWrapper::new(DEFAULT_QUEUE);
+    assert!(RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap());
+    let job_id = qw.new_running_default_job(&mut conn).await.id();
+    qw.fail_job(&mut conn, job_id).await;
+
+    // failed jobs can't be requeued, either by updating their status or retrying them
+    match RedisManager::set_job_status(&mut conn, job_id, &job::Status::Queued).await {
+        Err(OcyError::Conflict(_)) => (),
+        x => assert!(false, "Unexpected result when requeueing job: {:?}", x),
+    }
+    match RedisManager::retry_job(&mut conn, job_id).await {
+        Err(OcyError::Conflict(_)) => (),
+        x => assert!(false, "Unexpected result when retrying job: {:?}", x),
+    }
+    RedisManager::set_job_status(&mut conn, job_id, &job::Status::Cancelled).await.unwrap();
+
+    // only transitions clients can make can be allowed
+    let update = queue::SettingsUpdate {
+        allowed_transitions: Some(Some(vec![job::Transition::new(job::Status::Completed, job::Status::Queued)])),
+        ..Default::default()
+    };
+    match RedisManager::update_queue(&mut conn, DEFAULT_QUEUE, &update).await {
+        Err(OcyError::BadRequest(_)) => (),
+        x => assert!(false, "Unexpected result when allowing invalid transition: {:?}", x),
+    }
+}
+
+#[tokio::test]
+async fn job_search() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let other = QueueWrapper::new("other");
+    other.create_queue(&mut conn).await;
+
+    let tagged = job::CreateRequest { tags: Some(vec!["cust-42".to_owned()]), ..Default::default() };
+    let running = qw.new_running_default_job(&mut conn).await.id();
+    let failed = qw.new_running_default_job(&mut conn).await.id();
+    qw.fail_job(&mut conn, failed).await;
+    let queued = qw.new_job(&mut conn, &tagged).await.id();
+    let other_queued = other.new_job(&mut conn, &tagged).await.id();
+
+    let query = job::SearchQuery::default();
+    let all = RedisManager::search_job_ids(&mut conn, &query, 10).await.unwrap();
+    assert_eq!(all, vec![other_queued, queued, failed, running]);
+
+    let query = job::SearchQuery { status: Some(job::Status::Queued), ..Default::default() };
+    assert_eq!(RedisManager::search_job_ids(&mut conn, &query, 10).await.unwrap(), vec![other_queued, queued]);
+
+    let query = job::SearchQuery { tag: Some("cust-42".to_owned()), queue: Some("other".to_owned()), ..Default::default() };
+    assert_eq!(RedisManager::search_job_ids(&mut conn, &query, 10).await.unwrap(), vec![other_queued]);
+
+    let query = job::SearchQuery { status: Some(job::Status::Failed), ..Default::default() };
+    assert_eq!(RedisManager::search_job_ids(&mut conn, &query, 10).await.unwrap(), vec![failed]);
+
+    // pages continue from the cursor
+    let query = job::SearchQuery::default();
+    assert_eq!(RedisManager::search_job_ids(&mut conn, &query, 2).await.unwrap(), vec![other_queued, queued]);
+    let query = job::SearchQuery { cursor: Some(queued), ..Default::default() };
+    assert_eq!(RedisManager::search_job_ids(&mut conn, &query, 2).await.unwrap(), vec![failed, running]);
+}
+
+#[tokio::test]
+async fn queue_failure_summary() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+
+    for output in &["connection refused", "connection refused", "bad input"] {
+        let job_id = qw.new_running_default_job(&mut conn).await.id();
+        let update_req =
+            job::UpdateRequest { status: Some(job::Status::Failed), output: Some((*output).into()), ..Default::default() };
+        RedisManager::update_job(&mut conn, job_id, &update_req).await.unwrap();
+    }
+    let completed = qw.new_running_default_job(&mut conn).await.id();
+    qw.complete_job(&mut conn, completed).await;
+
+    let summary = RedisManager::failure_summary(&mut conn, DEFAULT_QUEUE).await.unwrap();
+    assert_eq!(summary.total, 3);
+    assert_eq!(summary.reasons.len(), 2);
+    assert_eq!(summary.reasons[0].reason, "connection refused");
+    assert_eq!(summary.reasons[0].count, 2);
+    assert_eq!(summary.reasons[1].sample_job_ids.len(), 1);
+    assert_eq!(
+        RedisManager::failure_summary(&mut conn, "missing").await,
+        Err(OcyError::NoSuchQueue("missing".to_owned()))
+    );
+}
+
+#[tokio::test]
+async fn job_error_code() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let job_id = qw.new_running_default_job(&mut conn).await.id();
+
+    let update_req = job::UpdateRequest { error_code: Some("refused".to_owned()), ..Default::default() };
+    match RedisManager::update_job(&mut conn, job_id, &update_req).await {
+        Err(OcyError::BadRequest(_)) => (),
+        x => assert!(false, "Unexpected result when setting error code without failing: {:?}", x),
+    }
+
+    let update_req = job::UpdateRequest {
+        status: Some(job::Status::Failed),
+        output: Some("partial output".into()),
+        error_code: Some("refused".to_owned()),
+        error_details: Some(serde_json::json!({"host": "service-x"})),
+        ..Default::default()
+    };
+    RedisManager::update_job(&mut conn, job_id, &update_req).await.unwrap();
+    let job_meta = qw.job_meta(&mut conn, job_id).await;
+    assert_eq!(job_meta.error_code(), Some("refused".to_owned()));
+    assert_eq!(job_meta.error_details(), Some(serde_json::json!({"host": "service-x"})));
+    assert_eq!(job_meta.output(), Some("partial output".into()));
+
+    let summary = RedisManager::failure_summary(&mut conn, DEFAULT_QUEUE).await.unwrap();
+    assert_eq!(summary.reasons[0].reason, "refused");
+
+    // error is cleared when the job fails again
+    RedisManager::set_job_status(&mut conn, job_id, &job::Status::Queued).await.unwrap();
+    qw.next_job(&mut conn).await;
+    qw.fail_job(&mut conn, job_id).await;
+    assert_eq!(qw.job_meta(&mut conn, job_id).await.error_code(), None);
+}
+
+#[tokio::test]
+async fn queue_retry_budget() {
+    let (_ctx, mut conn) = init().await;
+    let settings = queue::Settings {
+        retries: 5,
+        retry_budget: Some(1),
+        retry_budget_cooldown: Duration::from_secs(3600),
+        ..Default::default()
+    };
+    RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap();
+    let qw = QueueWrapper::new(DEFAULT_QUEUE);
+
+    let job_id1 = qw.new_running_default_job(&mut conn).await.id();
+    let job_id2 = qw.new_running_default_job(&mut conn).await.id();
+    let job_id3 = qw.new_default_job(&mut conn).await.id();
+    qw.fail_job(&mut conn, job_id1).await;
+    qw.fail_job(&mut conn, job_id2).await;
+
+    // only one retry is allowed, exceeding the budget pauses the queue and leaves the other job failed
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id1]);
+    assert_eq!(qw.job_status(&mut conn, job_id1).await, job::Status::Queued);
+    assert_eq!(qw.job_status(&mut conn, job_id2).await, job::Status::Failed);
+    qw.next_empty_job(&mut conn).await;
+    assert_eq!(qw.job_status(&mut conn, job_id3).await, job::Status::Queued);
+
+    let summaries = RedisManager::queue_summaries(&mut conn).await.unwrap();
+    assert!(summaries[DEFAULT_QUEUE].paused_until.is_some());
+
+    // no further jobs are retried while the queue is paused
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), Vec::<u64>::new());
+    assert_eq!(qw.job_status(&mut conn, job_id2).await, job::Status::Failed);
+}
+
+#[tokio::test]
+async fn queue_clone() {
+    let (_ctx, mut conn) = init().await;
+    let settings = queue::Settings { retries: 3, sla: Some(Duration::from_secs(600)), ..Default::default() };
+    RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap();
+    let qw = QueueWrapper::new(DEFAULT_QUEUE);
+
+    let running_id = qw.new_running_default_job(&mut conn).await.id();
+    let job_req1 = job::CreateRequest {
+        input: Some(serde_json::json!({"a": 1})),
+        tags: Some(vec!["t".to_owned()]),
+        timeout: Some(Duration::from_secs(30)),
+        ..Default::default()
+    };
+    let job_id1 = qw.new_job(&mut conn, &job_req1).await.id();
+    let job_id2 = qw.new_default_job(&mut conn).await.id();
+
+    assert_eq!(RedisManager::create_queue(&mut conn, "copy", &settings).await, Ok(()));
+    assert!(matches!(RedisManager::create_queue(&mut conn, "copy", &settings).await, Err(OcyError::Conflict(_))));
+    assert_eq!(RedisManager::queue_settings(&mut conn, "copy").await.unwrap(), settings);
+
+    // only queued jobs are copied, oldest first
+    let job_reqs = RedisManager::queued_job_copies(&mut conn, DEFAULT_QUEUE).await.unwrap();
+    assert_eq!(job_reqs.len(), 2);
+    assert_eq!(job_reqs[0].input, job_req1.input);
+    assert_eq!(job_reqs[0].tags, job_req1.tags);
+    assert_eq!(job_reqs[0].timeout, job_req1.timeout);
+    assert_eq!(job_reqs[0].retries, Some(3));
+    assert!(job_reqs[0].deadline.is_some());
+    assert_eq!(job_reqs[1].input, None);
+
+    let copy = QueueWrapper::new("copy");
+    let copy_id = RedisManager::create_job(&mut conn, "copy", &job_reqs[0]).await.unwrap().id();
+    let job = copy.next_job(&mut conn).await;
+    assert_eq!(job.id(), copy_id);
+    assert_eq!(job.input(), &job_req1.input);
+
+    // source queue is unchanged
+    assert_eq!(qw.next_job(&mut conn).await.id(), job_id1);
+    assert_eq!(qw.next_job(&mut conn).await.id(), job_id2);
+    assert_eq!(qw.job_status(&mut conn, running_id).await, job::Status::Running);
+    assert_eq!(
+        RedisManager::queued_job_copies(&mut conn, "missing").await.unwrap_err(),
+        OcyError::NoSuchQueue("missing".to_owned())
+    );
+}
+
+#[tokio::test]
+async fn queue_shadow() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let shadow = QueueWrapper::new("shadow");
+    RedisManager::create_or_update_queue(&mut conn, "shadow", &queue::Settings::default()).await.unwrap();
+    assert_eq!(RedisManager::shadow_target(&mut conn, DEFAULT_QUEUE).await, Ok(None));
+
+    let invalid = [
+        queue::Settings { shadow_to: Some(DEFAULT_QUEUE.to_owned()), ..Default::default() },
+        queue::Settings { shadow_to: Some("shadow!".to_owned()), ..Default::default() },
+        queue::Settings { shadow_to: Some("shadow".to_owned()), shadow_percent: 101, ..Default::default() },
+    ];
+    for settings in &invalid {
+        let res = RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, settings).await;
+        assert!(matches!(res, Err(OcyError::BadRequest(_))), "{:?}", settings);
+    }
+
+    let mut settings = queue::Settings { shadow_to: Some("shadow".to_owned()), ..Default::default() };
+    RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap();
+    assert_eq!(RedisManager::shadow_target(&mut conn, DEFAULT_QUEUE).await, Ok(Some("shadow".to_owned())));
+    settings.shadow_percent = 0;
+    RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap();
+    assert_eq!(RedisManager::shadow_target(&mut conn, DEFAULT_QUEUE).await, Ok(None));
+
+    let job_req = job::CreateRequest { input: Some(serde_json::json!([1, 2])), ..Default::default() };
+    let job_id = qw.new_job(&mut conn, &job_req).await.id();
+    let shadow_id = RedisManager::create_shadow_job(&mut conn, "shadow", &job_req, job_id).await.unwrap();
+    assert_eq!(qw.job_meta(&mut conn, job_id).await.shadow_of(), None);
+    assert_eq!(shadow.job_meta(&mut conn, shadow_id).await.shadow_of(), Some(job_id));
+    assert_eq!(shadow.next_job(&mut conn).await.input(), &job_req.input);
+}
+
+#[tokio::test]
+async fn job_callback_url() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+
+    let job_req = job::CreateRequest { callback_url: Some("ftp://example.com".to_owned()), ..Default::default() };
+    let res = RedisManager::create_job(&mut conn, DEFAULT_QUEUE, &job_req).await;
+    assert!(matches!(res, Err(OcyError::BadRequest(_))), "{:?}", res);
+
+    let url = "https://example.com/results";
+    let job_req = job::CreateRequest { callback_url: Some(url.to_owned()), ..Default::default() };
+    let job_id = qw.new_job(&mut conn, &job_req).await.id();
+    assert_eq!(qw.job_meta(&mut conn, job_id).await.callback_url().as_deref(), Some(url));
+
+    // shadows don't send results
+    RedisManager::create_or_update_queue(&mut conn, "shadow", &queue::Settings::default()).await.unwrap();
+    let shadow_id = RedisManager::create_shadow_job(&mut conn, "shadow", &job_req, job_id).await.unwrap();
+    assert_eq!(qw.job_meta(&mut conn, shadow_id).await.callback_url(), None);
+}
+
+#[tokio::test]
+async fn schema_version() {
+    let (_ctx, mut conn) = init().await;
+
+    // empty Redis is initialised to the current version
+    assert_eq!(schema::version(&mut conn).await, Ok(schema::SCHEMA_VERSION));
+    assert_eq!(schema::check_version(&mut conn).await, Ok(()));
+    assert_eq!(schema::migrate(&mut conn).await, Ok(Vec::new()));
+
+    // data from before versioning needs migrating
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let _: () = redis::cmd("HDEL").arg("ocypod:queue:default").arg("shadow_percent").query_async(&mut conn).await.unwrap();
+    let _: () = redis::cmd("DEL").arg("ocypod:schema_version").query_async(&mut conn).await.unwrap();
+    assert_eq!(schema::version(&mut conn).await, Ok(0));
+    assert!(matches!(schema::check_version(&mut conn).await, Err(OcyError::Conflict(_))));
+
+    let expected: Vec<u64> = (1..=schema::SCHEMA_VERSION).collect();
+    assert_eq!(schema::migrate(&mut conn).await, Ok(expected));
+    assert_eq!(schema::check_version(&mut conn).await, Ok(()));
+    let shadow_percent: Option<u64> = redis::cmd("HGET").arg("ocypod:queue:default").arg("shadow_percent")
+        .query_async(&mut conn).await.unwrap();
+    assert_eq!(shadow_percent, Some(100));
+    qw.new_default_job(&mut conn).await;
+
+    // data from a newer release can't be used
+    let _: () = redis::cmd("SET").arg("ocypod:schema_version").arg(schema::SCHEMA_VERSION + 1)
+        .query_async(&mut conn).await.unwrap();
+    assert!(matches!(schema::check_version(&mut conn).await, Err(OcyError::Conflict(_))));
+    assert!(schema::migrate(&mut conn).await.is_err());
+}
+
+#[tokio::test]
+async fn remove_lost_job() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let queued_id = qw.new_default_job(&mut conn).await.id();
+    let running_id = qw.new_running_default_job(&mut conn).await.id();
+
+    // jobs that still exist are left alone
+    assert_eq!(RedisManager::remove_lost_job(&mut conn, running_id).await.unwrap(), Vec::<String>::new());
+
+    for job_id in &[queued_id, running_id] {
+        let _: () = redis::cmd("DEL").arg(format!("ocypod:job:{}", job_id)).query_async(&mut conn).await.unwrap();
+    }
+    assert_eq!(RedisManager::remove_lost_job(&mut conn, queued_id).await.unwrap(), vec!["ocypod:queue:default:jobs"]);
+    assert_eq!(RedisManager::remove_lost_job(&mut conn, running_id).await.unwrap(), vec!["ocypod:running"]);
+    assert_eq!(qw.queue_size(&mut conn).await, 0);
+    assert_eq!(RedisManager::check_integrity(&mut conn, false).await.unwrap(), IntegrityReport::default());
+}
+
+#[tokio::test]
+async fn check_integrity() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    assert_eq!(RedisManager::check_integrity(&mut conn, false).await.unwrap(), IntegrityReport::default());
+
+    // completed job that's also listed as running
+    let completed_id = qw.new_running_default_job(&mut conn).await.id();
+    qw.complete_job(&mut conn, completed_id).await;
+    let _: () = redis::cmd("RPUSH").arg("ocypod:running").arg(completed_id).query_async(&mut conn).await.unwrap();
+
+    // running job that's not in the running list
+    let orphan_id = qw.new_running_default_job(&mut conn).await.id();
+    let _: () = redis::cmd("LREM").arg("ocypod:running").arg(0).arg(orphan_id).query_async(&mut conn).await.unwrap();
+
+    // deleted job that's still queued and tagged
+    let job_req = job::CreateRequest { tags: Some(vec!["tag".to_owned()]), ..Default::default() };
+    let deleted_id = qw.new_job(&mut conn, &job_req).await.id();
+    let _: () = redis::cmd("DEL").arg(format!("ocypod:job:{}", deleted_id)).query_async(&mut conn).await.unwrap();
+
+    // queue with no settings
+    let _: () = redis::cmd("SADD").arg("ocypod:queues").arg("missing").query_async(&mut conn).await.unwrap();
+
+    let mut expected = IntegrityReport {
+        dangling_queues: vec!["missing".to_owned()],
+        orphaned_jobs: vec![orphan_id],
+        ..Default::default()
+    };
+    expected.dangling_ids.insert("ocypod:queue:default:jobs".to_owned(), vec![deleted_id]);
+    expected.dangling_ids.insert("ocypod:tag:tag".to_owned(), vec![deleted_id]);
+    expected.mismatched_ids.insert("ocypod:running".to_owned(), vec![completed_id]);
+    assert_eq!(RedisManager::check_integrity(&mut conn, false).await.unwrap(), expected);
+
+    // checking without repairing leaves problems in place
+    assert_eq!(RedisManager::check_integrity(&mut conn, false).await.unwrap(), expected);
+
+    expected.repaired = true;
+    assert_eq!(RedisManager::check_integrity(&mut conn, true).await.unwrap(), expected);
+    assert_eq!(RedisManager::check_integrity(&mut conn, false).await.unwrap(), IntegrityReport::default());
+
+    // orphaned job is back in the running list, the completed job isn't
+    let running: Vec<u64> = redis::cmd("LRANGE").arg("ocypod:running").arg(0).arg(-1)
+        .query_async(&mut conn).await.unwrap();
+    assert_eq!(running, vec![orphan_id]);
+    assert_eq!(qw.job_status(&mut conn, completed_id).await, job::Status::Completed);
+    assert_eq!(qw.queue_size(&mut conn).await, 0);
+}
+
+#[tokio::test]
+async fn prune_tags() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    assert_eq!(RedisManager::prune_tags(&mut conn, false).await.unwrap(), HashMap::new());
+
+    let job_req = job::CreateRequest { tags: Some(vec!["a".to_owned(), "b".to_owned()]), ..Default::default() };
+    let kept_id = qw.new_job(&mut conn, &job_req).await.id();
+    let deleted_id = qw.new_job(&mut conn, &job_req).await.id();
+    let _: () = redis::cmd("DEL").arg(format!("ocypod:job:{}", deleted_id)).query_async(&mut conn).await.unwrap();
+
+    let mut expected = HashMap::new();
+    expected.insert("a".to_owned(), vec![deleted_id]);
+    expected.insert("b".to_owned(), vec![deleted_id]);
+    assert_eq!(RedisManager::prune_tags(&mut conn, true).await.unwrap(), expected);
+    assert!(RedisManager::tagged_job_ids(&mut conn, "a").await.unwrap().contains(&deleted_id));
+
+    assert_eq!(RedisManager::prune_tags(&mut conn, false).await.unwrap(), expected);
+    assert_eq!(RedisManager::tagged_job_ids(&mut conn, "a").await.unwrap(), vec![kept_id]);
+    assert_eq!(RedisManager::prune_tags(&mut conn, false).await.unwrap(), HashMap::new());
+
+    // tags are deleted once empty
+    let _: () = redis::cmd("DEL").arg(format!("ocypod:job:{}", kept_id)).query_async(&mut conn).await.unwrap();
+    RedisManager::prune_tags(&mut conn, false).await.unwrap();
+    let exists: bool = redis::cmd("EXISTS").arg("ocypod:tag:a").query_async(&mut conn).await.unwrap();
+    assert!(!exists);
+}
+
+#[tokio::test]
+async fn job_hold() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+
+    let held = qw.new_default_job(&mut conn).await.id();
+    let queued = qw.new_default_job(&mut conn).await.id();
+    RedisManager::hold_job(&mut conn, held).await.unwrap();
+    assert!(qw.job_meta(&mut conn, held).await.held());
+    assert_eq!(qw.job_status(&mut conn, held).await, job::Status::Queued);
+    assert_eq!(qw.queue_size(&mut conn).await, 1);
+    assert_eq!(RedisManager::queue_job_ids(&mut conn, DEFAULT_QUEUE).await.unwrap()[&job::Status::Queued].len(), 2);
+    assert_eq!(RedisManager::server_info(&mut conn).await.unwrap().queues[DEFAULT_QUEUE].held, 1);
+
+    // held jobs are skipped when dequeuing, and can't be held twice
+    assert_eq!(qw.next_job(&mut conn).await.id(), queued);
+    qw.next_empty_job(&mut conn).await;
+    match RedisManager::hold_job(&mut conn, held).await {
+        Err(OcyError::Conflict(_)) => (),
+        x => assert!(false, "Unexpected result when holding held job: {:?}", x),
+    }
+    match RedisManager::hold_job(&mut conn, queued).await {
+        Err(OcyError::Conflict(_)) => (),
+        x => assert!(false, "Unexpected result when holding running job: {:?}", x),
+    }
+
+    RedisManager::release_held_job(&mut conn, held).await.unwrap();
+    assert!(!qw.job_meta(&mut conn, held).await.held());
+    assert_eq!(qw.next_job(&mut conn).await.id(), held);
+    match RedisManager::release_held_job(&mut conn, held).await {
+        Err(OcyError::Conflict(_)) => (),
+        x => assert!(false, "Unexpected result when releasing running job: {:?}", x),
+    }
+
+    // cancelling a held job removes it from hold
+    let cancelled = qw.new_default_job(&mut conn).await.id();
+    RedisManager::hold_job(&mut conn, cancelled).await.unwrap();
+    RedisManager::set_job_status(&mut conn, cancelled, &job::Status::Cancelled).await.unwrap();
+    assert!(!qw.job_meta(&mut conn, cancelled).await.held());
+    assert_eq!(RedisManager::server_info(&mut conn).await.unwrap().queues[DEFAULT_QUEUE].held, 0);
+}
+
 #[tokio::test]
 async fn job_retry_delays() {
     let (_ctx, mut conn) = init().await;
@@ -483,7 +1485,7 @@ async fn job_retry_delays() {
     assert_eq!(job_info.retry_delays(), Some(delays));
 
     // no retry yet
-    assert_eq!(RedisManager::check_job_retries(&mut conn).await.unwrap(), empty);
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
     let job_info = qw.job_meta(&mut conn, job_id).await;
     assert_eq!(job_info.status(), job::Status::Failed);
     assert_eq!(job_info.retries_attempted(), 0);
@@ -491,6 +1493,45 @@ async fn job_retry_delays() {
     // TODO: finish off adding additional retry tests here - i.e. sleep then checking further delay times
 }
 
+#[tokio::test]
+async fn job_retry_after() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let job_req = job::CreateRequest { retries: Some(2), ..Default::default() };
+    let job_id = qw.new_running_job(&mut conn, &job_req).await.id();
+    let empty: Vec<u64> = Vec::new();
+
+    // can only be given when failing a job
+    let update_req = job::UpdateRequest { retry_after: Some(Duration::from_secs(600)), ..Default::default() };
+    match RedisManager::update_job(&mut conn, job_id, &update_req).await {
+        Err(OcyError::BadRequest(_)) => (),
+        x => assert!(false, "Unexpected result when setting retry_after without failing: {:?}", x),
+    }
+
+    // overrides the job's lack of retry delays
+    let update_req = job::UpdateRequest {
+        status: Some(job::Status::Failed),
+        retry_after: Some(Duration::from_secs(600)),
+        ..Default::default()
+    };
+    RedisManager::update_job(&mut conn, job_id, &update_req).await.unwrap();
+    assert_eq!(qw.job_meta(&mut conn, job_id).await.retry_after(), Some(Duration::from_secs(600)));
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), empty);
+    assert_eq!(qw.job_meta(&mut conn, job_id).await.status(), job::Status::Failed);
+
+    // cleared when the job is retried
+    RedisManager::set_job_status(&mut conn, job_id, &job::Status::Queued).await.unwrap();
+    assert_eq!(qw.job_meta(&mut conn, job_id).await.retry_after(), None);
+    qw.next_job(&mut conn).await;
+    let update_req = job::UpdateRequest {
+        status: Some(job::Status::Failed),
+        retry_after: Some(Duration::from_secs(0)),
+        ..Default::default()
+    };
+    RedisManager::update_job(&mut conn, job_id, &update_req).await.unwrap();
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id]);
+}
+
 #[tokio::test]
 async fn tag_creation() {
     let (_ctx, mut conn) = init().await;
@@ -614,6 +1655,14 @@ async fn job_fields() {
     assert_eq!(qw.job_fields(&mut conn, job_id, &[job::Field::RetriesAttempted]).await.retries_attempted(), 0);
     assert_eq!(qw.job_fields(&mut conn, job_id, &[job::Field::RetryDelays]).await.retry_delays(), None);
 
+    // derived timing fields
+    assert_eq!(qw.job_fields(&mut conn, job_id, &[job::Field::RunTime]).await.run_time(), None);
+    assert_eq!(qw.job_fields(&mut conn, job_id, &[job::Field::HeartbeatAge]).await.heartbeat_age(), None);
+    assert_eq!(qw.job_fields(&mut conn, job_id, &[job::Field::Attempts]).await.attempts(), 0);
+    let jm = qw.job_fields(&mut conn, job_id, &[job::Field::QueuedTime, job::Field::TotalTime]).await;
+    assert!(jm.queued_time().as_secs() <= jm.total_time().as_secs());
+    assert_eq!(serde_json::to_value(&jm).unwrap().as_object().unwrap().len(), 2);
+
     // multiple fields
     let jm = qw.job_fields(&mut conn, job_id, &[job::Field::Status, job::Field::Output]).await;
     assert_eq!(jm.status(), job_meta.status());
@@ -631,6 +1680,12 @@ async fn job_fields() {
     assert_eq!(jm.output(), job_meta.output());
     assert_eq!(jm.tags(), job_meta.tags());
     assert_eq!(jm.retry_delays(), job_meta.retry_delays());
+
+    // derived timing fields once running
+    qw.next_job(&mut conn).await;
+    let jm = qw.job_fields(&mut conn, job_id, &[job::Field::RunTime, job::Field::Attempts]).await;
+    assert!(jm.run_time().is_some());
+    assert_eq!(jm.attempts(), 1);
 }
 
 #[tokio::test]
@@ -653,6 +1708,49 @@ async fn update_job_heartbeat() {
     assert!(hb2 > hb1);
 }
 
+#[tokio::test]
+async fn update_job_heartbeats() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+
+    let running_id = qw.new_running_default_job(&mut conn).await.id();
+    let queued_id = qw.new_default_job(&mut conn).await.id();
+    let heartbeats: Vec<job::Heartbeat> = serde_json::from_value(serde_json::json!([
+        {"id": running_id, "progress": {"done": 3, "total": 10}},
+        queued_id,
+        9999,
+    ])).unwrap();
+
+    let results = RedisManager::update_job_heartbeats(&mut conn, &heartbeats, None, None).await.unwrap();
+    assert_eq!(results, job::HeartbeatResults {
+        updated: vec![running_id],
+        not_running: vec![queued_id],
+        not_found: vec![9999],
+    });
+    let job_meta = qw.job_meta(&mut conn, running_id).await;
+    assert!(job_meta.last_heartbeat().is_some());
+    assert_eq!(job_meta.progress(), Some(serde_json::json!({"done": 3, "total": 10})));
+    assert!(qw.job_meta(&mut conn, queued_id).await.last_heartbeat().is_none());
+
+    // progress is kept if not given
+    let heartbeats = vec![job::Heartbeat::Id(running_id)];
+    let results = RedisManager::update_job_heartbeats(&mut conn, &heartbeats, None, None).await.unwrap();
+    assert_eq!(results.updated, vec![running_id]);
+    assert_eq!(qw.job_meta(&mut conn, running_id).await.progress(), Some(serde_json::json!({"done": 3, "total": 10})));
+
+    // jobs outside the namespace are treated as not existing
+    let results = RedisManager::update_job_heartbeats(&mut conn, &heartbeats, Some("other"), None).await.unwrap();
+    assert_eq!(results.not_found, vec![running_id]);
+
+    // as are jobs outside the given queues
+    let queues = vec!["other".to_owned()];
+    let results = RedisManager::update_job_heartbeats(&mut conn, &heartbeats, None, Some(&queues)).await.unwrap();
+    assert_eq!(results.not_found, vec![running_id]);
+    let queues = vec!["other".to_owned(), qw.queue_name.clone()];
+    let results = RedisManager::update_job_heartbeats(&mut conn, &heartbeats, None, Some(&queues)).await.unwrap();
+    assert_eq!(results.updated, vec![running_id]);
+}
+
 #[tokio::test]
 async fn update_job_output() {
     let (_ctx, mut conn) = init().await;
@@ -804,14 +1902,14 @@ async fn job_heartbeat_timeout() {
     assert_eq!(qw.job_status(&mut conn, job_id_c).await, job::Status::Running);
 
     tokio::time::delay_for(time::Duration::from_secs(2)).await;
-    assert_eq!(RedisManager::check_job_timeouts(&mut conn).await.unwrap(), vec![job_id_a]);
+    assert_eq!(RedisManager::check_job_timeouts(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id_a]);
     assert_eq!(qw.job_status(&mut conn, job_id_a).await, job::Status::TimedOut);
     assert_eq!(qw.job_status(&mut conn, job_id_b).await, job::Status::Running);
     assert_eq!(qw.job_status(&mut conn, job_id_c).await, job::Status::Running);
 
     RedisManager::update_job_heartbeat(&mut conn, job_id_c).await.unwrap();
     tokio::time::delay_for(time::Duration::from_secs(2)).await;
-    assert_eq!(RedisManager::check_job_timeouts(&mut conn).await.unwrap(), vec![job_id_b]);
+    assert_eq!(RedisManager::check_job_timeouts(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id_b]);
     assert_eq!(qw.job_status(&mut conn, job_id_a).await, job::Status::TimedOut);
     assert_eq!(qw.job_status(&mut conn, job_id_b).await, job::Status::TimedOut);
     assert_eq!(qw.job_status(&mut conn, job_id_c).await, job::Status::Running);
@@ -844,11 +1942,265 @@ async fn job_timeout() {
     assert_eq!(qw.job_status(&mut conn, job_id_b).await, job::Status::Running);
 
     tokio::time::delay_for(time::Duration::from_secs(2)).await;
-    assert_eq!(RedisManager::check_job_timeouts(&mut conn).await.unwrap(), vec![1]);
+    assert_eq!(RedisManager::check_job_timeouts(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![1]);
     assert_eq!(qw.job_status(&mut conn, job_id_a).await, job::Status::TimedOut);
     assert_eq!(qw.job_status(&mut conn, job_id_b).await, job::Status::Running);
 }
 
+#[tokio::test]
+async fn job_total_timeout() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+
+    let job_req = job::CreateRequest {
+        timeout: Some(Duration::from_secs(3600)),
+        total_timeout: Some(Duration::from_secs(1)),
+        retries: Some(5),
+        ..Default::default()
+    };
+    let job_id = qw.new_job(&mut conn, &job_req).await.id();
+    qw.next_job(&mut conn).await;
+
+    // attempt timeout hasn't passed, but total timeout has, so job times out and isn't retried
+    tokio::time::delay_for(time::Duration::from_secs(2)).await;
+    assert_eq!(RedisManager::check_job_timeouts(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id]);
+    assert_eq!(qw.job_status(&mut conn, job_id).await, job::Status::TimedOut);
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), Vec::<u64>::new());
+    assert_eq!(RedisManager::failed_queue_size(&mut conn).await.unwrap(), 0);
+    assert_eq!(RedisManager::ended_queue_size(&mut conn).await.unwrap(), 1);
+    assert!(qw.job_fields(&mut conn, job_id, &[job::Field::Ended]).await.ended());
+}
+
+#[tokio::test]
+async fn worker_drain() {
+    let (_ctx, mut conn) = init().await;
+
+    let status = RedisManager::worker_status(&mut conn, "worker-1").await.unwrap();
+    assert!(!status.draining);
+    assert_eq!(status.draining_since, None);
+
+    assert_eq!(RedisManager::drain_worker(&mut conn, "worker-1").await.unwrap(), true);
+    assert_eq!(RedisManager::drain_worker(&mut conn, "worker-1").await.unwrap(), false);
+    let status = RedisManager::worker_status(&mut conn, "worker-1").await.unwrap();
+    assert!(status.draining);
+    assert!(status.draining_since.is_some());
+    assert!(!RedisManager::worker_status(&mut conn, "worker-2").await.unwrap().draining);
+
+    let draining = RedisManager::draining_workers(&mut conn).await.unwrap();
+    assert_eq!(draining, vec![status]);
+
+    assert_eq!(RedisManager::undrain_worker(&mut conn, "worker-1").await.unwrap(), true);
+    assert_eq!(RedisManager::undrain_worker(&mut conn, "worker-1").await.unwrap(), false);
+    assert!(RedisManager::draining_workers(&mut conn).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn job_routing_keys() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let routed =
+        |routing_key: &str| job::CreateRequest { routing_key: Some(routing_key.to_owned()), ..Default::default() };
+    let routing_keys = |keys: &[&str]| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+
+    let gpu_job = qw.new_job(&mut conn, &routed("gpu")).await;
+    assert_eq!(gpu_job.routing_key(), Some("gpu".to_owned()));
+    let shard_job = qw.new_job(&mut conn, &routed("shard-1")).await;
+    let plain_job = qw.new_default_job(&mut conn).await;
+    assert_eq!(qw.queue_size(&mut conn).await, 3);
+
+    let invalid = RedisManager::create_job(&mut conn, DEFAULT_QUEUE, &routed("a b")).await;
+    assert!(matches!(invalid, Err(OcyError::BadRequest(_))));
+    let invalid = RedisManager::next_routed_job(&mut conn, DEFAULT_QUEUE, &routing_keys(&["a:b"]), None, None).await;
+    assert!(matches!(invalid, Err(OcyError::BadRequest(_))));
+
+    // workers without routing keys only get jobs without one
+    assert_eq!(qw.next_job(&mut conn).await.id(), plain_job.id());
+    qw.next_empty_job(&mut conn).await;
+
+    // routing keys are tried in the order given
+    let keys = routing_keys(&["shard-1", "gpu"]);
+    let job = RedisManager::next_routed_job(&mut conn, DEFAULT_QUEUE, &keys, None, None).await.unwrap().unwrap();
+    assert_eq!(job.id(), shard_job.id());
+
+    // routed jobs keep their routing key when retried
+    RedisManager::hold_job(&mut conn, gpu_job.id()).await.unwrap();
+    RedisManager::release_held_job(&mut conn, gpu_job.id()).await.unwrap();
+    qw.next_empty_job(&mut conn).await;
+    assert_eq!(qw.queue_size(&mut conn).await, 1);
+    let keys = routing_keys(&["gpu"]);
+    let job = RedisManager::next_routed_job(&mut conn, DEFAULT_QUEUE, &keys, None, None).await.unwrap().unwrap();
+    assert_eq!(job.id(), gpu_job.id());
+    qw.fail_job(&mut conn, gpu_job.id()).await;
+    RedisManager::retry_job(&mut conn, gpu_job.id()).await.unwrap();
+    assert_eq!(qw.queue_size(&mut conn).await, 1);
+    qw.next_empty_job(&mut conn).await;
+
+    assert_eq!(
+        RedisManager::delete_queue(&mut conn, DEFAULT_QUEUE, true).await.unwrap(),
+        queue::Deletion::Deleted(queue::DeletedJobs { queued: 1 })
+    );
+    assert_eq!(RedisManager::job_status(&mut conn, gpu_job.id()).await, Err(OcyError::NoSuchJob(gpu_job.id())));
+}
+
+async fn next_session_job(conn: &mut Connection, worker_id: &str, config: &StickySessionsConfig) -> Option<u64> {
+    let job = RedisManager::next_routed_job(conn, DEFAULT_QUEUE, &[], Some(worker_id), Some(config)).await.unwrap();
+    job.map(|job| job.id())
+}
+
+#[tokio::test]
+async fn job_sticky_sessions() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let in_session =
+        |session_key: &str| job::CreateRequest { session_key: Some(session_key.to_owned()), ..Default::default() };
+    let config = StickySessionsConfig::default();
+
+    let first_job = qw.new_job(&mut conn, &in_session("s1")).await;
+    assert_eq!(first_job.session_key(), Some("s1".to_owned()));
+    let second_job = qw.new_job(&mut conn, &in_session("s1")).await;
+    let other_job = qw.new_job(&mut conn, &in_session("s2")).await;
+    assert_eq!(qw.queue_size(&mut conn).await, 3);
+
+    let both = job::CreateRequest { routing_key: Some("gpu".to_owned()), ..in_session("s1") };
+    let invalid = RedisManager::create_job(&mut conn, DEFAULT_QUEUE, &both).await;
+    assert!(matches!(invalid, Err(OcyError::BadRequest(_))));
+
+    // session jobs aren't given out without sticky sessions
+    qw.next_empty_job(&mut conn).await;
+
+    // each session's jobs are run one at a time
+    assert_eq!(next_session_job(&mut conn, "w1", &config).await, Some(first_job.id()));
+    assert_eq!(next_session_job(&mut conn, "w1", &config).await, Some(other_job.id()));
+    assert_eq!(next_session_job(&mut conn, "w2", &config).await, None);
+
+    // sessions stick to the worker that ran their last job while it's still asking for jobs
+    qw.complete_job(&mut conn, first_job.id()).await;
+    assert_eq!(next_session_job(&mut conn, "w2", &config).await, None);
+    assert_eq!(next_session_job(&mut conn, "w1", &config).await, Some(second_job.id()));
+
+    // but are given to other workers once it stops
+    qw.complete_job(&mut conn, second_job.id()).await;
+    let third_job = qw.new_job(&mut conn, &in_session("s1")).await;
+    let expired = StickySessionsConfig { worker_timeout: Duration::from_secs(0), ..config };
+    assert_eq!(next_session_job(&mut conn, "w2", &expired).await, Some(third_job.id()));
+    assert_eq!(qw.queue_size(&mut conn).await, 0);
+}
+
+#[tokio::test]
+async fn job_serialization_keys() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let serialized = |serialization_key: &str| job::CreateRequest {
+        serialization_key: Some(serialization_key.to_owned()),
+        ..Default::default()
+    };
+
+    let first_job = qw.new_job(&mut conn, &serialized("customer-1")).await;
+    assert_eq!(first_job.serialization_key(), Some("customer-1".to_owned()));
+    let second_job = qw.new_job(&mut conn, &serialized("customer-1")).await;
+    let other_job = qw.new_job(&mut conn, &serialized("customer-2")).await;
+    let plain_job = qw.new_default_job(&mut conn).await;
+    assert_eq!(qw.queue_size(&mut conn).await, 4);
+
+    let both = job::CreateRequest { session_key: Some("s1".to_owned()), ..serialized("customer-1") };
+    let invalid = RedisManager::create_job(&mut conn, DEFAULT_QUEUE, &both).await;
+    assert!(matches!(invalid, Err(OcyError::BadRequest(_))));
+
+    // jobs with the same serialization key never run at the same time
+    assert_eq!(qw.next_job(&mut conn).await.id(), first_job.id());
+    assert_eq!(qw.next_job(&mut conn).await.id(), other_job.id());
+    assert_eq!(qw.next_job(&mut conn).await.id(), plain_job.id());
+    qw.next_empty_job(&mut conn).await;
+
+    qw.complete_job(&mut conn, first_job.id()).await;
+    assert_eq!(qw.next_job(&mut conn).await.id(), second_job.id());
+    assert_eq!(qw.queue_size(&mut conn).await, 0);
+}
+
+async fn unique_job_id(conn: &mut Connection, job_req: &job::CreateRequest) -> Option<u64> {
+    RedisManager::unique_job(conn, DEFAULT_QUEUE, job_req).await.unwrap().map(|job| job.id())
+}
+
+#[tokio::test]
+async fn job_unique_keys() {
+    let (ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let job_req = job::CreateRequest { unique_key: Some("report-2020-01".to_owned()), ..Default::default() };
+
+    assert_eq!(unique_job_id(&mut conn, &job_req).await, None);
+    let job_id = qw.new_job(&mut conn, &job_req).await.id();
+    assert_eq!(unique_job_id(&mut conn, &job_req).await, Some(job_id));
+    qw.next_job(&mut conn).await;
+    assert_eq!(unique_job_id(&mut conn, &job_req).await, Some(job_id));
+
+    // completed jobs are only reused within the requested window
+    let update_req = job::UpdateRequest {
+        status: Some(job::Status::Completed),
+        output: Some(serde_json::json!({"rows": 3})),
+        ..Default::default()
+    };
+    RedisManager::update_job(&mut conn, job_id, &update_req).await.unwrap();
+    assert_eq!(unique_job_id(&mut conn, &job_req).await, None);
+    let reuse_req = job::CreateRequest { reuse_completed_within: Some(Duration::from_secs(60)), ..job_req.clone() };
+    let job = RedisManager::unique_job(&mut conn, DEFAULT_QUEUE, &reuse_req).await.unwrap().unwrap();
+    assert_eq!((job.id(), job.output()), (job_id, Some(serde_json::json!({"rows": 3}))));
+
+    let new_job_id = qw.new_job(&mut conn, &job_req).await.id();
+    assert_ne!(new_job_id, job_id);
+    assert_eq!(unique_job_id(&mut conn, &job_req).await, Some(new_job_id));
+    RedisManager::delete_job(&mut conn, new_job_id).await.unwrap();
+    assert_eq!(unique_job_id(&mut conn, &job_req).await, None);
+
+    // the unique key is claimed when creating the job, so concurrent requests create at most one job between them
+    let mut conns = Vec::new();
+    for _ in 0..5 {
+        conns.push(ctx.async_connection().await.unwrap());
+    }
+    let created = futures::future::join_all(
+        conns.iter_mut().map(|conn| RedisManager::create_job(conn, DEFAULT_QUEUE, &job_req)),
+    )
+    .await;
+    let created: Vec<job::Created> = created.into_iter().map(Result::unwrap).collect();
+    assert_eq!(created.iter().filter(|created| matches!(created, job::Created::New(_))).count(), 1);
+    let job_id = created[0].id();
+    assert!(created.iter().all(|created| created.id() == job_id));
+    assert_eq!(unique_job_id(&mut conn, &job_req).await, Some(job_id));
+}
+
+#[tokio::test]
+async fn job_results() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::with_default_queue(&mut conn).await;
+    let job_req = job::CreateRequest { unique_key: Some("report-2020-02".to_owned()), ..Default::default() };
+    let unique_key = job_req.unique_key.as_deref().unwrap();
+
+    let job_id = qw.new_job(&mut conn, &job_req).await.id();
+    qw.next_job(&mut conn).await;
+    assert_eq!(RedisManager::job_result(&mut conn, DEFAULT_QUEUE, unique_key).await, Ok(None));
+
+    let update_req = job::UpdateRequest {
+        status: Some(job::Status::Completed),
+        output: Some(serde_json::json!({"rows": 5})),
+        ..Default::default()
+    };
+    RedisManager::update_job(&mut conn, job_id, &update_req).await.unwrap();
+    assert_eq!(
+        RedisManager::job_result(&mut conn, DEFAULT_QUEUE, unique_key).await,
+        Ok(Some((job_id, serde_json::json!({"rows": 5}))))
+    );
+
+    // newer jobs with the same key don't replace the result until they complete
+    let new_job_id = qw.new_job(&mut conn, &job_req).await.id();
+    assert_eq!(RedisManager::job_result(&mut conn, DEFAULT_QUEUE, unique_key).await.unwrap().unwrap().0, job_id);
+    RedisManager::delete_job(&mut conn, new_job_id).await.unwrap();
+    RedisManager::delete_job(&mut conn, job_id).await.unwrap();
+    assert_eq!(RedisManager::job_result(&mut conn, DEFAULT_QUEUE, unique_key).await, Ok(None));
+    assert_eq!(
+        RedisManager::job_result(&mut conn, "missing", unique_key).await,
+        Err(OcyError::NoSuchQueue("missing".to_owned()))
+    );
+}
+
 #[tokio::test]
 async fn job_expiry() {
     let (_ctx, mut conn) = init().await;
@@ -880,16 +2232,16 @@ async fn job_expiry() {
     let mut expected_expired = vec![job_id_completed, job_id_cancelled,];
     expected_expired.sort();
     tokio::time::delay_for(time::Duration::from_secs(2)).await;
-    let mut expired = RedisManager::check_job_expiry(&mut conn).await.unwrap();
+    let mut expired = RedisManager::check_job_expiry(&mut conn, &expire_all()).await.unwrap();
     expired.sort();
     assert_eq!(expired, expected_expired);
 
     // no retries, failed/timed out jobs should be moved to ended queue, then expire next call
-    assert_eq!(RedisManager::check_job_retries(&mut conn).await.unwrap(), Vec::<u64>::new());
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), Vec::<u64>::new());
      let mut expected_expired = vec![job_id_failed, job_id_timed_out];
     expected_expired.sort();
     tokio::time::delay_for(time::Duration::from_secs(2)).await;
-    let mut expired = RedisManager::check_job_expiry(&mut conn).await.unwrap();
+    let mut expired = RedisManager::check_job_expiry(&mut conn, &expire_all()).await.unwrap();
     expired.sort();
     assert_eq!(expired, expected_expired);
 
@@ -901,6 +2253,121 @@ async fn job_expiry() {
     assert_eq!(RedisManager::job_status(&mut conn, job_id_queued).await,    Ok(job::Status::Queued));
 }
 
+#[tokio::test]
+async fn job_timeout_per_queue() {
+    let (_ctx, mut conn) = init().await;
+    let settings = queue::Settings { timeout_check_interval: Some(Duration::from_secs(5)), ..Default::default() };
+    RedisManager::create_or_update_queue(&mut conn, "fast", &settings).await.unwrap();
+    let settings = queue::Settings { timeout_check_interval: Some(Duration::from_secs(300)), ..Default::default() };
+    RedisManager::create_or_update_queue(&mut conn, "slow", &settings).await.unwrap();
+
+    let job_req = job::CreateRequest { timeout: Some(Duration::from_secs(1)), ..Default::default() };
+    let mut running = HashMap::new();
+    for queue_name in &["fast", "slow"] {
+        let qw = QueueWrapper::new(*queue_name);
+        let job_id = qw.new_job(&mut conn, &job_req).await.id();
+        qw.next_job(&mut conn).await;
+        running.insert(*queue_name, job_id);
+    }
+
+    // only the fast queue is due to be checked
+    let mut sweep = queue::CheckSweep::default();
+    sweep.queues.insert("fast".to_owned(), true);
+    sweep.queues.insert("slow".to_owned(), false);
+
+    tokio::time::delay_for(time::Duration::from_secs(2)).await;
+    assert_eq!(RedisManager::check_job_timeouts(&mut conn, &sweep).await.unwrap(), vec![running["fast"]]);
+    assert_eq!(RedisManager::job_status(&mut conn, running["slow"]).await, Ok(job::Status::Running));
+
+    assert_eq!(RedisManager::check_job_timeouts(&mut conn, &queue::CheckSweep::all()).await.unwrap(), vec![running["slow"]]);
+}
+
+#[tokio::test]
+async fn job_expiry_per_queue() {
+    let (_ctx, mut conn) = init().await;
+    let default_statuses = vec![job::Status::Completed, job::Status::Failed];
+    let default_interval = time::Duration::from_secs(300);
+
+    // queue "a" keeps failed jobs, queue "b" uses the defaults
+    let settings = queue::Settings {
+        expires_after: Duration::from_secs(1),
+        expiry_check_statuses: Some(vec![job::Status::Completed]),
+        ..Default::default()
+    };
+    RedisManager::create_or_update_queue(&mut conn, "a", &settings).await.unwrap();
+    let settings = queue::Settings { expires_after: Duration::from_secs(1), ..Default::default() };
+    RedisManager::create_or_update_queue(&mut conn, "b", &settings).await.unwrap();
+
+    let mut failed = HashMap::new();
+    for queue_name in &["a", "b"] {
+        let qw = QueueWrapper::new(*queue_name);
+        let job_id = qw.new_running_default_job(&mut conn).await.id();
+        qw.fail_job(&mut conn, job_id).await;
+        failed.insert(*queue_name, job_id);
+    }
+    assert_eq!(RedisManager::check_job_retries(&mut conn, &queue::CheckSweep::all()).await.unwrap(), Vec::<u64>::new());
+
+    let policies = RedisManager::queue_expiry_policies(&mut conn, &default_statuses, default_interval).await.unwrap();
+    assert_eq!(policies["a"].statuses, vec![job::Status::Completed]);
+    assert_eq!(policies["b"].statuses, default_statuses);
+
+    let mut sweep = queue::ExpirySweep::default();
+    for (queue_name, policy) in policies {
+        sweep.queues.insert(queue_name, Some(policy.statuses));
+    }
+
+    tokio::time::delay_for(time::Duration::from_secs(2)).await;
+    assert_eq!(RedisManager::check_job_expiry(&mut conn, &sweep).await.unwrap(), vec![failed["b"]]);
+    assert_eq!(RedisManager::job_status(&mut conn, failed["a"]).await, Ok(job::Status::Failed));
+}
+
+#[tokio::test]
+async fn expire_and_purge_dry_run() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::new(DEFAULT_QUEUE);
+    let settings = queue::Settings { expires_after: Duration::from_secs(1), ..Default::default() };
+    RedisManager::create_or_update_queue(&mut conn, DEFAULT_QUEUE, &settings).await.unwrap();
+    let statuses = [job::Status::Completed];
+
+    let completed = qw.new_running_default_job(&mut conn).await.id();
+    qw.complete_job(&mut conn, completed).await;
+    let cancelled = qw.new_default_job(&mut conn).await.id();
+    RedisManager::set_job_status(&mut conn, cancelled, &job::Status::Cancelled).await.unwrap();
+
+    // nothing has expired yet, but everything ended can be purged
+    let empty: Vec<u64> = Vec::new();
+    assert_eq!(RedisManager::expire_queue(&mut conn, DEFAULT_QUEUE, &statuses, true).await.unwrap(), empty);
+    let mut purged = RedisManager::purge_queue(&mut conn, DEFAULT_QUEUE, true).await.unwrap();
+    purged.sort();
+    assert_eq!(purged, vec![completed, cancelled]);
+
+    // dry runs don't remove anything
+    tokio::time::delay_for(time::Duration::from_secs(2)).await;
+    assert_eq!(RedisManager::expire_queue(&mut conn, DEFAULT_QUEUE, &statuses, true).await.unwrap(), vec![completed]);
+    assert_eq!(RedisManager::expire_queue(&mut conn, DEFAULT_QUEUE, &statuses, true).await.unwrap(), vec![completed]);
+    assert_eq!(qw.job_status(&mut conn, completed).await, job::Status::Completed);
+
+    assert_eq!(RedisManager::expire_queue(&mut conn, DEFAULT_QUEUE, &statuses, false).await.unwrap(), vec![completed]);
+    assert_eq!(RedisManager::job_status(&mut conn, completed).await, Err(OcyError::NoSuchJob(completed)));
+    assert_eq!(RedisManager::purge_queue(&mut conn, DEFAULT_QUEUE, false).await.unwrap(), vec![cancelled]);
+    assert_eq!(RedisManager::job_status(&mut conn, cancelled).await, Err(OcyError::NoSuchJob(cancelled)));
+
+    assert_eq!(
+        RedisManager::purge_queue(&mut conn, "missing", true).await,
+        Err(OcyError::NoSuchQueue("missing".to_owned()))
+    );
+}
+
+/// Expiry sweep that expires ended jobs of all statuses on every queue.
+fn expire_all() -> queue::ExpirySweep {
+    queue::ExpirySweep::all(vec![
+        job::Status::Failed,
+        job::Status::Completed,
+        job::Status::Cancelled,
+        job::Status::TimedOut,
+    ])
+}
+
 async fn create_job_in_all_states(
     conn: &mut Connection,
     queue: &str,
@@ -908,14 +2375,14 @@ async fn create_job_in_all_states(
 ) -> HashMap<job::Status, u64> {
      let mut job_req: job::CreateRequest = create_req.clone();
 
-    let job_id_running = RedisManager::create_job(conn, queue, &job_req).await.unwrap();
-    let job_id_completed = RedisManager::create_job(conn, queue, &job_req).await.unwrap();
-    let job_id_failed = RedisManager::create_job(conn, queue, &job_req).await.unwrap();
-    let job_id_cancelled = RedisManager::create_job(conn, queue, &job_req).await.unwrap();
+    let job_id_running = RedisManager::create_job(conn, queue, &job_req).await.unwrap().id();
+    let job_id_completed = RedisManager::create_job(conn, queue, &job_req).await.unwrap().id();
+    let job_id_failed = RedisManager::create_job(conn, queue, &job_req).await.unwrap().id();
+    let job_id_cancelled = RedisManager::create_job(conn, queue, &job_req).await.unwrap().id();
 
     job_req.timeout = Some(Duration::from_secs(1));
-    let job_id_timed_out = RedisManager::create_job(conn, queue, &job_req).await.unwrap();
-    let job_id_queued = RedisManager::create_job(conn, queue, &job_req).await.unwrap();
+    let job_id_timed_out = RedisManager::create_job(conn, queue, &job_req).await.unwrap().id();
+    let job_id_queued = RedisManager::create_job(conn, queue, &job_req).await.unwrap().id();
 
     let mut update_req = job::UpdateRequest::default();
 
@@ -941,7 +2408,7 @@ async fn create_job_in_all_states(
     RedisManager::next_queued_job(conn, queue).await.unwrap();
 
     tokio::time::delay_for(time::Duration::from_secs(2)).await;
-    assert_eq!(RedisManager::check_job_timeouts(conn).await.unwrap(), vec![job_id_timed_out]);
+    assert_eq!(RedisManager::check_job_timeouts(conn, &queue::CheckSweep::all()).await.unwrap(), vec![job_id_timed_out]);
 
     assert_eq!(RedisManager::job_status(conn, job_id_running).await, Ok(job::Status::Running));
     assert_eq!(RedisManager::job_status(conn, job_id_completed).await, Ok(job::Status::Completed));
@@ -959,3 +2426,96 @@ async fn create_job_in_all_states(
     map.insert(job::Status::Queued, job_id_queued);
     map
 }
+
+#[tokio::test]
+async fn namespace_storage() {
+    let (_ctx, mut conn) = init().await;
+    let qw = QueueWrapper::new("team-a.emails");
+    qw.create_queue(&mut conn).await;
+    assert_eq!(RedisManager::namespace_storage(&mut conn, "team-a").await.unwrap(), 0);
+
+    // {"a":1} is 7 bytes
+    let job_req = job::CreateRequest { input: Some(serde_json::json!({"a": 1})), ..Default::default() };
+    let job_id = qw.new_running_job(&mut conn, &job_req).await.id();
+    assert_eq!(RedisManager::namespace_storage(&mut conn, "team-a").await.unwrap(), 7);
+
+    let update_req = job::UpdateRequest { output: Some(serde_json::json!([1, 2])), ..Default::default() };
+    RedisManager::update_job(&mut conn, job_id, &update_req).await.unwrap();
+    assert_eq!(RedisManager::namespace_storage(&mut conn, "team-a").await.unwrap(), 12);
+
+    // storage is checked atomically against the namespace's limit
+    let limit = quota::StorageLimit { namespace: "team-a".to_owned(), max_bytes: 15, quota_bytes: 15 };
+    let res = RedisManager::create_job_within_limit(&mut conn, &qw.queue_name, &job_req, Some(&limit)).await;
+    assert!(matches!(res, Err(OcyError::QuotaExceeded(_))));
+    assert_eq!(RedisManager::namespace_storage(&mut conn, "team-a").await.unwrap(), 12);
+
+    assert!(RedisManager::delete_job(&mut conn, job_id).await.unwrap());
+    assert_eq!(RedisManager::namespace_storage(&mut conn, "team-a").await.unwrap(), 0);
+    RedisManager::create_job_within_limit(&mut conn, &qw.queue_name, &job_req, Some(&limit)).await.unwrap();
+    assert_eq!(RedisManager::namespace_storage(&mut conn, "team-a").await.unwrap(), 7);
+    assert_eq!(RedisManager::check_integrity(&mut conn, false).await.unwrap(), IntegrityReport::default());
+
+    // miscounted storage is reported, and repaired
+    let _: () = redis::cmd("HSET").arg("ocypod:namespace_storage").arg("team-a").arg(100)
+        .query_async(&mut conn).await.unwrap();
+    let mut expected = IntegrityReport::default();
+    expected.miscounted_storage.insert("team-a".to_owned(), 93);
+    assert_eq!(RedisManager::check_integrity(&mut conn, false).await.unwrap(), expected);
+    expected.repaired = true;
+    assert_eq!(RedisManager::check_integrity(&mut conn, true).await.unwrap(), expected);
+    assert_eq!(RedisManager::namespace_storage(&mut conn, "team-a").await.unwrap(), 7);
+}
+
+#[tokio::test]
+async fn shared_notifications() {
+    use ocypod::application::notify;
+    use ocypod::config::{HealthRule, NotificationRule};
+    use ocypod::events::{notifications::Metric, EventKind};
+
+    let (_ctx, mut conn) = init().await;
+    // counts expire relative to the current time
+    let now = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_secs() as i64;
+    let window = time::Duration::from_secs(300);
+    let rules = vec![NotificationRule {
+        queue: None,
+        metric: Metric::Failed,
+        threshold: 2.0,
+        window: Duration::from_secs(300),
+        cooldown: None,
+        min_jobs: 1,
+        template: Some("{queue} {value}".to_owned()),
+    }];
+    let health = &[HealthRule {
+        queue: Some("a".to_owned()),
+        successes: 1,
+        failing_template: Some("{queue} failing".to_owned()),
+        recovered_template: Some("{queue} recovered".to_owned()),
+    }];
+
+    let messages = notify::record_event(&mut conn, "a", EventKind::Completed, now, window, health).await.unwrap();
+    assert!(messages.is_empty());
+    let messages = notify::record_event(&mut conn, "a", EventKind::Failed, now, window, health).await.unwrap();
+    assert_eq!(messages, vec!["a failing".to_owned()]);
+    let messages = notify::record_event(&mut conn, "b", EventKind::Failed, now, window, health).await.unwrap();
+    assert!(messages.is_empty());
+    assert!(notify::evaluate(&mut conn, &rules, now).await.unwrap().is_empty());
+
+    // events counted by any server are evaluated together, 20s later is in a different bucket
+    let messages = notify::record_event(&mut conn, "a", EventKind::TimedOut, now + 20, window, health).await.unwrap();
+    assert!(messages.is_empty());
+    notify::record_event(&mut conn, "a", EventKind::Failed, now + 20, window, health).await.unwrap();
+    let counts = notify::window_counts(&mut conn, window, now + 20).await.unwrap();
+    assert_eq!(counts["a"].failed, 2);
+    assert_eq!(counts["a"].timed_out, 1);
+    assert_eq!(counts["a"].completed, 1);
+    assert_eq!(notify::evaluate(&mut conn, &rules, now + 20).await.unwrap(), vec!["a 2".to_owned()]);
+
+    // cooling down, regardless of which server evaluates the rule
+    assert!(notify::evaluate(&mut conn, &rules, now + 30).await.unwrap().is_empty());
+
+    // events outside the window aren't counted
+    assert!(notify::window_counts(&mut conn, window, now + 400).await.unwrap().is_empty());
+
+    let messages = notify::record_event(&mut conn, "a", EventKind::Completed, now + 30, window, health).await.unwrap();
+    assert_eq!(messages, vec!["a recovered".to_owned()]);
+}
@@ -0,0 +1,95 @@
+//! Integration tests of the in-process test server, using the Rust client.
+//!
+//! Requires the "test-util" and "client" features, and Redis to be installed, so that the test server can start its
+//! own `redis-server`.
+
+#![cfg(all(feature = "test-util", feature = "client"))]
+
+use std::collections::HashMap;
+
+use ocypod::application::file;
+use ocypod::client::{ClientError, Created};
+use ocypod::config::{ApiKeyConfig, Config, Durability};
+use ocypod::models::{job, queue, quota, Role};
+use ocypod::test_util::TestServer;
+use reqwest::StatusCode;
+
+#[actix_rt::test]
+async fn worker_round_trip() {
+    let server = TestServer::start().await.unwrap();
+    let client = server.client();
+
+    assert!(client.create_queue("default", &queue::Settings::default()).await.unwrap());
+    assert!(client.next_job("default").await.unwrap().is_none());
+
+    let job_req = job::CreateRequest { input: Some(serde_json::json!({"n": 1})), ..Default::default() };
+    let job_id = match client.create_job("default", &job_req).await.unwrap() {
+        Created::Job(job_id) => job_id,
+        created => panic!("Unexpected job creation: {:?}", created),
+    };
+    assert_eq!(client.queue_size("default").await.unwrap(), 1);
+
+    let payload = client.next_job("default").await.unwrap().unwrap();
+    assert_eq!(payload.id(), job_id);
+    assert_eq!(payload.input(), &job_req.input);
+    client.heartbeat(job_id).await.unwrap();
+    client.complete_job(job_id, Some(serde_json::json!(2))).await.unwrap();
+
+    assert_eq!(client.job_status(job_id).await.unwrap(), job::Status::Completed);
+    assert_eq!(client.job_output(job_id).await.unwrap(), serde_json::json!(2));
+}
+
+#[actix_rt::test]
+async fn job_callback_url_needs_admin_key() {
+    let mut config = Config::default();
+    config.auth.admin_keys.push("admin".to_owned());
+    config.auth.api_keys.insert(
+        "team-a-admin".to_owned(),
+        ApiKeyConfig::WithRoles { namespace: "team-a".to_owned(), roles: vec![Role::Admin], scopes: HashMap::new() },
+    );
+    let server = TestServer::start_with_config(config).await.unwrap();
+    let admin = server.client().with_api_key("admin");
+    let team_a = server.client().with_api_key("team-a-admin");
+    assert!(team_a.create_queue("default", &queue::Settings::default()).await.unwrap());
+
+    // namespace keys can't make the server send requests to arbitrary URLs, even with the admin role
+    let job_req = job::CreateRequest {
+        callback_url: Some("http://169.254.169.254/latest/meta-data".to_owned()),
+        ..Default::default()
+    };
+    match team_a.create_job("default", &job_req).await {
+        Err(ClientError::Status(status, _)) => assert_eq!(status, StatusCode::FORBIDDEN),
+        res => panic!("Unexpected job creation: {:?}", res),
+    }
+    assert_eq!(team_a.queue_size("default").await.unwrap(), 0);
+    assert!(matches!(team_a.create_job("default", &job::CreateRequest::default()).await, Ok(Created::Job(_))));
+
+    assert!(matches!(admin.create_job("team-a.default", &job_req).await, Ok(Created::Job(_))));
+    assert_eq!(admin.queue_size("team-a.default").await.unwrap(), 2);
+}
+
+#[actix_rt::test]
+async fn reattempt_within_quota() {
+    let mut config = Config::default();
+    config.auth.admin_keys.push("admin".to_owned());
+    config.auth.api_keys.insert("team-a-key".to_owned(), ApiKeyConfig::Namespace("team-a".to_owned()));
+    let job_quota = quota::Quota { max_queued_jobs: Some(1), ..Default::default() };
+    config.auth.quotas.insert("team-a".to_owned(), job_quota);
+    let server = TestServer::start_with_config(config).await.unwrap();
+    let admin = server.client().with_api_key("admin");
+    let team_a = server.client().with_api_key("team-a-key");
+    assert!(admin.create_queue("team-a.default", &queue::Settings::default()).await.unwrap());
+    assert!(matches!(team_a.create_job("default", &job::CreateRequest::default()).await, Ok(Created::Job(_))));
+
+    // reattempting a job creation request can't add jobs beyond the namespace's quota
+    let (_, attempt_id) = file::write_job("team-a.default", &job::CreateRequest::default(), Durability::None).unwrap();
+    let response = reqwest::Client::new()
+        .get(&format!("{}/queue/default/reattempt/{}", server.url(), attempt_id))
+        .bearer_auth("team-a-key")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(admin.queue_size("team-a.default").await.unwrap(), 1);
+    file::delete_job("team-a.default", attempt_id, false).unwrap();
+}